@@ -0,0 +1,162 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Key slots
+//!
+//! The function that clients which shard keys across several servers route a key with, so
+//! that they all agree on which server a key is on. Skytable itself isn't clustered; this only
+//! standardizes the routing (and `SYS KEYSLOT` returns the same slots).
+//!
+//! The slot of a key is the CRC-16 of the key (the XMODEM variant: polynomial `0x1021`, no
+//! reflection, an initial value of zero and no final XOR) modulo the number of slots, which
+//! is [`DEFAULT_SLOTS`] unless the client picks another. If the key has a _hash tag_, only the
+//! tag is hashed, so keys like `{user:1000}.followers` and `{user:1000}.following` are always
+//! in the same slot. The tag is what's between the first `{` and the first `}` after it, as
+//! long as that isn't empty. This is the same function (and, with the default number of
+//! slots, the same slots) as Redis Cluster's.
+//!
+//! **This is a stable format:** the slot of a key can't change from one release to the next,
+//! since that would move keys across the servers of every client
+
+/// The number of slots that keys are spread across, unless some other number is picked
+pub const DEFAULT_SLOTS: u32 = 16384;
+/// The most slots that keys can be spread across (there are only as many CRC-16s)
+pub const MAX_SLOTS: u32 = 1 << 16;
+
+const CRC16_TABLE: [u16; 256] = crc16_table();
+
+const fn crc16_table() -> [u16; 256] {
+    let mut table = [0u16; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = (i as u16) << 8;
+        let mut j = 0;
+        while j < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            j += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+/// The CRC-16 (XMODEM) of `data`
+pub fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |crc, byte| {
+        (crc << 8) ^ CRC16_TABLE[((crc >> 8) as u8 ^ byte) as usize]
+    })
+}
+
+/// Returns the part of `key` that is hashed: its hash tag if it has one, or else all of it
+pub fn hashed_part(key: &[u8]) -> &[u8] {
+    if let Some(open) = key.iter().position(|byte| *byte == b'{') {
+        let rest = &key[open + 1..];
+        match rest.iter().position(|byte| *byte == b'}') {
+            Some(close) if close != 0 => return &rest[..close],
+            _ => {}
+        }
+    }
+    key
+}
+
+/// Returns the slot of `key` out of `slots` slots (which has to be between 1 and
+/// [`MAX_SLOTS`])
+pub fn keyslot_of(key: &[u8], slots: u32) -> u32 {
+    assert!(
+        slots != 0 && slots <= MAX_SLOTS,
+        "the number of slots has to be between 1 and 65536"
+    );
+    crc16(hashed_part(key)) as u32 % slots
+}
+
+/// Returns the slot of `key` out of the [`DEFAULT_SLOTS`]
+pub fn keyslot(key: &[u8]) -> u32 {
+    keyslot_of(key, DEFAULT_SLOTS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        // the check value of CRC-16/XMODEM
+        assert_eq!(crc16(b"123456789"), 0x31C3);
+        assert_eq!(crc16(b""), 0);
+    }
+
+    #[test]
+    fn test_keyslot_vectors() {
+        // these can never change (see the module docs)
+        let vectors: &[(&[u8], u32)] = &[
+            (b"foo", 12182),
+            (b"bar", 5061),
+            (b"hello", 866),
+            (b"somekey", 11058),
+            (b"foo{hash_tag}", 2515),
+            (b"{user1000}.following", 3443),
+            (b"{user1000}.followers", 3443),
+            (b"", 0),
+        ];
+        for (key, slot) in vectors {
+            assert_eq!(keyslot(key), *slot, "{:?}", String::from_utf8_lossy(key));
+        }
+    }
+
+    #[test]
+    fn test_hash_tags() {
+        assert_eq!(hashed_part(b"{user1000}.following"), b"user1000");
+        assert_eq!(hashed_part(b"foo{bar}{zap}"), b"bar");
+        // the tag ends at the first `}`
+        assert_eq!(hashed_part(b"foo{{bar}}zap"), b"{bar");
+        // empty braces don't make a tag, even if there's another one after them
+        assert_eq!(hashed_part(b"foo{}{bar}"), b"foo{}{bar}");
+        assert_eq!(hashed_part(b"{}"), b"{}");
+        // and neither do unclosed ones
+        assert_eq!(hashed_part(b"foo{bar"), b"foo{bar");
+        assert_eq!(hashed_part(b"foo}bar{"), b"foo}bar{");
+    }
+
+    #[test]
+    fn test_keyslot_of_slots() {
+        assert_eq!(keyslot_of(b"foo", DEFAULT_SLOTS), keyslot(b"foo"));
+        assert_eq!(keyslot_of(b"foo", 1024), 918);
+        assert_eq!(keyslot_of(b"foo", 3), 1);
+        assert_eq!(keyslot_of(b"foo", 1), 0);
+        assert_eq!(keyslot_of(b"foo", MAX_SLOTS), crc16(b"foo") as u32);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_keyslot_of_no_slots() {
+        keyslot_of(b"foo", 0);
+    }
+}
@@ -31,6 +31,7 @@
 //!
 //! This contains modules which are shared by both the `cli` and the `server` modules
 
+pub mod keyslot;
 pub mod util;
 use skytable::Query;
 use std::error::Error;
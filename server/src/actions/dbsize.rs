@@ -29,7 +29,8 @@ use crate::dbnet::connection::prelude::*;
 action!(
     /// Returns the number of keys in the database
     fn dbsize(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, gt 1);
+        check_arity!(act, con, Arity::AtMost(1));
+        ensure_readable!(con);
         if act.len() == 0 {
             let len;
             {
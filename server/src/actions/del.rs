@@ -27,34 +27,43 @@
 //! # `DEL` queries
 //! This module provides functions to work with `DEL` queries
 
+use crate::actions::yield_on_chunk;
 use crate::dbnet::connection::prelude::*;
 
 action!(
     /// Run a `DEL` query
     ///
-    /// Do note that this function is blocking since it acquires a write lock.
-    /// It will write an entire datagroup, for this `del` action
+    /// The keys are deleted in chunks, yielding to the runtime in between. The server state
+    /// is checked before and after (and not for every key), so if the system was poisoned in
+    /// the meantime, the response is an error. This only covers the response: the keys that
+    /// were deleted by then stay deleted (like the keys that `MSET` set do), so the client has
+    /// to retry the query once the system is unpoisoned. If any of the keys is protected from deletion,
+    /// nothing is deleted and `protected-key` is returned
     fn del(handle: &Corestore, con: &mut T, act: ActionIter) {
-        err_if_len_is!(act, con, eq 0);
-        let done_howmany: Option<usize>;
-        {
-            if registry::state_okay() {
-                let mut many = 0;
-                let cmap = kve!(con, handle);
-                act.for_each(|key| {
-                    if not_enc_err!(cmap.remove(key)) {
-                        many += 1
-                    }
-                });
-                done_howmany = Some(many);
-            } else {
-                done_howmany = None;
+        check_arity!(act, con, Arity::NonZero);
+        // deleting frees memory, so this runs even if the memory guard blocks writes
+        throttle_writes!(con, handle, frees_memory);
+        let epoch = registry::poison_epoch();
+        if !registry::state_okay() {
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        let cmap = kve!(con, handle);
+        for (i, key) in act.as_ref().iter().enumerate() {
+            yield_on_chunk(i).await;
+            if cmap.is_protected(key) {
+                return conwrite!(con, responses::groups::PROTECTED_KEY);
+            }
+        }
+        let mut many = 0usize;
+        for (i, key) in act.enumerate() {
+            yield_on_chunk(i).await;
+            if not_enc_err!(cmap.remove(key)) {
+                many += 1
             }
         }
-        if let Some(done_howmany) = done_howmany {
-            con.write_response(done_howmany).await
-        } else {
-            con.write_response(responses::groups::SERVER_ERR).await
+        if !registry::unpoisoned_since(epoch) {
+            return con.write_response(responses::groups::SERVER_ERR).await;
         }
+        con.write_response(many).await
     }
 );
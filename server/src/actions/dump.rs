@@ -0,0 +1,298 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `DUMPKEY` and `RESTOREKEY` queries
+//!
+//! These move single keys between servers. `DUMPKEY` returns the value of a key serialized
+//! into an opaque blob which can be handed to `RESTOREKEY` on another server (or the same one).
+//!
+//! ## The dump format
+//!
+//! The blob is hex-encoded (lowercase) so that it can be passed around as a string by any
+//! client. The decoded blob looks like:
+//! ```text
+//! | version (1B) | flags (1B) | value length (8B LE) | value |
+//!     [metadata length (8B LE) | metadata] | CRC32-C (4B LE) |
+//! ```
+//! - The version is [`DUMP_VERSION`]. This byte will always come first, irrespective of the
+//!   version, and blobs with a version that this server doesn't know of are refused
+//! - Bit 0 of the flags tells us if there's a metadata section. No metadata is defined by this
+//!   version (it is reserved for key metadata such as the expiry) so it is skipped on restore.
+//!   The other bits must be unset
+//! - The checksum is the CRC32-C of everything that comes before it
+
+use crate::corestore::Data;
+use crate::dbnet::connection::prelude::*;
+use crate::resp::BytesWrapper;
+use crate::storage::crc32c;
+use bytes::Bytes;
+
+/// The version of the dump format written by this server
+pub const DUMP_VERSION: u8 = 1;
+/// Set if the dump has a metadata section
+const FLAG_HAS_METADATA: u8 = 0b1;
+const REPLACE: &[u8] = "REPLACE".as_bytes();
+/// The size of the version, flags and value length
+const HEADER_SIZE: usize = 10;
+const CHECKSUM_SIZE: usize = 4;
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+#[derive(Debug, PartialEq)]
+/// Errors that can occur while decoding a dump
+pub enum DumpError {
+    /// The dump is malformed or failed the checksum
+    Corrupted,
+    /// The dump was written in a version of the format that we don't know of
+    UnknownVersion,
+}
+
+impl DumpError {
+    /// Returns the response for this error
+    pub const fn response(&self) -> &'static [u8] {
+        match self {
+            Self::Corrupted => groups::CORRUPTED_DUMP,
+            Self::UnknownVersion => groups::UNKNOWN_DUMP_VERSION,
+        }
+    }
+}
+
+/// Serialize `value` into a hex-encoded dump
+pub fn serialize(value: &[u8]) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(HEADER_SIZE + value.len() + CHECKSUM_SIZE);
+    blob.push(DUMP_VERSION);
+    blob.push(0);
+    blob.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    blob.extend_from_slice(value);
+    let checksum = crc32c(&blob);
+    blob.extend_from_slice(&checksum.to_le_bytes());
+    let mut hex = Vec::with_capacity(blob.len() * 2);
+    for byte in blob {
+        hex.push(HEX_DIGITS[(byte >> 4) as usize]);
+        hex.push(HEX_DIGITS[(byte & 0x0F) as usize]);
+    }
+    hex
+}
+
+fn decode_nibble(nibble: u8) -> Result<u8, DumpError> {
+    match nibble {
+        b'0'..=b'9' => Ok(nibble - b'0'),
+        b'a'..=b'f' => Ok(nibble - b'a' + 10),
+        _ => Err(DumpError::Corrupted),
+    }
+}
+
+/// Read a little-endian length from `blob` at `at`, returning the length and the offset right
+/// after it
+fn read_len(blob: &[u8], at: usize) -> Result<(usize, usize), DumpError> {
+    let end = at + 8;
+    if blob.len() < end {
+        return Err(DumpError::Corrupted);
+    }
+    let mut len = [0u8; 8];
+    len.copy_from_slice(&blob[at..end]);
+    Ok((u64::from_le_bytes(len) as usize, end))
+}
+
+/// Decode a hex-encoded dump, returning the value in it
+pub fn deserialize(hex: &[u8]) -> Result<Vec<u8>, DumpError> {
+    if hex.len() < 2 || is_lowbit_set!(hex.len()) {
+        return Err(DumpError::Corrupted);
+    }
+    let blob = hex
+        .chunks_exact(2)
+        .map(|pair| -> Result<u8, DumpError> {
+            Ok((decode_nibble(pair[0])? << 4) | decode_nibble(pair[1])?)
+        })
+        .collect::<Result<Vec<u8>, DumpError>>()?;
+    // check the version first; a later version can change everything after it
+    if blob[0] != DUMP_VERSION {
+        return Err(DumpError::UnknownVersion);
+    }
+    if blob.len() < HEADER_SIZE + CHECKSUM_SIZE {
+        return Err(DumpError::Corrupted);
+    }
+    let (blob, checksum) = blob.split_at(blob.len() - CHECKSUM_SIZE);
+    let mut expected = [0u8; CHECKSUM_SIZE];
+    expected.copy_from_slice(checksum);
+    if crc32c(blob) != u32::from_le_bytes(expected) {
+        return Err(DumpError::Corrupted);
+    }
+    let flags = blob[1];
+    if flags & !FLAG_HAS_METADATA != 0 {
+        return Err(DumpError::Corrupted);
+    }
+    let (value_len, value_start) = read_len(blob, 2)?;
+    let value_end = match value_start.checked_add(value_len) {
+        Some(end) if end <= blob.len() => end,
+        _ => return Err(DumpError::Corrupted),
+    };
+    let end = if flags & FLAG_HAS_METADATA == 0 {
+        value_end
+    } else {
+        // no metadata is defined yet, so we just skip it
+        let (metadata_len, metadata_start) = read_len(blob, value_end)?;
+        match metadata_start.checked_add(metadata_len) {
+            Some(end) => end,
+            None => return Err(DumpError::Corrupted),
+        }
+    };
+    if end != blob.len() {
+        return Err(DumpError::Corrupted);
+    }
+    Ok(blob[value_start..value_end].to_owned())
+}
+
+action!(
+    /// Run a `DUMPKEY <key>` query. This returns the value of the key serialized into a blob
+    /// that can be restored with `RESTOREKEY`, or `Nil` if the key doesn't exist
+    fn dumpkey(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        ensure_readable!(con);
+        let key = next_or_err!(act, con);
+        let now = handle.get_store().get_clock().now().timestamp_millis();
+        match kve!(con, handle).get_cloned(key, now) {
+            Ok(Some(value)) => conwrite!(con, BytesWrapper(Bytes::from(self::serialize(&value))))?,
+            Ok(None) | Err(()) => conwrite!(con, groups::NIL)?,
+        }
+        Ok(())
+    }
+);
+
+action!(
+    /// Run a `RESTOREKEY <key> <blob> [REPLACE]` query. This restores the value in a blob
+    /// returned by `DUMPKEY` into `key`. Existing keys are only overwritten with `REPLACE`
+    fn restorekey(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(2, 3));
+        throttle_writes!(con, handle);
+        let key = next_or_err!(act, con);
+        let blob = next_or_err!(act, con);
+        let replace = match act.next() {
+            Some(arg) if arg.eq_ignore_ascii_case(REPLACE) => true,
+            Some(_) => return conwrite!(con, groups::ACTION_ERR),
+            None => false,
+        };
+        let value = match self::deserialize(&blob) {
+            Ok(value) => value,
+            Err(e) => return conwrite!(con, e.response()),
+        };
+        if registry::state_okay() {
+            let kve = kve!(con, handle);
+            check_value_sizes!(con, kve, Some(value.len()));
+            let (key, value) = (Data::from(key), Data::from(value));
+            let res = if replace {
+                kve.upsert(key, value).map(|_| true)
+            } else {
+                kve.set(key, value)
+            };
+            match res {
+                Ok(true) => conwrite!(con, groups::OKAY)?,
+                Ok(false) => conwrite!(con, groups::OVERWRITE_ERR)?,
+                Err(()) => conwrite!(con, groups::ENCODING_ERROR)?,
+            }
+        } else {
+            conwrite!(con, groups::SERVER_ERR)?;
+        }
+        Ok(())
+    }
+);
+
+#[test]
+fn test_dump_roundtrip_binary() {
+    let values: [&[u8]; 4] = [b"", b"hello", &[0, 159, 255, 10, 13], &[0xFF; 1024]];
+    for value in values.iter() {
+        let blob = serialize(value);
+        assert!(blob.iter().all(|b| b.is_ascii_hexdigit()));
+        assert_eq!(deserialize(&blob).unwrap(), *value);
+    }
+}
+
+#[test]
+fn test_dump_corrupted() {
+    let mut blob = serialize(b"hello");
+    // a flipped bit in the value fails the checksum
+    blob[HEADER_SIZE * 2 + 1] ^= 0b1;
+    assert_eq!(deserialize(&blob).unwrap_err(), DumpError::Corrupted);
+    // truncated
+    let blob = serialize(b"hello");
+    assert_eq!(
+        deserialize(&blob[..blob.len() - 2]).unwrap_err(),
+        DumpError::Corrupted
+    );
+    // not hex
+    assert_eq!(deserialize(b"01zz").unwrap_err(), DumpError::Corrupted);
+    assert_eq!(deserialize(b"").unwrap_err(), DumpError::Corrupted);
+}
+
+#[cfg(test)]
+/// Hex encode a raw (already checksummed) blob
+fn encode_raw(blob: &[u8]) -> Vec<u8> {
+    blob.iter()
+        .flat_map(|byte| {
+            vec![
+                HEX_DIGITS[(byte >> 4) as usize],
+                HEX_DIGITS[(byte & 0x0F) as usize],
+            ]
+        })
+        .collect()
+}
+
+#[cfg(test)]
+/// Build a raw blob with the given version and flags and append the checksum
+fn raw_blob(version: u8, flags: u8, rest: &[u8]) -> Vec<u8> {
+    let mut blob = vec![version, flags];
+    blob.extend_from_slice(rest);
+    let checksum = crc32c(&blob);
+    blob.extend_from_slice(&checksum.to_le_bytes());
+    blob
+}
+
+#[test]
+fn test_dump_unknown_version() {
+    let mut rest = 5u64.to_le_bytes().to_vec();
+    rest.extend_from_slice(b"hello");
+    let future = encode_raw(&raw_blob(DUMP_VERSION + 1, 0, &rest));
+    assert_eq!(deserialize(&future).unwrap_err(), DumpError::UnknownVersion);
+    // the same blob is good with our version
+    let current = encode_raw(&raw_blob(DUMP_VERSION, 0, &rest));
+    assert_eq!(deserialize(&current).unwrap(), b"hello");
+}
+
+#[test]
+fn test_dump_metadata_and_flags() {
+    let mut rest = 5u64.to_le_bytes().to_vec();
+    rest.extend_from_slice(b"hello");
+    rest.extend_from_slice(&3u64.to_le_bytes());
+    rest.extend_from_slice(b"ttl");
+    // metadata is skipped
+    let blob = encode_raw(&raw_blob(DUMP_VERSION, FLAG_HAS_METADATA, &rest));
+    assert_eq!(deserialize(&blob).unwrap(), b"hello");
+    // but we don't know of any other flags
+    let blob = encode_raw(&raw_blob(DUMP_VERSION, 0b10 | FLAG_HAS_METADATA, &rest));
+    assert_eq!(deserialize(&blob).unwrap_err(), DumpError::Corrupted);
+    // trailing bytes without the metadata flag
+    let blob = encode_raw(&raw_blob(DUMP_VERSION, 0, &rest));
+    assert_eq!(deserialize(&blob).unwrap_err(), DumpError::Corrupted);
+}
@@ -27,21 +27,32 @@
 //! # `EXISTS` queries
 //! This module provides functions to work with `EXISTS` queries
 
+use crate::actions::yield_on_chunk;
 use crate::dbnet::connection::prelude::*;
 use crate::queryengine::ActionIter;
 
 action!(
-    /// Run an `EXISTS` query
+    /// Run an `EXISTS` query. The keys are looked up in chunks, yielding to the runtime in
+    /// between
     fn exists(handle: &Corestore, con: &mut T, act: ActionIter) {
-        err_if_len_is!(act, con, eq 0);
+        check_arity!(act, con, Arity::NonZero);
+        ensure_readable!(con);
         let mut how_many_of_them_exist = 0usize;
+        if handle.ctable_surely_lacks(act.as_slice()) {
+            // the bloom filter of a table that hasn't been read in yet says that none of them
+            // exist, so don't read it in
+            con.write_response(how_many_of_them_exist).await?;
+            return Ok(());
+        }
         {
             let cmap = kve!(con, handle);
-            act.for_each(|key| {
-                if not_enc_err!(cmap.exists(key)) {
+            let now = handle.get_store().get_clock().now().timestamp_millis();
+            for (i, key) in act.enumerate() {
+                yield_on_chunk(i).await;
+                if not_enc_err!(cmap.exists(key, now)) {
                     how_many_of_them_exist += 1;
                 }
-            });
+            }
         }
         con.write_response(how_many_of_them_exist).await?;
         Ok(())
@@ -0,0 +1,97 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `EXPIREPREFIX` and `PERSISTPREFIX` queries
+//!
+//! These set and remove the expiry time on every key that starts with a prefix. The expiry
+//! times are absolute (milliseconds since the unix epoch, as returned by the store's clock) and
+//! keys that are past them are removed by the expiry sweeper. Like the deletion protection
+//! flags, the expiry times are persisted along with the table (as `<table>.expiry`) and are
+//! included in snapshots, so a restored snapshot still expires its keys at the original times
+
+use crate::actions::yield_on_chunk;
+use crate::dbnet::connection::prelude::*;
+
+action!(
+    /// Run an `EXPIREPREFIX <prefix> <seconds>` query, returning the number of keys whose
+    /// expiry time was set. The keys are walked in chunks, yielding to the runtime in between,
+    /// and the response is an error if the system was poisoned before they were all done
+    fn expireprefix(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(2));
+        throttle_writes!(con, handle);
+        let epoch = registry::poison_epoch();
+        if !registry::state_okay() {
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        let prefix = next_or_err!(act, con);
+        let seconds = next_or_err!(act, con);
+        let seconds = match String::from_utf8_lossy(&seconds).parse::<u32>() {
+            Ok(seconds) => seconds,
+            Err(_) => return con.write_response(responses::groups::WRONGTYPE_ERR).await,
+        };
+        let at =
+            handle.get_store().get_clock().now().timestamp_millis() + i64::from(seconds) * 1000;
+        let kve = kve!(con, handle);
+        let mut many = 0usize;
+        for (i, key) in kve.keys_with_prefix(&prefix).iter().enumerate() {
+            yield_on_chunk(i).await;
+            // the key may have been removed since we listed it
+            if kve.set_expiry(key, at) {
+                many += 1;
+            }
+        }
+        if !registry::unpoisoned_since(epoch) {
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        con.write_response(many).await
+    }
+);
+
+action!(
+    /// Run a `PERSISTPREFIX <prefix>` query, returning the number of keys whose expiry time
+    /// was removed. Just like `EXPIREPREFIX`, the keys are walked in chunks
+    fn persistprefix(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        throttle_writes!(con, handle);
+        let epoch = registry::poison_epoch();
+        if !registry::state_okay() {
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        let prefix = next_or_err!(act, con);
+        let kve = kve!(con, handle);
+        let mut many = 0usize;
+        for (i, key) in kve.keys_with_prefix(&prefix).iter().enumerate() {
+            yield_on_chunk(i).await;
+            if kve.clear_expiry(key) {
+                many += 1;
+            }
+        }
+        if !registry::unpoisoned_since(epoch) {
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        con.write_response(many).await
+    }
+);
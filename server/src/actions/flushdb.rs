@@ -24,27 +24,117 @@
  *
 */
 
+use crate::corestore::memstore::ObjectID;
 use crate::dbnet::connection::prelude::*;
 use crate::queryengine::ActionIter;
+use crate::resp::BytesWrapper;
+use bytes::Bytes;
+
+const FORCE: &[u8] = "FORCE".as_bytes();
 
 action!(
-    /// Delete all the keys in the database
+    /// Delete all the keys in the database: `FLUSHDB [<entity>] [FORCE]`
+    ///
+    /// Keys that are protected from deletion are left behind and the number of such keys is
+    /// returned instead of `Okay`. With `FORCE`, the protected keys are deleted too
     fn flushdb(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, gt 1);
+        check_arity!(act, con, Arity::AtMost(2));
+        let force = matches!(act.as_ref().last(), Some(arg) if arg.eq_ignore_ascii_case(FORCE));
+        if act.len() - force as usize > 1 {
+            // two args, but the last one wasn't FORCE
+            return conwrite!(con, responses::groups::ACTION_ERR);
+        }
+        // this flushes a table that might not be the one in use, so the table is checked
+        // once it's known instead of with the rest of the checks for writes
+        throttle_writes!(@pass con);
         if registry::state_okay() {
-            if act.len() == 0 {
+            let table = if act.len() == force as usize {
                 // flush the current table
-                get_tbl!(handle, con).truncate_table();
+                get_tbl!(handle, con)
             } else {
                 // flush the entity
-                let raw_entity = unsafe { act.next().unsafe_unwrap() };
+                let raw_entity = next_or_err!(act, con);
                 let entity = handle_entity!(con, raw_entity);
-                get_tbl!(entity, handle, con).truncate_table();
+                get_tbl!(entity, handle, con)
+            };
+            if table.is_read_only() {
+                return conwrite!(con, responses::groups::READ_ONLY_ENTITY);
+            }
+            if force {
+                table.truncate_table();
+                conwrite!(con, responses::groups::OKAY)?;
+            } else {
+                match table.truncate_unprotected() {
+                    0 => conwrite!(con, responses::groups::OKAY)?,
+                    skipped => conwrite!(con, skipped)?,
+                }
             }
-            conwrite!(con, responses::groups::OKAY)?;
         } else {
             conwrite!(con, responses::groups::SERVER_ERR)?;
         }
         Ok(())
     }
 );
+
+action!(
+    /// Clear every table in a keyspace: `FLUSHKS [<keyspace>]` (the current keyspace, if it
+    /// isn't given)
+    ///
+    /// Every table is cleared with [`Table::clear`](crate::corestore::table::Table::clear),
+    /// so the keys that are protected from deletion are left behind (volatile tables are
+    /// cleared just like the others). This returns a flat array with the name of every
+    /// table (in order), the number of keys that were removed from it and the number of
+    /// protected keys that were left behind. The worker yields after every table, so that a
+    /// keyspace with many tables doesn't starve the other connections
+    fn flushks(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        // like `FLUSHDB`, the keyspace is checked below
+        throttle_writes!(@pass con);
+        let keyspace = match act.next() {
+            Some(ksid) if ksid.len() > 64 => {
+                return conwrite!(con, responses::groups::BAD_CONTAINER_NAME);
+            }
+            Some(ksid) => match handle.get_keyspace(&ksid[..]) {
+                Some(keyspace) => keyspace,
+                None => return conwrite!(con, responses::groups::CONTAINER_NOT_FOUND),
+            },
+            None => match handle.get_cks() {
+                Some(keyspace) => keyspace,
+                None => return conwrite!(con, responses::groups::DEFAULT_UNSET),
+            },
+        };
+        if keyspace.is_read_only() {
+            return conwrite!(con, responses::groups::READ_ONLY_ENTITY);
+        }
+        if keyspace.is_archived() {
+            return conwrite!(con, responses::groups::KEYSPACE_ARCHIVED);
+        }
+        if !registry::state_okay() {
+            return conwrite!(con, responses::groups::SERVER_ERR);
+        }
+        let mut tables: Vec<(ObjectID, _)> = keyspace
+            .tables
+            .iter()
+            .map(|table| (table.key().clone(), table.value().clone()))
+            .collect();
+        tables.sort_by(|(a, _), (b, _)| a.cmp(b));
+        // the response can't fail half way through, so every table is read in first
+        for (_, table) in tables.iter() {
+            if !table.wait_loaded().await {
+                return conwrite!(con, responses::groups::SERVER_ERR);
+            }
+        }
+        con.write_flat_array_length(tables.len() * 3).await?;
+        for (tblid, table) in tables {
+            let cleared = table.clear();
+            let skipped = table.protected_count();
+            con.write_response(tblid).await?;
+            for count in [cleared, skipped].iter() {
+                con.write_response(BytesWrapper(Bytes::from(count.to_string())))
+                    .await?;
+            }
+            tokio::task::yield_now().await;
+        }
+        Ok(())
+    }
+);
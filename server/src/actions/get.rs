@@ -28,30 +28,47 @@
 //! This module provides functions to work with `GET` queries
 
 use crate::dbnet::connection::prelude::*;
-use crate::resp::BytesWrapper;
-use bytes::Bytes;
+use crate::kvengine::ReadError;
+use crate::resp::{BytesWrapper, FrameWrapper};
 
 action!(
     /// Run a `GET` query
     fn get(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, not 1);
-        let res: Option<Bytes> = {
-            let reader = kve!(con, handle);
-            unsafe {
-                // UNSAFE(@ohsayan): this is safe because we've already checked if the action
-                // group contains one argument (excluding the action itself)
-                match reader.get(act.next().unsafe_unwrap()) {
-                    Ok(v) => v.map(|b| b.get_blob().clone()),
-                    Err(_) => None,
+        check_arity!(act, con, Arity::Exactly(1));
+        ensure_readable!(con);
+        let key = next_or_err!(act, con);
+        if handle.ctable_surely_lacks(&[&key]) {
+            // nothing to read in for a key that the table's bloom filter doesn't have
+            con.write_response(responses::groups::NIL).await?;
+            return Ok(());
+        }
+        let kve = kve!(con, handle);
+        let now = handle.get_store().get_clock().now().timestamp_millis();
+        if kve.hooks().on_reads() {
+            // the table keeps the frames, so there's nothing to put together on a hit
+            match kve.get_frame(key, now) {
+                Ok(Some(frame)) => con.write_response(FrameWrapper(frame)).await?,
+                Err(ReadError::Unreadable) => {
+                    con.write_response(responses::groups::VALUE_UNREADABLE)
+                        .await?
+                }
+                Ok(None) | Err(ReadError::BadKey) => {
+                    con.write_response(responses::groups::NIL).await?
                 }
             }
-        };
-        if let Some(value) = res {
+            return Ok(());
+        }
+        // a table without read hooks checks the encoding, looks the key up and clones the value
+        match kve.read(key, now) {
             // Good, we got the value, write it off to the stream
-            con.write_response(BytesWrapper(value)).await?;
-        } else {
-            // Ah, couldn't find that key
-            con.write_response(responses::groups::NIL).await?;
+            Ok(Some(value)) => con.write_response(BytesWrapper(value)).await?,
+            // The value is there, but it can't be returned
+            Err(ReadError::Unreadable) => {
+                con.write_response(responses::groups::VALUE_UNREADABLE)
+                    .await?
+            }
+            // Ah, couldn't find that key (a key with the wrong encoding can't be in the table)
+            Ok(None) | Err(ReadError::BadKey) => con.write_response(responses::groups::NIL).await?,
         }
         Ok(())
     }
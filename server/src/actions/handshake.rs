@@ -0,0 +1,51 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `HANDSHAKE` queries
+//!
+//! See [`dbnet::handshake`](crate::dbnet::handshake) for the capabilities that can be
+//! negotiated
+
+use crate::dbnet::connection::prelude::*;
+use crate::resp::BytesWrapper;
+use bytes::Bytes;
+
+action!(
+    /// Run a `HANDSHAKE <capability> ...` query. This returns a flat array of the accepted
+    /// capabilities, which take effect from the next query on
+    fn handshake(_handle: &Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::NonZero);
+        let mut capabilities = *con.get_capabilities();
+        let accepted: Vec<String> = act.filter_map(|cap| capabilities.negotiate(&cap)).collect();
+        con.write_flat_array_length(accepted.len()).await?;
+        for capability in accepted {
+            con.write_response(BytesWrapper(Bytes::from(capability)))
+                .await?;
+        }
+        *con.get_mut_capabilities() = capabilities;
+        Ok(())
+    }
+);
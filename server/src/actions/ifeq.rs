@@ -0,0 +1,117 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `IFEQ` queries
+//!
+//! `IFEQ <key> <expected> <action> [<value>]` runs a single write on `key`, but only if the key
+//! holds `expected`. The embedded action is `SET <value>` or `UPDATE <value>` (which both
+//! replace the value of the key, since a key that matched always exists) or `DEL`, and it
+//! always works on the key of the `IFEQ`. The comparison and the write are a single operation
+//! on the key's entry, so this is "GET the key and if it's X, SET it to Y" without the race
+//!
+//! The response is that of the embedded action (`OKAY` for `SET` and `UPDATE`, `1` for `DEL`)
+//! if the key matched, or `not-matched` if the key doesn't exist or holds something else
+
+use crate::corestore::Data;
+use crate::dbnet::connection::prelude::*;
+use crate::kvengine::IfEq;
+use crate::queryengine;
+use bytes::Bytes;
+
+/// Replace the value of the key
+const SET: &[u8] = b"SET";
+/// Same as `SET` (in an `IFEQ`)
+const UPDATE: &[u8] = b"UPDATE";
+/// Remove the key
+const DEL: &[u8] = b"DEL";
+
+/// The write that an `IFEQ` makes if the key matched
+enum Embedded {
+    /// `SET` or `UPDATE`
+    Update(Bytes),
+    /// `DEL`
+    Delete,
+}
+
+action!(
+    /// Run an `IFEQ <key> <expected> SET|UPDATE <value>` or an `IFEQ <key> <expected> DEL`
+    /// query
+    fn ifeq(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(3, 4));
+        let key = next_or_err!(act, con);
+        let expected = next_or_err!(act, con);
+        let name = next_or_err!(act, con);
+        let embedded = match self::parse_embedded(&name, &mut act) {
+            Ok(embedded) => embedded,
+            Err(e) => return con.write_response(e).await,
+        };
+        // deleting frees memory, so only the writes are kept out by the memory guard
+        if !matches!(embedded, Embedded::Delete) && registry::get_memory_guard().is_blocking() {
+            return con.write_error(Hinted::OutOfMemory).await;
+        }
+        throttle_writes!(con, handle, frees_memory);
+        if !registry::state_okay() {
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        let kve = kve!(con, handle);
+        let outcome = match embedded {
+            Embedded::Update(value) => {
+                check_value_sizes!(con, kve, Some(value.len()));
+                kve.update_if_eq(Data::from(key), &expected, Data::from(value))
+            }
+            Embedded::Delete => kve.remove_if_eq(Data::from(key), &expected),
+        };
+        match outcome {
+            Ok(IfEq::Written) if name.eq_ignore_ascii_case(DEL) => con.write_response(1usize).await,
+            Ok(IfEq::Written) => con.write_response(responses::groups::OKAY).await,
+            Ok(IfEq::NotMatched) => con.write_response(responses::groups::NOT_MATCHED).await,
+            Ok(IfEq::Protected) => con.write_response(responses::groups::PROTECTED_KEY).await,
+            Err(()) => con.write_error(Hinted::Encoding).await,
+        }
+    }
+);
+
+/// Parse the action embedded in an `IFEQ` (named `name`, with its arguments being the rest of
+/// `act`), returning the error to respond with if it isn't one of the actions that can be
+/// embedded, has the wrong number of arguments or is disabled
+fn parse_embedded(name: &[u8], act: &mut ActionIter) -> Result<Embedded, &'static [u8]> {
+    let (action, arity) = [(SET, 1), (UPDATE, 1), (DEL, 0)]
+        .iter()
+        .copied()
+        .find(|(action, _)| action.eq_ignore_ascii_case(name))
+        .ok_or(responses::groups::UNKNOWN_ACTION)?;
+    if act.len() != arity {
+        return Err(responses::groups::ACTION_ERR);
+    }
+    if queryengine::is_disabled(action) {
+        return Err(responses::groups::ACTION_DISABLED);
+    }
+    let mut args = act.take_exact(arity).ok_or(responses::groups::ACTION_ERR)?;
+    match arity {
+        0 => Ok(Embedded::Delete),
+        _ => args.next_or_err().map(Embedded::Update),
+    }
+}
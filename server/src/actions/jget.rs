@@ -41,7 +41,7 @@ action! {
     /// ```
     ///
     fn jget(_handle: &crate::corestore::Corestore, con: &mut T, act: ActionIter) {
-        err_if_len_is!(act, con, not 1);
+        check_arity!(act, con, Arity::Exactly(1));
         todo!()
     }
 }
@@ -31,24 +31,15 @@ action!(
     ///
     /// At this moment, `keylen` only supports a single key
     fn keylen(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, not 1);
-        let res: Option<usize> = {
-            let reader = kve!(con, handle);
-            unsafe {
-                // UNSAFE(@ohsayan): this is completely safe as we've already checked
-                // the number of arguments is one
-                match reader.get(act.next().unsafe_unwrap()) {
-                    Ok(v) => v.map(|b| b.len()),
-                    Err(_) => None,
-                }
-            }
-        };
-        if let Some(value) = res {
+        check_arity!(act, con, Arity::Exactly(1));
+        ensure_readable!(con);
+        let key = next_or_err!(act, con);
+        let now = handle.get_store().get_clock().now().timestamp_millis();
+        match kve!(con, handle).get_cloned(key, now) {
             // Good, we got the key's length, write it off to the stream
-            con.write_response(value).await?;
-        } else {
+            Ok(Some(value)) => con.write_response(value.len()).await?,
             // Ah, couldn't find that key
-            con.write_response(responses::groups::NIL).await?;
+            Ok(None) | Err(()) => con.write_response(responses::groups::NIL).await?,
         }
         Ok(())
     }
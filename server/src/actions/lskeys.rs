@@ -26,21 +26,42 @@
 
 use crate::corestore::memstore::DdlError;
 use crate::dbnet::connection::prelude::*;
+use crate::registry;
 use crate::resp::BytesWrapper;
 use bytes::Bytes;
 
 const DEFAULT_COUNT: usize = 10;
+const ORDERED: &[u8] = "ORDERED".as_bytes();
 
 action!(
-    /// Run an `LSKEYS` query
+    /// Run an `LSKEYS [<entity>] [<count>] [ORDERED]` query
+    ///
+    /// With `ORDERED`, the keys are returned in ascending byte order so that two runs over the
+    /// same data return the same thing. Tables with an ordered index are read off the index;
+    /// the keys of any other table are sorted, which is refused for tables with more than
+    /// `maxorderedkeys` keys
     fn lskeys(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, gt 3);
-        let (table, count) = if act.len() == 0 {
+        check_arity!(act, con, Arity::AtMost(4));
+        ensure_readable!(con);
+        // the flag is always the last argument, and it's left in the iterator
+        let ordered = matches!(
+            act.as_slice().last(),
+            Some(arg) if arg.eq_ignore_ascii_case(ORDERED)
+        );
+        let argc = act.len() - ordered as usize;
+        if argc > 2 {
+            return conwrite!(con, groups::ACTION_ERR);
+        }
+        let (table, count) = if argc == 0 {
             (get_tbl!(handle, con), DEFAULT_COUNT)
-        } else if act.len() == 1 {
+        } else if argc == 1 {
             // two args, could either be count or an entity
-            let nextret = unsafe { act.next().unsafe_unwrap() };
-            if unsafe { nextret.get_unchecked(0) }.is_ascii_digit() {
+            let is_count = matches!(
+                act.peek().and_then(|arg| arg.first()),
+                Some(byte) if byte.is_ascii_digit()
+            );
+            let nextret = next_or_err!(act, con);
+            if is_count {
                 // noice, this is a number; let's try to parse it
                 let count = if let Ok(cnt) = String::from_utf8_lossy(&nextret).parse::<usize>() {
                     cnt
@@ -55,8 +76,8 @@ action!(
             }
         } else {
             // an entity and a count, gosh this fella is really trying us
-            let entity_ret = unsafe { act.next().unsafe_unwrap() };
-            let count_ret = unsafe { act.next().unsafe_unwrap() };
+            let entity_ret = next_or_err!(act, con);
+            let count_ret = next_or_err!(act, con);
             let entity = handle_entity!(con, entity_ret);
             let count = if let Ok(cnt) = String::from_utf8_lossy(&count_ret).parse::<usize>() {
                 cnt
@@ -68,13 +89,22 @@ action!(
         let kve = match table.get_kvstore() {
             Ok(kv) => kv,
             Err(DdlError::WrongModel) => return conwrite!(con, responses::groups::WRONG_MODEL),
+            // it was archived in the meantime
+            Err(DdlError::Archived) => return conwrite!(con, responses::groups::KEYSPACE_ARCHIVED),
             Err(_) => unsafe { impossible!() },
         };
-        let items: Vec<Bytes> = kve.__get_inner_ref().get_keys(count);
-        con.write_flat_array_length(items.len()).await?;
+        let items: Vec<Bytes> = if ordered {
+            match kve.ordered_keys(count, registry::get_max_ordered_keys()) {
+                Ok(keys) => keys.into_iter().map(|key| key.into_inner()).collect(),
+                Err(_) => return conwrite!(con, groups::TOO_MANY_TO_ORDER),
+            }
+        } else {
+            kve.__get_inner_ref().get_keys(count)
+        };
+        let mut array = con.start_flat_array(items.len()).await?;
         for item in items {
-            con.write_response(BytesWrapper(item)).await?;
+            array.write(BytesWrapper(item)).await?;
         }
-        Ok(())
+        array.finish()
     }
 );
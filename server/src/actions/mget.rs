@@ -24,31 +24,95 @@
  *
 */
 
+use crate::actions::yield_on_chunk;
 use crate::dbnet::connection::prelude::*;
+use crate::kvengine::ReadError;
 use crate::queryengine::ActionIter;
+use crate::resp::tristate::TriState;
 use crate::resp::BytesWrapper;
 use bytes::Bytes;
-use skytable::RespCode;
+
+const WITHMISSING: &[u8] = "WITHMISSING".as_bytes();
 
 action!(
-    /// Run an `MGET` query
-    ///
+    /// Run an `MGET` query. The keys are looked up in chunks, yielding to the runtime in
+    /// between
     fn mget(handle: &crate::corestore::Corestore, con: &mut T, act: ActionIter) {
-        crate::err_if_len_is!(act, con, eq 0);
-        con.write_array_length(act.len()).await?;
-        for key in act {
-            let res: Option<Bytes> = match kve!(con, handle).get(key) {
-                Ok(v) => v.map(|b| b.get_blob().clone()),
-                Err(_) => None,
+        check_arity!(act, con, Arity::NonZero);
+        ensure_readable!(con);
+        let tristate = con.get_capabilities().tristate;
+        // the table has to be there before the array is started
+        let kve = kve!(con, handle);
+        let now = handle.get_store().get_clock().now().timestamp_millis();
+        let mut array = con.start_array(act.len()).await?;
+        for (i, key) in act.enumerate() {
+            yield_on_chunk(i).await;
+            let element = match kve.read(key, now) {
+                // without the tristate encoding, the keys that couldn't be decoded have
+                // always been reported as missing
+                Err(ReadError::BadKey) if !tristate => TriState::Null,
+                result => TriState::from_lookup(result),
             };
-            if let Some(value) = res {
-                // Good, we got the value, write it off to the stream
-                con.write_response(BytesWrapper(value)).await?;
-            } else {
-                // Ah, couldn't find that key
-                con.write_response(RespCode::NotFound).await?;
+            array.write_element(element).await?;
+        }
+        array.finish()
+    }
+);
+
+action!(
+    /// Run a `GETM <key>... WITHMISSING` query. The keys are looked up just like they are for
+    /// `MGET`, but the response is split into sections instead of having an element for every
+    /// key: an array of a flat array of the keys that were found along with their values
+    /// (`<key> <value>` for every key), followed by a flat array of the keys that are missing.
+    /// If some keys can't be in the table at all (their encoding doesn't match the table's) or
+    /// hold values that can't be decoded, they are in a third flat array, which is only there
+    /// if there are such keys. Every
+    /// section echoes the keys as they were sent, in the order that they were sent in, so a key
+    /// that was sent twice is in its section twice
+    fn getm(handle: &crate::corestore::Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::AtLeast(2));
+        ensure_readable!(con);
+        // the flag is always the last argument (which leaves room for other forms)
+        let flagged = matches!(
+            act.as_slice().last(),
+            Some(arg) if arg.eq_ignore_ascii_case(WITHMISSING)
+        );
+        if !flagged {
+            return conwrite!(con, groups::ACTION_ERR);
+        }
+        let count = act.len() - 1;
+        let kve = kve!(con, handle);
+        let now = handle.get_store().get_clock().now().timestamp_millis();
+        let mut found: Vec<(Bytes, Bytes)> = Vec::new();
+        let mut missing: Vec<Bytes> = Vec::new();
+        let mut bad: Vec<Bytes> = Vec::new();
+        for (i, key) in act.take(count).enumerate() {
+            yield_on_chunk(i).await;
+            match kve.read(key.clone(), now) {
+                Ok(Some(value)) => found.push((key, value)),
+                Ok(None) => missing.push(key),
+                Err(_) => bad.push(key),
+            }
+        }
+        let mut array = con.start_array(if bad.is_empty() { 2 } else { 3 }).await?;
+        let mut pairs = array.start_flat_array(found.len() * 2).await?;
+        for (key, value) in found {
+            pairs.write(BytesWrapper(key)).await?;
+            pairs.write(BytesWrapper(value)).await?;
+        }
+        pairs.finish()?;
+        let mut keys = array.start_flat_array(missing.len()).await?;
+        for key in missing {
+            keys.write(BytesWrapper(key)).await?;
+        }
+        keys.finish()?;
+        if !bad.is_empty() {
+            let mut keys = array.start_flat_array(bad.len()).await?;
+            for key in bad {
+                keys.write(BytesWrapper(key)).await?;
             }
+            keys.finish()?;
         }
-        Ok(())
+        array.finish()
     }
 );
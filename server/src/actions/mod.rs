@@ -32,20 +32,40 @@
 
 pub mod dbsize;
 pub mod del;
+pub mod dump;
 pub mod exists;
+pub mod expire;
 pub mod flushdb;
 pub mod get;
+pub mod handshake;
+pub mod ifeq;
 pub mod jget;
 pub mod keylen;
 pub mod lskeys;
 pub mod mget;
 pub mod mset;
 pub mod mupdate;
+pub mod object;
 pub mod pop;
+pub mod rangekeys;
 pub mod set;
 pub mod strong;
+pub mod syncstream;
 pub mod update;
 pub mod uset;
+pub mod validate;
+
+#[cfg(test)]
+mod tests;
+
+use crate::corestore::Data;
+use crate::dbnet::connection::prelude::*;
+use crate::kvengine::PairStatus;
+use crate::resp::BytesWrapper;
+use bytes::Bytes;
+
+const STRICT: &[u8] = "STRICT".as_bytes();
+
 pub mod heya {
     //! Respond to `HEYA` queries
     use crate::dbnet::connection::prelude::*;
@@ -57,6 +77,24 @@ pub mod heya {
     );
 }
 
+/// The number of keys that the multi-key actions (`DEL`, `EXISTS`, `MGET` and `MSET`) work
+/// through before yielding to the runtime, so that a query with a huge number of keys doesn't
+/// starve the other connections on the same worker. This has to be a power of two
+pub const CHUNK_SIZE: usize = 4096;
+
+/// Call this before working on the `i`th key of a multi-key action. At the start of every
+/// chunk of [`CHUNK_SIZE`] keys (except the first), this yields to the runtime and returns
+/// true so that the action can recheck whatever it checks once per chunk
+pub async fn yield_on_chunk(i: usize) -> bool {
+    // see the note on modulo below
+    if i != 0 && i & (CHUNK_SIZE - 1) == 0 {
+        tokio::task::yield_now().await;
+        true
+    } else {
+        false
+    }
+}
+
 /*
  Don't modulo because it's an L1 miss and an L2 hit. Use lowbit checks to check for parity
 */
@@ -79,55 +117,138 @@ macro_rules! is_lowbit_unset {
     };
 }
 
-#[macro_export]
-macro_rules! err_if_len_is {
-    ($buf:ident, $con:ident, eq $len:literal) => {
-        if $buf.len() == $len {
-            return $con
-                .write_response(crate::protocol::responses::groups::ACTION_ERR)
-                .await;
-        }
-    };
-    ($buf:ident, $con:ident, not $len:literal) => {
-        if $buf.len() != $len {
-            return $con
-                .write_response(crate::protocol::responses::groups::ACTION_ERR)
-                .await;
-        }
-    };
-    ($buf:ident, $con:ident, gt $len:literal) => {
-        if $buf.len() > $len {
-            return $con
-                .write_response(crate::protocol::responses::groups::ACTION_ERR)
-                .await;
+/// The number of arguments that an action accepts. Actions check this with
+/// [`check_arity!`](crate::check_arity) and respond with the [arity error](Arity::error)
+/// that names the rule if they get any other number of arguments
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    /// Exactly `n` arguments
+    Exactly(usize),
+    /// Atleast `n` arguments
+    AtLeast(usize),
+    /// Atmost `n` arguments
+    AtMost(usize),
+    /// Anything from `min` to `max` (both inclusive) arguments
+    Between(usize, usize),
+    /// Atleast one argument
+    NonZero,
+    /// An even number of arguments (like `<key> <value>` pairs), and atleast `min` of them
+    Even(usize),
+    /// An odd number of arguments, and atleast `min` of them
+    Odd(usize),
+}
+
+impl Arity {
+    /// Check if `len` arguments satisfy the rule
+    pub const fn allows(&self, len: usize) -> bool {
+        match *self {
+            Self::Exactly(n) => len == n,
+            Self::AtLeast(n) => len >= n,
+            Self::AtMost(n) => len <= n,
+            Self::Between(min, max) => len >= min && len <= max,
+            Self::NonZero => len != 0,
+            Self::Even(min) => is_lowbit_unset!(len) && len >= min,
+            Self::Odd(min) => is_lowbit_set!(len) && len >= min,
         }
-    };
-    ($buf:ident, $con:ident, lt $len:literal) => {
-        if $buf.len() < $len {
-            return $con
-                .write_response(crate::protocol::responses::groups::ACTION_ERR)
-                .await;
+    }
+    /// The name of the rule, like `exactly-1` or `even-at-least-2`
+    pub fn rule(&self) -> String {
+        match *self {
+            Self::Exactly(n) => format!("exactly-{}", n),
+            Self::AtLeast(n) => format!("at-least-{}", n),
+            Self::AtMost(n) => format!("at-most-{}", n),
+            Self::Between(min, max) => format!("between-{}-and-{}", min, max),
+            Self::NonZero => "nonzero".to_owned(),
+            Self::Even(min) => format!("even-at-least-{}", min),
+            Self::Odd(min) => format!("odd-at-least-{}", min),
         }
-    };
-    ($buf:ident, $con:ident, gt_or_eq $len:literal) => {
-        if $buf.len() >= $len {
-            return $con
-                .write_response(crate::protocol::responses::groups::ACTION_ERR)
-                .await;
+    }
+    /// The error string that is returned when the rule isn't satisfied: `bad-arity:<rule>`
+    pub fn error(&self) -> Vec<u8> {
+        let error = format!("bad-arity:{}", self.rule());
+        format!("!{}\n{}\n", error.len(), error).into_bytes()
+    }
+}
+
+#[macro_export]
+/// Check the number of arguments in an [`ActionIter`](crate::queryengine::ActionIter)
+/// against an [`Arity`](crate::actions::Arity); if they don't match, the arity error is
+/// written and the action returns. For example: `check_arity!(act, con, Arity::Exactly(1))`
+macro_rules! check_arity {
+    ($buf:ident, $con:ident, $arity:expr) => {{
+        let arity: crate::actions::Arity = $arity;
+        if !arity.allows($buf.len()) {
+            return $con.write_response(arity.error()).await;
         }
-    };
-    ($buf:ident, $con:ident, lt_or_eq $len:literal) => {
-        if $buf.len() <= $len {
-            return $con
-                .write_response(crate::protocol::responses::groups::ACTION_ERR)
-                .await;
+    }};
+}
+
+#[macro_export]
+/// Get the next argument from an [`ActionIter`](crate::queryengine::ActionIter) or write an
+/// action error and return. This is meant to be used after the arity has been checked with
+/// `check_arity!`
+macro_rules! next_or_err {
+    ($buf:ident, $con:ident) => {
+        match $buf.next_or_err() {
+            Ok(arg) => arg,
+            Err(e) => return $con.write_response(e).await,
         }
     };
-    ($con:ident, $expr:expr) => {
-        if $expr {
-            return $con
-                .write_response(crate::protocol::responses::groups::ACTION_ERR)
-                .await;
+}
+
+#[macro_export]
+/// Check the lengths of the values that an action is about to write against the limit of the
+/// table (see [`KVEngine::check_value_sizes`](crate::kvengine::KVEngine::check_value_sizes));
+/// if any of them is too large, the error is written and the action returns without writing
+/// anything. For example: `check_value_sizes!(con, kve, Some(value.len()))`
+macro_rules! check_value_sizes {
+    ($con:ident, $kve:ident, $lengths:expr) => {
+        if let Err(e) = $kve.check_value_sizes($lengths) {
+            return $con.write_response(e.error()).await;
         }
     };
 }
+
+/// Check for (and skip over) the `STRICT` flag of a multi-pair write like `MSET` or `MUPDATE`.
+/// Since the pairs always come in twos, the flag is only looked for if there's an odd number
+/// of arguments (otherwise, `STRICT` is just a key)
+pub fn take_strict_flag(act: &mut ActionIter) -> bool {
+    let strict = is_lowbit_set!(act.len())
+        && matches!(act.peek(), Some(arg) if arg.eq_ignore_ascii_case(STRICT));
+    if strict {
+        let _ = act.next();
+    }
+    strict
+}
+
+/// Returns the lengths of the values in the `<key> <value>` pairs that are left in `act`, for
+/// [`check_value_sizes!`](crate::check_value_sizes)
+pub fn value_lengths<'a>(act: &'a ActionIter) -> impl Iterator<Item = usize> + 'a {
+    act.as_slice()
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .map(|value| value.len())
+}
+
+/// Collect the `<key> <value>` pairs of a multi-pair write for
+/// [`KVEngine::write_bulk_strict`](crate::kvengine::KVEngine::write_bulk_strict)
+pub fn collect_pairs(mut act: ActionIter) -> Vec<(Data, Data)> {
+    let mut pairs = Vec::with_capacity(act.len() / 2);
+    while let (Some(key), Some(value)) = (act.next(), act.next()) {
+        pairs.push((Data::from(key), Data::from(value)));
+    }
+    pairs
+}
+
+action!(
+    /// Write the statuses of the pairs of a strict multi-pair write, in the order of the pairs
+    fn write_pair_statuses(con: &mut T, statuses: &[PairStatus]) {
+        con.write_flat_array_length(statuses.len()).await?;
+        for status in statuses {
+            con.write_response(BytesWrapper(Bytes::from_static(status.as_str().as_bytes())))
+                .await?;
+        }
+        Ok(())
+    }
+);
@@ -24,38 +24,53 @@
  *
 */
 
+use crate::actions::{
+    collect_pairs, take_strict_flag, value_lengths, write_pair_statuses, yield_on_chunk,
+};
 use crate::corestore::Data;
 use crate::dbnet::connection::prelude::*;
+use crate::kvengine::BulkWrite;
 
 action!(
-    /// Run an `MSET` query
+    /// Run an `MSET [STRICT] <key> <value> ...` query, returning the number of pairs that were
+    /// set. The pairs are set in chunks, yielding to the runtime in between, and the response is
+    /// an error if the system was poisoned before they were all set.
+    ///
+    /// With `STRICT`, either all the pairs are set or none of them are: if any of them can't
+    /// be set, the status of every pair is returned instead (see [`write_pair_statuses`])
     fn mset(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
-        let howmany = act.len();
-        if is_lowbit_set!(howmany) || howmany == 0 {
-            // An odd number of arguments means that the number of keys
-            // is not the same as the number of values, we won't run this
-            // action at all
-            return con.write_response(responses::groups::ACTION_ERR).await;
+        let strict = take_strict_flag(&mut act);
+        // the number of keys has to be the same as the number of values
+        check_arity!(act, con, Arity::Even(2));
+        throttle_writes!(con, handle);
+        let epoch = registry::poison_epoch();
+        if !registry::state_okay() {
+            return con.write_response(responses::groups::SERVER_ERR).await;
         }
-        let done_howmany: Option<usize>;
-        {
-            if registry::state_okay() {
-                let writer = kve!(con, handle);
-                let mut didmany = 0;
-                while let (Some(key), Some(val)) = (act.next(), act.next()) {
-                    if not_enc_err!(writer.set(Data::from(key), Data::from(val))) {
-                        didmany += 1;
-                    }
+        let writer = kve!(con, handle);
+        // nothing is set if any of the values is too large, even without `STRICT`
+        check_value_sizes!(con, writer, value_lengths(&act));
+        if strict {
+            let pairs = collect_pairs(act);
+            return match writer.write_bulk_strict(pairs, BulkWrite::Set) {
+                Ok(_) if !registry::unpoisoned_since(epoch) => {
+                    con.write_response(responses::groups::SERVER_ERR).await
                 }
-                done_howmany = Some(didmany);
-            } else {
-                done_howmany = None;
+                Ok(didmany) => con.write_response(didmany).await,
+                Err(statuses) => write_pair_statuses(con, &statuses).await,
+            };
+        }
+        let (mut pairs, mut didmany) = (0, 0usize);
+        while let (Some(key), Some(val)) = (act.next(), act.next()) {
+            yield_on_chunk(pairs).await;
+            pairs += 1;
+            if not_enc_err!(writer.set(Data::from(key), Data::from(val))) {
+                didmany += 1;
             }
         }
-        if let Some(done_howmany) = done_howmany {
-            return con.write_response(done_howmany as usize).await;
-        } else {
+        if !registry::unpoisoned_since(epoch) {
             return con.write_response(responses::groups::SERVER_ERR).await;
         }
+        con.write_response(didmany).await
     }
 );
@@ -0,0 +1,77 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `OBJECT` queries
+//!
+//! `OBJECT` queries introspect a single key. They are of the form
+//! `OBJECT <TYPE|ENCODING|SIZE> <key>`
+
+use crate::corestore::object::ObjectMeta;
+use crate::dbnet::connection::prelude::*;
+
+const TYPE: &[u8] = "TYPE".as_bytes();
+const ENCODING: &[u8] = "ENCODING".as_bytes();
+const SIZE: &[u8] = "SIZE".as_bytes();
+
+enum Subcommand {
+    Type,
+    Encoding,
+    Size,
+}
+
+action!(
+    /// Run an `OBJECT` query. This returns the type, the encoding or the stored size of the
+    /// value of the given key or NIL if the key doesn't exist
+    fn object(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::NonZero);
+        ensure_readable!(con);
+        let mut subcommand = next_or_err!(act, con).to_vec();
+        subcommand.make_ascii_uppercase();
+        let subcommand = match subcommand.as_ref() {
+            TYPE => Subcommand::Type,
+            ENCODING => Subcommand::Encoding,
+            SIZE => Subcommand::Size,
+            _ => return conwrite!(con, groups::UNKNOWN_OBJECT_QUERY),
+        };
+        check_arity!(act, con, Arity::Exactly(1));
+        let key = next_or_err!(act, con);
+        let (value_type, meta) = {
+            let kve = kve!(con, handle);
+            let meta = match kve.get(key) {
+                Ok(Some(value)) => Some((value.encoding(), value.stored_size())),
+                Ok(None) | Err(_) => None,
+            };
+            (kve.get_value_type(), meta)
+        };
+        match (meta, subcommand) {
+            (None, _) => conwrite!(con, groups::NIL)?,
+            (Some(_), Subcommand::Type) => conwrite!(con, value_type.as_str())?,
+            (Some((encoding, _)), Subcommand::Encoding) => conwrite!(con, encoding.as_str())?,
+            (Some((_, size)), Subcommand::Size) => conwrite!(con, size)?,
+        }
+        Ok(())
+    }
+);
@@ -24,40 +24,85 @@
  *
 */
 
+use crate::actions::yield_on_chunk;
 use crate::corestore;
+use crate::corestore::Data;
 use crate::dbnet::connection::prelude::*;
+use crate::kvengine::KVEngine;
 use crate::protocol::responses;
 use crate::queryengine::ActionIter;
-use crate::resp::BytesWrapper;
+use crate::resp::tristate::{Legacy, TriState};
+use bytes::Bytes;
 
 action!(
-    /// Run a POP action
+    /// Run a POP action. The popped values are only written out once every key was popped,
+    /// so if the system is poisoned in the meantime, the response is a single error rather
+    /// than an array with errors in it. Since the client never gets the values then, they're
+    /// put back (with their expiry times), unless their keys were set again in the meantime
     fn pop(handle: &corestore::Corestore, con: &mut T, act: ActionIter) {
-        err_if_len_is!(act, con, eq 0);
-        if registry::state_okay() {
-            con.write_array_length(act.len()).await?;
-            for key in act {
-                if !registry::state_okay() {
-                    // we keep this check just in case the server fails in-between running a
-                    // pop operation
-                    con.write_response(responses::groups::SERVER_ERR).await?;
-                } else {
-                    match kve!(con, handle).pop(key) {
-                        Ok(Some((_key, val))) => {
-                            con.write_response(BytesWrapper(val.into_inner())).await?
-                        }
-                        Ok(None) => con.write_response(responses::groups::NIL).await?,
-                        Err(_) => {
-                            con.write_response(responses::groups::ENCODING_ERROR)
-                                .await?
-                        }
-                    }
+        check_arity!(act, con, Arity::NonZero);
+        throttle_writes!(con, handle);
+        let epoch = registry::poison_epoch();
+        if !registry::state_okay() {
+            // don't begin the operation at all if the database is poisoned
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        let kve = kve!(con, handle);
+        let mut popped = Vec::with_capacity(act.len());
+        for (i, key) in act.enumerate() {
+            yield_on_chunk(i).await;
+            let at = kve.get_expiry(&key);
+            popped.push((key.clone(), at, pop_one(kve, key)));
+        }
+        if !registry::unpoisoned_since(epoch) {
+            let now = handle.get_store().get_clock().now().timestamp_millis();
+            for (i, (key, at, element)) in popped.into_iter().enumerate() {
+                yield_on_chunk(i).await;
+                if let TriState::Value(value) = element {
+                    let _ = kve.set_with_expiry(Data::from(key), Data::from(value), at, now);
                 }
             }
-        } else {
-            // don't begin the operation at all if the database is poisoned
             return con.write_response(responses::groups::SERVER_ERR).await;
         }
-        Ok(())
+        let mut array = con.start_array(popped.len()).await?;
+        for (_, _, element) in popped {
+            array.write_element(element).await?;
+        }
+        array.finish()
+    }
+);
+
+action!(
+    /// Run a `GETDEL <key>` query: this removes the key and returns the value that it had,
+    /// atomically (so if two clients run it on the same key, only one of them gets the value).
+    /// It returns NIL if the key doesn't exist, and an error if the key is protected, if it
+    /// doesn't match the encoding of the table, if its value can't be decoded (in which case
+    /// the key is left alone) or if the table isn't a key/value table
+    fn getdel(handle: &corestore::Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        throttle_writes!(con, handle);
+        if !registry::state_okay() {
+            return con.write_response(responses::groups::SERVER_ERR).await;
+        }
+        let key = next_or_err!(act, con);
+        let popped = pop_one(kve!(con, handle), key);
+        con.write_response(Legacy(popped)).await
     }
 );
+
+/// Remove `key` and return the value that it had (for `POP` and `GETDEL`)
+fn pop_one(kve: &KVEngine, key: Bytes) -> TriState {
+    if kve.is_protected(&key) {
+        return TriState::Error(responses::groups::PROTECTED_KEY);
+    }
+    if kve.is_unreadable(&key) {
+        // leave the key where it is, so that it can still be found (and looked into)
+        registry::count_unreadable_value();
+        return TriState::Error(responses::groups::VALUE_UNREADABLE);
+    }
+    match kve.pop(key) {
+        Ok(Some((_key, val))) => TriState::Value(val.into_inner()),
+        Ok(None) => TriState::Null,
+        Err(_) => TriState::Error(responses::groups::ENCODING_ERROR),
+    }
+}
@@ -0,0 +1,86 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `RANGEKEYS` queries
+//! This module provides functions to work with `RANGEKEYS` queries. These only work on tables
+//! that were created with the `ordered` property
+
+use crate::dbnet::connection::prelude::*;
+use crate::resp::BytesWrapper;
+
+const WITHVALUES: &[u8] = "WITHVALUES".as_bytes();
+
+action!(
+    /// Run a `RANGEKEYS <start> <end> [<limit>] [WITHVALUES]` query
+    ///
+    /// This returns the keys in the inclusive range `start..=end` in ascending byte order as a
+    /// flat array. With `WITHVALUES`, every key is followed by its value
+    fn rangekeys(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(2, 4));
+        ensure_readable!(con);
+        let start = next_or_err!(act, con);
+        let end = next_or_err!(act, con);
+        let (mut limit, mut with_values) = (None, false);
+        for arg in act {
+            if arg.eq_ignore_ascii_case(WITHVALUES) {
+                if with_values {
+                    return conwrite!(con, groups::DUPLICATE_OPTION);
+                }
+                with_values = true;
+            } else if limit.is_none() {
+                match String::from_utf8_lossy(&arg).parse::<usize>() {
+                    Ok(lim) => limit = Some(lim),
+                    Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+                }
+            } else {
+                return conwrite!(con, groups::ACTION_ERR);
+            }
+        }
+        let limit = limit.unwrap_or(usize::MAX);
+        let kve = kve!(con, handle);
+        if with_values {
+            let pairs = match kve.range_pairs(&start, &end, limit) {
+                Some(pairs) => pairs,
+                None => return conwrite!(con, groups::NO_ORDERED_INDEX),
+            };
+            con.write_flat_array_length(pairs.len() * 2).await?;
+            for (key, value) in pairs {
+                con.write_response(BytesWrapper(key.into_inner())).await?;
+                con.write_response(BytesWrapper(value.into_inner())).await?;
+            }
+        } else {
+            let keys = match kve.range_keys(&start, &end, limit) {
+                Some(keys) => keys,
+                None => return conwrite!(con, groups::NO_ORDERED_INDEX),
+            };
+            con.write_flat_array_length(keys.len()).await?;
+            for key in keys {
+                con.write_response(BytesWrapper(key.into_inner())).await?;
+            }
+        }
+        Ok(())
+    }
+);
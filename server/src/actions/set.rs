@@ -26,6 +26,14 @@
 
 //! # `SET` queries
 //! This module provides functions to work with `SET` queries
+//!
+//! The key and the value can be followed by options: `NX` (only set the key if it doesn't
+//! exist), `XX` (only set it if it does) and `EX <seconds>` (expire the key after that many
+//! seconds). The options only begin after the key and the value, so a value like `NX` is just
+//! a value. A plain `SET` (or one with only `EX`) sets a key that doesn't exist and is an
+//! overwrite error otherwise, while a `SET` with `NX` or `XX` returns nil if its condition
+//! kept it from writing. A key that is past its expiry time counts as missing, even if the
+//! expiry service hasn't removed it yet
 
 use crate::corestore;
 use crate::dbnet::connection::prelude::*;
@@ -33,39 +41,59 @@ use crate::protocol::responses;
 use crate::queryengine::ActionIter;
 use corestore::Data;
 
+/// Only set the key if it doesn't exist
+const NX: &[u8] = b"NX";
+/// Only set the key if it exists
+const XX: &[u8] = b"XX";
+/// Expire the key after the given number of seconds
+const EX: &[u8] = b"EX";
+
 action!(
-    /// Run a `SET` query
+    /// Run a `SET <key> <value> [NX | XX] [EX <seconds>]` query. The `NX`/`XX` check, the
+    /// write and the expiry time are a single operation on the key's entry, so a concurrent
+    /// write can't get in between
     fn set(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, not 2);
+        check_arity!(act, con, Arity::AtLeast(2));
+        throttle_writes!(con, handle);
+        let key = next_or_err!(act, con);
+        let value = next_or_err!(act, con);
+        let options = match act.parse_options_and_flags(&[EX], &[NX, XX]) {
+            Ok(options) => options,
+            Err(e) => return con.write_response(e.response()).await,
+        };
+        let (nx, xx) = (options.has(NX), options.has(XX));
+        if nx && xx {
+            return con.write_response(responses::groups::ACTION_ERR).await;
+        }
+        let ttl = match options.get(EX) {
+            Some(seconds) => match String::from_utf8_lossy(seconds).parse::<u32>() {
+                Ok(seconds) => Some(seconds),
+                Err(_) => return con.write_response(responses::groups::WRONGTYPE_ERR).await,
+            },
+            None => None,
+        };
         let did_we = {
             if registry::state_okay() {
                 let writer = kve!(con, handle);
-                // clippy thinks we're doing something complex when we aren't, at all!
-                #[allow(clippy::blocks_in_if_conditions)]
-                if unsafe {
-                    // UNSAFE(@ohsayan): This is completely safe as we've already checked
-                    // that there are exactly 2 arguments
-                    not_enc_err!(writer.set(
-                        Data::from(act.next().unsafe_unwrap()),
-                        Data::from(act.next().unsafe_unwrap()),
-                    ))
-                } {
-                    Some(true)
+                check_value_sizes!(con, writer, Some(value.len()));
+                let now = handle.get_store().get_clock().now().timestamp_millis();
+                let at = ttl.map(|seconds| now + i64::from(seconds) * 1000);
+                let (key, value) = (Data::from(key), Data::from(value));
+                if xx {
+                    Some(writer.update_with_expiry(key, value, at, now))
                 } else {
-                    Some(false)
+                    Some(writer.set_with_expiry(key, value, at, now))
                 }
             } else {
                 None
             }
         };
-        if let Some(did_we) = did_we {
-            if did_we {
-                con.write_response(responses::groups::OKAY).await?;
-            } else {
-                con.write_response(responses::groups::OVERWRITE_ERR).await?;
-            }
-        } else {
-            con.write_response(responses::groups::SERVER_ERR).await?;
+        match did_we {
+            Some(Ok(true)) => con.write_response(responses::groups::OKAY).await?,
+            // the condition kept us from writing
+            Some(Ok(false)) if nx || xx => con.write_response(responses::groups::NIL).await?,
+            Some(_) => con.write_response(responses::groups::OVERWRITE_ERR).await?,
+            None => con.write_response(responses::groups::SERVER_ERR).await?,
         }
         Ok(())
     }
@@ -53,6 +53,8 @@ enum StrongActionResult {
     OverwriteError,
     /// An encoding error occurred
     EncodingError,
+    /// A key is protected from deletion
+    ProtectedKey,
     /// Everything worked as expected
     Okay,
 }
@@ -27,6 +27,7 @@
 use crate::actions::strong::StrongActionResult;
 use crate::dbnet::connection::prelude::*;
 use crate::kvengine::KVEngine;
+use crate::kvengine::PairSize;
 use crate::kvengine::SingleEncoder;
 use crate::util::compiler;
 
@@ -34,9 +35,12 @@ action! {
     /// Run an `SDEL` query
     ///
     /// This either returns `Okay` if all the keys were `del`eted, or it returns a
-    /// `Nil`, which is code `1`
+    /// `Nil`, which is code `1`. If any of the keys is protected from deletion, nothing is
+    /// deleted and `protected-key` is returned
     fn sdel(handle: &crate::corestore::Corestore, con: &mut T, act: ActionIter) {
-        err_if_len_is!(act, con, eq 0);
+        check_arity!(act, con, Arity::NonZero);
+        // deleting frees memory, so this runs even if the memory guard blocks writes
+        throttle_writes!(con, handle, frees_memory);
         let kve = kve!(con, handle);
         if registry::state_okay() {
             // guarantee one check: consistency
@@ -51,6 +55,7 @@ action! {
                     conwrite!(con, groups::NIL)?;
                 },
                 StrongActionResult::ServerError => conwrite!(con, groups::SERVER_ERR)?,
+                StrongActionResult::ProtectedKey => conwrite!(con, groups::PROTECTED_KEY)?,
                 StrongActionResult::EncodingError => {
                     // error we love to hate: encoding error, ugh
                     compiler::cold_err(conwrite!(con, groups::ENCODING_ERROR))?
@@ -76,11 +81,15 @@ pub(super) fn snapshot_and_del(
 ) -> StrongActionResult {
     let mut snapshots = Vec::with_capacity(act.len());
     let mut err_enc = false;
+    let mut err_protected = false;
     let iter_stat_ok;
     {
         iter_stat_ok = act.as_ref().iter().all(|key| {
             if compiler::likely(key_encoder.is_ok(key)) {
-                if let Some(snap) = kve.take_snapshot(key) {
+                if kve.is_protected(key) {
+                    err_protected = true;
+                    false
+                } else if let Some(snap) = kve.take_snapshot(key) {
                     snapshots.push(snap);
                     true
                 } else {
@@ -99,6 +108,9 @@ pub(super) fn snapshot_and_del(
     if compiler::unlikely(err_enc) {
         return compiler::cold_err(StrongActionResult::EncodingError);
     }
+    if err_protected {
+        return StrongActionResult::ProtectedKey;
+    }
     if registry::state_okay() {
         // guarantee upholded: consistency
         if iter_stat_ok {
@@ -110,7 +122,17 @@ pub(super) fn snapshot_and_del(
                 // value after we snapshotted it. In that case, let this key
                 // be whatever the "newer" value is. Since our snapshot is a "happens-before"
                 // thing, this is absolutely fine
-                let _ = lowtable.remove_if(&key, |_, val| val.eq(&snapshot));
+                // someone may have protected the key after we checked, so check again
+                let removed = kve.remove_indexed(|| {
+                    lowtable.remove_if(&key, |key, val| val.eq(&snapshot) && !kve.is_protected(key))
+                });
+                if let Some((key, value)) = removed {
+                    kve.account_stored(None, Some(PairSize::new(key.len(), value.len())));
+                    kve.clear_expiry(&key);
+                    kve.mark_dirty(key.len());
+                    kve.record_change(registry::Mutation::Remove(key));
+                }
+                kve.counters().deleted();
             });
             StrongActionResult::Okay
         } else {
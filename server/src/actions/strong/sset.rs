@@ -25,10 +25,12 @@
 */
 
 use crate::actions::strong::StrongActionResult;
+use crate::actions::value_lengths;
 use crate::corestore::Data;
 use crate::dbnet::connection::prelude::*;
 use crate::kvengine::DoubleEncoder;
 use crate::kvengine::KVEngine;
+use crate::kvengine::PairSize;
 use crate::util::compiler;
 
 action! {
@@ -37,11 +39,11 @@ action! {
     /// This either returns `Okay` if all the keys were set, or it returns an
     /// `Overwrite Error` or code `2`
     fn sset(handle: &crate::corestore::Corestore, con: &mut T, act: ActionIter) {
-        let howmany = act.len();
-        if is_lowbit_set!(howmany) || howmany == 0 {
-            return con.write_response(responses::groups::ACTION_ERR).await;
-        }
+        // the number of keys has to be the same as the number of values
+        check_arity!(act, con, Arity::Even(2));
+        throttle_writes!(con, handle);
         let kve = kve!(con, handle);
+        check_value_sizes!(con, kve, value_lengths(&act));
         if registry::state_okay() {
             let encoder = kve.get_encoder();
             let outcome = {
@@ -55,7 +57,7 @@ action! {
                     // error we love to hate: encoding error, ugh
                     compiler::cold_err(conwrite!(con, groups::ENCODING_ERROR))?
                 },
-                StrongActionResult::Nil => unsafe {
+                StrongActionResult::Nil | StrongActionResult::ProtectedKey => unsafe {
                     // SAFETY check: never the case
                     impossible!()
                 }
@@ -102,9 +104,23 @@ pub(super) fn snapshot_and_insert(
             let lowtable = lowtable;
             // fine, the keys were non-existent when we looked at them
             while let (Some(key), Some(value)) = (act.next(), act.next()) {
-                if let Some(fresh) = lowtable.fresh_entry(Data::from(key)) {
-                    fresh.insert(Data::from(value));
+                let size = PairSize::new(key.len(), value.len());
+                let delta = size.total();
+                let (key, value) = (Data::copy_from_slice(&key), Data::copy_from_slice(&value));
+                let inserted =
+                    kve.insert_indexed(key.clone(), |key| match lowtable.fresh_entry(key) {
+                        Some(fresh) => {
+                            kve.account_stored(Some(size), None);
+                            fresh.insert(value.clone());
+                            true
+                        }
+                        None => false,
+                    });
+                if inserted {
+                    kve.mark_dirty(delta);
+                    kve.record_change(registry::Mutation::Set(key, value));
                 }
+                kve.counters().wrote(if inserted { delta } else { 0 });
                 // we don't care if some other thread initialized the value we checked
                 // it. We expected a fresh entry, so that's what we'll check and use
             }
@@ -25,10 +25,12 @@
 */
 
 use crate::actions::strong::StrongActionResult;
+use crate::actions::value_lengths;
 use crate::corestore::Data;
 use crate::dbnet::connection::prelude::*;
 use crate::kvengine::DoubleEncoder;
 use crate::kvengine::KVEngine;
+use crate::kvengine::PairSize;
 use crate::util::compiler;
 
 action! {
@@ -37,11 +39,11 @@ action! {
     /// This either returns `Okay` if all the keys were updated, or it returns `Nil`
     /// or code `1`
     fn supdate(handle: &crate::corestore::Corestore, con: &mut T, act: ActionIter) {
-        let howmany = act.len();
-        if is_lowbit_set!(howmany) || howmany == 0 {
-            return con.write_response(responses::groups::ACTION_ERR).await;
-        }
+        // the number of keys has to be the same as the number of values
+        check_arity!(act, con, Arity::Even(2));
+        throttle_writes!(con, handle);
         let kve = kve!(con, handle);
+        check_value_sizes!(con, kve, value_lengths(&act));
         if registry::state_okay() {
             let encoder = kve.get_encoder();
             let outcome = {
@@ -58,7 +60,7 @@ action! {
                     // error we love to hate: encoding error, ugh
                     compiler::cold_err(conwrite!(con, groups::ENCODING_ERROR))?
                 },
-                StrongActionResult::OverwriteError => unsafe {
+                StrongActionResult::OverwriteError | StrongActionResult::ProtectedKey => unsafe {
                     // SAFETY check: never the case
                     impossible!()
                 }
@@ -118,13 +120,25 @@ pub(super) fn snapshot_and_update(
             {
                 // When we snapshotted, we looked at `snapshot`. If the value is still the
                 // same, then we'll update it. Otherwise, let it be
+                let delta = key.len() + value.len();
+                let mut updated = false;
                 if let Some(mut mutable) = lowtable.mut_entry(Data::from(key)) {
                     if mutable.get().eq(&snapshot) {
-                        mutable.insert(Data::from(value));
+                        updated = true;
+                        let value = Data::copy_from_slice(&value);
+                        let old = mutable.insert(value.clone());
+                        let key_len = mutable.key().len();
+                        kve.account_stored(
+                            Some(PairSize::new(key_len, value.len())),
+                            Some(PairSize::new(key_len, old.len())),
+                        );
+                        kve.mark_dirty(delta);
+                        kve.record_change(registry::Mutation::Update(mutable.key().clone(), value));
                     } else {
                         drop(mutable);
                     }
                 }
+                kve.counters().wrote(if updated { delta } else { 0 });
             }
             StrongActionResult::Okay
         } else {
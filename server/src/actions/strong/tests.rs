@@ -62,7 +62,7 @@ mod sdel_concurrency_tests {
         assert!(ret.is_ok());
         // although we told sdel to delete it, it shouldn't because we externally
         // updated the value
-        assert!(kve.exists(Data::from("k1")).unwrap());
+        assert!(kve.exists(Data::from("k1"), 0).unwrap());
     }
 }
 
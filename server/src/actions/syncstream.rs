@@ -0,0 +1,125 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `SYNCSTREAM` queries
+//!
+//! `SYNCSTREAM <epoch> <from-seq>` streams the mutations in the
+//! [change log](crate::registry::ChangeLog), starting with the sequence number `from-seq` of
+//! `epoch`. The first response is `Okay`, after which
+//! every change is sent as a separate response: a flat array made of the sequence number, the
+//! entity and the query that replays the change (for example, `["42", "default:default",
+//! "SET", "x", "100"]`). The connection stays attached, with the new changes being pushed as
+//! they are logged, until the client disconnects. Anything that the client sends in between
+//! detaches the stream (and is discarded).
+//!
+//! The sequence numbers start over whenever the server restarts, in a new epoch. If the epoch
+//! isn't the current one or the changes are no longer (or not yet) in the log,
+//! `sync-too-far-behind:<epoch>:<current-seq>:<newest-snapshot>` is returned instead and the
+//! stream ends. The reader should then restore the newest snapshot (the name is empty if there
+//! is none) and start over from the sequence number after `current-seq`, in `epoch`. A reader
+//! that is just starting out can pass any epoch (say, `0`) to be told where to start
+
+use crate::dbnet::connection::prelude::*;
+use crate::diskstore::snapshot;
+use crate::registry::{Change, TooFarBehind};
+use crate::resp::BytesWrapper;
+use bytes::Bytes;
+use skytable::RespCode;
+
+action!(
+    /// Run a `SYNCSTREAM <epoch> <from-seq>` query
+    fn syncstream(_handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(2));
+        let (epoch, from) = (next_or_err!(act, con), next_or_err!(act, con));
+        let parse = |arg: &[u8]| String::from_utf8_lossy(arg).parse::<u64>();
+        let (epoch, mut next) = match (parse(&epoch[..]), parse(&from[..])) {
+            (Ok(epoch), Ok(seq)) => (epoch, seq),
+            _ => return conwrite!(con, groups::WRONGTYPE_ERR),
+        };
+        let changelog = registry::get_changelog();
+        // subscribe before reading the log so that no change slips through in between
+        let mut notifier = changelog.subscribe();
+        let mut attached = false;
+        loop {
+            match changelog.since(epoch, next) {
+                Ok(changes) => {
+                    if !attached {
+                        conwrite!(con, groups::OKAY)?;
+                        attached = true;
+                    }
+                    for change in changes {
+                        next = change.seq + 1;
+                        write_change(con, change).await?;
+                    }
+                    con.flush_stream().await?;
+                }
+                Err(TooFarBehind { epoch, current }) => {
+                    if attached {
+                        // we fell behind while streaming
+                        con.write_simple_query_header().await?;
+                    }
+                    let error = format!(
+                        "sync-too-far-behind:{}:{}:{}",
+                        epoch,
+                        current,
+                        snapshot::newest_snapshot().unwrap_or_default()
+                    );
+                    return conwrite!(con, RespCode::ErrorString(error));
+                }
+            }
+            if changelog.is_closed() {
+                // the server is shutting down
+                return Ok(());
+            }
+            tokio::select! {
+                // lagging behind the notifications is fine since we read from the log anyway
+                _ = notifier.recv() => {}
+                _ = con.read_again() => {
+                    // the client either disconnected or sent something, so detach
+                    con.clear_buffer();
+                    return Ok(());
+                }
+            }
+        }
+    }
+);
+
+action!(
+    /// Write a change as a separate response
+    fn write_change(con: &mut T, change: Change) {
+        let query = change.mutation.to_query();
+        con.write_simple_query_header().await?;
+        con.write_flat_array_length(query.len() + 2).await?;
+        con.write_response(BytesWrapper(Bytes::from(change.seq.to_string())))
+            .await?;
+        con.write_response(BytesWrapper(change.entity.into_inner()))
+            .await?;
+        for arg in query {
+            con.write_response(BytesWrapper(arg)).await?;
+        }
+        Ok(())
+    }
+);
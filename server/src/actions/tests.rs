@@ -0,0 +1,82 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+mod arity {
+    use super::super::Arity;
+    /// Returns the argument counts from 0 to 8 that `arity` allows
+    fn allowed(arity: Arity) -> Vec<usize> {
+        (0..=8).filter(|len| arity.allows(*len)).collect()
+    }
+    #[test]
+    fn test_exactly() {
+        assert_eq!(allowed(Arity::Exactly(0)), vec![0]);
+        assert_eq!(allowed(Arity::Exactly(2)), vec![2]);
+        assert_eq!(Arity::Exactly(2).rule(), "exactly-2");
+    }
+    #[test]
+    fn test_at_least() {
+        assert_eq!(allowed(Arity::AtLeast(6)), vec![6, 7, 8]);
+        assert_eq!(Arity::AtLeast(6).rule(), "at-least-6");
+    }
+    #[test]
+    fn test_at_most() {
+        assert_eq!(allowed(Arity::AtMost(2)), vec![0, 1, 2]);
+        assert_eq!(Arity::AtMost(2).rule(), "at-most-2");
+    }
+    #[test]
+    fn test_between() {
+        assert_eq!(allowed(Arity::Between(2, 4)), vec![2, 3, 4]);
+        assert_eq!(Arity::Between(2, 4).rule(), "between-2-and-4");
+    }
+    #[test]
+    fn test_nonzero() {
+        assert_eq!(allowed(Arity::NonZero), vec![1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(Arity::NonZero.rule(), "nonzero");
+    }
+    #[test]
+    fn test_even() {
+        assert_eq!(allowed(Arity::Even(0)), vec![0, 2, 4, 6, 8]);
+        assert_eq!(allowed(Arity::Even(2)), vec![2, 4, 6, 8]);
+        assert_eq!(Arity::Even(2).rule(), "even-at-least-2");
+    }
+    #[test]
+    fn test_odd() {
+        assert_eq!(allowed(Arity::Odd(1)), vec![1, 3, 5, 7]);
+        assert_eq!(allowed(Arity::Odd(3)), vec![3, 5, 7]);
+        assert_eq!(Arity::Odd(3).rule(), "odd-at-least-3");
+    }
+    #[test]
+    fn test_error_names_the_rule() {
+        assert_eq!(
+            Arity::Exactly(1).error(),
+            b"!19\nbad-arity:exactly-1\n".to_vec()
+        );
+        assert_eq!(
+            Arity::Even(2).error(),
+            b"!25\nbad-arity:even-at-least-2\n".to_vec()
+        );
+    }
+}
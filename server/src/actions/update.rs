@@ -34,20 +34,15 @@ use crate::dbnet::connection::prelude::*;
 action!(
     /// Run an `UPDATE` query
     fn update(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, not 2);
+        check_arity!(act, con, Arity::Exactly(2));
+        throttle_writes!(con, handle);
+        let key = next_or_err!(act, con);
+        let value = next_or_err!(act, con);
         let did_we = {
             if registry::state_okay() {
                 let writer = kve!(con, handle);
-                // clippy thinks we're doing something complex when we aren't, at all!
-                #[allow(clippy::blocks_in_if_conditions)]
-                if unsafe {
-                    // UNSAFE(@ohsayan): This is completely safe as we've already checked
-                    // that there are exactly 2 arguments
-                    not_enc_err!(writer.update(
-                        Data::from(act.next().unsafe_unwrap()),
-                        Data::from(act.next().unsafe_unwrap()),
-                    ))
-                } {
+                check_value_sizes!(con, writer, Some(value.len()));
+                if not_enc_err!(writer.update(Data::from(key), Data::from(value))) {
                     Some(true)
                 } else {
                     Some(false)
@@ -24,6 +24,7 @@
  *
 */
 
+use crate::actions::value_lengths;
 use crate::corestore::Data;
 use crate::dbnet::connection::prelude::*;
 use crate::protocol::responses;
@@ -34,20 +35,19 @@ action!(
     ///
     /// This is like "INSERT or UPDATE"
     fn uset(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
+        // the number of keys has to be the same as the number of values
+        check_arity!(act, con, Arity::Even(2));
         let howmany = act.len();
-        if is_lowbit_set!(howmany) || howmany == 0 {
-            // An odd number of arguments means that the number of keys
-            // is not the same as the number of values, we won't run this
-            // action at all
-            return con.write_response(responses::groups::ACTION_ERR).await;
-        }
+        throttle_writes!(con, handle);
+        let epoch = registry::poison_epoch();
         let failed = {
             if registry::state_okay() {
                 let writer = kve!(con, handle);
+                check_value_sizes!(con, writer, value_lengths(&act));
                 while let (Some(key), Some(val)) = (act.next(), act.next()) {
                     let _ = writer.upsert(Data::from(key), Data::from(val));
                 }
-                false
+                !registry::unpoisoned_since(epoch)
             } else {
                 true
             }
@@ -0,0 +1,92 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `VALIDATE` queries
+//!
+//! `VALIDATE [<entity>] KEY <key> | VALUE <value> | PAIR <key> <value>` checks a key, a value
+//! or a pair against the encoding of a table (the current one if no entity is given) with the
+//! same check that the writes make, without touching the data. The response is `Okay` if the
+//! write would get past the check, or else `bad-key-encoding` or `bad-value-encoding` like a
+//! strict `MSET` reports it. Since the entity is optional, a table that is named like one of
+//! the subcommands has to be given as `<keyspace>:<table>`
+
+use crate::dbnet::connection::prelude::*;
+use crate::kvengine::PairStatus;
+use skytable::RespCode;
+
+const KEY: &[u8] = "KEY".as_bytes();
+const VALUE: &[u8] = "VALUE".as_bytes();
+const PAIR: &[u8] = "PAIR".as_bytes();
+
+/// Returns the number of arguments that follow `subcommand`, or `None` if it isn't one
+fn arguments_of(subcommand: &[u8]) -> Option<usize> {
+    if subcommand.eq_ignore_ascii_case(KEY) || subcommand.eq_ignore_ascii_case(VALUE) {
+        Some(1)
+    } else if subcommand.eq_ignore_ascii_case(PAIR) {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+action!(
+    /// Run a `VALIDATE [<entity>] KEY <key> | VALUE <value> | PAIR <key> <value>` query
+    fn validate(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(2, 4));
+        let first = next_or_err!(act, con);
+        let (entity, subcommand) = if arguments_of(&first).is_some() {
+            (None, first)
+        } else {
+            (Some(first), next_or_err!(act, con))
+        };
+        match arguments_of(&subcommand) {
+            Some(count) if count == act.len() => {}
+            Some(_) => return conwrite!(con, groups::ACTION_ERR),
+            None => return conwrite!(con, groups::UNKNOWN_VALIDATE_QUERY),
+        }
+        let (key, value) = if subcommand.eq_ignore_ascii_case(KEY) {
+            (act.next(), None)
+        } else if subcommand.eq_ignore_ascii_case(VALUE) {
+            (None, act.next())
+        } else {
+            (act.next(), act.next())
+        };
+        let status = match entity {
+            Some(raw_entity) => {
+                let entity = handle_entity!(con, raw_entity);
+                match get_tbl!(entity, handle, con).get_kvstore() {
+                    Ok(kve) => kve.check_encoding(key.as_deref(), value.as_deref()),
+                    Err(_) => return conwrite!(con, groups::WRONG_MODEL),
+                }
+            }
+            None => kve!(con, handle).check_encoding(key.as_deref(), value.as_deref()),
+        };
+        match status {
+            PairStatus::Okay => conwrite!(con, groups::OKAY),
+            status => conwrite!(con, RespCode::ErrorString(status.as_str().to_owned())),
+        }
+    }
+);
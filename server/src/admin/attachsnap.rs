@@ -0,0 +1,185 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Attaching snapshots
+//!
+//! `ATTACHSNAP <snapshot> [AS <alias>]` reads a snapshot from the snapshots directory (one of
+//! our own or one in `remote/`) into a read-only keyspace named `<alias>`, or `snap_<snapshot>`
+//! if no alias is given. It can be switched to with `USE` and read with the usual actions, so
+//! that old data can be looked at (or compared with the live data) without restoring it.
+//! Everything that would change it is rejected with `err-read-only-entity`.
+//!
+//! An attached snapshot only lives in memory: it is never flushed or snapshotted, it is left
+//! out of `INSPECT KEYSPACES` and `SYS STATS`, and it's gone after a restart.
+//! `DETACHSNAP <alias>` drops it (and frees its memory) once no connection is using it.
+//! What the snapshots take is reported by `SYS MEMORY`, and a snapshot that doesn't fit in the
+//! memory that's available (see [`memwatch::available_bytes`]) isn't attached at all
+
+use crate::admin::sys::write_pairs;
+use crate::corestore::memstore::{DdlError, ObjectID};
+use crate::dbnet::connection::prelude::*;
+use crate::queryengine::check_new_name;
+use crate::services::memwatch;
+use crate::storage::attach::{self, AttachError};
+use crate::storage::interface::dir_snaproot;
+use crate::storage::restore::RestoreError;
+use crate::util::fmt_key_safe;
+use core::str;
+use std::path::{Component, Path};
+
+const AS: &[u8] = "AS".as_bytes();
+
+action!(
+    /// Handle `ATTACHSNAP <snapshot> [AS <alias>]`. This returns a flat array of
+    /// `<name> <value>` pairs: the `keyspace` that the snapshot was attached as, the number of
+    /// `tables` in it and the `bytes` of memory that they take
+    fn attachsnap(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(1, 3));
+        if registry::is_sandbox() {
+            return conwrite!(con, groups::SANDBOX_MODE);
+        }
+        let snapname = unsafe {
+            // UNSAFE: We have already checked the arity
+            act.next().unsafe_unwrap()
+        };
+        let alias = match (act.next(), act.next()) {
+            (None, None) => None,
+            (Some(keyword), Some(alias)) if keyword.eq_ignore_ascii_case(AS) => Some(alias),
+            _ => return conwrite!(con, groups::ACTION_ERR),
+        };
+        let snapname = match str::from_utf8(&snapname) {
+            Ok(snapname) => snapname,
+            Err(_) => return conwrite!(con, groups::ENCODING_ERROR),
+        };
+        let snapdir = Path::new(dir_snaproot()).join(snapname);
+        let illegal_snapshot = Path::new(snapname)
+            .components()
+            .any(|part| !matches!(part, Component::Normal(_)));
+        if illegal_snapshot {
+            return conwrite!(con, groups::SNAPSHOT_ILLEGAL_NAME);
+        }
+        if !snapdir.is_dir() {
+            return conwrite!(con, groups::SNAPSHOT_NOT_FOUND);
+        }
+        let alias = match alias {
+            Some(alias) => alias.to_vec(),
+            None => self::default_alias(snapname).into_bytes(),
+        };
+        if let Err(e) = check_new_name(&alias) {
+            return conwrite!(con, e.error());
+        }
+        let alias = unsafe { ObjectID::from_slice(&alias) };
+        // don't bother reading the snapshot in if the name is taken
+        if handle.get_keyspace(&alias).is_some() {
+            return conwrite!(con, groups::ALREADY_EXISTS);
+        }
+        if !registry::state_okay() {
+            return conwrite!(con, groups::SERVER_ERR);
+        }
+        let available = memwatch::available_bytes(handle);
+        let now = handle.get_store().get_clock().now().timestamp_millis();
+        let read =
+            tokio::task::spawn_blocking(move || attach::read_snapshot(&snapdir, available, now))
+                .await
+                .expect("Something caused attaching the snapshot to panic");
+        let snapshot = match read {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                log::error!(
+                    "Failed to attach snapshot `{}`: {}",
+                    fmt_key_safe(snapname.as_bytes()),
+                    e
+                );
+                let error = match e {
+                    AttachError::OutOfMemory { .. } => groups::OUT_OF_MEMORY,
+                    AttachError::Snapshot(RestoreError::IoError(..)) => groups::SERVER_ERR,
+                    _ => groups::BAD_SNAPSHOT,
+                };
+                return conwrite!(con, error);
+            }
+        };
+        let (tables, bytes) = (snapshot.tables, snapshot.bytes);
+        match handle.attach_keyspace(alias.clone(), snapshot.keyspace) {
+            Ok(()) => {
+                log::info!(
+                    "Attached snapshot `{}` as `{}` ({} tables, {} bytes)",
+                    fmt_key_safe(snapname.as_bytes()),
+                    fmt_key_safe(&alias),
+                    tables,
+                    bytes
+                );
+                let pairs = [
+                    ("keyspace", String::from_utf8_lossy(&alias).into_owned()),
+                    ("tables", tables.to_string()),
+                    ("bytes", bytes.to_string()),
+                ];
+                write_pairs(con, &pairs).await
+            }
+            // someone took the name while we were reading the snapshot
+            Err(_) => conwrite!(con, groups::ALREADY_EXISTS),
+        }
+    }
+);
+
+action!(
+    /// Handle `DETACHSNAP <alias>`, which drops the snapshot that was attached as `alias`.
+    /// This returns `Okay`, or `still-in-use` if a connection is using it or one of its tables
+    fn detachsnap(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        let alias = unsafe {
+            // UNSAFE: We have already checked the arity
+            act.next().unsafe_unwrap()
+        };
+        if alias.len() > 64 {
+            return conwrite!(con, groups::CONTAINER_NAME_TOO_LONG);
+        }
+        let alias = unsafe { ObjectID::from_slice(&alias) };
+        let ret = match handle.detach_keyspace(alias.clone()) {
+            Ok(()) => {
+                log::info!("Detached snapshot `{}`", fmt_key_safe(&alias));
+                groups::OKAY
+            }
+            Err(DdlError::ObjectNotFound) => groups::CONTAINER_NOT_FOUND,
+            Err(DdlError::StillInUse) => groups::STILL_IN_USE,
+            Err(_) => unsafe {
+                // we know that Memstore::detach_keyspace won't return anything else
+                impossible!()
+            },
+        };
+        conwrite!(con, ret)
+    }
+);
+
+/// The name that a snapshot is attached as if no alias is given: `snap_<snapshot>`, with
+/// everything other than ASCII letters and digits replaced by `_` (so that `remote/x` is
+/// attached as `snap_remote_x`)
+fn default_alias(snapname: &str) -> String {
+    let name: String = snapname
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("snap_{}", name)
+}
@@ -24,17 +24,32 @@
  *
 */
 
+use crate::corestore::snaplock::SnapHolder;
 use crate::dbnet::connection::prelude::*;
-use crate::diskstore::snapshot::SnapshotEngine;
+use crate::diskstore::snapshot::{SnapengineError, SnapshotEngine};
 use crate::kvengine::encoding;
 use crate::storage;
-use crate::storage::interface::DIR_SNAPROOT;
+use crate::storage::interface::dir_snaproot;
 use std::path::{Component, PathBuf};
 
+const INCREMENTAL: &[u8] = "INCREMENTAL".as_bytes();
+
 action!(
     /// Create a snapshot
     ///
     fn mksnap(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(2));
+        if registry::is_sandbox() {
+            // there's no data directory to put the snapshot in
+            return con.write_response(responses::groups::SANDBOX_MODE).await;
+        }
+        if act.len() == 2 {
+            // `MKSNAP INCREMENTAL <base>`
+            if !next_or_err!(act, con).eq_ignore_ascii_case(INCREMENTAL) {
+                return con.write_response(responses::groups::ACTION_ERR).await;
+            }
+            return mksnap_incremental(handle, con, act).await;
+        }
         if act.len() == 0 {
             if !handle.is_snapshot_enabled() {
                 // Since snapshotting is disabled, we can't create a snapshot!
@@ -48,7 +63,8 @@ action!(
             let mut succeeded = None;
 
             let snapstatus = handle.get_snapstatus();
-            let snapengine = SnapshotEngine::new(snapstatus.max, handle);
+            let snapengine = SnapshotEngine::new(snapstatus.max, handle)
+                .map(|engine| engine.with_holder(SnapHolder::Mksnap));
             if snapengine.is_err() {
                 was_engine_error = true;
             } else if snapstatus.is_busy() {
@@ -79,9 +95,9 @@ action!(
                         .await;
                 }
             } else {
-                return con.write_response(responses::groups::SNAPSHOT_BUSY).await;
+                return con.write_error(Hinted::SnapshotBusy).await;
             }
-        } else if act.len() == 1 {
+        } else {
             // This means that the user wants to create a 'named' snapshot
             let snapname = unsafe {
                 // UNSAFE(@ohsayan): We've already checked that the action
@@ -93,7 +109,7 @@ action!(
             } else {
                 return con.write_response(responses::groups::ENCODING_ERROR).await;
             };
-            let mut path = PathBuf::from(DIR_SNAPROOT);
+            let mut path = PathBuf::from(dir_snaproot());
             path.push("remote");
             path.push(&snapname);
             let illegal_snapshot = path
@@ -132,10 +148,58 @@ action!(
             } else {
                 return con.write_response(responses::groups::OKAY.to_owned()).await;
             }
-        } else {
+        }
+    }
+);
+
+action!(
+    /// Create an incremental snapshot on top of the latest snapshot in the chain of the
+    /// (local) snapshot that is named in the query
+    fn mksnap_incremental(handle: &crate::corestore::Corestore, con: &mut T, mut act: ActionIter) {
+        if !handle.is_snapshot_enabled() {
             return con
-                .write_response(responses::groups::ACTION_ERR.to_owned())
+                .write_response(responses::groups::SNAPSHOT_DISABLED)
                 .await;
         }
+        let base = next_or_err!(act, con);
+        let base = if encoding::is_utf8(&base) {
+            unsafe { String::from_utf8_unchecked(base.to_vec()) }
+        } else {
+            return con.write_response(responses::groups::ENCODING_ERROR).await;
+        };
+        let snapstatus = handle.get_snapstatus();
+        if snapstatus.is_busy() {
+            return con.write_error(Hinted::SnapshotBusy).await;
+        }
+        let mut snapengine = match SnapshotEngine::new(snapstatus.max, handle) {
+            Ok(engine) => engine
+                .with_maxchain(snapstatus.maxchain)
+                .with_holder(SnapHolder::Mksnap),
+            Err(_) => {
+                return con
+                    .write_response(responses::groups::SERVER_ERR.to_owned())
+                    .await
+            }
+        };
+        match snapengine.mksnap_incremental(&base).await {
+            Ok(true) => con.write_response(responses::groups::OKAY.to_owned()).await,
+            Err(SnapengineError::NoSuchBase(_)) => {
+                con.write_response(responses::groups::SNAPSHOT_UNKNOWN_BASE)
+                    .await
+            }
+            Err(SnapengineError::ChainTooLong(_)) => {
+                con.write_response(responses::groups::SNAPSHOT_CHAIN_FULL)
+                    .await
+            }
+            Err(e) => {
+                log::error!("Error while creating incremental snapshot: {}", e);
+                con.write_response(responses::groups::SERVER_ERR.to_owned())
+                    .await
+            }
+            Ok(false) => {
+                con.write_response(responses::groups::SERVER_ERR.to_owned())
+                    .await
+            }
+        }
     }
 );
@@ -26,4 +26,8 @@
 
 //! Modules for administration of Skytable
 
+pub mod attachsnap;
 pub mod mksnap;
+pub mod protect;
+pub mod shipsnap;
+pub mod sys;
@@ -0,0 +1,75 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `PROTECT` and `UNPROTECT` queries
+//!
+//! These set and remove the deletion protection flag on a key. Protected keys can still be
+//! updated, but `DEL`, `SDEL`, `POP` and `FLUSHDB` (without `FORCE`) won't remove them. The
+//! flags are persisted along with the table (as `<table>.protected`) and are included in
+//! snapshots
+
+use crate::corestore::Data;
+use crate::dbnet::connection::prelude::*;
+
+action!(
+    /// Run a `PROTECT <key>` query. This returns `Okay` if the key was protected or `Nil` if
+    /// the key doesn't exist
+    fn protect(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        let key = next_or_err!(act, con);
+        ensure_writable!(con, handle);
+        if registry::state_okay() {
+            match kve!(con, handle).protect(Data::from(key)) {
+                Ok(true) => conwrite!(con, groups::OKAY)?,
+                Ok(false) => conwrite!(con, groups::NIL)?,
+                Err(()) => conwrite!(con, groups::ENCODING_ERROR)?,
+            }
+        } else {
+            conwrite!(con, groups::SERVER_ERR)?;
+        }
+        Ok(())
+    }
+);
+
+action!(
+    /// Run an `UNPROTECT <key>` query. This returns `Okay` if the protection was removed or
+    /// `Nil` if the key wasn't protected
+    fn unprotect(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        let key = next_or_err!(act, con);
+        ensure_writable!(con, handle);
+        if registry::state_okay() {
+            match kve!(con, handle).unprotect(Data::from(key)) {
+                Ok(true) => conwrite!(con, groups::OKAY)?,
+                Ok(false) => conwrite!(con, groups::NIL)?,
+                Err(()) => conwrite!(con, groups::ENCODING_ERROR)?,
+            }
+        } else {
+            conwrite!(con, groups::SERVER_ERR)?;
+        }
+        Ok(())
+    }
+);
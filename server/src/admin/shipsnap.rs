@@ -0,0 +1,683 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Shipping snapshots
+//!
+//! `SENDSNAP <snapshot> <host:port>` sends a snapshot from the snapshots directory of this
+//! server to another server, which keeps it with its `remote` snapshots. From there, it can be
+//! restored (with `--restore`) to seed a standby without copying the directories by hand.
+//!
+//! The snapshot is sent over a plain (not TLS) Skyhash connection to the normal port of the
+//! other server, as a run of `RECVSNAP` queries. Every one of them has the token that both
+//! servers are configured with (`snaptoken`), and a server without a token refuses them all:
+//! ```text
+//! RECVSNAP <token> BEGIN <snapshot>
+//! RECVSNAP <token> CHUNK <snapshot> <file> <bytes>
+//! RECVSNAP <token> COMMIT <snapshot> <manifest>
+//! ```
+//! - `BEGIN` starts receiving the snapshot into `remote/<snapshot>.partial`. What's left there
+//! of an earlier transfer (that was interrupted) is removed first
+//! - `CHUNK` appends the bytes to a file of the snapshot, given by its path in the snapshot
+//! (with `/` separators). Every file is sent in one or more chunks, one file after the other
+//! - `COMMIT` has a line for every file of the snapshot: `<CRC32-C (hex)> <size> <file>`. The
+//! receiver checks that it has exactly these files, with these sizes and checksums, before it
+//! renames the directory to `remote/<snapshot>`. If it doesn't, `err-snapshot-checksum` is
+//! returned and the directory is left for the next attempt to clean up
+//!
+//! An [incremental snapshot](crate::storage::chain) can only be restored along with the
+//! snapshots before it in its chain, so these have to be shipped as well
+
+use crate::admin::sys::write_pairs;
+use crate::dbnet::connection::prelude::*;
+use crate::storage::crc32c;
+use crate::storage::interface::dir_snaproot;
+use crate::util::fmt_key_safe;
+use core::str;
+use openssl::memcmp;
+use std::fs;
+use std::io::{Error as IoError, ErrorKind, Result as IoResult, Write};
+use std::path::{Component, Path, PathBuf};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::net::TcpStream;
+
+const RECVSNAP: &[u8] = "RECVSNAP".as_bytes();
+const BEGIN: &[u8] = "BEGIN".as_bytes();
+const CHUNK: &[u8] = "CHUNK".as_bytes();
+const COMMIT: &[u8] = "COMMIT".as_bytes();
+/// The directory (in the snapshots directory) that received snapshots are kept in
+const REMOTE: &str = "remote";
+/// Added to the name of a snapshot while it is being received
+const PARTIAL_SUFFIX: &str = ".partial";
+/// The most bytes of a file that are sent in one `CHUNK`
+const CHUNK_SIZE: usize = 1024 * 1024;
+
+action!(
+    /// Handle `SENDSNAP <snapshot> <host:port>`. This returns a flat array of `<name> <value>`
+    /// pairs: the number of `files` and the `bytes_sent`, along with the `status` (`okay` if
+    /// the other server verified and kept the snapshot, otherwise the error that it returned)
+    fn sendsnap(_handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(2));
+        if registry::is_sandbox() {
+            return conwrite!(con, groups::SANDBOX_MODE);
+        }
+        let (snapname, addr) = unsafe {
+            // UNSAFE: We have already checked the arity
+            (act.next().unsafe_unwrap(), act.next().unsafe_unwrap())
+        };
+        let token = match registry::get_snapshot_token() {
+            Some(token) => token,
+            None => return conwrite!(con, groups::SNAPSHOT_TOKEN_ERR),
+        };
+        let (snapname, addr) = match (str::from_utf8(&snapname), str::from_utf8(&addr)) {
+            (Ok(snapname), Ok(addr)) => (snapname, addr),
+            _ => return conwrite!(con, groups::ENCODING_ERROR),
+        };
+        // this can be one of our own snapshots or one in `remote/`, but nothing outside
+        let snapdir = Path::new(dir_snaproot()).join(snapname);
+        let illegal_snapshot = Path::new(snapname)
+            .components()
+            .any(|part| !matches!(part, Component::Normal(_)));
+        if illegal_snapshot {
+            return conwrite!(con, groups::SNAPSHOT_ILLEGAL_NAME);
+        }
+        let is_dir = tokio::fs::metadata(&snapdir)
+            .await
+            .map_or(false, |meta| meta.is_dir());
+        if !is_dir {
+            return conwrite!(con, groups::SNAPSHOT_NOT_FOUND);
+        }
+        // the other server keeps it under its own name
+        let name = match Path::new(snapname)
+            .file_name()
+            .and_then(|name| name.to_str())
+        {
+            Some(name) => name,
+            None => return conwrite!(con, groups::SNAPSHOT_ILLEGAL_NAME),
+        };
+        match send_snapshot(&snapdir, name, addr, &token).await {
+            Ok(shipped) => {
+                let pairs = [
+                    ("files", shipped.files.to_string()),
+                    ("bytes_sent", shipped.bytes_sent.to_string()),
+                    ("status", shipped.status),
+                ];
+                write_pairs(con, &pairs).await
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to send snapshot `{}` to {}: {}",
+                    fmt_key_safe(snapname.as_bytes()),
+                    fmt_key_safe(addr.as_bytes()),
+                    e
+                );
+                conwrite!(con, groups::SNAPSHOT_TRANSFER_FAILED)
+            }
+        }
+    }
+);
+
+action!(
+    /// Handle `RECVSNAP <token> BEGIN|CHUNK|COMMIT <snapshot> [<args>]`, the queries that
+    /// [`SENDSNAP`](sendsnap) sends a snapshot with
+    fn recvsnap(_handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(3, 5));
+        if registry::is_sandbox() {
+            return conwrite!(con, groups::SANDBOX_MODE);
+        }
+        let (token, mut step, snapname) = unsafe {
+            // UNSAFE: We have already checked the arity
+            (
+                act.next().unsafe_unwrap(),
+                act.next().unsafe_unwrap().to_vec(),
+                act.next().unsafe_unwrap(),
+            )
+        };
+        match registry::get_snapshot_token() {
+            // compared in constant time, so that the time taken doesn't give the token away
+            Some(ours) if ours.len() == token.len() && memcmp::eq(ours.as_bytes(), &token) => {}
+            _ => return conwrite!(con, groups::SNAPSHOT_TOKEN_ERR),
+        }
+        let snapname = match received_name(&snapname) {
+            Some(snapname) => snapname,
+            None => return conwrite!(con, groups::SNAPSHOT_ILLEGAL_NAME),
+        };
+        let remote = Path::new(dir_snaproot()).join(REMOTE);
+        let dst = remote.join(snapname);
+        let partial = remote.join(snapname.to_owned() + PARTIAL_SUFFIX);
+        step.make_ascii_uppercase();
+        let args: Vec<_> = act.collect();
+        // the steps write (and COMMIT reads back) the files, so they run on the blocking pool
+        let ret = tokio::task::spawn_blocking(move || match (step.as_ref(), &args[..]) {
+            (BEGIN, []) => begin(&dst, &partial),
+            (CHUNK, [file, bytes]) => append_chunk(&partial, file, bytes),
+            (COMMIT, [manifest]) => commit(&partial, &dst, manifest),
+            _ => Ok(groups::ACTION_ERR),
+        })
+        .await
+        .expect("Something caused receiving the snapshot to panic");
+        match ret {
+            Ok(response) => conwrite!(con, response),
+            Err(e) => {
+                log::error!(
+                    "Failed to receive snapshot `{}`: {}",
+                    fmt_key_safe(snapname.as_bytes()),
+                    e
+                );
+                conwrite!(con, groups::SERVER_ERR)
+            }
+        }
+    }
+);
+
+/// A file of a snapshot, as it is listed in the manifest that is sent with `COMMIT`
+#[derive(Debug, PartialEq)]
+struct ManifestEntry {
+    /// The path of the file in the snapshot (with `/` separators)
+    path: String,
+    size: u64,
+    /// The CRC32-C of the file
+    checksum: u32,
+}
+
+impl ManifestEntry {
+    fn of(path: String, data: &[u8]) -> Self {
+        Self {
+            path,
+            size: data.len() as u64,
+            checksum: crc32c(data),
+        }
+    }
+    /// Returns the line of the manifest for this file: `<CRC32-C (hex)> <size> <path>`
+    fn to_line(&self) -> String {
+        format!("{:08x} {} {}\n", self.checksum, self.size, self.path)
+    }
+    /// Parse a line of the manifest (without the newline)
+    fn parse(line: &str) -> Option<Self> {
+        let mut parts = line.splitn(3, ' ');
+        let checksum = u32::from_str_radix(parts.next()?, 16).ok()?;
+        let size = parts.next()?.parse().ok()?;
+        let path = parts.next().filter(|path| !path.is_empty())?.to_owned();
+        Some(Self {
+            path,
+            size,
+            checksum,
+        })
+    }
+}
+
+/// What was sent by [`send_snapshot`]
+pub struct Shipped {
+    /// The number of files that were sent
+    pub files: usize,
+    /// The bytes of these files
+    pub bytes_sent: u64,
+    /// `okay` if the other server verified and kept the snapshot, otherwise the error that it
+    /// returned
+    pub status: String,
+}
+
+/// Send the snapshot in `snapdir` to the server at `addr`, which keeps it as `name`. This
+/// only fails if there's a problem with reading the snapshot or with the connection; if the
+/// other server refuses the snapshot, its error is in the [`Shipped::status`]
+pub async fn send_snapshot(
+    snapdir: &Path,
+    name: &str,
+    addr: &str,
+    token: &str,
+) -> IoResult<Shipped> {
+    let root = snapdir.to_path_buf();
+    let files = tokio::task::spawn_blocking(move || {
+        let mut files = Vec::new();
+        self::list_files(&root, &root, &mut files)?;
+        files.sort();
+        Ok::<_, IoError>(files)
+    })
+    .await
+    .expect("Something caused listing the snapshot to panic")?;
+    let mut receiver = Receiver::connect(addr).await?;
+    let mut shipped = Shipped {
+        files: 0,
+        bytes_sent: 0,
+        status: "okay".to_owned(),
+    };
+    let (token, name) = (token.as_bytes(), name.as_bytes());
+    if let Err(refused) = receiver.query(&[RECVSNAP, token, BEGIN, name]).await? {
+        shipped.status = refused;
+        return Ok(shipped);
+    }
+    let mut manifest = String::new();
+    for path in files {
+        let data = tokio::fs::read(snapdir.join(&path)).await?;
+        // an empty file still needs a chunk, so that it is created
+        let chunks: Vec<&[u8]> = if data.is_empty() {
+            vec![&data[..]]
+        } else {
+            data.chunks(CHUNK_SIZE).collect()
+        };
+        for chunk in chunks {
+            let query = [RECVSNAP, token, CHUNK, name, path.as_bytes(), chunk];
+            if let Err(refused) = receiver.query(&query).await? {
+                shipped.status = refused;
+                return Ok(shipped);
+            }
+            shipped.bytes_sent += chunk.len() as u64;
+        }
+        manifest.push_str(&ManifestEntry::of(path, &data).to_line());
+        shipped.files += 1;
+    }
+    let query = [RECVSNAP, token, COMMIT, name, manifest.as_bytes()];
+    if let Err(refused) = receiver.query(&query).await? {
+        shipped.status = refused;
+    }
+    Ok(shipped)
+}
+
+/// A connection to the server that a snapshot is sent to
+struct Receiver {
+    stream: BufReader<TcpStream>,
+}
+
+impl Receiver {
+    async fn connect(addr: &str) -> IoResult<Self> {
+        let stream = TcpStream::connect(addr).await?;
+        Ok(Self {
+            stream: BufReader::new(stream),
+        })
+    }
+    /// Run a query and read its response, which is `Err` with the response code (or the error
+    /// string) unless it is Okay
+    async fn query(&mut self, args: &[&[u8]]) -> IoResult<Result<(), String>> {
+        let mut query = format!("*1\n_{}\n", args.len()).into_bytes();
+        for arg in args {
+            query.extend_from_slice(format!("+{}\n", arg.len()).as_bytes());
+            query.extend_from_slice(arg);
+            query.push(b'\n');
+        }
+        self.stream.get_mut().write_all(&query).await?;
+        let unexpected = || IoError::new(ErrorKind::InvalidData, "unexpected response");
+        if self.read_line().await? != "*1" {
+            return Err(unexpected());
+        }
+        // everything that `RECVSNAP` returns is a response code or an error string
+        let len = match self.read_line().await?.strip_prefix('!') {
+            Some(len) => len.parse::<usize>().map_err(|_| unexpected())?,
+            None => return Err(unexpected()),
+        };
+        let mut code = vec![0; len + 1];
+        self.stream.read_exact(&mut code).await?;
+        code.pop();
+        match String::from_utf8(code) {
+            Ok(code) if code == "0" => Ok(Ok(())),
+            Ok(code) => Ok(Err(code)),
+            Err(_) => Err(unexpected()),
+        }
+    }
+    async fn read_line(&mut self) -> IoResult<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            return Err(IoError::new(
+                ErrorKind::UnexpectedEof,
+                "the connection was closed",
+            ));
+        }
+        line.pop();
+        Ok(line)
+    }
+}
+
+/// List the paths (relative to `root`, with `/` separators) of the files in `dir` and in the
+/// directories in it, into `files`
+fn list_files(root: &Path, dir: &Path, files: &mut Vec<String>) -> IoResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            self::list_files(root, &path, files)?;
+            continue;
+        }
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let parts: Option<Vec<&str>> = relative
+            .components()
+            .map(|part| part.as_os_str().to_str())
+            .collect();
+        match parts {
+            Some(parts) => files.push(parts.join("/")),
+            None => {
+                return Err(IoError::new(
+                    ErrorKind::InvalidData,
+                    format!("`{}` doesn't have a UTF-8 name", path.display()),
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of a received snapshot, if it is a plain name (that doesn't look like a
+/// transfer in progress)
+fn received_name(name: &[u8]) -> Option<&str> {
+    let name = str::from_utf8(name).ok()?;
+    let mut parts = Path::new(name).components();
+    match (parts.next(), parts.next()) {
+        (Some(Component::Normal(_)), None) if !name.ends_with(PARTIAL_SUFFIX) => Some(name),
+        _ => None,
+    }
+}
+
+/// Returns the path of a file in a received snapshot, if it stays in the snapshot
+fn file_path(file: &[u8]) -> Option<PathBuf> {
+    let file = str::from_utf8(file).ok()?;
+    let mut path = PathBuf::new();
+    for part in file.split('/') {
+        let mut parts = Path::new(part).components();
+        match (parts.next(), parts.next()) {
+            (Some(Component::Normal(_)), None) => path.push(part),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+/// Render a path in the snapshots directory (whose last part was sent by a client) for a log
+/// line, just like [`fmt_key_safe`]
+fn fmt_path_safe(path: &Path) -> String {
+    fmt_key_safe(path.to_string_lossy().as_bytes()).into_owned()
+}
+
+/// Start receiving a snapshot into `partial`, unless there already is one at `dst`
+fn begin(dst: &Path, partial: &Path) -> IoResult<&'static [u8]> {
+    if dst.exists() {
+        return Ok(groups::ALREADY_EXISTS);
+    }
+    if partial.exists() {
+        log::info!(
+            "Removing the interrupted transfer at `{}`",
+            fmt_path_safe(partial)
+        );
+        fs::remove_dir_all(partial)?;
+    }
+    fs::create_dir_all(partial)?;
+    Ok(groups::OKAY)
+}
+
+/// Append `bytes` to `file` in the snapshot that is being received into `partial`
+fn append_chunk(partial: &Path, file: &[u8], bytes: &[u8]) -> IoResult<&'static [u8]> {
+    if !partial.is_dir() {
+        return Ok(groups::NO_SNAPSHOT_TRANSFER);
+    }
+    let path = match self::file_path(file) {
+        Some(path) => partial.join(path),
+        None => return Ok(groups::SNAPSHOT_ILLEGAL_NAME),
+    };
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?
+        .write_all(bytes)?;
+    Ok(groups::OKAY)
+}
+
+/// Check the snapshot in `partial` against the `manifest` and move it to `dst` if it matches
+fn commit(partial: &Path, dst: &Path, manifest: &[u8]) -> IoResult<&'static [u8]> {
+    if !partial.is_dir() {
+        return Ok(groups::NO_SNAPSHOT_TRANSFER);
+    }
+    let entries: Option<Vec<ManifestEntry>> = str::from_utf8(manifest)
+        .ok()
+        .and_then(|manifest| manifest.lines().map(ManifestEntry::parse).collect());
+    let mut entries = match entries {
+        Some(entries) => entries,
+        None => return Ok(groups::BAD_EXPRESSION),
+    };
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    let mut received = Vec::new();
+    self::list_files(partial, partial, &mut received)?;
+    received.sort();
+    let listed: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    if received != listed {
+        log::error!(
+            "The files received at `{}` don't match the manifest",
+            fmt_path_safe(partial)
+        );
+        return Ok(groups::SNAPSHOT_CHECKSUM);
+    }
+    for entry in entries {
+        let data = fs::read(partial.join(&entry.path))?;
+        if ManifestEntry::of(entry.path.clone(), &data) != entry {
+            log::error!(
+                "`{}` in `{}` failed the checksum",
+                fmt_key_safe(entry.path.as_bytes()),
+                fmt_path_safe(partial)
+            );
+            return Ok(groups::SNAPSHOT_CHECKSUM);
+        }
+    }
+    fs::rename(partial, dst)?;
+    log::info!("Received snapshot `{}`", fmt_path_safe(dst));
+    Ok(groups::OKAY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SnapshotConfig;
+    use crate::corestore::memstore::{Memstore, DEFAULT};
+    use crate::corestore::Data;
+    use crate::dbnet::{BaseListener, MultiListener, MAXIMUM_CONNECTION_LIMIT};
+    use crate::storage::interface::override_data_dir;
+    use crate::storage::{flush, restore, unflush};
+    use std::env;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::process;
+    use std::sync::Arc;
+    use tokio::sync::{broadcast, Semaphore};
+    use tokio::task::JoinHandle;
+
+    const TOKEN: &str = "sekrit";
+
+    /// Start a server (backed by an empty store) on an ephemeral port for the snapshots to be
+    /// sent to, and return its address
+    async fn start_receiver() -> (String, JoinHandle<()>) {
+        let db = Corestore::default_with_store(Memstore::new_default());
+        let (signal, _) = broadcast::channel(1);
+        let climit = Arc::new(Semaphore::new(MAXIMUM_CONNECTION_LIMIT));
+        let base = BaseListener::init(&db, IpAddr::V4(Ipv4Addr::LOCALHOST), 0, climit, signal)
+            .await
+            .expect("Failed to bind to an ephemeral port");
+        let addr = base.listener.local_addr().unwrap().to_string();
+        let mut server = MultiListener::new_insecure_only(base).unwrap();
+        let handle = tokio::spawn(async move {
+            let _ = server.run_server().await;
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn test_manifest_entry() {
+        let entry = ManifestEntry::of("default/default".to_owned(), b"123456789");
+        assert_eq!(entry.to_line(), "e3069283 9 default/default\n");
+        assert_eq!(
+            ManifestEntry::parse("e3069283 9 default/default"),
+            Some(entry)
+        );
+        assert_eq!(ManifestEntry::parse("e3069283 9 "), None);
+        assert_eq!(ManifestEntry::parse("e3069283 nine default/default"), None);
+    }
+
+    #[test]
+    fn test_received_paths_stay_in_the_snapshot() {
+        assert_eq!(received_name(b"seed"), Some("seed"));
+        assert_eq!(received_name(b"seed.partial"), None);
+        assert_eq!(received_name(b"../seed"), None);
+        assert_eq!(received_name(b"remote/seed"), None);
+        assert_eq!(
+            file_path(b"default/default"),
+            Some(Path::new("default").join("default"))
+        );
+        assert_eq!(file_path(b"../PRELOAD"), None);
+        assert_eq!(file_path(b"/etc/passwd"), None);
+        assert_eq!(file_path(b"default//default"), None);
+    }
+
+    #[tokio::test]
+    async fn test_ship_and_restore_snapshot() {
+        let root = env::temp_dir().join(format!("skyd-shipsnap-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let (sender_dir, receiver_dir) = (root.join("sender"), root.join("receiver"));
+        let (sender_dir, receiver_dir) =
+            (sender_dir.to_str().unwrap(), receiver_dir.to_str().unwrap());
+        // the sending server takes a snapshot of its data
+        let store = Memstore::new_default();
+        let tbl = store
+            .get_keyspace_atomic_ref(&DEFAULT)
+            .unwrap()
+            .get_table_atomic_ref(&DEFAULT)
+            .unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        for key in ["user:a", "user:b", "user:c"].iter() {
+            kve.set(Data::from(*key), Data::from(key.to_uppercase()))
+                .unwrap();
+        }
+        override_data_dir(Some(sender_dir));
+        flush::snap_flush_full("seed", &store).unwrap();
+        let snapdir = Path::new(dir_snaproot()).join("seed");
+        // the receiving server has what's left of an interrupted transfer
+        override_data_dir(Some(receiver_dir));
+        let remote = Path::new(dir_snaproot()).join(REMOTE);
+        fs::create_dir_all(remote.join("seed.partial")).unwrap();
+        fs::write(remote.join("seed.partial/stale"), b"stale").unwrap();
+        registry::set_snapshot_token(Some(TOKEN.to_owned()));
+        let (addr, server) = start_receiver().await;
+        let shipped = send_snapshot(&snapdir, "seed", &addr, TOKEN).await.unwrap();
+        assert_eq!(shipped.status, "okay");
+        let mut files = Vec::new();
+        list_files(&snapdir, &snapdir, &mut files).unwrap();
+        assert_eq!(shipped.files, files.len());
+        assert!(shipped.bytes_sent > 0);
+        let received = remote.join("seed");
+        assert!(!received.join("stale").exists());
+        assert!(!remote.join("seed.partial").exists());
+        // it's already there, so it isn't received again
+        let again = send_snapshot(&snapdir, "seed", &addr, TOKEN).await.unwrap();
+        assert_eq!(again.status, "err-already-exists");
+        // and the receiving server can be restored from it
+        restore::restore_from(received.to_str().unwrap(), false).unwrap();
+        let restored = unflush::read_full(&SnapshotConfig::default(), false).unwrap();
+        override_data_dir(None);
+        server.abort();
+        let tbl = restored
+            .get_keyspace_atomic_ref(&DEFAULT)
+            .unwrap()
+            .get_table_atomic_ref(&DEFAULT)
+            .unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        assert_eq!(kve.len(), 3);
+        for key in ["user:a", "user:b", "user:c"].iter() {
+            assert_eq!(
+                kve.take_snapshot(key.as_bytes()),
+                Some(Data::from(key.to_uppercase()))
+            );
+        }
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_recvsnap_needs_the_token() {
+        let root = env::temp_dir().join(format!("skyd-shipsnap-token-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let snapdir = root.join("snapshot");
+        fs::create_dir_all(&snapdir).unwrap();
+        fs::write(snapdir.join("PRELOAD"), b"").unwrap();
+        override_data_dir(Some(root.join("receiver").to_str().unwrap()));
+        registry::set_snapshot_token(Some(TOKEN.to_owned()));
+        let (addr, server) = start_receiver().await;
+        let shipped = send_snapshot(&snapdir, "seed", &addr, "wrong")
+            .await
+            .unwrap();
+        assert_eq!(shipped.status, "err-snapshot-token");
+        assert_eq!((shipped.files, shipped.bytes_sent), (0, 0));
+        assert!(!Path::new(dir_snaproot()).join(REMOTE).exists());
+        override_data_dir(None);
+        server.abort();
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_corrupted_transfer_is_not_kept() {
+        let root = env::temp_dir().join(format!("skyd-shipsnap-corrupted-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        override_data_dir(Some(root.to_str().unwrap()));
+        registry::set_snapshot_token(Some(TOKEN.to_owned()));
+        let (addr, server) = start_receiver().await;
+        let mut receiver = Receiver::connect(&addr).await.unwrap();
+        let (token, name) = (TOKEN.as_bytes(), "seed".as_bytes());
+        assert_eq!(
+            receiver
+                .query(&[RECVSNAP, token, BEGIN, name])
+                .await
+                .unwrap(),
+            Ok(())
+        );
+        let query = [
+            RECVSNAP,
+            token,
+            CHUNK,
+            name,
+            "PRELOAD".as_bytes(),
+            "data".as_bytes(),
+        ];
+        assert_eq!(receiver.query(&query).await.unwrap(), Ok(()));
+        // the checksum is for some other data
+        let manifest = ManifestEntry::of("PRELOAD".to_owned(), b"atad").to_line();
+        let query = [RECVSNAP, token, COMMIT, name, manifest.as_bytes()];
+        assert_eq!(
+            receiver.query(&query).await.unwrap(),
+            Err("err-snapshot-checksum".to_owned())
+        );
+        let remote = Path::new(dir_snaproot()).join(REMOTE);
+        assert!(!remote.join("seed").exists());
+        assert!(remote.join("seed.partial").exists());
+        // and a snapshot that was never begun can't be added to
+        let other = "other".as_bytes();
+        let query = [
+            RECVSNAP,
+            token,
+            CHUNK,
+            other,
+            "PRELOAD".as_bytes(),
+            "data".as_bytes(),
+        ];
+        assert_eq!(
+            receiver.query(&query).await.unwrap(),
+            Err("err-no-snapshot-transfer".to_owned())
+        );
+        override_data_dir(None);
+        server.abort();
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
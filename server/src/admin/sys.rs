@@ -0,0 +1,1403 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `SYS` queries
+//!
+//! `SYS` queries report on the state of the server itself (or shut it down, or compact its
+//! files). They are of the form `SYS <subcommand> <args>`
+
+use crate::actions::yield_on_chunk;
+use crate::corestore::snaplock::SnapHolder;
+use crate::corestore::table::Table;
+use crate::dbnet::connection::prelude::*;
+use crate::kvengine::ENTRY_OVERHEAD;
+use crate::queryengine;
+use crate::registry::{EventReader, IoKind, KeyEvent, LockHolder, LockKind, ShutdownKind};
+use crate::resp::BytesWrapper;
+use crate::services::memwatch;
+use crate::storage;
+use crate::storage::error::StorageResult;
+use bytes::Bytes;
+use libsky::keyslot;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const HEALTH: &[u8] = "HEALTH".as_bytes();
+const STATS: &[u8] = "STATS".as_bytes();
+const MEMORY: &[u8] = "MEMORY".as_bytes();
+const SHUTDOWN: &[u8] = "SHUTDOWN".as_bytes();
+const RESTART: &[u8] = "RESTART".as_bytes();
+const PREPARE: &[u8] = "PREPARE".as_bytes();
+const COMPACT: &[u8] = "COMPACT".as_bytes();
+const DDLLOG: &[u8] = "DDLLOG".as_bytes();
+const FLUSHLOG: &[u8] = "FLUSHLOG".as_bytes();
+const TRACE: &[u8] = "TRACE".as_bytes();
+const ON: &[u8] = "ON".as_bytes();
+const OFF: &[u8] = "OFF".as_bytes();
+const DUMP: &[u8] = "DUMP".as_bytes();
+const RESET: &[u8] = "RESET".as_bytes();
+const FLUSHWAIT: &[u8] = "FLUSHWAIT".as_bytes();
+const SNAPSTATE: &[u8] = "SNAPSTATE".as_bytes();
+const BGPAUSE: &[u8] = "BGPAUSE".as_bytes();
+const BGRESUME: &[u8] = "BGRESUME".as_bytes();
+const BGSTATE: &[u8] = "BGSTATE".as_bytes();
+const CLIENTS: &[u8] = "CLIENTS".as_bytes();
+const KEYSLOT: &[u8] = "KEYSLOT".as_bytes();
+const LOCKS: &[u8] = "LOCKS".as_bytes();
+const CONFIG: &[u8] = "CONFIG".as_bytes();
+const SLOWLOG: &[u8] = "SLOWLOG".as_bytes();
+const GET: &[u8] = "GET".as_bytes();
+const LEN: &[u8] = "LEN".as_bytes();
+const THRESHOLD: &[u8] = "THRESHOLD".as_bytes();
+const SLEEP: &[u8] = "SLEEP".as_bytes();
+const RECOVERYREPORT: &[u8] = "RECOVERYREPORT".as_bytes();
+const IDLE: &[u8] = "IDLE".as_bytes();
+const TABLES: &[u8] = "TABLES".as_bytes();
+const METRICS: &[u8] = "METRICS".as_bytes();
+const PROMETHEUS: &[u8] = "PROMETHEUS".as_bytes();
+const UNREADABLE: &[u8] = "UNREADABLE".as_bytes();
+const SCAN: &[u8] = "SCAN".as_bytes();
+const THROTTLE: &[u8] = "THROTTLE".as_bytes();
+const UNLIMITED: &[u8] = "UNLIMITED".as_bytes();
+const EVENTS: &[u8] = "EVENTS".as_bytes();
+#[cfg(test)]
+const DESYNC: &[u8] = "DESYNC".as_bytes();
+/// The number of records `SYS DDLLOG` returns if no count is given
+const DEFAULT_DDLLOG_COUNT: usize = 10;
+/// The number of records `SYS FLUSHLOG` returns if no count is given
+const DEFAULT_FLUSHLOG_COUNT: usize = 10;
+/// The number of milliseconds `SYS FLUSHWAIT` waits for if no timeout is given
+const DEFAULT_FLUSHWAIT_TIMEOUT: u64 = 10_000;
+/// The number of records `SYS SLOWLOG GET` returns if no count is given
+const DEFAULT_SLOWLOG_COUNT: usize = 10;
+/// The most milliseconds that `SYS SLEEP` sleeps for
+const MAX_SLEEP: u64 = 10_000;
+
+action!(
+    /// Handle `SYS <subcommand>` like queries
+    fn sys(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::NonZero);
+        let mut subcommand = next_or_err!(act, con).to_vec();
+        subcommand.make_ascii_uppercase();
+        match subcommand.as_ref() {
+            HEALTH => sys_health(handle, con, act).await?,
+            STATS => sys_stats(handle, con, act).await?,
+            MEMORY => sys_memory(handle, con, act).await?,
+            SHUTDOWN => sys_shutdown(ShutdownKind::Shutdown, con, act).await?,
+            RESTART => sys_shutdown(ShutdownKind::Restart, con, act).await?,
+            COMPACT => sys_compact(handle, con, act).await?,
+            DDLLOG => sys_ddllog(con, act).await?,
+            FLUSHLOG => sys_flushlog(con, act).await?,
+            TRACE => sys_trace(con, act).await?,
+            FLUSHWAIT => sys_flushwait(con, act).await?,
+            SNAPSTATE => sys_snapstate(handle, con, act).await?,
+            BGPAUSE => sys_bgpause(con, act).await?,
+            BGRESUME => sys_bgresume(con, act).await?,
+            BGSTATE => sys_bgstate(con, act).await?,
+            CLIENTS => sys_clients(con, act).await?,
+            KEYSLOT => sys_keyslot(con, act).await?,
+            LOCKS => sys_locks(con, act).await?,
+            CONFIG => sys_config(con, act).await?,
+            SLOWLOG => sys_slowlog(con, act).await?,
+            SLEEP => sys_sleep(con, act).await?,
+            RECOVERYREPORT => sys_recoveryreport(con, act).await?,
+            IDLE => sys_idle(handle, con, act).await?,
+            METRICS => sys_metrics(handle, con, act).await?,
+            UNREADABLE => sys_unreadable(handle, con, act).await?,
+            THROTTLE => sys_throttle(con, act).await?,
+            EVENTS => sys_events(handle, con, act).await?,
+            #[cfg(test)]
+            DESYNC => sys_desync(con, act).await?,
+            _ => conwrite!(con, groups::UNKNOWN_SYS_QUERY)?,
+        }
+        Ok(())
+    }
+);
+
+action!(
+    /// Handle `SYS STATS RESET [entity]`: this zeroes the read and write counters of the
+    /// given table, or of every table if no table is given
+    fn sys_stats_reset(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        match act.next() {
+            Some(entity) => {
+                let entity = handle_entity!(con, entity);
+                get_tbl!(entity, handle, con).reset_counters();
+            }
+            None => handle.get_store().keyspaces.iter().for_each(|keyspace| {
+                keyspace
+                    .value()
+                    .tables
+                    .iter()
+                    .for_each(|table| table.value().reset_counters())
+            }),
+        }
+        conwrite!(con, groups::OKAY)
+    }
+);
+
+action!(
+    /// Handle `SYS HEALTH`: this returns a flat array of `<name> <value>` pairs. The
+    /// `memory_state` is `blocked` while the memory guard rejects writes (and `okay`
+    /// otherwise), `memory_bytes` is the last memory usage that was sampled (`0` if no
+    /// memory ceiling is configured) and `degraded` has the reasons for the server being
+    /// degraded, separated by commas (`none` if there are none): every table that drifted
+    /// from its file in the latest consistency check, as `drift:<keyspace>:<table>`
+    fn sys_health(_handle: &Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        let tracker = registry::get_dirty_tracker();
+        let progress = registry::get_flush_progress();
+        let guard = registry::get_memory_guard();
+        let memory_state = if guard.is_blocking() {
+            "blocked"
+        } else {
+            "okay"
+        };
+        let state = if registry::state_okay() {
+            "good"
+        } else {
+            "critical"
+        };
+        let pairs = [
+            ("state", state.to_owned()),
+            ("dirty_bytes", tracker.get().to_string()),
+            ("dirty_mark", tracker.get_mark().to_string()),
+            ("flush_started_seq", progress.started().to_string()),
+            ("durable_seq", progress.durable().to_string()),
+            ("memory_state", memory_state.to_owned()),
+            ("memory_bytes", guard.usage().to_string()),
+            ("degraded", self::degraded_reasons()),
+        ];
+        write_pairs(con, &pairs).await
+    }
+);
+
+/// Returns the reasons for the server being degraded that `SYS HEALTH` reports, separated by
+/// commas, or `none`
+fn degraded_reasons() -> String {
+    let reasons: Vec<String> = registry::get_selfcheck()
+        .drifting()
+        .into_iter()
+        .map(|table| format!("drift:{}", table))
+        .collect();
+    if reasons.is_empty() {
+        "none".to_owned()
+    } else {
+        reasons.join(",")
+    }
+}
+
+action!(
+    /// Handle `SYS STATS`: this returns a flat array of `<name> <value>` pairs describing
+    /// the tables across all keyspaces, the response compression counters, the records
+    /// that didn't match their checksums when they were read in, the totals of the
+    /// [flush log](registry::FlushLog), the reads that found values that don't decode and the
+    /// times that the consistency checker found a table drifting from its file.
+    /// `SYS STATS RESET [entity]` zeroes the read and write counters of tables instead
+    fn sys_stats(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(2));
+        if let Some(subcommand) = act.next() {
+            if !subcommand.eq_ignore_ascii_case(RESET) {
+                return conwrite!(con, groups::UNKNOWN_SYS_QUERY);
+            }
+            return sys_stats_reset(handle, con, act).await;
+        }
+        let (mut tables, mut ordered, mut index_keys, mut index_bytes) = (0usize, 0, 0, 0);
+        let mut unloaded = 0usize;
+        for keyspace in handle.get_store().keyspaces.iter() {
+            for table in keyspace.value().tables.iter() {
+                tables += 1;
+                if !table.value().is_loaded() {
+                    unloaded += 1;
+                }
+                // don't read in the tables that haven't been accessed yet
+                if let Some(index) = table
+                    .value()
+                    .loaded_kvstore()
+                    .and_then(|kve| kve.get_ordered_index())
+                {
+                    ordered += 1;
+                    index_keys += index.len();
+                    index_bytes += index.approx_memory();
+                }
+            }
+        }
+        let flushes = registry::get_flush_log().totals();
+        let pairs = [
+            ("tables", tables.to_string()),
+            ("ordered_indexes", ordered.to_string()),
+            ("ordered_index_keys", index_keys.to_string()),
+            ("ordered_index_bytes", index_bytes.to_string()),
+            (
+                "compressed_frames",
+                registry::get_compression().get_frames().to_string(),
+            ),
+            (
+                "compression_bytes_saved",
+                registry::get_compression().get_bytes_saved().to_string(),
+            ),
+            ("loaded_tables", (tables - unloaded).to_string()),
+            ("unloaded_tables", unloaded.to_string()),
+            (
+                "checksum_mismatches",
+                registry::get_checksum_mismatches().to_string(),
+            ),
+            ("flushes", flushes.flushes.to_string()),
+            ("flushed_tables", flushes.tables.to_string()),
+            ("flushed_bytes", flushes.bytes.to_string()),
+            ("flushed_dirty_bytes", flushes.dirty.to_string()),
+            ("flush_time_us", flushes.duration.as_micros().to_string()),
+            ("write_amplification", ratio(flushes.write_amplification())),
+            (
+                "unreadable_values",
+                registry::get_unreadable_values().to_string(),
+            ),
+            (
+                "selfcheck_drifts",
+                registry::get_selfcheck().drifts().to_string(),
+            ),
+        ];
+        write_pairs(con, &pairs).await
+    }
+);
+
+action!(
+    /// Handle `SYS MEMORY`: this returns the [memory report](memory_report) as a flat array
+    /// of `<name> <value>` pairs
+    fn sys_memory(handle: &Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        write_pairs(con, &memory_report(handle)).await
+    }
+);
+
+action!(
+    /// Handle `SYS SHUTDOWN PREPARE`, `SYS SHUTDOWN <token>` and `SYS RESTART <token>`.
+    /// `PREPARE` returns a token that is good for one shutdown (or restart) from this
+    /// connection. Passing it back responds with `Okay` and then shuts the server down just
+    /// like a termination signal would (with a final flush). A wrong token discards the one
+    /// that was handed out
+    fn sys_shutdown(kind: ShutdownKind, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        let arg = next_or_err!(act, con);
+        if arg.eq_ignore_ascii_case(PREPARE) {
+            let token = shutdown_token();
+            *con.get_mut_shutdown_token() = Some(token.clone());
+            return con.write_response(BytesWrapper(Bytes::from(token))).await;
+        }
+        match con.get_mut_shutdown_token().take() {
+            Some(token) if token.as_bytes() == arg.as_ref() => {
+                conwrite!(con, groups::OKAY)?;
+                // the client should hear back before we stop accepting anything
+                con.flush_stream().await?;
+                match kind {
+                    ShutdownKind::Shutdown => log::info!("A client asked the server to shut down"),
+                    ShutdownKind::Restart => log::info!("A client asked the server to restart"),
+                }
+                registry::get_shutdown().request(kind);
+                Ok(())
+            }
+            _ => conwrite!(con, groups::BAD_SHUTDOWN_TOKEN),
+        }
+    }
+);
+
+action!(
+    /// Handle `SYS COMPACT [<entity>]`: this rewrites the file of the table (or the files of
+    /// all the tables if no entity is given) from what it holds in memory and returns a flat
+    /// array of `<name> <value>` pairs: the number of `tables` whose files were rewritten and
+    /// the size of these files before (`bytes_before`) and after (`bytes_after`)
+    fn sys_compact(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        if registry::is_sandbox() {
+            return conwrite!(con, groups::SANDBOX_MODE);
+        }
+        if !registry::state_okay() {
+            return conwrite!(con, groups::SERVER_ERR);
+        }
+        let only = match act.next() {
+            Some(raw_entity) => {
+                let entity = handle_entity!(con, raw_entity);
+                Some(get_tbl!(entity, handle, con))
+            }
+            None => None,
+        };
+        let owned_handle = handle.clone();
+        let compacted = tokio::task::spawn_blocking(move || compact(&owned_handle, only))
+            .await
+            .expect("Something caused the compaction to panic");
+        match compacted {
+            Ok((tables, before, after)) => {
+                let pairs = [
+                    ("tables", tables.to_string()),
+                    ("bytes_before", before.to_string()),
+                    ("bytes_after", after.to_string()),
+                ];
+                write_pairs(con, &pairs).await
+            }
+            Err(e) => {
+                log::error!("Compaction failed with error: {}", e);
+                conwrite!(con, groups::SERVER_ERR)
+            }
+        }
+    }
+);
+
+action!(
+    /// Handle `SYS RECOVERYREPORT`: this returns what didn't match the `PARTMAP`s of the
+    /// keyspaces at startup (see [`storage::reconcile`](crate::storage::reconcile)) as a flat
+    /// array of `<keyspace>:<table>` and `orphan-adopted`, `orphan-quarantined` or
+    /// `missing-loaded-empty` pairs. The array is empty if everything matched
+    fn sys_recoveryreport(con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        write_pairs(con, &registry::get_recovery_report().entries()).await
+    }
+);
+
+action!(
+    #[cfg(test)]
+    /// Handle `SYS DESYNC`, which is only there for the tests: this starts an array of three
+    /// elements and returns after writing the first one, like a buggy action would
+    fn sys_desync(con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        let mut array = con.start_array(3).await?;
+        array
+            .write(BytesWrapper(Bytes::from_static(b"first")))
+            .await?;
+        Ok(())
+    }
+);
+
+action!(
+    /// Handle `SYS DDLLOG [<count>]`: this returns the last `count` (10, if not given) records
+    /// of the [DDL log](registry::DdlLog), oldest first, as a flat array with the `time`,
+    /// `client`, `statement` and `outcome` of every record
+    fn sys_ddllog(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        let count = match act.next() {
+            Some(count) => match String::from_utf8_lossy(&count).parse::<usize>() {
+                Ok(count) => count,
+                Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+            },
+            None => DEFAULT_DDLLOG_COUNT,
+        };
+        let records = registry::get_ddl_log().tail(count);
+        con.write_flat_array_length(records.len() * 4).await?;
+        for record in records {
+            con.write_response(BytesWrapper(Bytes::from(record.time)))
+                .await?;
+            con.write_response(BytesWrapper(Bytes::from(record.client)))
+                .await?;
+            con.write_response(BytesWrapper(Bytes::from(record.statement)))
+                .await?;
+            con.write_response(BytesWrapper(Bytes::from(record.outcome)))
+                .await?;
+        }
+        Ok(())
+    }
+);
+
+action!(
+    /// Handle `SYS FLUSHLOG [<count>]`: this returns the last `count` (10, if not given)
+    /// records of the [flush log](registry::FlushLog), oldest first, as a flat array with the
+    /// `time`, the number of `tables` written out, the `bytes` written, the `dirty_bytes`
+    /// mutated in these tables, the `duration` (in microseconds), the `write_amplification`
+    /// and the bytes written for every table (as space separated `<keyspace>:<table>=<bytes>`)
+    /// of every record
+    fn sys_flushlog(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        let count = match act.next() {
+            Some(count) => match String::from_utf8_lossy(&count).parse::<usize>() {
+                Ok(count) => count,
+                Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+            },
+            None => DEFAULT_FLUSHLOG_COUNT,
+        };
+        let records = registry::get_flush_log().tail(count);
+        con.write_flat_array_length(records.len() * 7).await?;
+        for record in records {
+            let report = &record.report;
+            let tables: Vec<String> = report
+                .tables
+                .iter()
+                .map(|table| format!("{}={}", table.table, table.bytes))
+                .collect();
+            let fields = [
+                record.time.clone(),
+                report.tables.len().to_string(),
+                report.bytes().to_string(),
+                report.dirty().to_string(),
+                record.duration.as_micros().to_string(),
+                ratio(report.write_amplification()),
+                tables.join(" "),
+            ];
+            for field in fields.iter() {
+                con.write_response(BytesWrapper(Bytes::from(field.clone())))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+);
+
+action!(
+    /// Handle `SYS LOCKS [min-held-ms]`: this returns the [locks](registry::LockTable) that
+    /// have been held for at least `min-held-ms` milliseconds (all of them by default), the
+    /// longest held first, as `<lock>:<holder>` and the milliseconds it has been held for. These
+    /// are followed by the longest hold of every lock that ended in the last minute
+    /// (`longest_<lock>_ms`)
+    fn sys_locks(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        let min = match act.next() {
+            Some(min) => match String::from_utf8_lossy(&min).parse::<u64>() {
+                Ok(min) => Duration::from_millis(min),
+                Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+            },
+            None => Duration::from_millis(0),
+        };
+        let locks = registry::get_locks();
+        let mut pairs: Vec<(String, String)> = locks
+            .held(min)
+            .into_iter()
+            .map(|held| {
+                (
+                    format!("{}:{}", held.kind.as_str(), held.holder.as_str()),
+                    held.held.as_millis().to_string(),
+                )
+            })
+            .collect();
+        for kind in LockKind::ALL.iter() {
+            pairs.push((
+                format!("longest_{}_ms", kind.as_str()),
+                locks.longest_recent(*kind).as_millis().to_string(),
+            ));
+        }
+        write_pairs(con, &pairs).await
+    }
+);
+
+action!(
+    /// Handle `SYS KEYSLOT <key> [nslots]`: this returns the [slot](libsky::keyslot) of the
+    /// key out of `nslots` slots (16384 by default), for clients that shard keys across
+    /// several servers
+    fn sys_keyslot(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(1, 2));
+        let key = next_or_err!(act, con);
+        let slots = match act.next() {
+            Some(slots) => match String::from_utf8_lossy(&slots).parse::<u32>() {
+                Ok(slots) if slots != 0 && slots <= keyslot::MAX_SLOTS => slots,
+                _ => return conwrite!(con, groups::WRONGTYPE_ERR),
+            },
+            None => keyslot::DEFAULT_SLOTS,
+        };
+        con.write_response(keyslot::keyslot_of(&key, slots) as usize)
+            .await
+    }
+);
+
+action!(
+    /// Handle `SYS CONFIG`: this returns the settings that the server is running with as a flat
+    /// array of `<name> <value>` pairs. `disabled_actions` lists the disabled actions separated
+    /// by commas (it's empty if no action is disabled) and `max_pipeline_depth` is `none` if
+    /// there's no limit. The threshold of the slow query log is the one it has right now, which
+    /// may have been changed with `SYS SLOWLOG THRESHOLD`
+    fn sys_config(con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        let disabled: Vec<String> = queryengine::disabled_actions()
+            .into_iter()
+            .map(|name| String::from_utf8_lossy(name).into_owned())
+            .collect();
+        let pipeline = registry::get_pipeline_limits();
+        let pairs = [
+            ("disabled_actions", disabled.join(",")),
+            ("max_name_len", registry::get_max_name_len().to_string()),
+            (
+                "max_pause_secs",
+                registry::get_background().max_pause().as_secs().to_string(),
+            ),
+            (
+                "max_value_size",
+                registry::get_value_limit().map_or_else(|| "none".to_owned(), |l| l.to_string()),
+            ),
+            (
+                "max_ordered_keys",
+                registry::get_max_ordered_keys().to_string(),
+            ),
+            ("load_threads", registry::get_load_threads().to_string()),
+            (
+                "paranoid",
+                registry::get_paranoid_mode().as_str().to_owned(),
+            ),
+            ("max_inflight", pipeline.max_inflight().to_string()),
+            (
+                "max_inflight_bytes",
+                pipeline.max_inflight_bytes().to_string(),
+            ),
+            (
+                "max_pipeline_depth",
+                match pipeline.max_depth() {
+                    0 => "none".to_owned(),
+                    depth => depth.to_string(),
+                },
+            ),
+            (
+                "slowlog_threshold_us",
+                registry::get_slowlog().threshold().to_string(),
+            ),
+            ("slowlog_size", registry::get_slowlog().size().to_string()),
+        ];
+        write_pairs(con, &pairs).await
+    }
+);
+
+action!(
+    /// Handle `SYS CLIENTS`: this returns the [open connections](registry::Clients), ordered by
+    /// their IDs, as a flat array with the `connection`, the `address` (`local` if there's
+    /// none), the bytes of responses that are `buffered` right now and the most that ever were
+    /// (`peak_buffered`) for every connection, followed by the queries (`queued`) and the
+    /// bytes (`queued_bytes`) that its open transaction has queued, as `<used>/<limit>`, and
+    /// the responses to pipelined queries that it holds back right now (`inflight`)
+    fn sys_clients(con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        let clients = registry::get_clients().list();
+        let quota = registry::get_txn_quota();
+        con.write_flat_array_length(clients.len() * 7).await?;
+        for client in clients {
+            let peer = client.peer();
+            let (queued, queued_bytes) = client.queued();
+            let fields = [
+                peer.id.to_string(),
+                peer.addr
+                    .map(|addr| addr.to_string())
+                    .unwrap_or_else(|| "local".to_owned()),
+                client.buffered().to_string(),
+                client.peak_buffered().to_string(),
+                format!("{}/{}", queued, quota.max_queued()),
+                format!("{}/{}", queued_bytes, quota.max_bytes()),
+                client.inflight().to_string(),
+            ];
+            for field in fields.iter() {
+                con.write_response(BytesWrapper(Bytes::from(field.clone())))
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+);
+
+action!(
+    /// Handle `SYS TRACE ON|OFF`, `SYS TRACE <connection-id> ON|OFF` and `SYS TRACE DUMP`. The
+    /// first two turn tracing on (or off) for this connection or for the connection with the
+    /// given ID, responding with `Nil` if there's no such connection. `DUMP` returns the
+    /// buffered [trace records](registry::Tracer), oldest first, as a flat array with the
+    /// `connection`, `action`, `args` and the `parse`, `dispatch`, `execute` and `write`
+    /// timings (in microseconds) of every record
+    fn sys_trace(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(1, 2));
+        if act.len() == 1 && act.as_ref()[0].eq_ignore_ascii_case(DUMP) {
+            let records = registry::get_tracer().dump();
+            con.write_flat_array_length(records.len() * 7).await?;
+            for record in records {
+                let fields = [
+                    record.connection.to_string(),
+                    record.action,
+                    record.args.to_string(),
+                    record.parse.as_micros().to_string(),
+                    record.dispatch.as_micros().to_string(),
+                    record.execute.as_micros().to_string(),
+                    record.write.as_micros().to_string(),
+                ];
+                for field in fields.iter() {
+                    con.write_response(BytesWrapper(Bytes::from(field.clone())))
+                        .await?;
+                }
+            }
+            return Ok(());
+        }
+        let connection = if act.len() == 2 {
+            let id = next_or_err!(act, con);
+            match String::from_utf8_lossy(&id).parse::<u64>() {
+                Ok(id) => id,
+                Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+            }
+        } else {
+            con.get_peer().id
+        };
+        let switch = next_or_err!(act, con);
+        let on = if switch.eq_ignore_ascii_case(ON) {
+            true
+        } else if switch.eq_ignore_ascii_case(OFF) {
+            false
+        } else {
+            return conwrite!(con, groups::ACTION_ERR);
+        };
+        if registry::get_tracer().set_tracing(connection, on) {
+            conwrite!(con, groups::OKAY)
+        } else {
+            conwrite!(con, groups::NIL)
+        }
+    }
+);
+
+action!(
+    /// Handle `SYS SLOWLOG GET [count]`, `SYS SLOWLOG LEN`, `SYS SLOWLOG RESET` and
+    /// `SYS SLOWLOG THRESHOLD [microseconds]`. `GET` returns the last `count` (10 by default)
+    /// records of the [slow query log](registry::SlowLog), newest first, as a flat array with
+    /// the `id`, the `time`, the `connection`, the `action`, the `args` and the time the action
+    /// took (in microseconds) along with the `parse` and `dispatch` timings (in microseconds,
+    /// `none` if the connection wasn't traced) of every record. `LEN` returns the number of
+    /// records and `RESET` drops them. `THRESHOLD` returns the threshold, or sets it if one is
+    /// given
+    fn sys_slowlog(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Between(1, 2));
+        let slowlog = registry::get_slowlog();
+        let subcommand = next_or_err!(act, con);
+        if subcommand.eq_ignore_ascii_case(GET) {
+            let count = match act.next() {
+                Some(count) => match String::from_utf8_lossy(&count).parse::<usize>() {
+                    Ok(count) => count,
+                    Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+                },
+                None => DEFAULT_SLOWLOG_COUNT,
+            };
+            let records = slowlog.get(count);
+            con.write_flat_array_length(records.len() * 8).await?;
+            for record in records {
+                let (parse, dispatch) = match record.stages {
+                    Some(stages) => (
+                        stages.parse.as_micros().to_string(),
+                        stages.dispatch.as_micros().to_string(),
+                    ),
+                    None => ("none".to_owned(), "none".to_owned()),
+                };
+                let fields = [
+                    record.id.to_string(),
+                    record.time,
+                    record.connection.to_string(),
+                    record.action,
+                    record.args.to_string(),
+                    record.duration.as_micros().to_string(),
+                    parse,
+                    dispatch,
+                ];
+                for field in fields.iter() {
+                    con.write_response(BytesWrapper(Bytes::from(field.clone())))
+                        .await?;
+                }
+            }
+            Ok(())
+        } else if subcommand.eq_ignore_ascii_case(THRESHOLD) {
+            match act.next() {
+                Some(threshold) => match String::from_utf8_lossy(&threshold).parse::<u64>() {
+                    Ok(threshold) => {
+                        slowlog.set_threshold(threshold);
+                        conwrite!(con, groups::OKAY)
+                    }
+                    Err(_) => conwrite!(con, groups::WRONGTYPE_ERR),
+                },
+                None => con.write_response(slowlog.threshold() as usize).await,
+            }
+        } else if act.len() != 0 {
+            conwrite!(con, groups::ACTION_ERR)
+        } else if subcommand.eq_ignore_ascii_case(LEN) {
+            con.write_response(slowlog.len()).await
+        } else if subcommand.eq_ignore_ascii_case(RESET) {
+            slowlog.reset();
+            conwrite!(con, groups::OKAY)
+        } else {
+            conwrite!(con, groups::UNKNOWN_SYS_QUERY)
+        }
+    }
+);
+
+action!(
+    /// Handle `SYS IDLE TABLES <seconds>`: this returns a flat array of `<keyspace>:<table>
+    /// <seconds>` pairs with the tables that haven't been written to or read from (if reads
+    /// are tracked) for at least `seconds`, along with how long they have been idle. Tables
+    /// that were never active come first (as `never`), followed by the rest, the longest idle
+    /// first
+    fn sys_idle(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(2));
+        if !next_or_err!(act, con).eq_ignore_ascii_case(TABLES) {
+            return conwrite!(con, groups::UNKNOWN_SYS_QUERY);
+        }
+        let threshold = match String::from_utf8_lossy(&next_or_err!(act, con)).parse::<u64>() {
+            Ok(threshold) => threshold,
+            Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+        };
+        let now = handle.get_store().get_clock().now().timestamp() as u64;
+        let mut idle = Vec::new();
+        for keyspace in handle.get_store().keyspaces.iter() {
+            let ksid = String::from_utf8_lossy(keyspace.key()).into_owned();
+            for table in keyspace.value().tables.iter() {
+                let activity = table.value().activity();
+                // what other connections did with the table since they last stamped it
+                if activity.is_touched() {
+                    activity.stamp(now);
+                }
+                let idle_for = activity
+                    .last_active()
+                    .map(|last_active| now.saturating_sub(last_active));
+                if idle_for.map_or(true, |idle_for| idle_for >= threshold) {
+                    let entity = format!("{}:{}", ksid, String::from_utf8_lossy(table.key()));
+                    idle.push((entity, idle_for));
+                }
+            }
+        }
+        // `None` (never active) sorts before everything else
+        idle.sort_by(|(a, a_idle), (b, b_idle)| {
+            a_idle
+                .map(|idle| !idle)
+                .cmp(&b_idle.map(|idle| !idle))
+                .then_with(|| a.cmp(b))
+        });
+        let pairs: Vec<(String, String)> = idle
+            .into_iter()
+            .map(|(entity, idle_for)| {
+                let idle_for = idle_for
+                    .map(|idle_for| idle_for.to_string())
+                    .unwrap_or_else(|| "never".to_owned());
+                (entity, idle_for)
+            })
+            .collect();
+        write_pairs(con, &pairs).await
+    }
+);
+
+action!(
+    /// Handle `SYS UNREADABLE SCAN [<entity>]`: this walks the table (the current table if no
+    /// entity is given) and returns a flat array of the keys whose values don't decode to a
+    /// value of the table's type, which the reads return `err-value-unreadable` for. The keys
+    /// are taken first and then looked into in chunks, yielding to the runtime in between, so
+    /// a large table doesn't hold up the other connections on the worker
+    fn sys_unreadable(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(2));
+        if !next_or_err!(act, con).eq_ignore_ascii_case(SCAN) {
+            return conwrite!(con, groups::UNKNOWN_SYS_QUERY);
+        }
+        let table = match act.next() {
+            Some(raw_entity) => {
+                let entity = handle_entity!(con, raw_entity);
+                get_tbl!(entity, handle, con)
+            }
+            None => get_tbl!(handle, con),
+        };
+        let kve = match table.get_kvstore() {
+            Ok(kve) => kve,
+            Err(_) => return conwrite!(con, groups::WRONG_MODEL),
+        };
+        // only the values of a table with typed values have anything to be decoded
+        let keys = if kve.get_encoding().1 {
+            kve.__get_inner_ref().get_keys(kve.len())
+        } else {
+            Vec::new()
+        };
+        let mut unreadable = Vec::new();
+        for (i, key) in keys.into_iter().enumerate() {
+            yield_on_chunk(i).await;
+            if kve.is_unreadable(&key) {
+                unreadable.push(key);
+            }
+        }
+        let mut array = con.start_flat_array(unreadable.len()).await?;
+        for key in unreadable {
+            array.write(BytesWrapper(key)).await?;
+        }
+        array.finish()
+    }
+);
+
+action!(
+    /// Handle `SYS EVENTS ON [<entity>]`: this streams the key events of a table (the current
+    /// one if no entity is given) like `SYNCSTREAM` streams its changes. After `Okay`, every
+    /// event is sent as a separate response: a flat array of the type of the event, the key and
+    /// the second it happened in (for example, `["expired", "session:1", "1791971200"]`). If
+    /// we fall behind the change log, a `["dropped", "<count>", "<now>"]` event tells how many
+    /// changes (of any table) were missed. Anything that the client sends detaches the stream
+    fn sys_events(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(2));
+        if !next_or_err!(act, con).eq_ignore_ascii_case(ON) {
+            return conwrite!(con, groups::UNKNOWN_SYS_QUERY);
+        }
+        let table = match act.next() {
+            Some(raw_entity) => {
+                let entity = handle_entity!(con, raw_entity);
+                get_tbl!(entity, handle, con)
+            }
+            None => get_tbl!(handle, con),
+        };
+        let entity = match table.get_kvstore() {
+            Ok(kve) => kve.entity().cloned(),
+            Err(_) => return conwrite!(con, groups::WRONG_MODEL),
+        };
+        let changelog = registry::get_changelog();
+        // subscribe before reading the log so that no event slips through in between
+        let mut notifier = changelog.subscribe();
+        // a table that isn't logged has no events, but the client can still wait on it
+        let mut reader = entity.map(|entity| EventReader::new(changelog, entity));
+        conwrite!(con, groups::OKAY)?;
+        con.flush_stream().await?;
+        loop {
+            if let Some(reader) = reader.as_mut() {
+                let (dropped, events) = reader.read(changelog);
+                if dropped != 0 {
+                    let dropped = KeyEvent {
+                        event: "dropped",
+                        key: Bytes::from(dropped.to_string()),
+                        at: SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map(|since| since.as_secs())
+                            .unwrap_or(0),
+                    };
+                    write_event(con, dropped).await?;
+                }
+                for event in events {
+                    write_event(con, event).await?;
+                }
+                con.flush_stream().await?;
+            }
+            if changelog.is_closed() {
+                // the server is shutting down
+                return Ok(());
+            }
+            tokio::select! {
+                _ = notifier.recv() => {}
+                _ = con.read_again() => {
+                    // the client either disconnected or sent something, so detach
+                    con.clear_buffer();
+                    return Ok(());
+                }
+            }
+        }
+    }
+);
+
+action!(
+    /// Write a key event as a separate response
+    fn write_event(con: &mut T, event: KeyEvent) {
+        con.write_simple_query_header().await?;
+        con.write_flat_array_length(3).await?;
+        con.write_response(BytesWrapper(Bytes::from_static(event.event.as_bytes())))
+            .await?;
+        con.write_response(BytesWrapper(event.key)).await?;
+        con.write_response(BytesWrapper(Bytes::from(event.at.to_string())))
+            .await?;
+        Ok(())
+    }
+);
+
+action!(
+    /// Handle `SYS METRICS [PROMETHEUS|RESET]`: this returns the latency histograms (see
+    /// [`registry::Metrics`]) as a flat array of `<name> <histogram>` pairs, or as a single
+    /// string in the Prometheus text exposition format with `PROMETHEUS`, which also has the
+    /// sizes of the keys and values of the tables (see [`table_sizes`]). `RESET` zeroes the
+    /// latency histograms
+    fn sys_metrics(handle: &Corestore, con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        let metrics = registry::get_metrics();
+        match act.next() {
+            None => write_pairs(con, &metrics.compact()).await,
+            Some(format) if format.eq_ignore_ascii_case(PROMETHEUS) => {
+                let mut exposition = metrics.prometheus();
+                exposition.push_str(&table_sizes(handle));
+                con.write_response(BytesWrapper(Bytes::from(exposition)))
+                    .await
+            }
+            Some(subcommand) if subcommand.eq_ignore_ascii_case(RESET) => {
+                metrics.reset();
+                conwrite!(con, groups::OKAY)
+            }
+            Some(_) => conwrite!(con, groups::UNKNOWN_SYS_QUERY),
+        }
+    }
+);
+
+action!(
+    /// Handle `SYS SLEEP <milliseconds>`: this responds with `Okay` after sleeping for the
+    /// given time (10 seconds at most). It is there to debug clients and the slow query log
+    fn sys_sleep(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(1));
+        let millis = next_or_err!(act, con);
+        match String::from_utf8_lossy(&millis).parse::<u64>() {
+            Ok(millis) if millis <= MAX_SLEEP => {
+                tokio::time::sleep(Duration::from_millis(millis)).await;
+                conwrite!(con, groups::OKAY)
+            }
+            _ => conwrite!(con, groups::WRONGTYPE_ERR),
+        }
+    }
+);
+
+action!(
+    /// Handle `SYS FLUSHWAIT [<timeout-ms>]`: this waits until every mutation made so far
+    /// has been flushed to disk, asking the flush service for a flush if it needs one. It
+    /// returns `Okay` once a flush that began after the mutations completes successfully, a
+    /// server error if a flush fails or `err-flush-timeout` if nothing was flushed in
+    /// `timeout-ms` milliseconds (the connection's default timeout if not given, or 10 seconds
+    /// if it has none)
+    fn sys_flushwait(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        let timeout = match act.next() {
+            Some(timeout) => match String::from_utf8_lossy(&timeout).parse::<u64>() {
+                Ok(timeout) => timeout,
+                Err(_) => return conwrite!(con, groups::WRONGTYPE_ERR),
+            },
+            None => con
+                .get_capabilities()
+                .default_timeout
+                .map_or(DEFAULT_FLUSHWAIT_TIMEOUT, |timeout| {
+                    timeout.as_millis() as u64
+                }),
+        };
+        if registry::is_sandbox() {
+            // nothing is ever flushed, so this would only time out
+            return conwrite!(con, groups::SANDBOX_MODE);
+        }
+        let seq = registry::get_changelog().current_seq();
+        let waiting = registry::get_flush_progress().wait_durable(seq);
+        match tokio::time::timeout(Duration::from_millis(timeout), waiting).await {
+            Ok(true) => conwrite!(con, groups::OKAY),
+            Ok(false) => conwrite!(con, groups::SERVER_ERR),
+            Err(_) => con.write_error(Hinted::FlushTimeout).await,
+        }
+    }
+);
+
+action!(
+    /// Handle `SYS SNAPSTATE`: this returns a flat array of `<name> <value>` pairs with the
+    /// current holder of the snapshot lock (`none` if it's free), for how long it has held the
+    /// lock, the number of times the lock was acquired and the total time spent waiting for it.
+    /// These are followed by the limit on the bandwidth of the snapshots (`unlimited` if
+    /// there's none) and, for the snapshot that is running, the bytes it has written so far,
+    /// the bytes it was estimated to write and how much longer it should take going by that
+    /// (all of them `none` if no snapshot is running)
+    fn sys_snapstate(handle: &Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        if !handle.is_snapshot_enabled() {
+            return conwrite!(con, groups::SNAPSHOT_DISABLED);
+        }
+        let lock = &handle.get_snapstatus().in_progress;
+        let (holder, held) = match lock.holder() {
+            Some((holder, held)) => (holder.as_str(), held),
+            None => ("none", Duration::from_secs(0)),
+        };
+        let progress = registry::get_io_throttle().snapshot_progress();
+        let (written, estimated) = match progress.bytes() {
+            Some((written, estimated)) => (written.to_string(), estimated.to_string()),
+            None => ("none".to_owned(), "none".to_owned()),
+        };
+        let eta = progress
+            .eta()
+            .map_or_else(|| "none".to_owned(), |eta| eta.as_millis().to_string());
+        let pairs = [
+            ("holder", holder.to_owned()),
+            ("held_ms", held.as_millis().to_string()),
+            ("acquisitions", lock.acquisitions().to_string()),
+            ("waited_us", lock.waited().as_micros().to_string()),
+            ("throttle", throttle_of(IoKind::Snapshot)),
+            ("written_bytes", written),
+            ("estimated_bytes", estimated),
+            ("eta_ms", eta),
+        ];
+        write_pairs(con, &pairs).await
+    }
+);
+
+/// Returns the limit on the bandwidth of `kind`, as `SYS THROTTLE` and `SYS SNAPSTATE` report
+/// it
+fn throttle_of(kind: IoKind) -> String {
+    registry::get_io_throttle()
+        .limit(kind)
+        .map_or_else(|| "unlimited".to_owned(), |limit| limit.to_string())
+}
+
+action!(
+    /// Handle `SYS THROTTLE` and `SYS THROTTLE snapshot|flush <bytes-per-second>|UNLIMITED`.
+    /// The first returns a flat array of `<name> <value>` pairs with the limits on the
+    /// bandwidth of the snapshots and of the periodic flushes (`unlimited` if there's none),
+    /// and the second sets (or lifts) one of them. A new limit applies to the snapshot or the
+    /// flush that is running right away (see [`registry::IoThrottle`])
+    fn sys_throttle(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(2));
+        if act.len() == 0 {
+            let pairs = [IoKind::Snapshot, IoKind::Flush]
+                .iter()
+                .map(|kind| (kind.as_str(), throttle_of(*kind)))
+                .collect::<Vec<_>>();
+            return write_pairs(con, &pairs).await;
+        }
+        let kind = next_or_err!(act, con);
+        let kind = match [IoKind::Snapshot, IoKind::Flush]
+            .iter()
+            .find(|known| kind.eq_ignore_ascii_case(known.as_str().as_bytes()))
+        {
+            Some(kind) => *kind,
+            None => return conwrite!(con, groups::ACTION_ERR),
+        };
+        let limit = match act.next() {
+            Some(limit) if limit.eq_ignore_ascii_case(UNLIMITED) => None,
+            Some(limit) => match String::from_utf8_lossy(&limit).parse::<u64>() {
+                Ok(limit) if limit != 0 => Some(limit),
+                _ => return conwrite!(con, groups::WRONGTYPE_ERR),
+            },
+            None => return conwrite!(con, groups::ACTION_ERR),
+        };
+        registry::get_io_throttle().set_limit(kind, limit);
+        log::info!(
+            "Set the limit on the bandwidth of the {}s to {}",
+            kind.as_str(),
+            throttle_of(kind)
+        );
+        conwrite!(con, groups::OKAY)
+    }
+);
+
+action!(
+    /// Handle `SYS BGPAUSE [flush|snapshot|all] [<seconds>]`: this pauses the given background
+    /// service (or both of them, if none is given) for `seconds`, or for the configured maximum
+    /// if no time is given or if it is longer than the maximum. A paused service skips its
+    /// scheduled runs, but a run that's already underway isn't interrupted
+    fn sys_bgpause(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(2));
+        let control = registry::get_background();
+        let services = match act.next() {
+            Some(name) => match control.lookup(&name) {
+                Some(services) => services,
+                None => return conwrite!(con, groups::ACTION_ERR),
+            },
+            None => control.all().to_vec(),
+        };
+        let requested = match act.next() {
+            Some(seconds) => match String::from_utf8_lossy(&seconds).parse::<u64>() {
+                Ok(seconds) if seconds != 0 => Some(Duration::from_secs(seconds)),
+                _ => return conwrite!(con, groups::WRONGTYPE_ERR),
+            },
+            None => None,
+        };
+        let duration = control.pause_duration(requested);
+        for service in services {
+            service.pause(duration);
+            log::info!(
+                "Paused the {} service for {}s",
+                service.name(),
+                duration.as_secs()
+            );
+        }
+        conwrite!(con, groups::OKAY)
+    }
+);
+
+action!(
+    /// Handle `SYS BGRESUME [flush|snapshot|all]`: this resumes the given background service
+    /// (or both of them, if none is given). Resuming a service that isn't paused does nothing
+    fn sys_bgresume(con: &mut T, mut act: ActionIter) {
+        check_arity!(act, con, Arity::AtMost(1));
+        let control = registry::get_background();
+        let services = match act.next() {
+            Some(name) => match control.lookup(&name) {
+                Some(services) => services,
+                None => return conwrite!(con, groups::ACTION_ERR),
+            },
+            None => control.all().to_vec(),
+        };
+        for service in services {
+            if service.resume() {
+                log::info!("Resumed the {} service", service.name());
+            }
+        }
+        conwrite!(con, groups::OKAY)
+    }
+);
+
+action!(
+    /// Handle `SYS BGSTATE`: this returns a flat array of `<name> <value>` pairs with the
+    /// state (`paused` or `running`) of every background service, how long it is until its
+    /// next run (`none` if it isn't scheduled), how much longer it is paused for and the
+    /// longest that a service can be paused for
+    fn sys_bgstate(con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        let control = registry::get_background();
+        let mut pairs = Vec::new();
+        for service in control.all().iter() {
+            let paused_for = service.paused_for();
+            let state = if paused_for.is_some() {
+                "paused"
+            } else {
+                "running"
+            };
+            let next_run = service
+                .next_run_in()
+                .map_or_else(|| "none".to_owned(), |at| at.as_millis().to_string());
+            let paused_ms = paused_for.map_or(0, |left| left.as_millis());
+            let name = service.name();
+            pairs.push((format!("{}_state", name), state.to_owned()));
+            pairs.push((format!("{}_next_run_ms", name), next_run));
+            pairs.push((format!("{}_paused_ms", name), paused_ms.to_string()));
+        }
+        pairs.push((
+            "max_pause_secs".to_owned(),
+            control.max_pause().as_secs().to_string(),
+        ));
+        write_pairs(con, &pairs).await
+    }
+);
+
+/// Rewrite the files of all the persistent tables (or only the file of `only`) and return
+/// the number of files that were rewritten along with their total size before and after.
+///
+/// This holds the flush lock and the snapshot lock (if snapshots are enabled) throughout, so
+/// that neither BGSAVE nor a snapshot can run in the meantime
+fn compact(handle: &Corestore, only: Option<Arc<Table>>) -> StorageResult<(usize, u64, u64)> {
+    let _flush_lock = registry::lock_flush_state(LockHolder::Compact);
+    let _snap_lock = if handle.is_snapshot_enabled() {
+        Some(handle.lock_snap(SnapHolder::Compact))
+    } else {
+        None
+    };
+    let (mut tables, mut before, mut after) = (0usize, 0u64, 0u64);
+    for keyspace in handle.get_store().keyspaces.iter() {
+        for table in keyspace.value().tables.iter() {
+            if matches!(&only, Some(only) if !Arc::ptr_eq(only, table.value())) {
+                continue;
+            }
+            let compacted =
+                storage::flush::oneshot::compact_table(table.key(), keyspace.key(), table.value())?;
+            if let Some((was, is)) = compacted {
+                tables += 1;
+                before += was;
+                after += is;
+            }
+        }
+    }
+    // the snapshot lock goes before the flush lock
+    Ok((tables, before, after))
+}
+
+/// Make a token for `SYS SHUTDOWN PREPARE`. This only has to be hard to guess, which the
+/// randomly seeded hasher of the standard library takes care of
+fn shutdown_token() -> String {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos())
+        .unwrap_or_default();
+    hasher.write_u128(now);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Format a write amplification ratio with two decimals, or as `none` if there is none
+fn ratio(write_amplification: Option<f64>) -> String {
+    match write_amplification {
+        Some(ratio) => format!("{:.2}", ratio),
+        None => "none".to_owned(),
+    }
+}
+
+/// Returns the `<name> <value>` pairs that describe the memory used by the tables. The
+/// totals come first:
+/// - `tracked_bytes`: the bytes held by the keys and values across all tables
+/// - `entries`: the number of pairs across all tables
+/// - `entry_overhead_bytes`: an estimate of what the entries cost on top of the keys and
+/// values (see [`ENTRY_OVERHEAD`])
+/// - `attached_bytes`: an estimate of what the attached snapshots take (these are left out of
+/// the other totals)
+///
+/// followed by the allocator's `allocator_allocated`, `allocator_active`,
+/// `allocator_resident` and `allocator_metadata` (only if the server was built with the
+/// `allocstats` feature), then `keyspace_tracked_bytes:<keyspace>` for every keyspace,
+/// each followed by `table_tracked_bytes:<keyspace>:<table>` and
+/// `table_entries:<keyspace>:<table>` for its tables, and then `attached_bytes:<alias>` for
+/// every attached snapshot.
+///
+/// Since the tables keep count of what they hold, this doesn't walk the data, only the
+/// tables
+pub fn memory_report(handle: &Corestore) -> Vec<(String, String)> {
+    let (mut tracked, mut entries) = (0usize, 0usize);
+    let mut per_keyspace = Vec::new();
+    for keyspace in handle.get_store().keyspaces.iter() {
+        let ksid = String::from_utf8_lossy(keyspace.key()).into_owned();
+        let (mut ks_tracked, mut per_table) = (0usize, Vec::new());
+        for table in keyspace.value().tables.iter() {
+            if let Some(kve) = table.value().loaded_kvstore() {
+                let entity = format!("{}:{}", ksid, String::from_utf8_lossy(table.key()));
+                let (tbl_tracked, tbl_entries) = (kve.stored_bytes(), kve.len());
+                ks_tracked += tbl_tracked;
+                entries += tbl_entries;
+                per_table.push((
+                    format!("table_tracked_bytes:{}", entity),
+                    tbl_tracked.to_string(),
+                ));
+                per_table.push((format!("table_entries:{}", entity), tbl_entries.to_string()));
+            }
+        }
+        tracked += ks_tracked;
+        per_keyspace.push((
+            format!("keyspace_tracked_bytes:{}", ksid),
+            ks_tracked.to_string(),
+        ));
+        per_keyspace.extend(per_table);
+    }
+    let mut attached = 0usize;
+    let mut per_snapshot = Vec::new();
+    for snapshot in handle.get_store().attached.iter() {
+        let bytes = memwatch::keyspace_bytes(snapshot.value());
+        attached += bytes;
+        per_snapshot.push((
+            format!("attached_bytes:{}", String::from_utf8_lossy(snapshot.key())),
+            bytes.to_string(),
+        ));
+    }
+    let mut report = vec![
+        ("tracked_bytes".to_owned(), tracked.to_string()),
+        ("entries".to_owned(), entries.to_string()),
+        (
+            "entry_overhead_bytes".to_owned(),
+            (entries * ENTRY_OVERHEAD).to_string(),
+        ),
+        ("attached_bytes".to_owned(), attached.to_string()),
+    ];
+    report.extend(allocator_stats());
+    report.extend(per_keyspace);
+    report.extend(per_snapshot);
+    report
+}
+
+/// Returns the size histograms of the tables in the Prometheus text exposition format: the
+/// lengths of the keys (`skyd_table_key_size_bytes`) and of the values
+/// (`skyd_table_value_size_bytes`) that every table holds, labelled with the `table` (as
+/// `<keyspace>:<table>`), followed by the longest key (`skyd_table_largest_key_bytes`) and
+/// value (`skyd_table_largest_value_bytes`) that it held since it was read in. The tables
+/// that weren't read in yet are left out, so that a scrape never loads them
+pub fn table_sizes(handle: &Corestore) -> String {
+    let mut tables = Vec::new();
+    for keyspace in handle.get_store().keyspaces.iter() {
+        let ksid = String::from_utf8_lossy(keyspace.key()).into_owned();
+        for table in keyspace.value().tables.iter() {
+            if let Some(kve) = table.value().loaded_kvstore() {
+                let label = format!(
+                    "table=\"{}:{}\"",
+                    ksid,
+                    String::from_utf8_lossy(table.key())
+                );
+                tables.push((label, kve.key_sizes().values(), kve.value_sizes().values()));
+            }
+        }
+    }
+    fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+        out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+    }
+    let mut out = String::new();
+    header(
+        &mut out,
+        "skyd_table_key_size_bytes",
+        "histogram",
+        "The lengths of the keys that the tables hold",
+    );
+    for (label, keys, _) in tables.iter() {
+        keys.write_prometheus(&mut out, "skyd_table_key_size_bytes", label);
+    }
+    header(
+        &mut out,
+        "skyd_table_value_size_bytes",
+        "histogram",
+        "The lengths of the values that the tables hold",
+    );
+    for (label, _, values) in tables.iter() {
+        values.write_prometheus(&mut out, "skyd_table_value_size_bytes", label);
+    }
+    header(
+        &mut out,
+        "skyd_table_largest_key_bytes",
+        "gauge",
+        "The longest key that the tables held since they were read in",
+    );
+    for (label, keys, _) in tables.iter() {
+        out.push_str(&format!(
+            "skyd_table_largest_key_bytes{{{}}} {}\n",
+            label, keys.max
+        ));
+    }
+    header(
+        &mut out,
+        "skyd_table_largest_value_bytes",
+        "gauge",
+        "The longest value that the tables held since they were read in",
+    );
+    for (label, _, values) in tables.iter() {
+        out.push_str(&format!(
+            "skyd_table_largest_value_bytes{{{}}} {}\n",
+            label, values.max
+        ));
+    }
+    out
+}
+
+#[cfg(all(feature = "allocstats", not(target_env = "msvc")))]
+/// Returns the jemalloc statistics (the ones that can't be read are left out)
+fn allocator_stats() -> Vec<(String, String)> {
+    use jemalloc_ctl::{epoch, stats};
+    // the statistics are cached, so refresh them first
+    let _ = epoch::advance();
+    let stats = [
+        ("allocator_allocated", stats::allocated::read()),
+        ("allocator_active", stats::active::read()),
+        ("allocator_resident", stats::resident::read()),
+        ("allocator_metadata", stats::metadata::read()),
+    ];
+    stats
+        .iter()
+        .filter_map(|(name, stat)| {
+            stat.as_ref()
+                .ok()
+                .map(|bytes| ((*name).to_owned(), bytes.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(not(all(feature = "allocstats", not(target_env = "msvc"))))]
+/// The allocator statistics are only available with the `allocstats` feature
+fn allocator_stats() -> Vec<(String, String)> {
+    Vec::new()
+}
+
+action!(
+    /// Write a flat array of `<name> <value>` pairs (all the values are written as strings)
+    fn write_pairs(con: &mut T, pairs: &[(impl AsRef<str> + Sync, String)]) {
+        con.write_flat_array_length(pairs.len() * 2).await?;
+        for (name, value) in pairs {
+            con.write_response(BytesWrapper(Bytes::copy_from_slice(
+                name.as_ref().as_bytes(),
+            )))
+            .await?;
+            con.write_response(BytesWrapper(Bytes::from(value.clone())))
+                .await?;
+        }
+        Ok(())
+    }
+);
@@ -25,11 +25,16 @@
 */
 
 use crate::config::BGSave;
+use crate::config::LazyLoad;
 use crate::config::SnapshotConfig;
+use crate::corestore::memstore::Memstore;
 use crate::corestore::Corestore;
+use crate::daemon::{self, Readiness};
 use crate::dbnet::{self, Terminator};
+use crate::registry;
 use crate::services;
 use crate::PortConfig;
+use tokio::net::TcpListener;
 use tokio::sync::broadcast;
 
 #[cfg(unix)]
@@ -67,12 +72,24 @@ pub async fn run(
     snapshot_cfg: SnapshotConfig,
     _restore_filepath: Option<String>,
     maxcon: usize,
+    lazyload: LazyLoad,
+    healthport: Option<u16>,
+    config_file: Option<String>,
 ) -> Result<Corestore, String> {
     // Intialize the broadcast channel
     let (signal, _) = broadcast::channel(1);
 
-    let db = Corestore::init_with_snapcfg(&snapshot_cfg)
-        .map_err(|e| format!("Error while initializing database: {}", e))?;
+    let db = if registry::is_sandbox() {
+        // start out empty, and keep the DDL log in memory
+        Corestore::default_with_store(Memstore::new_default())
+    } else {
+        let db = Corestore::init_with_snapcfg(&snapshot_cfg, lazyload.is_enabled())
+            .map_err(|e| format!("Error while initializing database: {}", e))?;
+        registry::get_ddl_log()
+            .open(registry::ddl_log_path())
+            .map_err(|e| format!("Error while opening the DDL log: {}", e))?;
+        db
+    };
 
     // initialize the background services
     let bgsave_handle = tokio::spawn(services::bgsave::bgsave_scheduler(
@@ -85,12 +102,70 @@ pub async fn run(
         snapshot_cfg,
         Terminator::new(signal.subscribe()),
     ));
+    let expiry_handle = tokio::spawn(services::expiry::expiry_sweeper(
+        db.clone(),
+        Terminator::new(signal.subscribe()),
+    ));
+    let memwatch_handle = if registry::get_memory_guard().ceiling().is_some() {
+        Some(tokio::spawn(services::memwatch::memory_watcher(
+            db.clone(),
+            Terminator::new(signal.subscribe()),
+        )))
+    } else {
+        None
+    };
+    let selfcheck_handle = registry::get_selfcheck().interval().map(|every| {
+        tokio::spawn(services::selfcheck::selfcheck_service(
+            db.clone(),
+            every,
+            Terminator::new(signal.subscribe()),
+        ))
+    });
+    let prewarm_handle = match lazyload {
+        LazyLoad::Enabled { prewarm } if !prewarm.is_empty() => {
+            Some(tokio::spawn(services::prewarm::prewarm_service(
+                db.clone(),
+                prewarm,
+                Terminator::new(signal.subscribe()),
+            )))
+        }
+        _ => None,
+    };
+    // a SIGHUP sets the log targets up again from the configuration file
+    #[cfg(unix)]
+    let reload_handle = config_file.map(|file| {
+        tokio::spawn(crate::logger::reload_on_hangup(
+            file,
+            Terminator::new(signal.subscribe()),
+        ))
+    });
+    #[cfg(not(unix))]
+    drop(config_file);
 
     // bind the ctrlc handler
     let sig = tokio::signal::ctrl_c();
 
+    // health checks are answered on their own port and don't take a connection permit
+    let health_handle = match healthport {
+        Some(port) => {
+            let listener = TcpListener::bind((ports.host(), port))
+                .await
+                .map_err(|e| format!("Failed to bind to the health port with error: {}", e))?;
+            log::info!("Answering health checks on: {}", port);
+            Some(tokio::spawn(dbnet::health::health_listener(
+                listener,
+                registry::get_serving_state,
+                Terminator::new(signal.subscribe()),
+            )))
+        }
+        None => None,
+    };
+
     // start the server (single or multiple listeners)
     let mut server = dbnet::connect(ports, maxcon, db.clone(), signal.clone()).await?;
+    // the data has been loaded and the listeners are bound, so tell the service manager (if
+    // any) that we're ready
+    daemon::notify(Readiness::Ready);
 
     #[cfg(not(unix))]
     {
@@ -100,26 +175,49 @@ pub async fn run(
         tokio::select! {
             _ = server.run_server() => {}
             _ = sig => {}
+            _ = registry::get_shutdown().wait() => {}
         }
     }
     #[cfg(unix)]
     {
         let sigterm = UnixTerminationSignal::init()?;
-        // apart from CTRLC, the only other thing we care about is SIGTERM
-        // FIXME(@ohsayan): Maybe we should respond to SIGHUP too?
+        // apart from CTRLC, the only other thing that shuts us down is SIGTERM (a SIGHUP only
+        // reloads the logging configuration)
+        // `SYS SHUTDOWN` and `SYS RESTART` take the same path as SIGTERM
         tokio::select! {
             _ = server.run_server() => {},
             _ = sig => {},
-            _ = sigterm => {}
+            _ = sigterm => {},
+            _ = registry::get_shutdown().wait() => {}
         }
     }
 
     log::info!("Signalling all workers to shut down");
+    daemon::notify(Readiness::Stopping);
     // drop the signal and let others exit
     drop(signal);
+    // connections streaming changes are waiting on the change log instead
+    registry::get_changelog().close();
     server.finish_with_termsig().await;
 
     // wait for the background services to terminate
+    if let Some(health_handle) = health_handle {
+        let _ = health_handle.await;
+    }
+    if let Some(prewarm_handle) = prewarm_handle {
+        let _ = prewarm_handle.await;
+    }
+    if let Some(memwatch_handle) = memwatch_handle {
+        let _ = memwatch_handle.await;
+    }
+    if let Some(selfcheck_handle) = selfcheck_handle {
+        let _ = selfcheck_handle.await;
+    }
+    #[cfg(unix)]
+    if let Some(reload_handle) = reload_handle {
+        let _ = reload_handle.await;
+    }
+    let _ = expiry_handle.await;
     let _ = snapshot_handle.await;
     let _ = bgsave_handle.await;
     Ok(db)
@@ -26,7 +26,25 @@
 
 //! This module provides tools to handle configuration files and settings
 
+mod schema;
+
+use crate::dbnet::compression::Algorithm;
 use crate::dbnet::MAXIMUM_CONNECTION_LIMIT;
+use crate::diskstore::snapshot::{self, DEF_MAX_CHAIN};
+use crate::import::ImportOpts;
+use crate::registry::DEFAULT_COMPRESSION_THRESHOLD;
+use crate::registry::DEFAULT_MAX_NAME_LEN;
+use crate::registry::DEFAULT_SYNC_BUFFER;
+use crate::registry::{
+    DEFAULT_MAX_INFLIGHT, DEFAULT_MAX_INFLIGHT_BYTES, DEFAULT_MAX_PIPELINE_DEPTH,
+};
+use crate::registry::{
+    DEFAULT_MAX_QUEUED, DEFAULT_MAX_TOTAL_QUEUED, DEFAULT_MAX_TOTAL_TXN_BYTES,
+    DEFAULT_MAX_TXN_BYTES,
+};
+use crate::registry::{DEFAULT_SLOWLOG_SIZE, DEFAULT_SLOWLOG_THRESHOLD};
+use crate::schema::SchemaOpts;
+use crate::storage::migrate::MigrateOpts;
 #[cfg(test)]
 use libsky::TResult;
 use serde::Deserialize;
@@ -36,6 +54,7 @@ use std::fs;
 #[cfg(test)]
 use std::net::Ipv6Addr;
 use std::net::{IpAddr, Ipv4Addr};
+use toml::Value;
 const DEFAULT_IPV4: IpAddr = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
 #[cfg(test)]
 const DEFAULT_PORT: u16 = 2003;
@@ -52,6 +71,28 @@ pub struct Config {
     snapshot: Option<ConfigKeySnapshot>,
     /// SSL configuration
     ssl: Option<KeySslOpts>,
+    /// Write backpressure configuration
+    backpressure: Option<ConfigKeyBackpressure>,
+    /// Response compression configuration
+    compression: Option<ConfigKeyCompression>,
+    /// Change stream configuration
+    syncstream: Option<ConfigKeySyncstream>,
+    /// Lazy loading configuration
+    lazyload: Option<ConfigKeyLazyload>,
+    /// Transaction limits
+    transactions: Option<ConfigKeyTransactions>,
+    /// Pipeline limits
+    pipeline: Option<ConfigKeyPipeline>,
+    /// Slow query log configuration
+    slowlog: Option<ConfigKeySlowlog>,
+    /// Latency histogram configuration
+    metrics: Option<ConfigKeyMetrics>,
+    /// IO throttling configuration
+    throttle: Option<ConfigKeyThrottle>,
+    /// Consistency checker configuration
+    selfcheck: Option<ConfigKeySelfcheck>,
+    /// Logging configuration
+    log: Option<ConfigKeyLog>,
 }
 
 /// The BGSAVE section in the config file
@@ -113,6 +154,57 @@ pub struct ConfigKeyServer {
     noart: Option<bool>,
     /// The maximum number of clients
     maxclient: Option<usize>,
+    /// Whether reads are served while a failed flush blocks writes (defaults to serving
+    /// them)
+    readpolicy: Option<ReadPolicy>,
+    /// The largest value (in bytes) that can be written to a table that doesn't have a limit
+    /// of its own (no limit if this isn't set)
+    maxvaluesize: Option<u64>,
+    /// The directory that everything is stored in (defaults to `data`, in the directory that
+    /// the server is started in)
+    datadir: Option<String>,
+    /// The longest (in seconds) that `SYS BGPAUSE` can pause the background services for
+    /// (defaults to an hour)
+    maxpause: Option<u64>,
+    /// The longest name (in bytes) that a new keyspace or table can have (defaults to 64)
+    maxnamelen: Option<usize>,
+    /// The token that snapshots are shipped to and from other servers with (snapshots can't
+    /// be shipped if this isn't set)
+    snaptoken: Option<String>,
+    /// The most threads that the tables are read in with at startup (defaults to 8, and never
+    /// more than the number of CPUs)
+    loadthreads: Option<usize>,
+    /// The most keys that a table can have for `LSKEYS ... ORDERED` to sort them (defaults to
+    /// 1000000; tables with an ordered index are never refused)
+    maxorderedkeys: Option<usize>,
+    /// The actions that are disabled (they return an error instead of running)
+    disabledactions: Option<Vec<String>>,
+    /// The port that health checks are answered on (there's no health listener if this isn't
+    /// set)
+    healthport: Option<u16>,
+    /// The false positive rate of the bloom filters that are written along with the tables
+    /// (defaults to 0.01)
+    bloomfprate: Option<f64>,
+    /// The most memory (in bytes) that the server can use before it rejects writes (there's
+    /// no ceiling if this isn't set)
+    memoryceiling: Option<usize>,
+    /// Whether the records of the tables are written with checksums, and what happens to the
+    /// records that don't match theirs when they're read in (defaults to no checksums)
+    paranoid: Option<ParanoidMode>,
+    /// Whether the flushes are batched through io_uring, if the server was built with the
+    /// `uring` feature and the kernel has it (defaults to false)
+    uring: Option<bool>,
+    /// What happens at startup to the table files that no `PARTMAP` has (defaults to failing
+    /// the startup)
+    orphans: Option<OrphanPolicy>,
+    /// Whether a table whose file is missing is loaded empty instead of failing the startup
+    /// (defaults to false)
+    allowmissing: Option<bool>,
+    /// Whether the time of the latest read of every table is tracked (defaults to false)
+    trackreads: Option<bool>,
+    /// Whether the names of keyspaces and tables are compared without regard to ASCII case
+    /// (defaults to false)
+    caseinsensitive: Option<bool>,
 }
 
 /// The snapshot section in the TOML file
@@ -124,10 +216,548 @@ pub struct ConfigKeySnapshot {
     ///
     /// If atmost is set to `0`, then all the snapshots will be kept
     atmost: usize,
+    /// Remove snapshots once they are older than this many seconds
+    maxage: Option<u64>,
+    /// The maximum number of incremental snapshots in a chain
+    maxchain: Option<usize>,
+    /// What the names of the snapshots start with
+    prefix: Option<String>,
     /// Prevent writes to the database if snapshotting fails
     failsafe: Option<bool>,
 }
 
+/// The backpressure section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyBackpressure {
+    /// The number of bytes that can be mutated (but not flushed) before writes are
+    /// throttled
+    dirtymark: usize,
+    /// What to do with writes once `dirtymark` is crossed (defaults to delaying them)
+    policy: Option<BackpressurePolicy>,
+}
+
+/// What happens to writes once the dirty bytes mark is crossed
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum BackpressurePolicy {
+    /// Delay the writes until a flush completes
+    Delay,
+    /// Reject the writes with a `server-busy-writes` error
+    Reject,
+}
+
+impl BackpressurePolicy {
+    pub const fn is_reject(&self) -> bool {
+        matches!(self, BackpressurePolicy::Reject)
+    }
+}
+
+/// What happens to reads while a failed flush blocks writes
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum ReadPolicy {
+    /// Fail the reads with a server error, just like the writes
+    Fail,
+    /// Serve the reads from memory, even though it might be ahead of what's on disk
+    StaleOk,
+}
+
+impl ReadPolicy {
+    pub const fn is_stale_ok(&self) -> bool {
+        matches!(self, ReadPolicy::StaleOk)
+    }
+}
+
+/// Whether the records of the tables are written with checksums and what happens to the
+/// records that don't match theirs when the tables are read in. Files with checksums can be
+/// read whatever the mode is, but they're only verified if the checksums are on. With the
+/// checksums on, the periodic flushes also sync the directories that they rename files in
+/// (snapshots always do)
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum ParanoidMode {
+    /// Write no checksums and don't verify them
+    Off = 0,
+    /// Write and verify the checksums, logging and counting the records that don't match
+    /// (they're still read in)
+    Report = 1,
+    /// Write and verify the checksums, refusing to read in a table with a record that
+    /// doesn't match
+    Reject = 2,
+}
+
+impl ParanoidMode {
+    pub const fn has_checksums(&self) -> bool {
+        !matches!(self, ParanoidMode::Off)
+    }
+    pub const fn is_reject(&self) -> bool {
+        matches!(self, ParanoidMode::Reject)
+    }
+    /// Whether the periodic flushes sync the directories of the files that they write
+    pub const fn syncs_dirs(&self) -> bool {
+        self.has_checksums()
+    }
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            ParanoidMode::Off => "off",
+            ParanoidMode::Report => "report",
+            ParanoidMode::Reject => "reject",
+        }
+    }
+}
+
+/// What happens at startup to the files in the directory of a keyspace that look like the
+/// files of a table, but whose table isn't in the `PARTMAP` of the keyspace (say, after a
+/// partial manual restore)
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+#[repr(u8)]
+pub enum OrphanPolicy {
+    /// Refuse to start, reporting every orphan
+    Fail = 0,
+    /// Register the orphans as persistent `keymap(binstr,binstr)` tables
+    Adopt = 1,
+    /// Move the orphans out of the way, into the `.orphaned` directory of their keyspace
+    Quarantine = 2,
+}
+
+impl OrphanPolicy {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            OrphanPolicy::Fail => "fail",
+            OrphanPolicy::Adopt => "adopt",
+            OrphanPolicy::Quarantine => "quarantine",
+        }
+    }
+}
+
+/// The write backpressure configuration
+#[derive(Debug, PartialEq)]
+pub struct BackpressurePref {
+    /// Throttle writes once these many bytes haven't been flushed
+    pub dirtymark: usize,
+    /// What to do with the throttled writes
+    pub policy: BackpressurePolicy,
+}
+
+impl BackpressurePref {
+    pub const fn new(dirtymark: usize, policy: BackpressurePolicy) -> Self {
+        BackpressurePref { dirtymark, policy }
+    }
+}
+
+/// The compression section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyCompression {
+    /// The algorithm that clients can negotiate (defaults to LZ4)
+    algorithm: Option<CompressionAlgorithm>,
+    /// Responses that are at least these many bytes long are compressed
+    threshold: Option<usize>,
+}
+
+/// The syncstream section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeySyncstream {
+    /// The number of recent mutations to keep around for `SYNCSTREAM`
+    buffer: usize,
+}
+
+/// The lazyload section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyLazyload {
+    /// Whether only the metadata of the tables is read at boot (and the data on first access)
+    enabled: bool,
+    /// The tables (as `<keyspace>:<table>`) to read in the background after boot, in this order
+    prewarm: Option<Vec<String>>,
+}
+
+/// The transactions section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyTransactions {
+    /// The most queries that a transaction can queue
+    maxqueued: Option<usize>,
+    /// The most bytes that a transaction can queue
+    maxbytes: Option<usize>,
+    /// The most queries that all the open transactions can queue together
+    maxtotalqueued: Option<usize>,
+    /// The most bytes that all the open transactions can queue together
+    maxtotalbytes: Option<usize>,
+}
+
+/// The limits on what transactions can queue
+#[derive(Debug, PartialEq)]
+pub struct TxnLimits {
+    /// The most queries that a transaction can queue
+    pub maxqueued: usize,
+    /// The most bytes that a transaction can queue
+    pub maxbytes: usize,
+    /// The most queries that all the open transactions can queue together
+    pub maxtotalqueued: usize,
+    /// The most bytes that all the open transactions can queue together
+    pub maxtotalbytes: usize,
+}
+
+impl TxnLimits {
+    pub const fn new(
+        maxqueued: usize,
+        maxbytes: usize,
+        maxtotalqueued: usize,
+        maxtotalbytes: usize,
+    ) -> Self {
+        TxnLimits {
+            maxqueued,
+            maxbytes,
+            maxtotalqueued,
+            maxtotalbytes,
+        }
+    }
+    /// The default transaction limits
+    ///
+    /// Defaults:
+    /// - `maxqueued`: 10000
+    /// - `maxbytes`: 16MB
+    /// - `maxtotalqueued`: 1000000
+    /// - `maxtotalbytes`: 256MB
+    pub const fn default() -> Self {
+        TxnLimits::new(
+            DEFAULT_MAX_QUEUED,
+            DEFAULT_MAX_TXN_BYTES,
+            DEFAULT_MAX_TOTAL_QUEUED,
+            DEFAULT_MAX_TOTAL_TXN_BYTES,
+        )
+    }
+}
+
+/// The pipeline section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyPipeline {
+    /// The most responses to pipelined queries that a connection holds back
+    maxinflight: Option<usize>,
+    /// The most bytes of those responses that a connection holds back
+    maxinflightbytes: Option<usize>,
+    /// The most queries that a client can pipeline in one go (0 is no limit)
+    maxdepth: Option<usize>,
+}
+
+/// The limits on pipelined queries
+#[derive(Debug, PartialEq)]
+pub struct PipelineLimits {
+    /// The most responses to pipelined queries that a connection holds back
+    pub maxinflight: usize,
+    /// The most bytes of those responses that a connection holds back
+    pub maxinflightbytes: usize,
+    /// The most queries that a client can pipeline in one go (0 is no limit)
+    pub maxdepth: usize,
+}
+
+impl PipelineLimits {
+    pub const fn new(maxinflight: usize, maxinflightbytes: usize, maxdepth: usize) -> Self {
+        PipelineLimits {
+            maxinflight,
+            maxinflightbytes,
+            maxdepth,
+        }
+    }
+    /// The default pipeline limits
+    ///
+    /// Defaults:
+    /// - `maxinflight`: 128
+    /// - `maxinflightbytes`: 64KB
+    /// - `maxdepth`: 0 (no limit)
+    pub const fn default() -> Self {
+        PipelineLimits::new(
+            DEFAULT_MAX_INFLIGHT,
+            DEFAULT_MAX_INFLIGHT_BYTES,
+            DEFAULT_MAX_PIPELINE_DEPTH,
+        )
+    }
+}
+
+/// The slowlog section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeySlowlog {
+    /// The number of microseconds that an action has to take to be logged
+    threshold: Option<u64>,
+    /// The number of slow queries that are kept around
+    size: Option<usize>,
+}
+
+/// The slow query log configuration
+#[derive(Debug, PartialEq)]
+pub struct SlowlogPref {
+    /// The number of microseconds that an action has to take to be logged
+    pub threshold: u64,
+    /// The number of slow queries that are kept around
+    pub size: usize,
+}
+
+impl SlowlogPref {
+    pub const fn new(threshold: u64, size: usize) -> Self {
+        SlowlogPref { threshold, size }
+    }
+    /// The default slow query log configuration
+    ///
+    /// Defaults:
+    /// - `threshold`: 10000 (10ms)
+    /// - `size`: 128
+    pub const fn default() -> Self {
+        SlowlogPref::new(DEFAULT_SLOWLOG_THRESHOLD, DEFAULT_SLOWLOG_SIZE)
+    }
+}
+
+/// The metrics section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyMetrics {
+    /// The upper bounds of the buckets of the latency histograms, in microseconds
+    buckets: Option<Vec<u64>>,
+}
+
+/// The latency histogram configuration
+#[derive(Debug, PartialEq)]
+pub struct MetricsPref {
+    /// The upper bounds of the buckets of the latency histograms, in microseconds (the
+    /// default buckets are used if this is `None`)
+    pub buckets: Option<Vec<u64>>,
+}
+
+impl MetricsPref {
+    pub const fn new(buckets: Option<Vec<u64>>) -> Self {
+        MetricsPref { buckets }
+    }
+    /// The default latency histogram configuration: log-scaled buckets from 100µs to 10s
+    pub const fn default() -> Self {
+        MetricsPref::new(None)
+    }
+}
+
+/// The throttle section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyThrottle {
+    /// The most bytes per second that the snapshots write
+    snapshot: Option<u64>,
+    /// The most bytes per second that the periodic flushes write
+    flush: Option<u64>,
+}
+
+/// The IO throttling configuration
+#[derive(Debug, PartialEq)]
+pub struct ThrottlePref {
+    /// The most bytes per second that the snapshots write (no limit if `None`)
+    pub snapshot: Option<u64>,
+    /// The most bytes per second that the periodic flushes write (no limit if `None`)
+    pub flush: Option<u64>,
+}
+
+impl ThrottlePref {
+    pub const fn new(snapshot: Option<u64>, flush: Option<u64>) -> Self {
+        ThrottlePref { snapshot, flush }
+    }
+    /// The default IO throttling configuration: neither the snapshots nor the periodic flushes
+    /// are throttled
+    pub const fn default() -> Self {
+        ThrottlePref::new(None, None)
+    }
+}
+
+/// The selfcheck section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeySelfcheck {
+    /// How often (in hours) the tables are checked against their files
+    every: u64,
+    /// The records that a table can drift by before it is flagged
+    tolerance: Option<u64>,
+}
+
+/// The consistency checker configuration
+#[derive(Debug, PartialEq)]
+pub struct SelfcheckPref {
+    /// How often (in hours) the tables are checked against their files (never if `None`)
+    pub every: Option<u64>,
+    /// The records that a table can drift by before it is flagged
+    pub tolerance: u64,
+}
+
+impl SelfcheckPref {
+    pub const fn new(every: Option<u64>, tolerance: u64) -> Self {
+        SelfcheckPref { every, tolerance }
+    }
+    /// The default consistency checker configuration: the tables are never checked
+    pub const fn default() -> Self {
+        SelfcheckPref::new(None, 0)
+    }
+}
+
+/// The log section in the TOML file
+#[derive(Deserialize, Debug, PartialEq)]
+pub struct ConfigKeyLog {
+    /// Where the logs are written
+    targets: Option<Vec<LogTarget>>,
+    /// The filter of the targets that don't have one of their own
+    level: Option<String>,
+    stderrlevel: Option<String>,
+    filelevel: Option<String>,
+    journaldlevel: Option<String>,
+    sysloglevel: Option<String>,
+    /// The file that the `file` target writes to
+    file: Option<String>,
+    /// The size (in bytes) that the log file is rotated at
+    maxsize: Option<u64>,
+    /// How often (in seconds) the log file is rotated
+    every: Option<u64>,
+    /// The rotated log files that are kept
+    keep: Option<usize>,
+}
+
+/// Somewhere that the logs can be written
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    /// The standard error (which is where the logs have always gone)
+    Stderr,
+    /// A file, rotated by size or by age
+    File,
+    /// The systemd journal (falls back to syslog if there's no journal to write to)
+    Journald,
+    /// The local syslog daemon, in RFC 5424 format
+    Syslog,
+}
+
+impl LogTarget {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            LogTarget::Stderr => "stderr",
+            LogTarget::File => "file",
+            LogTarget::Journald => "journald",
+            LogTarget::Syslog => "syslog",
+        }
+    }
+}
+
+/// The default number of rotated log files that are kept
+const DEFAULT_LOG_KEEP: usize = 5;
+
+/// The logging configuration
+#[derive(Debug, PartialEq, Clone)]
+pub struct LogPref {
+    /// Where the logs are written (only to the standard error if `None`)
+    pub targets: Option<Vec<LogTarget>>,
+    /// The filter of the targets that don't have one of their own (in the syntax of `SKY_LOG`,
+    /// which is used if this is `None`)
+    pub level: Option<String>,
+    /// The filters of the targets, in the order of [`LogTarget`]
+    pub levels: [Option<String>; 4],
+    /// The file that the `file` target writes to
+    pub file: Option<String>,
+    /// The size (in bytes) that the log file is rotated at (never if `None`)
+    pub maxsize: Option<u64>,
+    /// How often (in seconds) the log file is rotated (never if `None`)
+    pub every: Option<u64>,
+    /// The rotated log files that are kept
+    pub keep: usize,
+}
+
+impl LogPref {
+    /// The default logging configuration: everything that `SKY_LOG` lets through goes to the
+    /// standard error
+    pub const fn default() -> Self {
+        LogPref {
+            targets: None,
+            level: None,
+            levels: [None, None, None, None],
+            file: None,
+            maxsize: None,
+            every: None,
+            keep: DEFAULT_LOG_KEEP,
+        }
+    }
+    /// Returns the targets that the logs are written to
+    pub fn targets(&self) -> &[LogTarget] {
+        self.targets.as_deref().unwrap_or(&[LogTarget::Stderr])
+    }
+    /// Returns the filter of `target`, if it has one of its own
+    pub fn level_of(&self, target: LogTarget) -> Option<&str> {
+        self.levels[target as usize].as_deref()
+    }
+    /// Returns an error if the targets can't be set up the way they're configured
+    pub fn check(&self) -> Result<(), &'static str> {
+        if self.targets().is_empty() {
+            return Err("The logs have to be written to at least one target!");
+        }
+        if self.targets().contains(&LogTarget::File) && self.file.is_none() {
+            return Err("The `file` log target needs a `file` to write to!");
+        }
+        if self.file.as_deref() == Some("") {
+            return Err("The log file can't be empty!");
+        }
+        if self.maxsize == Some(0) || self.every == Some(0) {
+            return Err("The log file has to be rotated at more than 0 bytes or seconds!");
+        }
+        Ok(())
+    }
+}
+
+/// How the tables are read at boot
+#[derive(Debug, PartialEq)]
+pub enum LazyLoad {
+    /// Only read the metadata of the tables at boot and the data of a table on first access.
+    /// The tables in `prewarm` (as `<keyspace>:<table>`) are read in the background, in order
+    Enabled { prewarm: Vec<String> },
+    /// Read every table at boot
+    Disabled,
+}
+
+impl LazyLoad {
+    pub const fn is_enabled(&self) -> bool {
+        matches!(self, LazyLoad::Enabled { .. })
+    }
+}
+
+/// The algorithm that clients can negotiate to compress responses
+#[derive(Deserialize, Debug, PartialEq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionAlgorithm {
+    Lz4,
+    /// Don't let clients negotiate compression
+    None,
+}
+
+impl CompressionAlgorithm {
+    /// Returns the algorithm to be used by connections (`None` if compression is disabled)
+    pub const fn get_algorithm(&self) -> Option<Algorithm> {
+        match self {
+            CompressionAlgorithm::Lz4 => Some(Algorithm::Lz4),
+            CompressionAlgorithm::None => None,
+        }
+    }
+}
+
+/// The response compression configuration
+#[derive(Debug, PartialEq)]
+pub struct CompressionPref {
+    /// The algorithm that clients can negotiate
+    pub algorithm: CompressionAlgorithm,
+    /// Compress responses that are at least these many bytes long
+    pub threshold: usize,
+}
+
+impl CompressionPref {
+    pub const fn new(algorithm: CompressionAlgorithm, threshold: usize) -> Self {
+        CompressionPref {
+            algorithm,
+            threshold,
+        }
+    }
+    /// The default compression configuration
+    ///
+    /// Defaults:
+    /// - `algorithm`: lz4
+    /// - `threshold`: 4096
+    pub const fn default() -> Self {
+        CompressionPref::new(CompressionAlgorithm::Lz4, DEFAULT_COMPRESSION_THRESHOLD)
+    }
+}
+
 /// Port configuration
 ///
 /// This enumeration determines whether the ports are:
@@ -174,6 +804,14 @@ impl PortConfig {
     pub const fn new_multi(host: IpAddr, port: u16, ssl: SslOpts) -> Self {
         PortConfig::Multi { host, port, ssl }
     }
+    /// Returns the address that the listeners bind to
+    pub const fn host(&self) -> IpAddr {
+        match self {
+            PortConfig::InsecureOnly { host, .. }
+            | PortConfig::SecureOnly { host, .. }
+            | PortConfig::Multi { host, .. } => *host,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, PartialEq)]
@@ -212,6 +850,12 @@ pub struct SnapshotPref {
     pub every: u64,
     /// The maximum numeber of snapshots to be kept
     pub atmost: usize,
+    /// The age (in seconds) after which snapshots are removed
+    pub maxage: Option<u64>,
+    /// The maximum number of incremental snapshots in a chain
+    pub maxchain: usize,
+    /// What the names of the snapshots start with (nothing if it's empty)
+    pub prefix: String,
     /// Lock writes if snapshotting fails
     pub poison: bool,
 }
@@ -222,12 +866,27 @@ impl SnapshotPref {
         SnapshotPref {
             every,
             atmost,
+            maxage: None,
+            maxchain: DEF_MAX_CHAIN,
+            prefix: String::new(),
             poison,
         }
     }
-    /// Returns `every,almost` as a tuple for pattern matching
-    pub const fn decompose(self) -> (u64, usize, bool) {
-        (self.every, self.atmost, self.poison)
+    /// Remove snapshots once they're older than `maxage` seconds
+    pub fn with_maxage(self, maxage: Option<u64>) -> Self {
+        SnapshotPref { maxage, ..self }
+    }
+    /// Allow up to `maxchain` incremental snapshots in a chain
+    pub fn with_maxchain(self, maxchain: usize) -> Self {
+        SnapshotPref { maxchain, ..self }
+    }
+    /// Start the names of the snapshots with `prefix`
+    pub fn with_prefix(self, prefix: String) -> Self {
+        SnapshotPref { prefix, ..self }
+    }
+    /// Returns `every,almost,maxage,poison` as a tuple for pattern matching
+    pub fn decompose(self) -> (u64, usize, Option<u64>, bool) {
+        (self.every, self.atmost, self.maxage, self.poison)
     }
 }
 
@@ -268,6 +927,68 @@ pub struct ParsedConfig {
     pub ports: PortConfig,
     /// The maximum number of connections
     pub maxcon: usize,
+    /// The write backpressure configuration (disabled if `None`)
+    pub backpressure: Option<BackpressurePref>,
+    /// The response compression configuration
+    pub compression: CompressionPref,
+    /// The number of recent mutations to keep around for `SYNCSTREAM`
+    pub syncbuffer: usize,
+    /// What happens to reads while writes are blocked
+    pub readpolicy: ReadPolicy,
+    /// How the tables are read at boot
+    pub lazyload: LazyLoad,
+    /// The largest value (in bytes) that can be written to the tables that don't have a limit
+    /// of their own (no limit if `None`)
+    pub maxvaluesize: Option<u64>,
+    /// The directory that everything is stored in (the default one if `None`)
+    pub datadir: Option<String>,
+    /// The longest (in seconds) that the background services can be paused for (the default
+    /// if `None`)
+    pub maxpause: Option<u64>,
+    /// The longest name (in bytes) that a new keyspace or table can have (the default if `None`)
+    pub maxnamelen: Option<usize>,
+    /// The token that snapshots are shipped with (no shipping if `None`)
+    pub snaptoken: Option<String>,
+    /// The most threads that the tables are read in with at startup (the default if `None`)
+    pub loadthreads: Option<usize>,
+    /// The most keys that a table can have for `LSKEYS ... ORDERED` to sort them (the default
+    /// if `None`)
+    pub maxorderedkeys: Option<usize>,
+    /// The actions that are disabled
+    pub disabledactions: Vec<String>,
+    /// The port that health checks are answered on (no health listener if `None`)
+    pub healthport: Option<u16>,
+    /// The false positive rate of the bloom filters of the tables (the default if `None`)
+    pub bloomfprate: Option<f64>,
+    /// The memory usage (in bytes) above which writes are rejected (no ceiling if `None`)
+    pub memoryceiling: Option<usize>,
+    /// Whether the records are written with checksums and what happens to the ones that don't
+    /// match
+    pub paranoid: ParanoidMode,
+    /// Whether the flushes are batched through io_uring (if it's available)
+    pub uring: bool,
+    /// What happens to the table files that no `PARTMAP` has
+    pub orphans: OrphanPolicy,
+    /// Whether a table whose file is missing is loaded empty
+    pub allowmissing: bool,
+    /// Whether the time of the latest read of every table is tracked
+    pub trackreads: bool,
+    /// Whether the names of keyspaces and tables are compared without regard to ASCII case
+    pub caseinsensitive: bool,
+    /// The limits on what transactions can queue
+    pub transactions: TxnLimits,
+    /// The limits on pipelined queries
+    pub pipeline: PipelineLimits,
+    /// The slow query log configuration
+    pub slowlog: SlowlogPref,
+    /// The latency histogram configuration
+    pub metrics: MetricsPref,
+    /// The limits on the bandwidth of the snapshots and the periodic flushes
+    pub throttle: ThrottlePref,
+    /// The consistency checker configuration
+    pub selfcheck: SelfcheckPref,
+    /// The logging configuration
+    pub log: LogPref,
 }
 
 impl ParsedConfig {
@@ -277,7 +998,13 @@ impl ParsedConfig {
             Ok(f) => f,
             Err(e) => return Err(ConfigError::OSError(e.into())),
         };
-        match toml::from_str(&file) {
+        Self::from_layers(&file, &schema::env_vars())
+    }
+    /// Create a new `ParsedConfig` from the TOML in `file` with the environment overrides in
+    /// `vars` layered over it (see [`schema`] for the order of precedence)
+    fn from_layers(file: &str, vars: &[(String, String)]) -> Result<Self, ConfigError> {
+        let layered = schema::load(file, vars)?;
+        match Value::Table(layered).try_into() {
             Ok(cfgfile) => Ok(ParsedConfig::from_config(cfgfile)),
             Err(e) => Err(ConfigError::SyntaxError(e.into())),
         }
@@ -301,11 +1028,16 @@ impl ParsedConfig {
             snapshot: cfg_info
                 .snapshot
                 .map(|snapshot| {
-                    SnapshotConfig::Enabled(SnapshotPref::new(
-                        snapshot.every,
-                        snapshot.atmost,
-                        option_unwrap_or!(snapshot.failsafe, true),
-                    ))
+                    SnapshotConfig::Enabled(
+                        SnapshotPref::new(
+                            snapshot.every,
+                            snapshot.atmost,
+                            option_unwrap_or!(snapshot.failsafe, true),
+                        )
+                        .with_maxage(snapshot.maxage)
+                        .with_maxchain(option_unwrap_or!(snapshot.maxchain, DEF_MAX_CHAIN))
+                        .with_prefix(snapshot.prefix.unwrap_or_default()),
+                    )
                 })
                 .unwrap_or_else(SnapshotConfig::default),
             ports: if let Some(sslopts) = cfg_info.ssl {
@@ -338,12 +1070,116 @@ impl ParsedConfig {
                 }
             },
             maxcon: option_unwrap_or!(cfg_info.server.maxclient, MAXIMUM_CONNECTION_LIMIT),
+            backpressure: cfg_info.backpressure.map(|bp| {
+                BackpressurePref::new(
+                    bp.dirtymark,
+                    option_unwrap_or!(bp.policy, BackpressurePolicy::Delay),
+                )
+            }),
+            compression: cfg_info
+                .compression
+                .map(|compression| {
+                    CompressionPref::new(
+                        option_unwrap_or!(compression.algorithm, CompressionAlgorithm::Lz4),
+                        option_unwrap_or!(compression.threshold, DEFAULT_COMPRESSION_THRESHOLD),
+                    )
+                })
+                .unwrap_or_else(CompressionPref::default),
+            syncbuffer: cfg_info
+                .syncstream
+                .map(|syncstream| syncstream.buffer)
+                .unwrap_or(DEFAULT_SYNC_BUFFER),
+            readpolicy: option_unwrap_or!(cfg_info.server.readpolicy, ReadPolicy::StaleOk),
+            lazyload: match cfg_info.lazyload {
+                Some(lazyload) if lazyload.enabled => LazyLoad::Enabled {
+                    prewarm: lazyload.prewarm.unwrap_or_default(),
+                },
+                _ => LazyLoad::Disabled,
+            },
+            maxvaluesize: cfg_info.server.maxvaluesize,
+            datadir: cfg_info.server.datadir,
+            maxpause: cfg_info.server.maxpause,
+            maxnamelen: cfg_info.server.maxnamelen,
+            snaptoken: cfg_info.server.snaptoken,
+            loadthreads: cfg_info.server.loadthreads,
+            maxorderedkeys: cfg_info.server.maxorderedkeys,
+            disabledactions: cfg_info.server.disabledactions.unwrap_or_default(),
+            healthport: cfg_info.server.healthport,
+            bloomfprate: cfg_info.server.bloomfprate,
+            memoryceiling: cfg_info.server.memoryceiling,
+            paranoid: option_unwrap_or!(cfg_info.server.paranoid, ParanoidMode::Off),
+            uring: option_unwrap_or!(cfg_info.server.uring, false),
+            orphans: option_unwrap_or!(cfg_info.server.orphans, OrphanPolicy::Fail),
+            allowmissing: option_unwrap_or!(cfg_info.server.allowmissing, false),
+            trackreads: option_unwrap_or!(cfg_info.server.trackreads, false),
+            caseinsensitive: option_unwrap_or!(cfg_info.server.caseinsensitive, false),
+            transactions: cfg_info
+                .transactions
+                .map(|txn| {
+                    TxnLimits::new(
+                        option_unwrap_or!(txn.maxqueued, DEFAULT_MAX_QUEUED),
+                        option_unwrap_or!(txn.maxbytes, DEFAULT_MAX_TXN_BYTES),
+                        option_unwrap_or!(txn.maxtotalqueued, DEFAULT_MAX_TOTAL_QUEUED),
+                        option_unwrap_or!(txn.maxtotalbytes, DEFAULT_MAX_TOTAL_TXN_BYTES),
+                    )
+                })
+                .unwrap_or_else(TxnLimits::default),
+            pipeline: cfg_info
+                .pipeline
+                .map(|pipeline| {
+                    PipelineLimits::new(
+                        option_unwrap_or!(pipeline.maxinflight, DEFAULT_MAX_INFLIGHT),
+                        option_unwrap_or!(pipeline.maxinflightbytes, DEFAULT_MAX_INFLIGHT_BYTES),
+                        option_unwrap_or!(pipeline.maxdepth, DEFAULT_MAX_PIPELINE_DEPTH),
+                    )
+                })
+                .unwrap_or_else(PipelineLimits::default),
+            slowlog: cfg_info
+                .slowlog
+                .map(|slowlog| {
+                    SlowlogPref::new(
+                        option_unwrap_or!(slowlog.threshold, DEFAULT_SLOWLOG_THRESHOLD),
+                        option_unwrap_or!(slowlog.size, DEFAULT_SLOWLOG_SIZE),
+                    )
+                })
+                .unwrap_or_else(SlowlogPref::default),
+            metrics: MetricsPref::new(cfg_info.metrics.and_then(|metrics| metrics.buckets)),
+            throttle: cfg_info
+                .throttle
+                .map(|throttle| ThrottlePref::new(throttle.snapshot, throttle.flush))
+                .unwrap_or_else(ThrottlePref::default),
+            selfcheck: cfg_info
+                .selfcheck
+                .map(|selfcheck| {
+                    SelfcheckPref::new(
+                        Some(selfcheck.every),
+                        selfcheck.tolerance.unwrap_or_default(),
+                    )
+                })
+                .unwrap_or_else(SelfcheckPref::default),
+            log: cfg_info
+                .log
+                .map(|log| LogPref {
+                    targets: log.targets,
+                    level: log.level,
+                    levels: [
+                        log.stderrlevel,
+                        log.filelevel,
+                        log.journaldlevel,
+                        log.sysloglevel,
+                    ],
+                    file: log.file,
+                    maxsize: log.maxsize,
+                    every: log.every,
+                    keep: option_unwrap_or!(log.keep, DEFAULT_LOG_KEEP),
+                })
+                .unwrap_or_else(LogPref::default),
         }
     }
     #[cfg(test)]
     /// Create a new `ParsedConfig` from a `TOML` string
     pub fn new_from_toml_str(tomlstr: String) -> TResult<Self> {
-        Ok(ParsedConfig::from_config(toml::from_str(&tomlstr)?))
+        ParsedConfig::from_layers(&tomlstr, &[]).map_err(|e| e.to_string().into())
     }
     /// Create a new `ParsedConfig` with all the fields
     pub const fn new(
@@ -352,6 +1188,35 @@ impl ParsedConfig {
         snapshot: SnapshotConfig,
         ports: PortConfig,
         maxcon: usize,
+        backpressure: Option<BackpressurePref>,
+        compression: CompressionPref,
+        syncbuffer: usize,
+        readpolicy: ReadPolicy,
+        lazyload: LazyLoad,
+        maxvaluesize: Option<u64>,
+        datadir: Option<String>,
+        maxpause: Option<u64>,
+        maxnamelen: Option<usize>,
+        snaptoken: Option<String>,
+        loadthreads: Option<usize>,
+        maxorderedkeys: Option<usize>,
+        disabledactions: Vec<String>,
+        healthport: Option<u16>,
+        bloomfprate: Option<f64>,
+        memoryceiling: Option<usize>,
+        paranoid: ParanoidMode,
+        uring: bool,
+        orphans: OrphanPolicy,
+        allowmissing: bool,
+        trackreads: bool,
+        caseinsensitive: bool,
+        transactions: TxnLimits,
+        pipeline: PipelineLimits,
+        slowlog: SlowlogPref,
+        metrics: MetricsPref,
+        throttle: ThrottlePref,
+        selfcheck: SelfcheckPref,
+        log: LogPref,
     ) -> Self {
         ParsedConfig {
             noart,
@@ -359,6 +1224,35 @@ impl ParsedConfig {
             snapshot,
             ports,
             maxcon,
+            backpressure,
+            compression,
+            syncbuffer,
+            readpolicy,
+            lazyload,
+            maxvaluesize,
+            datadir,
+            maxpause,
+            maxnamelen,
+            snaptoken,
+            loadthreads,
+            maxorderedkeys,
+            disabledactions,
+            healthport,
+            bloomfprate,
+            memoryceiling,
+            paranoid,
+            uring,
+            orphans,
+            allowmissing,
+            trackreads,
+            caseinsensitive,
+            transactions,
+            pipeline,
+            slowlog,
+            metrics,
+            throttle,
+            selfcheck,
+            log,
         }
     }
     /// Create a default `ParsedConfig` with the following setup defaults:
@@ -375,15 +1269,53 @@ impl ParsedConfig {
             snapshot: SnapshotConfig::default(),
             ports: PortConfig::new_insecure_only(DEFAULT_IPV4, 2003),
             maxcon: MAXIMUM_CONNECTION_LIMIT,
+            backpressure: None,
+            compression: CompressionPref::default(),
+            syncbuffer: DEFAULT_SYNC_BUFFER,
+            readpolicy: ReadPolicy::StaleOk,
+            lazyload: LazyLoad::Disabled,
+            maxvaluesize: None,
+            datadir: None,
+            maxpause: None,
+            maxnamelen: None,
+            snaptoken: None,
+            loadthreads: None,
+            maxorderedkeys: None,
+            disabledactions: Vec::new(),
+            healthport: None,
+            bloomfprate: None,
+            memoryceiling: None,
+            paranoid: ParanoidMode::Off,
+            uring: false,
+            orphans: OrphanPolicy::Fail,
+            allowmissing: false,
+            trackreads: false,
+            caseinsensitive: false,
+            transactions: TxnLimits::default(),
+            pipeline: PipelineLimits::default(),
+            slowlog: SlowlogPref::default(),
+            metrics: MetricsPref::default(),
+            throttle: ThrottlePref::default(),
+            selfcheck: SelfcheckPref::default(),
+            log: LogPref::default(),
         }
     }
     /// Returns `false` if `noart` is enabled. Otherwise it returns `true`
     pub const fn is_artful(&self) -> bool {
         !self.noart
     }
+    /// Turn off everything that would read or write the data directory, for `--sandbox`
+    pub fn into_sandbox(mut self) -> Self {
+        self.bgsave = BGSave::Disabled;
+        self.snapshot = SnapshotConfig::Disabled;
+        self.lazyload = LazyLoad::Disabled;
+        // nothing would ever bring the dirty bytes down
+        self.backpressure = None;
+        self
+    }
 }
 
-use clap::{load_yaml, App};
+use clap::{load_yaml, App, ArgMatches};
 
 /// The type of configuration:
 /// - We either used a custom configuration file given to us by the user (`Custom`) OR
@@ -400,11 +1332,14 @@ pub enum ConfigType<T, U> {
 /// - The config file has an invalid value, which is syntatically correct
 /// but logically incorrect (`CfgError`)
 /// - The command line arguments have an invalid value/invalid values (`CliArgError`)
+/// - The config file has unknown keys or values of the wrong type, or so do the environment
+/// overrides (`SchemaError`)
 pub enum ConfigError {
     OSError(Box<dyn Error>),
     SyntaxError(Box<dyn Error>),
     CfgError(&'static str),
     CliArgErr(&'static str),
+    SchemaError(String),
 }
 
 impl fmt::Display for ConfigError {
@@ -414,18 +1349,172 @@ impl fmt::Display for ConfigError {
             ConfigError::SyntaxError(e) => writeln!(f, "syntax error in configuration file: {}", e),
             ConfigError::CfgError(e) => write!(f, "Configuration error: {}", e),
             ConfigError::CliArgErr(e) => write!(f, "Argument error: {}", e),
+            ConfigError::SchemaError(e) => write!(f, "Configuration error: {}", e),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// How the server was asked to start
+pub enum StartMode {
+    /// Run the preflight checks and then start the server
+    Normal,
+    /// Only run the preflight checks and exit (`--check-config`)
+    CheckOnly,
+    /// Run the preflight checks, import a file into a table and exit (`skyd import`)
+    Import,
+    /// Run the preflight checks, migrate the data directory to another storage profile and
+    /// exit (`skyd migrate-data`)
+    Migrate,
+    /// Run the preflight checks, export or apply a schema file and exit (`skyd schema`)
+    Schema,
+    /// Print the validated configuration file in its canonical form and exit (`--dump-config`)
+    DumpConfig,
+}
+
+#[derive(Debug, PartialEq)]
+/// Options that only affect how the server starts up. These are accepted along with a
+/// configuration file
+pub struct StartupOpts {
+    /// How the server was asked to start
+    pub mode: StartMode,
+    /// The snapshot directory to restore before starting up (`--restore-from`)
+    pub restore_from: Option<String>,
+    /// Whether the restore can overwrite existing data (`--force`)
+    pub force: bool,
+    /// What to import, if the server was started with `skyd import`
+    pub import: Option<ImportOpts>,
+    /// The profile to migrate to, if the server was started with `skyd migrate-data`
+    pub migrate: Option<MigrateOpts>,
+    /// What to do with a schema file, if the server was started with `skyd schema`
+    pub schema: Option<SchemaOpts>,
+    /// Whether all the data is kept in memory, without touching the data directory
+    /// (`--sandbox`)
+    pub sandbox: bool,
+    /// The configuration file that was passed with `--withconfig`, if any
+    pub config_file: Option<String>,
+}
+
+impl StartupOpts {
+    /// Returns the error for the options that can't be used with `--sandbox`, since they're
+    /// all about the data directory. `restorefile` is set if `--restore` was passed
+    fn sandbox_conflict(&self, restorefile: bool) -> Option<ConfigError> {
+        if !self.sandbox {
+            return None;
         }
+        let err = if self.restore_from.is_some() {
+            "`--sandbox` can't be used with `--restore-from`"
+        } else if restorefile {
+            "`--sandbox` can't be used with `--restore`"
+        } else {
+            match self.mode {
+                StartMode::Normal | StartMode::DumpConfig => return None,
+                StartMode::CheckOnly => "`--sandbox` can't be used with `--check-config`",
+                StartMode::Import => "`--sandbox` can't be used with `skyd import`",
+                StartMode::Migrate => "`--sandbox` can't be used with `skyd migrate-data`",
+                StartMode::Schema => "`--sandbox` can't be used with `skyd schema`",
+            }
+        };
+        Some(ConfigError::CliArgErr(err))
     }
 }
 
-/// This function returns a  `ConfigType<ParsedConfig>`
+/// This function returns a  `ConfigType<ParsedConfig>` along with the [`StartupOpts`]
 ///
 /// This parses a configuration file if it is supplied as a command line argument
 /// or it returns the default configuration. **If** the configuration file
 /// contains an error, then this returns it as an `Err` variant
-pub fn get_config_file_or_return_cfg() -> Result<ConfigType<ParsedConfig, String>, ConfigError> {
+pub fn get_config_file_or_return_cfg() -> (
+    StartupOpts,
+    Result<ConfigType<ParsedConfig, String>, ConfigError>,
+) {
     let cfg_layout = load_yaml!("../cli.yml");
     let matches = App::from_yaml(cfg_layout).get_matches();
+    let (import, import_err) = match matches
+        .subcommand_matches("import")
+        .map(ImportOpts::from_matches)
+    {
+        Some(Ok(import)) => (Some(import), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let (migrate, migrate_err) = match matches
+        .subcommand_matches("migrate-data")
+        .map(MigrateOpts::from_matches)
+    {
+        Some(Ok(migrate)) => (Some(migrate), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let (schema, schema_err) = match matches
+        .subcommand_matches("schema")
+        .map(SchemaOpts::from_matches)
+    {
+        Some(Ok(schema)) => (Some(schema), None),
+        Some(Err(e)) => (None, Some(e)),
+        None => (None, None),
+    };
+    let mode = if matches.is_present("checkconfig") {
+        StartMode::CheckOnly
+    } else if matches.is_present("dumpconfig") {
+        StartMode::DumpConfig
+    } else if import.is_some() {
+        StartMode::Import
+    } else if migrate.is_some() {
+        StartMode::Migrate
+    } else if schema.is_some() {
+        StartMode::Schema
+    } else {
+        StartMode::Normal
+    };
+    let opts = StartupOpts {
+        mode,
+        restore_from: matches.value_of("restorefrom").map(|v| v.to_string()),
+        force: matches.is_present("force"),
+        import,
+        migrate,
+        schema,
+        sandbox: matches.is_present("sandbox"),
+        config_file: matches.value_of("config").map(|v| v.to_string()),
+    };
+    let conflict = opts.sandbox_conflict(matches.is_present("restore"));
+    let nothing_to_dump = if opts.mode == StartMode::DumpConfig && opts.config_file.is_none() {
+        Some(ConfigError::CliArgErr(
+            "`--dump-config` needs a configuration file (`--withconfig`)",
+        ))
+    } else {
+        None
+    };
+    let cfg = match import_err
+        .or(migrate_err)
+        .or(schema_err)
+        .or(conflict)
+        .or(nothing_to_dump)
+    {
+        Some(e) => Err(e),
+        None => parse_config_args(&matches),
+    };
+    (opts, cfg)
+}
+
+/// Returns the configuration file at `location` with the environment overrides layered over
+/// it, in its canonical form (for `--dump-config`)
+pub fn dump_config(location: &str) -> Result<String, ConfigError> {
+    let file = fs::read_to_string(location).map_err(|e| ConfigError::OSError(e.into()))?;
+    schema::load(&file, &schema::env_vars()).map(|layered| schema::canonical(&layered))
+}
+
+/// Returns true if `rate` can be used as the false positive rate of a bloom filter (a rate of
+/// 0 would need an infinitely large filter and a rate of 1 is no filter at all)
+fn is_valid_fp_rate(rate: f64) -> bool {
+    rate > 0.0 && rate < 1.0
+}
+
+/// Get the configuration from the command line arguments or from the configuration file that
+/// was passed in them
+fn parse_config_args(
+    matches: &ArgMatches,
+) -> Result<ConfigType<ParsedConfig, String>, ConfigError> {
     let restorefile = matches.value_of("restore").map(|v| v.to_string());
     // Check flags
     let sslonly = matches.is_present("sslonly");
@@ -439,29 +1528,88 @@ pub fn get_config_file_or_return_cfg() -> Result<ConfigType<ParsedConfig, String
     let custom_ssl_port = sslport.is_some();
     let snapevery = matches.value_of("snapevery");
     let snapkeep = matches.value_of("snapkeep");
+    let snapmaxage = matches.value_of("snapmaxage");
+    let snapmaxchain = matches.value_of("snapmaxchain");
+    let snapprefix = matches.value_of("snapprefix");
     let saveduration = matches.value_of("saveduration");
     let sslkey = matches.value_of("sslkey");
     let sslchain = matches.value_of("sslchain");
     let maxcon = matches.value_of("maxcon");
     let passfile = matches.value_of("tlspassin");
+    let dirtymark = matches.value_of("dirtymark");
+    let dirtypolicy = matches.value_of("dirtypolicy");
+    let compression = matches.value_of("compression");
+    let compressthreshold = matches.value_of("compressthreshold");
+    let syncbuffer = matches.value_of("syncbuffer");
+    let readpolicy = matches.value_of("readpolicy");
+    let lazyload = matches.is_present("lazyload");
+    let maxvaluesize = matches.value_of("maxvaluesize");
+    let datadir = matches.value_of("datadir");
+    let maxpause = matches.value_of("maxpause");
+    let maxnamelen = matches.value_of("maxnamelen");
+    let snaptoken = matches.value_of("snaptoken");
+    let loadthreads = matches.value_of("loadthreads");
+    let maxorderedkeys = matches.value_of("maxorderedkeys");
+    let disabledactions = matches.values_of("disableaction");
+    let healthport = matches.value_of("healthport");
+    let bloomfprate = matches.value_of("bloomfprate");
+    let memoryceiling = matches.value_of("memoryceiling");
+    let paranoid = matches.value_of("paranoid");
+    let uring = matches.is_present("uring");
+    let orphans = matches.value_of("orphans");
+    let allowmissing = matches.is_present("allowmissing");
+    let trackreads = matches.is_present("trackreads");
+    let caseinsensitive = matches.is_present("caseinsensitive");
     let cli_has_overrideable_args = host.is_some()
         || port.is_some()
         || noart
         || nosave
         || snapevery.is_some()
         || snapkeep.is_some()
+        || snapmaxage.is_some()
+        || snapmaxchain.is_some()
+        || snapprefix.is_some()
         || saveduration.is_some()
         || sslchain.is_some()
         || sslkey.is_some()
         || maxcon.is_some()
         || custom_ssl_port
         || passfile.is_some()
+        || dirtymark.is_some()
+        || dirtypolicy.is_some()
+        || compression.is_some()
+        || compressthreshold.is_some()
+        || syncbuffer.is_some()
+        || readpolicy.is_some()
+        || lazyload
+        || maxvaluesize.is_some()
+        || datadir.is_some()
+        || maxpause.is_some()
+        || maxnamelen.is_some()
+        || snaptoken.is_some()
+        || loadthreads.is_some()
+        || maxorderedkeys.is_some()
+        || disabledactions.is_some()
+        || healthport.is_some()
+        || bloomfprate.is_some()
+        || memoryceiling.is_some()
+        || paranoid.is_some()
+        || uring
+        || orphans.is_some()
+        || allowmissing
+        || trackreads
+        || caseinsensitive
         || sslonly;
     if filename.is_some() && cli_has_overrideable_args {
         return Err(ConfigError::CfgError(
             "Either use command line arguments or use a configuration file",
         ));
     }
+    if filename.is_none() && schema::has_overrides(&schema::env_vars()) {
+        return Err(ConfigError::CfgError(
+            "The `SKY_<SECTION>_<KEY>` environment variables can only override a configuration file",
+        ));
+    }
     // At this point we're sure that either a configuration file or command-line arguments
     // were supplied
     if cli_has_overrideable_args {
@@ -553,6 +1701,30 @@ pub fn get_config_file_or_return_cfg() -> Result<ConfigType<ParsedConfig, String
             },
             None => None,
         };
+        let snapmaxage: Option<u64> = match snapmaxage {
+            Some(maxage) => match maxage.parse() {
+                Ok(maxage) if maxage != 0 => Some(maxage),
+                _ => return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--snapmaxage`. Expected a non-zero unsigned 64-bit integer",
+                )),
+            },
+            None => None,
+        };
+        let snapmaxchain: Option<usize> =
+            match snapmaxchain {
+                Some(maxchain) => match maxchain.parse() {
+                    Ok(maxchain) => Some(maxchain),
+                    Err(_) => return Err(ConfigError::CliArgErr(
+                        "Invalid value for `--snapmaxchain`. Expected an unsigned 64-bit integer",
+                    )),
+                },
+                None => None,
+            };
+        if !snapprefix.map_or(true, snapshot::is_valid_prefix) {
+            return Err(ConfigError::CliArgErr(
+                "Invalid value for `--snapprefix`. The prefix can only have letters, digits, `_` and `-`",
+            ));
+        }
         let failsafe = if let Ok(failsafe) = option_unwrap_or!(
             matches
                 .value_of("stop-write-on-fail")
@@ -566,9 +1738,12 @@ pub fn get_config_file_or_return_cfg() -> Result<ConfigType<ParsedConfig, String
             ));
         };
         let snapcfg = match (snapevery, snapkeep) {
-            (Some(every), Some(keep)) => {
-                SnapshotConfig::Enabled(SnapshotPref::new(every, keep, failsafe))
-            }
+            (Some(every), Some(keep)) => SnapshotConfig::Enabled(
+                SnapshotPref::new(every, keep, failsafe)
+                    .with_maxage(snapmaxage)
+                    .with_maxchain(option_unwrap_or!(snapmaxchain, DEF_MAX_CHAIN))
+                    .with_prefix(snapprefix.unwrap_or_default().to_owned()),
+            ),
             (Some(_), None) => {
                 return Err(ConfigError::CliArgErr(
                     "No value supplied for `--snapkeep`. When you supply `--snapevery`, you also need to specify `--snapkeep`"
@@ -579,6 +1754,21 @@ pub fn get_config_file_or_return_cfg() -> Result<ConfigType<ParsedConfig, String
                     "No value supplied for `--snapevery`. When you supply `--snapkeep`, you also need to specify `--snapevery`"
                 ));
             }
+            (None, None) if snapmaxage.is_some() => {
+                return Err(ConfigError::CliArgErr(
+                    "No value supplied for `--snapevery` and `--snapkeep`. When you supply `--snapmaxage`, you also need to specify them",
+                ));
+            }
+            (None, None) if snapmaxchain.is_some() => {
+                return Err(ConfigError::CliArgErr(
+                    "No value supplied for `--snapevery` and `--snapkeep`. When you supply `--snapmaxchain`, you also need to specify them",
+                ));
+            }
+            (None, None) if snapprefix.is_some() => {
+                return Err(ConfigError::CliArgErr(
+                    "No value supplied for `--snapevery` and `--snapkeep`. When you supply `--snapprefix`, you also need to specify them",
+                ));
+            }
             (None, None) => SnapshotConfig::Disabled,
         };
         let portcfg = match (
@@ -617,20 +1807,231 @@ pub fn get_config_file_or_return_cfg() -> Result<ConfigType<ParsedConfig, String
                 ));
             }
         };
-        let cfg = ParsedConfig::new(noart, bgsave, snapcfg, portcfg, maxcon);
-        return Ok(ConfigType::Custom(cfg, restorefile));
-    }
-    if let Some(filename) = filename {
-        match ParsedConfig::new_from_file(filename.to_owned()) {
-            Ok(cfg) => {
-                if cfg.bgsave.is_disabled() {
-                    log::warn!("BGSAVE is disabled: If this system crashes unexpectedly, it may lead to the loss of data");
+        let policy = match dirtypolicy {
+            Some("delay") | None => BackpressurePolicy::Delay,
+            Some("reject") => BackpressurePolicy::Reject,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--dirtypolicy`. Expected either `delay` or `reject`",
+                ))
+            }
+        };
+        let backpressure = match dirtymark.map(|mark| mark.parse::<usize>()) {
+            Some(Ok(mark)) if mark != 0 => Some(BackpressurePref::new(mark, policy)),
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--dirtymark`. Expected a positive integer",
+                ))
+            }
+            None => {
+                if dirtypolicy.is_some() {
+                    log::warn!("Ignoring value for `--dirtypolicy` as `--dirtymark` was not set");
                 }
-                if let SnapshotConfig::Enabled(e) = &cfg.snapshot {
-                    if e.every == 0 {
-                        return Err(ConfigError::CfgError(
-                            "The snapshot duration has to be greater than 0!",
-                        ));
+                None
+            }
+        };
+        let algorithm = match compression {
+            Some("lz4") | None => CompressionAlgorithm::Lz4,
+            Some("none") => CompressionAlgorithm::None,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--compression`. Expected either `lz4` or `none`",
+                ))
+            }
+        };
+        let threshold = match compressthreshold.map(|threshold| threshold.parse::<usize>()) {
+            Some(Ok(threshold)) => threshold,
+            Some(Err(_)) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--compressthreshold`. Expected an unsigned integer",
+                ))
+            }
+            None => DEFAULT_COMPRESSION_THRESHOLD,
+        };
+        let compression = CompressionPref::new(algorithm, threshold);
+        let syncbuffer = match syncbuffer.map(|buffer| buffer.parse::<usize>()) {
+            Some(Ok(buffer)) => buffer,
+            Some(Err(_)) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--syncbuffer`. Expected an unsigned integer",
+                ))
+            }
+            None => DEFAULT_SYNC_BUFFER,
+        };
+        let readpolicy = match readpolicy {
+            Some("stale-ok") | None => ReadPolicy::StaleOk,
+            Some("fail") => ReadPolicy::Fail,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--readpolicy`. Expected either `stale-ok` or `fail`",
+                ))
+            }
+        };
+        let lazyload = if lazyload {
+            LazyLoad::Enabled {
+                prewarm: Vec::new(),
+            }
+        } else {
+            LazyLoad::Disabled
+        };
+        let maxvaluesize = match maxvaluesize.map(|size| size.parse::<u64>()) {
+            Some(Ok(size)) if size != 0 => Some(size),
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--maxvaluesize`. Expected a positive integer",
+                ))
+            }
+            None => None,
+        };
+        let maxpause = match maxpause.map(|secs| secs.parse::<u64>()) {
+            Some(Ok(secs)) if secs != 0 => Some(secs),
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--maxpause`. Expected a positive integer",
+                ))
+            }
+            None => None,
+        };
+        let maxnamelen = match maxnamelen.map(|len| len.parse::<usize>()) {
+            Some(Ok(len)) if (1..=DEFAULT_MAX_NAME_LEN).contains(&len) => Some(len),
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--maxnamelen`. Expected an integer between 1 and 64",
+                ))
+            }
+            None => None,
+        };
+        if snaptoken == Some("") {
+            return Err(ConfigError::CliArgErr(
+                "Invalid value for `--snaptoken`. The token can't be empty",
+            ));
+        }
+        let loadthreads = match loadthreads.map(|threads| threads.parse::<usize>()) {
+            Some(Ok(threads)) if threads != 0 => Some(threads),
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--loadthreads`. Expected an integer greater than 0",
+                ))
+            }
+            None => None,
+        };
+        let maxorderedkeys = match maxorderedkeys.map(|keys| keys.parse::<usize>()) {
+            Some(Ok(keys)) => Some(keys),
+            Some(Err(_)) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--maxorderedkeys`. Expected an unsigned integer",
+                ))
+            }
+            None => None,
+        };
+        let healthport = match healthport.map(|port| port.parse::<u16>()) {
+            Some(Ok(port)) => Some(port),
+            Some(Err(_)) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--healthport`. Expected a port number",
+                ))
+            }
+            None => None,
+        };
+        let bloomfprate = match bloomfprate.map(|rate| rate.parse::<f64>()) {
+            Some(Ok(rate)) if self::is_valid_fp_rate(rate) => Some(rate),
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--bloomfprate`. Expected a number between 0 and 1",
+                ))
+            }
+            None => None,
+        };
+        let memoryceiling = match memoryceiling.map(|bytes| bytes.parse::<usize>()) {
+            Some(Ok(bytes)) if bytes != 0 => Some(bytes),
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--memoryceiling`. Expected an integer greater than 0",
+                ))
+            }
+            None => None,
+        };
+        let paranoid = match paranoid {
+            Some("off") | None => ParanoidMode::Off,
+            Some("report") => ParanoidMode::Report,
+            Some("reject") => ParanoidMode::Reject,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--paranoid`. Expected `off`, `report` or `reject`",
+                ))
+            }
+        };
+        let orphans = match orphans {
+            Some("fail") | None => OrphanPolicy::Fail,
+            Some("adopt") => OrphanPolicy::Adopt,
+            Some("quarantine") => OrphanPolicy::Quarantine,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--orphans`. Expected `fail`, `adopt` or `quarantine`",
+                ))
+            }
+        };
+        let cfg = ParsedConfig::new(
+            noart,
+            bgsave,
+            snapcfg,
+            portcfg,
+            maxcon,
+            backpressure,
+            compression,
+            syncbuffer,
+            readpolicy,
+            lazyload,
+            maxvaluesize,
+            datadir.map(str::to_owned),
+            maxpause,
+            maxnamelen,
+            snaptoken.map(str::to_owned),
+            loadthreads,
+            maxorderedkeys,
+            disabledactions
+                .map(|actions| actions.map(str::to_owned).collect())
+                .unwrap_or_default(),
+            healthport,
+            bloomfprate,
+            memoryceiling,
+            paranoid,
+            uring,
+            orphans,
+            allowmissing,
+            trackreads,
+            caseinsensitive,
+            TxnLimits::default(),
+            PipelineLimits::default(),
+            SlowlogPref::default(),
+            MetricsPref::default(),
+            ThrottlePref::default(),
+            SelfcheckPref::default(),
+            LogPref::default(),
+        );
+        return Ok(ConfigType::Custom(cfg, restorefile));
+    }
+    if let Some(filename) = filename {
+        match ParsedConfig::new_from_file(filename.to_owned()) {
+            Ok(cfg) => {
+                if cfg.bgsave.is_disabled() {
+                    log::warn!("BGSAVE is disabled: If this system crashes unexpectedly, it may lead to the loss of data");
+                }
+                if let SnapshotConfig::Enabled(e) = &cfg.snapshot {
+                    if e.every == 0 {
+                        return Err(ConfigError::CfgError(
+                            "The snapshot duration has to be greater than 0!",
+                        ));
+                    }
+                    if e.maxage == Some(0) {
+                        return Err(ConfigError::CfgError(
+                            "The maximum snapshot age has to be greater than 0!",
+                        ));
+                    }
+                    if !snapshot::is_valid_prefix(&e.prefix) {
+                        return Err(ConfigError::CfgError(
+                            "The snapshot prefix can only have letters, digits, `_` and `-`!",
+                        ));
                     }
                 }
                 if let BGSave::Enabled(dur) = &cfg.bgsave {
@@ -640,6 +2041,85 @@ pub fn get_config_file_or_return_cfg() -> Result<ConfigType<ParsedConfig, String
                         ));
                     }
                 }
+                if let Some(bp) = &cfg.backpressure {
+                    if bp.dirtymark == 0 {
+                        return Err(ConfigError::CfgError(
+                            "The dirty bytes mark has to be greater than 0!",
+                        ));
+                    }
+                }
+                let txn = &cfg.transactions;
+                if txn.maxqueued == 0
+                    || txn.maxbytes == 0
+                    || txn.maxtotalqueued == 0
+                    || txn.maxtotalbytes == 0
+                {
+                    return Err(ConfigError::CfgError(
+                        "The transaction limits have to be greater than 0!",
+                    ));
+                }
+                if cfg.pipeline.maxinflight == 0 || cfg.pipeline.maxinflightbytes == 0 {
+                    return Err(ConfigError::CfgError(
+                        "The in-flight limits of pipelines have to be greater than 0!",
+                    ));
+                }
+                if cfg.slowlog.size == 0 {
+                    return Err(ConfigError::CfgError(
+                        "The slow query log has to keep at least one query!",
+                    ));
+                }
+                if cfg.throttle.snapshot == Some(0) || cfg.throttle.flush == Some(0) {
+                    return Err(ConfigError::CfgError(
+                        "The throttle limits have to be greater than 0!",
+                    ));
+                }
+                if cfg.selfcheck.every == Some(0) {
+                    return Err(ConfigError::CfgError(
+                        "The tables have to be checked at least an hour apart!",
+                    ));
+                }
+                cfg.log.check().map_err(ConfigError::CfgError)?;
+                if let Some(buckets) = &cfg.metrics.buckets {
+                    crate::registry::check_buckets(buckets).map_err(ConfigError::CfgError)?;
+                }
+                if cfg.maxvaluesize == Some(0) {
+                    return Err(ConfigError::CfgError(
+                        "The maximum value size has to be greater than 0!",
+                    ));
+                }
+                if cfg.datadir.as_deref() == Some("") {
+                    return Err(ConfigError::CfgError("The data directory can't be empty!"));
+                }
+                if cfg.maxpause == Some(0) {
+                    return Err(ConfigError::CfgError(
+                        "The maximum pause has to be greater than 0!",
+                    ));
+                }
+                if let Some(len) = cfg.maxnamelen {
+                    if !(1..=DEFAULT_MAX_NAME_LEN).contains(&len) {
+                        return Err(ConfigError::CfgError(
+                            "The maximum name length has to be between 1 and 64!",
+                        ));
+                    }
+                }
+                if cfg.snaptoken.as_deref() == Some("") {
+                    return Err(ConfigError::CfgError("The snapshot token can't be empty!"));
+                }
+                if cfg.loadthreads == Some(0) {
+                    return Err(ConfigError::CfgError(
+                        "The number of load threads has to be greater than 0!",
+                    ));
+                }
+                if cfg.memoryceiling == Some(0) {
+                    return Err(ConfigError::CfgError(
+                        "The memory ceiling has to be greater than 0!",
+                    ));
+                }
+                if let Some(false) = cfg.bloomfprate.map(self::is_valid_fp_rate) {
+                    return Err(ConfigError::CfgError(
+                        "The false positive rate of the bloom filters has to be between 0 and 1!",
+                    ));
+                }
                 Ok(ConfigType::Custom(cfg, restorefile))
             }
             Err(e) => Err(e),
@@ -722,7 +2202,36 @@ mod tests {
                 bgsave: BGSave::default(),
                 snapshot: SnapshotConfig::default(),
                 ports: PortConfig::default(),
-                maxcon: MAXIMUM_CONNECTION_LIMIT
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: None,
+                compression: CompressionPref::default(),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
             }
         );
     }
@@ -741,7 +2250,36 @@ mod tests {
                     IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0x1)),
                     DEFAULT_PORT
                 ),
-                maxcon: MAXIMUM_CONNECTION_LIMIT
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: None,
+                compression: CompressionPref::default(),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
             }
         );
     }
@@ -755,7 +2293,12 @@ mod tests {
             ParsedConfig::new(
                 false,
                 BGSave::default(),
-                SnapshotConfig::Enabled(SnapshotPref::new(3600, 4, true)),
+                SnapshotConfig::Enabled(
+                    SnapshotPref::new(3600, 4, true)
+                        .with_maxage(Some(604800))
+                        .with_maxchain(24)
+                        .with_prefix("primary-".to_owned())
+                ),
                 PortConfig::new_secure_only(
                     DEFAULT_IPV4,
                     SslOpts::new(
@@ -765,7 +2308,44 @@ mod tests {
                         Some("/path/to/cert/passphrase.txt".to_owned())
                     )
                 ),
-                MAXIMUM_CONNECTION_LIMIT
+                MAXIMUM_CONNECTION_LIMIT,
+                Some(BackpressurePref::new(134217728, BackpressurePolicy::Delay)),
+                CompressionPref::new(CompressionAlgorithm::Lz4, 4096),
+                4096,
+                ReadPolicy::StaleOk,
+                LazyLoad::Disabled,
+                Some(67108864),
+                Some("/var/lib/skytable".to_owned()),
+                Some(1800),
+                Some(48),
+                Some("my-shared-secret".to_owned()),
+                Some(4),
+                Some(250000),
+                vec!["flushdb".to_owned()],
+                Some(2005),
+                Some(0.001),
+                Some(2147483648),
+                ParanoidMode::Report,
+                true,
+                OrphanPolicy::Quarantine,
+                true,
+                true,
+                true,
+                TxnLimits::new(5000, 8388608, 500000, 134217728),
+                PipelineLimits::new(256, 131072, 100000),
+                SlowlogPref::new(5000, 256),
+                MetricsPref::new(Some(vec![500, 1000, 5000, 10000, 50000, 100000, 1000000])),
+                ThrottlePref::new(Some(104857600), Some(524288000)),
+                SelfcheckPref::new(Some(6), 10),
+                LogPref {
+                    targets: Some(vec![LogTarget::Stderr, LogTarget::File]),
+                    level: Some("info".to_owned()),
+                    levels: [None, Some("info,skyd::dbnet=debug".to_owned()), None, None],
+                    file: Some("/var/log/skytable/skyd.log".to_owned()),
+                    maxsize: Some(67108864),
+                    every: Some(86400),
+                    keep: 7,
+                }
             )
         );
     }
@@ -788,7 +2368,36 @@ mod tests {
                 bgsave: BGSave::new(true, 600),
                 snapshot: SnapshotConfig::default(),
                 ports: PortConfig::default(),
-                maxcon: MAXIMUM_CONNECTION_LIMIT
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: None,
+                compression: CompressionPref::default(),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
             }
         );
     }
@@ -808,7 +2417,36 @@ mod tests {
                 bgsave: BGSave::default(),
                 snapshot: SnapshotConfig::default(),
                 ports: PortConfig::default(),
-                maxcon: MAXIMUM_CONNECTION_LIMIT
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: None,
+                compression: CompressionPref::default(),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
             }
         )
     }
@@ -828,7 +2466,36 @@ mod tests {
                 bgsave: BGSave::new(true, 600),
                 snapshot: SnapshotConfig::default(),
                 ports: PortConfig::default(),
-                maxcon: MAXIMUM_CONNECTION_LIMIT
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: None,
+                compression: CompressionPref::default(),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
             }
         )
     }
@@ -844,8 +2511,700 @@ mod tests {
                 bgsave: BGSave::default(),
                 noart: false,
                 ports: PortConfig::default(),
-                maxcon: MAXIMUM_CONNECTION_LIMIT
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: None,
+                compression: CompressionPref::default(),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_file_backpressure() {
+        let file = get_toml_from_examples_dir("backpressure.toml".to_owned()).unwrap();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(
+            cfg,
+            ParsedConfig {
+                noart: false,
+                bgsave: BGSave::default(),
+                snapshot: SnapshotConfig::default(),
+                ports: PortConfig::default(),
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: Some(BackpressurePref::new(1024, BackpressurePolicy::Reject)),
+                compression: CompressionPref::default(),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_file_backpressure_bad_policy() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [backpressure]
+        dirtymark = 1024
+        policy = "drop"
+    "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file);
+        assert!(cfg.is_err());
+    }
+
+    #[test]
+    fn test_config_file_compression() {
+        let file = get_toml_from_examples_dir("compression.toml".to_owned()).unwrap();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(
+            cfg,
+            ParsedConfig {
+                noart: false,
+                bgsave: BGSave::default(),
+                snapshot: SnapshotConfig::default(),
+                ports: PortConfig::default(),
+                maxcon: MAXIMUM_CONNECTION_LIMIT,
+                backpressure: None,
+                compression: CompressionPref::new(CompressionAlgorithm::None, 65536),
+                syncbuffer: DEFAULT_SYNC_BUFFER,
+                readpolicy: ReadPolicy::StaleOk,
+                lazyload: LazyLoad::Disabled,
+                maxvaluesize: None,
+                datadir: None,
+                maxpause: None,
+                maxnamelen: None,
+                snaptoken: None,
+                loadthreads: None,
+                maxorderedkeys: None,
+                disabledactions: Vec::new(),
+                healthport: None,
+                bloomfprate: None,
+                memoryceiling: None,
+                paranoid: ParanoidMode::Off,
+                uring: false,
+                orphans: OrphanPolicy::Fail,
+                allowmissing: false,
+                trackreads: false,
+                caseinsensitive: false,
+                transactions: TxnLimits::default(),
+                pipeline: PipelineLimits::default(),
+                slowlog: SlowlogPref::default(),
+                metrics: MetricsPref::default(),
+                throttle: ThrottlePref::default(),
+                selfcheck: SelfcheckPref::default(),
+                log: LogPref::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_config_toml_readpolicy() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        readpolicy = "fail"
+    "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.readpolicy, ReadPolicy::Fail);
+        assert_eq!(ParsedConfig::default().readpolicy, ReadPolicy::StaleOk);
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        readpolicy = "stale"
+    "#
+        .to_owned();
+        assert!(ParsedConfig::new_from_toml_str(file).is_err());
+    }
+
+    #[test]
+    fn test_config_toml_maxvaluesize() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        maxvaluesize = 1048576
+    "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.maxvaluesize, Some(1048576));
+        assert_eq!(ParsedConfig::default().maxvaluesize, None);
+    }
+
+    #[test]
+    fn test_config_toml_datadir() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        datadir = "/var/lib/skytable"
+    "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.datadir.as_deref(), Some("/var/lib/skytable"));
+        assert_eq!(ParsedConfig::default().datadir, None);
+    }
+
+    #[test]
+    fn test_config_toml_maxpause() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        maxpause = 600
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.maxpause, Some(600));
+        assert_eq!(ParsedConfig::default().maxpause, None);
+    }
+
+    #[test]
+    fn test_config_toml_maxnamelen() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        maxnamelen = 32
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.maxnamelen, Some(32));
+        assert_eq!(ParsedConfig::default().maxnamelen, None);
+    }
+
+    #[test]
+    fn test_config_toml_transactions() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [transactions]
+        maxqueued = 100
+        maxtotalbytes = 1048576
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(
+            cfg.transactions,
+            TxnLimits::new(
+                100,
+                DEFAULT_MAX_TXN_BYTES,
+                DEFAULT_MAX_TOTAL_QUEUED,
+                1048576
+            )
+        );
+        assert_eq!(ParsedConfig::default().transactions, TxnLimits::default());
+    }
+
+    #[test]
+    fn test_config_toml_pipeline() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [pipeline]
+        maxinflight = 16
+        maxdepth = 10000
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(
+            cfg.pipeline,
+            PipelineLimits::new(16, DEFAULT_MAX_INFLIGHT_BYTES, 10000)
+        );
+        assert_eq!(ParsedConfig::default().pipeline, PipelineLimits::default());
+    }
+
+    #[test]
+    fn test_config_toml_slowlog() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [slowlog]
+        threshold = 0
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.slowlog, SlowlogPref::new(0, DEFAULT_SLOWLOG_SIZE));
+        assert_eq!(ParsedConfig::default().slowlog, SlowlogPref::default());
+    }
+
+    #[test]
+    fn test_config_toml_metrics() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [metrics]
+        buckets = [1000, 10000]
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.metrics, MetricsPref::new(Some(vec![1000, 10000])));
+        assert_eq!(ParsedConfig::default().metrics, MetricsPref::default());
+    }
+
+    #[test]
+    fn test_config_toml_throttle() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [throttle]
+        snapshot = 1048576
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.throttle, ThrottlePref::new(Some(1048576), None));
+        assert_eq!(ParsedConfig::default().throttle, ThrottlePref::default());
+    }
+
+    #[test]
+    fn test_config_toml_selfcheck() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [selfcheck]
+        every = 12
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.selfcheck, SelfcheckPref::new(Some(12), 0));
+        assert_eq!(ParsedConfig::default().selfcheck, SelfcheckPref::default());
+    }
+
+    #[test]
+    fn test_config_toml_log() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [log]
+        targets = ["stderr", "file"]
+        level = "warn"
+        filelevel = "skyd::dbnet=debug,info"
+        file = "skyd.log"
+        maxsize = 1048576
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.log.targets(), &[LogTarget::Stderr, LogTarget::File]);
+        assert_eq!(cfg.log.level.as_deref(), Some("warn"));
+        assert_eq!(cfg.log.level_of(LogTarget::Stderr), None);
+        assert_eq!(
+            cfg.log.level_of(LogTarget::File),
+            Some("skyd::dbnet=debug,info")
+        );
+        assert_eq!(cfg.log.maxsize, Some(1048576));
+        assert_eq!(cfg.log.keep, DEFAULT_LOG_KEEP);
+        assert!(cfg.log.check().is_ok());
+        assert_eq!(ParsedConfig::default().log.targets(), &[LogTarget::Stderr]);
+        // a file target with no file to write to
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [log]
+        targets = ["file"]
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert!(cfg.log.check().is_err());
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [log]
+        targets = ["stdout"]
+        "#
+        .to_owned();
+        assert!(ParsedConfig::new_from_toml_str(file).is_err());
+    }
+
+    #[test]
+    fn test_config_toml_memoryceiling() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        memoryceiling = 1073741824
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.memoryceiling, Some(1073741824));
+        assert_eq!(ParsedConfig::default().memoryceiling, None);
+    }
+
+    #[test]
+    fn test_config_toml_paranoid() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        paranoid = "reject"
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.paranoid, ParanoidMode::Reject);
+        assert_eq!(ParsedConfig::default().paranoid, ParanoidMode::Off);
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        paranoid = "yes"
+        "#
+        .to_owned();
+        assert!(ParsedConfig::new_from_toml_str(file).is_err());
+    }
+
+    #[test]
+    fn test_config_toml_orphans() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        orphans = "quarantine"
+        allowmissing = true
+        trackreads = true
+        caseinsensitive = true
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.orphans, OrphanPolicy::Quarantine);
+        assert!(cfg.allowmissing);
+        assert!(cfg.trackreads);
+        assert!(cfg.caseinsensitive);
+        assert_eq!(ParsedConfig::default().orphans, OrphanPolicy::Fail);
+        assert!(!ParsedConfig::default().allowmissing);
+        assert!(!ParsedConfig::default().trackreads);
+        assert!(!ParsedConfig::default().caseinsensitive);
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        orphans = "ignore"
+        "#
+        .to_owned();
+        assert!(ParsedConfig::new_from_toml_str(file).is_err());
+    }
+
+    #[test]
+    fn test_config_toml_bloomfprate() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        bloomfprate = 0.05
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.bloomfprate, Some(0.05));
+        assert_eq!(ParsedConfig::default().bloomfprate, None);
+        assert!(!is_valid_fp_rate(0.0));
+        assert!(!is_valid_fp_rate(1.0));
+        assert!(!is_valid_fp_rate(f64::NAN));
+        assert!(is_valid_fp_rate(0.5));
+    }
+
+    #[test]
+    fn test_config_toml_healthport() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        healthport = 2010
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.healthport, Some(2010));
+        assert_eq!(ParsedConfig::default().healthport, None);
+    }
+
+    #[test]
+    fn test_config_toml_disabledactions() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        disabledactions = ["flushdb", "SYS"]
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(
+            cfg.disabledactions,
+            vec!["flushdb".to_owned(), "SYS".to_owned()]
+        );
+        assert!(ParsedConfig::default().disabledactions.is_empty());
+    }
+
+    #[test]
+    fn test_config_toml_maxorderedkeys() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        maxorderedkeys = 100
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.maxorderedkeys, Some(100));
+        assert_eq!(ParsedConfig::default().maxorderedkeys, None);
+    }
+
+    #[test]
+    fn test_config_toml_loadthreads() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        loadthreads = 2
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.loadthreads, Some(2));
+        assert_eq!(ParsedConfig::default().loadthreads, None);
+    }
+
+    #[test]
+    fn test_config_toml_snaptoken() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        snaptoken = "sekrit"
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.snaptoken.as_deref(), Some("sekrit"));
+        assert_eq!(ParsedConfig::default().snaptoken, None);
+    }
+
+    #[test]
+    fn test_config_toml_snapshot_prefix() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [snapshot]
+        every = 3600
+        atmost = 4
+        prefix = "nightly-"
+        "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        match cfg.snapshot {
+            SnapshotConfig::Enabled(pref) => assert_eq!(pref.prefix, "nightly-"),
+            SnapshotConfig::Disabled => panic!("snapshots should be enabled"),
+        }
+        assert!(snapshot::is_valid_prefix("nightly-"));
+        assert!(snapshot::is_valid_prefix(""));
+        assert!(!snapshot::is_valid_prefix("../nightly"));
+        assert!(!snapshot::is_valid_prefix("night ly"));
+    }
+
+    #[test]
+    fn test_config_toml_lazyload() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [lazyload]
+        enabled = true
+        prewarm = ["app:users", "app:sessions"]
+    "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(
+            cfg.lazyload,
+            LazyLoad::Enabled {
+                prewarm: vec!["app:users".to_owned(), "app:sessions".to_owned()]
+            }
+        );
+        assert_eq!(ParsedConfig::default().lazyload, LazyLoad::Disabled);
+    }
+
+    #[test]
+    fn test_config_toml_syncstream() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [syncstream]
+        buffer = 0
+    "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file).unwrap();
+        assert_eq!(cfg.syncbuffer, 0);
+        assert_eq!(ParsedConfig::default().syncbuffer, DEFAULT_SYNC_BUFFER);
+    }
+
+    #[test]
+    fn test_env_overrides_take_precedence() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        maxclient = 100
+        [snapshot]
+        every = 3600
+        atmost = 4
+    "#;
+        let vars = vec![
+            ("SKY_SERVER_PORT".to_owned(), "2010".to_owned()),
+            ("SKY_SNAPSHOT_ATMOST".to_owned(), "8".to_owned()),
+            ("SKY_BGSAVE_ENABLED".to_owned(), "false".to_owned()),
+        ];
+        let cfg = ParsedConfig::from_layers(file, &vars).unwrap();
+        // the environment wins over the file
+        assert_eq!(cfg.ports, PortConfig::new_insecure_only(DEFAULT_IPV4, 2010));
+        match &cfg.snapshot {
+            SnapshotConfig::Enabled(pref) => {
+                assert_eq!(pref.every, 3600);
+                assert_eq!(pref.atmost, 8);
             }
+            other => panic!("expected snapshots to be enabled, got {:?}", other),
+        }
+        // even for a section that the file doesn't have
+        assert!(cfg.bgsave.is_disabled());
+        // and the file wins over the defaults
+        assert_eq!(cfg.maxcon, 100);
+        assert_eq!(cfg.syncbuffer, DEFAULT_SYNC_BUFFER);
+    }
+
+    #[test]
+    fn test_dump_config_round_trip() {
+        let file = get_toml_from_examples_dir("template.toml".to_owned()).unwrap();
+        let dump = schema::canonical(&schema::load(&file, &[]).unwrap());
+        assert_eq!(
+            ParsedConfig::from_layers(&dump, &[]).unwrap(),
+            ParsedConfig::new_from_toml_str(file).unwrap()
         );
     }
+
+    #[test]
+    fn test_sandbox_conflicts() {
+        let sandbox = StartupOpts {
+            mode: StartMode::Normal,
+            restore_from: None,
+            force: false,
+            import: None,
+            migrate: None,
+            schema: None,
+            sandbox: true,
+            config_file: None,
+        };
+        assert!(sandbox.sandbox_conflict(false).is_none());
+        let conflict = |opts: &StartupOpts, restorefile| match opts.sandbox_conflict(restorefile) {
+            Some(ConfigError::CliArgErr(e)) => e,
+            e => panic!("expected an argument error, got {:?}", e),
+        };
+        assert_eq!(
+            conflict(&sandbox, true),
+            "`--sandbox` can't be used with `--restore`"
+        );
+        let restoring = StartupOpts {
+            restore_from: Some("snapshots/remote/before".to_owned()),
+            ..sandbox
+        };
+        assert_eq!(
+            conflict(&restoring, false),
+            "`--sandbox` can't be used with `--restore-from`"
+        );
+        let checking = StartupOpts {
+            mode: StartMode::CheckOnly,
+            restore_from: None,
+            ..restoring
+        };
+        assert_eq!(
+            conflict(&checking, false),
+            "`--sandbox` can't be used with `--check-config`"
+        );
+        // and none of them matter without `--sandbox`
+        let not_sandboxed = StartupOpts {
+            restore_from: Some("snapshots/remote/before".to_owned()),
+            sandbox: false,
+            ..checking
+        };
+        assert!(not_sandboxed.sandbox_conflict(true).is_none());
+    }
+
+    #[test]
+    fn test_sandbox_turns_off_persistence() {
+        let file = r#"
+        [server]
+        host = "127.0.0.1"
+        port = 2003
+        [bgsave]
+        enabled = true
+        every = 60
+        [snapshot]
+        every = 3600
+        atmost = 4
+    "#
+        .to_owned();
+        let cfg = ParsedConfig::new_from_toml_str(file)
+            .unwrap()
+            .into_sandbox();
+        assert_eq!(cfg.bgsave, BGSave::Disabled);
+        assert_eq!(cfg.snapshot, SnapshotConfig::Disabled);
+        assert_eq!(cfg.ports, ParsedConfig::default().ports);
+    }
 }
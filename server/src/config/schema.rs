@@ -0,0 +1,699 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! The schema of the configuration file
+//!
+//! The configuration file is checked against the schema before it is deserialized, so that a
+//! misspelt key is reported (along with the keys that it was probably meant to be) instead of
+//! being silently ignored, and so that a value of the wrong type is reported along with the
+//! path of its key.
+//!
+//! Any key can also be set with a `SKY_<SECTION>_<KEY>` environment variable (for example,
+//! `SKY_SERVER_PORT=2010` or `SKY_SNAPSHOT_EVERY=600`). These are layered over the
+//! configuration file, so a key is taken from (in the order of precedence):
+//! 1. The environment
+//! 2. The configuration file
+//! 3. The built-in defaults
+//!
+//! Arrays are set with comma separated values (like `SKY_METRICS_BUCKETS=500,1000,5000`).
+//! Since the other `SKY_*` variables (like `SKY_LOG`) don't name a key of a section, they are
+//! left alone; a variable naming a section with a key that doesn't exist is an error though.
+
+use super::ConfigError;
+use std::env;
+use std::net::IpAddr;
+use toml::value::Table;
+use toml::Value;
+
+/// The prefix of the environment variables that override the configuration file
+const ENV_PREFIX: &str = "SKY_";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The type of the value that a key takes
+pub enum Kind {
+    Bool,
+    /// A non-negative integer
+    Int,
+    /// An integer that fits in a port number
+    Port,
+    /// A float (integers are accepted too)
+    Float,
+    Str,
+    /// A string with an IPv4 or IPv6 address
+    Ip,
+    /// One of these strings
+    OneOf(&'static [&'static str]),
+    /// An array of non-negative integers
+    IntArray,
+    StrArray,
+}
+
+impl Kind {
+    /// Returns true if `value` has the type that this kind describes
+    fn accepts(&self, value: &Value) -> bool {
+        match (self, value) {
+            (Kind::Bool, Value::Boolean(_)) => true,
+            (Kind::Int, Value::Integer(int)) => *int >= 0,
+            (Kind::Port, Value::Integer(int)) => *int >= 0 && *int <= i64::from(u16::MAX),
+            (Kind::Float, Value::Float(_)) | (Kind::Float, Value::Integer(_)) => true,
+            (Kind::Str, Value::String(_)) => true,
+            (Kind::Ip, Value::String(s)) => s.parse::<IpAddr>().is_ok(),
+            (Kind::OneOf(variants), Value::String(s)) => variants.contains(&s.as_str()),
+            (Kind::IntArray, Value::Array(array)) => array.iter().all(|v| Kind::Int.accepts(v)),
+            (Kind::StrArray, Value::Array(array)) => array.iter().all(|v| Kind::Str.accepts(v)),
+            _ => false,
+        }
+    }
+    /// Describes what this kind expects, for the errors
+    fn expected(&self) -> String {
+        match self {
+            Kind::Bool => "a boolean".to_owned(),
+            Kind::Int => "a non-negative integer".to_owned(),
+            Kind::Port => "a port number (0 to 65535)".to_owned(),
+            Kind::Float => "a number".to_owned(),
+            Kind::Str => "a string".to_owned(),
+            Kind::Ip => "an IPv4 or IPv6 address".to_owned(),
+            Kind::OneOf(variants) => {
+                let quoted: Vec<String> = variants.iter().map(|v| format!("\"{}\"", v)).collect();
+                match quoted.split_last() {
+                    Some((last, rest)) if !rest.is_empty() => {
+                        format!("one of {} or {}", rest.join(", "), last)
+                    }
+                    _ => quoted.concat(),
+                }
+            }
+            Kind::IntArray => "an array of non-negative integers".to_owned(),
+            Kind::StrArray => "an array of strings".to_owned(),
+        }
+    }
+    /// Parse the value of an environment variable as this kind. Arrays are comma separated
+    fn parse_env(&self, raw: &str) -> Option<Value> {
+        let value = match self {
+            Kind::Bool => Value::Boolean(raw.parse().ok()?),
+            Kind::Int | Kind::Port => Value::Integer(raw.parse().ok()?),
+            Kind::Float => Value::Float(raw.parse().ok()?),
+            Kind::Str | Kind::Ip | Kind::OneOf(_) => Value::String(raw.to_owned()),
+            Kind::IntArray | Kind::StrArray => {
+                let elem = if let Kind::IntArray = self {
+                    Kind::Int
+                } else {
+                    Kind::Str
+                };
+                let mut array = Vec::new();
+                for item in raw
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                {
+                    array.push(elem.parse_env(item)?);
+                }
+                Value::Array(array)
+            }
+        };
+        Some(value)
+    }
+}
+
+/// A key of a section
+pub struct Key {
+    pub name: &'static str,
+    pub kind: Kind,
+    /// Whether the section has to have this key
+    pub required: bool,
+}
+
+const fn opt(name: &'static str, kind: Kind) -> Key {
+    Key {
+        name,
+        kind,
+        required: false,
+    }
+}
+
+const fn req(name: &'static str, kind: Kind) -> Key {
+    Key {
+        name,
+        kind,
+        required: true,
+    }
+}
+
+/// A section (table) of the configuration file
+pub struct Section {
+    pub name: &'static str,
+    pub keys: &'static [Key],
+}
+
+impl Section {
+    fn key(&self, name: &str) -> Option<&'static Key> {
+        self.keys.iter().find(|key| key.name == name)
+    }
+}
+
+/// Every section of the configuration file, in the order that they're dumped in. This has to
+/// be kept in sync with [`super::Config`]
+pub const SCHEMA: &[Section] = &[
+    Section {
+        name: "server",
+        keys: &[
+            req("host", Kind::Ip),
+            req("port", Kind::Port),
+            opt("noart", Kind::Bool),
+            opt("maxclient", Kind::Int),
+            opt("readpolicy", Kind::OneOf(&["fail", "stale-ok"])),
+            opt("maxvaluesize", Kind::Int),
+            opt("datadir", Kind::Str),
+            opt("maxpause", Kind::Int),
+            opt("maxnamelen", Kind::Int),
+            opt("snaptoken", Kind::Str),
+            opt("loadthreads", Kind::Int),
+            opt("maxorderedkeys", Kind::Int),
+            opt("disabledactions", Kind::StrArray),
+            opt("healthport", Kind::Port),
+            opt("bloomfprate", Kind::Float),
+            opt("memoryceiling", Kind::Int),
+            opt("paranoid", Kind::OneOf(&["off", "report", "reject"])),
+            opt("uring", Kind::Bool),
+            opt("orphans", Kind::OneOf(&["fail", "adopt", "quarantine"])),
+            opt("allowmissing", Kind::Bool),
+            opt("trackreads", Kind::Bool),
+            opt("caseinsensitive", Kind::Bool),
+        ],
+    },
+    Section {
+        name: "bgsave",
+        keys: &[opt("enabled", Kind::Bool), opt("every", Kind::Int)],
+    },
+    Section {
+        name: "snapshot",
+        keys: &[
+            req("every", Kind::Int),
+            req("atmost", Kind::Int),
+            opt("maxage", Kind::Int),
+            opt("maxchain", Kind::Int),
+            opt("prefix", Kind::Str),
+            opt("failsafe", Kind::Bool),
+        ],
+    },
+    Section {
+        name: "ssl",
+        keys: &[
+            req("key", Kind::Str),
+            req("chain", Kind::Str),
+            req("port", Kind::Port),
+            opt("only", Kind::Bool),
+            opt("passin", Kind::Str),
+        ],
+    },
+    Section {
+        name: "backpressure",
+        keys: &[
+            req("dirtymark", Kind::Int),
+            opt("policy", Kind::OneOf(&["delay", "reject"])),
+        ],
+    },
+    Section {
+        name: "compression",
+        keys: &[
+            opt("algorithm", Kind::OneOf(&["lz4", "none"])),
+            opt("threshold", Kind::Int),
+        ],
+    },
+    Section {
+        name: "syncstream",
+        keys: &[req("buffer", Kind::Int)],
+    },
+    Section {
+        name: "lazyload",
+        keys: &[req("enabled", Kind::Bool), opt("prewarm", Kind::StrArray)],
+    },
+    Section {
+        name: "transactions",
+        keys: &[
+            opt("maxqueued", Kind::Int),
+            opt("maxbytes", Kind::Int),
+            opt("maxtotalqueued", Kind::Int),
+            opt("maxtotalbytes", Kind::Int),
+        ],
+    },
+    Section {
+        name: "pipeline",
+        keys: &[
+            opt("maxinflight", Kind::Int),
+            opt("maxinflightbytes", Kind::Int),
+            opt("maxdepth", Kind::Int),
+        ],
+    },
+    Section {
+        name: "slowlog",
+        keys: &[opt("threshold", Kind::Int), opt("size", Kind::Int)],
+    },
+    Section {
+        name: "metrics",
+        keys: &[opt("buckets", Kind::IntArray)],
+    },
+    Section {
+        name: "throttle",
+        keys: &[opt("snapshot", Kind::Int), opt("flush", Kind::Int)],
+    },
+    Section {
+        name: "selfcheck",
+        keys: &[req("every", Kind::Int), opt("tolerance", Kind::Int)],
+    },
+    Section {
+        name: "log",
+        keys: &[
+            opt("targets", Kind::StrArray),
+            opt("level", Kind::Str),
+            opt("stderrlevel", Kind::Str),
+            opt("filelevel", Kind::Str),
+            opt("journaldlevel", Kind::Str),
+            opt("sysloglevel", Kind::Str),
+            opt("file", Kind::Str),
+            opt("maxsize", Kind::Int),
+            opt("every", Kind::Int),
+            opt("keep", Kind::Int),
+        ],
+    },
+];
+
+/// The sections that every configuration file has to have
+const REQUIRED_SECTIONS: &[&str] = &["server"];
+
+fn section(name: &str) -> Option<&'static Section> {
+    SCHEMA.iter().find(|section| section.name == name)
+}
+
+/// Returns the environment variables, leaving out the ones that aren't valid unicode (since
+/// none of them can be an override anyway)
+pub fn env_vars() -> Vec<(String, String)> {
+    env::vars_os()
+        .filter_map(|(k, v)| Some((k.into_string().ok()?, v.into_string().ok()?)))
+        .collect()
+}
+
+/// Returns the section and key that the environment variable `var` overrides, if it looks
+/// like an override at all (`SKY_<SECTION>_<KEY>` with a known section)
+fn split_override(var: &str) -> Option<(&'static Section, String)> {
+    if !var.starts_with(ENV_PREFIX) {
+        return None;
+    }
+    let mut parts = var[ENV_PREFIX.len()..].splitn(2, '_');
+    let section = section(&parts.next()?.to_ascii_lowercase())?;
+    let key = parts.next()?.to_ascii_lowercase();
+    Some((section, key))
+}
+
+/// Returns true if any of `vars` overrides a key of the configuration file
+pub fn has_overrides(vars: &[(String, String)]) -> bool {
+    vars.iter().any(|(var, _)| split_override(var).is_some())
+}
+
+/// Layer the overrides in `vars` over `config`, returning the variables that couldn't be
+/// applied
+fn layer_env(config: &mut Table, vars: &[(String, String)]) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (var, raw) in vars {
+        let (section, keyname) = match split_override(var) {
+            Some(split) => split,
+            None => continue,
+        };
+        let key = match section.key(&keyname) {
+            Some(key) => key,
+            None => {
+                let mut err = format!("unknown key `{}.{}` in `{}`", section.name, keyname, var);
+                let names = section.keys.iter().map(|key| key.name);
+                if let Some(hint) = did_you_mean(&keyname, names, |name| {
+                    format!("{}{}_{}", ENV_PREFIX, section.name, name).to_ascii_uppercase()
+                }) {
+                    err.push_str(&hint);
+                }
+                errors.push(err);
+                continue;
+            }
+        };
+        match key.kind.parse_env(raw) {
+            Some(value) => {
+                let table = config
+                    .entry(section.name.to_owned())
+                    .or_insert_with(|| Value::Table(Table::new()));
+                // if the file has a value where the section should be, validation will
+                // report that
+                if let Value::Table(table) = table {
+                    table.insert(key.name.to_owned(), value);
+                }
+            }
+            None => errors.push(format!(
+                "`{}` should be {}, not \"{}\"",
+                var,
+                key.kind.expected(),
+                raw
+            )),
+        }
+    }
+    errors
+}
+
+/// Check `config` against the schema, returning every problem with it
+pub fn validate(config: &Table) -> Vec<String> {
+    let mut errors = Vec::new();
+    for (name, value) in config {
+        let section = match section(name) {
+            Some(section) => section,
+            None => {
+                let mut err = format!("unknown section `[{}]`", name);
+                let names = SCHEMA.iter().map(|section| section.name);
+                if let Some(hint) = did_you_mean(name, names, |name| format!("[{}]", name)) {
+                    err.push_str(&hint);
+                }
+                errors.push(err);
+                continue;
+            }
+        };
+        let table = match value {
+            Value::Table(table) => table,
+            other => {
+                errors.push(format!(
+                    "`{}` should be a section, not {}",
+                    name,
+                    describe(other)
+                ));
+                continue;
+            }
+        };
+        for (keyname, value) in table {
+            match section.key(keyname) {
+                Some(key) if key.kind.accepts(value) => {}
+                Some(key) => errors.push(format!(
+                    "`{}.{}` should be {}, not {}",
+                    name,
+                    keyname,
+                    key.kind.expected(),
+                    describe(value)
+                )),
+                None => {
+                    let mut err = format!("unknown key `{}.{}`", name, keyname);
+                    let names = section.keys.iter().map(|key| key.name);
+                    if let Some(hint) =
+                        did_you_mean(keyname, names, |key| format!("{}.{}", name, key))
+                    {
+                        err.push_str(&hint);
+                    }
+                    errors.push(err);
+                }
+            }
+        }
+        for key in section.keys.iter().filter(|key| key.required) {
+            if !table.contains_key(key.name) {
+                errors.push(format!("`{}.{}` is missing", name, key.name));
+            }
+        }
+    }
+    for section in REQUIRED_SECTIONS {
+        if !config.contains_key(*section) {
+            errors.push(format!("the `[{}]` section is missing", section));
+        }
+    }
+    errors
+}
+
+/// Parse `file`, layer the overrides in `vars` over it and check the result against the
+/// schema
+pub fn load(file: &str, vars: &[(String, String)]) -> Result<Table, ConfigError> {
+    let mut config: Table = match toml::from_str(file) {
+        Ok(config) => config,
+        Err(e) => return Err(ConfigError::SyntaxError(e.into())),
+    };
+    let mut errors = layer_env(&mut config, vars);
+    errors.extend(validate(&config));
+    if errors.is_empty() {
+        Ok(config)
+    } else {
+        Err(ConfigError::SchemaError(errors.join("; ")))
+    }
+}
+
+/// Returns `config` in its canonical form: the sections and their keys in the order of the
+/// schema, one key on every line and without any comments
+pub fn canonical(config: &Table) -> String {
+    let mut out = String::new();
+    for section in SCHEMA {
+        let table = match config.get(section.name) {
+            Some(Value::Table(table)) => table,
+            _ => continue,
+        };
+        if !out.is_empty() {
+            out.push('\n');
+        }
+        out.push_str(&format!("[{}]\n", section.name));
+        for key in section.keys {
+            if let Some(value) = table.get(key.name) {
+                out.push_str(&format!("{} = {}\n", key.name, render(value)));
+            }
+        }
+    }
+    out
+}
+
+/// Render `value` as TOML
+fn render(value: &Value) -> String {
+    match value {
+        Value::String(s) => {
+            let mut out = String::with_capacity(s.len() + 2);
+            out.push('"');
+            for c in s.chars() {
+                match c {
+                    '"' => out.push_str("\\\""),
+                    '\\' => out.push_str("\\\\"),
+                    '\n' => out.push_str("\\n"),
+                    '\t' => out.push_str("\\t"),
+                    '\r' => out.push_str("\\r"),
+                    c if c.is_control() => out.push_str(&format!("\\u{:04X}", c as u32)),
+                    c => out.push(c),
+                }
+            }
+            out.push('"');
+            out
+        }
+        Value::Integer(int) => int.to_string(),
+        Value::Boolean(b) => b.to_string(),
+        Value::Float(f) if f.is_nan() => "nan".to_owned(),
+        Value::Float(f) if f.is_infinite() && *f > 0.0 => "inf".to_owned(),
+        Value::Float(f) if f.is_infinite() => "-inf".to_owned(),
+        // a float needs the decimal point to stay a float when the dump is read back in
+        Value::Float(f) if f.fract() == 0.0 => format!("{:.1}", f),
+        Value::Float(f) => f.to_string(),
+        Value::Datetime(dt) => dt.to_string(),
+        Value::Array(array) => {
+            let items: Vec<String> = array.iter().map(render).collect();
+            format!("[{}]", items.join(", "))
+        }
+        Value::Table(table) => {
+            let items: Vec<String> = table
+                .iter()
+                .map(|(k, v)| format!("{} = {}", render(&Value::String(k.clone())), render(v)))
+                .collect();
+            format!("{{ {} }}", items.join(", "))
+        }
+    }
+}
+
+/// Describe `value` for the errors
+fn describe(value: &Value) -> String {
+    match value {
+        Value::String(_) | Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => {
+            format!("the {} {}", value.type_str(), render(value))
+        }
+        Value::Array(_) => format!("the array {}", render(value)),
+        other => format!("a {}", other.type_str()),
+    }
+}
+
+/// Returns the ` (did you mean ...?)` hint with the candidates that are close enough to
+/// `name` to be what was meant, formatted with `show`
+fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl Iterator<Item = &'a str>,
+    show: impl Fn(&str) -> String,
+) -> Option<String> {
+    let threshold = (name.len() / 3).max(1);
+    let mut close: Vec<(usize, &str)> = candidates
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= threshold)
+        .collect();
+    if close.is_empty() {
+        return None;
+    }
+    close.sort_by_key(|(distance, _)| *distance);
+    let shown: Vec<String> = close
+        .iter()
+        .map(|(_, candidate)| format!("`{}`", show(candidate)))
+        .collect();
+    let hint = match shown.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => shown.concat(),
+    };
+    Some(format!(" (did you mean {}?)", hint))
+}
+
+/// The number of single character insertions, deletions, substitutions and swaps of two
+/// adjacent characters that turn `a` into `b` (the optimal string alignment distance)
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            let mut distance = (d[i - 1][j - 1] + cost)
+                .min(d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(d[i - 2][j - 2] + 1);
+            }
+            d[i][j] = distance;
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors_of(file: &str) -> String {
+        match load(file, &[]) {
+            Err(ConfigError::SchemaError(e)) => e,
+            other => panic!("expected a schema error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("every", "every"), 0);
+        assert_eq!(edit_distance("evry", "every"), 1);
+        assert_eq!(edit_distance("snapshto", "snapshot"), 1);
+        assert_eq!(edit_distance("port", "host"), 2);
+        assert_eq!(edit_distance("", "port"), 4);
+    }
+
+    #[test]
+    fn test_unknown_keys_are_suggested() {
+        let e = errors_of("[server]\nhost = \"127.0.0.1\"\nport = 2003\nnoatr = true\n");
+        assert_eq!(
+            e,
+            "unknown key `server.noatr` (did you mean `server.noart`?)"
+        );
+        let e = errors_of("[server]\nhost = \"127.0.0.1\"\nport = 2003\n[snapshto]\nevery = 1\n");
+        assert_eq!(
+            e,
+            "unknown section `[snapshto]` (did you mean `[snapshot]`?)"
+        );
+        // nothing is close enough to suggest
+        let e = errors_of("[server]\nhost = \"127.0.0.1\"\nport = 2003\nmaxcon = 100\n");
+        assert_eq!(e, "unknown key `server.maxcon`");
+    }
+
+    #[test]
+    fn test_type_errors_have_the_key_path() {
+        let e = errors_of("[server]\nhost = \"127.0.0.1\"\nport = 2003\nmaxclient = \"100\"\n");
+        assert_eq!(
+            e,
+            "`server.maxclient` should be a non-negative integer, not the string \"100\""
+        );
+        let e = errors_of("[server]\nhost = \"localhost\"\nport = 20033002\n");
+        assert_eq!(
+            e,
+            "`server.host` should be an IPv4 or IPv6 address, not the string \"localhost\"; \
+            `server.port` should be a port number (0 to 65535), not the integer 20033002"
+        );
+        let e = errors_of(
+            "[server]\nhost = \"127.0.0.1\"\nport = 2003\n[backpressure]\npolicy = \"drop\"\n",
+        );
+        assert_eq!(
+            e,
+            "`backpressure.policy` should be one of \"delay\" or \"reject\", not the string \
+            \"drop\"; `backpressure.dirtymark` is missing"
+        );
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        let vars = vec![
+            ("SKY_SERVER_PORT".to_owned(), "2010".to_owned()),
+            ("SKY_METRICS_BUCKETS".to_owned(), "500, 1000".to_owned()),
+            // not an override
+            ("SKY_LOG".to_owned(), "trace".to_owned()),
+        ];
+        let config =
+            Value::Table(load("[server]\nhost = \"127.0.0.1\"\nport = 2003\n", &vars).unwrap());
+        assert_eq!(config["server"]["port"], Value::Integer(2010));
+        assert_eq!(
+            config["metrics"]["buckets"],
+            Value::Array(vec![Value::Integer(500), Value::Integer(1000)])
+        );
+        let vars = vec![("SKY_SERVER_PROT".to_owned(), "2010".to_owned())];
+        match load("[server]\nhost = \"127.0.0.1\"\nport = 2003\n", &vars) {
+            Err(ConfigError::SchemaError(e)) => assert_eq!(
+                e,
+                "unknown key `server.prot` in `SKY_SERVER_PROT` (did you mean `SKY_SERVER_PORT`?)"
+            ),
+            other => panic!("expected a schema error, got {:?}", other),
+        }
+        let vars = vec![("SKY_SERVER_PORT".to_owned(), "twenty".to_owned())];
+        match load("[server]\nhost = \"127.0.0.1\"\nport = 2003\n", &vars) {
+            Err(ConfigError::SchemaError(e)) => assert_eq!(
+                e,
+                "`SKY_SERVER_PORT` should be a port number (0 to 65535), not \"twenty\""
+            ),
+            other => panic!("expected a schema error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_canonical_round_trip() {
+        let file = "[snapshot] # comment\natmost = 4\nevery = 3600\n\
+            [server]\nport = 2003\nhost = '127.0.0.1'\nbloomfprate = 1.0\n\
+            snaptoken = \"a \\\"quoted\\\" token\"\ndisabledactions = [ \"flushdb\" ]\n";
+        let config = load(file, &[]).unwrap();
+        let dump = canonical(&config);
+        assert_eq!(
+            dump,
+            "[server]\nhost = \"127.0.0.1\"\nport = 2003\nsnaptoken = \"a \\\"quoted\\\" token\"\n\
+            disabledactions = [\"flushdb\"]\nbloomfprate = 1.0\n\n\
+            [snapshot]\nevery = 3600\natmost = 4\n"
+        );
+        let reloaded = load(&dump, &[]).unwrap();
+        assert_eq!(reloaded, config);
+        assert_eq!(canonical(&reloaded), dump);
+    }
+}
@@ -0,0 +1,97 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Clocks
+//!
+//! Anything that depends on the current time should ask the [`Clock`] held by the
+//! [`Memstore`](super::memstore::Memstore) instead of calling `Utc::now()` directly. On a
+//! server this is always the [`SystemClock`], but tests can swap in a [`MockClock`] that only
+//! moves when it is told to, so that time dependent behavior can be tested without sleeping
+
+use chrono::prelude::*;
+use std::sync::Arc;
+#[cfg(test)]
+use {crate::corestore::lock::QuickLock, chrono::Duration};
+
+/// A source for the current time
+pub trait Clock: Send + Sync {
+    /// Returns the current (wall-clock) time
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// An atomic reference to a clock, shared by all the handles to a [`Memstore`](super::memstore::Memstore)
+pub type ClockRef = Arc<dyn Clock>;
+
+/// The real clock
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+#[cfg(test)]
+/// A clock that stands still unless it is explicitly moved with [`MockClock::advance`]
+pub struct MockClock {
+    now: QuickLock<DateTime<Utc>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    /// Create a new mock clock that is stopped at `now`
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            now: QuickLock::new(now),
+        }
+    }
+    /// Create a new mock clock that is stopped at the given `YYYY-MM-DD HH:MM:SS` (UTC)
+    pub fn at(year: i32, month: u32, day: u32, hour: u32, min: u32, sec: u32) -> Self {
+        Self::new(Utc.ymd(year, month, day).and_hms(hour, min, sec))
+    }
+    /// Move the clock forward by `by`
+    pub fn advance(&self, by: Duration) {
+        let mut now = self.now.lock();
+        *now = *now + by;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock()
+    }
+}
+
+#[test]
+fn test_mock_clock_only_moves_when_advanced() {
+    let clock = MockClock::at(2021, 7, 1, 10, 0, 0);
+    let start = clock.now();
+    assert_eq!(clock.now(), start);
+    clock.advance(Duration::seconds(90));
+    assert_eq!(clock.now() - start, Duration::seconds(90));
+    assert_eq!(clock.now(), Utc.ymd(2021, 7, 1).and_hms(10, 1, 30));
+}
@@ -0,0 +1,228 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Hashers
+//!
+//! The maps that hold the tables and the keyspaces ([`Coremap`](super::htable::Coremap)) hash
+//! their keys with [`HashBuilder`], which is picked when the server is built:
+//! - By default, this is the standard library's [`RandomState`]: SipHash-1-3, keyed with 128
+//!   bits that are drawn from the randomness of the OS when the process starts (and every map
+//!   gets a key of its own). Since clients can't know the key, they can't pick keys that land
+//!   in the same bucket to slow the server down (hash flooding), even though the keys come
+//!   straight from them. The key is never logged or exposed in any other way
+//! - With the `fasthash` feature, this is [`FastState`] (FxHash), which is a lot faster (most
+//!   of all on short keys) but isn't keyed at all, so anyone can work out which keys collide.
+//!   Only use this if every client of the server is trusted
+//!
+//! To compare the two on a machine, run:
+//! ```text
+//! cargo test --release bench_hashers -- --ignored --nocapture
+//! ```
+
+#[cfg(not(feature = "fasthash"))]
+use std::collections::hash_map::RandomState;
+#[cfg(any(test, feature = "fasthash"))]
+use {
+    core::convert::TryInto,
+    std::hash::{BuildHasherDefault, Hasher},
+};
+
+#[cfg(not(feature = "fasthash"))]
+/// The state that the hashers of the maps are built from (see the [module docs](self))
+pub type HashBuilder = RandomState;
+#[cfg(feature = "fasthash")]
+/// The state that the hashers of the maps are built from (see the [module docs](self))
+pub type HashBuilder = FastState;
+
+#[cfg(any(test, feature = "fasthash"))]
+/// Builds [`FxHasher`]s, which all start out the same
+pub type FastState = BuildHasherDefault<FxHasher>;
+
+#[cfg(any(test, feature = "fasthash"))]
+/// The multiplier of FxHash
+const FX_SEED: u64 = 0x51_7c_c1_b7_27_22_0a_95;
+
+#[cfg(any(test, feature = "fasthash"))]
+#[derive(Debug, Default, Clone, Copy)]
+/// FxHash (the hasher of rustc and Firefox): every word is mixed in with a rotate, a xor and
+/// a multiply. This is fast, but it isn't keyed
+pub struct FxHasher {
+    hash: u64,
+}
+
+#[cfg(any(test, feature = "fasthash"))]
+impl FxHasher {
+    fn add_to_hash(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(FX_SEED);
+    }
+}
+
+#[cfg(any(test, feature = "fasthash"))]
+impl Hasher for FxHasher {
+    fn write(&mut self, mut bytes: &[u8]) {
+        while bytes.len() >= 8 {
+            let (word, rest) = bytes.split_at(8);
+            self.add_to_hash(u64::from_le_bytes(word.try_into().unwrap()));
+            bytes = rest;
+        }
+        if bytes.len() >= 4 {
+            let (word, rest) = bytes.split_at(4);
+            self.add_to_hash(u32::from_le_bytes(word.try_into().unwrap()) as u64);
+            bytes = rest;
+        }
+        for byte in bytes {
+            self.add_to_hash(*byte as u64);
+        }
+    }
+    fn write_u8(&mut self, i: u8) {
+        self.add_to_hash(i as u64);
+    }
+    fn write_u16(&mut self, i: u16) {
+        self.add_to_hash(i as u64);
+    }
+    fn write_u32(&mut self, i: u32) {
+        self.add_to_hash(i as u64);
+    }
+    fn write_u64(&mut self, i: u64) {
+        self.add_to_hash(i);
+    }
+    fn write_usize(&mut self, i: usize) {
+        self.add_to_hash(i as u64);
+    }
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corestore::htable::Data;
+    use dashmap::DashMap;
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hash};
+    use std::time::Instant;
+
+    fn hash_of(state: &impl BuildHasher, key: &Data) -> u64 {
+        let mut hasher = state.build_hasher();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn test_fxhash_is_deterministic() {
+        let key = Data::from("skytable is a database");
+        let (a, b) = (FastState::default(), FastState::default());
+        assert_eq!(hash_of(&a, &key), hash_of(&b, &key));
+        assert_ne!(
+            hash_of(&a, &key),
+            hash_of(&a, &Data::from("skytable is a databasf"))
+        );
+    }
+
+    /// Print the buckets (out of 1024) that a set of keys land in with [`HashBuilder`]. This
+    /// is run in child processes by [`test_processes_hash_keys_differently`]
+    #[test]
+    #[ignore]
+    fn print_buckets() {
+        let state = HashBuilder::default();
+        let buckets: Vec<String> = (0..64)
+            .map(|i| (hash_of(&state, &Data::from(format!("key{}", i))) % 1024).to_string())
+            .collect();
+        println!("buckets: {}", buckets.join(","));
+    }
+
+    /// Run [`print_buckets`] in a new process and return what it printed
+    fn buckets_in_a_new_process() -> String {
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(&[
+                "corestore::hasher::tests::print_buckets",
+                "--exact",
+                "--ignored",
+                "--nocapture",
+                "--test-threads",
+                "1",
+            ])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        stdout
+            .lines()
+            .find_map(|line| line.split_once("buckets: "))
+            .map(|(_, buckets)| buckets.to_owned())
+            .expect("the child process didn't print the buckets")
+    }
+
+    #[test]
+    #[cfg(not(feature = "fasthash"))]
+    fn test_processes_hash_keys_differently() {
+        // every process draws a key of its own, so the same keys land in different buckets
+        // in another process (and can't be worked out from the outside)
+        assert_ne!(buckets_in_a_new_process(), buckets_in_a_new_process());
+    }
+
+    #[test]
+    #[cfg(feature = "fasthash")]
+    fn test_processes_hash_keys_the_same() {
+        // the fast hasher isn't keyed at all
+        assert_eq!(buckets_in_a_new_process(), buckets_in_a_new_process());
+    }
+
+    /// Time inserting and then reading `keys` in a map with `state`, returning the nanoseconds
+    /// that every key took
+    fn time_with<S: BuildHasher + Clone>(state: S, keys: &[Data]) -> (f64, f64) {
+        let map = DashMap::with_capacity_and_hasher(keys.len(), state);
+        let start = Instant::now();
+        for key in keys {
+            map.insert(key.clone(), key.clone());
+        }
+        let inserts = start.elapsed();
+        let start = Instant::now();
+        for key in keys {
+            assert!(map.get(key).is_some());
+        }
+        let reads = start.elapsed();
+        let per_key = |total: std::time::Duration| total.as_nanos() as f64 / keys.len() as f64;
+        (per_key(inserts), per_key(reads))
+    }
+
+    /// Compare the keyed default with the fast hasher (see the module docs)
+    #[test]
+    #[ignore]
+    fn bench_hashers() {
+        for key_len in [8, 32, 256].iter().copied() {
+            let keys: Vec<Data> = (0..1_000_000u64)
+                .map(|i| Data::from(format!("{:0>width$}", i, width = key_len)))
+                .collect();
+            let (keyed_set, keyed_get) = time_with(RandomState::new(), &keys);
+            let (fast_set, fast_get) = time_with(FastState::default(), &keys);
+            println!(
+                "{:>3} byte keys: keyed {:.1} ns/insert {:.1} ns/get, fast {:.1} ns/insert {:.1} ns/get",
+                key_len, keyed_set, keyed_get, fast_set, fast_get
+            );
+        }
+    }
+}
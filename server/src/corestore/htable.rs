@@ -24,10 +24,10 @@
  *
 */
 
+pub use super::hasher::HashBuilder;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use std::borrow::Borrow;
-use std::collections::hash_map::RandomState;
 use std::fmt;
 use std::hash::Hash;
 use std::iter::FromIterator;
@@ -39,11 +39,13 @@ pub use dashmap::lock::RwLockReadGuard as MapRWLGuard;
 pub use dashmap::mapref::entry::Entry as MapEntry;
 pub use dashmap::mapref::entry::OccupiedEntry;
 use dashmap::mapref::entry::VacantEntry;
-pub use dashmap::mapref::one::Ref as MapSingleReference;
 use dashmap::mapref::one::Ref;
 use dashmap::DashMap;
 pub use dashmap::SharedValue;
-pub type HashTable<K, V> = DashMap<K, V>;
+/// The maps hash their keys with [`HashBuilder`] (see [`hasher`](super::hasher))
+pub type HashTable<K, V> = DashMap<K, V, HashBuilder>;
+/// A reference to an entry of a [`HashTable`]
+pub type MapSingleReference<'a, K, V> = Ref<'a, K, V, HashBuilder>;
 
 #[derive(Debug)]
 /// The Coremap contains the actual key/value pairs along with additional fields for data safety
@@ -58,7 +60,7 @@ where
 impl<K: Eq + Hash, V> Default for Coremap<K, V> {
     fn default() -> Self {
         Coremap {
-            inner: HashTable::new(),
+            inner: HashTable::with_hasher(HashBuilder::default()),
         }
     }
 }
@@ -70,7 +72,7 @@ impl<K: Eq + Hash, V> Coremap<K, V> {
     }
     pub fn with_capacity(cap: usize) -> Self {
         Coremap {
-            inner: HashTable::with_capacity(cap),
+            inner: HashTable::with_capacity_and_hasher(cap, HashBuilder::default()),
         }
     }
 }
@@ -112,11 +114,11 @@ where
         self.inner.clear()
     }
     /// Return a non-consuming iterator
-    pub fn iter(&self) -> Iter<'_, K, V> {
+    pub fn iter(&self) -> Iter<'_, K, V, HashBuilder> {
         self.inner.iter()
     }
     /// Get a reference to the value of a key, if it exists
-    pub fn get<Q>(&self, key: &Q) -> Option<Ref<'_, K, V>>
+    pub fn get<Q>(&self, key: &Q) -> Option<MapSingleReference<'_, K, V>>
     where
         K: Borrow<Q>,
         Q: Hash + Eq + ?Sized,
@@ -150,23 +152,22 @@ where
     pub fn upsert(&self, k: K, v: V) {
         let _ = self.inner.insert(k, v);
     }
-    /// Returns true if the value was updated
-    pub fn true_if_update(&self, k: K, v: V) -> bool {
-        if let MapEntry::Occupied(mut oe) = self.inner.entry(k) {
-            oe.insert(v);
-            true
-        } else {
-            false
-        }
+    /// Update or insert, returning the value that was replaced (if any)
+    pub fn replace(&self, k: K, v: V) -> Option<V> {
+        self.inner.insert(k, v)
+    }
+    /// Only keep the entries for which `keep` returns true
+    pub fn retain(&self, keep: impl FnMut(&K, &mut V) -> bool) {
+        self.inner.retain(keep)
     }
-    pub fn mut_entry(&self, key: K) -> Option<OccupiedEntry<K, V, RandomState>> {
+    pub fn mut_entry(&self, key: K) -> Option<OccupiedEntry<K, V, HashBuilder>> {
         if let MapEntry::Occupied(oe) = self.inner.entry(key) {
             Some(oe)
         } else {
             None
         }
     }
-    pub fn fresh_entry(&self, key: K) -> Option<VacantEntry<K, V, RandomState>> {
+    pub fn fresh_entry(&self, key: K) -> Option<VacantEntry<K, V, HashBuilder>> {
         if let MapEntry::Vacant(ve) = self.inner.entry(key) {
             Some(ve)
         } else {
@@ -189,7 +190,7 @@ impl Coremap<Data, Data> {
 
 impl<K: Eq + Hash, V> IntoIterator for Coremap<K, V> {
     type Item = (K, V);
-    type IntoIter = dashmap::iter::OwningIter<K, V>;
+    type IntoIter = dashmap::iter::OwningIter<K, V, HashBuilder>;
     fn into_iter(self) -> Self::IntoIter {
         self.inner.into_iter()
     }
@@ -229,13 +230,13 @@ where
         T: IntoIterator<Item = (K, V)>,
     {
         Coremap {
-            inner: DashMap::from_iter(iter),
+            inner: HashTable::from_iter(iter),
         }
     }
 }
 
 /// A wrapper for `Bytes`
-#[derive(Debug, PartialEq, Clone, Hash)]
+#[derive(Debug, PartialEq, PartialOrd, Ord, Clone, Hash)]
 pub struct Data {
     /// The blob of data
     blob: Bytes,
@@ -24,6 +24,8 @@
  *
 */
 
+use crate::corestore::lock::QuickLock;
+use core::convert::Infallible;
 use core::hint::spin_loop as let_the_cpu_relax;
 use core::mem;
 use core::ops::Deref;
@@ -35,6 +37,86 @@ use core::sync::atomic::Ordering;
 const ORD_ACQ: Ordering = Ordering::Acquire;
 const ORD_SEQ: Ordering = Ordering::SeqCst;
 
+/// Holds the `init_state` of a lazy for as long as it lives. The flag is released on drop, so
+/// an initializer that fails (or panics) doesn't leave the other threads spinning forever
+struct InitLock<'a> {
+    init_state: &'a AtomicBool,
+}
+
+impl<'a> InitLock<'a> {
+    fn acquire(init_state: &'a AtomicBool) -> Self {
+        // hold on until someone is trying to init
+        while init_state
+            .compare_exchange(false, true, ORD_SEQ, ORD_SEQ)
+            .is_err()
+        {
+            let_the_cpu_relax();
+        }
+        Self { init_state }
+    }
+}
+
+impl<'a> Drop for InitLock<'a> {
+    fn drop(&mut self) {
+        assert!(self.init_state.swap(false, ORD_SEQ));
+    }
+}
+
+/// Returns the value behind `value`, running `init` to initialize it if it's still null. A
+/// single atomic load is all that it takes once the value is there
+fn get_or_try_init<'a, T, E>(
+    value: &'a AtomicPtr<T>,
+    init_state: &AtomicBool,
+    init: impl FnOnce() -> Result<T, E>,
+) -> Result<&'a T, E> {
+    let value_ptr = value.load(ORD_ACQ);
+    if !value_ptr.is_null() {
+        // the value has already been initialized, return
+        unsafe {
+            // UNSAFE(@ohsayan): We've just asserted that the value is not null
+            return Ok(&*value_ptr);
+        }
+    }
+    // it's null, so it's useless
+    let _lock = InitLock::acquire(init_state);
+    /*
+     see the value before the last store. while we were one the loop,
+     some other thread could have initialized it already
+    */
+    let value_ptr = value.load(ORD_ACQ);
+    if !value_ptr.is_null() {
+        // no more init, someone initialized it already
+        unsafe {
+            // UNSAFE(@ohsayan): We've already loaded the value checked
+            // that it isn't null
+            Ok(&*value_ptr)
+        }
+    } else {
+        // so no one cared to initialize the value in between
+        // fine, we'll init it. If this fails, the value stays null and the lock is
+        // released so that the next caller can try again
+        let value_ptr = Box::into_raw(Box::new(init()?));
+        // now swap out the older value and check it for sanity
+        assert!(value.swap(value_ptr, ORD_SEQ).is_null());
+        unsafe {
+            // UNSAFE(@ohsayan): We just initialized the value ourselves
+            // so it is not null!
+            Ok(&*value_ptr)
+        }
+    }
+}
+
+/// Frees the value behind `value`, if there is one
+fn drop_value<T>(value: &mut AtomicPtr<T>) {
+    let value_ptr = value.load(ORD_ACQ);
+    if !value_ptr.is_null() {
+        unsafe {
+            // UNSAFE(@ohsayan): We've just checked if the value is null or not
+            mem::drop(Box::from_raw(value_ptr))
+        }
+    }
+}
+
 /// A lazily intialized, or _call by need_ value
 #[derive(Debug)]
 pub struct Lazy<T, F> {
@@ -62,51 +144,11 @@ where
 {
     type Target = T;
     fn deref(&self) -> &Self::Target {
-        let value_ptr = self.value.load(ORD_ACQ);
-        if !value_ptr.is_null() {
-            // the value has already been initialized, return
-            unsafe {
-                // UNSAFE(@ohsayan): We've just asserted that the value is not null
-                return &*value_ptr;
-            }
-        }
-        // it's null, so it's useless
-
-        // hold on until someone is trying to init
-        while self
-            .init_state
-            .compare_exchange(false, true, ORD_SEQ, ORD_SEQ)
-            .is_err()
-        {
-            let_the_cpu_relax();
-        }
-        /*
-         see the value before the last store. while we were one the loop,
-         some other thread could have initialized it already
-        */
-        let value_ptr = self.value.load(ORD_ACQ);
-        if !value_ptr.is_null() {
-            // no more init, someone initialized it already
-            assert!(self.init_state.swap(false, ORD_SEQ));
-            unsafe {
-                // UNSAFE(@ohsayan): We've already loaded the value checked
-                // that it isn't null
-                &*value_ptr
-            }
-        } else {
-            // so no one cared to initialize the value in between
-            // fine, we'll init it
-            let value = (self.init_func)();
-            let value_ptr = Box::into_raw(Box::new(value));
-            // now swap out the older value and check it for sanity
-            assert!(self.value.swap(value_ptr, ORD_SEQ).is_null());
-            // set trying to init flag to false
-            assert!(self.init_state.swap(false, ORD_SEQ));
-            unsafe {
-                // UNSAFE(@ohsayan): We just initialized the value ourselves
-                // so it is not null!
-                &*value_ptr
-            }
+        match get_or_try_init(&self.value, &self.init_state, || {
+            Ok::<T, Infallible>((self.init_func)())
+        }) {
+            Ok(value) => value,
+            Err(never) => match never {},
         }
     }
 }
@@ -123,17 +165,147 @@ impl<T, F> Drop for Lazy<T, F> {
     fn drop(&mut self) {
         if mem::needs_drop::<T>() {
             // this needs drop
-            let value_ptr = self.value.load(ORD_ACQ);
-            if !value_ptr.is_null() {
-                unsafe {
-                    // UNSAFE(@ohsayan): We've just checked if the value is null or not
-                    mem::drop(Box::from_raw(value_ptr))
-                }
+            drop_value(&mut self.value)
+        }
+    }
+}
+
+/// A cell that is initialized (at most once) on first access, with the initializer passed in
+/// on access instead of being fixed up front. This means that the initializer can fail: when
+/// it does, the cell stays empty and the next access tries again
+#[derive(Debug)]
+pub struct LazyCell<T> {
+    /// the value (null at first)
+    value: AtomicPtr<T>,
+    /// is some thread trying to initialize the value
+    init_state: AtomicBool,
+}
+
+// the value is handed out to (and may be dropped by) any thread, which the `AtomicPtr` alone
+// doesn't account for
+unsafe impl<T: Send + Sync> Sync for LazyCell<T> {}
+unsafe impl<T: Send> Send for LazyCell<T> {}
+
+impl<T> LazyCell<T> {
+    pub const fn new() -> Self {
+        Self {
+            value: AtomicPtr::new(ptr::null_mut()),
+            init_state: AtomicBool::new(false),
+        }
+    }
+    /// Returns the value if it has been initialized
+    pub fn get(&self) -> Option<&T> {
+        let value_ptr = self.value.load(ORD_ACQ);
+        if value_ptr.is_null() {
+            None
+        } else {
+            unsafe {
+                // UNSAFE(@ohsayan): We've just asserted that the value is not null
+                Some(&*value_ptr)
+            }
+        }
+    }
+    /// Returns the value, initializing it with `init` if it hasn't been initialized yet
+    pub fn get_or_init(&self, init: impl FnOnce() -> T) -> &T {
+        match self.get_or_try_init(|| Ok::<T, Infallible>(init())) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+    /// Returns the value, initializing it with `init` if it hasn't been initialized yet. If
+    /// `init` fails, the error is returned and the cell is left empty
+    pub fn get_or_try_init<E>(&self, init: impl FnOnce() -> Result<T, E>) -> Result<&T, E> {
+        get_or_try_init(&self.value, &self.init_state, init)
+    }
+}
+
+impl<T> Drop for LazyCell<T> {
+    fn drop(&mut self) {
+        drop_value(&mut self.value)
+    }
+}
+
+/// A [`Lazy`] whose value can be swapped out at runtime with [`ReplaceableLazy::replace`].
+/// Reading the value is still just a single atomic load once it's there.
+///
+/// Replaced values are retired rather than freed, since other threads may still be holding
+/// references to them: they're only dropped along with the lazy itself. So, this is for values
+/// that are replaced rarely (say, when the configuration changes) and not for hot paths
+#[derive(Debug)]
+pub struct ReplaceableLazy<T, F> {
+    /// the current value (null at first)
+    value: AtomicPtr<T>,
+    /// the function that will init the value
+    init_func: F,
+    /// is some thread trying to initialize (or replace) the value
+    init_state: AtomicBool,
+    /// the values that have been replaced. These are kept as raw pointers (and not as boxes,
+    /// which would claim unique ownership) since other threads may still be reading them
+    retired: QuickLock<Vec<*mut T>>,
+}
+
+// see the note on `LazyCell`
+unsafe impl<T: Send + Sync, F: Sync> Sync for ReplaceableLazy<T, F> {}
+unsafe impl<T: Send, F: Send> Send for ReplaceableLazy<T, F> {}
+
+impl<T, F> ReplaceableLazy<T, F> {
+    pub const fn new(init_func: F) -> Self {
+        Self {
+            value: AtomicPtr::new(ptr::null_mut()),
+            init_func,
+            init_state: AtomicBool::new(false),
+            retired: QuickLock::new(Vec::new()),
+        }
+    }
+    /// Atomically replaces the value with `value`, returning the older value (if the lazy had
+    /// been initialized). Every access that follows sees the new value
+    pub fn replace(&self, value: T) -> Option<&T> {
+        let new_ptr = Box::into_raw(Box::new(value));
+        // don't race with a thread that's initializing the value for the first time
+        let _lock = InitLock::acquire(&self.init_state);
+        let old_ptr = self.value.swap(new_ptr, ORD_SEQ);
+        if old_ptr.is_null() {
+            None
+        } else {
+            // the value stays where it is till the lazy is dropped
+            self.retired.lock().push(old_ptr);
+            unsafe {
+                // UNSAFE(@ohsayan): See above; retired values live as long as the lazy does
+                Some(&*old_ptr)
             }
         }
     }
 }
 
+impl<T, F> Deref for ReplaceableLazy<T, F>
+where
+    F: Fn() -> T,
+{
+    type Target = T;
+    fn deref(&self) -> &Self::Target {
+        match get_or_try_init(&self.value, &self.init_state, || {
+            Ok::<T, Infallible>((self.init_func)())
+        }) {
+            Ok(value) => value,
+            Err(never) => match never {},
+        }
+    }
+}
+
+impl<T, F> Drop for ReplaceableLazy<T, F> {
+    fn drop(&mut self) {
+        for old_ptr in self.retired.lock().drain(..) {
+            unsafe {
+                // UNSAFE(@ohsayan): Every retired value was put in with Box::into_raw and was
+                // swapped out (once) by `replace`. No one can be holding a reference to it
+                // anymore since references to the values don't outlive the lazy
+                mem::drop(Box::from_raw(old_ptr))
+            }
+        }
+        drop_value(&mut self.value)
+    }
+}
+
 cfg_test!(
     use crate::corestore::Data;
     use crate::corestore::lazy;
@@ -216,4 +388,115 @@ cfg_test!(
         });
         drop(x);
     }
+
+    #[test]
+    fn test_lazy_cell_init_race() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        static CELL: LazyCell<String> = LazyCell::new();
+        static INITS: AtomicUsize = AtomicUsize::new(0);
+        let barrier = Arc::new(std::sync::Barrier::new(8));
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let value = CELL.get_or_init(|| {
+                        INITS.fetch_add(1, Ordering::SeqCst);
+                        format!("thread-{}", i)
+                    });
+                    value as *const String as usize
+                })
+            })
+            .collect();
+        let addrs: Vec<usize> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        // only one of the threads got to run its initializer, and everyone sees its value
+        assert_eq!(INITS.load(Ordering::SeqCst), 1);
+        assert!(addrs.iter().all(|addr| *addr == addrs[0]));
+        assert!(CELL.get().unwrap().starts_with("thread-"));
+    }
+
+    #[test]
+    fn test_lazy_cell_try_init_fails_then_succeeds() {
+        let cell: LazyCell<u8> = LazyCell::new();
+        assert_eq!(cell.get_or_try_init(|| Err("not now")), Err("not now"));
+        // a failed init leaves the cell empty (and unlocked)
+        assert!(cell.get().is_none());
+        assert_eq!(cell.get_or_try_init(|| Ok::<u8, &str>(1)), Ok(&1));
+        // and once it's there, the initializer isn't called anymore
+        assert_eq!(cell.get_or_try_init(|| Err("unreachable")), Ok(&1));
+        assert_eq!(cell.get_or_init(|| 2), &1);
+    }
+
+    #[test]
+    fn test_lazy_cell_drop() {
+        let cell: LazyCell<WeirdTestStruct> = LazyCell::new();
+        // nothing to drop
+        drop(cell);
+        let cell: LazyCell<WeirdTestStruct> = LazyCell::new();
+        cell.get_or_init(|| WeirdTestStruct(0));
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| drop(cell)));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_replaceable_lazy_replace() {
+        let lazy: ReplaceableLazy<String, fn() -> String> =
+            ReplaceableLazy::new(|| "first".to_owned());
+        // not initialized yet, so there's no older value
+        assert_eq!(lazy.replace("second".to_owned()), None);
+        assert_eq!(lazy.as_str(), "second");
+        let old = lazy.replace("third".to_owned()).unwrap();
+        // we can still use the older value after it's been replaced
+        assert_eq!(old, "second");
+        assert_eq!(lazy.as_str(), "third");
+    }
+
+    #[test]
+    fn test_replaceable_lazy_replacement_is_visible_across_threads() {
+        use std::sync::mpsc;
+        static VALUE: ReplaceableLazy<u64, fn() -> u64> = ReplaceableLazy::new(|| 0);
+        assert_eq!(*VALUE, 0);
+        let (tx, rx) = mpsc::channel::<u64>();
+        let (acktx, ackrx) = mpsc::channel();
+        let reader = thread::spawn(move || {
+            // hold on to a reference from before the replacement
+            let before: &u64 = &VALUE;
+            for expected in rx {
+                assert_eq!(*VALUE, expected);
+                acktx.send(()).unwrap();
+            }
+            *before
+        });
+        for value in 1..=100 {
+            assert_eq!(VALUE.replace(value), Some(&(value - 1)));
+            tx.send(value).unwrap();
+            ackrx.recv().unwrap();
+        }
+        drop(tx);
+        assert_eq!(reader.join().unwrap(), 0);
+        assert_eq!(*VALUE, 100);
+    }
+
+    #[test]
+    fn test_replaceable_lazy_drops_retired_values() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+        struct Counted(Arc<AtomicUsize>);
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let drops = Arc::new(AtomicUsize::new(0));
+        let init_drops = drops.clone();
+        let lazy = ReplaceableLazy::new(move || Counted(init_drops.clone()));
+        let _init = &*lazy;
+        lazy.replace(Counted(drops.clone()));
+        lazy.replace(Counted(drops.clone()));
+        // nothing is freed until the lazy goes away
+        assert_eq!(drops.load(Ordering::SeqCst), 0);
+        drop(lazy);
+        assert_eq!(drops.load(Ordering::SeqCst), 3);
+    }
 );
@@ -99,14 +99,11 @@ impl<T> QuickLock<T> {
                 Ordering::SeqCst,
                 Ordering::Relaxed,
             );
-            match ret {
-                Ok(_) => break QLGuard::init(self),
-                Err(is_locked) => {
-                    if !is_locked {
-                        break QLGuard::init(self);
-                    }
-                }
+            if ret.is_ok() {
+                break QLGuard::init(self);
             }
+            // a weak exchange can fail even if the lock is free, so we can only ever take
+            // the lock by actually swapping the state in
             let_the_cpu_relax()
         }
     }
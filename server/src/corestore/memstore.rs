@@ -56,15 +56,16 @@
 
 use super::KeyspaceResult;
 use crate::corestore::array::Array;
+use crate::corestore::clock::{ClockRef, SystemClock};
 use crate::corestore::htable::Coremap;
 use crate::corestore::lock::{QLGuard, QuickLock};
 use crate::corestore::table::Table;
 use crate::corestore::SnapshotStatus;
 use crate::registry;
+use crate::registry::{LockHolder, LockKind, Recorded};
 use crate::SnapshotConfig;
-use core::borrow::Borrow;
-use core::hash::Hash;
 use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 #[sky_macros::array]
@@ -87,6 +88,33 @@ pub const SYSTEM: ObjectID = unsafe {
     Array::from_const(SYSTEM_ARRAY, 6)
 };
 
+/// Returns the key that the keyspace or table called `name` is kept under (and that its files
+/// are named after). This is the name itself, unless `caseinsensitive` is set: then its ASCII
+/// letters are lowercased, so that `Foo` and `foo` are the same entity. Since no entity can
+/// have a name longer than an [`ObjectID`], such names have no key
+pub fn entity_key(name: &[u8]) -> Option<ObjectID> {
+    if name.len() > 64 {
+        return None;
+    }
+    let mut key = unsafe {
+        // UNSAFE: we just checked the length
+        ObjectID::from_slice(name)
+    };
+    if registry::is_case_insensitive() {
+        key.make_ascii_lowercase();
+    }
+    Some(key)
+}
+
+/// Same as [`entity_key`], for a name that is already an [`ObjectID`]
+pub fn key_of(name: &ObjectID) -> ObjectID {
+    let mut key = name.clone();
+    if registry::is_case_insensitive() {
+        key.make_ascii_lowercase();
+    }
+    key
+}
+
 #[test]
 fn test_def_macro_sanity() {
     // just make sure our macro is working as expected
@@ -146,8 +174,14 @@ pub enum DdlError {
     NotReady,
     /// The target object is not empty
     NotEmpty,
+    /// The table is the default table of its keyspace
+    IsDefault,
     /// The DDL transaction failed
     DdlTransactionFailure,
+    /// The object is read-only (like an attached snapshot)
+    ReadOnly,
+    /// The keyspace of the object is archived
+    Archived,
 }
 
 #[derive(Debug)]
@@ -159,10 +193,16 @@ pub enum DdlError {
 pub struct Memstore {
     /// the keyspaces
     pub keyspaces: Coremap<ObjectID, Arc<Keyspace>>,
+    /// the snapshots that were attached with `ATTACHSNAP`, by their alias. These are read-only,
+    /// live only in memory and are never flushed or snapshotted (which is why they're kept out
+    /// of `keyspaces`)
+    pub attached: Coremap<ObjectID, Arc<Keyspace>>,
     /// the snapshot configuration
     pub snap_config: Option<SnapshotStatus>,
     /// A **virtual lock** on the preload file
     preload_lock: QuickLock<()>,
+    /// the clock that is used for anything that depends on the current time
+    clock: ClockRef,
 }
 
 impl Memstore {
@@ -170,8 +210,10 @@ impl Memstore {
     pub fn new_empty() -> Self {
         Self {
             keyspaces: Coremap::new(),
+            attached: Coremap::new(),
             snap_config: None,
             preload_lock: QuickLock::new(()),
+            clock: Arc::new(SystemClock),
         }
     }
     pub fn init_with_all(
@@ -180,12 +222,14 @@ impl Memstore {
     ) -> Self {
         Self {
             keyspaces,
+            attached: Coremap::new(),
             snap_config: if let SnapshotConfig::Enabled(pref) = snap_config {
-                Some(SnapshotStatus::new(pref.atmost))
+                Some(SnapshotStatus::new(pref.atmost).with_maxchain(pref.maxchain))
             } else {
                 None
             },
             preload_lock: QuickLock::new(()),
+            clock: Arc::new(SystemClock),
         }
     }
     /// Create a new in-memory table with the default keyspace and the default
@@ -214,22 +258,72 @@ impl Memstore {
                 n.true_if_insert(SYSTEM, Arc::new(Keyspace::empty()));
                 n
             },
+            attached: Coremap::new(),
             snap_config: None,
             preload_lock: QuickLock::new(()),
+            clock: Arc::new(SystemClock),
         }
     }
-    /// Get an atomic reference to a keyspace
+    /// Use `clock` instead of the system clock. This is meant for tests that need to control
+    /// the passage of time
+    #[cfg(test)]
+    pub fn with_clock(mut self, clock: ClockRef) -> Self {
+        self.clock = clock;
+        self
+    }
+    /// Get the clock
+    pub fn get_clock(&self) -> &ClockRef {
+        &self.clock
+    }
+    /// Get an atomic reference to a keyspace by its name (this also resolves attached
+    /// snapshots)
     pub fn get_keyspace_atomic_ref<Q>(&self, keyspace_identifier: &Q) -> Option<Arc<Keyspace>>
     where
-        ObjectID: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: AsRef<[u8]> + ?Sized,
     {
-        self.keyspaces.get(keyspace_identifier).map(|ns| ns.clone())
+        let ksid = self::entity_key(keyspace_identifier.as_ref())?;
+        self.keyspaces
+            .get(&ksid)
+            .or_else(|| self.attached.get(&ksid))
+            .map(|ns| ns.clone())
     }
-    /// Returns true if a new keyspace was created
+    /// Returns true if a new keyspace was created. It is kept under the [key](entity_key) of
+    /// `keyspace_identifier`, but keeps the name as it was given
     pub fn create_keyspace(&self, keyspace_identifier: ObjectID) -> bool {
-        self.keyspaces
-            .true_if_insert(keyspace_identifier, Arc::new(Keyspace::empty()))
+        let ksid = self::key_of(&keyspace_identifier);
+        let ks = Keyspace::empty().with_name(&ksid, keyspace_identifier);
+        // an attached snapshot holds the name
+        !self.attached.contains_key(&ksid) && self.keyspaces.true_if_insert(ksid, Arc::new(ks))
+    }
+    /// Attach the (read-only) keyspace `ks` as `alias`. Returns false if there already is a
+    /// keyspace or an attached snapshot with that name
+    ///
+    /// **Trip switch handled:** Not needed (attached snapshots are never flushed)
+    pub fn attach_keyspace(&self, alias: ObjectID, ks: Keyspace) -> bool {
+        let ksid = self::key_of(&alias);
+        let ks = ks.with_name(&ksid, alias);
+        !self.keyspaces.contains_key(&ksid) && self.attached.true_if_insert(ksid, Arc::new(ks))
+    }
+    /// Detach the attached snapshot `alias`, only if no one is using it or any of its tables
+    /// (for the same reasons as [`Self::force_drop_keyspace`])
+    pub fn detach_keyspace(&self, alias: ObjectID) -> KeyspaceResult<()> {
+        match self.attached.mut_entry(alias) {
+            Some(keyspace) => {
+                let not_in_use = Arc::strong_count(keyspace.get()) == 1
+                    && keyspace
+                        .get()
+                        .tables
+                        .iter()
+                        .all(|table| Arc::strong_count(table.value()) == 1);
+                if not_in_use {
+                    keyspace.remove_entry();
+                    Ok(())
+                } else {
+                    Err(DdlError::StillInUse)
+                }
+            }
+            None => Err(DdlError::ObjectNotFound),
+        }
     }
     /// Drop a keyspace only if it is empty and has no clients connected to it
     ///
@@ -241,6 +335,9 @@ impl Memstore {
     pub fn drop_keyspace(&self, ksid: ObjectID) -> KeyspaceResult<()> {
         if ksid.eq(&SYSTEM) || ksid.eq(&DEFAULT) {
             Err(DdlError::ProtectedObject)
+        } else if self.attached.contains_key(&ksid) {
+            // that's what DETACHSNAP is for
+            Err(DdlError::ReadOnly)
         } else if !self.keyspaces.contains_key(&ksid) {
             Err(DdlError::ObjectNotFound)
         } else {
@@ -279,6 +376,8 @@ impl Memstore {
     pub fn force_drop_keyspace(&self, ksid: ObjectID) -> KeyspaceResult<()> {
         if ksid.eq(&SYSTEM) || ksid.eq(&DEFAULT) {
             Err(DdlError::ProtectedObject)
+        } else if self.attached.contains_key(&ksid) {
+            Err(DdlError::ReadOnly)
         } else if !self.keyspaces.contains_key(&ksid) {
             Err(DdlError::ObjectNotFound)
         } else {
@@ -324,6 +423,17 @@ pub struct Keyspace {
     replication_strategy: cluster::ReplicationStrategy,
     /// A **virtual lock** on the partmap for this keyspace
     partmap_lock: QuickLock<()>,
+    /// the table that a `USE` of just the keyspace switches to (if it exists)
+    default_table: QuickLock<ObjectID>,
+    /// set for attached snapshots, whose tables can't be created, dropped or written to
+    read_only: bool,
+    /// set while the keyspace is archived (see [`Self::is_archived`])
+    archived: AtomicBool,
+    /// the name of the keyspace as it was created, if that isn't its key (see [`entity_key`])
+    name: Option<ObjectID>,
+    /// the names of the tables as they were created, for the tables whose names aren't their
+    /// keys
+    table_names: Coremap<ObjectID, ObjectID>,
 }
 
 #[cfg(test)]
@@ -340,11 +450,19 @@ impl Keyspace {
             tables: {
                 let ht = Coremap::new();
                 // add the default table
-                ht.true_if_insert(DEFAULT, Arc::new(Table::new_default_kve()));
+                ht.true_if_insert(
+                    DEFAULT,
+                    Arc::new(Table::new_default_kve().with_entity(&DEFAULT, &DEFAULT)),
+                );
                 ht
             },
             replication_strategy: cluster::ReplicationStrategy::default(),
             partmap_lock: QuickLock::new(()),
+            default_table: QuickLock::new(DEFAULT),
+            read_only: false,
+            archived: AtomicBool::new(false),
+            name: None,
+            table_names: Coremap::new(),
         }
     }
     pub fn init_with_all_def_strategy(tables: Coremap<ObjectID, Arc<Table>>) -> Self {
@@ -352,6 +470,11 @@ impl Keyspace {
             tables,
             replication_strategy: cluster::ReplicationStrategy::default(),
             partmap_lock: QuickLock::new(()),
+            default_table: QuickLock::new(DEFAULT),
+            read_only: false,
+            archived: AtomicBool::new(false),
+            name: None,
+            table_names: Coremap::new(),
         }
     }
     /// Create a new empty keyspace with zero tables
@@ -360,22 +483,130 @@ impl Keyspace {
             tables: Coremap::new(),
             replication_strategy: cluster::ReplicationStrategy::default(),
             partmap_lock: QuickLock::new(()),
+            default_table: QuickLock::new(DEFAULT),
+            read_only: false,
+            archived: AtomicBool::new(false),
+            name: None,
+            table_names: Coremap::new(),
+        }
+    }
+    /// Remember `name` as the name of the keyspace if it isn't its key `ksid` (see
+    /// [`entity_key`])
+    pub fn with_name(mut self, ksid: &ObjectID, name: ObjectID) -> Self {
+        if name != *ksid {
+            self.name = Some(name);
+        }
+        self
+    }
+    /// Returns the name of the keyspace as it was created, if that isn't its key
+    pub fn name(&self) -> Option<&ObjectID> {
+        self.name.as_ref()
+    }
+    /// Returns the name of the table `tblid` as it was created (see [`entity_key`])
+    pub fn table_name(&self, tblid: &ObjectID) -> ObjectID {
+        match self.table_names.get(tblid) {
+            Some(name) => name.clone(),
+            None => tblid.clone(),
+        }
+    }
+    /// Remember `name` as the name of the table `tblid` if it isn't its key
+    pub fn set_table_name(&self, tblid: ObjectID, name: ObjectID) {
+        if name != tblid {
+            self.table_names.upsert(tblid, name);
+        }
+    }
+    /// Make the keyspace read-only. Its tables should be read-only as well
+    /// (see [`Table::with_read_only`])
+    pub fn with_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+    /// Returns true if the keyspace can't be changed (see [`Self::with_read_only`])
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    /// Returns true if the keyspace is archived: its tables are only on disk, reject every
+    /// action with `err-keyspace-archived` and are skipped by the flushes until the keyspace
+    /// is unarchived. Tables can't be created in it, dropped from it or made its default
+    pub fn is_archived(&self) -> bool {
+        self.archived.load(Ordering::Acquire)
+    }
+    /// Mark the keyspace and every one of its tables as archived (or not). The flag is kept
+    /// in the `PARTMAP` (see [`BYTEMARK_STORAGE_FLAG_ARCHIVED`](crate::storage::bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED)), so it is
+    /// persisted with the next write of the `PARTMAP`
+    pub fn set_archived(&self, archived: bool) {
+        self.archived.store(archived, Ordering::Release);
+        for table in self.tables.iter() {
+            table.value().set_archived(archived);
+        }
+    }
+    /// Returns true if any table of this keyspace is referenced from outside the keyspace,
+    /// like by a connection that has it as its current table
+    pub fn tables_in_use(&self) -> bool {
+        self.tables
+            .iter()
+            .any(|table| Arc::strong_count(table.value()) != 1)
+    }
+    /// Swap every table of this keyspace for its archived copy (see
+    /// [`Table::archived_copy`]), dropping what the tables held in memory. The tables have to
+    /// be flushed first and none of them can be in use (see [`Self::tables_in_use`])
+    pub fn swap_in_archived_copies(&self, ksid: &ObjectID) {
+        let copies: Vec<(ObjectID, Table)> = self
+            .tables
+            .iter()
+            .map(|table| {
+                (
+                    table.key().clone(),
+                    table.value().archived_copy(ksid, table.key()),
+                )
+            })
+            .collect();
+        for (tblid, copy) in copies {
+            self.tables.upsert(tblid, Arc::new(copy));
         }
     }
     pub fn table_count(&self) -> usize {
         self.tables.len()
     }
-    /// Get an atomic reference to a table in this keyspace if it exists
+    /// Returns the ID of the default table of this keyspace (`default` unless it was changed)
+    pub fn default_table(&self) -> ObjectID {
+        self.default_table.lock().clone()
+    }
+    /// Get an atomic reference to the default table of this keyspace if it exists
+    pub fn get_default_table(&self) -> Option<Arc<Table>> {
+        let default_table = self.default_table.lock();
+        self.get_table_atomic_ref(&*default_table)
+    }
+    /// Make `tblid` the default table of this keyspace. The table has to exist
+    pub fn set_default_table(&self, tblid: ObjectID) -> KeyspaceResult<()> {
+        let tblid = self::key_of(&tblid);
+        // drop_table holds this too, so the table can't go away in between
+        let mut default_table = self.default_table.lock();
+        if self.tables.contains_key(&tblid) {
+            *default_table = tblid;
+            Ok(())
+        } else {
+            Err(DdlError::ObjectNotFound)
+        }
+    }
+    /// Get an atomic reference to a table in this keyspace by its name, if it exists
     pub fn get_table_atomic_ref<Q>(&self, table_identifier: &Q) -> Option<Arc<Table>>
     where
-        ObjectID: Borrow<Q>,
-        Q: Hash + Eq + PartialEq<ObjectID> + ?Sized,
+        Q: AsRef<[u8]> + ?Sized,
     {
-        self.tables.get(table_identifier).map(|v| v.clone())
+        let tblid = self::entity_key(table_identifier.as_ref())?;
+        self.tables.get(&tblid).map(|v| v.clone())
     }
-    /// Create a new table
+    /// Create a new table. It is kept under the [key](entity_key) of `tableid`, but keeps the
+    /// name as it was given
     pub fn create_table(&self, tableid: ObjectID, table: Table) -> bool {
-        self.tables.true_if_insert(tableid, Arc::new(table))
+        let tblid = self::key_of(&tableid);
+        if self.tables.true_if_insert(tblid.clone(), Arc::new(table)) {
+            self.set_table_name(tblid, tableid);
+            true
+        } else {
+            false
+        }
     }
     /// Drop a table if it exists, if it is not forbidden and if no one references
     /// back to it. We don't want any looming table references i.e table gets deleted
@@ -386,22 +617,30 @@ impl Keyspace {
     /// **Trip switch handled:** Yes
     pub fn drop_table<Q>(&self, table_identifier: &Q) -> KeyspaceResult<()>
     where
-        ObjectID: Borrow<Q>,
-        Q: Hash + Eq + PartialEq<ObjectID> + ?Sized,
+        Q: AsRef<[u8]> + ?Sized,
     {
-        if table_identifier.eq(&DEFAULT) {
+        let tblid = match self::entity_key(table_identifier.as_ref()) {
+            Some(tblid) => tblid,
+            None => return Err(DdlError::ObjectNotFound),
+        };
+        // hold this so that the table can't be made the default while we remove it
+        let default_table = self.default_table.lock();
+        if tblid.eq(&DEFAULT) {
             Err(DdlError::ProtectedObject)
-        } else if !self.tables.contains_key(table_identifier) {
+        } else if tblid.eq(&*default_table) {
+            Err(DdlError::IsDefault)
+        } else if !self.tables.contains_key(&tblid) {
             Err(DdlError::ObjectNotFound)
         } else {
             // has table
-            let did_remove =
-                self.tables
-                    .true_remove_if(table_identifier, |_table_id, table_atomic_ref| {
-                        // 1 because this should just be us, the one instance
-                        Arc::strong_count(table_atomic_ref) == 1
-                    });
+            let did_remove = self
+                .tables
+                .true_remove_if(&tblid, |_table_id, table_atomic_ref| {
+                    // 1 because this should just be us, the one instance
+                    Arc::strong_count(table_atomic_ref) == 1
+                });
             if did_remove {
+                self.table_names.remove(&tblid);
                 // we need to re-init tree; so trip
                 registry::get_preload_tripswitch().trip();
                 Ok(())
@@ -415,10 +654,11 @@ impl Keyspace {
     pub unsafe fn force_remove_table(&self, tblid: &ObjectID) {
         // atomic remember? nobody cares about the result
         self.tables.remove(tblid);
+        self.table_names.remove(tblid);
     }
 
-    pub fn lock_partmap(&self) -> QLGuard<'_, ()> {
-        self.partmap_lock.lock()
+    pub fn lock_partmap(&self) -> Recorded<QLGuard<'_, ()>> {
+        Recorded::new(LockKind::Partmap, LockHolder::Ddl, self.partmap_lock.lock())
     }
 }
 
@@ -462,3 +702,32 @@ fn test_keyspace_try_delete_protected_table() {
         DdlError::ProtectedObject
     );
 }
+
+#[test]
+fn test_keyspace_try_delete_default_table() {
+    let our_keyspace = Keyspace::empty_default();
+    assert!(our_keyspace.create_table(
+        unsafe_objectid_from_slice!("apps"),
+        Table::new_default_kve()
+    ));
+    assert_eq!(
+        our_keyspace
+            .set_default_table(unsafe_objectid_from_slice!("nope"))
+            .unwrap_err(),
+        DdlError::ObjectNotFound
+    );
+    our_keyspace
+        .set_default_table(unsafe_objectid_from_slice!("apps"))
+        .unwrap();
+    assert_eq!(
+        our_keyspace
+            .drop_table(&unsafe_objectid_from_slice!("apps"))
+            .unwrap_err(),
+        DdlError::IsDefault
+    );
+    // once the default is moved, the table can go
+    our_keyspace.set_default_table(DEFAULT).unwrap();
+    assert!(our_keyspace
+        .drop_table(&unsafe_objectid_from_slice!("apps"))
+        .is_ok());
+}
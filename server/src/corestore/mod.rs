@@ -24,35 +24,42 @@
  *
 */
 
-use crate::corestore::lock::QLGuard;
+use crate::corestore::memstore;
 use crate::corestore::memstore::DdlError;
 use crate::corestore::memstore::Keyspace;
 use crate::corestore::memstore::Memstore;
 use crate::corestore::memstore::ObjectID;
 use crate::corestore::memstore::DEFAULT;
+use crate::corestore::memstore::SYSTEM;
+use crate::corestore::snaplock::{SnapGuard, SnapHolder, SnapLock};
 use crate::corestore::table::Table;
 use crate::dbnet::connection::ProtocolConnectionExt;
+use crate::diskstore::snapshot::DEF_MAX_CHAIN;
 use crate::kvengine::KVEngine;
 use crate::protocol::Query;
 use crate::queryengine;
 use crate::registry;
+use crate::registry::LockHolder;
 use crate::storage;
+use crate::storage::error::StorageResult;
+use crate::util::fmt_key_safe;
 use crate::util::Unwrappable;
-use crate::IoResult;
 use crate::SnapshotConfig;
-use core::borrow::Borrow;
-use core::hash::Hash;
 pub use htable::Data;
 use libsky::TResult;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
 pub mod array;
 pub mod buffers;
+pub mod clock;
+pub mod hasher;
 pub mod htable;
 pub mod iarray;
 pub mod lazy;
 pub mod lock;
 pub mod memstore;
+pub mod object;
+pub mod snaplock;
 pub mod table;
 #[cfg(test)]
 mod tests;
@@ -102,7 +109,8 @@ impl<'a> From<BorrowedEntityGroupRaw<'a>> for BorrowedEntityGroup<'a> {
 pub struct Corestore {
     /// the default keyspace for this instance of the object
     cks: Option<Arc<Keyspace>>,
-    /// the current table for this instance of the object
+    /// the current table for this instance of the object. Since we hold a reference to it,
+    /// the table can't be dropped (by anyone) while it is in use here
     ctable: Option<Arc<Table>>,
     /// an atomic reference to the actual backing storage
     store: Arc<Memstore>,
@@ -110,7 +118,7 @@ pub struct Corestore {
 
 /// The status and details of the snapshotting service
 ///
-/// The in_progress field is kept behind a mutex to ensure only one snapshot
+/// The in_progress field is a [lock](SnapLock) that ensures only one snapshot
 /// operation can run at a time. Although on the server side this isn't a problem
 /// because we don't have multiple snapshot tasks, but can be an issue when external
 /// snapshots are triggered, for example via `MKSNAP`
@@ -118,8 +126,10 @@ pub struct Corestore {
 pub struct SnapshotStatus {
     /// The maximum number of recent snapshots to keep
     pub max: usize,
+    /// The maximum number of incremental snapshots in a chain
+    pub maxchain: usize,
     /// The current state of the snapshot service
-    pub in_progress: lock::QuickLock<()>,
+    pub in_progress: SnapLock,
 }
 
 impl SnapshotStatus {
@@ -127,13 +137,18 @@ impl SnapshotStatus {
     pub fn new(max: usize) -> Self {
         SnapshotStatus {
             max,
-            in_progress: lock::QuickLock::new(()),
+            maxchain: DEF_MAX_CHAIN,
+            in_progress: SnapLock::new(),
         }
     }
+    /// Allow up to `maxchain` incremental snapshots in a chain
+    pub fn with_maxchain(self, maxchain: usize) -> Self {
+        SnapshotStatus { maxchain, ..self }
+    }
 
-    /// Lock the snapshot service
-    pub fn lock_snap(&self) -> lock::QLGuard<'_, ()> {
-        self.in_progress.lock()
+    /// Lock the snapshot service for `holder`
+    pub fn lock_snap(&self, holder: SnapHolder) -> SnapGuard<'_> {
+        self.in_progress.lock(holder)
     }
 
     /// Check if the snapshot service is busy
@@ -144,14 +159,15 @@ impl SnapshotStatus {
 
 impl Corestore {
     /// This is the only function you'll ever need to either create a new database instance
-    /// or restore from an earlier instance
-    pub fn init_with_snapcfg(snapcfg: &SnapshotConfig) -> IoResult<Self> {
-        let store = storage::unflush::read_full(snapcfg)?;
+    /// or restore from an earlier instance. If `lazy` is set, the data of the tables is only
+    /// read on first access
+    pub fn init_with_snapcfg(snapcfg: &SnapshotConfig, lazy: bool) -> StorageResult<Self> {
+        let store = storage::unflush::read_full(snapcfg, lazy)?;
         Ok(Self::default_with_store(store))
     }
-    pub fn lock_snap(&self) -> QLGuard<'_, ()> {
+    pub fn lock_snap(&self, holder: SnapHolder) -> SnapGuard<'_> {
         match &self.store.snap_config {
-            Some(lck) => lck.lock_snap(),
+            Some(lck) => lck.lock_snap(holder),
             None => unsafe { impossible!() },
         }
     }
@@ -180,14 +196,14 @@ impl Corestore {
     /// false is returned. Else true is returned
     pub fn swap_entity(&mut self, entity: BorrowedEntityGroup) -> KeyspaceResult<()> {
         match entity {
-            // Switch to the provided keyspace
+            // Switch to the provided keyspace and to its default table, if it has one
             BorrowedEntityGroup {
                 va: Some(ks),
                 vb: None,
             } => match self.store.get_keyspace_atomic_ref(ks) {
                 Some(ksref) => {
+                    self.ctable = ksref.get_default_table();
                     self.cks = Some(ksref);
-                    self.ctable = None;
                 }
                 None => return Err(DdlError::ObjectNotFound),
             },
@@ -208,14 +224,14 @@ impl Corestore {
     }
     pub fn get_keyspace<Q>(&self, ksid: &Q) -> Option<Arc<Keyspace>>
     where
-        ObjectID: Borrow<Q>,
-        Q: Hash + Eq + ?Sized,
+        Q: AsRef<[u8]> + ?Sized,
     {
         self.store.get_keyspace_atomic_ref(ksid)
     }
-    /// Get an atomic reference to a table
+    /// Get an atomic reference to a table. The tables of an archived keyspace aren't handed
+    /// out ([`DdlError::Archived`] is returned instead)
     pub fn get_table(&self, entity: BorrowedEntityGroup) -> KeyspaceResult<Arc<Table>> {
-        match entity {
+        let tbl = match entity {
             BorrowedEntityGroup {
                 va: Some(ksid),
                 vb: Some(table),
@@ -237,25 +253,93 @@ impl Corestore {
                 None => Err(DdlError::DefaultNotFound),
             },
             _ => unsafe { impossible!() },
+        }?;
+        if tbl.is_archived() {
+            Err(DdlError::Archived)
+        } else {
+            Ok(tbl)
         }
     }
+    /// Returns the ID of the current keyspace
+    fn get_cks_id(&self) -> Option<ObjectID> {
+        let cks = self.cks.as_ref()?;
+        self.store
+            .keyspaces
+            .iter()
+            .find(|ks| Arc::ptr_eq(ks.value(), cks))
+            .map(|ks| ks.key().clone())
+    }
     pub fn get_ctable(&self) -> Option<Arc<Table>> {
         self.ctable.clone()
     }
+    /// Returns the current keyspace, if it is set
+    pub fn get_cks(&self) -> Option<Arc<Keyspace>> {
+        self.cks.clone()
+    }
 
     /// Get the key/value store
     ///
-    /// `Err`s are propagated if the target table has an incorrect table or if
-    /// the default table is unset
+    /// `Err`s are propagated if the target table has an incorrect table, if its data couldn't
+    /// be read in or if the default table is unset
     pub fn get_kvstore(&self) -> KeyspaceResult<&KVEngine> {
         match &self.ctable {
             Some(tbl) => match tbl.get_kvstore() {
                 Ok(kvs) => Ok(kvs),
+                Err(DdlError::NotReady) => Err(DdlError::NotReady),
+                Err(DdlError::Archived) => Err(DdlError::Archived),
                 _ => Err(DdlError::WrongModel),
             },
             None => Err(DdlError::DefaultNotFound),
         }
     }
+    /// Get the key/value store if the data of the current table is in memory (see
+    /// [`Table::loaded_kvstore`]). Since nothing is read in, this doesn't check if the table
+    /// was archived
+    pub fn get_loaded_kvstore(&self) -> Option<&KVEngine> {
+        self.ctable.as_ref().and_then(|tbl| tbl.loaded_kvstore())
+    }
+    /// Returns true if the current table definitely has none of `keys`, which is only known
+    /// without reading in its data (see [`Table::surely_lacks`]). If there's no current table,
+    /// this is false and the action reports that as usual
+    pub fn ctable_surely_lacks<K: AsRef<[u8]>>(&self, keys: &[K]) -> bool {
+        match &self.ctable {
+            Some(tbl) => keys.iter().all(|key| tbl.surely_lacks(key.as_ref())),
+            None => false,
+        }
+    }
+    /// Returns true if the current table can't be written to (see [`Table::is_read_only`])
+    pub fn ctable_is_read_only(&self) -> bool {
+        match &self.ctable {
+            Some(tbl) => tbl.is_read_only(),
+            None => false,
+        }
+    }
+    /// Returns true if the keyspace of the current table is archived (see
+    /// [`Table::is_archived`])
+    pub fn ctable_is_archived(&self) -> bool {
+        match &self.ctable {
+            Some(tbl) => tbl.is_archived(),
+            None => false,
+        }
+    }
+    /// Read in the data of the current table if it hasn't been read in yet (see
+    /// [`Table::wait_loaded`]). This returns false if it couldn't be read in
+    pub async fn ensure_ctable_loaded(&self) -> bool {
+        match &self.ctable {
+            Some(tbl) => tbl.wait_loaded().await,
+            None => true,
+        }
+    }
+    /// Stamp the reads and writes made on the current table since it was last stamped with
+    /// the current time (see [`Activity::stamp`](crate::kvengine::stats::Activity::stamp))
+    pub fn stamp_activity(&self) {
+        if let Some(tbl) = &self.ctable {
+            let activity = tbl.activity();
+            if activity.is_touched() {
+                activity.stamp(self.store.get_clock().now().timestamp() as u64);
+            }
+        }
+    }
 
     pub fn is_snapshot_enabled(&self) -> bool {
         self.store.snap_config.is_some()
@@ -274,26 +358,39 @@ impl Corestore {
         entity: OwnedEntityGroup,
         modelcode: u8,
         volatile: bool,
+        ordered: bool,
+        cached: bool,
+        value_limit: Option<u64>,
     ) -> KeyspaceResult<()> {
         // first lock the global flush state
-        let flush_lock = registry::lock_flush_state();
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
         let ret;
         match entity {
             // Important: create table <tblname> is only ks
             (Some(tblid), None) => {
                 ret = match &self.cks {
+                    Some(ks) if ks.is_read_only() => Err(DdlError::ReadOnly),
+                    Some(ks) if ks.is_archived() => Err(DdlError::Archived),
                     Some(ks) => {
-                        let tbl = Table::from_model_code(modelcode, volatile);
-                        if let Some(tbl) = tbl {
-                            if ks.create_table(tblid, tbl) {
-                                // we need to re-init tree; so trip
-                                registry::get_preload_tripswitch().trip();
-                                Ok(())
-                            } else {
-                                Err(DdlError::AlreadyExists)
+                        let tbl = Table::from_model_code(modelcode, volatile, ordered);
+                        match (tbl, self.get_cks_id()) {
+                            (Some(tbl), Some(ksid)) => {
+                                let tbl = tbl
+                                    .with_entity(&ksid, &memstore::key_of(&tblid))
+                                    .with_created(self.store.get_clock().now())
+                                    .with_value_limit(value_limit)
+                                    .with_read_cache(cached);
+                                if ks.create_table(tblid, tbl) {
+                                    // we need to re-init tree; so trip
+                                    registry::get_preload_tripswitch().trip();
+                                    Ok(())
+                                } else {
+                                    Err(DdlError::AlreadyExists)
+                                }
                             }
-                        } else {
-                            Err(DdlError::WrongModel)
+                            (None, _) => Err(DdlError::WrongModel),
+                            // the current keyspace was dropped from under us
+                            (Some(_), None) => Err(DdlError::ObjectNotFound),
                         }
                     }
                     None => Err(DdlError::DefaultNotFound),
@@ -301,9 +398,16 @@ impl Corestore {
             }
             (Some(ksid), Some(tblid)) => {
                 ret = match self.store.get_keyspace_atomic_ref(&ksid) {
+                    Some(kspace) if kspace.is_read_only() => Err(DdlError::ReadOnly),
+                    Some(kspace) if kspace.is_archived() => Err(DdlError::Archived),
                     Some(kspace) => {
-                        let tbl = Table::from_model_code(modelcode, volatile);
+                        let tbl = Table::from_model_code(modelcode, volatile, ordered);
                         if let Some(tbl) = tbl {
+                            let tbl = tbl
+                                .with_entity(&memstore::key_of(&ksid), &memstore::key_of(&tblid))
+                                .with_created(self.store.get_clock().now())
+                                .with_value_limit(value_limit)
+                                .with_read_cache(cached);
                             if kspace.create_table(tblid, tbl) {
                                 // trip the preload switch
                                 registry::get_preload_tripswitch().trip();
@@ -325,25 +429,58 @@ impl Corestore {
         ret
     }
 
-    /// Drop a table
+    /// Drop a table, along with its files (see [`storage::flush::drop_table`]). Like creating
+    /// a table, this happens under the global flush lock. If the files couldn't be removed,
+    /// the table is still dropped from memory (and the next flush leaves it out) but
+    /// [`DdlError::DdlTransactionFailure`] is returned
+    ///
+    /// **Trip switch handled:** Yes
     pub fn drop_table(&self, entity: BorrowedEntityGroup) -> KeyspaceResult<()> {
-        match entity {
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
+        let (ks, ksid, tblid) = match entity {
             BorrowedEntityGroup {
                 va: Some(tblid),
                 vb: None,
             } => match &self.cks {
-                Some(ks) => ks.drop_table(tblid),
-                None => Err(DdlError::DefaultNotFound),
+                Some(ks) => (ks.clone(), self.get_cks_id(), tblid),
+                None => return Err(DdlError::DefaultNotFound),
             },
             BorrowedEntityGroup {
                 va: Some(ksid),
                 vb: Some(tblid),
             } => match self.store.get_keyspace_atomic_ref(ksid) {
-                Some(ks) => ks.drop_table(tblid),
-                None => Err(DdlError::ObjectNotFound),
+                Some(ks) => (ks, memstore::entity_key(ksid), tblid),
+                None => return Err(DdlError::ObjectNotFound),
             },
             _ => unsafe { impossible!() },
+        };
+        if ks.is_read_only() {
+            return Err(DdlError::ReadOnly);
         }
+        if ks.is_archived() {
+            return Err(DdlError::Archived);
+        }
+        ks.drop_table(tblid)?;
+        let ret = match ksid {
+            // nothing was ever written in sandbox mode
+            Some(_) if registry::is_sandbox() => Ok(()),
+            Some(ksid) => {
+                let tblid = memstore::key_of(&unsafe { ObjectID::from_slice(tblid) });
+                storage::flush::drop_table(&ksid, &tblid, &ks).map_err(|e| {
+                    log::error!(
+                        "Failed to remove the files of the dropped table '{}:{}': {}",
+                        fmt_key_safe(&ksid),
+                        fmt_key_safe(&tblid),
+                        e
+                    );
+                    DdlError::DdlTransactionFailure
+                })
+            }
+            // the current keyspace was dropped from under us, along with its files
+            None => Ok(()),
+        };
+        drop(flush_lock);
+        ret
     }
 
     /// Create a keyspace **without any transactional guarantees**
@@ -351,7 +488,7 @@ impl Corestore {
     /// **Trip switch handled:** Yes
     pub fn create_keyspace(&self, ksid: ObjectID) -> KeyspaceResult<()> {
         // lock the global flush lock (see comment in create_table to know why)
-        let flush_lock = registry::lock_flush_state();
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
         let ret = if self.store.create_keyspace(ksid) {
             // woo, created
             // trip the preload switch
@@ -365,16 +502,147 @@ impl Corestore {
         ret
     }
 
-    /// Drop a keyspace
+    /// Drop a keyspace, along with its directory (see [`storage::flush::drop_keyspace`]).
+    /// Errors are handled just like in [`Self::drop_table`]
     pub fn drop_keyspace(&self, ksid: ObjectID) -> KeyspaceResult<()> {
+        let ksid = memstore::key_of(&ksid);
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
         // trip switch is handled by memstore here
-        self.store.drop_keyspace(ksid)
+        let ret = self
+            .store
+            .drop_keyspace(ksid.clone())
+            .and_then(|()| self.drop_keyspace_files(&ksid));
+        drop(flush_lock);
+        ret
     }
 
-    /// Force drop a keyspace
+    /// Force drop a keyspace (and its tables), along with its directory
     pub fn force_drop_keyspace(&self, ksid: ObjectID) -> KeyspaceResult<()> {
+        let ksid = memstore::key_of(&ksid);
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
         // trip switch is handled by memstore here
-        self.store.force_drop_keyspace(ksid)
+        let ret = self
+            .store
+            .force_drop_keyspace(ksid.clone())
+            .and_then(|()| self.drop_keyspace_files(&ksid));
+        drop(flush_lock);
+        ret
+    }
+
+    /// Attach the read-only keyspace `ks` (read from a snapshot) as `alias`. This holds the
+    /// global flush lock so that it can't race with a keyspace of the same name being created
+    pub fn attach_keyspace(&self, alias: ObjectID, ks: Keyspace) -> KeyspaceResult<()> {
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
+        let ret = if self.store.attach_keyspace(alias, ks) {
+            Ok(())
+        } else {
+            Err(DdlError::AlreadyExists)
+        };
+        drop(flush_lock);
+        ret
+    }
+
+    /// Detach the snapshot that was attached as `alias`. It has no files to remove
+    pub fn detach_keyspace(&self, alias: ObjectID) -> KeyspaceResult<()> {
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
+        let ret = self.store.detach_keyspace(memstore::key_of(&alias));
+        drop(flush_lock);
+        ret
+    }
+
+    /// Archive a keyspace: its tables are flushed and then swapped for their archived copies
+    /// (see [`Keyspace::swap_in_archived_copies`]), which leave the data on disk. Like dropping
+    /// a table, this happens under the global flush lock and only if none of the tables are
+    /// in use, since whoever has one could otherwise go on writing to a table that is no
+    /// longer in the keyspace. If the flush fails, the keyspace is left as it was
+    pub fn archive_keyspace(&self, ksid: ObjectID) -> KeyspaceResult<()> {
+        let ksid = memstore::key_of(&ksid);
+        if ksid.eq(&SYSTEM) || ksid.eq(&DEFAULT) {
+            return Err(DdlError::ProtectedObject);
+        }
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
+        let ret = match self.store.get_keyspace_atomic_ref(&ksid) {
+            Some(ks) if ks.is_read_only() => Err(DdlError::ReadOnly),
+            // nothing to do
+            Some(ks) if ks.is_archived() => Ok(()),
+            Some(ks) => {
+                // from here on, the actions turn the tables away, so they can't be taken up
+                // once we've seen that they aren't in use
+                ks.set_archived(true);
+                if ks.tables_in_use() {
+                    ks.set_archived(false);
+                    Err(DdlError::StillInUse)
+                } else {
+                    match storage::flush::archive_keyspace(&ksid, &ks, &self.store) {
+                        Ok(()) => {
+                            ks.swap_in_archived_copies(&ksid);
+                            Ok(())
+                        }
+                        Err(e) => {
+                            log::error!(
+                                "Failed to flush the keyspace '{}' to archive it: {}",
+                                fmt_key_safe(&ksid),
+                                e
+                            );
+                            ks.set_archived(false);
+                            Err(DdlError::DdlTransactionFailure)
+                        }
+                    }
+                }
+            }
+            None => Err(DdlError::ObjectNotFound),
+        };
+        drop(flush_lock);
+        ret
+    }
+
+    /// Unarchive a keyspace. Its tables are read in on first access (see
+    /// [`Table::ensure_loaded`]) and its `PARTMAP` is written out right away, so that it is
+    /// still unarchived after a restart. If that fails, the keyspace is unarchived anyway (the
+    /// next flush writes the `PARTMAP`) but [`DdlError::DdlTransactionFailure`] is returned
+    pub fn unarchive_keyspace(&self, ksid: ObjectID) -> KeyspaceResult<()> {
+        let ksid = memstore::key_of(&ksid);
+        let flush_lock = registry::lock_flush_state(LockHolder::Ddl);
+        let ret = match self.store.get_keyspace_atomic_ref(&ksid) {
+            Some(ks) if !ks.is_archived() => Ok(()),
+            Some(ks) => {
+                ks.set_archived(false);
+                if registry::is_sandbox() {
+                    Ok(())
+                } else {
+                    let ks_path = unsafe {
+                        concat_str!(storage::interface::dir_ksroot(), "/", ksid.as_str())
+                    };
+                    storage::flush::oneshot::flush_partmap(&ksid, &ks)
+                        .and_then(|_| storage::interface::sync_dir(&ks_path))
+                        .map_err(|e| {
+                            log::error!(
+                                "Failed to write out the metadata of the unarchived keyspace '{}': {}",
+                                fmt_key_safe(&ksid),
+                                e
+                            );
+                            DdlError::DdlTransactionFailure
+                        })
+                }
+            }
+            None => Err(DdlError::ObjectNotFound),
+        };
+        drop(flush_lock);
+        ret
+    }
+
+    fn drop_keyspace_files(&self, ksid: &ObjectID) -> KeyspaceResult<()> {
+        if registry::is_sandbox() {
+            return Ok(());
+        }
+        storage::flush::drop_keyspace(ksid, &self.store).map_err(|e| {
+            log::error!(
+                "Failed to remove the directory of the dropped keyspace '{}': {}",
+                fmt_key_safe(ksid),
+                e
+            );
+            DdlError::DdlTransactionFailure
+        })
     }
 
     /// Execute a query that has already been validated by `Connection::read_query`
@@ -385,13 +653,19 @@ impl Corestore {
     {
         match query {
             Query::SimpleQuery(q) => {
+                con.write_request_id().await?;
                 con.write_simple_query_header().await?;
                 queryengine::execute_simple(self, con, q).await?;
-                con.flush_stream().await?;
+                con.finish_response().await?;
             }
             // TODO(@ohsayan): Pipeline commands haven't been implemented yet
             Query::PipelinedQuery(_) => unimplemented!(),
         }
+        if let Some(trace) = con.get_mut_trace().take() {
+            if let Some(record) = trace.finish(con.get_peer().id) {
+                registry::get_tracer().record(record);
+            }
+        }
         Ok(())
     }
     pub fn strong_count(&self) -> usize {
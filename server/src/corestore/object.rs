@@ -0,0 +1,94 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Object metadata
+//!
+//! Stored values expose what they are and how they're stored through [`ObjectMeta`], so that
+//! anything that introspects them (like `OBJECT` queries) doesn't need to know how a value is
+//! represented in a given model
+
+use crate::corestore::Data;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// The type of a value, as seen by clients
+pub enum ObjectType {
+    /// A binary string
+    BinStr,
+    /// A unicode string
+    Str,
+}
+
+impl ObjectType {
+    /// Returns the name of this type (the one used in table descriptions)
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::BinStr => "binstr",
+            Self::Str => "str",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// How a value is laid out in storage
+pub enum ObjectEncoding {
+    /// The value is stored as is
+    Raw,
+}
+
+impl ObjectEncoding {
+    /// Returns the name of this encoding
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Raw => "raw",
+        }
+    }
+}
+
+/// Metadata that a stored value exposes about itself
+pub trait ObjectMeta {
+    /// Returns how this value is laid out in storage
+    fn encoding(&self) -> ObjectEncoding;
+    /// Returns the number of bytes used to store this value
+    fn stored_size(&self) -> usize;
+}
+
+impl ObjectMeta for Data {
+    fn encoding(&self) -> ObjectEncoding {
+        ObjectEncoding::Raw
+    }
+    fn stored_size(&self) -> usize {
+        self.len()
+    }
+}
+
+#[test]
+fn test_data_object_meta() {
+    let data = Data::from("hello");
+    assert_eq!(data.encoding(), ObjectEncoding::Raw);
+    assert_eq!(data.encoding().as_str(), "raw");
+    assert_eq!(data.stored_size(), 5);
+    assert_eq!(Data::from("").stored_size(), 0);
+}
@@ -0,0 +1,227 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The snapshot lock
+//!
+//! Only one snapshot (or anything else that has to see the snapshot directory or the data
+//! files as a whole) can run at a time. The [`SnapLock`] makes sure of it and also records
+//! who holds it (and since when) and how long everyone had to wait for it, which
+//! `SYS SNAPSTATE` reports.
+//!
+//! The lock is only ever released by dropping the [`SnapGuard`], so that a snapshot that
+//! fails (or panics) partway can't leave it held. It is also recorded in the
+//! [lock registry](crate::registry::get_locks) for `SYS LOCKS`
+
+use super::lock::{QLGuard, QuickLock};
+use crate::registry::{self, LockHolder, LockKind, LockRecord};
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Who holds the snapshot lock
+pub enum SnapHolder {
+    /// The snapshot service, creating a snapshot or removing expired ones
+    Scheduler,
+    /// A client's `MKSNAP`
+    Mksnap,
+    /// A client's `SYS COMPACT`, which rewrites the data files
+    Compact,
+}
+
+impl SnapHolder {
+    /// Returns the name of the holder, as reported by `SYS SNAPSTATE`
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Scheduler => "scheduler",
+            Self::Mksnap => "mksnap",
+            Self::Compact => "compact",
+        }
+    }
+}
+
+impl From<SnapHolder> for LockHolder {
+    fn from(holder: SnapHolder) -> Self {
+        match holder {
+            SnapHolder::Scheduler => Self::Scheduler,
+            SnapHolder::Mksnap => Self::Mksnap,
+            SnapHolder::Compact => Self::Compact,
+        }
+    }
+}
+
+#[derive(Debug)]
+/// The snapshot lock, along with who holds it and the time spent waiting for it
+pub struct SnapLock {
+    lock: QuickLock<()>,
+    /// the current holder and when it got the lock
+    holder: QuickLock<Option<(SnapHolder, Instant)>>,
+    /// the number of times the lock was acquired
+    acquisitions: AtomicU64,
+    /// the total time spent waiting for the lock, in microseconds
+    waited_us: AtomicU64,
+}
+
+/// A guard for the [`SnapLock`]: the lock is released when this is dropped
+pub struct SnapGuard<'a> {
+    owner: &'a SnapLock,
+    _record: LockRecord<'static>,
+    _lock: QLGuard<'a, ()>,
+}
+
+impl SnapLock {
+    pub const fn new() -> Self {
+        Self {
+            lock: QuickLock::new(()),
+            holder: QuickLock::new(None),
+            acquisitions: AtomicU64::new(0),
+            waited_us: AtomicU64::new(0),
+        }
+    }
+    /// Acquire the lock for `holder`. This is blocking (it spins until the lock is free)
+    pub fn lock(&self, holder: SnapHolder) -> SnapGuard<'_> {
+        let start = Instant::now();
+        let lock = self.lock.lock();
+        let now = Instant::now();
+        self.waited_us
+            .fetch_add((now - start).as_micros() as u64, Ordering::Relaxed);
+        self.acquisitions.fetch_add(1, Ordering::Relaxed);
+        *self.holder.lock() = Some((holder, now));
+        SnapGuard {
+            owner: self,
+            _record: registry::get_locks().record(LockKind::Snapshot, holder.into()),
+            _lock: lock,
+        }
+    }
+    /// Check if the lock is held
+    pub fn is_locked(&self) -> bool {
+        self.lock.is_locked()
+    }
+    /// Returns the current holder and for how long it has held the lock
+    pub fn holder(&self) -> Option<(SnapHolder, Duration)> {
+        let holder = *self.holder.lock();
+        holder.map(|(holder, since)| (holder, since.elapsed()))
+    }
+    /// Returns the number of times the lock was acquired
+    pub fn acquisitions(&self) -> u64 {
+        self.acquisitions.load(Ordering::Relaxed)
+    }
+    /// Returns the total time spent waiting for the lock
+    pub fn waited(&self) -> Duration {
+        Duration::from_micros(self.waited_us.load(Ordering::Relaxed))
+    }
+}
+
+impl<'a> Drop for SnapGuard<'a> {
+    fn drop(&mut self) {
+        // the holder is cleared before the lock itself is released (when the fields are
+        // dropped), so that the next holder's record can't be cleared by us
+        *self.owner.holder.lock() = None;
+    }
+}
+
+#[test]
+fn test_snap_lock_records_its_holder() {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    let lock = Arc::new(SnapLock::new());
+    assert_eq!(lock.holder(), None);
+    let (locked, wait_for_lock) = mpsc::channel();
+    let (release, wait_for_release) = mpsc::channel::<()>();
+    let holder = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _guard = lock.lock(SnapHolder::Mksnap);
+            locked.send(()).unwrap();
+            wait_for_release.recv().unwrap();
+            thread::sleep(Duration::from_millis(20));
+        })
+    };
+    wait_for_lock.recv().unwrap();
+    assert!(lock.is_locked());
+    assert!(matches!(lock.holder(), Some((SnapHolder::Mksnap, _))));
+    // this has to wait until the other thread is done
+    release.send(()).unwrap();
+    {
+        let _guard = lock.lock(SnapHolder::Compact);
+        assert!(matches!(lock.holder(), Some((SnapHolder::Compact, _))));
+    }
+    holder.join().unwrap();
+    assert!(!lock.is_locked());
+    assert_eq!(lock.holder(), None);
+    assert_eq!(lock.acquisitions(), 2);
+    assert!(lock.waited() >= Duration::from_millis(10));
+}
+
+#[test]
+fn test_snap_lock_is_released_on_a_panic() {
+    let lock = SnapLock::new();
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        let _guard = lock.lock(SnapHolder::Scheduler);
+        panic!("the snapshot failed");
+    }));
+    assert!(panicked.is_err());
+    assert!(!lock.is_locked());
+    assert_eq!(lock.holder(), None);
+}
+
+#[test]
+fn test_snap_lock_is_in_the_lock_registry() {
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    // other tests take snapshot locks too, but never for as long as this one does
+    let ours = |min: u64| {
+        registry::get_locks()
+            .held(Duration::from_millis(min))
+            .into_iter()
+            .filter(|held| held.kind == LockKind::Snapshot && held.holder == LockHolder::Mksnap)
+            .map(|held| held.held)
+            .max()
+    };
+    let lock = Arc::new(SnapLock::new());
+    let (locked, wait_for_lock) = mpsc::channel();
+    let (release, wait_for_release) = mpsc::channel::<()>();
+    // a slow snapshot, which holds the lock until it's told to finish
+    let snapshot = {
+        let lock = lock.clone();
+        thread::spawn(move || {
+            let _guard = lock.lock(SnapHolder::Mksnap);
+            locked.send(()).unwrap();
+            wait_for_release.recv().unwrap();
+        })
+    };
+    wait_for_lock.recv().unwrap();
+    thread::sleep(Duration::from_millis(100));
+    let first = ours(100).expect("the snapshot lock isn't recorded");
+    thread::sleep(Duration::from_millis(100));
+    let second = ours(200).expect("the snapshot lock isn't recorded");
+    assert!(second > first);
+    release.send(()).unwrap();
+    snapshot.join().unwrap();
+    assert_eq!(ours(200), None);
+    assert!(registry::get_locks().longest_recent(LockKind::Snapshot) >= second);
+}
@@ -26,10 +26,20 @@
 
 use crate::corestore::htable::Coremap;
 use crate::corestore::memstore::DdlError;
+use crate::corestore::memstore::ObjectID;
 use crate::corestore::Data;
 use crate::corestore::KeyspaceResult;
+use crate::kvengine::stats::Activity;
 use crate::kvengine::KVEngine;
+use crate::registry;
+use crate::storage::bloom::BloomFilter;
 use crate::storage::bytemarks;
+use crate::storage::error::StorageResult;
+use crate::storage::interface::ThreadDataDir;
+use crate::util::fmt_key_safe;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 
 #[derive(Debug)]
 pub enum DataModel {
@@ -45,11 +55,55 @@ pub struct Table {
     model_store: DataModel,
     /// is the table volatile
     volatile: bool,
+    /// when the table was created, if it was created since the server started
+    created: Option<DateTime<Utc>>,
+    /// where to read the data of the table from, if it was loaded from disk lazily (only its
+    /// metadata is read at boot, the data is read on first access)
+    pending: Option<PendingLoad>,
+    /// set for the tables of an attached snapshot, which can't be written to
+    read_only: bool,
+    /// set while the keyspace of the table is archived (see [`Self::is_archived`])
+    archived: AtomicBool,
+}
+
+#[derive(Debug)]
+/// The data of a table that is read in on first access
+struct PendingLoad {
+    ksid: ObjectID,
+    tblid: ObjectID,
+    /// set once the data has been read in
+    loaded: AtomicBool,
+    /// held while the data is being read in, so that a concurrent first access waits for the
+    /// load instead of reading the data in again
+    loading: Mutex<()>,
+    /// the bloom filter of the keys in the table's file, if it has a usable one
+    bloom: Option<BloomFilter>,
 }
 
 impl Table {
-    /// Get the key/value store if the table is a key/value store
-    pub const fn get_kvstore(&self) -> KeyspaceResult<&KVEngine> {
+    /// Get the key/value store if the table is a key/value store. If the data of the table
+    /// hasn't been read in yet, this blocks until it is and returns [`DdlError::NotReady`] if
+    /// it couldn't be read in. Async callers should call [`Self::wait_loaded`] first
+    pub fn get_kvstore(&self) -> KeyspaceResult<&KVEngine> {
+        if self.is_archived() {
+            return Err(DdlError::Archived);
+        }
+        if self.ensure_loaded().is_err() {
+            return Err(DdlError::NotReady);
+        }
+        self.kvstore()
+    }
+    /// Get the key/value store without reading in the data of the table if it hasn't been
+    /// read in yet. Use this for anything that walks over every table and shouldn't force
+    /// a load (an unloaded table is clean and is the same as what's on disk)
+    pub fn loaded_kvstore(&self) -> Option<&KVEngine> {
+        if self.is_loaded() {
+            self.kvstore().ok()
+        } else {
+            None
+        }
+    }
+    const fn kvstore(&self) -> KeyspaceResult<&KVEngine> {
         #[allow(irrefutable_let_patterns)]
         if let DataModel::KV(kvs) = &self.model_store {
             Ok(kvs)
@@ -57,76 +111,485 @@ impl Table {
             Err(DdlError::WrongModel)
         }
     }
-    pub fn count(&self) -> usize {
+    /// Returns true if the data of the table is in memory. This is false only for a table
+    /// that was loaded lazily and hasn't been accessed yet
+    pub fn is_loaded(&self) -> bool {
+        match &self.pending {
+            Some(pending) => pending.loaded.load(Ordering::Acquire),
+            None => true,
+        }
+    }
+    /// Returns true if the table definitely doesn't have `key`, which is only known without
+    /// reading in its data: that is, if the table hasn't been read in yet and the bloom filter
+    /// of its file doesn't have the key. Once the table is read in, this is always false
+    pub fn surely_lacks(&self, key: &[u8]) -> bool {
+        match &self.pending {
+            Some(PendingLoad {
+                loaded,
+                bloom: Some(bloom),
+                ..
+            }) => !loaded.load(Ordering::Acquire) && !bloom.may_contain(key),
+            _ => false,
+        }
+    }
+    /// Read in the data of the table if it was loaded lazily and it hasn't been read in yet.
+    /// If another thread is reading it in, this waits for it to finish. The data of an
+    /// archived table is never read in, since it is only on disk until the keyspace is
+    /// unarchived
+    pub fn ensure_loaded(&self) -> StorageResult<()> {
+        let pending = match &self.pending {
+            Some(pending) if !pending.loaded.load(Ordering::Acquire) && !self.is_archived() => {
+                pending
+            }
+            _ => return Ok(()),
+        };
+        // a panic while loading leaves the table unloaded, so we can just try again
+        let _loading = pending
+            .loading
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if pending.loaded.load(Ordering::Acquire) {
+            // someone else loaded it while we waited
+            return Ok(());
+        }
+        let (ksid, tblid) = (fmt_key_safe(&pending.ksid), fmt_key_safe(&pending.tblid));
+        let (data, protected, expiries) =
+            match crate::storage::unflush::read_table_data(&pending.ksid, &pending.tblid) {
+                Ok(read) => read,
+                Err(e) => {
+                    log::error!("Failed to load table '{}:{}': {}", ksid, tblid, e);
+                    return Err(e);
+                }
+            };
+        let count = data.len();
         match &self.model_store {
-            DataModel::KV(kv) => kv.len(),
+            DataModel::KV(kv) => {
+                kv.load_from_disk(data, protected.into_iter(), expiries.into_iter())
+            }
         }
+        pending.loaded.store(true, Ordering::Release);
+        log::info!("Loaded table '{}:{}' ({} record(s))", ksid, tblid, count);
+        Ok(())
     }
-    /// Returns this table's _description_
-    pub fn describe_self(&self) -> &'static str {
+    /// Read in the data of the table (see [`Self::ensure_loaded`]) without blocking the
+    /// runtime. This returns false if the data couldn't be read in
+    pub async fn wait_loaded(self: &Arc<Self>) -> bool {
+        if self.is_loaded() {
+            return true;
+        }
+        let table = self.clone();
+        let data_dir = ThreadDataDir::current();
+        matches!(
+            tokio::task::spawn_blocking(move || {
+                data_dir.inherit();
+                table.ensure_loaded()
+            })
+            .await,
+            Ok(Ok(()))
+        )
+    }
+    pub fn count(&self) -> usize {
+        match self.get_kvstore() {
+            Ok(kv) => kv.len(),
+            Err(_) => 0,
+        }
+    }
+    /// Returns the name of the model, in the form that it is created with (for example,
+    /// `keymap(str,binstr)`)
+    pub fn model_name(&self) -> &'static str {
         match self.get_model_code() {
-            0 if self.is_volatile() => "KeyValue { data:(binstr,binstr), volatile:true }",
-            0 if !self.is_volatile() => "KeyValue { data:(binstr,binstr), volatile:false }",
-            1 if self.is_volatile() => "KeyValue { data:(binstr,str), volatile:true }",
-            1 if !self.is_volatile() => "KeyValue { data:(binstr,str), volatile:false }",
-            2 if self.is_volatile() => "KeyValue { data:(str,str), volatile:true }",
-            2 if !self.is_volatile() => "KeyValue { data:(str,str), volatile:false }",
-            3 if self.is_volatile() => "KeyValue { data:(str,binstr), volatile:true }",
-            3 if !self.is_volatile() => "KeyValue { data:(str,binstr), volatile:false }",
+            0 => "keymap(binstr,binstr)",
+            1 => "keymap(binstr,str)",
+            2 => "keymap(str,str)",
+            3 => "keymap(str,binstr)",
             _ => unsafe { impossible!() },
         }
     }
+    /// Returns the `<name> <value>` pairs that describe this table:
+    /// - `model`: the model (see [`Self::model_name`])
+    /// - `key_type` and `value_type`: `str` if the keys (or values) have to be UTF-8, else
+    /// `binstr`
+    /// - `volatile` and `ordered`: `true` or `false`
+    /// - `records`: the number of records
+    /// - `tracked_bytes`: the bytes held by the keys and values
+    /// - `max_value_size`: the largest value (in bytes) that can be written, which is the
+    /// server-wide limit unless the table has its own, or `unlimited`
+    /// - `created`: when the table was created (as an RFC 3339 timestamp), or `unknown` if it
+    /// was loaded from disk since that isn't saved
+    /// - `last_write` and `last_read`: when the table was last written to (or created) and
+    /// read from (see [`Activity`]), or `never`. The reads are `untracked` unless `trackreads`
+    /// is set
+    /// - the read and write counters (see
+    /// [`CounterValues::to_pairs`](crate::kvengine::stats::CounterValues::to_pairs)), which
+    /// count from the creation of the table or the latest `SYS STATS RESET`
+    /// - `read_cache`: whether the table keeps a cache of `GET` responses
+    /// - `cache_hits` and `cache_misses`: the `GET`s that did (or didn't) find the response in
+    /// the cache, which count like the other counters (both are zero without a cache)
+    /// - `key_encoding` and `value_encoding`: what a key (or value) is checked against before
+    /// it's written, which is `utf8` or `none` (see [`KVEngine::encoding_rules`]). A client can
+    /// run the same check with `VALIDATE`
+    /// - `largest_key` and `largest_value`: the longest key (or value) in bytes that the table
+    /// held since it was read in, even if it was deleted since
+    /// - `key_sizes` and `value_sizes`: the approximate distribution of the lengths of the
+    /// keys (or values) that the table holds, as `<bound>:<count>` for every bucket that isn't
+    /// empty, separated by spaces, or `none` (see
+    /// [`SizeValues::compact`](crate::kvengine::stats::SizeValues::compact))
+    ///
+    /// None of these need a walk through the data
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        let type_name = |is_str: bool| if is_str { "str" } else { "binstr" };
+        // an unloaded table has to be read in for the record count
+        let _ = self.ensure_loaded();
+        let kv = match &self.model_store {
+            DataModel::KV(kv) => kv,
+        };
+        let (key_is_str, value_is_str) = kv.get_encoding();
+        let mut properties = vec![
+            ("model", self.model_name().to_owned()),
+            ("key_type", type_name(key_is_str).to_owned()),
+            ("value_type", type_name(value_is_str).to_owned()),
+            ("volatile", self.is_volatile().to_string()),
+            ("ordered", self.is_ordered().to_string()),
+            ("records", kv.len().to_string()),
+            ("tracked_bytes", kv.stored_bytes().to_string()),
+            (
+                "max_value_size",
+                kv.value_limit()
+                    .map(|limit| limit.to_string())
+                    .unwrap_or_else(|| "unlimited".to_owned()),
+            ),
+            (
+                "created",
+                self.created
+                    .map(|created| created.to_rfc3339_opts(SecondsFormat::Secs, true))
+                    .unwrap_or_else(|| "unknown".to_owned()),
+            ),
+        ];
+        let activity = self.activity();
+        let last_read = match activity.last_read() {
+            None if !registry::tracks_reads() => "untracked".to_owned(),
+            last_read => self::format_time(last_read),
+        };
+        properties.push(("last_write", self::format_time(activity.last_write())));
+        properties.push(("last_read", last_read));
+        properties.extend(kv.counters().values().to_pairs());
+        let cache = kv.read_cache();
+        let (hits, misses) = cache
+            .map(|cache| (cache.hits(), cache.misses()))
+            .unwrap_or_default();
+        properties.push(("read_cache", cache.is_some().to_string()));
+        properties.push(("cache_hits", hits.to_string()));
+        properties.push(("cache_misses", misses.to_string()));
+        let (key_rule, value_rule) = kv.encoding_rules();
+        properties.push(("key_encoding", key_rule.to_owned()));
+        properties.push(("value_encoding", value_rule.to_owned()));
+        let (key_sizes, value_sizes) = (kv.key_sizes().values(), kv.value_sizes().values());
+        properties.push(("largest_key", key_sizes.max.to_string()));
+        properties.push(("largest_value", value_sizes.max.to_string()));
+        properties.push(("key_sizes", key_sizes.compact()));
+        properties.push(("value_sizes", value_sizes.compact()));
+        properties
+    }
+    /// Returns when the table was last written to and read from
+    pub fn activity(&self) -> &Activity {
+        match &self.model_store {
+            DataModel::KV(kv) => kv.counters().activity(),
+        }
+    }
+    /// Zero the read and write counters of the table. A table that hasn't been read in yet
+    /// has nothing counted, so it is left alone
+    pub fn reset_counters(&self) {
+        if let Some(kv) = self.loaded_kvstore() {
+            kv.counters().reset();
+            if let Some(cache) = kv.read_cache() {
+                cache.reset_counters();
+            }
+        }
+    }
     pub fn truncate_table(&self) {
+        // the table has to be read in, or the next flush would skip it and leave the old
+        // data behind
+        let _ = self.ensure_loaded();
+        match self.model_store {
+            DataModel::KV(ref kv) => {
+                kv.truncate_table();
+            }
+        }
+    }
+    /// Remove the keys of the table that aren't protected from deletion and return the number
+    /// of keys that were removed. Every model clears itself with its own primitives, so this
+    /// works for any table
+    pub fn clear(&self) -> usize {
+        let _ = self.ensure_loaded();
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.clear(),
+        }
+    }
+    /// Returns the number of keys that are protected from deletion (and so survive a
+    /// [`Self::clear`])
+    pub fn protected_count(&self) -> usize {
         match self.model_store {
-            DataModel::KV(ref kv) => kv.truncate_table(),
+            DataModel::KV(ref kv) => kv.protected_count(),
         }
     }
-    /// Returns the storage type as an 8-bit uint
-    pub const fn storage_type(&self) -> u8 {
-        self.volatile as u8
+    /// Truncate the table, leaving behind the keys that are protected from deletion. This
+    /// returns the number of keys that were left behind
+    pub fn truncate_unprotected(&self) -> usize {
+        let _ = self.ensure_loaded();
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.truncate_unprotected(),
+        }
+    }
+    /// Returns the number of bytes mutated since this table was last flushed
+    pub fn dirty_bytes(&self) -> usize {
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.dirty_bytes(),
+        }
+    }
+    /// Returns the change log sequence number at the time of the latest mutation on this
+    /// table (or of its creation)
+    pub fn last_modified(&self) -> u64 {
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.last_modified(),
+        }
+    }
+    /// Take away `flushed` bytes once they have been written out to disk
+    pub fn clear_dirty(&self, flushed: usize) {
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.clear_dirty(flushed),
+        }
+    }
+    /// Returns the storage type as an 8-bit uint. If the table keeps an ordered index, the
+    /// [`bytemarks::BYTEMARK_STORAGE_FLAG_ORDERED`] bit is set too, if it has its own
+    /// limit on the size of values, so is [`bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT`],
+    /// if it has a read cache, so is [`bytemarks::BYTEMARK_STORAGE_FLAG_READ_CACHE`] and if
+    /// it has any [`Activity`], so is [`bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY`]
+    pub fn storage_type(&self) -> u8 {
+        let ordered = if self.is_ordered() {
+            bytemarks::BYTEMARK_STORAGE_FLAG_ORDERED
+        } else {
+            0
+        };
+        let value_limit = if self.own_value_limit().is_some() {
+            bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT
+        } else {
+            0
+        };
+        let read_cache = if self.has_read_cache() {
+            bytemarks::BYTEMARK_STORAGE_FLAG_READ_CACHE
+        } else {
+            0
+        };
+        let activity = if self.activity().last_active().is_some() {
+            bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY
+        } else {
+            0
+        };
+        self.volatile as u8 | ordered | value_limit | read_cache | activity
+    }
+    /// Returns the limit on the size of values that was set on this table, if any (see
+    /// [`KVEngine::own_value_limit`])
+    pub fn own_value_limit(&self) -> Option<u64> {
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.own_value_limit(),
+        }
+    }
+    /// Returns true if the table keeps a cache of `GET` responses
+    pub fn has_read_cache(&self) -> bool {
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.read_cache().is_some(),
+        }
+    }
+    /// Returns true if the table keeps an ordered index
+    pub fn is_ordered(&self) -> bool {
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.is_ordered(),
+        }
     }
     /// Returns the volatility of the table
     pub const fn is_volatile(&self) -> bool {
         self.volatile
     }
-    /// Create a new KVE Table with the provided settings
+    /// Returns true if the table can't be written to (see [`Self::with_read_only`])
+    pub const fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+    /// Returns true if the keyspace of the table is archived. The actions reject an archived
+    /// table with `err-keyspace-archived` and its data isn't read in, so what it has on disk
+    /// is all there is of it
+    pub fn is_archived(&self) -> bool {
+        self.archived.load(Ordering::Acquire)
+    }
+    /// Mark the table as archived (or not) along with its keyspace (see
+    /// [`Keyspace::set_archived`](crate::corestore::memstore::Keyspace::set_archived))
+    pub fn set_archived(&self, archived: bool) {
+        self.archived.store(archived, Ordering::Release)
+    }
+    /// Returns a copy of this table for its archived keyspace: a table with the same settings
+    /// whose data is left on disk (see [`Self::new_unloaded`]) and that is marked as archived.
+    /// Volatile tables have nothing on disk, so their copies are empty, as after a restart.
+    /// The table has to have been flushed, since its data isn't carried over
+    pub fn archived_copy(&self, ksid: &ObjectID, tblid: &ObjectID) -> Self {
+        let (model_code, ordered) = (self.get_model_code(), self.is_ordered());
+        let tbl = if self.volatile {
+            Self::from_model_code(model_code, true, ordered).map(|tbl| tbl.with_entity(ksid, tblid))
+        } else {
+            let bloom = crate::storage::unflush::read_bloom_filter(ksid, tblid);
+            Self::new_unloaded(ksid, tblid, ordered, model_code, bloom)
+        };
+        let mut tbl = match tbl {
+            Some(tbl) => tbl,
+            None => unsafe { impossible!() },
+        };
+        tbl.created = self.created;
+        let activity = self.activity();
+        tbl.activity().restore(
+            activity.last_write().unwrap_or(0),
+            activity.last_read().unwrap_or(0),
+        );
+        tbl.set_archived(true);
+        tbl.with_value_limit(self.own_value_limit())
+            .with_read_cache(self.has_read_cache())
+    }
+    /// Create a new KVE Table with the provided settings. If `ordered` is set, an ordered
+    /// index is built over `data`
     pub fn new_kve_with_data(
         data: Coremap<Data, Data>,
         volatile: bool,
+        ordered: bool,
         k_enc: bool,
         v_enc: bool,
     ) -> Self {
+        let kve = KVEngine::init_with_data(k_enc, v_enc, data);
+        let kve = if ordered {
+            kve.with_ordered_index()
+        } else {
+            kve
+        };
         Self {
             volatile,
-            model_store: DataModel::KV(KVEngine::init_with_data(k_enc, v_enc, data)),
+            model_store: DataModel::KV(kve),
+            created: None,
+            pending: None,
+            read_only: false,
+            archived: AtomicBool::new(false),
         }
     }
-    pub fn new_kve_with_encoding(volatile: bool, k_enc: bool, v_enc: bool) -> Self {
-        Self {
-            volatile,
-            model_store: DataModel::KV(KVEngine::init(k_enc, v_enc)),
-        }
+    pub fn new_kve_with_encoding(volatile: bool, ordered: bool, k_enc: bool, v_enc: bool) -> Self {
+        Self::new_kve_with_data(Coremap::new(), volatile, ordered, k_enc, v_enc)
+    }
+    pub fn from_model_code(code: u8, volatile: bool, ordered: bool) -> Option<Self> {
+        Self::from_model_code_with_data(code, Coremap::new(), volatile, ordered)
     }
-    pub fn from_model_code(code: u8, volatile: bool) -> Option<Self> {
+    /// Same as [`Self::from_model_code`], except that the table starts out with `data`
+    pub fn from_model_code_with_data(
+        code: u8,
+        data: Coremap<Data, Data>,
+        volatile: bool,
+        ordered: bool,
+    ) -> Option<Self> {
         let ret = match code {
-            0 => Self::new_kve_with_encoding(volatile, false, false),
-            1 => Self::new_kve_with_encoding(volatile, false, true),
-            2 => Self::new_kve_with_encoding(volatile, true, true),
-            3 => Self::new_kve_with_encoding(volatile, true, false),
+            0 => Self::new_kve_with_data(data, volatile, ordered, false, false),
+            1 => Self::new_kve_with_data(data, volatile, ordered, false, true),
+            2 => Self::new_kve_with_data(data, volatile, ordered, true, true),
+            3 => Self::new_kve_with_data(data, volatile, ordered, true, false),
             _ => return None,
         };
         Some(ret)
     }
+    /// Create an empty table for a table on disk whose data is only read in on first access
+    /// (see [`Self::ensure_loaded`]). Only tables that aren't volatile have data on disk. The
+    /// `bloom` filter of the table's file (if any) answers lookups of missing keys until then
+    pub fn new_unloaded(
+        ksid: &ObjectID,
+        tblid: &ObjectID,
+        ordered: bool,
+        model_code: u8,
+        bloom: Option<BloomFilter>,
+    ) -> Option<Self> {
+        let mut tbl = Self::from_model_code(model_code, false, ordered)?.with_entity(ksid, tblid);
+        tbl.pending = Some(PendingLoad {
+            ksid: ksid.clone(),
+            tblid: tblid.clone(),
+            loaded: AtomicBool::new(false),
+            loading: Mutex::new(()),
+            bloom,
+        });
+        Some(tbl)
+    }
     /// Create a new kve with default settings but with provided volatile configuration
     pub fn new_kve_with_volatile(volatile: bool) -> Self {
-        Self::new_kve_with_data(Coremap::new(), volatile, false, false)
+        Self::new_kve_with_data(Coremap::new(), volatile, false, false, false)
     }
     /// Returns the default kve:
     /// - `k_enc`: `false`
     /// - `v_enc`: `false`
     /// - `volatile`: `false`
+    /// - `ordered`: `false`
     pub fn new_default_kve() -> Self {
-        Self::new_kve_with_data(Coremap::new(), false, false, false)
+        Self::new_kve_with_data(Coremap::new(), false, false, false, false)
+    }
+    /// Log the mutations on this table in the change log, under `<ksid>:<tblid>`
+    pub fn with_entity(self, ksid: &ObjectID, tblid: &ObjectID) -> Self {
+        let mut entity = Vec::with_capacity(ksid.len() + 1 + tblid.len());
+        entity.extend_from_slice(ksid);
+        entity.push(b':');
+        entity.extend_from_slice(tblid);
+        let model_store = match self.model_store {
+            DataModel::KV(kv) => DataModel::KV(kv.with_entity(Data::from(entity))),
+        };
+        Self {
+            model_store,
+            volatile: self.volatile,
+            created: self.created,
+            pending: self.pending,
+            read_only: self.read_only,
+            archived: self.archived,
+        }
+    }
+    /// Remember that the table was created at `created`, which also counts as its latest write
+    pub fn with_created(mut self, created: DateTime<Utc>) -> Self {
+        self.created = Some(created);
+        self.activity().restore(created.timestamp() as u64, 0);
+        self
+    }
+    /// Keep a cache of `GET` responses for this table if `cached` is set (see
+    /// [`crate::kvengine::cache`])
+    pub fn with_read_cache(self, cached: bool) -> Self {
+        if !cached {
+            return self;
+        }
+        let model_store = match self.model_store {
+            DataModel::KV(kv) => DataModel::KV(kv.with_read_cache()),
+        };
+        Self {
+            model_store,
+            volatile: self.volatile,
+            created: self.created,
+            pending: self.pending,
+            read_only: self.read_only,
+            archived: self.archived,
+        }
+    }
+    /// Make the table read-only: the writes to it are rejected with `err-read-only-entity`
+    /// (the actions check [`Self::is_read_only`] before they write). This is only for tables
+    /// that are never flushed, like the tables of an attached snapshot
+    pub fn with_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+    /// Limit the size of the values that can be written to this table to `limit` bytes,
+    /// instead of the server-wide limit
+    pub fn with_value_limit(self, limit: Option<u64>) -> Self {
+        self.set_value_limit(limit);
+        self
+    }
+    /// Change the limit on the size of the values of this table (see [`Self::with_value_limit`]).
+    /// The values that are already in the table are left as they are
+    pub fn set_value_limit(&self, limit: Option<u64>) {
+        match self.model_store {
+            DataModel::KV(ref kv) => kv.set_value_limit(limit),
+        }
     }
     /// Returns the model code. See [`bytemarks`] for more info
     pub fn get_model_code(&self) -> u8 {
@@ -162,3 +625,13 @@ impl Table {
         &self.model_store
     }
 }
+
+/// Format a time in seconds since the unix epoch as an RFC 3339 timestamp, or `never`
+fn format_time(secs: Option<u64>) -> String {
+    match secs {
+        Some(secs) => Utc
+            .timestamp(secs as i64, 0)
+            .to_rfc3339_opts(SecondsFormat::Secs, true),
+        None => "never".to_owned(),
+    }
+}
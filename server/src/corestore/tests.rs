@@ -119,3 +119,82 @@ mod memstore_keyspace_tests {
         assert!(ms.force_drop_keyspace(obj).is_ok());
     }
 }
+
+mod corestore_entity_tests {
+    use super::super::memstore::*;
+    use super::super::Corestore;
+    use crate::queryengine::parser::get_query_entity;
+
+    #[test]
+    fn test_current_table_cannot_be_dropped() {
+        let db = Corestore::default_with_store(Memstore::new_default());
+        let ksid = unsafe { ObjectID::from_slice("myks") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl") };
+        db.create_keyspace(ksid.clone()).unwrap();
+        db.create_table(
+            (Some(ksid.clone()), Some(tblid)),
+            0,
+            false,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        // this is the connection that is using the table
+        let mut con = db.clone();
+        con.swap_entity(get_query_entity(b"myks:mytbl").unwrap())
+            .unwrap();
+        // so no other connection can remove the table (or its keyspace) from under it
+        assert_eq!(
+            db.drop_table(get_query_entity(b"myks:mytbl").unwrap())
+                .unwrap_err(),
+            DdlError::StillInUse
+        );
+        assert_eq!(
+            db.force_drop_keyspace(ksid.clone()).unwrap_err(),
+            DdlError::StillInUse
+        );
+        assert!(con.get_kvstore().is_ok());
+        // once the connection moves on, the table can be dropped
+        con.swap_entity(get_query_entity(b"default:default").unwrap())
+            .unwrap();
+        assert!(db
+            .drop_table(get_query_entity(b"myks:mytbl").unwrap())
+            .is_ok());
+        assert!(db.force_drop_keyspace(ksid).is_ok());
+    }
+}
+
+mod table_counter_tests {
+    use super::super::table::Table;
+    use super::super::Data;
+
+    #[test]
+    fn test_counters_are_per_table() {
+        let (first, second) = (Table::new_default_kve(), Table::new_default_kve());
+        let (kv1, kv2) = (first.get_kvstore().unwrap(), second.get_kvstore().unwrap());
+        assert!(kv1.set(Data::from("x"), Data::from("100")).unwrap());
+        // an existing key, so it's counted without any bytes written
+        assert!(!kv1.set(Data::from("x"), Data::from("200")).unwrap());
+        assert!(kv1.update(Data::from("x"), Data::from("10")).unwrap());
+        assert!(kv1.get(Data::from("x")).unwrap().is_some());
+        assert!(kv1.get_cloned(Data::from("y"), 0).unwrap().is_none());
+        assert!(kv1.remove(Data::from("x")).unwrap());
+        assert!(kv2.get_cloned(Data::from("y"), 0).unwrap().is_none());
+        kv2.upsert(Data::from("y"), Data::from("1")).unwrap();
+        let values = kv1.counters().values();
+        assert_eq!((values.gets, values.hits, values.misses), (2, 1, 1));
+        assert_eq!((values.sets, values.dels), (3, 1));
+        assert_eq!((values.bytes_read, values.bytes_written), (2, 7));
+        let values = kv2.counters().values();
+        assert_eq!((values.gets, values.misses, values.sets), (1, 1, 1));
+        assert_eq!(values.bytes_written, 2);
+        // only the table that was reset starts over
+        first.reset_counters();
+        let values = kv1.counters().values();
+        assert_eq!((values.gets, values.sets, values.dels), (0, 0, 0));
+        assert_eq!((values.bytes_read, values.bytes_written), (0, 0));
+        assert_eq!(kv2.counters().values().sets, 1);
+        assert_eq!(kv2.counters().values().gets, 1);
+    }
+}
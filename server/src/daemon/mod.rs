@@ -0,0 +1,143 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Service managers
+//!
+//! When the server is run by a service manager, it tells the manager once it is ready to serve
+//! clients (the listeners are bound and the data has been loaded) and once it starts to shut
+//! down. On Linux, this is systemd's `sd_notify` protocol (for `Type=notify` units). On
+//! Windows, the server registers itself with the service control manager, which can then stop
+//! it just like `SYS SHUTDOWN` does. Outside a service manager, none of this does anything
+
+#[cfg(target_os = "linux")]
+mod sdnotify;
+#[cfg(windows)]
+pub mod winservice;
+
+use crate::IoResult;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// What the service manager is told about the server
+pub enum Readiness {
+    /// The server accepts connections
+    Ready,
+    /// The server is shutting down (and is saving the data)
+    Stopping,
+    /// The server has shut down and is about to exit
+    Stopped,
+}
+
+impl fmt::Display for Readiness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let readiness = match self {
+            Self::Ready => "ready",
+            Self::Stopping => "stopping",
+            Self::Stopped => "stopped",
+        };
+        f.write_str(readiness)
+    }
+}
+
+/// Something that tells a service manager about the server
+pub trait Notifier {
+    /// Tell the service manager about `readiness`
+    fn notify(&self, readiness: Readiness) -> IoResult<()>;
+}
+
+/// Tell the service manager that started the server (if any) about `readiness`
+pub fn notify(readiness: Readiness) {
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(notifier) = sdnotify::SdNotify::from_env() {
+            notify_with(&notifier, readiness);
+        }
+    }
+    #[cfg(windows)]
+    {
+        if let Some(notifier) = winservice::notifier() {
+            notify_with(&notifier, readiness);
+        }
+    }
+    #[cfg(not(any(target_os = "linux", windows)))]
+    let _ = readiness;
+}
+
+/// Tell `notifier` about `readiness`. This is only logged if it fails, since the server works
+/// just the same if the service manager doesn't hear about it
+pub fn notify_with(notifier: &dyn Notifier, readiness: Readiness) {
+    if let Err(e) = notifier.notify(readiness) {
+        log::warn!(
+            "Failed to tell the service manager that the server is {}: {}",
+            readiness,
+            e
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{notify_with, Notifier, Readiness};
+    use crate::IoResult;
+    use std::cell::RefCell;
+    use std::io::{Error as IoError, ErrorKind};
+
+    #[derive(Default)]
+    struct Recorder {
+        seen: RefCell<Vec<Readiness>>,
+        fail: bool,
+    }
+
+    impl Notifier for Recorder {
+        fn notify(&self, readiness: Readiness) -> IoResult<()> {
+            self.seen.borrow_mut().push(readiness);
+            if self.fail {
+                Err(IoError::new(ErrorKind::Other, "no service manager"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[test]
+    fn test_notify_with() {
+        let recorder = Recorder::default();
+        notify_with(&recorder, Readiness::Ready);
+        notify_with(&recorder, Readiness::Stopping);
+        notify_with(&recorder, Readiness::Stopped);
+        assert_eq!(
+            *recorder.seen.borrow(),
+            vec![Readiness::Ready, Readiness::Stopping, Readiness::Stopped]
+        );
+        // a notifier that fails doesn't stop the server
+        let failing = Recorder {
+            fail: true,
+            ..Recorder::default()
+        };
+        notify_with(&failing, Readiness::Ready);
+        assert_eq!(*failing.seen.borrow(), vec![Readiness::Ready]);
+    }
+}
@@ -0,0 +1,119 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # `sd_notify`
+//!
+//! systemd passes the path to a datagram socket in `NOTIFY_SOCKET` to the services that it
+//! expects to hear from; every datagram is a list of `KEY=VALUE` lines. Abstract socket
+//! addresses (the ones starting with `@`) aren't supported by the standard library and are
+//! reported as an error instead
+
+use super::{Notifier, Readiness};
+use crate::IoResult;
+use std::env;
+use std::io::{Error as IoError, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
+
+/// The variable that systemd passes the socket in
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// The notification socket of systemd
+pub struct SdNotify {
+    socket: PathBuf,
+}
+
+impl SdNotify {
+    /// Returns the socket that systemd passed to the server, if any
+    pub fn from_env() -> Option<Self> {
+        env::var_os(NOTIFY_SOCKET).map(|socket| Self::new(socket.into()))
+    }
+    fn new(socket: PathBuf) -> Self {
+        Self { socket }
+    }
+}
+
+impl Readiness {
+    /// What systemd is told (systemd doesn't need to hear that the server has stopped since
+    /// it sees the process exit)
+    fn sd_message(self) -> Option<&'static str> {
+        match self {
+            Self::Ready => Some("READY=1"),
+            Self::Stopping => Some("STOPPING=1"),
+            Self::Stopped => None,
+        }
+    }
+}
+
+impl Notifier for SdNotify {
+    fn notify(&self, readiness: Readiness) -> IoResult<()> {
+        let message = match readiness.sd_message() {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+        if self.socket.as_os_str().as_bytes().first() == Some(&b'@') {
+            return Err(IoError::new(
+                ErrorKind::Other,
+                "abstract notification sockets aren't supported",
+            ));
+        }
+        UnixDatagram::unbound()?.send_to(message.as_bytes(), &self.socket)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Notifier, Readiness, SdNotify};
+    use std::env;
+    use std::fs;
+    use std::os::unix::net::UnixDatagram;
+    use std::process;
+
+    #[test]
+    fn test_sd_notify() {
+        let path = env::temp_dir().join(format!("skyd-sdnotify-{}.sock", process::id()));
+        let _ = fs::remove_file(&path);
+        let systemd = UnixDatagram::bind(&path).unwrap();
+        systemd.set_nonblocking(true).unwrap();
+        let notifier = SdNotify::new(path.clone());
+        let mut buf = [0u8; 64];
+        notifier.notify(Readiness::Ready).unwrap();
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        notifier.notify(Readiness::Stopping).unwrap();
+        let len = systemd.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"STOPPING=1");
+        // nothing is sent for this one
+        notifier.notify(Readiness::Stopped).unwrap();
+        assert!(systemd.recv(&mut buf).is_err());
+        fs::remove_file(&path).unwrap();
+        assert!(SdNotify::new("@systemd/notify".into())
+            .notify(Readiness::Ready)
+            .is_err());
+    }
+}
@@ -0,0 +1,198 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Windows services
+//!
+//! When the service control manager starts the server, [`start`] connects to it and registers
+//! a control handler. The server is reported as running once it is [ready](Readiness::Ready)
+//! and a stop request (or the system shutting down) shuts the server down gracefully, the same
+//! way `SYS SHUTDOWN` does. If the server was started any other way, it runs as it always did
+
+use super::{Notifier, Readiness};
+use crate::corestore::lock::QuickLock;
+use crate::registry::{self, ShutdownKind};
+use crate::IoResult;
+use std::io::Error as IoError;
+use std::ptr;
+use std::sync::mpsc::{self, Sender};
+use std::thread;
+use winapi::shared::minwindef::{DWORD, LPVOID};
+use winapi::shared::winerror::{
+    ERROR_CALL_NOT_IMPLEMENTED, ERROR_FAILED_SERVICE_CONTROLLER_CONNECT, NO_ERROR,
+};
+use winapi::um::winnt::{LPWSTR, SERVICE_WIN32_OWN_PROCESS};
+use winapi::um::winsvc::{
+    RegisterServiceCtrlHandlerExW, SetServiceStatus, StartServiceCtrlDispatcherW,
+    SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP, SERVICE_CONTROL_INTERROGATE,
+    SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP, SERVICE_RUNNING, SERVICE_START_PENDING,
+    SERVICE_STATUS, SERVICE_STATUS_HANDLE, SERVICE_STOPPED, SERVICE_STOP_PENDING,
+    SERVICE_TABLE_ENTRYW,
+};
+
+/// The name of the service. This is ignored for a process that runs a single service, which is
+/// why the server can be installed under any name
+const SERVICE_NAME: &str = "skyd";
+/// How long the service control manager should wait for the server to start or stop (the data
+/// is saved before the server stops)
+const WAIT_HINT_MS: DWORD = 60_000;
+
+/// The handle that the status of the service is reported through
+struct StatusHandle(SERVICE_STATUS_HANDLE);
+// the handle is only a token for `SetServiceStatus`, which can be called from any thread
+unsafe impl Send for StatusHandle {}
+
+/// Set once the control handler is registered, if the server runs as a service
+static STATUS_HANDLE: QuickLock<Option<StatusHandle>> = QuickLock::new(None);
+/// Tells [`start`] whether the server runs as a service
+static STARTED: QuickLock<Option<Sender<bool>>> = QuickLock::new(None);
+
+fn service_name() -> Vec<u16> {
+    SERVICE_NAME.encode_utf16().chain(Some(0)).collect()
+}
+
+/// Connect to the service control manager, if it started the server. This returns once it
+/// is known whether the server runs as a service, and has to be called early on since the
+/// service control manager only waits so long for a service to connect
+pub fn start() {
+    let (started, is_service) = mpsc::channel();
+    *STARTED.lock() = Some(started.clone());
+    let dispatcher = thread::Builder::new()
+        .name("service".to_owned())
+        .spawn(move || {
+            let mut name = service_name();
+            let table = [
+                SERVICE_TABLE_ENTRYW {
+                    lpServiceName: name.as_mut_ptr(),
+                    lpServiceProc: Some(service_main),
+                },
+                SERVICE_TABLE_ENTRYW {
+                    lpServiceName: ptr::null_mut(),
+                    lpServiceProc: None,
+                },
+            ];
+            // this calls `service_main` and then dispatches the control requests until the
+            // service has stopped. It fails right away if the server isn't a service
+            if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } == 0 {
+                let e = IoError::last_os_error();
+                if e.raw_os_error() != Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT as i32) {
+                    log::warn!("Failed to connect to the service control manager: {}", e);
+                }
+                let _ = started.send(false);
+            }
+        });
+    match dispatcher {
+        Ok(_) => {
+            if let Ok(true) = is_service.recv() {
+                log::info!("Running as a service");
+            }
+        }
+        Err(e) => log::warn!("Failed to start the service dispatcher: {}", e),
+    }
+    STARTED.lock().take();
+}
+
+unsafe extern "system" fn service_main(_argc: DWORD, _argv: *mut LPWSTR) {
+    let name = service_name();
+    let handle =
+        RegisterServiceCtrlHandlerExW(name.as_ptr(), Some(control_handler), ptr::null_mut());
+    let registered = !handle.is_null();
+    if registered {
+        *STATUS_HANDLE.lock() = Some(StatusHandle(handle));
+        if let Err(e) = set_status(SERVICE_START_PENDING) {
+            log::warn!("Failed to report the status of the service: {}", e);
+        }
+    } else {
+        log::warn!(
+            "Failed to register the service control handler: {}",
+            IoError::last_os_error()
+        );
+    }
+    if let Some(started) = STARTED.lock().take() {
+        let _ = started.send(registered);
+    }
+}
+
+unsafe extern "system" fn control_handler(
+    control: DWORD,
+    _event_type: DWORD,
+    _event_data: LPVOID,
+    _context: LPVOID,
+) -> DWORD {
+    match control {
+        SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+            log::info!("The service control manager asked the server to stop");
+            registry::get_shutdown().request(ShutdownKind::Shutdown);
+            NO_ERROR
+        }
+        SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+        _ => ERROR_CALL_NOT_IMPLEMENTED,
+    }
+}
+
+fn set_status(state: DWORD) -> IoResult<()> {
+    let handle = match &*STATUS_HANDLE.lock() {
+        Some(handle) => handle.0,
+        None => return Ok(()),
+    };
+    let pending = state == SERVICE_START_PENDING || state == SERVICE_STOP_PENDING;
+    let mut status = SERVICE_STATUS {
+        dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+        dwCurrentState: state,
+        // the server can only be stopped once it is running
+        dwControlsAccepted: if state == SERVICE_RUNNING {
+            SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+        } else {
+            0
+        },
+        dwWin32ExitCode: NO_ERROR,
+        dwServiceSpecificExitCode: 0,
+        dwCheckPoint: 0,
+        dwWaitHint: if pending { WAIT_HINT_MS } else { 0 },
+    };
+    if unsafe { SetServiceStatus(handle, &mut status) } == 0 {
+        Err(IoError::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// The service control manager, if the server runs as a service
+pub struct ServiceControlManager;
+
+/// Returns the service control manager if the server runs as a service
+pub fn notifier() -> Option<ServiceControlManager> {
+    STATUS_HANDLE.lock().as_ref().map(|_| ServiceControlManager)
+}
+
+impl Notifier for ServiceControlManager {
+    fn notify(&self, readiness: Readiness) -> IoResult<()> {
+        set_status(match readiness {
+            Readiness::Ready => SERVICE_RUNNING,
+            Readiness::Stopping => SERVICE_STOP_PENDING,
+            Readiness::Stopped => SERVICE_STOPPED,
+        })
+    }
+}
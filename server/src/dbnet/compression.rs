@@ -0,0 +1,189 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Response compression
+//!
+//! Connections that negotiated compression (see [`handshake`](super::handshake)) get every
+//! response that is larger than the configured threshold wrapped in a compressed frame:
+//! ```text
+//! ~<uncompressed length>\n<compressed length>\n<LZ4 block>
+//! ```
+//! The uncompressed length lets clients allocate the whole response up front. The block
+//! holds the complete response (starting with its `*1\n` header) in the
+//! [LZ4 block format](https://github.com/lz4/lz4/blob/dev/doc/lz4_Block_format.md).
+//! Responses have to be assembled in memory to be compressed, so the ones that grow beyond
+//! [`RESPONSE_FLUSH_THRESHOLD`](super::connection::RESPONSE_FLUSH_THRESHOLD) (or twice the
+//! threshold, if that is more) are sent uncompressed instead.
+//!
+//! The blocks are written by [`lz4_flex`] (in block mode), so they decode with any
+//! implementation of the format
+
+use crate::corestore::buffers::Integer64;
+use lz4_flex::block;
+
+/// The type symbol of a compressed frame
+pub const TSYMBOL_COMPRESSED: u8 = b'~';
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// The compression algorithms that can be negotiated
+pub enum Algorithm {
+    Lz4,
+}
+
+impl Algorithm {
+    /// Returns the name that is used for this algorithm in handshakes
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lz4 => "lz4",
+        }
+    }
+    /// Returns the algorithm with the given name in a handshake
+    pub fn from_name(name: &[u8]) -> Option<Self> {
+        if name.eq_ignore_ascii_case(b"lz4") {
+            Some(Self::Lz4)
+        } else {
+            None
+        }
+    }
+}
+
+/// Compress `input` into an LZ4 block, appending it to `out`
+pub fn compress_into(input: &[u8], out: &mut Vec<u8>) {
+    let start = out.len();
+    out.resize(start + block::get_maximum_output_size(input.len()), 0);
+    let written = block::compress_into(input, &mut out[start..])
+        .expect("The output has room for the largest block");
+    out.truncate(start + written);
+}
+
+/// Assemble a compressed frame for `response` into `out`
+pub fn assemble_frame(response: &[u8], out: &mut Vec<u8>) {
+    let mut block = Vec::with_capacity(response.len() / 2);
+    self::compress_into(response, &mut block);
+    out.push(TSYMBOL_COMPRESSED);
+    out.extend_from_slice(&Integer64::from(response.len()));
+    out.push(b'\n');
+    out.extend_from_slice(&Integer64::from(block.len()));
+    out.push(b'\n');
+    out.extend_from_slice(&block);
+}
+
+#[cfg(test)]
+/// Decompress an LZ4 block that should hold `uncompressed_len` bytes. This is only used by
+/// the tests since clients never send compressed data
+pub fn decompress(input: &[u8], uncompressed_len: usize) -> Option<Vec<u8>> {
+    block::decompress(input, uncompressed_len)
+        .ok()
+        .filter(|out| out.len() == uncompressed_len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(input: &[u8]) -> usize {
+        let mut block = Vec::new();
+        compress_into(input, &mut block);
+        assert_eq!(decompress(&block, input.len()).unwrap(), input);
+        block.len()
+    }
+
+    #[test]
+    fn test_roundtrip_short_inputs() {
+        for len in 0..=32 {
+            let input: Vec<u8> = (0..len).map(|i| (i % 3) as u8).collect();
+            roundtrip(&input);
+        }
+    }
+
+    #[test]
+    fn test_roundtrip_compressible() {
+        let input = "skytable ".repeat(10_000).into_bytes();
+        let compressed = roundtrip(&input);
+        assert!(compressed < input.len() / 20);
+        // long runs of a single byte (matches that overlap with themselves)
+        assert!(roundtrip(&[b'x'; 100_000]) < 1000);
+    }
+
+    #[test]
+    fn test_roundtrip_incompressible() {
+        // a simple xorshift so that there are (almost) no matches
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let input: Vec<u8> = (0..70_000)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect();
+        roundtrip(&input);
+    }
+
+    #[test]
+    fn test_reference_block() {
+        // the block the reference implementation writes for twenty `a`s: one literal, a
+        // self-overlapping match of 14 bytes and 5 trailing literals
+        let block = [0x1A, b'a', 0x01, 0x00, 0x50, b'a', b'a', b'a', b'a', b'a'];
+        assert_eq!(decompress(&block, 20).unwrap(), vec![b'a'; 20]);
+        // and the one that `lz4 -1` writes for an array response, where the length of the
+        // match doesn't fit into the token
+        let response = b"&4\n+8\nskytable\n+8\nskytable\n+8\nskytable\n+5\nhello\n";
+        let block = [
+            0xEF, 0x26, 0x34, 0x0A, 0x2B, 0x38, 0x0A, 0x73, 0x6B, 0x79, 0x74, 0x61, 0x62, 0x6C,
+            0x65, 0x0C, 0x00, 0x07, 0x80, 0x35, 0x0A, 0x68, 0x65, 0x6C, 0x6C, 0x6F, 0x0A,
+        ];
+        assert_eq!(decompress(&block, response.len()).unwrap(), &response[..]);
+    }
+
+    #[test]
+    fn test_decompress_rejects_bad_blocks() {
+        // offset pointing before the start of the output
+        assert!(decompress(&[0x10, b'a', 0x02, 0x00, 0x00], 10).is_none());
+        // truncated literals
+        assert!(decompress(&[0x50, b'a'], 5).is_none());
+        // wrong length
+        assert!(decompress(&[0x10, b'a'], 2).is_none());
+    }
+
+    #[test]
+    fn test_frame() {
+        let response = "*1\n+5\nhello\n".repeat(100).into_bytes();
+        let mut frame = Vec::new();
+        assemble_frame(&response, &mut frame);
+        let header = format!("~{}\n", response.len());
+        assert!(frame.starts_with(header.as_bytes()));
+        let rest = &frame[header.len()..];
+        let newline = rest.iter().position(|b| *b == b'\n').unwrap();
+        let block_len: usize = std::str::from_utf8(&rest[..newline])
+            .unwrap()
+            .parse()
+            .unwrap();
+        let block = &rest[newline + 1..];
+        assert_eq!(block.len(), block_len);
+        assert_eq!(decompress(block, response.len()).unwrap(), response);
+    }
+}
@@ -35,7 +35,10 @@
 //! enables this connection object/type to use methods like read_query enabling it to read and interact with queries and write
 //! respones in compliance with the Skyhash protocol.
 
+use super::compression;
+use super::handshake::Capabilities;
 use super::tcp::Connection;
+use crate::corestore::buffers::Integer64;
 use crate::corestore::Corestore;
 use crate::dbnet::tcp::BufferedSocketStream;
 use crate::dbnet::Terminator;
@@ -43,18 +46,28 @@ use crate::protocol;
 use crate::protocol::responses;
 use crate::protocol::ParseError;
 use crate::protocol::Query;
+use crate::queryengine::multi::{TxnState, Watches};
+use crate::queryengine::plan::BatchPlan;
+use crate::registry;
+use crate::registry::{ClientStats, QueryTrace};
+use crate::resp::retry::Hinted;
+use crate::resp::tristate::{Legacy, TriState};
+use crate::resp::IsConnection;
 use crate::resp::Writable;
 use crate::IoResult;
-use bytes::Buf;
-use bytes::BytesMut;
+use bytes::{Buf, BytesMut};
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
 use libsky::TResult;
 use std::future::Future;
 use std::io::Error as IoError;
 use std::io::ErrorKind;
 use std::marker::PhantomData;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::sync::Arc;
 use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufWriter;
 use tokio::sync::mpsc;
@@ -62,6 +75,150 @@ use tokio::sync::Semaphore;
 
 pub const SIMPLE_QUERY_HEADER: [u8; 3] = [b'*', b'1', b'\n'];
 
+/// Assemble a length header (`<tsymbol><len>\n`) into `scratch`
+fn assemble_length(scratch: &mut BytesMut, tsymbol: u8, len: usize) {
+    scratch.extend_from_slice(&[tsymbol]);
+    scratch.extend_from_slice(&Integer64::from(len));
+    scratch.extend_from_slice(&[b'\n']);
+}
+
+/// The most bytes of a response that are held in the response buffer (unless twice the
+/// compression threshold is even more). A response that grows beyond this is written out as it
+/// is assembled, uncompressed, so that a huge response doesn't have to fit in memory
+pub const RESPONSE_FLUSH_THRESHOLD: usize = 256 * 1024;
+
+/// The buffer that responses are assembled in if they might have to be compressed
+#[derive(Debug, Default)]
+pub struct ResponseBuffer {
+    /// the part of the response that hasn't been written out yet
+    staged: BytesMut,
+    /// set once a part of the response has been written out, since the rest of it can't be
+    /// compressed anymore
+    spilled: bool,
+}
+
+impl ResponseBuffer {
+    /// Returns the part of the response that hasn't been written out yet
+    pub fn staged(&self) -> &[u8] {
+        &self.staged
+    }
+    /// Returns true if the response can still be compressed
+    pub fn is_compressible(&self) -> bool {
+        !self.spilled
+    }
+    /// Get ready for the next response
+    pub fn clear(&mut self) {
+        self.staged.clear();
+        self.spilled = false;
+    }
+}
+
+/// Where the frames of a response are written to: straight to the (buffered) stream, or into
+/// the response buffer if the response may have to be compressed once it has been assembled
+pub struct ResponseWriter<'a, Strm> {
+    stream: &'a mut BufWriter<Strm>,
+    /// the response buffer along with the most bytes that it may hold
+    response: Option<(&'a mut ResponseBuffer, usize)>,
+    /// the stats of the connection, which track the bytes that are buffered
+    client: &'a ClientStats,
+}
+
+impl<'a, Strm> ResponseWriter<'a, Strm> {
+    /// Write straight to the stream
+    pub fn stream(stream: &'a mut BufWriter<Strm>, client: &'a ClientStats) -> Self {
+        Self {
+            stream,
+            response: None,
+            client,
+        }
+    }
+    /// Write into the response buffer, until the response grows beyond
+    /// [`RESPONSE_FLUSH_THRESHOLD`] (or twice the compression `threshold`, if that is more)
+    pub fn buffered(
+        stream: &'a mut BufWriter<Strm>,
+        response: &'a mut ResponseBuffer,
+        threshold: usize,
+        client: &'a ClientStats,
+    ) -> Self {
+        Self {
+            stream,
+            response: Some((
+                response,
+                threshold.saturating_mul(2).max(RESPONSE_FLUSH_THRESHOLD),
+            )),
+            client,
+        }
+    }
+}
+
+impl<'a, Strm> IsConnection for ResponseWriter<'a, Strm>
+where
+    Strm: AsyncWrite + Unpin + Send + Sync,
+{
+    fn write_lowlevel<'s>(
+        &'s mut self,
+        bytes: &'s [u8],
+    ) -> Pin<Box<dyn Future<Output = Result<(), IoError>> + Send + Sync + 's>> {
+        Box::pin(async move {
+            self.client.wrote(bytes.len());
+            // once a part of the response was written out, the rest goes straight to the stream
+            if let Some((response, limit)) = &mut self.response {
+                if !response.spilled {
+                    if response.staged.len() + bytes.len() < *limit {
+                        response.staged.extend_from_slice(bytes);
+                        self.client
+                            .set_buffered(response.staged.len() + self.stream.buffer().len());
+                        return Ok(());
+                    }
+                    // too large to hold on to, so this response won't be compressed
+                    response.spilled = true;
+                    self.client.set_buffered(
+                        response.staged.len() + bytes.len() + self.stream.buffer().len(),
+                    );
+                    self.stream.write_all(&response.staged).await?;
+                    response.staged.clear();
+                }
+            }
+            self.client
+                .set_buffered(bytes.len() + self.stream.buffer().len());
+            self.stream.write_all(bytes).await?;
+            self.client.set_buffered(self.stream.buffer().len());
+            Ok(())
+        })
+    }
+}
+
+/// The counter that connection IDs are handed out from
+static NEXT_PEER_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Who is on the other end of a connection: a process-wide unique ID and, for connections
+/// accepted over the network, the remote address
+#[derive(Debug, Clone, PartialEq)]
+pub struct Peer {
+    pub id: u64,
+    pub addr: Option<SocketAddr>,
+}
+
+impl Peer {
+    /// Identify a new connection
+    pub fn new(addr: Option<SocketAddr>) -> Self {
+        Self {
+            id: NEXT_PEER_ID.fetch_add(1, Ordering::Relaxed),
+            addr,
+        }
+    }
+}
+
+impl fmt::Display for Peer {
+    /// Formats as `#<id> <addr>`, or `#<id> local` if there is no remote address
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.addr {
+            Some(addr) => write!(f, "#{} {}", self.id, addr),
+            None => write!(f, "#{} local", self.id),
+        }
+    }
+}
+
 pub enum QueryResult {
     Q(Query),
     E(&'static [u8]),
@@ -69,39 +226,159 @@ pub enum QueryResult {
     Wrongtype,
 }
 
+impl QueryResult {
+    /// Returns what a parse attempt amounts to, or `None` if the rest of the query has to be
+    /// read first
+    pub fn from_parsed(parsed: Result<Query, ParseError>) -> Option<Self> {
+        let ret = match parsed {
+            Ok(query) => QueryResult::Q(query),
+            Err(ParseError::Empty) => QueryResult::Empty,
+            Err(ParseError::NotEnough) => return None,
+            Err(ParseError::DatatypeParseFailure) => QueryResult::Wrongtype,
+            Err(ParseError::UnexpectedByte) | Err(ParseError::BadPacket) => {
+                QueryResult::E(responses::full_responses::R_PACKET_ERR)
+            }
+            Err(ParseError::UnknownDatatype) => {
+                QueryResult::E(responses::full_responses::R_UNKNOWN_DATA_TYPE)
+            }
+        };
+        Some(ret)
+    }
+}
+
 pub mod prelude {
     //! A 'prelude' for callers that would like to use the `ProtocolConnection` and `ProtocolConnectionExt` traits
     //!
     //! This module is hollow itself, it only re-exports from `dbnet::con` and `tokio::io`
     pub use super::ProtocolConnectionExt;
+    pub use crate::actions::Arity;
     pub use crate::aerr;
+    pub use crate::check_arity;
+    pub use crate::check_value_sizes;
     pub use crate::conwrite;
     pub use crate::corestore::Corestore;
     pub use crate::default_keyspace;
-    pub use crate::err_if_len_is;
+    pub use crate::ensure_readable;
+    pub use crate::ensure_writable;
     pub use crate::get_tbl;
     pub use crate::handle_entity;
     pub use crate::is_lowbit_set;
     pub use crate::kve;
+    pub use crate::next_or_err;
     pub use crate::not_enc_err;
     pub use crate::protocol::responses;
     pub use crate::protocol::responses::groups;
     pub use crate::queryengine::ActionIter;
     pub use crate::registry;
+    pub use crate::resp::retry::Hinted;
+    pub use crate::throttle_writes;
     pub use crate::util::Unwrappable;
     pub use tokio::io::{AsyncReadExt, AsyncWriteExt};
     #[macro_export]
     macro_rules! kve {
-        ($con:expr, $store:expr) => {
+        (@resolve $con:expr, $store:expr) => {{
+            // a table that was loaded lazily is read in on first access
+            if !$store.ensure_ctable_loaded().await {
+                return $con
+                    .write_response(crate::protocol::responses::groups::SERVER_ERR)
+                    .await;
+            }
             match $store.get_kvstore() {
-                Ok(store) => store,
+                Ok(store) => {
+                    $con.get_mut_plan().resolved_kvstore();
+                    store
+                }
+                Err(crate::corestore::memstore::DdlError::Archived) => {
+                    // it was archived in the meantime
+                    return $con
+                        .write_response(crate::protocol::responses::groups::KEYSPACE_ARCHIVED)
+                        .await;
+                }
                 _ => {
                     // wrong model
                     return $con
-                        .write_response(crate::protocol::responses::groups::WRONG_MODEL)
+                        .write_error(crate::resp::retry::Hinted::WrongModel)
                         .await;
                 }
             }
+        }};
+        ($con:expr, $store:expr) => {{
+            if $store.ctable_is_archived() {
+                return $con
+                    .write_response(crate::protocol::responses::groups::KEYSPACE_ARCHIVED)
+                    .await;
+            }
+            // an earlier query of the batch may have resolved the table already
+            let planned = if $con.get_mut_plan().has_kvstore() {
+                $store.get_loaded_kvstore()
+            } else {
+                None
+            };
+            match planned {
+                Some(store) => store,
+                None => crate::kve!(@resolve $con, $store),
+            }
+        }};
+    }
+    #[macro_export]
+    /// Reject the write if the current table is read-only (like the tables of an attached
+    /// snapshot)
+    macro_rules! ensure_writable {
+        ($con:expr, $handle:expr) => {
+            if $handle.ctable_is_read_only() {
+                return $con
+                    .write_response(crate::protocol::responses::groups::READ_ONLY_ENTITY)
+                    .await;
+            }
+        };
+    }
+    #[macro_export]
+    /// Reject the write if the current table is read-only or if the
+    /// [memory guard](crate::registry::MemoryGuard) blocks writes, wait for (or reject the
+    /// write if so configured) the flush service if the dirty bytes mark has been crossed, and
+    /// then pass through the [write gate](crate::registry::WriteGate). Place this before
+    /// borrowing the table. Writes that only free memory pass `frees_memory` so that they can
+    /// run while the memory guard blocks writes
+    macro_rules! throttle_writes {
+        (@pass $con:expr) => {
+            let timeout = $con.get_capabilities().default_timeout;
+            if !crate::registry::get_dirty_tracker().throttle(timeout).await {
+                return $con
+                    .write_error(crate::resp::retry::Hinted::BusyWrites)
+                    .await;
+            }
+            // this is held until the action returns. The queries of a transaction don't
+            // pass through the gate, since `EXEC` has closed it for them
+            let _gate = if $con.get_mut_txn_state().is_executing() {
+                None
+            } else {
+                Some(crate::registry::get_write_gate().pass().await)
+            };
+        };
+        ($con:expr, $handle:expr) => {
+            crate::ensure_writable!($con, $handle);
+            if crate::registry::get_memory_guard().is_blocking() {
+                return $con
+                    .write_error(crate::resp::retry::Hinted::OutOfMemory)
+                    .await;
+            }
+            crate::throttle_writes!(@pass $con);
+        };
+        ($con:expr, $handle:expr, frees_memory) => {
+            crate::ensure_writable!($con, $handle);
+            crate::throttle_writes!(@pass $con);
+        };
+    }
+    #[macro_export]
+    /// Reject the read with a server error if the system state doesn't allow reads. Place
+    /// this at the start of actions that only read data
+    macro_rules! ensure_readable {
+        ($con:expr) => {
+            if !crate::registry::reads_okay() {
+                return $con
+                    .write_response(crate::protocol::responses::groups::SERVER_ERR)
+                    .await;
+            }
         };
     }
     #[macro_export]
@@ -139,10 +416,12 @@ pub mod prelude {
         };
     }
     #[macro_export]
+    /// Get the table, reading in its data first if it was loaded lazily and hasn't been
+    /// accessed yet
     macro_rules! get_tbl {
         ($entity:expr, $store:expr, $con:expr) => {{
             use crate::corestore::memstore::DdlError;
-            match $store.get_table($entity) {
+            let tbl = match $store.get_table($entity) {
                 Ok(tbl) => tbl,
                 Err(DdlError::DefaultNotFound) => {
                     return conwrite!($con, crate::protocol::responses::groups::DEFAULT_UNSET);
@@ -153,14 +432,28 @@ pub mod prelude {
                         crate::protocol::responses::groups::CONTAINER_NOT_FOUND
                     );
                 }
+                Err(DdlError::Archived) => {
+                    return conwrite!($con, crate::protocol::responses::groups::KEYSPACE_ARCHIVED);
+                }
                 Err(_) => unsafe { impossible!() },
+            };
+            if !tbl.wait_loaded().await {
+                return conwrite!($con, crate::protocol::responses::groups::SERVER_ERR);
             }
+            tbl
         }};
         ($store:expr, $con:expr) => {{
-            match $store.get_ctable() {
+            let tbl = match $store.get_ctable() {
                 Some(tbl) => tbl,
                 None => return conwrite!($con, crate::protocol::responses::groups::DEFAULT_UNSET),
+            };
+            if tbl.is_archived() {
+                return conwrite!($con, crate::protocol::responses::groups::KEYSPACE_ARCHIVED);
+            }
+            if !tbl.wait_loaded().await {
+                return conwrite!($con, crate::protocol::responses::groups::SERVER_ERR);
             }
+            tbl
         }};
     }
     #[macro_export]
@@ -213,17 +506,61 @@ where
             ret
         })
     }
-    /// Try to parse a query from the buffered data
-    fn try_query(&self) -> Result<(Query, usize), ParseError> {
+    /// Try to parse a query from the buffered data. If there's an entire query in the buffer,
+    /// it is split off the buffer and its elements are slices of it (so that nothing is
+    /// copied). The memory that the query took up is reused once the elements are dropped
+    ///
+    /// If the connection is traced, the query is traced from the first time its bytes are
+    /// looked at
+    ///
+    /// If request ids were negotiated, the id frame that the query is tagged with (if any) is
+    /// read first and kept until the response is written
+    fn try_query(&mut self) -> Result<Query, ParseError> {
         if self.get_buffer().is_empty() {
             return Err(ParseError::Empty);
         }
-        protocol::Parser::new(self.get_buffer()).parse()
+        if self.get_mut_trace().is_none() && self.is_traced() {
+            *self.get_mut_trace() = Some(QueryTrace::start());
+        }
+        let parsed = self
+            .read_request_id()
+            .and_then(|()| protocol::Parser::query_len(self.get_buffer()))
+            .and_then(|forward_by| {
+                let frame = self.get_mut_buffer().split_to(forward_by).freeze();
+                protocol::Parser::new_shared(&frame)
+                    .parse()
+                    .map(|(query, _)| query)
+            });
+        match (&parsed, self.get_mut_trace()) {
+            (Ok(_), Some(trace)) => trace.parsed(),
+            // wait for the rest of the query
+            (Err(ParseError::NotEnough), _) | (_, None) => {}
+            // nothing is run for a malformed query, so there's nothing to trace
+            (Err(_), trace) => *trace = None,
+        }
+        parsed
+    }
+    /// Read the request id frame at the start of the buffer, if request ids were negotiated
+    /// and the id of the next query hasn't been read yet
+    fn read_request_id(&mut self) -> Result<(), ParseError> {
+        if !self.get_capabilities().request_ids || self.get_mut_request_id().is_some() {
+            return Ok(());
+        }
+        if let Some((id, forward_by)) = protocol::Parser::request_id(self.get_buffer())? {
+            self.get_mut_buffer().advance(forward_by);
+            *self.get_mut_request_id() = Some(id);
+        }
+        Ok(())
     }
     /// Read a query from the remote end
     ///
     /// This function asynchronously waits until all the data required
-    /// for parsing the query is available
+    /// for parsing the query is available. Queries that were pipelined behind the last one
+    /// (and are already in the buffer) are read without waiting, but the responses that are
+    /// held back are always flushed before waiting for more data. The queries that are read
+    /// without waiting make up a batch, which shares a [`BatchPlan`]. A client that pipelines
+    /// more queries in one go than it may (see [`registry::PipelineLimits`]) has its
+    /// connection closed, which is what [`QueryResult::Empty`] amounts to
     fn read_query<'r, 's>(
         &'r mut self,
     ) -> Pin<Box<dyn Future<Output = Result<QueryResult, IoError>> + Send + 's>>
@@ -235,24 +572,31 @@ where
             let mv_self = self;
             let _: Result<QueryResult, IoError> = {
                 loop {
-                    mv_self.read_again().await?;
-                    match mv_self.try_query() {
-                        Ok((query, forward_by)) => {
-                            mv_self.advance_buffer(forward_by);
-                            return Ok(QueryResult::Q(query));
-                        }
-                        Err(ParseError::Empty) => return Ok(QueryResult::Empty),
-                        Err(ParseError::NotEnough) => (),
-                        Err(ParseError::DatatypeParseFailure) => return Ok(QueryResult::Wrongtype),
-                        Err(ParseError::UnexpectedByte) | Err(ParseError::BadPacket) => {
-                            return Ok(QueryResult::E(responses::full_responses::R_PACKET_ERR));
-                        }
-                        Err(ParseError::UnknownDatatype) => {
-                            return Ok(QueryResult::E(
-                                responses::full_responses::R_UNKNOWN_DATA_TYPE,
-                            ));
+                    if !mv_self.get_buffer().is_empty() {
+                        if let Some(result) = QueryResult::from_parsed(mv_self.try_query()) {
+                            let depth = mv_self.get_client().read_query();
+                            if registry::get_pipeline_limits().is_too_deep(depth) {
+                                log::warn!(
+                                    "Closing connection {}: it pipelined more than {} queries without waiting for their responses",
+                                    mv_self.get_peer(),
+                                    registry::get_pipeline_limits().max_depth()
+                                );
+                                return Ok(QueryResult::Empty);
+                            }
+                            return Ok(result);
                         }
                     }
+                    if mv_self.get_client().inflight() != 0 {
+                        // never wait for the client while it waits for these
+                        mv_self.flush_stream().await?;
+                    }
+                    mv_self.get_client().drained();
+                    // the batch that was read is over
+                    mv_self.get_mut_plan().invalidate();
+                    mv_self.read_again().await?;
+                    if mv_self.get_buffer().is_empty() {
+                        return Ok(QueryResult::Empty);
+                    }
                 }
             };
         })
@@ -270,7 +614,78 @@ where
             let mv_self = self;
             let streamer = streamer;
             let ret: IoResult<()> = {
-                streamer.write(&mut mv_self.get_mut_stream()).await?;
+                streamer.write(&mut mv_self.get_mut_writer()).await?;
+                Ok(())
+            };
+            ret
+        })
+    }
+    /// Write an error response: with its retry hint (see [`retry`](crate::resp::retry)) if
+    /// the connection negotiated `retryhints`, or as its response code otherwise
+    fn write_error<'r, 's>(
+        &'r mut self,
+        error: Hinted,
+    ) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            if self.get_capabilities().retry_hints {
+                self.write_assembled(move |scratch| error.assemble(scratch))
+                    .await
+            } else {
+                self.write_response(error.code()).await
+            }
+        })
+    }
+    /// Assemble a frame in the connection's scratch buffer with `assemble` and then write it
+    /// to the stream in one go
+    ///
+    /// The scratch buffer is reused across frames (and queries) to avoid allocating for every
+    /// frame. It is only ever handed out to `assemble` which can't hold on to it across an
+    /// await, and it is cleared before the frame is assembled
+    fn write_assembled<'r, 's>(
+        &'r mut self,
+        assemble: impl FnOnce(&mut BytesMut) + Send + 's,
+    ) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            let mv_self = self;
+            let ret: IoResult<()> = {
+                let (scratch, mut writer) = mv_self.get_mut_scratch_and_writer();
+                scratch.clear();
+                assemble(scratch);
+                writer.write_lowlevel(&scratch[..]).await?;
+                Ok(())
+            };
+            ret
+        })
+    }
+    /// Write the id frame (`#<id>\n`) of the request id that the query was tagged with, if
+    /// any. This has to be written ahead of the rest of the response
+    fn write_request_id<'r, 's>(
+        &'r mut self,
+    ) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            let mv_self = self;
+            let ret: IoResult<()> = {
+                if let Some(id) = mv_self.get_mut_request_id().take() {
+                    mv_self
+                        .write_assembled(move |scratch| {
+                            scratch.extend_from_slice(&[protocol::REQUEST_ID_TSYMBOL]);
+                            scratch.extend_from_slice(&Integer64::init(id));
+                            scratch.extend_from_slice(&[b'\n']);
+                        })
+                        .await?;
+                }
                 Ok(())
             };
             ret
@@ -305,9 +720,9 @@ where
         Box::pin(async move {
             let mv_self = self;
             let ret: IoResult<()> = {
-                mv_self.write_response([b'_']).await?;
-                mv_self.write_response(len.to_string().into_bytes()).await?;
-                mv_self.write_response([b'\n']).await?;
+                mv_self
+                    .write_assembled(move |scratch| assemble_length(scratch, b'_', len))
+                    .await?;
                 Ok(())
             };
             ret
@@ -325,14 +740,63 @@ where
         Box::pin(async move {
             let mv_self = self;
             let ret: IoResult<()> = {
-                mv_self.write_response([b'&']).await?;
-                mv_self.write_response(len.to_string().into_bytes()).await?;
-                mv_self.write_response([b'\n']).await?;
+                mv_self
+                    .write_assembled(move |scratch| assemble_length(scratch, b'&', len))
+                    .await?;
                 Ok(())
             };
             ret
         })
     }
+    /// Start an array response of `len` elements (`&<len>\n`). The elements have to be
+    /// written through the returned [`ArrayWriter`], which makes sure that there are exactly
+    /// `len` of them
+    fn start_array<'r, 's>(
+        &'r mut self,
+        len: usize,
+    ) -> Pin<Box<dyn Future<Output = IoResult<ArrayWriter<'r, Self, Strm>>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            self.write_array_length(len).await?;
+            Ok(ArrayWriter::new(self, len))
+        })
+    }
+    /// Same as [`Self::start_array`], but for a flat array (`_<len>\n`)
+    fn start_flat_array<'r, 's>(
+        &'r mut self,
+        len: usize,
+    ) -> Pin<Box<dyn Future<Output = IoResult<ArrayWriter<'r, Self, Strm>>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            self.write_flat_array_length(len).await?;
+            Ok(ArrayWriter::new(self, len))
+        })
+    }
+    /// Write an element of a multi-key action's array response: with the tristate encoding
+    /// if the connection negotiated it or the way that it was always written otherwise
+    fn write_element<'r, 's>(
+        &'r mut self,
+        element: TriState,
+    ) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            let mv_self = self;
+            if mv_self.get_capabilities().tristate {
+                mv_self.write_response(element).await
+            } else {
+                mv_self.write_response(Legacy(element)).await
+            }
+        })
+    }
     /// Wraps around the `write_response` used to differentiate between a
     /// success response and an error response
     fn close_conn_with_error<'r, 's>(
@@ -353,6 +817,37 @@ where
             ret
         })
     }
+    /// Finish writing the response to a query. If the next query was pipelined behind it (it
+    /// is already in the buffer), the response is held back to go out along with the responses
+    /// after it, unless the connection already holds back as many responses (or bytes of them)
+    /// as it may (see [`registry::PipelineLimits`]). Otherwise the stream is flushed, which
+    /// waits for the socket to take the responses
+    fn finish_response<'r, 's>(
+        &'r mut self,
+    ) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            let mv_self = self;
+            let ret: IoResult<()> = {
+                mv_self.write_staged().await?;
+                let (inflight, bytes) = mv_self.get_client().finished_response();
+                let pipelined = !mv_self.get_buffer().is_empty();
+                if pipelined && registry::get_pipeline_limits().can_hold_back(inflight, bytes) {
+                    let buffered = mv_self.get_stream().buffer().len();
+                    mv_self.get_client().set_buffered(buffered);
+                } else {
+                    mv_self.flush_stream().await?;
+                }
+                Ok(())
+            };
+            ret
+        })
+    }
+    /// Flush the stream. If compression was negotiated, the assembled response is written
+    /// out first (as a compressed frame, if it is large enough)
     fn flush_stream<'r, 's>(&'r mut self) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 's>>
     where
         'r: 's,
@@ -361,7 +856,41 @@ where
         Box::pin(async move {
             let mv_self = self;
             let ret: IoResult<()> = {
+                mv_self.write_staged().await?;
                 mv_self.get_mut_stream().flush().await?;
+                mv_self.get_client().set_buffered(0);
+                mv_self.get_client().flushed();
+                Ok(())
+            };
+            ret
+        })
+    }
+    /// Write the assembled response to the (buffered) stream, as a compressed frame if
+    /// compression was negotiated and the response is large enough. This does nothing if
+    /// compression wasn't negotiated, since the response went straight to the stream
+    fn write_staged<'r, 's>(&'r mut self) -> Pin<Box<dyn Future<Output = IoResult<()>> + Send + 's>>
+    where
+        'r: 's,
+        Self: Send + 's,
+    {
+        Box::pin(async move {
+            let mv_self = self;
+            let ret: IoResult<()> = {
+                let negotiated = mv_self.get_capabilities().compression;
+                let (response, stream) = mv_self.get_mut_response_and_stream();
+                let staged = response.staged();
+                match negotiated {
+                    Some(negotiated)
+                        if response.is_compressible() && staged.len() >= negotiated.threshold =>
+                    {
+                        let mut frame = Vec::with_capacity(staged.len() / 2);
+                        compression::assemble_frame(staged, &mut frame);
+                        registry::get_compression().record_frame(staged.len(), frame.len());
+                        stream.write_all(&frame).await?;
+                    }
+                    _ => stream.write_all(staged).await?,
+                }
+                response.clear();
                 Ok(())
             };
             ret
@@ -369,6 +898,102 @@ where
     }
 }
 
+/// # Array responses
+///
+/// An array response that is being written, as started with
+/// [`ProtocolConnectionExt::start_array`]. The writer holds on to the connection until the
+/// array is done, so nothing else can be written in between, and it counts the elements that
+/// are written through it. If it is dropped before every element was written (say, because
+/// the action returned early), or if more elements are written than the array has room for,
+/// the client would read whatever comes next as a part of the array. So the connection is
+/// marked as desynced instead, and the connection loop closes it rather than reading the next
+/// query off it
+pub struct ArrayWriter<'a, T, Strm>
+where
+    T: ProtocolConnectionExt<Strm> + ?Sized,
+    Strm: AsyncReadExt + AsyncWriteExt + Unpin + Send + Sync,
+{
+    con: &'a mut T,
+    /// the elements that haven't been written yet
+    remaining: usize,
+    _marker: PhantomData<Strm>,
+}
+
+impl<'a, T, Strm> ArrayWriter<'a, T, Strm>
+where
+    T: ProtocolConnectionExt<Strm> + ?Sized,
+    Strm: AsyncReadExt + AsyncWriteExt + Unpin + Send + Sync,
+{
+    fn new(con: &'a mut T, len: usize) -> Self {
+        Self {
+            con,
+            remaining: len,
+            _marker: PhantomData,
+        }
+    }
+    /// Count another element, failing (and marking the connection as desynced) if the array
+    /// is already full
+    fn take_slot(&mut self) -> IoResult<()> {
+        if self.remaining == 0 {
+            self.con.mark_desynced();
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                "more elements than the array response has room for",
+            ));
+        }
+        self.remaining -= 1;
+        Ok(())
+    }
+    /// Write the next element
+    pub async fn write(&mut self, element: impl Writable + Send) -> IoResult<()> {
+        self.take_slot()?;
+        self.con.write_response(element).await
+    }
+    /// Write the next element of a multi-key action's response (see
+    /// [`ProtocolConnectionExt::write_element`])
+    pub async fn write_element(&mut self, element: TriState) -> IoResult<()> {
+        self.take_slot()?;
+        self.con.write_element(element).await
+    }
+    /// Start a flat array of `len` elements as the next element
+    pub async fn start_flat_array(&mut self, len: usize) -> IoResult<ArrayWriter<'_, T, Strm>> {
+        self.take_slot()?;
+        self.con.start_flat_array(len).await
+    }
+    /// Returns the number of elements that haven't been written yet
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+    /// Finish the array, failing if any of its elements weren't written (the connection is
+    /// marked as desynced in that case)
+    pub fn finish(self) -> IoResult<()> {
+        if self.remaining != 0 {
+            return Err(IoError::new(
+                ErrorKind::InvalidData,
+                "the array response was finished before all its elements were written",
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, T, Strm> Drop for ArrayWriter<'a, T, Strm>
+where
+    T: ProtocolConnectionExt<Strm> + ?Sized,
+    Strm: AsyncReadExt + AsyncWriteExt + Unpin + Send + Sync,
+{
+    fn drop(&mut self) {
+        if self.remaining != 0 {
+            log::error!(
+                "An array response to connection {} is missing {} element(s)",
+                self.con.get_peer(),
+                self.remaining
+            );
+            self.con.mark_desynced();
+        }
+    }
+}
+
 /// # The `ProtocolConnection` trait
 ///
 /// The `ProtocolConnection` trait has low-level methods that can be used to interface with raw sockets. Any type
@@ -399,10 +1024,43 @@ pub trait ProtocolConnection<Strm> {
     ///
     /// This is to avoid double mutable reference errors
     fn get_mut_both(&mut self) -> (&mut BytesMut, &mut BufWriter<Strm>);
-    /// Advance the read buffer by `forward_by` positions
-    fn advance_buffer(&mut self, forward_by: usize) {
-        self.get_mut_buffer().advance(forward_by)
-    }
+    /// Returns a **mutable** reference to (scratch buffer, response writer)
+    ///
+    /// The scratch buffer is used to assemble frames before they're written out
+    fn get_mut_scratch_and_writer(&mut self) -> (&mut BytesMut, ResponseWriter<'_, Strm>);
+    /// Returns the writer that the frames of a response should be written to. Unless
+    /// compression was negotiated, this is the stream itself
+    fn get_mut_writer(&mut self) -> ResponseWriter<'_, Strm>;
+    /// Returns a **mutable** reference to (response buffer, stream)
+    fn get_mut_response_and_stream(&mut self) -> (&mut ResponseBuffer, &mut BufWriter<Strm>);
+    /// Returns an **immutable** reference to the stats of this connection
+    fn get_client(&self) -> &ClientStats;
+    /// Returns an **immutable** reference to the negotiated capabilities
+    fn get_capabilities(&self) -> &Capabilities;
+    /// Returns a **mutable** reference to the negotiated capabilities
+    fn get_mut_capabilities(&mut self) -> &mut Capabilities;
+    /// Returns a **mutable** reference to the shutdown token handed out on this connection
+    fn get_mut_shutdown_token(&mut self) -> &mut Option<String>;
+    /// Returns an **immutable** reference to the peer on the other end of this connection
+    fn get_peer(&self) -> &Peer;
+    /// Returns true if the queries on this connection are traced
+    fn is_traced(&self) -> bool;
+    /// Returns a **mutable** reference to the query that is being traced, if any
+    fn get_mut_trace(&mut self) -> &mut Option<QueryTrace>;
+    /// Returns a **mutable** reference to the state of the transaction on this connection
+    fn get_mut_txn_state(&mut self) -> &mut TxnState;
+    /// Returns a **mutable** reference to the keys that this connection is watching
+    fn get_mut_watches(&mut self) -> &mut Watches;
+    /// Returns a **mutable** reference to the plan of the batch of queries that is being run
+    fn get_mut_plan(&mut self) -> &mut BatchPlan;
+    /// Returns a **mutable** reference to the request id that the query which is being read
+    /// (or run) was tagged with
+    fn get_mut_request_id(&mut self) -> &mut Option<u64>;
+    /// Returns true if a response was cut short, so that the client can't tell where the next
+    /// response starts (see [`ArrayWriter`])
+    fn is_desynced(&self) -> bool;
+    /// Mark the connection as desynced, so that it is closed once the query is done
+    fn mark_desynced(&mut self);
     /// Clear the internal buffer completely
     fn clear_buffer(&mut self) {
         self.get_mut_buffer().clear()
@@ -437,6 +1095,71 @@ where
     fn get_mut_both(&mut self) -> (&mut BytesMut, &mut BufWriter<T>) {
         (&mut self.buffer, &mut self.stream)
     }
+    fn get_mut_scratch_and_writer(&mut self) -> (&mut BytesMut, ResponseWriter<'_, T>) {
+        let writer = match self.capabilities.compression {
+            Some(negotiated) => ResponseWriter::buffered(
+                &mut self.stream,
+                &mut self.response,
+                negotiated.threshold,
+                &self.client,
+            ),
+            None => ResponseWriter::stream(&mut self.stream, &self.client),
+        };
+        (&mut self.scratch, writer)
+    }
+    fn get_mut_writer(&mut self) -> ResponseWriter<'_, T> {
+        match self.capabilities.compression {
+            Some(negotiated) => ResponseWriter::buffered(
+                &mut self.stream,
+                &mut self.response,
+                negotiated.threshold,
+                &self.client,
+            ),
+            None => ResponseWriter::stream(&mut self.stream, &self.client),
+        }
+    }
+    fn get_mut_response_and_stream(&mut self) -> (&mut ResponseBuffer, &mut BufWriter<T>) {
+        (&mut self.response, &mut self.stream)
+    }
+    fn get_client(&self) -> &ClientStats {
+        &self.client
+    }
+    fn get_capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+    fn get_mut_capabilities(&mut self) -> &mut Capabilities {
+        &mut self.capabilities
+    }
+    fn get_mut_shutdown_token(&mut self) -> &mut Option<String> {
+        &mut self.shutdown_token
+    }
+    fn get_peer(&self) -> &Peer {
+        &self.peer
+    }
+    fn is_traced(&self) -> bool {
+        self.tracing.load(Ordering::Relaxed)
+    }
+    fn get_mut_trace(&mut self) -> &mut Option<QueryTrace> {
+        &mut self.trace
+    }
+    fn get_mut_txn_state(&mut self) -> &mut TxnState {
+        &mut self.txn
+    }
+    fn get_mut_watches(&mut self) -> &mut Watches {
+        &mut self.watches
+    }
+    fn get_mut_plan(&mut self) -> &mut BatchPlan {
+        &mut self.plan
+    }
+    fn get_mut_request_id(&mut self) -> &mut Option<u64> {
+        &mut self.request_id
+    }
+    fn is_desynced(&self) -> bool {
+        self.desynced
+    }
+    fn mark_desynced(&mut self) {
+        self.desynced = true;
+    }
 }
 
 /// # A generic connection handler
@@ -488,16 +1211,18 @@ where
                 }
             };
             match try_df {
-                Ok(QueryResult::Q(s)) => {
-                    self.db.execute_query(s, &mut self.con).await?;
-                }
-                Ok(QueryResult::E(r)) => self.con.close_conn_with_error(r).await?,
-                Ok(QueryResult::Wrongtype) => {
-                    self.con
-                        .close_conn_with_error(responses::groups::WRONGTYPE_ERR.to_owned())
-                        .await?
-                }
                 Ok(QueryResult::Empty) => return Ok(()),
+                Ok(result) => {
+                    respond(&mut self.db, &mut self.con, result).await?;
+                    if self.con.is_desynced() {
+                        // the client would read the next response as a part of the last one
+                        log::error!(
+                            "Closing connection {}: the response to its last query was cut short",
+                            self.con.get_peer()
+                        );
+                        return Ok(());
+                    }
+                }
                 #[cfg(windows)]
                 Err(e) => match e.kind() {
                     ErrorKind::ConnectionReset => return Ok(()),
@@ -511,6 +1236,29 @@ where
     }
 }
 
+/// Respond to what [`ProtocolConnectionExt::read_query`] read off the connection: run the
+/// query, or write out the error for a malformed one
+async fn respond<T, Strm>(db: &mut Corestore, con: &mut T, result: QueryResult) -> TResult<()>
+where
+    T: ProtocolConnectionExt<Strm>,
+    Strm: Sync + Send + Unpin + AsyncWriteExt + AsyncReadExt,
+{
+    match result {
+        QueryResult::Q(query) => db.execute_query(query, con).await?,
+        QueryResult::E(r) => {
+            con.write_request_id().await?;
+            con.close_conn_with_error(r).await?
+        }
+        QueryResult::Wrongtype => {
+            con.write_request_id().await?;
+            con.close_conn_with_error(responses::groups::WRONGTYPE_ERR.to_owned())
+                .await?
+        }
+        QueryResult::Empty => {}
+    }
+    Ok(())
+}
+
 impl<T, Strm> Drop for ConnectionHandler<T, Strm>
 where
     T: ProtocolConnectionExt<Strm>,
@@ -522,3 +1270,822 @@ where
         self.climit.add_permits(1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::Element;
+    use crate::tests::inproc::{
+        new_con, new_store, old_length, old_usize, output_of, packet_of, run, run_raw, written,
+        TestConnection,
+    };
+    use bytes::Bytes;
+    use skytable::RespCode;
+    use std::io::Cursor;
+
+    #[tokio::test]
+    async fn test_length_headers_match_old_output() {
+        for len in [0, 1, 9, 10, 99, 100, 12345, usize::MAX].iter().copied() {
+            let mut con = new_con();
+            con.write_array_length(len).await.unwrap();
+            con.write_flat_array_length(len).await.unwrap();
+            con.write_response(len).await.unwrap();
+            con.write_response(len as u64).await.unwrap();
+            con.flush_stream().await.unwrap();
+            let mut expected = old_length('&', len);
+            expected.extend(old_length('_', len));
+            expected.extend(old_usize(len));
+            expected.extend(old_usize(len));
+            assert_eq!(written(&con), &expected[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_respcodes_match_old_output() {
+        let mut con = new_con();
+        con.write_response(RespCode::NotFound).await.unwrap();
+        con.write_response(RespCode::ErrorString("protected-key".to_owned()))
+            .await
+            .unwrap();
+        con.flush_stream().await.unwrap();
+        assert_eq!(written(&con), b"!1\n1\n!13\nprotected-key\n");
+    }
+
+    #[tokio::test]
+    async fn test_scratch_is_reused() {
+        let mut con = new_con();
+        let capacity = con.scratch.capacity();
+        for len in 0..1000 {
+            con.write_array_length(len).await.unwrap();
+        }
+        // the scratch buffer never had to grow
+        assert_eq!(con.scratch.capacity(), capacity);
+    }
+
+    /// A set of queries along with the output that they had before frames were assembled in
+    /// the scratch buffer (when they're run in order)
+    fn action_matrix() -> Vec<(Vec<&'static str>, Vec<u8>)> {
+        fn output(parts: &[&[u8]]) -> Vec<u8> {
+            let mut ret = SIMPLE_QUERY_HEADER.to_vec();
+            parts.iter().for_each(|part| ret.extend_from_slice(part));
+            ret
+        }
+        vec![
+            (vec!["HEYA"], output(&[responses::groups::HEYA])),
+            (vec!["SET", "x", "100"], output(&[responses::groups::OKAY])),
+            (
+                vec!["MSET", "y", "200", "z", "300"],
+                output(&[&old_usize(2)]),
+            ),
+            (
+                vec!["MGET", "x", "nope", "z"],
+                output(&[
+                    &old_length('&', 3),
+                    b"+3\n100\n",
+                    responses::groups::NIL,
+                    b"+3\n300\n",
+                ]),
+            ),
+            (vec!["EXISTS", "x", "y", "nope"], output(&[&old_usize(2)])),
+            (vec!["DEL", "x", "y"], output(&[&old_usize(2)])),
+            (vec!["DBSIZE"], output(&[&old_usize(1)])),
+            (
+                vec!["POP", "z", "nope"],
+                output(&[&old_length('&', 2), b"+3\n300\n", responses::groups::NIL]),
+            ),
+            (vec!["GET", "z"], output(&[responses::groups::NIL])),
+        ]
+    }
+
+    #[tokio::test]
+    async fn test_action_output_matches_old_output() {
+        let mut db = new_store();
+        for (query, expected) in action_matrix() {
+            let mut con = new_con();
+            let query = Element::FlatArray(
+                query
+                    .iter()
+                    .map(|arg| Bytes::copy_from_slice(arg.as_bytes()))
+                    .collect(),
+            );
+            db.execute_query(Query::SimpleQuery(query), &mut con)
+                .await
+                .unwrap();
+            assert_eq!(written(&con), &expected[..]);
+        }
+    }
+
+    /// Read a query from the connection, assuming that there is one
+    async fn read_one(con: &mut TestConnection) -> Query {
+        match con.read_query().await.unwrap() {
+            QueryResult::Q(query) => query,
+            _ => panic!("Failed to read a query"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_action_output_over_the_wire_matches_old_output() {
+        let mut db = new_store();
+        for (query, expected) in action_matrix() {
+            let packet = packet_of(&query);
+            let mut con = TestConnection::new(Cursor::new(packet.clone()));
+            let query = read_one(&mut con).await;
+            assert!(con.buffer.is_empty());
+            db.execute_query(query, &mut con).await.unwrap();
+            // the output is written right after the query on the same cursor
+            assert_eq!(&written(&con)[packet.len()..], &expected[..]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_array_writer_counts_the_elements() {
+        let element = || crate::resp::BytesWrapper(Bytes::from_static(b"x"));
+        // every element is there
+        let mut con = new_con();
+        let mut array = con.start_array(2).await.unwrap();
+        array.write(element()).await.unwrap();
+        assert_eq!(array.remaining(), 1);
+        array.write_element(TriState::Null).await.unwrap();
+        assert!(array.finish().is_ok());
+        assert!(!con.is_desynced());
+        // one is missing
+        let mut con = new_con();
+        let mut array = con.start_flat_array(2).await.unwrap();
+        array.write(element()).await.unwrap();
+        assert!(array.finish().is_err());
+        assert!(con.is_desynced());
+        // an early return drops the writer
+        let mut con = new_con();
+        drop(con.start_array(1).await.unwrap());
+        assert!(con.is_desynced());
+        // one too many
+        let mut con = new_con();
+        let mut array = con.start_array(1).await.unwrap();
+        array.write(element()).await.unwrap();
+        assert!(array.write(element()).await.is_err());
+        drop(array);
+        assert!(con.is_desynced());
+        // a nested array is one element of its parent
+        let mut con = new_con();
+        let mut array = con.start_array(1).await.unwrap();
+        let mut nested = array.start_flat_array(1).await.unwrap();
+        nested.write(element()).await.unwrap();
+        nested.finish().unwrap();
+        assert!(array.finish().is_ok());
+        assert!(!con.is_desynced());
+        assert_eq!(written(&con), &b"&1\n_1\n+1\nx\n"[..]);
+    }
+
+    #[tokio::test]
+    async fn test_desynced_connections_are_closed() {
+        let db = new_store();
+        let mut packet = packet_of(&["SYS", "DESYNC"]);
+        packet.extend(packet_of(&["HEYA"]));
+        let con = TestConnection::new(Cursor::new(packet.clone()));
+        let (signal, _) = tokio::sync::broadcast::channel(1);
+        let (term_tx, _term_rx) = mpsc::channel(1);
+        let mut handler: ConnectionHandler<TestConnection, Cursor<Vec<u8>>> =
+            ConnectionHandler::new(
+                db,
+                con,
+                Arc::new(Semaphore::new(0)),
+                Terminator::new(signal.subscribe()),
+                term_tx,
+            );
+        handler.run().await.unwrap();
+        // the HEYA was never answered, so the client can't mistake its response for the rest
+        // of the array
+        let mut expected = SIMPLE_QUERY_HEADER.to_vec();
+        expected.extend(old_length('&', 3));
+        expected.extend_from_slice(b"+5\nfirst\n");
+        assert_eq!(&written(&handler.con)[packet.len()..], &expected[..]);
+    }
+
+    #[tokio::test]
+    async fn test_read_query_leaves_the_next_query_in_the_buffer() {
+        let mut packet = packet_of(&["SET", "x", "100"]);
+        let next = packet_of(&["GET", "x"]);
+        packet.extend_from_slice(&next[..next.len() - 3]);
+        let mut con = TestConnection::new(Cursor::new(packet));
+        assert_eq!(
+            read_one(&mut con).await,
+            Query::SimpleQuery(Element::FlatArray(vec![
+                Bytes::from("SET"),
+                Bytes::from("x"),
+                Bytes::from("100")
+            ]))
+        );
+        assert_eq!(&con.buffer[..], &next[..next.len() - 3]);
+    }
+
+    #[tokio::test]
+    #[cfg(not(target_env = "msvc"))]
+    async fn test_reading_a_large_mget_does_not_copy_the_keys() {
+        use crate::util::alloc;
+        let keys: Vec<String> = (0..10_000).map(|i| format!("key{}", i)).collect();
+        let mut query = vec!["MGET"];
+        query.extend(keys.iter().map(String::as_str));
+        let packet = packet_of(&query);
+        // copying the elements out of the buffer allocates for every one of them
+        let before = alloc::allocations();
+        let (copied, _) = protocol::Parser::new(&packet).parse().unwrap();
+        let copying = alloc::allocations() - before;
+        assert!(copying > query.len());
+        let mut con = TestConnection::new(Cursor::new(packet));
+        let before = alloc::allocations();
+        let read = read_one(&mut con).await;
+        // growing the read buffer and the array of elements is all that's left
+        let zero_copy = alloc::allocations() - before;
+        assert!(
+            zero_copy < 64,
+            "reading the query made {} allocations",
+            zero_copy
+        );
+        assert_eq!(read, copied);
+    }
+
+    #[tokio::test]
+    async fn test_empty_values_round_trip_as_empty_strings() {
+        let mut db = new_store();
+        // the empty value is a zero-length element on the wire, and is read back as one
+        let packet = packet_of(&["SET", "x", ""]);
+        assert!(packet.ends_with(b"+1\nx\n+0\n\n"));
+        let mut con = TestConnection::new(Cursor::new(packet.clone()));
+        let query = read_one(&mut con).await;
+        assert_eq!(
+            query,
+            Query::SimpleQuery(Element::FlatArray(vec![
+                Bytes::from("SET"),
+                Bytes::from("x"),
+                Bytes::new()
+            ]))
+        );
+        db.execute_query(query, &mut con).await.unwrap();
+        assert_eq!(
+            &written(&con)[packet.len()..],
+            &output_of(responses::groups::OKAY)[..]
+        );
+        // and it's written out as a zero-length string, which is never confused with NIL
+        let empty = output_of(b"+0\n\n");
+        let nil = output_of(responses::groups::NIL);
+        assert_ne!(empty, nil);
+        let mut con = new_con();
+        assert_eq!(run(&mut db, &mut con, &["GET", "x"]).await, empty);
+        assert_eq!(run(&mut db, &mut con, &["GETDEL", "x"]).await, empty);
+        assert_eq!(run(&mut db, &mut con, &["GETDEL", "x"]).await, nil);
+        assert_eq!(run(&mut db, &mut con, &["GET", "x"]).await, nil);
+    }
+
+    #[tokio::test]
+    async fn test_retry_hints() {
+        use crate::registry::DirtyTracker;
+        let tracker: &'static DirtyTracker = Box::leak(Box::new(DirtyTracker::default()));
+        registry::override_dirty_tracker(Some(tracker));
+        let mut db = new_store();
+        let mut con = new_con();
+        let mut plain = new_con();
+        assert_eq!(
+            run(
+                &mut db,
+                &mut con,
+                &["HANDSHAKE", "retryhints", "timeout:50"]
+            )
+            .await,
+            b"*1\n_2\n+10\nretryhints\n+10\ntimeout:50\n".to_vec()
+        );
+        let create = ["CREATE", "TABLE", "default:hinted", "keymap(str,str)"];
+        run(&mut db, &mut con, &create).await;
+        for con in [&mut plain, &mut con].iter_mut() {
+            run(&mut db, con, &["USE", "default:hinted"]).await;
+        }
+        // an encoding error isn't worth retrying, so it doesn't get a hint
+        let bad_key: &[u8] = b"Hello \xF0\x90\x80World";
+        let ifeq: [&[u8]; 5] = [b"IFEQ", bad_key, b"old", b"SET", b"new"];
+        assert_eq!(
+            run_raw(&mut db, &mut con, &ifeq).await,
+            output_of(responses::groups::ENCODING_ERROR)
+        );
+        // a write that is held back gives up once the connection's timeout runs out, and it
+        // gets a hint that suggests waiting for at least the recheck interval
+        tracker.configure(16, false);
+        tracker.add(32);
+        let hinted = run(&mut db, &mut con, &["SET", "x", "100"]).await;
+        let hinted = String::from_utf8(hinted).unwrap();
+        let mut lines = hinted.lines();
+        assert_eq!(lines.next(), Some("*1"));
+        // the hint is a part of the error frame
+        let len: usize = lines.next().unwrap()[1..].parse().unwrap();
+        let error = lines.next().unwrap();
+        assert_eq!(error.len(), len);
+        let after: u64 = error
+            .strip_prefix("server-busy-writes;retry=")
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(after >= 100);
+        // rejected writes get the hint too, while the connection that didn't ask for hints
+        // gets the plain response code
+        tracker.configure(16, true);
+        let rejected = run(&mut db, &mut con, &["SET", "x", "100"]).await;
+        assert!(String::from_utf8(rejected)
+            .unwrap()
+            .contains("server-busy-writes;retry="));
+        assert_eq!(
+            run(&mut db, &mut plain, &["SET", "x", "100"]).await,
+            output_of(responses::groups::SERVER_BUSY_WRITES)
+        );
+        tracker.sub(32);
+        assert_eq!(
+            run(&mut db, &mut con, &["SET", "x", "100"]).await,
+            output_of(responses::groups::OKAY)
+        );
+        registry::override_dirty_tracker(None);
+    }
+
+    /// Split a compressed frame into its uncompressed length and its block
+    fn parse_frame(frame: &[u8]) -> (usize, &[u8]) {
+        assert_eq!(frame[0], compression::TSYMBOL_COMPRESSED);
+        let mut lines = frame[1..].splitn(3, |b| *b == b'\n');
+        let mut next_usize = || {
+            String::from_utf8(lines.next().unwrap().to_vec())
+                .unwrap()
+                .parse::<usize>()
+                .unwrap()
+        };
+        let (uncompressed_len, compressed_len) = (next_usize(), next_usize());
+        let block = lines.next().unwrap();
+        assert_eq!(block.len(), compressed_len);
+        (uncompressed_len, block)
+    }
+
+    #[tokio::test]
+    async fn test_compression_is_negotiated_per_connection() {
+        let mut db = new_store();
+        let value = "skytable ".repeat(10_000);
+        let mut con = new_con();
+        // the handshake response itself is never compressed
+        assert_eq!(
+            run(&mut db, &mut con, &["HANDSHAKE", "compress:lz4"]).await,
+            b"*1\n_1\n+12\ncompress:lz4\n".to_vec()
+        );
+        assert_eq!(
+            run(&mut db, &mut con, &["SET", "big", value.as_str()]).await,
+            output_of(responses::groups::OKAY)
+        );
+        // a large response is sent as a compressed frame
+        let frame = run(&mut db, &mut con, &["GET", "big"]).await;
+        let (uncompressed_len, block) = parse_frame(&frame);
+        assert!(block.len() < value.len());
+        let expected = format!("*1\n+{}\n{}\n", value.len(), value).into_bytes();
+        assert_eq!(uncompressed_len, expected.len());
+        assert_eq!(
+            compression::decompress(block, uncompressed_len).unwrap(),
+            expected
+        );
+        // a response under the threshold is sent as is
+        run(&mut db, &mut con, &["SET", "small", "100"]).await;
+        assert_eq!(
+            run(&mut db, &mut con, &["GET", "small"]).await,
+            b"*1\n+3\n100\n".to_vec()
+        );
+        // a connection that never negotiated compression gets the plain response
+        let mut plain = new_con();
+        assert_eq!(run(&mut db, &mut plain, &["GET", "big"]).await, expected);
+    }
+
+    #[tokio::test]
+    async fn test_large_responses_are_not_held_in_memory() {
+        let mut db = new_store();
+        let value = "skytable ".repeat(100);
+        let keys: Vec<String> = (0..1000).map(|i| format!("large_{}", i)).collect();
+        let mut con = new_con();
+        run(&mut db, &mut con, &["HANDSHAKE", "compress:lz4"]).await;
+        for key in keys.iter() {
+            run(&mut db, &mut con, &["SET", key.as_str(), value.as_str()]).await;
+        }
+        let mut mget = vec!["MGET"];
+        mget.extend(keys.iter().map(|key| key.as_str()));
+        let mut expected = format!("&{}\n", keys.len());
+        for _ in keys.iter() {
+            expected.push_str(&format!("+{}\n{}\n", value.len(), value));
+        }
+        let expected = output_of(expected.as_bytes());
+        assert!(expected.len() > 3 * RESPONSE_FLUSH_THRESHOLD);
+        // the response was too large to buffer, so it went out uncompressed as it was written
+        assert_eq!(run(&mut db, &mut con, &mget).await, expected);
+        assert!(con.client.peak_buffered() < RESPONSE_FLUSH_THRESHOLD + 16 * 1024);
+        assert_eq!(con.client.buffered(), 0);
+        // the next response can be compressed again
+        let frame = run(&mut db, &mut con, &mget[..50]).await;
+        let (uncompressed_len, block) = parse_frame(&frame);
+        assert_eq!(
+            compression::decompress(block, uncompressed_len).unwrap()[..3],
+            b"*1\n"[..]
+        );
+        // and without compression, nothing but the stream's buffer is held on to
+        let mut plain = new_con();
+        assert_eq!(run(&mut db, &mut plain, &mget).await, expected);
+        assert!(plain.client.peak_buffered() < 16 * 1024);
+    }
+
+    #[tokio::test]
+    async fn test_handshake_rejects_unknown_capabilities() {
+        let mut db = new_store();
+        let mut con = new_con();
+        assert_eq!(
+            run(
+                &mut db,
+                &mut con,
+                &["HANDSHAKE", "compress:zstd", "teleport"]
+            )
+            .await,
+            b"*1\n_0\n".to_vec()
+        );
+        assert!(con.get_capabilities().compression.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_tristate_elements_are_negotiated() {
+        use crate::resp::tristate::{self, TriState};
+        let mut db = new_store();
+        let mut plain = new_con();
+        let mut con = new_con();
+        let create = ["CREATE", "TABLE", "default:tristate", "keymap(str,binstr)"];
+        run(&mut db, &mut plain, &create).await;
+        for con in [&mut plain, &mut con].iter_mut() {
+            run(&mut db, con, &["USE", "default:tristate"]).await;
+        }
+        assert_eq!(
+            run(&mut db, &mut con, &["HANDSHAKE", "tristate"]).await,
+            b"*1\n_1\n+8\ntristate\n".to_vec()
+        );
+        assert!(con.get_capabilities().tristate && !plain.get_capabilities().tristate);
+        let bad_key: &[u8] = b"Hello \xF0\x90\x80World";
+        // (action, without the capability, with it)
+        let expected: [(&str, &[u8], &[u8]); 2] = [
+            (
+                "MGET",
+                b"&3\n+3\n100\n!1\n1\n!1\n1\n",
+                b"&3\n+3\n100\n-\n^1\n9\n",
+            ),
+            (
+                "POP",
+                b"&3\n+3\n100\n!1\n1\n!1\n9\n",
+                b"&3\n+3\n100\n-\n^1\n9\n",
+            ),
+        ];
+        for (action, legacy, negotiated) in expected.iter() {
+            let query: [&[u8]; 4] = [action.as_bytes(), b"x", b"absent", bad_key];
+            run(&mut db, &mut plain, &["SET", "x", "100"]).await;
+            assert_eq!(
+                run_raw(&mut db, &mut plain, &query).await,
+                output_of(legacy)
+            );
+            run(&mut db, &mut plain, &["SET", "x", "100"]).await;
+            let output = run_raw(&mut db, &mut con, &query).await;
+            assert_eq!(output, output_of(negotiated));
+            assert_eq!(
+                tristate::decode_array(&output[SIMPLE_QUERY_HEADER.len()..]),
+                vec![
+                    TriState::Value(Bytes::from("100")),
+                    TriState::Null,
+                    TriState::Error(responses::groups::ENCODING_ERROR)
+                ]
+            );
+        }
+        // the response codes in legacy arrays are decoded as errors
+        assert_eq!(
+            tristate::decode_array(b"&3\n+3\n100\n!1\n1\n!1\n9\n"),
+            vec![
+                TriState::Value(Bytes::from("100")),
+                TriState::Error(responses::groups::NIL),
+                TriState::Error(responses::groups::ENCODING_ERROR)
+            ]
+        );
+        // single-key actions are never affected
+        assert_eq!(
+            run(&mut db, &mut con, &["GETDEL", "absent"]).await,
+            output_of(responses::groups::NIL)
+        );
+    }
+
+    #[test]
+    fn test_peers_are_told_apart() {
+        let local = new_con();
+        let remote = new_con().with_peer_addr("127.0.0.1:49152".parse().unwrap());
+        let (local, remote) = (local.get_peer(), remote.get_peer());
+        assert_ne!(local.id, remote.id);
+        assert_eq!(local.to_string(), format!("#{} local", local.id));
+        assert_eq!(
+            remote.to_string(),
+            format!("#{} 127.0.0.1:49152", remote.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trace_records_the_stages_of_traced_queries() {
+        let mut db = new_store();
+        let mut con = new_con();
+        let id = con.get_peer().id;
+        let traces = || -> Vec<(String, usize)> {
+            registry::get_tracer()
+                .dump()
+                .into_iter()
+                .filter(|record| record.connection == id)
+                .map(|record| (record.action, record.args))
+                .collect()
+        };
+        let okay = output_of(responses::groups::OKAY);
+        // nothing is timed until tracing is turned on
+        send(&mut db, &mut con, &["SET", "x", "100"]).await;
+        assert!(traces().is_empty());
+        assert_eq!(send(&mut db, &mut con, &["SYS", "TRACE", "ON"]).await, okay);
+        send(&mut db, &mut con, &["GET", "x"]).await;
+        send(&mut db, &mut con, &["MGET", "x", "y"]).await;
+        assert_eq!(
+            traces(),
+            vec![("GET".to_owned(), 1), ("MGET".to_owned(), 2)]
+        );
+        // every record has the connection, the action, the argument count and four timings
+        let dump = send(&mut db, &mut con, &["SYS", "TRACE", "DUMP"]).await;
+        let total = registry::get_tracer().dump().len() - 1;
+        assert!(dump.starts_with(&output_of(&old_length('_', total * 7))));
+        let get = format!("+{}\n{}\n+3\nGET\n+1\n1\n", id.to_string().len(), id);
+        assert!(String::from_utf8(dump).unwrap().contains(&get));
+        // turning it off stops the collection (but the query that turned it off is traced)
+        assert_eq!(
+            send(&mut db, &mut con, &["SYS", "TRACE", "OFF"]).await,
+            okay
+        );
+        send(&mut db, &mut con, &["GET", "x"]).await;
+        assert_eq!(traces().len(), 4);
+        assert_eq!(traces()[3], ("SYS".to_owned(), 2));
+        assert!(con.trace.is_none());
+        // tracing can be turned on for another connection too
+        let other = new_con();
+        let other_id = other.get_peer().id.to_string();
+        assert_eq!(
+            send(&mut db, &mut con, &["SYS", "TRACE", &other_id, "ON"]).await,
+            okay
+        );
+        assert!(other.is_traced() && !con.is_traced());
+        drop(other);
+        assert_eq!(
+            send(&mut db, &mut con, &["SYS", "TRACE", &other_id, "ON"]).await,
+            output_of(responses::groups::NIL)
+        );
+    }
+
+    /// Hand `query` to the connection the way that a client would send it, then run it and
+    /// return whatever it wrote out
+    async fn send(db: &mut Corestore, con: &mut TestConnection, query: &[&str]) -> Vec<u8> {
+        let already_written = written(con).len();
+        con.buffer.extend_from_slice(&packet_of(query));
+        let query = con.try_query().unwrap();
+        db.execute_query(query, con).await.unwrap();
+        written(con)[already_written..].to_vec()
+    }
+
+    /// Tag `packet` (or `response`) with the request id `id`
+    fn tagged(id: u64, packet: &[u8]) -> Vec<u8> {
+        let mut ret = format!("#{}\n", id).into_bytes();
+        ret.extend_from_slice(packet);
+        ret
+    }
+
+    #[tokio::test]
+    async fn test_responses_carry_the_request_ids_of_their_queries() {
+        use crate::actions::Arity;
+        let mut db = new_store();
+        let mut con = new_con();
+        assert_eq!(
+            run(&mut db, &mut con, &["HANDSHAKE", "reqids"]).await,
+            b"*1\n_1\n+6\nreqids\n".to_vec()
+        );
+        // pipeline tagged and untagged queries, ending with a malformed one
+        let mut packets = tagged(7, &packet_of(&["SET", "x", "100"]));
+        packets.extend(packet_of(&["GET", "x"]));
+        packets.extend(tagged(u64::MAX, &packet_of(&["GET", "x"])));
+        packets.extend(tagged(9, &packet_of(&["GET"])));
+        packets.extend(tagged(11, b"*1\n%1\n"));
+        con.buffer.extend_from_slice(&packets);
+        let already_written = written(&con).len();
+        for _ in 0..5 {
+            let result = QueryResult::from_parsed(con.try_query()).unwrap();
+            respond(&mut db, &mut con, result).await.unwrap();
+        }
+        let mut expected = tagged(7, &output_of(responses::groups::OKAY));
+        expected.extend(output_of(b"+3\n100\n"));
+        expected.extend(tagged(u64::MAX, &output_of(b"+3\n100\n")));
+        expected.extend(tagged(9, &output_of(&Arity::Exactly(1).error())));
+        expected.extend(tagged(11, responses::full_responses::R_UNKNOWN_DATA_TYPE));
+        assert_eq!(&written(&con)[already_written..], &expected[..]);
+        // the id is held on to while the rest of the query is on its way
+        con.buffer.clear();
+        let packet = tagged(5, &packet_of(&["HEYA"]));
+        let (head, tail) = packet.split_at(packet.len() - 3);
+        con.buffer.extend_from_slice(head);
+        assert_eq!(con.try_query(), Err(ParseError::NotEnough));
+        assert_eq!(con.request_id, Some(5));
+        con.buffer.extend_from_slice(tail);
+        let already_written = written(&con).len();
+        let query = con.try_query().unwrap();
+        db.execute_query(query, &mut con).await.unwrap();
+        assert_eq!(
+            &written(&con)[already_written..],
+            &tagged(5, &output_of(responses::groups::HEYA))[..]
+        );
+        assert_eq!(con.request_id, None);
+        // and without the capability, a tagged query is malformed
+        let mut plain = new_con();
+        plain
+            .buffer
+            .extend_from_slice(&tagged(1, &packet_of(&["HEYA"])));
+        assert!(matches!(
+            QueryResult::from_parsed(plain.try_query()),
+            Some(QueryResult::E(err)) if err == responses::full_responses::R_PACKET_ERR
+        ));
+    }
+
+    #[test]
+    fn test_split_reads_hold_on_to_the_request_ids() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+        // tag every other frame, so that some cuts land inside the tags
+        let frames: Vec<(Option<u64>, Vec<u8>)> = protocol::tests::corpus()
+            .into_iter()
+            .enumerate()
+            .map(|(i, frame)| match i % 2 {
+                0 => (Some(i as u64 * 1000), tagged(i as u64 * 1000, &frame)),
+                _ => (None, frame),
+            })
+            .collect();
+        let stream: Vec<u8> = frames.iter().flat_map(|(_, f)| f.clone()).collect();
+        let expected: Vec<(Option<u64>, Query)> = frames
+            .iter()
+            .map(|(id, frame)| {
+                let untagged = match id {
+                    Some(id) => &frame[format!("#{}\n", id).len()..],
+                    None => &frame[..],
+                };
+                (*id, protocol::Parser::new(untagged).parse().unwrap().0)
+            })
+            .collect();
+        for seed in 0..50 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut cuts: Vec<usize> = (0..rng.gen_range(1..64))
+                .map(|_| rng.gen_range(0..=stream.len()))
+                .collect();
+            cuts.sort_unstable();
+            cuts.push(stream.len());
+            let mut con = new_con();
+            con.capabilities.request_ids = true;
+            let mut read = Vec::new();
+            let mut start = 0;
+            for cut in cuts {
+                con.buffer.extend_from_slice(&stream[start..cut]);
+                start = cut;
+                loop {
+                    match con.try_query() {
+                        // the id is taken once the response is written
+                        Ok(query) => read.push((con.request_id.take(), query)),
+                        Err(ParseError::NotEnough) | Err(ParseError::Empty) => break,
+                        Err(e) => panic!("{:?} with seed {}", e, seed),
+                    }
+                }
+            }
+            assert!(con.buffer.is_empty());
+            assert_eq!(con.request_id, None);
+            assert_eq!(read, expected, "seed {}", seed);
+        }
+    }
+
+    /// Answer every query that `con` has to read, returning how many responses were held back
+    /// after each one of them
+    async fn answer_pipelined(db: &mut Corestore, con: &mut TestConnection) -> Vec<usize> {
+        let mut held_back = Vec::new();
+        loop {
+            match con.read_query().await.unwrap() {
+                QueryResult::Empty => break held_back,
+                result => respond(db, con, result).await.unwrap(),
+            }
+            assert_eq!(con.client.inflight() == 0, con.stream.buffer().is_empty());
+            held_back.push(con.client.inflight());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pipelined_responses_are_held_back_up_to_the_limits() {
+        use crate::registry::PipelineLimits;
+        let limits: &'static PipelineLimits = Box::leak(Box::new(PipelineLimits::new()));
+        registry::override_pipeline_limits(Some(limits));
+        let mut db = new_store();
+        let mut con = new_con();
+        run(&mut db, &mut con, &["SET", "x", "100"]).await;
+        let response = output_of(b"+3\n100\n");
+        let pipelined: Vec<u8> = (0..8).flat_map(|_| packet_of(&["GET", "x"])).collect();
+        // by the number of responses
+        limits.configure(3, usize::MAX, 0);
+        con.buffer.extend_from_slice(&pipelined);
+        let already_written = written(&con).len();
+        assert_eq!(
+            answer_pipelined(&mut db, &mut con).await,
+            [1, 2, 0, 1, 2, 0, 1, 0]
+        );
+        let expected: Vec<u8> = (0..8).flat_map(|_| response.clone()).collect();
+        assert_eq!(&written(&con)[already_written..], &expected[..]);
+        // by the bytes of the responses
+        limits.configure(usize::MAX, response.len() * 2 + 1, 0);
+        con.buffer.extend_from_slice(&pipelined);
+        assert_eq!(
+            answer_pipelined(&mut db, &mut con).await,
+            [1, 2, 0, 1, 2, 0, 1, 0]
+        );
+        // a query that wasn't pipelined is always answered right away
+        limits.configure(3, usize::MAX, 0);
+        con.buffer.extend_from_slice(&packet_of(&["GET", "x"]));
+        assert_eq!(answer_pipelined(&mut db, &mut con).await, [0]);
+        registry::override_pipeline_limits(None);
+    }
+
+    #[tokio::test]
+    async fn test_use_takes_effect_in_the_middle_of_a_batch() {
+        let mut db = new_store();
+        let mut con = new_con();
+        let setup: [&[&str]; 6] = [
+            &["CREATE", "TABLE", "default:planned", "keymap(str,str)"],
+            &["CREATE", "KEYSPACE", "plannedks"],
+            &["SET", "x", "one"],
+            &["USE", "default:planned"],
+            &["SET", "x", "two"],
+            &["USE", "default:default"],
+        ];
+        for query in setup.iter() {
+            run(&mut db, &mut con, query).await;
+        }
+        // what a keyspace without a default table answers, one query at a time
+        let mut other_db = db.clone();
+        let mut other = new_con();
+        run(&mut other_db, &mut other, &["USE", "plannedks"]).await;
+        let unset = run(&mut other_db, &mut other, &["GET", "x"]).await;
+        let (one, two) = (output_of(b"+3\none\n"), output_of(b"+3\ntwo\n"));
+        assert_ne!(unset, two);
+        let okay = output_of(responses::groups::OKAY);
+        // the batches are kept short enough for the pipeline limits of the other tests
+        let into_planned: [&[&str]; 4] = [
+            &["GET", "x"],
+            &["USE", "default:planned"],
+            &["GET", "x"],
+            &["GET", "x"],
+        ];
+        assert_eq!(
+            answer_batch(&mut db, &mut con, &into_planned).await,
+            [one, okay.clone(), two.clone(), two.clone()].concat()
+        );
+        let into_keyspace: [&[&str]; 3] = [&["GET", "x"], &["USE", "plannedks"], &["GET", "x"]];
+        assert_eq!(
+            answer_batch(&mut db, &mut con, &into_keyspace).await,
+            [two, okay, unset].concat()
+        );
+    }
+
+    /// Send `queries` in one go and answer them, returning the responses
+    async fn answer_batch(
+        db: &mut Corestore,
+        con: &mut TestConnection,
+        queries: &[&[&str]],
+    ) -> Vec<u8> {
+        let pipelined: Vec<u8> = queries.iter().flat_map(|query| packet_of(query)).collect();
+        con.buffer.extend_from_slice(&pipelined);
+        let already_written = written(con).len();
+        answer_pipelined(db, con).await;
+        // the plan was thrown away before reading again
+        assert!(!con.plan.has_kvstore());
+        written(con)[already_written..].to_vec()
+    }
+
+    #[tokio::test]
+    async fn test_pipelining_too_deep_closes_the_connection() {
+        use crate::registry::PipelineLimits;
+        let limits: &'static PipelineLimits = Box::leak(Box::new(PipelineLimits::new()));
+        registry::override_pipeline_limits(Some(limits));
+        limits.configure(2, usize::MAX, 4);
+        let mut db = new_store();
+        let mut con = new_con();
+        let pipelined: Vec<u8> = (0..4).flat_map(|_| packet_of(&["HEYA"])).collect();
+        // as deep as it may go
+        con.buffer.extend_from_slice(&pipelined);
+        assert_eq!(answer_pipelined(&mut db, &mut con).await, [1, 0, 1, 0]);
+        // the depth starts over once the connection has had to wait for more queries
+        con.buffer.extend_from_slice(&pipelined);
+        con.buffer.extend_from_slice(&packet_of(&["HEYA"]));
+        let already_written = written(&con).len();
+        assert_eq!(answer_pipelined(&mut db, &mut con).await, [1, 0, 1, 0]);
+        // the responses to the queries ahead of the one that went too deep are all written
+        let expected: Vec<u8> = (0..4)
+            .flat_map(|_| output_of(responses::groups::HEYA))
+            .collect();
+        assert_eq!(&written(&con)[already_written..], &expected[..]);
+        registry::override_pipeline_limits(None);
+    }
+}
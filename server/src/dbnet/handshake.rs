@@ -0,0 +1,157 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Handshakes
+//!
+//! Clients negotiate optional protocol capabilities for a connection with a
+//! `HANDSHAKE <capability> ...` query, ideally right after connecting. Capabilities are of
+//! the form `<name>[:<argument>]` and the server replies with a flat array of the
+//! capabilities that it accepted (in the same form), leaving out the ones it doesn't know
+//! about or doesn't support. Accepted capabilities take effect from the next query on.
+//!
+//! The capabilities are:
+//! - `compress:<algorithm>`: compress responses that are larger than the server's threshold
+//!   (see [`compression`](super::compression)). The only algorithm is `lz4`
+//! - `tristate`: write the elements of multi-key actions' array responses as a value, null or
+//!   an error with a code (see [`tristate`](crate::resp::tristate)), so that clients can tell
+//!   a missing key from a failed one without parsing response codes inside arrays
+//! - `reqids`: queries may be tagged with a request id by prefixing them with an id frame
+//!   (`#<id>\n`, where the id is an unsigned 64-bit integer). The response to a tagged query
+//!   (even if it's an error, like one for a malformed query) is prefixed with the same id frame,
+//!   so that clients can match up responses without relying on their order alone. Queries
+//!   that aren't tagged get their responses without an id, and responses are still written in
+//!   the order that the queries were sent in. If the response is compressed, the id frame is a
+//!   part of the compressed data
+//! - `retryhints`: the errors that are worth retrying carry a hint after their code (see
+//!   [`retry`](crate::resp::retry)), with the time after which a retry should go through if
+//!   the server can tell
+//! - `timeout:<ms>`: the default timeout of the connection, in milliseconds: a write that's
+//!   held back by backpressure gives up after this long (with `server-busy-writes`) instead of
+//!   waiting for as long as it takes, and it's the timeout of `SYS FLUSHWAIT` if the query
+//!   doesn't give one
+
+use super::compression::Algorithm;
+use crate::registry;
+use std::time::Duration;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+/// The capabilities that were negotiated for a connection
+pub struct Capabilities {
+    /// Compress responses (disabled if `None`)
+    pub compression: Option<Compression>,
+    /// Use the tristate encoding for the elements of multi-key actions' responses
+    pub tristate: bool,
+    /// Accept queries that are tagged with request ids
+    pub request_ids: bool,
+    /// Write retry hints in the errors that are worth retrying
+    pub retry_hints: bool,
+    /// The default timeout of the connection (there's none if `None`)
+    pub default_timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Compress responses that are at least `threshold` bytes long with `algorithm`
+pub struct Compression {
+    pub algorithm: Algorithm,
+    pub threshold: usize,
+}
+
+impl Capabilities {
+    /// Try to enable `capability`. This returns the capability (in its canonical form) if it
+    /// was accepted
+    pub fn negotiate(&mut self, capability: &[u8]) -> Option<String> {
+        let (name, argument) = match capability.iter().position(|byte| *byte == b':') {
+            Some(at) => (&capability[..at], Some(&capability[at + 1..])),
+            None => (capability, None),
+        };
+        if name.eq_ignore_ascii_case(b"compress") {
+            let algorithm = Algorithm::from_name(argument?)?;
+            let settings = registry::get_compression();
+            if settings.get_algorithm() != Some(algorithm) {
+                return None;
+            }
+            self.compression = Some(Compression {
+                algorithm,
+                threshold: settings.get_threshold(),
+            });
+            Some(concat_str!("compress:", algorithm.as_str()))
+        } else if name.eq_ignore_ascii_case(b"tristate") && argument.is_none() {
+            self.tristate = true;
+            Some("tristate".to_owned())
+        } else if name.eq_ignore_ascii_case(b"reqids") && argument.is_none() {
+            self.request_ids = true;
+            Some("reqids".to_owned())
+        } else if name.eq_ignore_ascii_case(b"retryhints") && argument.is_none() {
+            self.retry_hints = true;
+            Some("retryhints".to_owned())
+        } else if name.eq_ignore_ascii_case(b"timeout") {
+            let millis = String::from_utf8_lossy(argument?).parse::<u64>().ok()?;
+            if millis == 0 {
+                return None;
+            }
+            self.default_timeout = Some(Duration::from_millis(millis));
+            Some(format!("timeout:{}", millis))
+        } else {
+            None
+        }
+    }
+}
+
+#[test]
+fn test_negotiate() {
+    let mut caps = Capabilities::default();
+    assert_eq!(caps.negotiate(b"teleport"), None);
+    assert_eq!(caps.negotiate(b"compress"), None);
+    assert_eq!(caps.negotiate(b"compress:zstd"), None);
+    assert_eq!(caps.compression, None);
+    assert_eq!(caps.negotiate(b"tristate:yes"), None);
+    assert!(!caps.tristate);
+    assert_eq!(caps.negotiate(b"TriState"), Some("tristate".to_owned()));
+    assert!(caps.tristate);
+    assert_eq!(
+        caps.negotiate(b"COMPRESS:LZ4"),
+        Some("compress:lz4".to_owned())
+    );
+    assert_eq!(
+        caps.compression.map(|compression| compression.algorithm),
+        Some(Algorithm::Lz4)
+    );
+    assert_eq!(caps.negotiate(b"reqids:yes"), None);
+    assert!(!caps.request_ids);
+    assert_eq!(caps.negotiate(b"reqids"), Some("reqids".to_owned()));
+    assert!(caps.request_ids);
+    assert_eq!(caps.negotiate(b"RETRYHINTS"), Some("retryhints".to_owned()));
+    assert!(caps.retry_hints);
+    assert_eq!(caps.negotiate(b"timeout"), None);
+    assert_eq!(caps.negotiate(b"timeout:0"), None);
+    assert_eq!(caps.negotiate(b"timeout:soon"), None);
+    assert_eq!(caps.default_timeout, None);
+    assert_eq!(
+        caps.negotiate(b"timeout:250"),
+        Some("timeout:250".to_owned())
+    );
+    assert_eq!(caps.default_timeout, Some(Duration::from_millis(250)));
+}
@@ -0,0 +1,169 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The health listener
+//!
+//! TCP probes only tell if the port is open, which it stays even if the server is poisoned.
+//! If a health port is configured, a connection to it is answered with a single line that
+//! describes the [system state](SystemState) (the same one that `SYS HEALTH` reports) and is
+//! closed right away: there's no handshake and nothing is read from the client, so any load
+//! balancer check or `nc` can use it.
+//!
+//! The listener runs on its own task and doesn't take a permit from the `maxcon` semaphore,
+//! so it keeps answering even if every connection slot is taken. At most [`MAX_CONCURRENT`]
+//! clients are answered at once and a client has [`WRITE_TIMEOUT`] to take its answer
+
+use crate::dbnet::Terminator;
+use crate::registry::SystemState;
+use std::sync::Arc;
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Semaphore;
+use tokio::time::{self, Duration};
+
+/// The most clients that are answered at once
+pub const MAX_CONCURRENT: usize = 16;
+/// How long a client has to take its answer
+const WRITE_TIMEOUT: Duration = Duration::from_secs(1);
+/// How long to wait before accepting again if accepting failed
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// Returns the line that a health check is answered with in `state`
+pub const fn status_line(state: SystemState) -> &'static [u8] {
+    match state {
+        SystemState::Okay => b"OK\n",
+        SystemState::WriteBlocked => b"DEGRADED\n",
+        SystemState::Poisoned => b"POISONED\n",
+    }
+}
+
+/// Answer every connection to `listener` with the [status line](status_line) for what
+/// `state` returns at the time, until a termination signal is received
+pub async fn health_listener(
+    listener: TcpListener,
+    state: fn() -> SystemState,
+    mut terminator: Terminator,
+) {
+    let permits = Arc::new(Semaphore::new(MAX_CONCURRENT));
+    loop {
+        let permit = tokio::select! {
+            permit = permits.clone().acquire_owned() => match permit {
+                Ok(permit) => permit,
+                // we never close the semaphore
+                Err(_) => break,
+            },
+            _ = terminator.receive_signal() => break,
+        };
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = terminator.receive_signal() => break,
+        };
+        match accepted {
+            Ok((stream, _)) => {
+                let line = status_line(state());
+                tokio::spawn(async move {
+                    answer(stream, line).await;
+                    drop(permit);
+                });
+            }
+            Err(e) => {
+                log::warn!("Failed to accept a health check: {}", e);
+                time::sleep(ACCEPT_BACKOFF).await;
+            }
+        }
+    }
+    log::info!("Health listener has exited");
+}
+
+/// Write `line` to the client and close the connection. The client is simply dropped if it
+/// doesn't take the line in time
+async fn answer(mut stream: TcpStream, line: &'static [u8]) {
+    let _ = time::timeout(WRITE_TIMEOUT, async {
+        stream.write_all(line).await?;
+        stream.shutdown().await
+    })
+    .await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::sync::broadcast;
+
+    /// Run a health listener for `state` on a free port, returning its address, the
+    /// shutdown signal and the handle of its task
+    async fn start(
+        state: fn() -> SystemState,
+    ) -> (
+        std::net::SocketAddr,
+        broadcast::Sender<()>,
+        tokio::task::JoinHandle<()>,
+    ) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (signal, _) = broadcast::channel(1);
+        let handle = tokio::spawn(health_listener(
+            listener,
+            state,
+            Terminator::new(signal.subscribe()),
+        ));
+        (addr, signal, handle)
+    }
+
+    async fn check(addr: std::net::SocketAddr) -> Vec<u8> {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let mut ret = Vec::new();
+        stream.read_to_end(&mut ret).await.unwrap();
+        ret
+    }
+
+    #[tokio::test]
+    async fn test_health_okay_and_poisoned() {
+        let (addr, signal, handle) = start(|| SystemState::Okay).await;
+        assert_eq!(check(addr).await, b"OK\n");
+        drop(signal);
+        handle.await.unwrap();
+        let (addr, signal, handle) = start(|| SystemState::Poisoned).await;
+        assert_eq!(check(addr).await, b"POISONED\n");
+        drop(signal);
+        handle.await.unwrap();
+        assert_eq!(status_line(SystemState::WriteBlocked), b"DEGRADED\n");
+    }
+
+    #[tokio::test]
+    async fn test_health_more_clients_than_permits() {
+        let (addr, signal, handle) = start(|| SystemState::Okay).await;
+        let checks: Vec<_> = (0..MAX_CONCURRENT * 2)
+            .map(|_| tokio::spawn(check(addr)))
+            .collect();
+        for check in checks {
+            assert_eq!(check.await.unwrap(), b"OK\n");
+        }
+        drop(signal);
+        handle.await.unwrap();
+    }
+}
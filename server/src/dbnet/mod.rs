@@ -51,11 +51,17 @@ use tls::SslListener;
 use tokio::net::TcpListener;
 use tokio::sync::Semaphore;
 use tokio::sync::{broadcast, mpsc};
+pub mod compression;
 pub mod connection;
+pub mod handshake;
+pub mod health;
 #[macro_use]
 mod macros;
+mod sniff;
 mod tcp;
-mod tls;
+#[cfg(test)]
+pub use tcp::Connection;
+pub mod tls;
 
 pub const MAXIMUM_CONNECTION_LIMIT: usize = 50000;
 
@@ -0,0 +1,306 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Clients on the wrong port
+//!
+//! TLS clients pointed at the plain port (and plain clients pointed at the TLS port) only ever
+//! see an unreadable response or a failed handshake, so both listeners look at the first bytes
+//! of a new connection to tell the user what went wrong. The bytes are only peeked at (they
+//! stay in the socket for the TLS handshake or the query parser), and we only wait for as
+//! long as it takes them to arrive, up to [`SNIFF_TIMEOUT`]. A client that sends its query
+//! right away is handled as soon as the query arrives, just like before, while a client that
+//! dawdles is simply handed over without being sniffed.
+//!
+//! A TLS connection begins with a handshake record, whose first byte is the content type
+//! `0x16` and whose second byte is the major version `0x03`. A Skyhash connection begins
+//! with a query (`*`) or a request id frame (`#`), so a legitimate query can never be
+//! mistaken for a `ClientHello` (or the other way round): anything that doesn't begin with
+//! those bytes is handled exactly as it was before, and the plain listener replies with
+//! [`TLS_ON_PLAIN_PORT`](crate::protocol::responses::groups::TLS_ON_PLAIN_PORT) instead of a
+//! protocol error to a connection that does. The TLS listener can't reply to a plaintext
+//! client in a way that the client would understand either way, so it only logs a clearer
+//! message for a failed handshake.
+//!
+//! The warnings are rate limited (see [`Mismatches`]), since a misconfigured client usually
+//! retries in a loop
+
+use crate::protocol::responses::groups;
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::time;
+
+/// How long we wait for the first bytes of a new connection to arrive
+pub const SNIFF_TIMEOUT: Duration = Duration::from_millis(100);
+/// How long we try to write out the error for a TLS client on the plain port
+const REPLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// The least number of seconds between two warnings for the same kind of mismatch
+const WARN_INTERVAL_SECS: u64 = 10;
+/// As many bytes as we need to tell the protocols apart
+const SNIFF_LEN: usize = 2;
+
+/// The content type of a TLS handshake record
+const TLS_HANDSHAKE: u8 = 0x16;
+/// The major version of every TLS (and SSL 3) record
+const TLS_MAJOR_VERSION: u8 = 0x03;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// What the first bytes of a connection look like
+pub enum Sniffed {
+    /// a TLS handshake record
+    Tls,
+    /// a Skyhash query (or a request id frame)
+    Skyhash,
+    /// anything else (including nothing at all, if nothing arrived in time)
+    Unknown,
+}
+
+impl Sniffed {
+    /// Tell what the first bytes of a connection are
+    pub fn classify(first: &[u8]) -> Self {
+        match first {
+            [TLS_HANDSHAKE, TLS_MAJOR_VERSION, ..] => Self::Tls,
+            [b'*', ..] | [b'#', ..] => Self::Skyhash,
+            _ => Self::Unknown,
+        }
+    }
+    /// Peek at the first bytes of `stream` (without reading them off the socket) and tell what
+    /// they are
+    pub async fn peek(stream: &TcpStream) -> Self {
+        let mut first = [0; SNIFF_LEN];
+        match time::timeout(SNIFF_TIMEOUT, stream.peek(&mut first)).await {
+            Ok(Ok(read)) => Self::classify(&first[..read]),
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// The connections that were opened to the wrong port. The warning for a mismatch is logged
+/// at most once every [`WARN_INTERVAL_SECS`], along with the number of mismatches that
+/// weren't logged
+pub struct Mismatches {
+    /// what is logged for a mismatch
+    warning: &'static str,
+    /// the mismatches so far
+    count: AtomicUsize,
+    /// the mismatches when the last warning was logged
+    warned_count: AtomicUsize,
+    /// the time that the last warning was logged at, in seconds since the epoch
+    warned_at: AtomicU64,
+}
+
+impl Mismatches {
+    pub const fn new(warning: &'static str) -> Self {
+        Self {
+            warning,
+            count: AtomicUsize::new(0),
+            warned_count: AtomicUsize::new(0),
+            warned_at: AtomicU64::new(0),
+        }
+    }
+    /// Count a mismatch for a connection from `peer`, logging a warning if we haven't done so
+    /// in a while
+    pub fn report(&self, peer: SocketAddr) {
+        let count = self.count.fetch_add(1, Ordering::SeqCst) + 1;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let warned_at = self.warned_at.load(Ordering::SeqCst);
+        if now < warned_at + WARN_INTERVAL_SECS && warned_at != 0 {
+            return;
+        }
+        // only one of the connections that race here gets to log
+        if self
+            .warned_at
+            .compare_exchange(warned_at, now, Ordering::SeqCst, Ordering::SeqCst)
+            .is_err()
+        {
+            return;
+        }
+        let suppressed = count - self.warned_count.swap(count, Ordering::SeqCst) - 1;
+        if suppressed == 0 {
+            log::warn!("Connection from {}: {}", peer, self.warning);
+        } else {
+            log::warn!(
+                "Connection from {}: {} ({} more since the last warning)",
+                peer,
+                self.warning,
+                suppressed
+            );
+        }
+    }
+    #[cfg(test)]
+    /// Returns the number of mismatches so far
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+}
+
+/// TLS clients on the plain port
+pub static TLS_ON_PLAIN_PORT: Mismatches = Mismatches::new(
+    "a TLS client connected to the plain (non-TLS) port; \
+    connect without TLS or to the TLS port instead",
+);
+/// Plaintext clients on the TLS port
+pub static PLAIN_ON_TLS_PORT: Mismatches = Mismatches::new(
+    "a plaintext client failed the TLS handshake on the TLS port; \
+    connect with TLS or to the plain port instead",
+);
+
+/// If the connection on the plain port that was accepted from `peer` begins with a TLS
+/// handshake, tell the client (in the clear) that it's on the wrong port and return true. The
+/// connection is then closed by dropping the stream
+pub async fn refuse_tls_client(stream: &mut TcpStream, peer: SocketAddr) -> bool {
+    if Sniffed::peek(stream).await != Sniffed::Tls {
+        return false;
+    }
+    TLS_ON_PLAIN_PORT.report(peer);
+    // the client is most likely waiting for a `ServerHello`, so this is just for whoever
+    // looks at the raw bytes
+    let _ = time::timeout(REPLY_TIMEOUT, async {
+        stream.write_all(groups::TLS_ON_PLAIN_PORT).await?;
+        stream.shutdown().await
+    })
+    .await;
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbnet::tls;
+    use openssl::asn1::Asn1Time;
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::PKey;
+    use openssl::rsa::Rsa;
+    use openssl::ssl::{SslAcceptor, SslConnector, SslMethod, SslVerifyMode};
+    use openssl::x509::{X509NameBuilder, X509};
+    use std::pin::Pin;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+    use tokio_openssl::SslStream;
+
+    /// The start of a `ClientHello` (a handshake record of TLS 1.0, which is what most clients
+    /// put in the record layer)
+    const CLIENT_HELLO: [u8; 6] = [0x16, 0x03, 0x01, 0x00, 0xf1, 0x01];
+
+    /// Connect to a new listener and return both ends
+    async fn pair() -> (TcpStream, (TcpStream, SocketAddr)) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap());
+        let (client, server) = tokio::join!(client, listener.accept());
+        (client.unwrap(), server.unwrap())
+    }
+    /// An acceptor with a throwaway self-signed certificate
+    fn acceptor() -> SslAcceptor {
+        let pkey = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "localhost").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&pkey).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&pkey, MessageDigest::sha256()).unwrap();
+        let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+        acceptor.set_private_key(&pkey).unwrap();
+        acceptor.set_certificate(&cert.build()).unwrap();
+        acceptor.build()
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(Sniffed::classify(&CLIENT_HELLO), Sniffed::Tls);
+        assert_eq!(Sniffed::classify(b"*1\n~1\n4\nHEYA\n"), Sniffed::Skyhash);
+        assert_eq!(Sniffed::classify(b"#7\n*1\n"), Sniffed::Skyhash);
+        // a lone handshake byte could be anything, and so could nothing at all
+        assert_eq!(Sniffed::classify(&[0x16]), Sniffed::Unknown);
+        assert_eq!(Sniffed::classify(&[0x16, 0x02]), Sniffed::Unknown);
+        assert_eq!(Sniffed::classify(b""), Sniffed::Unknown);
+    }
+    #[tokio::test]
+    async fn test_tls_client_on_the_plain_port_is_told_so() {
+        let before = TLS_ON_PLAIN_PORT.count();
+        let (mut client, (mut server, peer)) = pair().await;
+        client.write_all(&CLIENT_HELLO).await.unwrap();
+        assert!(refuse_tls_client(&mut server, peer).await);
+        drop(server);
+        let mut reply = Vec::new();
+        client.read_to_end(&mut reply).await.unwrap();
+        assert_eq!(reply, groups::TLS_ON_PLAIN_PORT);
+        assert!(TLS_ON_PLAIN_PORT.count() > before);
+    }
+    #[tokio::test]
+    async fn test_real_tls_client_on_the_plain_port_is_refused() {
+        let before = TLS_ON_PLAIN_PORT.count();
+        let (client, (mut server, peer)) = pair().await;
+        let mut connector = SslConnector::builder(SslMethod::tls()).unwrap();
+        connector.set_verify(SslVerifyMode::NONE);
+        let ssl = connector
+            .build()
+            .configure()
+            .unwrap()
+            .into_ssl("localhost")
+            .unwrap();
+        let mut client = SslStream::new(ssl, client).unwrap();
+        let (connected, refused) = tokio::join!(
+            Pin::new(&mut client).connect(),
+            refuse_tls_client(&mut server, peer)
+        );
+        assert!(refused);
+        drop(server);
+        connected.unwrap_err();
+        assert!(TLS_ON_PLAIN_PORT.count() > before);
+    }
+    #[tokio::test]
+    async fn test_query_on_the_plain_port_is_left_alone() {
+        let (mut client, (mut server, peer)) = pair().await;
+        client.write_all(b"*1\n~1\n4\nHEYA\n").await.unwrap();
+        assert!(!refuse_tls_client(&mut server, peer).await);
+        // and it's still there to be read
+        let mut query = [0; 14];
+        server.read_exact(&mut query).await.unwrap();
+        assert_eq!(&query, b"*1\n~1\n4\nHEYA\n");
+        // a client that hasn't sent anything yet is handed over after the timeout
+        let (_client, (mut server, peer)) = pair().await;
+        assert!(!refuse_tls_client(&mut server, peer).await);
+    }
+    #[tokio::test]
+    async fn test_plain_client_on_the_tls_port_is_reported() {
+        let before = PLAIN_ON_TLS_PORT.count();
+        let (mut client, (server, peer)) = pair().await;
+        client.write_all(b"*1\n~1\n4\nHEYA\n").await.unwrap();
+        tls::handshake(&acceptor(), server, peer).await.unwrap_err();
+        assert!(PLAIN_ON_TLS_PORT.count() > before);
+    }
+}
@@ -24,25 +24,42 @@
  *
 */
 
-use crate::dbnet::connection::ConnectionHandler;
+use crate::dbnet::connection::{ConnectionHandler, Peer, ResponseBuffer};
+use crate::dbnet::handshake::Capabilities;
+use crate::dbnet::sniff;
 use crate::dbnet::BaseListener;
 use crate::dbnet::Terminator;
 use crate::protocol;
+use crate::queryengine::multi::{TxnState, Watches};
+use crate::queryengine::plan::BatchPlan;
+use crate::registry;
+use crate::registry::{ClientStats, QueryTrace};
 use bytes::BytesMut;
+use core::sync::atomic::AtomicBool;
 use libsky::TResult;
 use libsky::BUF_CAP;
 pub use protocol::ParseResult;
 pub use protocol::Query;
+use std::net::SocketAddr;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::io::AsyncWrite;
 use tokio::io::BufWriter;
 use tokio::net::TcpStream;
 use tokio::time;
 
+/// The initial capacity of the per-connection scratch buffer used to assemble frames. It only
+/// grows if a larger frame is assembled and keeps its capacity between queries
+const SCRATCH_CAP: usize = 64;
+
 pub trait BufferedSocketStream: AsyncWrite {}
 
 impl BufferedSocketStream for TcpStream {}
 
+#[cfg(test)]
+/// The connection of the in-process tests, which writes to the buffer that it reads from
+impl BufferedSocketStream for std::io::Cursor<Vec<u8>> {}
+
 /// A TCP/SSL connection wrapper
 pub struct Connection<T>
 where
@@ -53,6 +70,33 @@ where
     pub stream: BufWriter<T>,
     /// The in-memory read buffer. The size is given by `BUF_CAP`
     pub buffer: BytesMut,
+    /// The scratch buffer used to assemble frames before they're written to the stream
+    pub scratch: BytesMut,
+    /// The buffer that responses are assembled in if they might have to be compressed. This
+    /// is only used if compression was negotiated
+    pub response: ResponseBuffer,
+    /// The capabilities negotiated for this connection
+    pub capabilities: Capabilities,
+    /// The token handed out by `SYS SHUTDOWN PREPARE` on this connection, if any
+    pub shutdown_token: Option<String>,
+    /// The peer on the other end of this connection
+    pub peer: Peer,
+    /// The stats of this connection (see [`registry::Clients`])
+    pub client: Arc<ClientStats>,
+    /// Whether the queries on this connection are traced (see [`registry::Tracer`])
+    pub tracing: Arc<AtomicBool>,
+    /// The query that is being traced, if any
+    pub trace: Option<QueryTrace>,
+    /// The state of the transaction on this connection
+    pub txn: TxnState,
+    /// The keys that this connection is watching
+    pub watches: Watches,
+    /// What the queries of the batch that is being run have resolved (see [`BatchPlan`])
+    pub plan: BatchPlan,
+    /// The request id that the query which is being read (or run) was tagged with, if any
+    pub request_id: Option<u64>,
+    /// Whether a response was cut short (see [`ArrayWriter`](super::connection::ArrayWriter))
+    pub desynced: bool,
 }
 
 impl<T> Connection<T>
@@ -61,11 +105,41 @@ where
 {
     /// Initiailize a new `Connection` instance
     pub fn new(stream: T) -> Self {
+        let peer = Peer::new(None);
         Connection {
             stream: BufWriter::new(stream),
             buffer: BytesMut::with_capacity(BUF_CAP),
+            scratch: BytesMut::with_capacity(SCRATCH_CAP),
+            response: ResponseBuffer::default(),
+            capabilities: Capabilities::default(),
+            shutdown_token: None,
+            tracing: registry::get_tracer().register(peer.id),
+            client: registry::get_clients().register(&peer),
+            trace: None,
+            txn: TxnState::Idle,
+            watches: Watches::default(),
+            plan: BatchPlan::new(),
+            request_id: None,
+            desynced: false,
+            peer,
         }
     }
+    /// Set the remote address this connection was accepted from
+    pub fn with_peer_addr(mut self, addr: SocketAddr) -> Self {
+        self.peer.addr = Some(addr);
+        self.client = registry::get_clients().register(&self.peer);
+        self
+    }
+}
+
+impl<T> Drop for Connection<T>
+where
+    T: BufferedSocketStream,
+{
+    fn drop(&mut self) {
+        registry::get_tracer().unregister(self.peer.id);
+        registry::get_clients().unregister(self.peer.id);
+    }
 }
 
 // We'll use the idea of gracefully shutting down from tokio
@@ -77,13 +151,12 @@ pub struct Listener {
 
 impl Listener {
     /// Accept an incoming connection
-    async fn accept(&mut self) -> TResult<TcpStream> {
+    async fn accept(&mut self) -> TResult<(TcpStream, SocketAddr)> {
         // We will steal the idea of Ethernet's backoff for connection errors
         let mut backoff = 1;
         loop {
             match self.base.listener.accept().await {
-                // We don't need the bindaddr
-                Ok((stream, _)) => return Ok(stream),
+                Ok(accepted) => return Ok(accepted),
                 Err(e) => {
                     if backoff > 64 {
                         // Too many retries, goodbye user
@@ -111,15 +184,26 @@ impl Listener {
              can arise and it will flood the log and might also result
              in a crash
             */
-            let stream = skip_loop_err!(self.accept().await);
-            let mut chandle = ConnectionHandler::new(
-                self.base.db.clone(),
-                Connection::new(stream),
-                self.base.climit.clone(),
-                Terminator::new(self.base.signal.subscribe()),
-                self.base.terminate_tx.clone(),
-            );
+            let (mut stream, addr) = skip_loop_err!(self.accept().await);
+            let db = self.base.db.clone();
+            let climit = self.base.climit.clone();
+            let terminator = Terminator::new(self.base.signal.subscribe());
+            let terminate_tx = self.base.terminate_tx.clone();
             tokio::spawn(async move {
+                // this is sniffed here rather than in the accept loop, so that a client that
+                // is slow to send its first bytes doesn't hold up the others
+                if sniff::refuse_tls_client(&mut stream, addr).await {
+                    // give back the permit that was taken for this connection
+                    climit.add_permits(1);
+                    return;
+                }
+                let mut chandle = ConnectionHandler::new(
+                    db,
+                    Connection::new(stream).with_peer_addr(addr),
+                    climit,
+                    terminator,
+                    terminate_tx,
+                );
                 if let Err(e) = chandle.run().await {
                     log::error!("Error: {}", e);
                 }
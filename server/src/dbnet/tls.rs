@@ -25,6 +25,7 @@
 */
 
 use super::connection::ConnectionHandler;
+use super::sniff::{Sniffed, PLAIN_ON_TLS_PORT};
 use crate::dbnet::tcp::BufferedSocketStream;
 use crate::dbnet::tcp::Connection;
 use crate::dbnet::BaseListener;
@@ -35,6 +36,7 @@ use openssl::rsa::Rsa;
 use openssl::ssl::{Ssl, SslAcceptor, SslFiletype, SslMethod};
 use std::fs;
 use std::io::Error as IoError;
+use std::net::SocketAddr;
 use std::pin::Pin;
 use tokio::net::TcpStream;
 use tokio::time::{self, Duration};
@@ -54,47 +56,19 @@ impl SslListener {
         base: BaseListener,
         tls_passfile: Option<String>,
     ) -> TResult<Self> {
-        let mut acceptor_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
-        // cert is the same for both
-        acceptor_builder.set_certificate_chain_file(chain_file)?;
-        if let Some(tls_passfile) = tls_passfile {
-            // first read in the private key
-            let tls_private_key = fs::read(key_file).map_err(|e: IoError| {
-                format!("Failed to read TLS private key file with error: {}", e)
-            })?;
-            // read the passphrase because the passphrase file stream was provided
-            let tls_keyfile_stream = fs::read(tls_passfile).map_err(|e: IoError| {
-                format!(
-                    "Failed to read TLS private key passphrase file with error: {}",
-                    e
-                )
-            })?;
-            // decrypt the private key
-            let pkey = Rsa::private_key_from_pem_passphrase(&tls_private_key, &tls_keyfile_stream)?;
-            let pkey = PKey::from_rsa(pkey)?;
-            // set the private key for the acceptor
-            acceptor_builder.set_private_key(&pkey)?;
-        } else {
-            // no passphrase, needs interactive
-            acceptor_builder.set_private_key_file(key_file, SslFiletype::PEM)?;
-        }
         Ok(SslListener {
             base,
-            acceptor: acceptor_builder.build(),
+            acceptor: build_acceptor(key_file, chain_file, tls_passfile)?,
         })
     }
-    async fn accept(&mut self) -> TResult<SslStream<TcpStream>> {
+    async fn accept(&mut self) -> TResult<(SslStream<TcpStream>, SocketAddr)> {
         let mut backoff = 1;
         loop {
             match self.base.listener.accept().await {
-                // We don't need the bindaddr
                 // We get the encrypted stream which we need to decrypt
                 // by using the acceptor
-                Ok((stream, _)) => {
-                    let ssl = Ssl::new(self.acceptor.context())?;
-                    let mut stream = SslStream::new(ssl, stream)?;
-                    Pin::new(&mut stream).accept().await?;
-                    return Ok(stream);
+                Ok((stream, addr)) => {
+                    return Ok((handshake(&self.acceptor, stream, addr).await?, addr))
                 }
                 Err(e) => {
                     if backoff > 64 {
@@ -122,10 +96,10 @@ impl SslListener {
              can arise and it will flood the log and might also result
              in a crash
             */
-            let stream = skip_loop_err!(self.accept().await);
+            let (stream, addr) = skip_loop_err!(self.accept().await);
             let mut sslhandle = ConnectionHandler::new(
                 self.base.db.clone(),
-                Connection::new(stream),
+                Connection::new(stream).with_peer_addr(addr),
                 self.base.climit.clone(),
                 Terminator::new(self.base.signal.subscribe()),
                 self.base.terminate_tx.clone(),
@@ -139,3 +113,64 @@ impl SslListener {
         }
     }
 }
+
+/// Run the TLS handshake on a connection that was accepted from `peer`. If the handshake
+/// fails and the client looks like it's speaking plaintext Skyhash, this is reported (see
+/// [`sniff`](super::sniff))
+pub async fn handshake(
+    acceptor: &SslAcceptor,
+    stream: TcpStream,
+    peer: SocketAddr,
+) -> TResult<SslStream<TcpStream>> {
+    let sniffed = Sniffed::peek(&stream).await;
+    let ssl = Ssl::new(acceptor.context())?;
+    let mut stream = SslStream::new(ssl, stream)?;
+    if let Err(e) = Pin::new(&mut stream).accept().await {
+        if sniffed == Sniffed::Skyhash {
+            PLAIN_ON_TLS_PORT.report(peer);
+        }
+        return Err(e.into());
+    }
+    Ok(stream)
+}
+
+/// Build an acceptor from the PEM private key and certificate chain files. If no passphrase
+/// file is provided and the key is encrypted, then the passphrase is asked for interactively
+pub fn build_acceptor(
+    key_file: String,
+    chain_file: String,
+    tls_passfile: Option<String>,
+) -> TResult<SslAcceptor> {
+    let mut acceptor_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    // cert is the same for both
+    acceptor_builder.set_certificate_chain_file(chain_file)?;
+    if let Some(tls_passfile) = tls_passfile {
+        // first read in the private key
+        let tls_private_key = fs::read(key_file).map_err(|e: IoError| {
+            format!("Failed to read TLS private key file with error: {}", e)
+        })?;
+        // read the passphrase because the passphrase file stream was provided
+        let tls_keyfile_stream = fs::read(tls_passfile).map_err(|e: IoError| {
+            format!(
+                "Failed to read TLS private key passphrase file with error: {}",
+                e
+            )
+        })?;
+        // decrypt the private key
+        let pkey = Rsa::private_key_from_pem_passphrase(&tls_private_key, &tls_keyfile_stream)?;
+        let pkey = PKey::from_rsa(pkey)?;
+        // set the private key for the acceptor
+        acceptor_builder.set_private_key(&pkey)?;
+    } else {
+        // no passphrase, needs interactive
+        acceptor_builder.set_private_key_file(key_file, SslFiletype::PEM)?;
+    }
+    Ok(acceptor_builder.build())
+}
+
+/// Check that the PEM certificate chain file can be loaded
+pub fn check_chain_file(chain_file: &str) -> TResult<()> {
+    let mut acceptor_builder = SslAcceptor::mozilla_intermediate(SslMethod::tls())?;
+    acceptor_builder.set_certificate_chain_file(chain_file)?;
+    Ok(())
+}
@@ -26,28 +26,144 @@
 
 //! Tools for creating snapshots
 
-use crate::corestore::lazy::Lazy;
+use crate::corestore::lazy::ReplaceableLazy;
+use crate::corestore::snaplock::SnapHolder;
 use crate::corestore::Corestore;
+use crate::registry;
 use crate::storage;
-use crate::storage::interface::DIR_SNAPROOT;
-use chrono::prelude::*;
+use crate::storage::chain::{self, ChainLink};
+use crate::storage::error::StorageError;
+use crate::storage::interface::{dir_root, dir_snaproot};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
 use regex::Regex;
+use std::collections::HashSet;
 use std::fmt;
 use std::fs;
-use std::io::{self, ErrorKind};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 /// Matches any string which is in the following format:
 /// ```text
-/// YYYYMMDD-HHMMSS
+/// <prefix>YYYYMMDD-HHMMSS
 /// ```
-pub static SNAP_MATCH: Lazy<Regex, fn() -> Regex> = Lazy::new(|| {
-    Regex::new("^\\d{4}(0[1-9]|1[012])(0[1-9]|[12][0-9]|3[01])(-)(?:(?:([01]?\\d|2[0-3]))?([0-5]?\\d))?([0-5]?\\d)$").unwrap()
-});
+/// The prefix is empty, unless it's been changed with [`set_snapshot_prefix`]
+pub static SNAP_MATCH: ReplaceableLazy<Regex, fn() -> Regex> =
+    ReplaceableLazy::new(|| snap_match(""));
+/// What the names of the snapshots that we take start with
+static SNAP_PREFIX: ReplaceableLazy<String, fn() -> String> = ReplaceableLazy::new(String::new);
+
+/// The pattern for the timestamp at the end of a snapshot's name
+const SNAP_TIMESTAMP_PATTERN: &str = "\\d{4}(0[1-9]|1[012])(0[1-9]|[12][0-9]|3[01])(-)(?:(?:([01]?\\d|2[0-3]))?([0-5]?\\d))?([0-5]?\\d)$";
+
+/// Returns a regex that matches the names of snapshots that start with `prefix`
+fn snap_match(prefix: &str) -> Regex {
+    let mut pattern = String::from("^");
+    pattern.push_str(&regex::escape(prefix));
+    pattern.push_str(SNAP_TIMESTAMP_PATTERN);
+    Regex::new(&pattern).unwrap()
+}
+
+/// Start the names of the snapshots that we take with `prefix` (the `prefix` key of the
+/// snapshot configuration), and make [`SNAP_MATCH`] only match the names that start with it
+pub fn set_snapshot_prefix(prefix: &str) {
+    SNAP_PREFIX.replace(prefix.to_owned());
+    SNAP_MATCH.replace(snap_match(prefix));
+}
+
+/// Returns true if `prefix` can start the names of snapshots: it can only have ASCII letters,
+/// digits, `_` and `-`, so that the names stay plain directory names
+pub fn is_valid_prefix(prefix: &str) -> bool {
+    prefix
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// The format of the timestamps in the names of snapshots
+const SNAP_NAME_FORMAT: &str = "%Y%m%d-%H%M%S";
+/// The length of a timestamp in [`SNAP_NAME_FORMAT`]
+const SNAP_NAME_LEN: usize = "YYYYMMDD-HHMMSS".len();
+
+/// Returns the time at which a snapshot was created, going by its name. Names end with the
+/// timestamp, so this works for names with a prefix too
+fn snapshot_time(name: &str) -> Option<DateTime<Utc>> {
+    let timestamp = name.get(name.len().checked_sub(SNAP_NAME_LEN)?..)?;
+    NaiveDateTime::parse_from_str(timestamp, SNAP_NAME_FORMAT)
+        .ok()
+        .map(|time| DateTime::from_utc(time, Utc))
+}
+
+/// Returns the name of the newest (local) snapshot, if there is one
+pub fn newest_snapshot() -> Option<String> {
+    fs::read_dir(dir_snaproot())
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| SNAP_MATCH.is_match(name))
+        // the names are timestamps, so the newest one sorts last
+        .max()
+}
 
 /// The default snapshot count is 12, assuming that the user would take a snapshot
 /// every 2 hours (or 7200 seconds)
 const DEF_SNAPSHOT_COUNT: usize = 12;
 
+/// By default, a chain can have up to 12 incremental snapshots before a full snapshot has to
+/// be taken
+pub const DEF_MAX_CHAIN: usize = 12;
+
+/// The file (in the snapshot directory) in which the snapshot queue is saved: the number of
+/// snapshots to keep on the first line and then the names of the snapshots in the order they
+/// were created
+const STATE_FILE: &str = ".queue";
+
+/// Read the saved snapshot queue, if there is one
+fn read_state() -> Option<(String, Vec<String>)> {
+    let state = fs::read_to_string(crate::concat_str!(dir_snaproot(), "/", STATE_FILE)).ok()?;
+    let mut lines = state.lines();
+    let limit = lines.next()?.to_owned();
+    Some((limit, lines.map(|name| name.to_owned()).collect()))
+}
+
+/// Returns the path of the (local) snapshot `name`
+fn snap_path(name: &str) -> PathBuf {
+    Path::new(dir_snaproot()).join(name)
+}
+
+/// Returns the snapshots that the snapshot `name` depends on: its parent, the parent's parent
+/// and so on, up to the full snapshot at the start of its chain
+fn ancestors(name: &str) -> Vec<String> {
+    let mut ancestors: Vec<String> = Vec::new();
+    let mut link = chain::read_link(&snap_path(name));
+    while let Some(ChainLink {
+        parent: Some(parent),
+        ..
+    }) = link
+    {
+        if ancestors.contains(&parent) {
+            // a corrupted chain; don't go around in circles
+            break;
+        }
+        link = chain::read_link(&snap_path(&parent));
+        ancestors.push(parent);
+    }
+    ancestors
+}
+
+/// Order the snapshots that are on disk: the ones we knew about keep their recorded order
+/// and the rest (which we never saw being created) follow, oldest first. Recorded snapshots
+/// that aren't on disk anymore are dropped
+fn reconcile(recorded: Vec<String>, mut on_disk: Vec<String>) -> Vec<String> {
+    let mut snaps: Vec<String> = recorded
+        .into_iter()
+        .filter(|snap| on_disk.contains(snap))
+        .collect();
+    on_disk.retain(|snap| !snaps.contains(snap));
+    // the names are timestamps
+    on_disk.sort();
+    snaps.extend(on_disk);
+    snaps
+}
+
 /// # Snapshot Engine
 ///
 /// This object provides methods to create and delete snapshots. There should be a
@@ -56,6 +172,12 @@ const DEF_SNAPSHOT_COUNT: usize = 12;
 pub struct SnapshotEngine<'a> {
     /// File names of the snapshots (relative paths)
     snaps: queue::Queue,
+    /// Snapshots older than this are removed (if it's set)
+    maxage: Option<Duration>,
+    /// The maximum number of incremental snapshots in a chain
+    maxchain: usize,
+    /// Who the snapshot lock is taken for
+    holder: SnapHolder,
     /// An atomic reference to the coretable
     dbref: &'a Corestore,
 }
@@ -63,48 +185,78 @@ pub struct SnapshotEngine<'a> {
 #[derive(Debug)]
 pub enum SnapengineError {
     EngineError(&'static str),
-    IoError(io::Error),
+    /// The snapshot directory couldn't be read or written
+    Storage(StorageError),
+    /// There's no snapshot with this name to take an incremental snapshot against
+    NoSuchBase(String),
+    /// The chain already has the maximum number of incremental snapshots
+    ChainTooLong(usize),
 }
 
 impl fmt::Display for SnapengineError {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::result::Result<(), fmt::Error> {
         match self {
             Self::EngineError(estr) => {
-                formatter.write_str("Snapshot engine error")?;
+                formatter.write_str("Snapshot engine error: ")?;
                 formatter.write_str(estr)?;
             }
-            Self::IoError(e) => {
-                formatter.write_str("Snapshot engine IOError:")?;
-                formatter.write_str(&e.to_string())?;
+            Self::Storage(e) => write!(formatter, "Snapshot engine error: {}", e)?,
+            Self::NoSuchBase(base) => {
+                write!(formatter, "No snapshot named '{}' to build on", base)?;
             }
+            Self::ChainTooLong(max) => write!(
+                formatter,
+                "The chain already has {} incremental snapshot(s); take a full snapshot first",
+                max
+            )?,
         }
         Ok(())
     }
 }
 
+impl std::error::Error for SnapengineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Storage(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<StorageError> for SnapengineError {
+    fn from(e: StorageError) -> Self {
+        Self::Storage(e)
+    }
+}
+
 impl<'a> SnapshotEngine<'a> {
     /// Create a new `Snapshot` instance
     ///
     /// This also attempts to check if the snapshots directory exists;
-    /// If the directory doesn't exist, then it is created
+    /// If the directory doesn't exist, then it is created. If it does, the queue is rebuilt
+    /// from the snapshots that are actually in it, in the order recorded in the state file
     pub fn new<'b: 'a>(maxtop: usize, dbref: &'b Corestore) -> Result<Self, SnapengineError> {
-        let mut snaps = Vec::with_capacity(maxtop);
         let q_cfg_tuple = if maxtop == 0 {
             (DEF_SNAPSHOT_COUNT, true)
         } else {
             (maxtop, false)
         };
-        match fs::create_dir(DIR_SNAPROOT) {
-            Ok(_) => (),
+        match fs::create_dir(dir_snaproot()) {
+            Ok(_) => storage::interface::sync_dir(dir_root())?,
             Err(e) => match e.kind() {
                 ErrorKind::AlreadyExists => {
                     // Now it's our turn to look for the existing snapshots
-                    let dir = fs::read_dir(DIR_SNAPROOT).map_err(SnapengineError::IoError)?;
+                    let mut on_disk = Vec::new();
+                    let dir = fs::read_dir(dir_snaproot())
+                        .map_err(StorageError::io("list", dir_snaproot()))?;
                     for entry in dir {
-                        let entry = entry.map_err(SnapengineError::IoError)?;
+                        let entry = entry.map_err(StorageError::io("list", dir_snaproot()))?;
                         let path = entry.path();
-                        // We'll skip the directory that contains remotely created snapshots
+                        let fname = entry.file_name();
                         if path.is_file() {
+                            if fname == STATE_FILE {
+                                continue;
+                            }
                             // If the entry is not a directory then some other
                             // file(s) is present in the directory
                             println!("Erroring at: {:?}", path);
@@ -112,56 +264,175 @@ impl<'a> SnapshotEngine<'a> {
                                 "The snapshot directory contains unrecognized files/directories",
                             ));
                         }
-                        if !path.is_dir() {
-                            let fname = entry.file_name();
-                            let file_name = if let Some(good_file_name) = fname.to_str() {
-                                good_file_name
-                            } else {
-                                // The filename contains invalid characters
-                                return Err(SnapengineError::EngineError(
-                                "The snapshot file names have invalid characters. This should not happen! Please report an error")
-                            );
-                            };
-                            if SNAP_MATCH.is_match(file_name) {
-                                // Good, the file name matched the format we were expecting
-                                // This is a valid snapshot, add it to our `Vec` of snaps
-                                snaps.push(file_name.to_owned());
-                            } else {
-                                // The filename contains invalid characters
-                                return Err(SnapengineError::EngineError(
-                                "The snapshot file names have invalid characters. This should not happen! Please report an error"
-                            ));
+                        // We'll skip the directory that contains remotely created snapshots
+                        // (or anything else that isn't named like one of our snapshots)
+                        match fname.to_str() {
+                            Some(file_name) if SNAP_MATCH.is_match(file_name) => {
+                                on_disk.push(file_name.to_owned())
                             }
+                            _ => {}
                         }
                     }
-                    if snaps.is_empty() {
-                        return Ok(SnapshotEngine {
-                            snaps: queue::Queue::new(q_cfg_tuple),
-                            dbref,
-                        });
-                    } else {
-                        return Ok(SnapshotEngine {
-                            snaps: queue::Queue::init_pre(q_cfg_tuple, snaps),
-                            dbref,
-                        });
+                    let (limit, recorded) = match read_state() {
+                        Some((limit, recorded)) => (Some(limit), recorded),
+                        None => (None, Vec::new()),
+                    };
+                    let snaps = queue::Queue::init_pre(q_cfg_tuple, reconcile(recorded, on_disk));
+                    match limit {
+                        Some(limit) if limit != snaps.limit() => log::info!(
+                            "The number of snapshots to keep changed from {} to {}",
+                            limit,
+                            snaps.limit()
+                        ),
+                        _ => {}
                     }
+                    return Ok(SnapshotEngine {
+                        snaps,
+                        maxage: None,
+                        maxchain: DEF_MAX_CHAIN,
+                        holder: SnapHolder::Scheduler,
+                        dbref,
+                    });
                 }
-                _ => return Err(SnapengineError::IoError(e)),
+                _ => return Err(StorageError::io("create the directory", dir_snaproot())(e).into()),
             },
         }
         Ok(SnapshotEngine {
             snaps: queue::Queue::new(q_cfg_tuple),
+            maxage: None,
+            maxchain: DEF_MAX_CHAIN,
+            holder: SnapHolder::Scheduler,
             dbref,
         })
     }
-    /// Generate the snapshot name
+    /// Remove snapshots once they're older than `maxage`. Both this and the number of
+    /// snapshots to keep apply, so whichever one is stricter wins
+    pub fn with_maxage(mut self, maxage: Option<Duration>) -> Self {
+        self.maxage = maxage;
+        self
+    }
+    /// Refuse to add more than `maxchain` incremental snapshots to a chain, so that a full
+    /// snapshot has to be taken every once in a while
+    pub fn with_maxchain(mut self, maxchain: usize) -> Self {
+        self.maxchain = maxchain;
+        self
+    }
+    /// Take the snapshot lock for `holder` (instead of for the snapshot service)
+    pub fn with_holder(mut self, holder: SnapHolder) -> Self {
+        self.holder = holder;
+        self
+    }
+    /// Generate the snapshot name from the prefix and the current time (as reported by the
+    /// store's clock)
     fn get_snapname(&self) -> String {
-        Utc::now().format("%Y%m%d-%H%M%S").to_string()
+        let now = self.dbref.get_store().get_clock().now();
+        format!("{}{}", SNAP_PREFIX.as_str(), now.format(SNAP_NAME_FORMAT))
+    }
+    /// Remove the snapshots that are older than the maximum age from the queue, returning
+    /// their names (oldest first). Snapshots whose names don't tell their age are kept
+    fn evict_expired(&mut self) -> Vec<String> {
+        let maxage = match self.maxage {
+            Some(maxage) => maxage,
+            None => return Vec::new(),
+        };
+        let now = self.dbref.get_store().get_clock().now();
+        let mut expired = Vec::new();
+        self.snaps.retain(|snap| match snapshot_time(snap) {
+            Some(time) if now - time > maxage => {
+                expired.push(snap.clone());
+                false
+            }
+            _ => true,
+        });
+        expired
     }
-    pub fn _mksnap_nonblocking_section(&mut self) -> (String, Option<String>) {
+    /// Forget about any snapshot that was deleted behind our back; it would otherwise take
+    /// up a slot in the queue and fail the rotation once it's the oldest
+    fn forget_deleted(&mut self) {
+        self.snaps.retain(|snap| snap_path(snap).is_dir());
+    }
+    /// Add the snapshot `snapname` (which applies on top of `parent`, if it's incremental) to
+    /// the queue, returning the names of the snapshots that should be removed (oldest first)
+    fn rotate(&mut self, snapname: String, parent: Option<&str>) -> Vec<String> {
+        // the snapshots that are too old go first, and then the excess if we were asked to
+        // keep fewer snapshots than we did earlier
+        let mut old_snaps = self.evict_expired();
+        old_snaps.extend(self.snaps.evict_excess());
+        old_snaps.extend(self.snaps.add(snapname));
+        if old_snaps.is_empty() {
+            return old_snaps;
+        }
+        // a snapshot can't go as long as an incremental snapshot that we keep depends on it
+        let mut needed: HashSet<String> = self
+            .snaps
+            .items()
+            .iter()
+            .flat_map(|snap| ancestors(snap))
+            .collect();
+        if let Some(parent) = parent {
+            needed.extend(ancestors(parent));
+            needed.insert(parent.to_owned());
+        }
+        let (kept, old_snaps): (Vec<String>, Vec<String>) = old_snaps
+            .into_iter()
+            .partition(|snap| needed.contains(snap));
+        if !kept.is_empty() {
+            log::info!(
+                "Keeping {} old snapshot(s) since newer incremental snapshots depend on them",
+                kept.len()
+            );
+            self.snaps.put_back(kept);
+        }
+        old_snaps
+    }
+    /// Add a new snapshot to the queue, returning its name along with the names of the
+    /// snapshots that should be removed (oldest first)
+    pub fn _mksnap_nonblocking_section(&mut self) -> (String, Vec<String>) {
+        self.forget_deleted();
+        let snapname = self.get_snapname();
+        let old_snaps = self.rotate(snapname.clone(), None);
+        (snapname, old_snaps)
+    }
+    /// Returns the newest snapshot in the queue that is either `base` or one that depends on
+    /// it
+    fn chain_tip(&self, base: &str) -> Option<String> {
+        if !snap_path(base).is_dir() {
+            return None;
+        }
+        self.snaps
+            .items()
+            .iter()
+            .rev()
+            .find(|snap| snap.as_str() == base || ancestors(snap).iter().any(|a| a == base))
+            .cloned()
+    }
+    /// Add a new incremental snapshot on top of the latest snapshot in the chain of `base` to
+    /// the queue, returning its name, its parent (and the parent's place in the chain) and the
+    /// names of the snapshots that should be removed (oldest first)
+    pub fn _mksnap_incremental_nonblocking_section(
+        &mut self,
+        base: &str,
+    ) -> Result<(String, (String, ChainLink), Vec<String>), SnapengineError> {
+        self.forget_deleted();
+        let parent = self
+            .chain_tip(base)
+            .ok_or_else(|| SnapengineError::NoSuchBase(base.to_owned()))?;
+        // a snapshot without a chain file was made before we had chains, so it can only be a
+        // full snapshot (and we can't tell when it was taken)
+        let parent_link =
+            chain::read_link(&snap_path(&parent)).unwrap_or_else(|| ChainLink::full(0, 0));
+        if parent_link.depth >= self.maxchain {
+            return Err(SnapengineError::ChainTooLong(self.maxchain));
+        }
         let snapname = self.get_snapname();
-        let old_snap_if_any = self.snaps.add(snapname.clone());
-        (snapname, old_snap_if_any)
+        if snap_path(&snapname).exists() {
+            // the parent was taken in this very second
+            return Err(SnapengineError::EngineError(
+                ": a snapshot with the same name already exists",
+            ));
+        }
+        let old_snaps = self.rotate(snapname.clone(), Some(&parent));
+        Ok((snapname, (parent, parent_link), old_snaps))
     }
 
     /// Blocking section of the snapshotting process
@@ -172,35 +443,90 @@ impl<'a> SnapshotEngine<'a> {
     pub(in crate::diskstore::snapshot) fn mksnap_blocking_section(
         snapname: String,
         handle: Corestore,
-        oldsnap: Option<String>,
+        parent: Option<(String, ChainLink)>,
+        oldsnaps: Vec<String>,
+        state: String,
+        holder: SnapHolder,
     ) -> bool {
         // This is a potentially blocking section
-        // So we acquired a lock
-        let lck = handle.lock_snap(); // Lock the snapshot service
-                                      // Another blocking section that does the actual I/O
-        if let Err(e) = storage::flush::snap_flush_full(&snapname, handle.get_store()) {
+        // So we lock the snapshot service (the guard releases it on every way out of here)
+        let _lck = handle.lock_snap(holder);
+        let started = Instant::now();
+        // Another blocking section that does the actual I/O
+        let store = handle.get_store();
+        // whatever is mutated from here on is picked up by the next incremental snapshot
+        let changelog = registry::get_changelog();
+        let (epoch, seq) = (changelog.epoch(), changelog.current_seq());
+        let flushed = match parent {
+            None => storage::flush::snap_flush_full(&snapname, store)
+                .map(|_| ChainLink::full(epoch, seq)),
+            Some((parent, of)) => storage::flush::snap_flush_incremental(&snapname, store, |tbl| {
+                of.is_outdated(epoch, tbl.last_modified())
+            })
+            .map(|_| ChainLink::incremental(parent, &of, epoch, seq)),
+        };
+        // the flush syncs the directories of the snapshot (and the snapshot directory that it's
+        // in) and writing the CHAIN syncs the snapshot's directory again, so once both are done
+        // the snapshot survives a power cut
+        let flushed = flushed.and_then(|link| chain::write_link(&snap_path(&snapname), &link));
+        if let Err(e) = flushed {
             log::error!("Snapshotting failed with error: '{}'", e);
-            drop(lck);
             return false;
         } else {
+            registry::get_metrics().record_snapshot(started.elapsed());
             log::info!("Successfully created snapshot");
         }
-        if let Some(old_snapshot) = oldsnap {
-            if let Err(e) = fs::remove_dir_all(crate::concat_str!(DIR_SNAPROOT, "/", &old_snapshot))
-            {
-                log::error!(
-                    "Failed to delete snapshot '{}' with error '{}'",
-                    old_snapshot,
-                    e
-                );
-                drop(lck);
-                return false;
-            } else {
-                log::info!("Successfully removed old snapshot");
+        let okay = Self::remove_snapshots(oldsnaps);
+        Self::save_state(state);
+        okay
+    }
+    /// Remove the given snapshots from the disk, returning false if any of them couldn't be
+    /// removed. A failure doesn't stop the rest from being removed
+    fn remove_snapshots(snaps: Vec<String>) -> bool {
+        let mut okay = true;
+        for old_snapshot in snaps {
+            match fs::remove_dir_all(crate::concat_str!(dir_snaproot(), "/", &old_snapshot)) {
+                Ok(_) => log::info!("Successfully removed old snapshot"),
+                Err(e) if e.kind() == ErrorKind::NotFound => {
+                    // someone already removed it for us
+                    log::warn!("Old snapshot '{}' was already removed", old_snapshot);
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to delete snapshot '{}' with error '{}'",
+                        old_snapshot,
+                        e
+                    );
+                    okay = false;
+                }
             }
         }
-        drop(lck);
-        true
+        okay
+    }
+    /// Save the snapshot queue in the state file
+    fn save_state(state: String) {
+        if let Err(e) = fs::write(crate::concat_str!(dir_snaproot(), "/", STATE_FILE), state) {
+            log::warn!("Failed to save the snapshot queue with error '{}'", e);
+        }
+    }
+    /// Remove the snapshots that are older than the maximum age (if there is one). This
+    /// happens whenever a snapshot is created, but it should also be done once when the engine
+    /// starts. It returns false if any of the expired snapshots couldn't be removed
+    pub async fn remove_expired(&mut self) -> bool {
+        let expired = self.evict_expired();
+        if expired.is_empty() {
+            return true;
+        }
+        let state = self.snaps.to_state();
+        let (owned_handle, holder) = (self.dbref.clone(), self.holder);
+        tokio::task::spawn_blocking(move || {
+            let _lck = owned_handle.lock_snap(holder);
+            let okay = SnapshotEngine::remove_snapshots(expired);
+            SnapshotEngine::save_state(state);
+            okay
+        })
+        .await
+        .expect("SNAPSHOT SWEEP INTERNAL SERVICE PANIC")
     }
     /// Create a snapshot
     ///
@@ -219,13 +545,46 @@ impl<'a> SnapshotEngine<'a> {
     /// of a runtime error.
     pub async fn mksnap(&mut self) -> bool {
         let (create_this, remove_this) = self._mksnap_nonblocking_section();
-        let owned_handle = self.dbref.clone();
+        let state = self.snaps.to_state();
+        let (owned_handle, holder) = (self.dbref.clone(), self.holder);
         tokio::task::spawn_blocking(move || {
-            SnapshotEngine::mksnap_blocking_section(create_this, owned_handle, remove_this)
+            SnapshotEngine::mksnap_blocking_section(
+                create_this,
+                owned_handle,
+                None,
+                remove_this,
+                state,
+                holder,
+            )
         })
         .await
         .expect("MKSNAP INTERNAL SERVICE PANIC")
     }
+    /// Create an incremental snapshot with the tables that were modified since the latest
+    /// snapshot in the chain that starts with (or goes through) `base`
+    ///
+    /// Just like [`Self::mksnap`], this returns `Ok(true)` if everything went well and
+    /// `Ok(false)` if the snapshot couldn't be written. If there's no such snapshot, or if the
+    /// chain is already at its maximum length, an error is returned and nothing is written
+    pub async fn mksnap_incremental(&mut self, base: &str) -> Result<bool, SnapengineError> {
+        let (create_this, parent, remove_this) =
+            self._mksnap_incremental_nonblocking_section(base)?;
+        let state = self.snaps.to_state();
+        let (owned_handle, holder) = (self.dbref.clone(), self.holder);
+        let created = tokio::task::spawn_blocking(move || {
+            SnapshotEngine::mksnap_blocking_section(
+                create_this,
+                owned_handle,
+                Some(parent),
+                remove_this,
+                state,
+                holder,
+            )
+        })
+        .await
+        .expect("MKSNAP INTERNAL SERVICE PANIC");
+        Ok(created)
+    }
 }
 
 mod queue {
@@ -272,9 +631,49 @@ mod queue {
                 x
             }
         }
+        /// Remove the oldest items that don't fit in the queue anymore (the maximum length
+        /// could have been lowered after they were added)
+        pub fn evict_excess(&mut self) -> Vec<String> {
+            if self.dontpop || self.queue.len() <= self.maxlen {
+                Vec::new()
+            } else {
+                let excess = self.queue.len() - self.maxlen;
+                self.queue.drain(..excess).collect()
+            }
+        }
+        /// Keep only the items for which `f` returns true
+        pub fn retain(&mut self, f: impl FnMut(&String) -> bool) {
+            self.queue.retain(f)
+        }
+        /// The items in the queue, oldest first
+        pub fn items(&self) -> &[String] {
+            &self.queue
+        }
+        /// Put items that were evicted back at the front of the queue (oldest first)
+        pub fn put_back(&mut self, items: Vec<String>) {
+            self.queue.splice(0..0, items);
+        }
+        /// The number of items that are kept (`all` if nothing is ever popped)
+        pub fn limit(&self) -> String {
+            if self.dontpop {
+                "all".to_owned()
+            } else {
+                self.maxlen.to_string()
+            }
+        }
+        /// The queue as it is saved in the state file
+        pub fn to_state(&self) -> String {
+            let mut state = self.limit();
+            for item in &self.queue {
+                state.push('\n');
+                state.push_str(item);
+            }
+            state
+        }
         /// Check if we have reached the maximum queue size limit
         fn is_overflow(&self) -> bool {
-            self.queue.len() == self.maxlen
+            // there can be more items than that if some were put back
+            self.queue.len() >= self.maxlen
         }
         /// Remove the last item inserted
         fn pop(&mut self) -> Option<String> {
@@ -297,6 +696,23 @@ mod queue {
         assert_eq!(q.add(String::from("snap6")), Some(String::from("snap2")));
     }
 
+    #[test]
+    fn test_queue_evict_excess() {
+        // the queue used to hold four items, but now it can only hold two
+        let mut q = Queue::init_pre(
+            (2, false),
+            vec!["snap1".to_owned(), "snap2".to_owned(), "snap3".to_owned()],
+        );
+        assert_eq!(q.evict_excess(), vec!["snap1".to_owned()]);
+        assert!(q.evict_excess().is_empty());
+        assert_eq!(q.add(String::from("snap4")), Some(String::from("snap2")));
+        assert_eq!(q.to_state(), "2\nsnap3\nsnap4");
+        // nothing is evicted if we keep everything
+        let mut q = Queue::init_pre((1, true), vec!["snap1".to_owned(), "snap2".to_owned()]);
+        assert!(q.evict_excess().is_empty());
+        assert_eq!(q.to_state(), "all\nsnap1\nsnap2");
+    }
+
     #[test]
     fn test_queue_dontpop() {
         // This means that items can only be added or all of them can be deleted
@@ -309,3 +725,291 @@ mod queue {
         assert!(q.add(String::from("snap6")).is_none());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{queue, SnapengineError, SnapshotEngine};
+    use crate::corestore::clock::MockClock;
+    use crate::corestore::memstore::Memstore;
+    use crate::corestore::snaplock::SnapHolder;
+    use crate::corestore::{Corestore, SnapshotStatus};
+    use crate::storage::interface::dir_snaproot;
+    use chrono::{Duration, TimeZone, Utc};
+    use std::fs;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_snapshot_names_follow_the_clock() {
+        fs::create_dir_all(dir_snaproot()).unwrap();
+        let clock = Arc::new(MockClock::at(2021, 7, 1, 10, 0, 0));
+        let store =
+            Corestore::default_with_store(Memstore::new_default().with_clock(clock.clone()));
+        // keep every snapshot so that none of the existing ones are evicted
+        let mut engine = SnapshotEngine::new(0, &store).unwrap();
+        let (first, evicted) = engine._mksnap_nonblocking_section();
+        assert_eq!(first, "20210701-100000");
+        assert!(evicted.is_empty());
+        clock.advance(Duration::seconds(1));
+        let (second, evicted) = engine._mksnap_nonblocking_section();
+        assert_eq!(second, "20210701-100001");
+        assert!(evicted.is_empty());
+        // a month (and two hours) later
+        clock.advance(Duration::days(30) + Duration::hours(2));
+        let (third, _) = engine._mksnap_nonblocking_section();
+        assert_eq!(third, "20210731-120001");
+        assert!(super::SNAP_MATCH.is_match(&first));
+        assert!(super::SNAP_MATCH.is_match(&third));
+    }
+
+    #[test]
+    fn test_snap_match_with_prefix() {
+        let matcher = super::snap_match("nightly.");
+        assert!(matcher.is_match("nightly.20210701-100000"));
+        assert!(!matcher.is_match("20210701-100000"));
+        // the prefix is matched literally
+        assert!(!matcher.is_match("nightlyx20210701-100000"));
+        assert!(!matcher.is_match("nightly.20210701"));
+        assert!(super::snap_match("").is_match("20210701-100000"));
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|n| n.to_string()).collect() };
+        // the recorded order wins, snapshots that are gone are dropped and the ones that we
+        // didn't know about are added oldest first
+        assert_eq!(
+            super::reconcile(
+                names(&["20210701-100002", "20210701-100000", "20210701-100001"]),
+                names(&[
+                    "20210701-100004",
+                    "20210701-100000",
+                    "20210701-100003",
+                    "20210701-100002"
+                ])
+            ),
+            names(&[
+                "20210701-100002",
+                "20210701-100000",
+                "20210701-100003",
+                "20210701-100004"
+            ])
+        );
+        assert_eq!(
+            super::reconcile(Vec::new(), names(&["20210701-100001", "20210701-100000"])),
+            names(&["20210701-100000", "20210701-100001"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rotation_heals_deleted_snapshots() {
+        fs::create_dir_all(dir_snaproot()).unwrap();
+        let snap_path = |name: &str| format!("{}/{}", dir_snaproot(), name);
+        let clock = Arc::new(MockClock::at(2019, 3, 1, 10, 0, 0));
+        let mut memstore = Memstore::new_default().with_clock(clock.clone());
+        memstore.snap_config = Some(SnapshotStatus::new(2));
+        let store = Corestore::default_with_store(memstore);
+        // other tests share the snapshot directory, so only manage the snapshots made here
+        let mut engine = SnapshotEngine {
+            snaps: queue::Queue::new((2, false)),
+            maxage: None,
+            maxchain: super::DEF_MAX_CHAIN,
+            holder: SnapHolder::Scheduler,
+            dbref: &store,
+        };
+        assert!(engine.mksnap().await);
+        clock.advance(Duration::seconds(1));
+        assert!(engine.mksnap().await);
+        // an operator removes the oldest snapshot behind our back
+        fs::remove_dir_all(snap_path("20190301-100000")).unwrap();
+        clock.advance(Duration::seconds(1));
+        assert!(engine.mksnap().await);
+        assert_eq!(engine.snaps.items(), ["20190301-100001", "20190301-100002"]);
+        assert!(fs::metadata(snap_path("20190301-100001")).is_ok());
+        // and this one rotates the oldest snapshot out as usual
+        clock.advance(Duration::seconds(1));
+        assert!(engine.mksnap().await);
+        assert_eq!(engine.snaps.items(), ["20190301-100002", "20190301-100003"]);
+        assert!(fs::metadata(snap_path("20190301-100001")).is_err());
+        // a snapshot that disappears right before it's rotated out doesn't fail the rotation
+        clock.advance(Duration::seconds(1));
+        assert!(SnapshotEngine::mksnap_blocking_section(
+            "20190301-100004".to_owned(),
+            store.clone(),
+            None,
+            vec!["20190301-095959".to_owned()],
+            engine.snaps.to_state(),
+            SnapHolder::Scheduler,
+        ));
+        for snap in &["20190301-100002", "20190301-100003", "20190301-100004"] {
+            fs::remove_dir_all(snap_path(snap)).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_snap_lock_is_free_after_a_failed_snapshot() {
+        use std::thread;
+        use std::time::Duration as StdDuration;
+        fs::create_dir_all(dir_snaproot()).unwrap();
+        let mut memstore = Memstore::new_default();
+        memstore.snap_config = Some(SnapshotStatus::new(2));
+        let store = Corestore::default_with_store(memstore);
+        let lock = &store.get_snapstatus().in_progress;
+        // a file where the snapshot's directory should go makes the flush fail
+        let in_the_way = format!("{}/{}", dir_snaproot(), "20150101-000000");
+        fs::write(&in_the_way, b"in the way").unwrap();
+        // a slow MKSNAP holds the lock while the snapshot service waits for it
+        let slow = store.lock_snap(SnapHolder::Mksnap);
+        let snapshot = {
+            let store = store.clone();
+            thread::spawn(move || {
+                SnapshotEngine::mksnap_blocking_section(
+                    "20150101-000000".to_owned(),
+                    store,
+                    None,
+                    Vec::new(),
+                    String::new(),
+                    SnapHolder::Scheduler,
+                )
+            })
+        };
+        thread::sleep(StdDuration::from_millis(50));
+        match lock.holder() {
+            Some((SnapHolder::Mksnap, held)) => assert!(held >= StdDuration::from_millis(50)),
+            holder => panic!("unexpected holder: {:?}", holder),
+        }
+        drop(slow);
+        // the snapshot fails and the lock is free again
+        assert!(!snapshot.join().unwrap());
+        assert!(!lock.is_locked());
+        assert_eq!(lock.holder(), None);
+        assert_eq!(lock.acquisitions(), 2);
+        fs::remove_file(in_the_way).unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_time() {
+        let time = Utc.ymd(2017, 6, 1).and_hms(12, 30, 5);
+        assert_eq!(super::snapshot_time("20170601-123005"), Some(time));
+        assert_eq!(super::snapshot_time("remote-20170601-123005"), Some(time));
+        assert_eq!(super::snapshot_time("20170601"), None);
+        assert_eq!(super::snapshot_time("20171301-123005"), None);
+    }
+
+    #[tokio::test]
+    async fn test_expired_snapshots_are_removed() {
+        fs::create_dir_all(dir_snaproot()).unwrap();
+        let snap_path = |name: &str| format!("{}/{}", dir_snaproot(), name);
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|n| n.to_string()).collect() };
+        let clock = Arc::new(MockClock::at(2017, 6, 10, 12, 0, 0));
+        let mut memstore = Memstore::new_default().with_clock(clock.clone());
+        memstore.snap_config = Some(SnapshotStatus::new(10));
+        let store = Corestore::default_with_store(memstore);
+        // some old snapshots: the first two are more than a week old
+        let old = [
+            "20170601-120000",
+            "20170603-115959",
+            "20170603-120000",
+            "20170609-120000",
+        ];
+        for snap in old.iter() {
+            fs::create_dir_all(snap_path(snap)).unwrap();
+        }
+        // other tests share the snapshot directory, so only manage the snapshots made here
+        let mut engine = SnapshotEngine {
+            snaps: queue::Queue::init_pre((10, false), names(&old)),
+            maxage: None,
+            maxchain: super::DEF_MAX_CHAIN,
+            holder: SnapHolder::Scheduler,
+            dbref: &store,
+        }
+        .with_maxage(Some(Duration::days(7)));
+        assert!(engine.remove_expired().await);
+        assert_eq!(engine.snaps.items(), ["20170603-120000", "20170609-120000"]);
+        assert!(fs::metadata(snap_path("20170601-120000")).is_err());
+        assert!(fs::metadata(snap_path("20170603-115959")).is_err());
+        assert!(fs::metadata(snap_path("20170603-120000")).is_ok());
+        // a day later, creating a snapshot removes the one that's now too old
+        clock.advance(Duration::days(1));
+        assert!(engine.mksnap().await);
+        assert_eq!(engine.snaps.items(), ["20170609-120000", "20170611-120000"]);
+        assert!(fs::metadata(snap_path("20170603-120000")).is_err());
+        assert!(fs::metadata(snap_path("20170609-120000")).is_ok());
+        // the count still applies when it's stricter than the age
+        engine.snaps = queue::Queue::init_pre((2, false), engine.snaps.items().to_vec());
+        clock.advance(Duration::seconds(1));
+        assert!(engine.mksnap().await);
+        assert_eq!(engine.snaps.items(), ["20170611-120000", "20170611-120001"]);
+        assert!(fs::metadata(snap_path("20170609-120000")).is_err());
+        // nothing expires without a maximum age
+        let mut engine = engine.with_maxage(None);
+        clock.advance(Duration::days(30));
+        assert!(engine.remove_expired().await);
+        assert_eq!(engine.snaps.items(), ["20170611-120000", "20170611-120001"]);
+        for snap in engine.snaps.items() {
+            fs::remove_dir_all(snap_path(snap)).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_rotation_keeps_the_chains_of_kept_snapshots() {
+        fs::create_dir_all(dir_snaproot()).unwrap();
+        let snap_path = |name: &str| format!("{}/{}", dir_snaproot(), name);
+        let clock = Arc::new(MockClock::at(2016, 4, 1, 10, 0, 0));
+        let mut memstore = Memstore::new_default().with_clock(clock.clone());
+        memstore.snap_config = Some(SnapshotStatus::new(2));
+        let store = Corestore::default_with_store(memstore);
+        // other tests share the snapshot directory, so only manage the snapshots made here
+        let mut engine = SnapshotEngine {
+            snaps: queue::Queue::new((2, false)),
+            maxage: None,
+            maxchain: 2,
+            holder: SnapHolder::Scheduler,
+            dbref: &store,
+        };
+        assert!(matches!(
+            engine.mksnap_incremental("20160401-100000").await,
+            Err(SnapengineError::NoSuchBase(_))
+        ));
+        assert!(engine.mksnap().await);
+        for _ in 0..2 {
+            clock.advance(Duration::seconds(1));
+            assert!(engine.mksnap_incremental("20160401-100000").await.unwrap());
+        }
+        // the base would have been rotated out, but the incremental snapshots need it
+        assert_eq!(
+            engine.snaps.items(),
+            ["20160401-100000", "20160401-100001", "20160401-100002"]
+        );
+        assert!(fs::metadata(snap_path("20160401-100000")).is_ok());
+        // the chain is full
+        clock.advance(Duration::seconds(1));
+        assert!(matches!(
+            engine.mksnap_incremental("20160401-100000").await,
+            Err(SnapengineError::ChainTooLong(2))
+        ));
+        assert_eq!(engine.snaps.items().len(), 3);
+        // the tip of the chain still needs the rest of it
+        assert!(engine.mksnap().await);
+        assert_eq!(
+            engine.snaps.items(),
+            [
+                "20160401-100000",
+                "20160401-100001",
+                "20160401-100002",
+                "20160401-100003"
+            ]
+        );
+        // once the tip goes, so does the whole chain
+        clock.advance(Duration::seconds(1));
+        assert!(engine.mksnap().await);
+        assert_eq!(engine.snaps.items(), ["20160401-100003", "20160401-100004"]);
+        for snap in &["20160401-100000", "20160401-100001", "20160401-100002"] {
+            assert!(fs::metadata(snap_path(snap)).is_err());
+        }
+        for snap in engine.snaps.items() {
+            fs::remove_dir_all(snap_path(snap)).unwrap();
+        }
+    }
+}
@@ -0,0 +1,547 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Importing data
+//!
+//! `skyd import --entity <ks:tbl> <file>` loads the records in a CSV or JSON lines file into a
+//! table and then exits. The data directory is loaded just like it would be on startup, the
+//! records are set (keys that already exist are left untouched and counted) and everything is
+//! flushed to disk before exiting. The file is streamed, so it can be larger than the memory
+//! that we have.
+//!
+//! Every record is checked against the encoding of the table. By default, the import is aborted
+//! on the first bad record and since nothing is flushed in that case, the data directory is left
+//! as it was. With `--on-error skip`, bad records are skipped instead and their line numbers are
+//! listed in the final report
+
+use crate::config::ConfigError;
+use crate::corestore::memstore::DdlError;
+use crate::corestore::table::Table;
+use crate::corestore::{Corestore, Data};
+use crate::kvengine::{DoubleEncoder, KVEngine, PairStatus};
+use crate::queryengine::parser;
+use crate::queryengine::ActionIter;
+use bytes::Bytes;
+use clap::ArgMatches;
+use serde::Deserialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Error as IoError};
+use std::sync::Arc;
+
+/// The number of records processed between progress messages, by default
+const DEFAULT_PROGRESS_EVERY: usize = 100_000;
+/// The number of records that are set at once
+const BATCH_SIZE: usize = 1024;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// The format of the file that is imported
+pub enum Format {
+    /// Comma separated values, with the key and the value in (configurable) columns
+    Csv,
+    /// A JSON object per line, with a `key` and a `value` field
+    JsonLines,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// What to do with a bad record
+pub enum OnError {
+    /// Stop the import without writing anything to disk
+    Abort,
+    /// Skip the record and report its line number at the end
+    Skip,
+}
+
+#[derive(Debug, PartialEq)]
+/// The options passed to `skyd import`
+pub struct ImportOpts {
+    /// The table to import into
+    pub entity: String,
+    /// The format of the file
+    pub format: Format,
+    /// The file to import
+    pub file: String,
+    /// The CSV column holding the key
+    pub key_column: usize,
+    /// The CSV column holding the value
+    pub value_column: usize,
+    /// Whether the first line of the CSV file is a header
+    pub has_header: bool,
+    /// What to do with a bad record
+    pub on_error: OnError,
+    /// The model to create the table with, if it doesn't exist
+    pub create: Option<String>,
+    /// The number of records processed between progress messages
+    pub progress_every: usize,
+}
+
+impl ImportOpts {
+    /// Get the options from the matches of the `import` subcommand
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, ConfigError> {
+        let format = match matches.value_of("format") {
+            None | Some("csv") => Format::Csv,
+            Some("jsonl") => Format::JsonLines,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--format`. Expected `csv` or `jsonl`",
+                ))
+            }
+        };
+        let on_error = match matches.value_of("onerror") {
+            None | Some("abort") => OnError::Abort,
+            Some("skip") => OnError::Skip,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--on-error`. Expected `abort` or `skip`",
+                ))
+            }
+        };
+        let key_column = match matches.value_of("keycol").map(|col| col.parse()) {
+            None => 0,
+            Some(Ok(col)) => col,
+            Some(Err(_)) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--key-column`. Expected a column number",
+                ))
+            }
+        };
+        let value_column = match matches.value_of("valuecol").map(|col| col.parse()) {
+            None => 1,
+            Some(Ok(col)) => col,
+            Some(Err(_)) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--value-column`. Expected a column number",
+                ))
+            }
+        };
+        let progress_every = match matches.value_of("progress").map(|n| n.parse()) {
+            None => DEFAULT_PROGRESS_EVERY,
+            Some(Ok(n)) if n != 0 => n,
+            Some(_) => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--progress`. Expected a positive integer",
+                ))
+            }
+        };
+        Ok(Self {
+            // both of these are required
+            entity: matches.value_of("entity").unwrap_or_default().to_owned(),
+            file: matches.value_of("file").unwrap_or_default().to_owned(),
+            format,
+            key_column,
+            value_column,
+            has_header: matches.is_present("header"),
+            on_error,
+            create: matches.value_of("create").map(|model| model.to_owned()),
+            progress_every,
+        })
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+/// What an import did
+pub struct Report {
+    /// The number of records that were set
+    pub imported: usize,
+    /// The number of records whose keys already existed
+    pub existing: usize,
+    /// The line numbers of the records that were skipped
+    pub skipped: Vec<u64>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "imported {} records, {} keys already existed, skipped {} records",
+            self.imported,
+            self.existing,
+            self.skipped.len()
+        )?;
+        if !self.skipped.is_empty() {
+            let lines: Vec<String> = self.skipped.iter().map(|line| line.to_string()).collect();
+            write!(f, " (on lines {})", lines.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+/// An error that stops an import
+pub enum ImportError {
+    /// The file couldn't be read
+    Io(IoError),
+    /// A bad record (and what's wrong with it) on the given line
+    BadRecord(u64, String),
+    /// The target table couldn't be used or created
+    Table(String),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Io(e) => write!(f, "failed to read the file: {}", e),
+            ImportError::BadRecord(line, e) => write!(f, "bad record on line {}: {}", line, e),
+            ImportError::Table(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<IoError> for ImportError {
+    fn from(e: IoError) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+#[derive(Deserialize)]
+/// A record in a JSON lines file
+struct JsonRecord {
+    key: String,
+    value: String,
+}
+
+/// Import the file in `opts` into its table (creating the table if we were asked to). This
+/// doesn't flush anything
+pub fn run(db: &Corestore, opts: &ImportOpts) -> Result<Report, ImportError> {
+    let table = get_table(db, &opts.entity, opts.create.as_deref())?;
+    let kve = table.get_kvstore().map_err(|_| {
+        ImportError::Table(format!(
+            "the table `{}` isn't a key/value table",
+            opts.entity
+        ))
+    })?;
+    log::info!("Importing `{}` into `{}`", opts.file, opts.entity);
+    let file = File::open(&opts.file)?;
+    import_from(kve, BufReader::new(file), opts)
+}
+
+/// Get the table that we'll import into, creating it with `create` as its model first if
+/// we were asked to
+fn get_table(
+    db: &Corestore,
+    entity: &str,
+    create: Option<&str>,
+) -> Result<Arc<Table>, ImportError> {
+    if let Some(model) = create {
+        create_table(db, entity, model)?;
+    }
+    let parsed = parser::get_query_entity(entity.as_bytes())
+        .map_err(|_| ImportError::Table(format!("`{}` isn't a valid entity", entity)))?;
+    db.get_table(parsed)
+        .map_err(|_| ImportError::Table(format!("the table `{}` doesn't exist", entity)))
+}
+
+/// Create the table (and its keyspace) with the given model, if it doesn't already exist
+fn create_table(db: &Corestore, entity: &str, model: &str) -> Result<(), ImportError> {
+    let mut args = ActionIter::new(vec![
+        Bytes::copy_from_slice(entity.as_bytes()),
+        Bytes::copy_from_slice(model.as_bytes()),
+    ]);
+    let (entity_group, model_code) = parser::parse_table_args(&mut args).map_err(|_| {
+        ImportError::Table(format!(
+            "can't create `{}` with the model `{}`",
+            entity, model
+        ))
+    })?;
+    if let (Some(ksid), Some(_)) = &entity_group {
+        // it's fine if the keyspace already exists
+        let _ = db.create_keyspace(ksid.clone());
+    }
+    match db.create_table(entity_group, model_code, false, false, false, None) {
+        Ok(()) => {
+            log::info!("Created table `{}` with model `{}`", entity, model);
+            Ok(())
+        }
+        Err(DdlError::AlreadyExists) => Ok(()),
+        Err(_) => Err(ImportError::Table(format!(
+            "failed to create the table `{}`",
+            entity
+        ))),
+    }
+}
+
+/// Import the records read from `reader` into `kve`
+pub fn import_from(
+    kve: &KVEngine,
+    reader: impl BufRead,
+    opts: &ImportOpts,
+) -> Result<Report, ImportError> {
+    let mut importer = Importer::new(kve, opts);
+    match opts.format {
+        Format::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(opts.has_header)
+                .flexible(true)
+                .from_reader(reader);
+            for record in reader.byte_records() {
+                let (line, pair) = match record {
+                    Ok(record) => (
+                        record.position().map(|pos| pos.line()).unwrap_or_default(),
+                        csv_pair(&record, opts),
+                    ),
+                    Err(e) if e.is_io_error() => return Err(ImportError::Io(e.into())),
+                    Err(e) => (
+                        e.position().map(|pos| pos.line()).unwrap_or_default(),
+                        Err(e.to_string()),
+                    ),
+                };
+                importer.add(line, pair)?;
+            }
+        }
+        Format::JsonLines => {
+            for (idx, line) in reader.split(b'\n').enumerate() {
+                let mut line = line?;
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                if line.iter().all(|byte| byte.is_ascii_whitespace()) {
+                    continue;
+                }
+                let pair = serde_json::from_slice::<JsonRecord>(&line)
+                    .map(|record| (record.key.into_bytes(), record.value.into_bytes()))
+                    .map_err(|e| e.to_string());
+                importer.add(idx as u64 + 1, pair)?;
+            }
+        }
+    }
+    Ok(importer.finish())
+}
+
+/// Get the key and the value out of a CSV record
+fn csv_pair(record: &csv::ByteRecord, opts: &ImportOpts) -> Result<(Vec<u8>, Vec<u8>), String> {
+    match (record.get(opts.key_column), record.get(opts.value_column)) {
+        (Some(key), Some(value)) => Ok((key.to_owned(), value.to_owned())),
+        _ => Err(format!(
+            "expected the key and the value in columns {} and {}",
+            opts.key_column, opts.value_column
+        )),
+    }
+}
+
+/// Validates the records and sets them in batches
+struct Importer<'a> {
+    kve: &'a KVEngine,
+    encoder: DoubleEncoder,
+    on_error: OnError,
+    progress_every: usize,
+    batch: Vec<(Data, Data)>,
+    processed: usize,
+    report: Report,
+}
+
+impl<'a> Importer<'a> {
+    fn new(kve: &'a KVEngine, opts: &ImportOpts) -> Self {
+        Self {
+            kve,
+            encoder: kve.get_encoder(),
+            on_error: opts.on_error,
+            progress_every: opts.progress_every,
+            batch: Vec::with_capacity(BATCH_SIZE),
+            processed: 0,
+            report: Report::default(),
+        }
+    }
+    /// Add the record read from the given line
+    fn add(
+        &mut self,
+        line: u64,
+        pair: Result<(Vec<u8>, Vec<u8>), String>,
+    ) -> Result<(), ImportError> {
+        let pair = pair.and_then(|(key, value)| {
+            if self.encoder.is_ok(&key, &value) {
+                Ok((key, value))
+            } else {
+                Err("the key or the value doesn't match the encoding of the table".to_owned())
+            }
+        });
+        match pair {
+            Ok((key, value)) => {
+                self.batch.push((Data::from(key), Data::from(value)));
+                if self.batch.len() == BATCH_SIZE {
+                    self.set_batch();
+                }
+            }
+            Err(e) => match self.on_error {
+                OnError::Abort => return Err(ImportError::BadRecord(line, e)),
+                OnError::Skip => {
+                    log::warn!("Skipping the record on line {}: {}", line, e);
+                    self.report.skipped.push(line);
+                }
+            },
+        }
+        self.processed += 1;
+        if self.processed % self.progress_every == 0 {
+            log::info!("Processed {} records", self.processed);
+        }
+        Ok(())
+    }
+    fn set_batch(&mut self) {
+        // the records have been validated already, so a pair can only fail if the key exists
+        for status in self.kve.set_bulk(self.batch.drain(..)) {
+            if status == PairStatus::Okay {
+                self.report.imported += 1;
+            } else {
+                self.report.existing += 1;
+            }
+        }
+    }
+    /// Set what's left in the batch and get the report
+    fn finish(mut self) -> Report {
+        self.set_batch();
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corestore::memstore::Memstore;
+    use clap::{load_yaml, App};
+
+    fn opts(format: Format, on_error: OnError) -> ImportOpts {
+        ImportOpts {
+            entity: "import:people".to_owned(),
+            format,
+            file: String::new(),
+            key_column: 0,
+            value_column: 1,
+            has_header: false,
+            on_error,
+            create: None,
+            progress_every: DEFAULT_PROGRESS_EVERY,
+        }
+    }
+
+    fn value_of(kve: &KVEngine, key: &str) -> Option<Vec<u8>> {
+        kve.get(Data::from(key.to_owned()))
+            .unwrap()
+            .map(|value| value.to_vec())
+    }
+
+    #[test]
+    fn test_import_csv_skips_bad_records() {
+        // a (str, str) table
+        let kve = KVEngine::init(true, true);
+        kve.set(Data::from("joe"), Data::from("40")).unwrap();
+        let mut opts = opts(Format::Csv, OnError::Skip);
+        opts.has_header = true;
+        let file: &[u8] = b"name,age\nsayan,20\nbroken\nbad,\xff\xfe\njoe,30\n";
+        let report = import_from(&kve, file, &opts).unwrap();
+        assert_eq!(
+            report,
+            Report {
+                imported: 1,
+                existing: 1,
+                skipped: vec![3, 4],
+            }
+        );
+        assert_eq!(value_of(&kve, "sayan").unwrap(), b"20");
+        assert_eq!(value_of(&kve, "joe").unwrap(), b"40");
+        assert!(value_of(&kve, "name").is_none());
+        assert!(value_of(&kve, "broken").is_none());
+    }
+
+    #[test]
+    fn test_import_csv_columns() {
+        let kve = KVEngine::default();
+        let mut opts = opts(Format::Csv, OnError::Abort);
+        opts.key_column = 2;
+        opts.value_column = 0;
+        let file: &[u8] = b"20,x,sayan\n30,y,joe\n";
+        let report = import_from(&kve, file, &opts).unwrap();
+        assert_eq!(report.imported, 2);
+        assert_eq!(value_of(&kve, "sayan").unwrap(), b"20");
+        assert_eq!(value_of(&kve, "joe").unwrap(), b"30");
+    }
+
+    #[test]
+    fn test_import_jsonl() {
+        let db = Corestore::default_with_store(Memstore::new_default());
+        let table = get_table(&db, "import:people", Some("keymap(str,str)")).unwrap();
+        let kve = table.get_kvstore().unwrap();
+        let file: &[u8] =
+            b"{\"key\":\"sayan\",\"value\":\"20\"}\n\n{\"key\":\"joe\",\"value\":\"30\"}\r\n";
+        let report = import_from(kve, file, &opts(Format::JsonLines, OnError::Abort)).unwrap();
+        assert_eq!(
+            report,
+            Report {
+                imported: 2,
+                existing: 0,
+                skipped: vec![],
+            }
+        );
+        // the records can be read back from the table
+        let table = get_table(&db, "import:people", None).unwrap();
+        let kve = table.get_kvstore().unwrap();
+        assert_eq!(value_of(kve, "sayan").unwrap(), b"20");
+        assert_eq!(value_of(kve, "joe").unwrap(), b"30");
+    }
+
+    #[test]
+    fn test_import_needs_an_existing_table() {
+        let db = Corestore::default_with_store(Memstore::new_default());
+        assert!(matches!(
+            get_table(&db, "import:nope", None),
+            Err(ImportError::Table(_))
+        ));
+        assert!(matches!(
+            get_table(&db, "import:nope", Some("keymap(str)")),
+            Err(ImportError::Table(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_aborts_on_a_bad_record() {
+        let kve = KVEngine::default();
+        let file: &[u8] = b"{\"key\":\"sayan\",\"value\":\"20\"}\n{\"key\":\"joe\"}\n";
+        let ret = import_from(&kve, file, &opts(Format::JsonLines, OnError::Abort));
+        assert!(matches!(ret, Err(ImportError::BadRecord(2, _))));
+    }
+
+    #[test]
+    fn test_import_opts_from_args() {
+        let cfg_layout = load_yaml!("cli.yml");
+        let matches = App::from_yaml(cfg_layout).get_matches_from(vec![
+            "skyd",
+            "import",
+            "--entity",
+            "import:people",
+            "--format",
+            "jsonl",
+            "--on-error",
+            "skip",
+            "people.jsonl",
+        ]);
+        let parsed = ImportOpts::from_matches(matches.subcommand_matches("import").unwrap());
+        assert_eq!(
+            parsed.unwrap(),
+            ImportOpts {
+                file: "people.jsonl".to_owned(),
+                ..opts(Format::JsonLines, OnError::Skip)
+            }
+        );
+    }
+}
@@ -0,0 +1,262 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Read cache
+//!
+//! A table created with the `cached` property keeps the response frames of the values that
+//! `GET` has read, so that reading a hot key again is a single lookup and a copy of the frame
+//! to the stream: the key isn't checked against the encoding of the table and the response
+//! isn't put together again. Only values that were found are kept.
+//!
+//! Every mutation on the table removes the frame of the key that it touches (and a truncate
+//! drops all of them) through [`KVEngine::record_change`](super::KVEngine::record_change),
+//! before the write responds. A read that misses the cache only keeps the frame that it built
+//! if no mutation was made on the table (on any key) while it was reading: every invalidation
+//! bumps an epoch, and the epoch is checked under the lock of the entry the frame would go
+//! into. So a frame for a value that has since been replaced is never kept.
+//!
+//! The cache holds at most [`READ_CACHE_CAPACITY`] bytes of keys and frames. To make room,
+//! arbitrary frames are dropped, and values larger than a sixteenth of the capacity aren't
+//! cached at all
+
+use crate::corestore::htable::Coremap;
+use crate::corestore::htable::Data;
+use bytes::Bytes;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+const ORD_RELAXED: Ordering = Ordering::Relaxed;
+const ORD_SEQ: Ordering = Ordering::SeqCst;
+
+/// The number of bytes (of keys and frames) that the read cache of a table can hold
+pub const READ_CACHE_CAPACITY: usize = 4 * 1024 * 1024;
+
+/// A cached response frame
+#[derive(Debug)]
+struct CachedFrame {
+    /// the frame, as it is written to the stream
+    frame: Bytes,
+    /// the length of the value in the frame, which is what the read counters count
+    value_len: usize,
+}
+
+impl CachedFrame {
+    /// The number of bytes that the frame (along with its key) takes up in the cache
+    fn cost(&self, key: &[u8]) -> usize {
+        key.len() + self.frame.len()
+    }
+}
+
+/// Returns the response frame of a string with the given value: `+<len>\n<value>\n`
+pub fn value_frame(value: &[u8]) -> Bytes {
+    let len = value.len().to_string();
+    let mut frame = Vec::with_capacity(1 + len.len() + 1 + value.len() + 1);
+    frame.push(b'+');
+    frame.extend_from_slice(len.as_bytes());
+    frame.push(b'\n');
+    frame.extend_from_slice(value);
+    frame.push(b'\n');
+    Bytes::from(frame)
+}
+
+/// The read cache of a table (see the [module documentation](self))
+#[derive(Debug)]
+pub struct ReadCache {
+    frames: Coremap<Data, CachedFrame>,
+    /// the number of bytes that can be held
+    capacity: usize,
+    /// the number of bytes held
+    used: AtomicUsize,
+    /// bumped on every invalidation
+    epoch: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for ReadCache {
+    fn default() -> Self {
+        Self::new(READ_CACHE_CAPACITY)
+    }
+}
+
+impl ReadCache {
+    /// Create a read cache that holds at most `capacity` bytes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            frames: Coremap::new(),
+            capacity,
+            used: AtomicUsize::new(0),
+            epoch: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+    /// Returns the frame of `key` along with the length of its value. If the frame isn't
+    /// cached, the value is read with `read` and its frame is cached (unless the table was
+    /// mutated in the meantime)
+    pub fn get_or_read(
+        &self,
+        key: &[u8],
+        read: impl FnOnce() -> Option<Bytes>,
+    ) -> Option<(Bytes, usize)> {
+        if let Some(cached) = self.frames.get(key) {
+            self.hits.fetch_add(1, ORD_RELAXED);
+            return Some((cached.frame.clone(), cached.value_len));
+        }
+        self.misses.fetch_add(1, ORD_RELAXED);
+        // this has to be read before the value: a mutation that we don't see here then bumps
+        // the epoch after changing the value
+        let epoch = self.epoch.load(ORD_SEQ);
+        let value = read()?;
+        let cached = CachedFrame {
+            frame: self::value_frame(&value),
+            value_len: value.len(),
+        };
+        let ret = (cached.frame.clone(), cached.value_len);
+        self.insert(key, cached, epoch);
+        Some(ret)
+    }
+    /// Cache the frame of `key` if there has been no invalidation since `epoch`
+    fn insert(&self, key: &[u8], cached: CachedFrame, epoch: u64) {
+        let cost = cached.cost(key);
+        if cost > self.capacity / 16 {
+            return;
+        }
+        self.make_room(cost);
+        if let Some(entry) = self.frames.fresh_entry(Data::copy_from_slice(key)) {
+            // an invalidation that bumps the epoch after this has to wait for the entry to be
+            // released before it can remove it
+            if self.epoch.load(ORD_SEQ) == epoch {
+                self.used.fetch_add(cost, ORD_RELAXED);
+                entry.insert(cached);
+            }
+        }
+    }
+    /// Drop frames until there is room for `cost` more bytes
+    fn make_room(&self, cost: usize) {
+        while self.used.load(ORD_RELAXED) + cost > self.capacity {
+            // the iterator holds on to a shard, so the key has to be let go of first
+            let victim = match self.frames.iter().next() {
+                Some(entry) => entry.key().clone(),
+                None => break,
+            };
+            self.remove(&victim);
+        }
+    }
+    fn remove(&self, key: &[u8]) {
+        if let Some((key, cached)) = self.frames.remove(key) {
+            self.used.fetch_sub(cached.cost(&key), ORD_RELAXED);
+        }
+    }
+    /// Drop the frame of `key`, which is about to be (or has been) mutated
+    pub fn invalidate(&self, key: &[u8]) {
+        self.epoch.fetch_add(1, ORD_SEQ);
+        self.remove(key);
+    }
+    /// Drop every frame
+    pub fn clear(&self) {
+        self.epoch.fetch_add(1, ORD_SEQ);
+        self.frames.retain(|key, cached| {
+            self.used.fetch_sub(cached.cost(key), ORD_RELAXED);
+            false
+        });
+    }
+    /// Returns the number of reads that found the frame in the cache
+    pub fn hits(&self) -> u64 {
+        self.hits.load(ORD_RELAXED)
+    }
+    /// Returns the number of reads that didn't find the frame in the cache
+    pub fn misses(&self) -> u64 {
+        self.misses.load(ORD_RELAXED)
+    }
+    /// Zero the hit and miss counters
+    pub fn reset_counters(&self) {
+        self.hits.store(0, ORD_RELAXED);
+        self.misses.store(0, ORD_RELAXED);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_is_a_string_response() {
+        assert_eq!(&value_frame(b"world")[..], b"+5\nworld\n");
+        assert_eq!(&value_frame(b"")[..], b"+0\n\n");
+    }
+
+    #[test]
+    fn test_misses_then_hits() {
+        let cache = ReadCache::default();
+        let read = || Some(Bytes::from_static(b"world"));
+        assert_eq!(
+            cache.get_or_read(b"hello", read).unwrap(),
+            (Bytes::from_static(b"+5\nworld\n"), 5)
+        );
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+        let frame = cache.get_or_read(b"hello", || panic!("the frame was cached"));
+        assert_eq!(frame.unwrap().0, Bytes::from_static(b"+5\nworld\n"));
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        // missing keys aren't cached
+        assert!(cache.get_or_read(b"nope", || None).is_none());
+        assert_eq!(cache.frames.len(), 1);
+        cache.invalidate(b"hello");
+        assert_eq!((cache.frames.len(), cache.used.load(ORD_RELAXED)), (0, 0));
+    }
+
+    #[test]
+    fn test_frame_read_across_an_invalidation_is_not_kept() {
+        let cache = ReadCache::default();
+        let frame = cache.get_or_read(b"hello", || {
+            // a write comes in while the value is being read
+            cache.invalidate(b"hello");
+            Some(Bytes::from_static(b"old"))
+        });
+        // the reader still gets what it read, but nobody else will
+        assert_eq!(frame.unwrap().1, 3);
+        assert_eq!(cache.frames.len(), 0);
+    }
+
+    #[test]
+    fn test_cache_stays_within_its_capacity() {
+        let cache = ReadCache::new(1024);
+        let value = Bytes::from(vec![b'x'; 32]);
+        for i in 0..100 {
+            let key = format!("key{:03}", i);
+            cache.get_or_read(key.as_bytes(), || Some(value.clone()));
+            assert!(cache.used.load(ORD_RELAXED) <= 1024);
+        }
+        assert_ne!(cache.frames.len(), 0);
+        // too large to be worth the room
+        let large = Bytes::from(vec![b'x'; 128]);
+        cache.get_or_read(b"large", || Some(large));
+        assert!(cache.get_or_read(b"large", || None).is_none());
+        cache.clear();
+        assert_eq!((cache.frames.len(), cache.used.load(ORD_RELAXED)), (0, 0));
+    }
+}
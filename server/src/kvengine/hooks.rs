@@ -0,0 +1,173 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Table hooks
+//!
+//! Some features of a table have to see its reads or its writes: the read cache serves the
+//! `GET`s and drops the frames of the keys that are written, and the change log (which
+//! `SYNCSTREAM` streams from) records every write. Which of these [`Hook`]s a table has is
+//! decided when it's set up and packed into a single [`Hooks`] word, so that a read or a write
+//! checks for all of them with one branch. A table with none of them reads a key with the
+//! encoding check (if the table has an encoding), one lookup and one refcounted clone of the
+//! value, and counts the read.
+//!
+//! A new feature that has to see the writes is another [`Hook`], with its bit and its arm in
+//! [`KVEngine::record_change`](super::KVEngine::record_change). The read path is only touched
+//! if the feature has to see the reads too, in which case it goes in [`READ_HOOKS`].
+//!
+//! The versions of the watched keys (see `WATCH`) see the writes as well, but a table is
+//! watched and unwatched long after it's set up. The bits above the hooks of the word count
+//! the keys that are watched, and the table keeps the word in an [`AtomicHooks`], so that a
+//! write still checks for the hooks and the watched keys with one load and one branch
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of the low bits of a [`Hooks`] word that hold the [`Hook`]s. The bits above
+/// them count the watched keys
+const HOOK_BITS: u32 = 8;
+/// The mask of the bits that hold the hooks
+const HOOK_MASK: usize = (1 << HOOK_BITS) - 1;
+/// One watched key
+const ONE_WATCHED: usize = 1 << HOOK_BITS;
+
+/// A feature of a table that sees its reads or its writes
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum Hook {
+    /// the [read cache](super::cache), which serves the reads and is invalidated by the writes
+    ReadCache = 0b01,
+    /// the [change log](crate::registry::ChangeLog), which records the writes
+    ChangeLog = 0b10,
+}
+
+/// The hooks that see the reads of a table
+pub const READ_HOOKS: u8 = Hook::ReadCache as u8;
+/// The hooks that see the writes of a table
+pub const WRITE_HOOKS: u8 = Hook::ReadCache as u8 | Hook::ChangeLog as u8;
+/// What has to see the writes: the write hooks and any watched key
+const WRITE_MASK: usize = WRITE_HOOKS as usize | !HOOK_MASK;
+
+/// The hooks that a table has. See the [module level docs](self) for more information
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hooks(usize);
+
+impl Hooks {
+    /// No hooks at all
+    pub const fn none() -> Self {
+        Self(0)
+    }
+    /// Add `hook`
+    pub const fn with(self, hook: Hook) -> Self {
+        Self(self.0 | hook as usize)
+    }
+    /// Returns true if any hook has to see the reads
+    pub const fn on_reads(self) -> bool {
+        self.0 & READ_HOOKS as usize != 0
+    }
+    /// Returns true if any hook (or any watched key) has to see the writes
+    pub const fn on_writes(self) -> bool {
+        self.0 & WRITE_MASK != 0
+    }
+    /// Returns the number of keys that are watched
+    pub const fn watched(self) -> usize {
+        self.0 >> HOOK_BITS
+    }
+}
+
+/// The [`Hooks`] word of a table. The hooks are only added while the table is set up, but the
+/// count of the watched keys changes whenever a key is watched or unwatched
+#[derive(Debug)]
+pub struct AtomicHooks(AtomicUsize);
+
+impl AtomicHooks {
+    /// No hooks and no watched keys
+    pub const fn new() -> Self {
+        Self(AtomicUsize::new(0))
+    }
+    /// Returns the hooks along with the number of watched keys
+    pub fn load(&self) -> Hooks {
+        Hooks(self.0.load(Ordering::SeqCst))
+    }
+    /// Add `hook`
+    pub fn add(&mut self, hook: Hook) {
+        *self.0.get_mut() |= hook as usize;
+    }
+    /// Count another watched key
+    pub fn add_watched(&self) {
+        self.0.fetch_add(ONE_WATCHED, Ordering::SeqCst);
+    }
+    /// Count a key that is no longer watched
+    pub fn remove_watched(&self) {
+        self.0.fetch_sub(ONE_WATCHED, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    static HOOKED_READS: core::cell::Cell<usize> = core::cell::Cell::new(0);
+}
+
+/// Count a read that went through the hooks (only in tests, which check that the reads of a
+/// table without hooks never do)
+#[inline(always)]
+pub(super) fn count_hooked_read() {
+    #[cfg(test)]
+    HOOKED_READS.with(|count| count.set(count.get() + 1));
+}
+
+#[cfg(test)]
+/// Returns the number of reads made by this thread that went through the hooks
+pub fn hooked_reads() -> usize {
+    HOOKED_READS.with(core::cell::Cell::get)
+}
+
+#[test]
+fn test_hooks_word() {
+    let hooks = Hooks::none();
+    assert!(!hooks.on_reads() && !hooks.on_writes());
+    let logged = hooks.with(Hook::ChangeLog);
+    assert!(!logged.on_reads() && logged.on_writes());
+    let cached = logged.with(Hook::ReadCache);
+    assert!(cached.on_reads() && cached.on_writes());
+    assert_eq!(cached.with(Hook::ReadCache), cached);
+}
+
+#[test]
+fn test_watched_keys_in_the_hooks_word() {
+    let mut hooks = AtomicHooks::new();
+    assert!(!hooks.load().on_writes());
+    // a watched key has to see the writes, but never the reads
+    hooks.add_watched();
+    hooks.add_watched();
+    assert_eq!(hooks.load().watched(), 2);
+    assert!(hooks.load().on_writes() && !hooks.load().on_reads());
+    hooks.add(Hook::ReadCache);
+    assert!(hooks.load().on_reads());
+    assert_eq!(hooks.load().watched(), 2);
+    hooks.remove_watched();
+    hooks.remove_watched();
+    assert_eq!(hooks.load(), Hooks::none().with(Hook::ReadCache));
+}
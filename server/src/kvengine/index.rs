@@ -0,0 +1,200 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Ordered index
+//!
+//! Tables created with the `ordered` property keep a sorted index of their keys alongside the
+//! hash table so that key ranges can be read without a full scan. Every mutation that adds or
+//! removes a key goes through [`OrderedIndex::insert_with`] or [`OrderedIndex::remove_with`]
+//! which mutate the table while holding the index's write lock: this way a concurrent insert and
+//! remove of the same key can never leave the index disagreeing with the table.
+//!
+//! The index is never persisted; it is rebuilt from the table's data when the table is loaded.
+//!
+//! ## Memory overhead
+//!
+//! The index shares the key bytes with the table (cloning a [`Data`] only bumps a reference
+//! count), so each indexed key costs one `Data` handle plus its share of the B-tree's node
+//! bookkeeping. [`OrderedIndex::approx_memory`] estimates this as `ENTRY_OVERHEAD` bytes per key
+
+use crate::corestore::Data;
+use core::mem;
+use core::ops::Bound;
+use std::collections::BTreeSet;
+use std::sync::PoisonError;
+use std::sync::RwLock;
+
+/// The approximate number of bytes that every indexed key costs: the key handle and about a
+/// pointer's worth of node bookkeeping
+const ENTRY_OVERHEAD: usize = mem::size_of::<Data>() + mem::size_of::<usize>();
+
+#[derive(Debug, Default)]
+/// A sorted index of the keys present in a table
+pub struct OrderedIndex {
+    keys: RwLock<BTreeSet<Data>>,
+}
+
+impl OrderedIndex {
+    /// Create an empty index
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Create an index over the provided keys (used when loading a table)
+    pub fn from_keys(keys: impl Iterator<Item = Data>) -> Self {
+        Self {
+            keys: RwLock::new(keys.collect()),
+        }
+    }
+    // the index is only ever mutated along with the table, so a panic while it was locked
+    // can't have left it in a state that's worse than what the table itself is in
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeSet<Data>> {
+        self.keys.read().unwrap_or_else(PoisonError::into_inner)
+    }
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeSet<Data>> {
+        self.keys.write().unwrap_or_else(PoisonError::into_inner)
+    }
+    /// Returns the number of indexed keys
+    pub fn len(&self) -> usize {
+        self.read().len()
+    }
+    /// Returns true if no keys are indexed
+    pub fn is_empty(&self) -> bool {
+        self.read().is_empty()
+    }
+    /// Returns the approximate number of bytes used by the index
+    pub fn approx_memory(&self) -> usize {
+        self.len() * ENTRY_OVERHEAD
+    }
+    /// Run `insert` (which should return true if it added `key` to the table) and index the key
+    /// if it did
+    pub fn insert_with(&self, key: Data, insert: impl FnOnce(Data) -> bool) -> bool {
+        let mut keys = self.write();
+        let did = insert(key.clone());
+        if did {
+            keys.insert(key);
+        }
+        did
+    }
+    /// Run `remove` (which should return the removed key/value pair, if any) and drop the key
+    /// from the index if something was removed
+    pub fn remove_with(
+        &self,
+        remove: impl FnOnce() -> Option<(Data, Data)>,
+    ) -> Option<(Data, Data)> {
+        let mut keys = self.write();
+        let removed = remove();
+        if let Some((key, _)) = &removed {
+            keys.remove(key);
+        }
+        removed
+    }
+    /// Run `clear` (which should empty the table) and empty the index
+    pub fn clear_with(&self, clear: impl FnOnce()) {
+        let mut keys = self.write();
+        clear();
+        keys.clear();
+    }
+    /// Run `retain` (which should only keep the keys in the table for which `keep` returns
+    /// true) and do the same with the index
+    pub fn retain_with(&self, retain: impl FnOnce(), keep: impl Fn(&Data) -> bool) {
+        let mut keys = self.write();
+        retain();
+        keys.retain(|key| keep(key));
+    }
+    /// Call `f` on (at most `limit`) keys within the inclusive range `start..=end`, in order.
+    /// The index stays read-locked until this returns, so no key can be added to or removed
+    /// from the table while `f` runs
+    pub fn for_each_in_range(&self, start: &[u8], end: &[u8], limit: usize, f: impl FnMut(&Data)) {
+        if start > end {
+            // BTreeSet::range panics on inverted ranges; this one is simply empty
+            return;
+        }
+        let keys = self.read();
+        keys.range::<[u8], _>((Bound::Included(start), Bound::Included(end)))
+            .take(limit)
+            .for_each(f);
+    }
+    /// Call `f` on (at most `limit`) keys from the start of the index, in order
+    pub fn for_each_first(&self, limit: usize, f: impl FnMut(&Data)) {
+        self.read().iter().take(limit).for_each(f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(index: &OrderedIndex, start: &str, end: &str, limit: usize) -> Vec<Data> {
+        let mut ret = Vec::new();
+        index.for_each_in_range(start.as_bytes(), end.as_bytes(), limit, |key| {
+            ret.push(key.clone())
+        });
+        ret
+    }
+
+    fn keys(keys: &[&'static str]) -> Vec<Data> {
+        keys.iter().map(|key| Data::from(*key)).collect()
+    }
+
+    #[test]
+    fn test_range_is_inclusive_and_ordered() {
+        let index = OrderedIndex::from_keys(keys(&["c", "a", "d", "b", "e"]).into_iter());
+        assert_eq!(range(&index, "b", "d", usize::MAX), keys(&["b", "c", "d"]));
+        assert_eq!(range(&index, "a", "a", usize::MAX), keys(&["a"]));
+        assert_eq!(range(&index, "b", "z", 2), keys(&["b", "c"]));
+        // bounds don't need to be present
+        assert_eq!(range(&index, "bb", "dd", usize::MAX), keys(&["c", "d"]));
+    }
+
+    #[test]
+    fn test_inverted_range_is_empty() {
+        let index = OrderedIndex::from_keys(keys(&["a", "b"]).into_iter());
+        assert!(range(&index, "b", "a", usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn test_first_keys_are_ordered() {
+        let index = OrderedIndex::from_keys(keys(&["c", "a", "b"]).into_iter());
+        let mut first = Vec::new();
+        index.for_each_first(2, |key| first.push(key.clone()));
+        assert_eq!(first, keys(&["a", "b"]));
+    }
+
+    #[test]
+    fn test_mutations_follow_the_closure() {
+        let index = OrderedIndex::new();
+        assert!(index.insert_with(Data::from("a"), |_| true));
+        assert!(!index.insert_with(Data::from("b"), |_| false));
+        assert_eq!(range(&index, "a", "z", usize::MAX), keys(&["a"]));
+        assert!(index.remove_with(|| None).is_none());
+        assert_eq!(index.len(), 1);
+        assert!(index
+            .remove_with(|| Some((Data::from("a"), Data::from("1"))))
+            .is_some());
+        assert_eq!(index.len(), 0);
+        assert_eq!(index.approx_memory(), 0);
+    }
+}
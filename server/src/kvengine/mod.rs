@@ -26,17 +26,40 @@
 
 use crate::corestore::htable::Coremap;
 use crate::corestore::htable::Data;
+use crate::corestore::htable::HashBuilder;
 use crate::corestore::htable::MapRWLGuard;
 use crate::corestore::htable::MapSingleReference;
 use crate::corestore::htable::SharedValue;
+use crate::corestore::object::ObjectType;
+use crate::registry;
+use crate::registry::Mutation;
+use bytes::Bytes;
 use core::borrow::Borrow;
 use core::hash::Hash;
+use core::mem;
 use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::AtomicUsize;
 use core::sync::atomic::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+pub mod cache;
 pub mod encoding;
+pub mod hooks;
+pub mod index;
+pub mod stats;
+pub use cache::ReadCache;
+use hooks::{AtomicHooks, Hook, Hooks};
+pub use index::OrderedIndex;
+pub use stats::{PairSize, SizeHistogram, TableCounters};
 
 const ORD_RELAXED: Ordering = Ordering::Relaxed;
 
+/// The approximate number of bytes that every pair costs on top of the key and the value
+/// themselves: the slot in the table (which holds both the `Data`s) and the control byte
+/// of the hashtable
+pub const ENTRY_OVERHEAD: usize = mem::size_of::<(Data, Data)>() + 1;
+
 /// A shard lock
 ///
 /// Our jagged or sharded or striped in-memory table is made of multiple in-memory shards
@@ -48,7 +71,8 @@ pub struct ShardLock<'a> {
     /// A reference to the table (just for lifetime convenience)
     _tableref: &'a Coremap<Data, Data>,
     /// the shard locks
-    shard_locks: Vec<MapRWLGuard<'a, std::collections::HashMap<Data, SharedValue<Data>>>>,
+    shard_locks:
+        Vec<MapRWLGuard<'a, std::collections::HashMap<Data, SharedValue<Data>, HashBuilder>>>,
 }
 
 impl<'a> ShardLock<'a> {
@@ -94,6 +118,72 @@ impl SingleEncoder {
     }
 }
 
+/// Copy a key or a value that is about to be stored (or logged). The keys and values in a query
+/// are slices of the connection's read buffer, and storing them as they are would keep the
+/// entire buffer alive for as long as they are stored
+fn detach(data: Data) -> Data {
+    Data::copy_from_slice(&data)
+}
+
+/// What happened (or what would happen) to a pair in a bulk write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PairStatus {
+    /// The pair was (or can be) written
+    Okay,
+    /// The key doesn't match the encoding of the table
+    BadKeyEncoding,
+    /// The value doesn't match the encoding of the table
+    BadValueEncoding,
+    /// The key already exists, and the write only sets new keys
+    Exists,
+    /// The key doesn't exist, and the write only updates existing keys
+    Missing,
+}
+
+impl PairStatus {
+    /// Returns the status code that is sent to the client for this status
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Okay => "ok",
+            Self::BadKeyEncoding => "bad-key-encoding",
+            Self::BadValueEncoding => "bad-value-encoding",
+            Self::Exists => "exists",
+            Self::Missing => "missing",
+        }
+    }
+}
+
+/// What a write that only happens if the key holds some value did (see
+/// [`KVEngine::update_if_eq`] and [`KVEngine::remove_if_eq`])
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IfEq {
+    /// The key held the value, and the write was made
+    Written,
+    /// The key doesn't exist or holds another value, so nothing was written
+    NotMatched,
+    /// The key held the value, but it's protected from deletion
+    Protected,
+}
+
+/// Why the value of a key couldn't be read (see [`KVEngine::read`])
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReadError {
+    /// The key doesn't match the encoding of the table, so it can't be in the table
+    BadKey,
+    /// The key is in the table, but the stored bytes of its value don't decode to a value of
+    /// the table's type
+    Unreadable,
+}
+
+/// The kind of a bulk write
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BulkWrite {
+    /// Set the values of non-existent keys (like `MSET`)
+    Set,
+    /// Update the values of existing keys (like `MUPDATE`)
+    Update,
+}
+
 // DROP impl isn't required as ShardLock's field types need-drop (std::mem)
 
 /// The key/value engine that acts as the in-memory backing store for the database
@@ -106,6 +196,55 @@ pub struct KVEngine {
     encoded_k: AtomicBool,
     /// the encoding switch for the value
     encoded_v: AtomicBool,
+    /// the number of bytes mutated since this table was last flushed
+    dirty: AtomicUsize,
+    /// the number of bytes held by the keys and values in this table
+    stored: AtomicUsize,
+    /// the lengths of the keys in this table
+    key_sizes: SizeHistogram,
+    /// the lengths of the values in this table
+    value_sizes: SizeHistogram,
+    /// the change log sequence number at the time of the latest mutation (or of the creation
+    /// of this table)
+    modified: AtomicU64,
+    /// the read and write counters
+    counters: TableCounters,
+    /// the ordered index, if this table has one
+    index: Option<OrderedIndex>,
+    /// the keys that are protected from deletion
+    protected: Coremap<Data, ()>,
+    /// the keys that have an expiry time, mapped to that time (in milliseconds since the
+    /// unix epoch)
+    expiry: Coremap<Data, i64>,
+    /// the entity (`<keyspace>:<table>`) under which the mutations on this table are logged.
+    /// If this isn't set, the mutations aren't logged
+    entity: Option<Data>,
+    /// the largest value (in bytes) that can be written to this table. A zero means that the
+    /// table doesn't have a limit of its own and the server-wide limit applies
+    value_limit: AtomicU64,
+    /// the cache of `GET` responses, if this table has one
+    cache: Option<ReadCache>,
+    /// the features of this table that see its reads or writes, along with the number of
+    /// keys in `versions` (see [`hooks`])
+    hooks: AtomicHooks,
+    /// the versions of the keys that connections are watching (see [`KVEngine::watch`]). A
+    /// key is only in here while it's watched, and its version isn't persisted
+    versions: Coremap<Data, KeyVersion>,
+}
+
+/// The version of a watched key, which every write of the key bumps. This is shared by the
+/// table and everyone watching the key
+pub type KeyVersion = Arc<AtomicU64>;
+
+/// A value is larger than what the table allows. This holds the limit that applied
+pub struct ValueTooLarge(pub u64);
+
+impl ValueTooLarge {
+    /// Returns the error to write out, which names the limit: `value-too-large:<limit>`
+    pub fn error(&self) -> Vec<u8> {
+        let err = format!("value-too-large:{}", self.0);
+        format!("!{}\n{}\n", err.len(), err).into_bytes()
+    }
 }
 
 /// Errors arising from trying to modify the definition of tables
@@ -128,10 +267,170 @@ impl KVEngine {
         Self::init_with_data(encoded_k, encoded_v, Coremap::new())
     }
     pub fn init_with_data(encoded_k: bool, encoded_v: bool, table: Coremap<Data, Data>) -> Self {
+        let (key_sizes, value_sizes) = (SizeHistogram::new(), SizeHistogram::new());
+        let stored = table
+            .iter()
+            .map(|kv| {
+                key_sizes.add(kv.key().len());
+                value_sizes.add(kv.value().len());
+                kv.key().len() + kv.value().len()
+            })
+            .sum();
         Self {
             table,
             encoded_k: AtomicBool::new(encoded_k),
             encoded_v: AtomicBool::new(encoded_v),
+            dirty: AtomicUsize::new(0),
+            stored: AtomicUsize::new(stored),
+            key_sizes,
+            value_sizes,
+            modified: AtomicU64::new(registry::get_changelog().current_seq()),
+            counters: TableCounters::new(),
+            index: None,
+            protected: Coremap::new(),
+            expiry: Coremap::new(),
+            entity: None,
+            value_limit: AtomicU64::new(0),
+            cache: None,
+            hooks: AtomicHooks::new(),
+            versions: Coremap::new(),
+        }
+    }
+    /// Build an ordered index over whatever is in the table and maintain it from here on
+    pub fn with_ordered_index(mut self) -> Self {
+        self.index = Some(OrderedIndex::from_keys(
+            self.table.iter().map(|kv| kv.key().clone()),
+        ));
+        self
+    }
+    /// Log the mutations on this table in the change log, under `entity`
+    pub fn with_entity(mut self, entity: Data) -> Self {
+        self.entity = Some(entity);
+        self.hooks.add(Hook::ChangeLog);
+        self
+    }
+    /// Keep the response frames of the values that are read with [`KVEngine::get_frame`]
+    /// (see [`cache`])
+    pub fn with_read_cache(mut self) -> Self {
+        self.cache = Some(ReadCache::default());
+        self.hooks.add(Hook::ReadCache);
+        self
+    }
+    /// Returns the entity that the mutations on this table are logged under, if they are
+    pub fn entity(&self) -> Option<&Data> {
+        self.entity.as_ref()
+    }
+    /// Returns the features of this table that see its reads or writes
+    pub fn hooks(&self) -> Hooks {
+        self.hooks.load()
+    }
+    /// Returns the read cache if this table has one
+    pub fn read_cache(&self) -> Option<&ReadCache> {
+        self.cache.as_ref()
+    }
+    /// Log a mutation in the change log and drop the cached frame of the key that it touched.
+    /// Anything that mutates the table without going through the methods on `KVEngine` needs
+    /// to call this (after mutating the table, and before responding)
+    pub fn record_change(&self, mutation: Mutation) {
+        // a table without any of the hooks (that nobody watches) pays for this branch and
+        // nothing else
+        let hooks = self.hooks.load();
+        if !hooks.on_writes() {
+            return;
+        }
+        if hooks.watched() != 0 {
+            self.bump_version(&mutation);
+        }
+        if let Some(cache) = &self.cache {
+            match &mutation {
+                Mutation::Set(key, _)
+                | Mutation::Update(key, _)
+                | Mutation::Upsert(key, _)
+                | Mutation::Remove(key)
+                | Mutation::Expire(key) => cache.invalidate(key),
+                // the flags don't show up in the value
+                Mutation::Protect(_) | Mutation::Unprotect(_) => {}
+                Mutation::Flush { .. } => cache.clear(),
+            }
+        }
+        if let Some(entity) = &self.entity {
+            registry::get_changelog().record(entity.clone(), mutation);
+        }
+    }
+    /// Bump the versions of the watched keys that `mutation` wrote
+    fn bump_version(&self, mutation: &Mutation) {
+        match mutation {
+            // the flags don't show up in the value
+            Mutation::Protect(_) | Mutation::Unprotect(_) => {}
+            // every watched key may have gone
+            Mutation::Flush { .. } => self.versions.iter().for_each(|version| {
+                version.value().fetch_add(1, Ordering::SeqCst);
+            }),
+            mutation => {
+                if let Some(version) = mutation.key().and_then(|key| self.versions.get(key)) {
+                    version.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        }
+    }
+    /// Start watching `key`, returning its version. Until it's handed back to
+    /// [`KVEngine::unwatch`], every write of the key (including the ones that remove it)
+    /// bumps the version
+    pub fn watch(&self, key: Data) -> KeyVersion {
+        self.versions
+            .inner
+            .entry(key)
+            .or_insert_with(|| {
+                self.hooks.add_watched();
+                Arc::new(AtomicU64::new(0))
+            })
+            .clone()
+    }
+    /// Stop watching `key` with `version` (which [`KVEngine::watch`] returned). The key is
+    /// forgotten once no one watches it
+    pub fn unwatch(&self, key: &Data, version: KeyVersion) {
+        drop(version);
+        // this holds the lock of the entry, so no one can start watching the key in between
+        if self
+            .versions
+            .remove_if(key, |_, version| Arc::strong_count(version) == 1)
+            .is_some()
+        {
+            self.hooks.remove_watched();
+        }
+    }
+    #[cfg(test)]
+    /// Returns the number of keys that are watched
+    pub fn watched_keys(&self) -> usize {
+        self.hooks.load().watched()
+    }
+    /// Returns the ordered index if this table has one
+    pub fn get_ordered_index(&self) -> Option<&OrderedIndex> {
+        self.index.as_ref()
+    }
+    /// Returns true if this table maintains an ordered index
+    pub fn is_ordered(&self) -> bool {
+        self.index.is_some()
+    }
+    /// Run `insert` (which returns true if it added `key` to the table), keeping the ordered
+    /// index in sync. Anything that adds keys without going through the methods on `KVEngine`
+    /// needs to use this
+    pub fn insert_indexed(&self, key: Data, insert: impl FnOnce(Data) -> bool) -> bool {
+        match &self.index {
+            Some(index) => index.insert_with(key, insert),
+            None => insert(key),
+        }
+    }
+    /// Run `remove` (which returns the removed pair, if any), keeping the ordered index in
+    /// sync. Anything that removes keys without going through the methods on `KVEngine` needs
+    /// to use this
+    pub fn remove_indexed(
+        &self,
+        remove: impl FnOnce() -> Option<(Data, Data)>,
+    ) -> Option<(Data, Data)> {
+        match &self.index {
+            Some(index) => index.remove_with(remove),
+            None => remove(),
         }
     }
     pub fn get_encoding(&self) -> (bool, bool) {
@@ -140,6 +439,34 @@ impl KVEngine {
             self.encoded_v.load(ORD_RELAXED),
         )
     }
+    /// Check a key and a value (either can be left out) against the encoding of the table.
+    /// This is the check that every write makes (and `VALIDATE` runs on its own), and it
+    /// doesn't look at the data
+    pub fn check_encoding(&self, key: Option<&[u8]>, value: Option<&[u8]>) -> PairStatus {
+        let (encoded_k, encoded_v) = self.get_encoding();
+        match (key, value) {
+            (Some(key), _) if encoded_k && !encoding::is_utf8(key) => PairStatus::BadKeyEncoding,
+            (_, Some(value)) if encoded_v && !encoding::is_utf8(value) => {
+                PairStatus::BadValueEncoding
+            }
+            _ => PairStatus::Okay,
+        }
+    }
+    /// Returns what the encoding of the table requires of the keys and of the values: `utf8`
+    /// if they have to be valid UTF-8, else `none`
+    pub fn encoding_rules(&self) -> (&'static str, &'static str) {
+        let rule = |is_str: bool| if is_str { "utf8" } else { "none" };
+        let (encoded_k, encoded_v) = self.get_encoding();
+        (rule(encoded_k), rule(encoded_v))
+    }
+    /// Returns the type of the values stored in this table
+    pub fn get_value_type(&self) -> ObjectType {
+        if self.encoded_v.load(ORD_RELAXED) {
+            ObjectType::Str
+        } else {
+            ObjectType::BinStr
+        }
+    }
     /// Returns an encoder for the key and the value
     pub fn get_encoder(&self) -> DoubleEncoder {
         let (encoded_k, encoded_v) = (
@@ -214,6 +541,103 @@ impl KVEngine {
     pub fn __get_inner_ref(&self) -> &Coremap<Data, Data> {
         &self.table
     }
+    /// Returns the number of bytes mutated since this table was last flushed
+    pub fn dirty_bytes(&self) -> usize {
+        self.dirty.load(ORD_RELAXED)
+    }
+    /// Account for a mutation of `delta` bytes. Anything that mutates the table without
+    /// going through the methods on `KVEngine` needs to call this
+    pub fn mark_dirty(&self, delta: usize) {
+        self.dirty.fetch_add(delta, ORD_RELAXED);
+        registry::get_dirty_tracker().add(delta);
+        self.touch();
+    }
+    /// Returns the change log sequence number at the time of the latest mutation on this table
+    /// (in the current [epoch](crate::registry::ChangeLog::epoch)). A snapshot that was taken
+    /// at a sequence number below this one might not have all the mutations
+    pub fn last_modified(&self) -> u64 {
+        self.modified.load(ORD_RELAXED)
+    }
+    /// Remember that the table was mutated at the current sequence number. This has to happen
+    /// after the mutation is visible, so that a snapshot that reads a later sequence number
+    /// is sure to see it
+    fn touch(&self) {
+        self.modified
+            .fetch_max(registry::get_changelog().current_seq(), ORD_RELAXED);
+    }
+    /// Returns the read and write counters of this table
+    pub fn counters(&self) -> &TableCounters {
+        &self.counters
+    }
+    /// Set the largest value (in bytes) that can be written to this table, or go back to the
+    /// server-wide limit with `None`. The values that are already in the table are left alone
+    pub fn set_value_limit(&self, limit: Option<u64>) {
+        self.value_limit.store(limit.unwrap_or(0), ORD_RELAXED)
+    }
+    /// Returns the limit on the size of values that was set on this table, if any
+    pub fn own_value_limit(&self) -> Option<u64> {
+        match self.value_limit.load(ORD_RELAXED) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+    /// Returns the limit on the size of values that applies to this table: its own if it
+    /// has one, or else the server-wide limit
+    pub fn value_limit(&self) -> Option<u64> {
+        self.own_value_limit().or_else(registry::get_value_limit)
+    }
+    /// Check the lengths of the values that a query is about to write against the limit that
+    /// applies to this table. This only needs the lengths, so it can be run before the
+    /// values are copied anywhere
+    pub fn check_value_sizes(
+        &self,
+        lengths: impl IntoIterator<Item = usize>,
+    ) -> Result<(), ValueTooLarge> {
+        match self.value_limit() {
+            Some(limit) if lengths.into_iter().any(|len| len as u64 > limit) => {
+                Err(ValueTooLarge(limit))
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Returns the number of bytes held by the keys and values in this table. This is kept
+    /// up to date as the table is mutated, so it doesn't need a walk over the table
+    pub fn stored_bytes(&self) -> usize {
+        self.stored.load(ORD_RELAXED)
+    }
+    /// Account for the pair `added` being stored and the pair `removed` being dropped, both in
+    /// the bytes stored and in the size histograms. A value that is replaced is dropped along
+    /// with its key, and the new value is added with the same key. Anything that mutates the
+    /// table without going through the methods on `KVEngine` needs to call this. The pair
+    /// should be added before (or while) it is visible in the table so that a racing removal
+    /// never takes away more than what was added
+    pub fn account_stored(&self, added: Option<PairSize>, removed: Option<PairSize>) {
+        if let Some(added) = added {
+            self.stored.fetch_add(added.total(), ORD_RELAXED);
+            self.key_sizes.add(added.key);
+            self.value_sizes.add(added.value);
+        }
+        if let Some(removed) = removed {
+            self.stored.fetch_sub(removed.total(), ORD_RELAXED);
+            self.key_sizes.remove(removed.key);
+            self.value_sizes.remove(removed.value);
+        }
+    }
+    /// Returns the histogram of the lengths of the keys in this table
+    pub fn key_sizes(&self) -> &SizeHistogram {
+        &self.key_sizes
+    }
+    /// Returns the histogram of the lengths of the values in this table
+    pub fn value_sizes(&self) -> &SizeHistogram {
+        &self.value_sizes
+    }
+    /// Take away `flushed` bytes once they have been written out to disk
+    pub fn clear_dirty(&self, flushed: usize) {
+        let _ = self.dirty.fetch_update(ORD_RELAXED, ORD_RELAXED, |cur| {
+            Some(cur.saturating_sub(flushed))
+        });
+        registry::get_dirty_tracker().sub(flushed);
+    }
     /// Alter the table and set the key encoding switch
     ///
     /// Note: this will need an empty table
@@ -256,89 +680,751 @@ impl KVEngine {
     {
         self.table.get(key).map(|v| v.clone())
     }
-    /// Truncate the table
-    pub fn truncate_table(&self) {
-        self.table.clear()
+    /// Truncate the table and return the number of keys that were removed. This removes the
+    /// protected keys too (along with their flags)
+    pub fn truncate_table(&self) -> usize {
+        let mut dropped = (0, 0, 0);
+        match &self.index {
+            Some(index) => index.clear_with(|| dropped = self.retain_keys(|_| false)),
+            None => dropped = self.retain_keys(|_| false),
+        }
+        let (removed, _, dropped) = dropped;
+        self.protected.clear();
+        self.expiry.clear();
+        // the dropped bytes count as mutated, so that whoever looks at the dirty bytes sees
+        // that the table changed since it was flushed
+        self.mark_dirty(dropped);
+        self.record_change(Mutation::Flush { force: true });
+        removed
     }
-    /// Get the value for a given key if it exists
-    pub fn get(&self, key: impl Into<Data>) -> Result<Option<MapSingleReference<Data, Data>>, ()> {
-        Ok(self.table.get(&self._encode_key(key.into())?))
+    /// Remove every key that isn't protected from deletion and return the number of keys that
+    /// were removed
+    pub fn clear(&self) -> usize {
+        self.clear_unprotected().0
+    }
+    /// Remove every key that isn't protected from deletion and return the number of protected
+    /// keys that were left behind
+    pub fn truncate_unprotected(&self) -> usize {
+        self.clear_unprotected().1
+    }
+    /// Remove every key that isn't protected from deletion and return the number of keys that
+    /// were removed and the number of (protected) keys that were kept. The keys that are
+    /// inserted while this runs count as neither
+    fn clear_unprotected(&self) -> (usize, usize) {
+        if self.protected.len() == 0 {
+            return (self.truncate_table(), 0);
+        }
+        let keep = |key: &Data| self.protected.contains_key(key);
+        let mut dropped = (0, 0, 0);
+        let mut retain = || dropped = self.retain_keys(keep);
+        match &self.index {
+            Some(index) => index.retain_with(retain, keep),
+            None => retain(),
+        }
+        let (removed, kept, dropped) = dropped;
+        self.expiry
+            .retain(|key, _| self.protected.contains_key(key));
+        self.mark_dirty(dropped);
+        self.record_change(Mutation::Flush { force: false });
+        (removed, kept)
+    }
+    /// Returns the number of keys that are protected from deletion
+    pub fn protected_count(&self) -> usize {
+        self.protected.len()
+    }
+    /// Only keep the pairs for which `keep` returns true (accounting for the ones that are
+    /// dropped) and return the number of pairs that were dropped, the number of pairs that were
+    /// kept and the number of bytes that were dropped
+    fn retain_keys(&self, keep: impl Fn(&Data) -> bool) -> (usize, usize, usize) {
+        let (mut removed, mut kept, mut dropped) = (0, 0, 0);
+        self.table.retain(|key, value| {
+            let retained = keep(key);
+            if retained {
+                kept += 1;
+            } else {
+                let size = PairSize::new(key.len(), value.len());
+                self.account_stored(None, Some(size));
+                removed += 1;
+                dropped += size.total();
+            }
+            retained
+        });
+        (removed, kept, dropped)
+    }
+    /// Protect an existing key from deletion. This returns false if the key doesn't exist
+    pub fn protect(&self, key: Data) -> Result<bool, ()> {
+        let key = self._encode_key(key)?;
+        // hold on to the entry so that the key can't be removed while we set the flag; removals
+        // check the flag while holding the entry too, so one of us always wins cleanly
+        let entry = match self.table.get(&key) {
+            Some(entry) => entry,
+            None => return Ok(false),
+        };
+        // use the stored key rather than (what might be) a slice of a query
+        let key = entry.key().clone();
+        let delta = key.len();
+        self.protected.upsert(key.clone(), ());
+        drop(entry);
+        self.mark_dirty(delta);
+        self.record_change(Mutation::Protect(key));
+        Ok(true)
+    }
+    /// Remove the deletion protection on a key. This returns false if the key wasn't protected
+    pub fn unprotect(&self, key: Data) -> Result<bool, ()> {
+        let key = self._encode_key(key)?;
+        let did = self.protected.true_if_removed(&key);
+        if did {
+            self.mark_dirty(key.len());
+            self.record_change(Mutation::Unprotect(detach(key)));
+        }
+        Ok(did)
+    }
+    /// Check if a key is protected from deletion
+    pub fn is_protected(&self, key: &[u8]) -> bool {
+        self.protected.contains_key(key)
     }
-    pub fn exists<Q>(&self, key: Q) -> Result<bool, ()>
+    /// Returns the keys that are protected from deletion
+    pub fn get_protected(&self) -> &Coremap<Data, ()> {
+        &self.protected
+    }
+    /// Restore the deletion protection flags (used when loading a table)
+    pub fn restore_protected(&self, keys: impl Iterator<Item = Data>) {
+        keys.for_each(|key| self.protected.upsert(key, ()));
+    }
+    /// Fill in the pairs of a table that was loaded lazily, along with their deletion
+    /// protection flags and expiry times. These are already on disk, so nothing is marked
+    /// dirty or logged in the change log
+    pub fn load_from_disk(
+        &self,
+        data: Coremap<Data, Data>,
+        protected: impl Iterator<Item = Data>,
+        expiries: impl Iterator<Item = (Data, i64)>,
+    ) {
+        for (key, value) in data {
+            let size = PairSize::new(key.len(), value.len());
+            self.insert_indexed(key, |key| match self.table.fresh_entry(key) {
+                Some(entry) => {
+                    self.account_stored(Some(size), None);
+                    entry.insert(value);
+                    true
+                }
+                None => false,
+            });
+        }
+        self.restore_protected(protected);
+        self.restore_expiries(expiries);
+    }
+    /// Set the expiry time (in milliseconds since the unix epoch) of an existing key, replacing
+    /// any older one. This returns false if the key doesn't exist
+    pub fn set_expiry(&self, key: &[u8], at: i64) -> bool {
+        // like with the protection flags, hold on to the entry so that the key can't be removed
+        // (and leave its expiry behind) while we set it
+        let entry = match self.table.get(key) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        let key = entry.key().clone();
+        let delta = key.len();
+        self.expiry.upsert(key, at);
+        drop(entry);
+        self.mark_dirty(delta);
+        true
+    }
+    /// Remove the expiry time of a key. This returns false if the key didn't have one
+    pub fn clear_expiry(&self, key: &[u8]) -> bool {
+        let did = self.expiry.true_if_removed(key);
+        if did {
+            self.mark_dirty(key.len());
+        }
+        did
+    }
+    /// Returns the expiry time of a key (in milliseconds since the unix epoch), if it has one
+    pub fn get_expiry(&self, key: &[u8]) -> Option<i64> {
+        self.expiry.get(key).map(|at| *at)
+    }
+    /// Returns the keys that have an expiry time, along with the times
+    pub fn get_expiries(&self) -> &Coremap<Data, i64> {
+        &self.expiry
+    }
+    /// Restore the expiry times (used when loading a table)
+    pub fn restore_expiries(&self, expiries: impl Iterator<Item = (Data, i64)>) {
+        expiries.for_each(|(key, at)| self.expiry.upsert(key, at));
+    }
+    /// Remove every key whose expiry time is at or before `now` (in milliseconds since the unix
+    /// epoch) and return the number of keys that were removed. Protected keys aren't removed,
+    /// but their expiry times are dropped all the same
+    pub fn remove_expired(&self, now: i64) -> usize {
+        let expired: Vec<Data> = self
+            .expiry
+            .iter()
+            .filter(|kv| *kv.value() <= now)
+            .map(|kv| kv.key().clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter(|key| self.remove_if_expired(key, now))
+            .count()
+    }
+    /// Remove `key` if its expiry time is at or before `now`, just like
+    /// [`KVEngine::remove_expired`] would, and return true if it was removed. The writes that
+    /// check whether a key exists use this first, so that a key that's only still around
+    /// because the table wasn't swept yet is treated as missing
+    fn remove_if_expired(&self, key: &Data, now: i64) -> bool {
+        // someone may have moved the expiry time after we looked
+        if !self.expiry.true_remove_if(key, |_, at| *at <= now) {
+            return false;
+        }
+        let removed = matches!(self.remove_as(key.clone(), Mutation::Expire), Ok(true));
+        if !removed {
+            // the key was protected; only its expiry time went away
+            self.touch();
+        }
+        removed
+    }
+    /// Returns the keys that start with `prefix`
+    pub fn keys_with_prefix(&self, prefix: &[u8]) -> Vec<Data> {
+        self.table
+            .iter()
+            .filter(|kv| kv.key().starts_with(prefix))
+            .map(|kv| kv.key().clone())
+            .collect()
+    }
+    /// Remove `key` unless it is protected from deletion
+    fn remove_unprotected<Q>(&self, key: &Q) -> Option<(Data, Data)>
     where
         Data: Borrow<Q>,
-        Q: AsRef<[u8]> + Hash + Eq,
+        Q: Hash + Eq + ?Sized,
     {
-        Ok(self.table.contains_key(&self._encode_key(key)?))
+        self.remove_indexed(|| {
+            self.table
+                .remove_if(key, |key, _| !self.protected.contains_key(key))
+        })
     }
-    /// Check the unicode encoding of a given byte array
-    fn _encode<Q>(data: Q) -> Result<Q, ()>
+    /// Returns (at most `limit`) keys in the inclusive range `start..=end`, in order. If the
+    /// table has no ordered index, `None` is returned
+    pub fn range_keys(&self, start: &[u8], end: &[u8], limit: usize) -> Option<Vec<Data>> {
+        let index = self.index.as_ref()?;
+        let mut keys = Vec::new();
+        index.for_each_in_range(start, end, limit, |key| keys.push(key.clone()));
+        Some(keys)
+    }
+    /// Returns (at most `count`) keys in ascending byte order. Tables with an ordered index are
+    /// read off the index; for the others only the keys are collected and sorted (so this
+    /// costs a handle per key, never a copy of the values) and if the table has more than `max`
+    /// keys, nothing is sorted and the number of keys is returned as the error
+    pub fn ordered_keys(&self, count: usize, max: usize) -> Result<Vec<Data>, usize> {
+        let mut keys = Vec::new();
+        if let Some(index) = &self.index {
+            index.for_each_first(count, |key| keys.push(key.clone()));
+            return Ok(keys);
+        }
+        let len = self.table.len();
+        if len > max {
+            return Err(len);
+        }
+        keys.reserve(len);
+        keys.extend(self.table.iter().map(|kv| kv.key().clone()));
+        keys.sort_unstable();
+        keys.truncate(count);
+        Ok(keys)
+    }
+    /// Same as [`KVEngine::range_keys`], except that the values are returned too. Since the
+    /// index is locked while the values are read, every returned key is paired with its value
+    pub fn range_pairs(&self, start: &[u8], end: &[u8], limit: usize) -> Option<Vec<(Data, Data)>> {
+        let index = self.index.as_ref()?;
+        let mut pairs = Vec::new();
+        index.for_each_in_range(start, end, limit, |key| {
+            if let Some(value) = self.table.get(key) {
+                pairs.push((key.clone(), value.clone()));
+            }
+        });
+        Some(pairs)
+    }
+    /// Get the value for a given key if it exists
+    pub fn get(&self, key: impl Into<Data>) -> Result<Option<MapSingleReference<Data, Data>>, ()> {
+        let value = self.table.get(&self._encode_key(key.into())?);
+        self.count_read(value.as_ref().map(|value| value.len()));
+        Ok(value)
+    }
+    /// Get the value of a key if it exists, without holding on to the table's entry. An error is
+    /// returned (instead of `None`) if the key doesn't match the encoding of the table. A key
+    /// whose expiry time is at or before `now` (in milliseconds since the unix epoch) is
+    /// missing, even if it wasn't swept yet
+    pub fn get_cloned(&self, key: impl Into<Data>, now: i64) -> Result<Option<Bytes>, ()> {
+        let key = self._encode_key(key.into())?;
+        let value = if self.is_expired(&key, now) {
+            None
+        } else {
+            self.table.get(&key).map(|value| value.get_blob().clone())
+        };
+        self.count_read(value.as_ref().map(|value| value.len()));
+        Ok(value)
+    }
+    /// Returns true if `key` has an expiry time at or before `now`. Such a key stays in the
+    /// table until the expiry service gets to it, so the reads have to leave it out themselves
+    fn is_expired(&self, key: &[u8], now: i64) -> bool {
+        self.expiry.get(key).map_or(false, |at| *at <= now)
+    }
+    /// Get the value of a key if it exists, like [`KVEngine::get_cloned`], but check that it
+    /// decodes to a value of the table's type first. This is what the read actions return
+    /// values with, so that a value that can't be decoded isn't mistaken for a missing key
+    pub fn read(&self, key: impl Into<Data>, now: i64) -> Result<Option<Bytes>, ReadError> {
+        match self.get_cloned(key, now) {
+            Ok(Some(value)) if !self.decodes(&value) => Err(Self::unreadable()),
+            Ok(value) => Ok(value),
+            Err(()) => Err(ReadError::BadKey),
+        }
+    }
+    /// Returns true if `key` holds a value that doesn't decode to a value of the table's type.
+    /// This counts neither as a read of the table nor as an unreadable read, so scanning the
+    /// table with it leaves the counters alone
+    pub fn is_unreadable(&self, key: &[u8]) -> bool {
+        self.encoded_v.load(ORD_RELAXED)
+            && self
+                .table
+                .get(key)
+                .map_or(false, |value| !self.decodes(value.get_blob()))
+    }
+    /// Returns true if the stored bytes of a value decode to a value of the table's type. The
+    /// values are checked when they are written, but not when they are read in from disk, so
+    /// this is where a value that rotted on disk is caught
+    fn decodes(&self, value: &[u8]) -> bool {
+        !self.encoded_v.load(ORD_RELAXED) || encoding::is_utf8(value)
+    }
+    /// Returns the error for a read that found a value that doesn't decode, and counts it
+    fn unreadable() -> ReadError {
+        registry::count_unreadable_value();
+        ReadError::Unreadable
+    }
+    #[cfg(test)]
+    /// Add `by` to the bytes that this table is tracked to hold without changing the table,
+    /// like an accounting bug would
+    pub fn skew_stored_bytes(&self, by: usize) {
+        self.stored.fetch_add(by, ORD_RELAXED);
+    }
+    #[cfg(test)]
+    /// Overwrite the stored bytes of the value of `key` with `raw` without checking them
+    /// against the encoding of the table (or telling the hooks), like a value that rotted on
+    /// disk and was read back in
+    pub fn corrupt_value(&self, key: Data, raw: Data) {
+        self.table.upsert(key, raw);
+    }
+    /// Get the response frame (see [`cache::value_frame`]) of the value of a key if it exists,
+    /// from the read cache if the table has one. A frame that is in the cache is returned
+    /// without checking the key against the encoding of the table (the key was checked when
+    /// the frame was cached), and a value that doesn't decode is never cached. Just like with
+    /// [`KVEngine::get_cloned`], a key that expired by `now` is missing
+    pub fn get_frame(&self, key: impl Into<Data>, now: i64) -> Result<Option<Bytes>, ReadError> {
+        let key = key.into();
+        let cache = match &self.cache {
+            Some(cache) if self.hooks().on_reads() => cache,
+            _ => return Ok(self.read(key, now)?.map(|v| cache::value_frame(&v))),
+        };
+        hooks::count_hooked_read();
+        if self.is_expired(&key, now) {
+            // the frame may still be in the cache, since it's only dropped with the key
+            self.count_read(None);
+            return Ok(None);
+        }
+        let mut failed = None;
+        let found = cache.get_or_read(&key, || match self._encode_key(key.clone()) {
+            Ok(key) => match self.table.get(&key) {
+                Some(value) if !self.decodes(value.get_blob()) => {
+                    failed = Some(ReadError::Unreadable);
+                    None
+                }
+                value => value.map(|value| value.get_blob().clone()),
+            },
+            Err(()) => {
+                failed = Some(ReadError::BadKey);
+                None
+            }
+        });
+        match failed {
+            Some(ReadError::Unreadable) => return Err(Self::unreadable()),
+            Some(error) => return Err(error),
+            None => {}
+        }
+        self.count_read(found.as_ref().map(|(_, len)| *len));
+        Ok(found.map(|(frame, _)| frame))
+    }
+    /// Count a read that found a value of the given length (or nothing)
+    fn count_read(&self, found: Option<usize>) {
+        match found {
+            Some(len) => self.counters.hit(len),
+            None => self.counters.miss(),
+        }
+    }
+    /// Returns true if `key` is in the table and hasn't expired by `now` (see
+    /// [`KVEngine::get_cloned`])
+    pub fn exists<Q>(&self, key: Q, now: i64) -> Result<bool, ()>
     where
         Data: Borrow<Q>,
-        Q: AsRef<[u8]>,
+        Q: AsRef<[u8]> + Hash + Eq,
     {
-        if encoding::is_utf8(data.borrow()) {
-            Ok(data)
-        } else {
-            Err(())
-        }
+        let key = self._encode_key(key)?;
+        Ok(self.table.contains_key(&key) && !self.is_expired(key.as_ref(), now))
     }
-    /// Check the unicode encoding of the given key, if the encoded_k flag is set
+    /// Check the encoding of the given key (see [`KVEngine::check_encoding`])
     fn _encode_key<Q>(&self, key: Q) -> Result<Q, ()>
     where
         Data: Borrow<Q>,
         Q: AsRef<[u8]>,
     {
-        if self.encoded_k.load(ORD_RELAXED) {
-            Self::_encode(key)
-        } else {
-            Ok(key)
+        match self.check_encoding(Some(key.as_ref()), None) {
+            PairStatus::Okay => Ok(key),
+            _ => Err(()),
         }
     }
-    /// Check the unicode encoding of the given value, if the encoded_v flag is set
+    /// Check the encoding of the given value (see [`KVEngine::check_encoding`])
     fn _encode_value<Q>(&self, value: Q) -> Result<Q, ()>
     where
         Data: Borrow<Q>,
         Q: AsRef<[u8]>,
     {
-        if self.encoded_v.load(ORD_RELAXED) {
-            Self::_encode(value)
-        } else {
-            Ok(value)
+        match self.check_encoding(None, Some(value.as_ref())) {
+            PairStatus::Okay => Ok(value),
+            _ => Err(()),
         }
     }
     /// Set the value of a non-existent key
     pub fn set(&self, key: Data, value: Data) -> Result<bool, ()> {
-        Ok(self
-            .table
-            .true_if_insert(self._encode_key(key)?, self._encode_value(value)?))
+        self.set_fresh(key, value, None)
+    }
+    /// Same as [`KVEngine::set`], but also give the new key an expiry time (in milliseconds
+    /// since the unix epoch), if one is provided. The expiry time is recorded before the new
+    /// entry is released, so no one sees the key without it. A key whose expiry time is at or
+    /// before `now` counts as missing, so it's replaced
+    pub fn set_with_expiry(
+        &self,
+        key: Data,
+        value: Data,
+        at: Option<i64>,
+        now: i64,
+    ) -> Result<bool, ()> {
+        self.remove_if_expired(&key, now);
+        self.set_fresh(key, value, at)
+    }
+    /// Set the value (and the expiry time, if one is provided) of a non-existent key
+    fn set_fresh(&self, key: Data, value: Data, at: Option<i64>) -> Result<bool, ()> {
+        let delta = key.len() + value.len();
+        let (key, value) = (self._encode_key(key)?, self._encode_value(value)?);
+        let (key, value) = (detach(key), detach(value));
+        let mutation = Mutation::Set(key.clone(), value.clone());
+        let size = PairSize::new(key.len(), value.len());
+        let did = self.insert_indexed(key, |key| match self.table.fresh_entry(key) {
+            Some(entry) => {
+                // accounted for while nobody else can see the key, and only if it's stored
+                self.account_stored(Some(size), None);
+                let entry = entry.insert(value);
+                match at {
+                    Some(at) => self.expiry.upsert(entry.key().clone(), at),
+                    // an expiry time that outlived its key isn't handed down to the new one
+                    None => {
+                        self.expiry.remove(entry.key());
+                    }
+                }
+                true
+            }
+            None => false,
+        });
+        if did {
+            self.mark_dirty(delta);
+            self.record_change(mutation);
+        }
+        self.counters.wrote(if did { delta } else { 0 });
+        Ok(did)
+    }
+    /// Set the values of the non-existent keys in `pairs`, returning the status of every pair.
+    /// Pairs that don't match the encoding of the table are left out
+    pub fn set_bulk(&self, pairs: impl Iterator<Item = (Data, Data)>) -> Vec<PairStatus> {
+        pairs
+            .map(|(key, value)| {
+                match self.check_encoding(Some(key.as_ref()), Some(value.as_ref())) {
+                    PairStatus::Okay => match self.set(key, value) {
+                        Ok(true) => PairStatus::Okay,
+                        _ => PairStatus::Exists,
+                    },
+                    bad => bad,
+                }
+            })
+            .collect()
+    }
+    /// Returns the status that every pair in `pairs` would have in a bulk write, without
+    /// writing anything. For a [`BulkWrite::Set`], the second of two pairs with the same key
+    /// is reported as existing since the first one would create the key
+    pub fn check_bulk(&self, pairs: &[(Data, Data)], write: BulkWrite) -> Vec<PairStatus> {
+        let mut seen = HashSet::with_capacity(pairs.len());
+        pairs
+            .iter()
+            .map(|(key, value)| {
+                let status = self.check_encoding(Some(key.as_ref()), Some(value.as_ref()));
+                if status != PairStatus::Okay {
+                    return status;
+                }
+                match write {
+                    BulkWrite::Set if self.table.contains_key(key) || !seen.insert(key) => {
+                        PairStatus::Exists
+                    }
+                    BulkWrite::Update if !self.table.contains_key(key) => PairStatus::Missing,
+                    _ => PairStatus::Okay,
+                }
+            })
+            .collect()
+    }
+    /// Write either all of `pairs` or none of them, returning the number of pairs written. If
+    /// any of the pairs would fail, nothing is written and the status of every pair is
+    /// returned instead.
+    ///
+    /// The pairs are checked before anything is written. If a concurrent write gets in
+    /// between (so that one of the writes fails after all), the writes that were already made
+    /// are taken back and the pair that failed is the only one that is reported
+    pub fn write_bulk_strict(
+        &self,
+        pairs: Vec<(Data, Data)>,
+        write: BulkWrite,
+    ) -> Result<usize, Vec<PairStatus>> {
+        let statuses = self.check_bulk(&pairs, write);
+        if statuses.iter().any(|status| *status != PairStatus::Okay) {
+            return Err(statuses);
+        }
+        let count = pairs.len();
+        // the keys that were written, along with their old values (if they had any)
+        let mut written: Vec<(Data, Option<Data>)> = Vec::with_capacity(count);
+        for (i, (key, value)) in pairs.into_iter().enumerate() {
+            let did = match write {
+                BulkWrite::Set => match self.set(key.clone(), value) {
+                    Ok(true) => {
+                        written.push((key, None));
+                        true
+                    }
+                    _ => false,
+                },
+                BulkWrite::Update => {
+                    let delta = key.len() + value.len();
+                    match self.update_returning_old(key.clone(), value, None) {
+                        Ok(Some(old)) => {
+                            self.counters.wrote(delta);
+                            written.push((key, Some(old)));
+                            true
+                        }
+                        _ => false,
+                    }
+                }
+            };
+            if !did {
+                for (key, old) in written.into_iter().rev() {
+                    let _ = match old {
+                        Some(old) => self.update_returning_old(key, old, None).map(|_| ()),
+                        None => self._remove(key).map(|_| ()),
+                    };
+                }
+                let mut statuses = vec![PairStatus::Okay; count];
+                statuses[i] = match write {
+                    BulkWrite::Set => PairStatus::Exists,
+                    BulkWrite::Update => PairStatus::Missing,
+                };
+                return Err(statuses);
+            }
+        }
+        Ok(count)
     }
     /// Update the value of an existing key
     pub fn update(&self, key: Data, value: Data) -> Result<bool, ()> {
-        Ok(self
-            .table
-            .true_if_update(self._encode_key(key)?, self._encode_value(value)?))
+        let delta = key.len() + value.len();
+        let did = self.update_returning_old(key, value, None)?.is_some();
+        self.counters.wrote(if did { delta } else { 0 });
+        Ok(did)
+    }
+    /// Same as [`KVEngine::update`], but also replace the expiry time of the key (in
+    /// milliseconds since the unix epoch), if one is provided. Without one, the key keeps the
+    /// expiry time that it had. Just like with [`KVEngine::set_with_expiry`], the expiry time
+    /// is recorded before the entry is released, and a key whose expiry time is at or before
+    /// `now` counts as missing, so it isn't updated
+    pub fn update_with_expiry(
+        &self,
+        key: Data,
+        value: Data,
+        at: Option<i64>,
+        now: i64,
+    ) -> Result<bool, ()> {
+        self.remove_if_expired(&key, now);
+        let delta = key.len() + value.len();
+        let did = self.update_returning_old(key, value, at)?.is_some();
+        self.counters.wrote(if did { delta } else { 0 });
+        Ok(did)
+    }
+    /// Update the value (and the expiry time, if one is provided) of an existing key,
+    /// returning the value that it replaced
+    fn update_returning_old(
+        &self,
+        key: Data,
+        value: Data,
+        at: Option<i64>,
+    ) -> Result<Option<Data>, ()> {
+        let delta = key.len() + value.len();
+        let (key, value) = (self._encode_key(key)?, self._encode_value(value)?);
+        let (key, value) = (detach(key), detach(value));
+        let mutation = Mutation::Update(key.clone(), value.clone());
+        let old = self.table.mut_entry(key).map(|mut entry| {
+            let (key_len, added) = (entry.key().len(), value.len());
+            let old = entry.insert(value);
+            if let Some(at) = at {
+                self.expiry.upsert(entry.key().clone(), at);
+            }
+            // the key stays, so only the value changes hands
+            self.account_stored(
+                Some(PairSize::new(key_len, added)),
+                Some(PairSize::new(key_len, old.len())),
+            );
+            old
+        });
+        if old.is_some() {
+            self.mark_dirty(delta);
+            self.record_change(mutation);
+        }
+        Ok(old)
     }
     /// Update or insert the value of a key
     pub fn upsert(&self, key: Data, value: Data) -> Result<(), ()> {
-        self.table
-            .upsert(self._encode_key(key)?, self._encode_value(value)?);
+        let delta = key.len() + value.len();
+        let (key, value) = (self._encode_key(key)?, self._encode_value(value)?);
+        let (key, value) = (detach(key), detach(value));
+        let mutation = Mutation::Upsert(key.clone(), value.clone());
+        let key_len = key.len();
+        self.account_stored(Some(PairSize::new(key_len, value.len())), None);
+        let mut replaced = None;
+        self.insert_indexed(key, |key| {
+            replaced = self.table.replace(key, value);
+            true
+        });
+        if let Some(old) = replaced {
+            // the existing key is kept, and we counted the new one too
+            self.account_stored(None, Some(PairSize::new(key_len, old.len())));
+        }
+        self.mark_dirty(delta);
+        self.record_change(mutation);
+        self.counters.wrote(delta);
         Ok(())
     }
-    /// Remove an existing key
+    /// Remove an existing key (unless it's protected from deletion)
     pub fn remove<Q>(&self, key: Q) -> Result<bool, ()>
     where
         Data: Borrow<Q>,
         Q: AsRef<[u8]> + Hash + Eq,
     {
-        Ok(self.table.true_if_removed(&self._encode_key(key)?))
+        let did = self._remove(key)?;
+        self.counters.deleted();
+        Ok(did)
     }
+    /// Same as [`KVEngine::remove`], except that the removal isn't counted as a delete made by
+    /// a client
+    fn _remove<Q>(&self, key: Q) -> Result<bool, ()>
+    where
+        Data: Borrow<Q>,
+        Q: AsRef<[u8]> + Hash + Eq,
+    {
+        self.remove_as(key, Mutation::Remove)
+    }
+    /// Same as [`KVEngine::_remove`], except that the removal is logged as `mutation`
+    fn remove_as<Q>(&self, key: Q, mutation: fn(Data) -> Mutation) -> Result<bool, ()>
+    where
+        Data: Borrow<Q>,
+        Q: AsRef<[u8]> + Hash + Eq,
+    {
+        let delta = key.as_ref().len();
+        let key = self._encode_key(key)?;
+        let removed = self.remove_unprotected(&key);
+        let did = removed.is_some();
+        if let Some((key, value)) = removed {
+            self.account_stored(None, Some(PairSize::new(key.len(), value.len())));
+            self.expiry.remove(&key);
+            self.mark_dirty(delta);
+            self.record_change(mutation(key));
+        }
+        Ok(did)
+    }
+    /// Remove an existing key (unless it's protected from deletion), returning the removed pair
     pub fn pop<Q>(&self, key: Q) -> Result<Option<(Data, Data)>, ()>
     where
         Data: Borrow<Q>,
         Q: AsRef<[u8]> + Hash + Eq,
     {
-        Ok(self.table.remove(&self._encode_key(key)?))
+        let delta = key.as_ref().len();
+        let key = self._encode_key(key)?;
+        let popped = self.remove_unprotected(&key);
+        if let Some((key, value)) = &popped {
+            self.account_stored(None, Some(PairSize::new(key.len(), value.len())));
+            self.expiry.remove(key);
+            self.mark_dirty(delta);
+            self.record_change(Mutation::Remove(key.clone()));
+        }
+        self.counters.deleted();
+        Ok(popped)
+    }
+    /// Replace the value of `key` with `value`, but only if the key holds `expected`. The
+    /// comparison and the write are made under the lock of the key's entry, so no other write
+    /// to the key can get in between
+    pub fn update_if_eq(&self, key: Data, expected: &[u8], value: Data) -> Result<IfEq, ()> {
+        let delta = key.len() + value.len();
+        let (key, value) = (self._encode_key(key)?, self._encode_value(value)?);
+        let (key, value) = (detach(key), detach(value));
+        let mutation = Mutation::Update(key.clone(), value.clone());
+        let matched = match self.table.mut_entry(key) {
+            Some(mut entry) if **entry.get() == *expected => {
+                let (key_len, added) = (entry.key().len(), value.len());
+                let old = entry.insert(value);
+                self.account_stored(
+                    Some(PairSize::new(key_len, added)),
+                    Some(PairSize::new(key_len, old.len())),
+                );
+                true
+            }
+            _ => false,
+        };
+        if !matched {
+            self.counters.wrote(0);
+            return Ok(IfEq::NotMatched);
+        }
+        self.mark_dirty(delta);
+        self.record_change(mutation);
+        self.counters.wrote(delta);
+        Ok(IfEq::Written)
+    }
+    /// Remove `key` (unless it's protected from deletion), but only if it holds `expected`.
+    /// Just like with [`KVEngine::update_if_eq`], this is a single operation on the key's entry
+    pub fn remove_if_eq(&self, key: Data, expected: &[u8]) -> Result<IfEq, ()> {
+        let delta = key.len();
+        let key = self._encode_key(key)?;
+        let mut protected = false;
+        let removed = self.remove_indexed(|| {
+            self.table.remove_if(&key, |key, value| {
+                if **value != *expected {
+                    return false;
+                }
+                protected = self.protected.contains_key(key);
+                !protected
+            })
+        });
+        self.counters.deleted();
+        match removed {
+            Some((key, value)) => {
+                self.account_stored(None, Some(PairSize::new(key.len(), value.len())));
+                self.expiry.remove(&key);
+                self.mark_dirty(delta);
+                self.record_change(Mutation::Remove(key));
+                Ok(IfEq::Written)
+            }
+            None if protected => Ok(IfEq::Protected),
+            None => Ok(IfEq::NotMatched),
+        }
+    }
+}
+
+impl Drop for KVEngine {
+    fn drop(&mut self) {
+        // whatever wasn't flushed will never be, so don't leave it behind in the global count
+        registry::get_dirty_tracker().sub(*self.dirty.get_mut());
     }
 }
 
@@ -359,6 +1445,36 @@ fn test_bad_unicode_key() {
     assert!(tbl.set(Data::from(bad_unicode), Data::from("123")).is_err());
 }
 
+#[test]
+fn test_get_cloned() {
+    let bad_unicode = b"Hello \xF0\x90\x80World".to_vec();
+    let tbl = KVEngine::init(true, false);
+    tbl.set(Data::from("hello"), Data::from("world")).unwrap();
+    assert_eq!(
+        tbl.get_cloned(Data::from("hello"), 0),
+        Ok(Some(Bytes::from("world")))
+    );
+    assert_eq!(tbl.get_cloned(Data::from("bye"), 0), Ok(None));
+    assert_eq!(tbl.get_cloned(Data::from(bad_unicode), 0), Err(()));
+}
+
+#[test]
+fn test_ordered_keys() {
+    let tbl = KVEngine::default();
+    for key in &["d", "b", "e", "a", "c"] {
+        tbl.set(Data::from(*key), Data::from("1")).unwrap();
+    }
+    let expected: Vec<Data> = ["a", "b", "c"].iter().map(|key| Data::from(*key)).collect();
+    assert_eq!(tbl.ordered_keys(3, 5), Ok(expected.clone()));
+    // the same data always comes back in the same order
+    assert_eq!(tbl.ordered_keys(3, 5), tbl.ordered_keys(3, 5));
+    // one key over the cap is refused with the number of keys, however few are asked for
+    assert_eq!(tbl.ordered_keys(1, 4), Err(5));
+    // the index doesn't need sorting, so it's never refused
+    let tbl = tbl.with_ordered_index();
+    assert_eq!(tbl.ordered_keys(3, 0), Ok(expected));
+}
+
 #[test]
 fn test_bad_unicode_value() {
     let bad_unicode = b"Hello \xF0\x90\x80World".to_vec();
@@ -418,3 +1534,691 @@ fn test_encoder_validate_with_non_unicode() {
     let encoder = tbl.get_encoder();
     assert!(!encoder.is_ok("hello".as_bytes(), b"Hello \xF0\x90\x80World"));
 }
+
+#[test]
+fn test_dirty_bytes() {
+    let tbl = KVEngine::default();
+    assert!(tbl.set(Data::from("k"), Data::from("vv")).unwrap());
+    assert_eq!(tbl.dirty_bytes(), 3);
+    // a failed write doesn't dirty anything
+    assert!(!tbl.set(Data::from("k"), Data::from("vv")).unwrap());
+    assert_eq!(tbl.dirty_bytes(), 3);
+    assert!(tbl.remove(bytes::Bytes::from("k")).unwrap());
+    assert_eq!(tbl.dirty_bytes(), 4);
+    tbl.clear_dirty(4);
+    assert_eq!(tbl.dirty_bytes(), 0);
+    // and so does a truncation, by the bytes that it dropped
+    assert!(tbl.set(Data::from("k"), Data::from("vv")).unwrap());
+    assert_eq!(tbl.truncate_table(), 1);
+    assert_eq!(tbl.dirty_bytes(), 6);
+}
+
+#[test]
+fn test_stored_bytes() {
+    let tbl = KVEngine::default();
+    assert!(tbl.set(Data::from("k"), Data::from("vv")).unwrap());
+    assert_eq!(tbl.stored_bytes(), 3);
+    // a failed write doesn't store anything
+    assert!(!tbl.set(Data::from("k"), Data::from("vvvv")).unwrap());
+    assert_eq!(tbl.stored_bytes(), 3);
+    assert!(tbl.update(Data::from("k"), Data::from("v")).unwrap());
+    assert_eq!(tbl.stored_bytes(), 2);
+    assert!(!tbl.update(Data::from("nope"), Data::from("v")).unwrap());
+    assert_eq!(tbl.stored_bytes(), 2);
+    tbl.upsert(Data::from("k"), Data::from("vvv")).unwrap();
+    tbl.upsert(Data::from("k2"), Data::from("v")).unwrap();
+    assert_eq!(tbl.stored_bytes(), 7);
+    assert!(tbl.remove(bytes::Bytes::from("k")).unwrap());
+    assert_eq!(tbl.stored_bytes(), 3);
+    tbl.set(Data::from("k3"), Data::from("vv")).unwrap();
+    assert!(tbl.protect(Data::from("k3")).unwrap());
+    assert_eq!(tbl.truncate_unprotected(), 1);
+    assert_eq!(tbl.stored_bytes(), 4);
+    tbl.truncate_table();
+    assert_eq!(tbl.stored_bytes(), 0);
+    // loaded tables start with whatever they hold
+    let loaded = Coremap::new();
+    loaded.upsert(Data::from("key"), Data::from("value"));
+    assert_eq!(
+        KVEngine::init_with_data(false, false, loaded).stored_bytes(),
+        8
+    );
+}
+
+#[test]
+fn test_size_histograms_follow_the_table() {
+    let tbl = KVEngine::default();
+    let sizes = |tbl: &KVEngine| {
+        let (keys, values) = (tbl.key_sizes().values(), tbl.value_sizes().values());
+        (keys.compact(), values.compact(), keys.max, values.max)
+    };
+    for (key, value) in [
+        ("a", "v"),
+        ("bb", "vvvv"),
+        ("ccc", ""),
+        ("dddd", "vvvvvvvvvv"),
+    ]
+    .iter()
+    {
+        assert!(tbl.set(Data::from(*key), Data::from(*value)).unwrap());
+    }
+    assert_eq!(
+        sizes(&tbl),
+        (
+            "1:1 3:2 7:1".to_owned(),
+            "0:1 1:1 7:1 15:1".to_owned(),
+            4,
+            10
+        )
+    );
+    // a write that doesn't go through counts for nothing, not even as the largest
+    assert!(!tbl
+        .set(Data::from("a"), Data::from(vec![0u8; 100]))
+        .unwrap());
+    assert!(!tbl
+        .update(Data::from("nope"), Data::from(vec![0u8; 100]))
+        .unwrap());
+    assert_eq!(tbl.value_sizes().values().max, 10);
+    // a replaced value moves to its new bucket and the key stays where it was
+    assert!(tbl.update(Data::from("bb"), Data::from("v")).unwrap());
+    tbl.upsert(Data::from("ccc"), Data::from("vv")).unwrap();
+    assert_eq!(
+        sizes(&tbl),
+        ("1:1 3:2 7:1".to_owned(), "1:2 3:1 15:1".to_owned(), 4, 10)
+    );
+    // a delete takes the pair out, but the largest sizes stay
+    assert!(tbl.remove(bytes::Bytes::from("dddd")).unwrap());
+    assert_eq!(
+        sizes(&tbl),
+        ("1:1 3:2".to_owned(), "1:2 3:1".to_owned(), 4, 10)
+    );
+    assert!(tbl.protect(Data::from("a")).unwrap());
+    assert_eq!(tbl.clear(), 2);
+    assert_eq!(sizes(&tbl), ("1:1".to_owned(), "1:1".to_owned(), 4, 10));
+    tbl.truncate_table();
+    assert_eq!(sizes(&tbl), ("none".to_owned(), "none".to_owned(), 4, 10));
+    assert_eq!(tbl.key_sizes().values().sum, 0);
+}
+
+#[test]
+fn test_ordered_index_tracks_mutations() {
+    let tbl = KVEngine::default().with_ordered_index();
+    for key in ["b", "a", "c"].iter() {
+        assert!(tbl.set(Data::from(*key), Data::from("v")).unwrap());
+    }
+    tbl.upsert(Data::from("d"), Data::from("v")).unwrap();
+    assert!(tbl.remove(bytes::Bytes::from("b")).unwrap());
+    assert!(tbl.pop(bytes::Bytes::from("c")).unwrap().is_some());
+    let expected: Vec<Data> = vec![Data::from("a"), Data::from("d")];
+    assert_eq!(tbl.range_keys(b"a", b"z", usize::MAX).unwrap(), expected);
+    tbl.truncate_table();
+    assert!(tbl.range_keys(b"a", b"z", usize::MAX).unwrap().is_empty());
+    // no index, no range queries
+    assert!(KVEngine::default().range_keys(b"a", b"z", 10).is_none());
+}
+
+#[test]
+fn test_ordered_index_under_concurrent_writers() {
+    use std::sync::Arc;
+    use std::thread;
+    let tbl = Arc::new(KVEngine::default().with_ordered_index());
+    // every writer sets and removes the same keys so that they race with each other
+    let writers: Vec<_> = (0..4)
+        .map(|i| {
+            let tbl = tbl.clone();
+            thread::spawn(move || {
+                for round in 0..500 {
+                    let key = Data::from(format!("key{:03}", round % 50));
+                    if (round + i) % 3 == 0 {
+                        let _ = tbl.remove(key.get_blob().clone()).unwrap();
+                    } else {
+                        let _ = tbl.set(key, Data::from("v")).unwrap();
+                    }
+                }
+            })
+        })
+        .collect();
+    writers.into_iter().for_each(|w| w.join().unwrap());
+    let mut keys: Vec<Data> = tbl
+        .__get_inner_ref()
+        .iter()
+        .map(|kv| kv.key().clone())
+        .collect();
+    keys.sort();
+    assert_eq!(tbl.range_keys(b"", b"\xFF", usize::MAX).unwrap(), keys);
+}
+
+#[test]
+fn test_protected_keys_survive_deletes() {
+    let tbl = KVEngine::default().with_ordered_index();
+    tbl.set(Data::from("a"), Data::from("1")).unwrap();
+    tbl.set(Data::from("b"), Data::from("2")).unwrap();
+    assert!(tbl.protect(Data::from("a")).unwrap());
+    // can't protect what doesn't exist
+    assert!(!tbl.protect(Data::from("c")).unwrap());
+    assert!(!tbl.remove(bytes::Bytes::from("a")).unwrap());
+    assert!(tbl.pop(bytes::Bytes::from("a")).unwrap().is_none());
+    // updates still go through
+    assert!(tbl.update(Data::from("a"), Data::from("11")).unwrap());
+    assert_eq!(tbl.truncate_unprotected(), 1);
+    assert_eq!(
+        tbl.range_keys(b"a", b"z", usize::MAX).unwrap(),
+        vec![Data::from("a")]
+    );
+    assert!(tbl.unprotect(Data::from("a")).unwrap());
+    assert!(!tbl.unprotect(Data::from("a")).unwrap());
+    assert!(tbl.remove(bytes::Bytes::from("a")).unwrap());
+    // truncating the table gets rid of the flags too
+    tbl.set(Data::from("a"), Data::from("1")).unwrap();
+    tbl.protect(Data::from("a")).unwrap();
+    tbl.truncate_table();
+    assert!(!tbl.is_protected(b"a"));
+    assert_eq!(tbl.len(), 0);
+}
+
+#[test]
+fn test_set_bulk_statuses() {
+    let tbl = KVEngine::init(true, false);
+    tbl.set(Data::from("a"), Data::from("1")).unwrap();
+    let statuses = tbl.set_bulk(
+        vec![
+            (Data::from("a"), Data::from("2")),
+            (Data::from(&b"\xF0\x90\x80"[..]), Data::from("2")),
+            (Data::from("b"), Data::from(&b"\xF0\x90\x80"[..])),
+            (Data::from("c"), Data::from("3")),
+        ]
+        .into_iter(),
+    );
+    assert_eq!(
+        statuses,
+        vec![
+            PairStatus::Exists,
+            PairStatus::BadKeyEncoding,
+            PairStatus::Okay,
+            PairStatus::Okay
+        ]
+    );
+    assert_eq!(tbl.len(), 3);
+}
+
+#[test]
+fn test_write_bulk_strict() {
+    let tbl = KVEngine::init(true, true);
+    tbl.set(Data::from("a"), Data::from("1")).unwrap();
+    let (len, stored) = (tbl.len(), tbl.stored_bytes());
+    // nothing is written if a single pair fails
+    let pairs = vec![
+        (Data::from("b"), Data::from("2")),
+        (Data::from("a"), Data::from("2")),
+        (Data::from("c"), Data::from(&b"\xF0\x90\x80"[..])),
+        (Data::from("b"), Data::from("3")),
+    ];
+    assert_eq!(
+        tbl.write_bulk_strict(pairs, BulkWrite::Set).unwrap_err(),
+        vec![
+            PairStatus::Okay,
+            PairStatus::Exists,
+            PairStatus::BadValueEncoding,
+            // b is set by the first pair
+            PairStatus::Exists
+        ]
+    );
+    assert_eq!((tbl.len(), tbl.stored_bytes()), (len, stored));
+    assert!(tbl.get(Data::from("b")).unwrap().is_none());
+    let pairs = vec![
+        (Data::from("a"), Data::from("11")),
+        (Data::from("b"), Data::from("22")),
+    ];
+    assert_eq!(
+        tbl.write_bulk_strict(pairs, BulkWrite::Update).unwrap_err(),
+        vec![PairStatus::Okay, PairStatus::Missing]
+    );
+    assert_eq!(*tbl.get(Data::from("a")).unwrap().unwrap(), Data::from("1"));
+    // and everything is written otherwise
+    let pairs = vec![
+        (Data::from("b"), Data::from("2")),
+        (Data::from("c"), Data::from("3")),
+    ];
+    assert_eq!(tbl.write_bulk_strict(pairs, BulkWrite::Set), Ok(2));
+    let pairs = vec![
+        (Data::from("a"), Data::from("11")),
+        (Data::from("b"), Data::from("22")),
+    ];
+    assert_eq!(tbl.write_bulk_strict(pairs, BulkWrite::Update), Ok(2));
+    assert_eq!(
+        *tbl.get(Data::from("b")).unwrap().unwrap(),
+        Data::from("22")
+    );
+    assert_eq!(tbl.len(), 3);
+}
+
+#[test]
+fn test_stored_pairs_dont_share_the_query() {
+    // the arguments of a query are slices of the connection's read buffer
+    let query = bytes::Bytes::from("k1v1k2v2v3");
+    let range = query.as_ptr_range();
+    let tbl = KVEngine::default();
+    assert!(tbl
+        .set(Data::from(query.slice(0..2)), Data::from(query.slice(2..4)))
+        .unwrap());
+    tbl.upsert(Data::from(query.slice(4..6)), Data::from(query.slice(6..8)))
+        .unwrap();
+    assert!(tbl
+        .update(
+            Data::from(query.slice(0..2)),
+            Data::from(query.slice(8..10))
+        )
+        .unwrap());
+    assert!(tbl.protect(Data::from(query.slice(4..6))).unwrap());
+    assert_eq!(tbl.len(), 2);
+    for pair in tbl.__get_inner_ref().iter() {
+        assert!(!range.contains(&pair.key().as_ptr()));
+        assert!(!range.contains(&pair.value().as_ptr()));
+    }
+    for key in tbl.get_protected().iter() {
+        assert!(!range.contains(&key.key().as_ptr()));
+    }
+}
+
+#[test]
+fn test_concurrent_pops_only_remove_once() {
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+    for _ in 0..100 {
+        let tbl = Arc::new(KVEngine::default());
+        tbl.set(Data::from("token"), Data::from("secret")).unwrap();
+        let barrier = Arc::new(Barrier::new(2));
+        let poppers: Vec<_> = (0..2)
+            .map(|_| {
+                let (tbl, barrier) = (tbl.clone(), barrier.clone());
+                thread::spawn(move || {
+                    barrier.wait();
+                    tbl.pop(bytes::Bytes::from("token")).unwrap()
+                })
+            })
+            .collect();
+        let popped: Vec<_> = poppers
+            .into_iter()
+            .filter_map(|p| p.join().unwrap())
+            .collect();
+        assert_eq!(popped.len(), 1);
+        assert_eq!(popped[0].1, Data::from("secret"));
+        assert!(tbl.get(Data::from("token")).unwrap().is_none());
+    }
+}
+
+#[test]
+fn test_set_with_expiry_race() {
+    use std::sync::Arc;
+    use std::thread;
+    let tbl = Arc::new(KVEngine::default());
+    let writers: Vec<_> = (0..8i64)
+        .map(|i| {
+            let tbl = tbl.clone();
+            thread::spawn(move || {
+                tbl.set_with_expiry(
+                    Data::from("race"),
+                    Data::from(i.to_string()),
+                    Some(1000 + i),
+                    0,
+                )
+                .unwrap()
+            })
+        })
+        .collect();
+    let won: Vec<i64> = writers
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, writer)| {
+            if writer.join().unwrap() {
+                Some(i as i64)
+            } else {
+                None
+            }
+        })
+        .collect();
+    // only one of them gets to create the key, and the key has that one's expiry time
+    assert_eq!(won.len(), 1);
+    let value = tbl.get_cloned(Data::from("race"), 0).unwrap().unwrap();
+    assert_eq!(value, won[0].to_string().as_bytes());
+    assert_eq!(tbl.get_expiry(b"race"), Some(1000 + won[0]));
+}
+
+#[test]
+fn test_update_with_expiry_race() {
+    use std::sync::Arc;
+    use std::thread;
+    let tbl = Arc::new(KVEngine::default());
+    assert!(tbl.set(Data::from("race"), Data::from("start")).unwrap());
+    let writers: Vec<_> = (0..8i64)
+        .map(|i| {
+            let tbl = tbl.clone();
+            thread::spawn(move || {
+                tbl.update_with_expiry(
+                    Data::from("race"),
+                    Data::from(i.to_string()),
+                    Some(1000 + i),
+                    0,
+                )
+                .unwrap()
+            })
+        })
+        .collect();
+    assert!(writers.into_iter().all(|writer| writer.join().unwrap()));
+    // whoever wrote last also set the expiry time
+    let value = tbl.get_cloned(Data::from("race"), 0).unwrap().unwrap();
+    let last: i64 = String::from_utf8_lossy(&value).parse().unwrap();
+    assert_eq!(tbl.get_expiry(b"race"), Some(1000 + last));
+    // and an update without an expiry time keeps the old one
+    assert!(tbl.update(Data::from("race"), Data::from("end")).unwrap());
+    assert_eq!(tbl.get_expiry(b"race"), Some(1000 + last));
+    assert!(!tbl
+        .update_with_expiry(Data::from("missing"), Data::from("1"), Some(1), 0)
+        .unwrap());
+    assert_eq!(tbl.get_expiry(b"missing"), None);
+}
+
+#[test]
+#[cfg(not(target_env = "msvc"))]
+fn test_reads_without_hooks() {
+    use crate::util::alloc;
+    let tbl = KVEngine::init(true, false).with_entity(Data::from("ks:tbl"));
+    let (key, missing) = (Data::from("hello"), Data::from("missing"));
+    tbl.set(key.clone(), Data::from("world")).unwrap();
+    // the first clone of the value shares it, which allocates
+    assert_eq!(
+        tbl.get_cloned(key.clone(), 0),
+        Ok(Some(Bytes::from("world")))
+    );
+    assert_eq!(tbl.get_cloned(missing.clone(), 0), Ok(None));
+    let (allocations, hooked) = (alloc::allocations(), hooks::hooked_reads());
+    for _ in 0..1000 {
+        assert!(tbl.get_cloned(key.clone(), 0).unwrap().is_some());
+        assert!(tbl.get_cloned(missing.clone(), 0).unwrap().is_none());
+    }
+    // the change log only sees the writes, so the reads just look the key up and clone
+    assert_eq!(alloc::allocations(), allocations);
+    assert_eq!(hooks::hooked_reads(), hooked);
+    assert_eq!(tbl.counters().values().hits, 1001);
+    // while a table with a read cache has to go through it
+    let cached = KVEngine::init(true, false).with_read_cache();
+    cached.set(key.clone(), Data::from("world")).unwrap();
+    cached.get_frame(key.clone(), 0).unwrap();
+    assert_eq!(hooks::hooked_reads(), hooked + 1);
+    assert!(cached.hooks().on_reads() && !tbl.hooks().on_reads());
+}
+
+/// Compare the reads of a table without hooks with those of a table that has all of them
+#[test]
+#[ignore]
+fn bench_reads_with_hooks() {
+    use std::time::Instant;
+    const KEYS: usize = 100_000;
+    let keys: Vec<Data> = (0..KEYS).map(|i| Data::from(i.to_string())).collect();
+    let time = |tbl: &KVEngine| {
+        for key in keys.iter() {
+            tbl.set(key.clone(), key.clone()).unwrap();
+        }
+        // warm it up (and fill the cache) first
+        keys.iter()
+            .for_each(|key| drop(tbl.get_frame(key.clone(), 0)));
+        let start = Instant::now();
+        for _ in 0..10 {
+            for key in keys.iter() {
+                assert!(tbl.get_frame(key.clone(), 0).unwrap().is_some());
+            }
+        }
+        start.elapsed().as_nanos() as f64 / (KEYS * 10) as f64
+    };
+    let plain = time(&KVEngine::default());
+    let hooked = time(
+        &KVEngine::default()
+            .with_entity(Data::from("ks:tbl"))
+            .with_read_cache(),
+    );
+    println!(
+        "without hooks {:.1} ns/get, with all of them {:.1} ns/get",
+        plain, hooked
+    );
+}
+
+#[test]
+fn test_unreadable_values() {
+    let rotten = || Data::from(b"Hello \xF0\x90\x80World".to_vec());
+    let tbl = KVEngine::init(true, true);
+    tbl.set(Data::from("good"), Data::from("value")).unwrap();
+    tbl.set(Data::from("bad"), Data::from("value")).unwrap();
+    tbl.corrupt_value(Data::from("bad"), rotten());
+    let counted = registry::get_unreadable_values();
+    assert_eq!(
+        tbl.read(Data::from("good"), 0),
+        Ok(Some(Bytes::from("value")))
+    );
+    assert_eq!(tbl.read(Data::from("bad"), 0), Err(ReadError::Unreadable));
+    assert_eq!(tbl.read(Data::from("absent"), 0), Ok(None));
+    assert_eq!(tbl.read(rotten(), 0), Err(ReadError::BadKey));
+    assert!(registry::get_unreadable_values() > counted);
+    assert!(tbl.is_unreadable(b"bad"));
+    assert!(!tbl.is_unreadable(b"good") && !tbl.is_unreadable(b"absent"));
+    // the stored bytes are still there
+    assert_eq!(
+        tbl.get_cloned(Data::from("bad"), 0),
+        Ok(Some(rotten().into_inner()))
+    );
+    // the values of a binary table are never decoded
+    let binary = KVEngine::init(true, false);
+    binary.set(Data::from("bad"), rotten()).unwrap();
+    assert_eq!(
+        binary.read(Data::from("bad"), 0),
+        Ok(Some(rotten().into_inner()))
+    );
+    assert!(!binary.is_unreadable(b"bad"));
+    // and the read cache never keeps the frame of a value that doesn't decode
+    let cached = KVEngine::init(true, true).with_read_cache();
+    cached.set(Data::from("bad"), Data::from("value")).unwrap();
+    cached.corrupt_value(Data::from("bad"), rotten());
+    for _ in 0..2 {
+        assert_eq!(
+            cached.get_frame(Data::from("bad"), 0),
+            Err(ReadError::Unreadable)
+        );
+    }
+    assert_eq!(cached.read_cache().unwrap().hits(), 0);
+}
+
+#[test]
+fn test_read_cache_engages() {
+    let tbl = KVEngine::init(true, false).with_read_cache();
+    tbl.set(Data::from("hello"), Data::from("world")).unwrap();
+    let frame = Bytes::from("+5\nworld\n");
+    assert_eq!(
+        tbl.get_frame(Data::from("hello"), 0),
+        Ok(Some(frame.clone()))
+    );
+    assert_eq!(tbl.get_frame(Data::from("hello"), 0), Ok(Some(frame)));
+    let cache = tbl.read_cache().unwrap();
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+    // the hits are counted as reads of the table all the same
+    assert_eq!(tbl.counters().values().hits, 2);
+    let bad_unicode = b"Hello \xF0\x90\x80World".to_vec();
+    assert_eq!(
+        tbl.get_frame(Data::from(bad_unicode), 0),
+        Err(ReadError::BadKey)
+    );
+    // every kind of mutation drops the frame
+    tbl.update(Data::from("hello"), Data::from("there"))
+        .unwrap();
+    assert_eq!(
+        tbl.get_frame(Data::from("hello"), 0),
+        Ok(Some(Bytes::from("+5\nthere\n")))
+    );
+    tbl.upsert(Data::from("hello"), Data::from("again"))
+        .unwrap();
+    assert_eq!(
+        tbl.get_frame(Data::from("hello"), 0),
+        Ok(Some(Bytes::from("+5\nagain\n")))
+    );
+    tbl.truncate_table();
+    assert_eq!(tbl.get_frame(Data::from("hello"), 0), Ok(None));
+    tbl.set(Data::from("hello"), Data::from("world")).unwrap();
+    tbl.get_frame(Data::from("hello"), 0).unwrap();
+    tbl.remove(Data::from("hello")).unwrap();
+    assert_eq!(tbl.get_frame(Data::from("hello"), 0), Ok(None));
+}
+
+#[test]
+fn test_read_cache_is_never_stale() {
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+    const WRITES: u64 = 2000;
+    let tbl = Arc::new(KVEngine::default().with_read_cache());
+    tbl.set(Data::from("counter"), Data::from("0")).unwrap();
+    let done = Arc::new(AtomicBool::new(false));
+    let value_of = |frame: Bytes| -> u64 {
+        let frame = String::from_utf8(frame.to_vec()).unwrap();
+        frame.lines().nth(1).unwrap().parse().unwrap()
+    };
+    let readers: Vec<_> = (0..4)
+        .map(|_| {
+            let (tbl, done) = (tbl.clone(), done.clone());
+            thread::spawn(move || {
+                let mut last = 0;
+                while !done.load(Ordering::Acquire) {
+                    let frame = tbl.get_frame(Data::from("counter"), 0).unwrap().unwrap();
+                    let value = value_of(frame);
+                    // the counter only goes up
+                    assert!(value >= last, "read {} after {}", value, last);
+                    last = value;
+                }
+            })
+        })
+        .collect();
+    for n in 1..=WRITES {
+        tbl.update(Data::from("counter"), Data::from(n.to_string()))
+            .unwrap();
+        // whatever the readers put in the cache, the write was seen
+        let frame = tbl.get_frame(Data::from("counter"), 0).unwrap().unwrap();
+        assert_eq!(value_of(frame), n);
+    }
+    done.store(true, Ordering::Release);
+    readers
+        .into_iter()
+        .for_each(|reader| reader.join().unwrap());
+    let frame = tbl.get_frame(Data::from("counter"), 0).unwrap().unwrap();
+    assert_eq!(value_of(frame), WRITES);
+    assert!(tbl.read_cache().unwrap().hits() > 0);
+}
+
+#[test]
+fn test_update_and_remove_if_eq() {
+    let tbl = KVEngine::default();
+    tbl.set(Data::from("x"), Data::from("100")).unwrap();
+    assert_eq!(
+        tbl.update_if_eq(Data::from("x"), b"200", Data::from("300")),
+        Ok(IfEq::NotMatched)
+    );
+    assert_eq!(
+        tbl.update_if_eq(Data::from("y"), b"100", Data::from("300")),
+        Ok(IfEq::NotMatched)
+    );
+    assert_eq!(
+        tbl.update_if_eq(Data::from("x"), b"100", Data::from("300")),
+        Ok(IfEq::Written)
+    );
+    assert_eq!(
+        tbl.get_cloned(Data::from("x"), 0),
+        Ok(Some(Bytes::from("300")))
+    );
+    assert_eq!(
+        tbl.remove_if_eq(Data::from("x"), b"100"),
+        Ok(IfEq::NotMatched)
+    );
+    assert!(tbl.protect(Data::from("x")).unwrap());
+    assert_eq!(
+        tbl.remove_if_eq(Data::from("x"), b"300"),
+        Ok(IfEq::Protected)
+    );
+    assert!(tbl.unprotect(Data::from("x")).unwrap());
+    assert_eq!(tbl.remove_if_eq(Data::from("x"), b"300"), Ok(IfEq::Written));
+    assert_eq!(tbl.len(), 0);
+    assert_eq!(tbl.stored_bytes(), 0);
+}
+
+#[test]
+fn test_racing_update_if_eq_is_linearizable() {
+    use std::sync::Arc;
+    use std::thread;
+    const INCREMENTS: u64 = 500;
+    let tbl = Arc::new(KVEngine::default());
+    tbl.set(Data::from("counter"), Data::from("0")).unwrap();
+    // every thread increments the counter by reading it and writing it back if it hasn't
+    // changed in the meantime, so no increment is lost and every value is written once
+    let writers: Vec<_> = (0..4)
+        .map(|_| {
+            let tbl = tbl.clone();
+            thread::spawn(move || {
+                let mut written = Vec::new();
+                while written.len() < INCREMENTS as usize {
+                    let current = tbl.get_cloned(Data::from("counter"), 0).unwrap().unwrap();
+                    let next = String::from_utf8_lossy(&current).parse::<u64>().unwrap() + 1;
+                    let next = next.to_string();
+                    match tbl.update_if_eq(
+                        Data::from("counter"),
+                        &current,
+                        Data::from(next.clone()),
+                    ) {
+                        Ok(IfEq::Written) => written.push(next),
+                        Ok(IfEq::NotMatched) => {}
+                        ret => panic!("unexpected result: {:?}", ret),
+                    }
+                }
+                written
+            })
+        })
+        .collect();
+    let mut written: Vec<u64> = writers
+        .into_iter()
+        .flat_map(|writer| writer.join().unwrap())
+        .map(|value| value.parse().unwrap())
+        .collect();
+    written.sort_unstable();
+    assert_eq!(written, (1..=4 * INCREMENTS).collect::<Vec<_>>());
+    assert_eq!(
+        tbl.get_cloned(Data::from("counter"), 0),
+        Ok(Some(Bytes::from((4 * INCREMENTS).to_string())))
+    );
+}
+
+#[test]
+fn test_versions_of_watched_keys() {
+    let tbl = KVEngine::default();
+    let version_of = |version: &KeyVersion| version.load(Ordering::SeqCst);
+    tbl.set(Data::from("x"), Data::from("1")).unwrap();
+    let x = tbl.watch(Data::from("x"));
+    // a key that isn't there can be watched too
+    let y = tbl.watch(Data::from("y"));
+    let also_x = tbl.watch(Data::from("x"));
+    assert!(Arc::ptr_eq(&x, &also_x));
+    assert_eq!(tbl.watched_keys(), 2);
+    tbl.update(Data::from("x"), Data::from("2")).unwrap();
+    assert_eq!((version_of(&x), version_of(&y)), (1, 0));
+    // the flags don't change the value
+    tbl.protect(Data::from("x")).unwrap();
+    tbl.unprotect(Data::from("x")).unwrap();
+    assert_eq!(version_of(&x), 1);
+    tbl.set(Data::from("y"), Data::from("1")).unwrap();
+    tbl.remove(Data::from("y")).unwrap();
+    assert_eq!(version_of(&y), 2);
+    tbl.truncate_table();
+    assert_eq!((version_of(&x), version_of(&y)), (2, 3));
+    // the key is forgotten once the last one watching it stops
+    tbl.unwatch(&Data::from("x"), x);
+    assert_eq!(tbl.watched_keys(), 2);
+    tbl.unwatch(&Data::from("x"), also_x);
+    tbl.unwatch(&Data::from("y"), y);
+    assert_eq!(tbl.watched_keys(), 0);
+    // and isn't bumped any more
+    tbl.set(Data::from("x"), Data::from("1")).unwrap();
+    assert_eq!(version_of(&tbl.watch(Data::from("x"))), 0);
+}
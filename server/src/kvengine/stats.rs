@@ -0,0 +1,469 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Table counters
+//!
+//! Every table counts the point reads and writes that are made on it, so that the tables
+//! carrying the load can be told apart. The counters live with the table, so a table that is
+//! dropped and created again starts from zero. A read is counted with a single relaxed
+//! increment on a miss and two on a hit; the total number of reads is derived from the hits
+//! and the misses
+//!
+//! The counters also keep the [`Activity`] of the table: when it was last written to and read
+//! from, so that abandoned tables can be found. Reads are only tracked if `trackreads` is set
+//!
+//! Along with these, a table keeps an approximate [`SizeHistogram`] of the lengths of its keys
+//! and one of the lengths of its values. These follow what the table holds (unlike the
+//! counters, a delete takes a pair out again) and are rebuilt when the table is read in
+
+use crate::corestore::lock::QuickLock;
+use crate::registry;
+use core::fmt::Write;
+use core::sync::atomic::Ordering;
+use core::sync::atomic::{AtomicU64, AtomicU8};
+use std::time::{Duration, Instant};
+
+const ORD_RELAXED: Ordering = Ordering::Relaxed;
+
+/// The table was written to since its activity was last stamped
+const TOUCHED_WRITE: u8 = 0b01;
+/// The table was read from since its activity was last stamped
+const TOUCHED_READ: u8 = 0b10;
+
+/// When a table was last written to and read from, in seconds since the unix epoch (0 if
+/// never). Since the tables don't know the time, a read or a write only raises a flag and
+/// the times are stamped after the query with the time of the store's clock (see
+/// [`Activity::stamp`]). A time only ever moves forward, and at most once a second. Writes
+/// and deletes count even if they didn't go through, since the table is still in use
+#[derive(Debug)]
+pub struct Activity {
+    touched: AtomicU8,
+    last_write: AtomicU64,
+    last_read: AtomicU64,
+}
+
+impl Activity {
+    const fn new() -> Self {
+        Self {
+            touched: AtomicU8::new(0),
+            last_write: AtomicU64::new(0),
+            last_read: AtomicU64::new(0),
+        }
+    }
+    fn touch(&self, flag: u8) {
+        // the flag is mostly raised already, and then this is only a load
+        if self.touched.load(ORD_RELAXED) & flag == 0 {
+            self.touched.fetch_or(flag, ORD_RELAXED);
+        }
+    }
+    /// Returns true if the table was written to (or read from) since the last stamp
+    pub fn is_touched(&self) -> bool {
+        self.touched.load(ORD_RELAXED) != 0
+    }
+    /// Stamp the reads and writes made since the last stamp with `now` (in seconds since the
+    /// unix epoch)
+    pub fn stamp(&self, now: u64) {
+        let touched = self.touched.swap(0, ORD_RELAXED);
+        if touched & TOUCHED_WRITE != 0 {
+            Self::move_to(&self.last_write, now);
+        }
+        if touched & TOUCHED_READ != 0 {
+            Self::move_to(&self.last_read, now);
+        }
+    }
+    fn move_to(time: &AtomicU64, now: u64) {
+        if time.load(ORD_RELAXED) < now {
+            time.fetch_max(now, ORD_RELAXED);
+        }
+    }
+    /// Set the times to what was saved (in the `PARTMAP`) or to the creation of the table.
+    /// Neither moves backwards
+    pub fn restore(&self, last_write: u64, last_read: u64) {
+        Self::move_to(&self.last_write, last_write);
+        Self::move_to(&self.last_read, last_read);
+    }
+    /// Returns the time of the latest write, if there was one
+    pub fn last_write(&self) -> Option<u64> {
+        Some(self.last_write.load(ORD_RELAXED)).filter(|time| *time != 0)
+    }
+    /// Returns the time of the latest read, if there was one (that was tracked)
+    pub fn last_read(&self) -> Option<u64> {
+        Some(self.last_read.load(ORD_RELAXED)).filter(|time| *time != 0)
+    }
+    /// Returns the time of the latest write or read, if there was one
+    pub fn last_active(&self) -> Option<u64> {
+        self.last_write().max(self.last_read())
+    }
+}
+
+/// The read and write counters of a table
+#[derive(Debug)]
+pub struct TableCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    bytes_read: AtomicU64,
+    sets: AtomicU64,
+    dels: AtomicU64,
+    bytes_written: AtomicU64,
+    /// the time at which the counters started counting (the creation of the table or the
+    /// latest reset)
+    since: QuickLock<Instant>,
+    /// when the table was last written to and read from, which a reset leaves alone
+    activity: Activity,
+}
+
+/// The values of a table's counters at some point in time
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CounterValues {
+    pub gets: u64,
+    pub hits: u64,
+    pub misses: u64,
+    pub sets: u64,
+    pub dels: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// the time that has passed since the counters started counting
+    pub elapsed: Duration,
+}
+
+impl Default for TableCounters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TableCounters {
+    pub fn new() -> Self {
+        Self {
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            bytes_read: AtomicU64::new(0),
+            sets: AtomicU64::new(0),
+            dels: AtomicU64::new(0),
+            bytes_written: AtomicU64::new(0),
+            since: QuickLock::new(Instant::now()),
+            activity: Activity::new(),
+        }
+    }
+    /// Count a read that found a value of `len` bytes
+    pub fn hit(&self, len: usize) {
+        self.hits.fetch_add(1, ORD_RELAXED);
+        self.bytes_read.fetch_add(len as u64, ORD_RELAXED);
+        self.touch_read();
+    }
+    /// Count a read that didn't find anything
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, ORD_RELAXED);
+        self.touch_read();
+    }
+    fn touch_read(&self) {
+        if registry::tracks_reads() {
+            self.activity.touch(TOUCHED_READ);
+        }
+    }
+    /// Count a write that stored `bytes` bytes (which is zero if the write didn't go through)
+    pub fn wrote(&self, bytes: usize) {
+        self.sets.fetch_add(1, ORD_RELAXED);
+        self.bytes_written.fetch_add(bytes as u64, ORD_RELAXED);
+        self.activity.touch(TOUCHED_WRITE);
+    }
+    /// Count a delete
+    pub fn deleted(&self) {
+        self.dels.fetch_add(1, ORD_RELAXED);
+        self.activity.touch(TOUCHED_WRITE);
+    }
+    /// Returns when the table was last written to and read from
+    pub fn activity(&self) -> &Activity {
+        &self.activity
+    }
+    /// Returns the current values of the counters
+    pub fn values(&self) -> CounterValues {
+        let (hits, misses) = (self.hits.load(ORD_RELAXED), self.misses.load(ORD_RELAXED));
+        CounterValues {
+            gets: hits + misses,
+            hits,
+            misses,
+            sets: self.sets.load(ORD_RELAXED),
+            dels: self.dels.load(ORD_RELAXED),
+            bytes_read: self.bytes_read.load(ORD_RELAXED),
+            bytes_written: self.bytes_written.load(ORD_RELAXED),
+            elapsed: self.since.lock().elapsed(),
+        }
+    }
+    /// Zero the counters and start counting again from now
+    pub fn reset(&self) {
+        let mut since = self.since.lock();
+        [
+            &self.hits,
+            &self.misses,
+            &self.bytes_read,
+            &self.sets,
+            &self.dels,
+            &self.bytes_written,
+        ]
+        .iter()
+        .for_each(|counter| counter.store(0, ORD_RELAXED));
+        *since = Instant::now();
+    }
+}
+
+impl CounterValues {
+    /// Returns the average number of `count` per second over the time counted
+    pub fn per_sec(&self, count: u64) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            count as f64 / secs
+        } else {
+            0.0
+        }
+    }
+    /// Returns the counters as `<name> <value>` pairs, along with the read, write and delete
+    /// rates since the counters started counting
+    pub fn to_pairs(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("gets", self.gets.to_string()),
+            ("hits", self.hits.to_string()),
+            ("misses", self.misses.to_string()),
+            ("sets", self.sets.to_string()),
+            ("dels", self.dels.to_string()),
+            ("bytes_read", self.bytes_read.to_string()),
+            ("bytes_written", self.bytes_written.to_string()),
+            ("counted_secs", self.elapsed.as_secs().to_string()),
+            ("gets_per_sec", format!("{:.2}", self.per_sec(self.gets))),
+            ("sets_per_sec", format!("{:.2}", self.per_sec(self.sets))),
+            ("dels_per_sec", format!("{:.2}", self.per_sec(self.dels))),
+        ]
+    }
+}
+
+/// The number of buckets of a [`SizeHistogram`]. A size with `i` significant bits is in
+/// bucket `i`, so the upper bound of a bucket is `2^i - 1` bytes, except for the last one
+/// which takes everything from 16MiB up
+pub const SIZE_BUCKETS: usize = 26;
+
+/// The sizes of a key and of its value, in bytes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PairSize {
+    pub key: usize,
+    pub value: usize,
+}
+
+impl PairSize {
+    pub const fn new(key: usize, value: usize) -> Self {
+        Self { key, value }
+    }
+    /// Returns the bytes held by the key and the value
+    pub const fn total(&self) -> usize {
+        self.key + self.value
+    }
+}
+
+/// An approximate histogram of the sizes (of the keys or of the values) of a table, in
+/// buckets that double in size (see [`SIZE_BUCKETS`]). Adding or removing a size is two
+/// relaxed atomic operations (three if it's the largest so far). Along with that, this keeps
+/// the largest size that it ever counted (a high-water mark), which doesn't go down when that
+/// size is removed
+#[derive(Debug, Default)]
+pub struct SizeHistogram {
+    counts: [AtomicU64; SIZE_BUCKETS],
+    sum: AtomicU64,
+    max: AtomicU64,
+}
+
+/// The values of a size histogram at some point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct SizeValues {
+    /// the number of sizes in every bucket (not cumulative)
+    pub counts: [u64; SIZE_BUCKETS],
+    /// the sum of the sizes
+    pub sum: u64,
+    /// the largest size that was ever counted (see [`SizeHistogram`])
+    pub max: u64,
+}
+
+impl SizeHistogram {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn index_of(size: usize) -> usize {
+        ((64 - (size as u64).leading_zeros()) as usize).min(SIZE_BUCKETS - 1)
+    }
+    /// Returns the upper bound of the bucket at `index`, or `None` for the last bucket
+    pub fn bound_of(index: usize) -> Option<u64> {
+        if index < SIZE_BUCKETS - 1 {
+            Some((1u64 << index) - 1)
+        } else {
+            None
+        }
+    }
+    /// Count a size
+    pub fn add(&self, size: usize) {
+        self.counts[Self::index_of(size)].fetch_add(1, ORD_RELAXED);
+        self.sum.fetch_add(size as u64, ORD_RELAXED);
+        if self.max.load(ORD_RELAXED) < size as u64 {
+            self.max.fetch_max(size as u64, ORD_RELAXED);
+        }
+    }
+    /// Take away a size that was counted before
+    pub fn remove(&self, size: usize) {
+        self.counts[Self::index_of(size)].fetch_sub(1, ORD_RELAXED);
+        self.sum.fetch_sub(size as u64, ORD_RELAXED);
+    }
+    /// Returns the current values. Since nothing is locked, a size that is counted at the same
+    /// time may be in the counts but not in the sum (or the other way around)
+    pub fn values(&self) -> SizeValues {
+        let mut counts = [0; SIZE_BUCKETS];
+        counts
+            .iter_mut()
+            .zip(self.counts.iter())
+            .for_each(|(value, count)| *value = count.load(ORD_RELAXED));
+        SizeValues {
+            counts,
+            sum: self.sum.load(ORD_RELAXED),
+            max: self.max.load(ORD_RELAXED),
+        }
+    }
+}
+
+impl SizeValues {
+    /// Returns the number of sizes that were counted
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+    /// Returns the buckets that aren't empty as `<bound>:<count>`, separated by spaces (`inf`
+    /// for the last bucket), or `none` if they're all empty
+    pub fn compact(&self) -> String {
+        let mut compact = String::new();
+        for (i, count) in self.counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            if !compact.is_empty() {
+                compact.push(' ');
+            }
+            match SizeHistogram::bound_of(i) {
+                Some(bound) => write!(compact, "{}:{}", bound, count),
+                None => write!(compact, "inf:{}", count),
+            }
+            .unwrap();
+        }
+        if compact.is_empty() {
+            compact.push_str("none");
+        }
+        compact
+    }
+    /// Write the histogram in the Prometheus text exposition format as `name`, with `label`
+    /// on every sample. The buckets are cumulative and bounded in bytes
+    pub fn write_prometheus(&self, out: &mut String, name: &str, label: &str) {
+        let mut cumulative = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            cumulative += count;
+            let bound = match SizeHistogram::bound_of(i) {
+                Some(bound) => bound.to_string(),
+                None => "+Inf".to_owned(),
+            };
+            writeln!(
+                out,
+                "{}_bucket{{{},le=\"{}\"}} {}",
+                name, label, bound, cumulative
+            )
+            .unwrap();
+        }
+        writeln!(out, "{}_sum{{{}}} {}", name, label, self.sum).unwrap();
+        writeln!(out, "{}_count{{{}}} {}", name, label, cumulative).unwrap();
+    }
+}
+
+#[test]
+fn test_counter_rates() {
+    let counters = TableCounters::new();
+    counters.hit(5);
+    counters.miss();
+    counters.wrote(10);
+    let mut values = counters.values();
+    assert_eq!(values.gets, 2);
+    assert_eq!(values.bytes_read, 5);
+    values.elapsed = Duration::from_secs(4);
+    assert_eq!(values.per_sec(values.gets), 0.5);
+    values.elapsed = Duration::from_secs(0);
+    assert_eq!(values.per_sec(values.sets), 0.0);
+}
+
+#[test]
+fn test_activity_is_stamped_after_the_fact() {
+    let counters = TableCounters::new();
+    let activity = counters.activity();
+    assert_eq!(activity.last_active(), None);
+    counters.wrote(10);
+    assert!(activity.is_touched());
+    activity.stamp(1000);
+    assert!(!activity.is_touched());
+    assert_eq!(activity.last_write(), Some(1000));
+    // nothing happened since, so nothing moves
+    activity.stamp(2000);
+    assert_eq!(activity.last_write(), Some(1000));
+    // and a time never goes backwards
+    counters.deleted();
+    activity.stamp(500);
+    assert_eq!(activity.last_write(), Some(1000));
+    activity.restore(1500, 0);
+    assert_eq!(activity.last_write(), Some(1500));
+    assert_eq!(activity.last_read(), None);
+    // a reset of the counters leaves the times alone
+    counters.reset();
+    assert_eq!(activity.last_active(), Some(1500));
+}
+
+#[test]
+fn test_size_histogram_buckets() {
+    let sizes = SizeHistogram::new();
+    assert_eq!(sizes.values().compact(), "none");
+    for size in [0, 1, 2, 3, 4, 7, 8, 1000, 20 << 20].iter() {
+        sizes.add(*size);
+    }
+    let values = sizes.values();
+    assert_eq!(values.count(), 9);
+    assert_eq!(values.max, 20 << 20);
+    assert_eq!(values.compact(), "0:1 1:1 3:2 7:2 15:1 1023:1 inf:1");
+    // a removal takes the size out of its bucket, but the largest size stays
+    sizes.remove(20 << 20);
+    sizes.remove(2);
+    let values = sizes.values();
+    assert_eq!(values.sum, 1 + 3 + 4 + 7 + 8 + 1000);
+    assert_eq!(values.compact(), "0:1 1:1 3:1 7:2 15:1 1023:1");
+    assert_eq!(values.max, 20 << 20);
+    let mut out = String::new();
+    values.write_prometheus(&mut out, "sizes", "table=\"t\"");
+    let lines: Vec<&str> = out.lines().collect();
+    assert_eq!(lines.len(), SIZE_BUCKETS + 2);
+    assert_eq!(lines[0], "sizes_bucket{table=\"t\",le=\"0\"} 1");
+    assert_eq!(lines[3], "sizes_bucket{table=\"t\",le=\"7\"} 5");
+    assert_eq!(
+        lines[SIZE_BUCKETS - 1],
+        "sizes_bucket{table=\"t\",le=\"+Inf\"} 7"
+    );
+    assert_eq!(lines[SIZE_BUCKETS + 1], "sizes_count{table=\"t\"} 7");
+}
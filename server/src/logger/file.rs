@@ -0,0 +1,198 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! A log file that is rotated by size or by age
+//!
+//! When `skyd.log` is rotated, `skyd.log.1` becomes `skyd.log.2` (and so on, up to the number of
+//! rotated files that are kept, past which the oldest one is removed), `skyd.log` becomes
+//! `skyd.log.1` and a new `skyd.log` is started. The age of a file is counted from the time that
+//! it was opened, so a file that was already there at startup is taken to be new
+
+use super::Target;
+use log::Record;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+pub struct RollingFile {
+    path: PathBuf,
+    file: File,
+    /// The bytes in the current file
+    written: u64,
+    /// When the current file was opened
+    opened: Instant,
+    /// The size that the file is rotated at (never if `None`)
+    maxsize: Option<u64>,
+    /// The age that the file is rotated at (never if `None`)
+    every: Option<Duration>,
+    /// The rotated files that are kept
+    keep: usize,
+}
+
+impl RollingFile {
+    /// Open the log file at `path` (creating it if it isn't there), appending to it
+    pub fn open(
+        path: &str,
+        maxsize: Option<u64>,
+        every: Option<Duration>,
+        keep: usize,
+    ) -> io::Result<Self> {
+        let path = PathBuf::from(path);
+        let file = self::append_to(&path)?;
+        Ok(RollingFile {
+            written: file.metadata()?.len(),
+            path,
+            file,
+            opened: Instant::now(),
+            maxsize,
+            every,
+            keep,
+        })
+    }
+    /// Returns the path of the `nth` rotated file
+    fn rotated(&self, nth: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", nth));
+        PathBuf::from(path)
+    }
+    /// Returns true if the file has to be rotated before `incoming` more bytes are written
+    fn is_due(&self, incoming: usize) -> bool {
+        let too_big = self.maxsize.map_or(false, |maxsize| {
+            self.written != 0 && self.written + incoming as u64 > maxsize
+        });
+        let too_old = self
+            .every
+            .map_or(false, |every| self.opened.elapsed() >= every);
+        too_big || too_old
+    }
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            self::remove_if_there(&self.path)?;
+        } else {
+            // renaming onto a file that is there doesn't work everywhere
+            self::remove_if_there(&self.rotated(self.keep))?;
+            for nth in (1..self.keep).rev() {
+                self::ignore_missing(fs::rename(self.rotated(nth), self.rotated(nth + 1)))?;
+            }
+            fs::rename(&self.path, self.rotated(1))?;
+        }
+        self.file = self::append_to(&self.path)?;
+        self.written = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Target for RollingFile {
+    fn write(&mut self, record: &Record<'_>) -> io::Result<()> {
+        let line = super::format_line(record);
+        if self.is_due(line.len()) {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn append_to(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+fn remove_if_there(path: &Path) -> io::Result<()> {
+    self::ignore_missing(fs::remove_file(path))
+}
+
+/// Returns `result`, unless it failed only because the file wasn't there
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::{env, process};
+
+    fn write_line(file: &mut RollingFile, message: &str) {
+        file.write(
+            &Record::builder()
+                .args(format_args!("{}", message))
+                .level(Level::Info)
+                .target("skyd")
+                .build(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_rotates_at_the_size_limit() {
+        let root = env::temp_dir().join(format!("skyd-logrotate-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("skyd.log");
+        // a line is a little over 40 bytes, so every line after the first rotates the file
+        let mut file = RollingFile::open(path.to_str().unwrap(), Some(64), None, 2).unwrap();
+        for message in ["first", "second", "third", "fourth"].iter() {
+            write_line(&mut file, message);
+        }
+        let read = |name: &str| fs::read_to_string(root.join(name)).unwrap();
+        assert!(read("skyd.log").ends_with("fourth\n"));
+        assert!(read("skyd.log.1").ends_with("third\n"));
+        assert!(read("skyd.log.2").ends_with("second\n"));
+        // only two rotated files are kept
+        assert!(!root.join("skyd.log.3").exists());
+        // a line that is bigger than the limit still makes it into a file
+        let long = "x".repeat(100);
+        write_line(&mut file, &long);
+        assert!(read("skyd.log").ends_with(&format!("{}\n", long)));
+        assert!(read("skyd.log.1").ends_with("fourth\n"));
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_keeping_nothing_starts_over() {
+        let root = env::temp_dir().join(format!("skyd-logrotate-none-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let path = root.join("skyd.log");
+        let mut file = RollingFile::open(path.to_str().unwrap(), Some(64), None, 0).unwrap();
+        write_line(&mut file, "first");
+        write_line(&mut file, "second");
+        let log = fs::read_to_string(&path).unwrap();
+        assert!(!log.contains("first") && log.ends_with("second\n"));
+        assert!(!root.join("skyd.log.1").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
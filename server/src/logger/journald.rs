@@ -0,0 +1,219 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! The systemd journal
+//!
+//! The records are sent to the journal over its native protocol, as a datagram of `FIELD=value`
+//! lines (a value with a newline in it is sent as the name of the field, a newline, the length
+//! of the value as a little endian `u64` and then the value itself). Along with the `MESSAGE`,
+//! every record has these fields:
+//! - `PRIORITY`: the syslog severity of its level
+//! - `SYSLOG_IDENTIFIER`: `skyd`
+//! - `TARGET`: its target (the module that logged it, unless it says otherwise)
+//! - `CODE_MODULE`, `CODE_FILE` and `CODE_LINE`: where it was logged, if that's known
+//!
+//! so that `journalctl -p warning` or `journalctl TARGET=skyd::dbnet::tcp` just work. If there's
+//! no journal to write to, the logs go to syslog instead
+
+use super::{Socket, Target};
+use log::Record;
+use std::io;
+
+#[cfg(target_os = "linux")]
+/// The socket that journald listens on
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// Append the field `name` with `value` to `datagram`
+fn append_field(datagram: &mut Vec<u8>, name: &str, value: &[u8]) {
+    datagram.extend_from_slice(name.as_bytes());
+    if value.contains(&b'\n') {
+        datagram.push(b'\n');
+        datagram.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        datagram.push(b'=');
+    }
+    datagram.extend_from_slice(value);
+    datagram.push(b'\n');
+}
+
+/// Serialize `record` into an entry of the journal
+pub fn serialize(record: &Record<'_>) -> Vec<u8> {
+    let mut datagram = Vec::new();
+    let priority = super::severity_of(record.level()).to_string();
+    self::append_field(&mut datagram, "PRIORITY", priority.as_bytes());
+    self::append_field(&mut datagram, "SYSLOG_IDENTIFIER", b"skyd");
+    self::append_field(&mut datagram, "TARGET", record.target().as_bytes());
+    if let Some(module) = record.module_path() {
+        self::append_field(&mut datagram, "CODE_MODULE", module.as_bytes());
+    }
+    if let Some(file) = record.file() {
+        self::append_field(&mut datagram, "CODE_FILE", file.as_bytes());
+    }
+    if let Some(line) = record.line() {
+        self::append_field(&mut datagram, "CODE_LINE", line.to_string().as_bytes());
+    }
+    let message = record.args().to_string();
+    self::append_field(&mut datagram, "MESSAGE", message.as_bytes());
+    datagram
+}
+
+pub struct Journald<S> {
+    socket: S,
+}
+
+impl<S: Socket> Target for Journald<S> {
+    fn write(&mut self, record: &Record<'_>) -> io::Result<()> {
+        self.socket.send(&self::serialize(record))
+    }
+}
+
+/// Open the journal, or syslog if there's no journal to write to
+pub fn open() -> io::Result<Box<dyn Target>> {
+    #[cfg(target_os = "linux")]
+    {
+        if let Ok(socket) = super::UnixSocket::connect(JOURNAL_SOCKET) {
+            return Ok(Box::new(Journald { socket }));
+        }
+    }
+    log::warn!("There's no journal to write the logs to, so they're going to syslog instead");
+    super::syslog::open()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+    use std::convert::TryInto;
+
+    /// Keeps what would've been sent to the journal
+    struct MockSocket(Vec<Vec<u8>>);
+
+    impl Socket for MockSocket {
+        fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+            self.0.push(datagram.to_owned());
+            Ok(())
+        }
+    }
+
+    /// Read the fields out of an entry of the journal
+    fn parse(mut datagram: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let mut fields = Vec::new();
+        while !datagram.is_empty() {
+            let end = datagram
+                .iter()
+                .position(|&b| b == b'=' || b == b'\n')
+                .unwrap();
+            let name = String::from_utf8(datagram[..end].to_owned()).unwrap();
+            let value = if datagram[end] == b'=' {
+                let len = datagram[end..].iter().position(|&b| b == b'\n').unwrap() - 1;
+                datagram = &datagram[end + 1..];
+                len
+            } else {
+                let len = u64::from_le_bytes(datagram[end + 1..end + 9].try_into().unwrap());
+                datagram = &datagram[end + 9..];
+                len as usize
+            };
+            fields.push((name, datagram[..value].to_owned()));
+            assert_eq!(datagram[value], b'\n');
+            datagram = &datagram[value + 1..];
+        }
+        fields
+    }
+
+    fn field<'a>(fields: &'a [(String, Vec<u8>)], name: &str) -> Option<&'a [u8]> {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value.as_slice())
+    }
+
+    #[test]
+    fn test_fields_of_a_record() {
+        let mut journald = Journald {
+            socket: MockSocket(Vec::new()),
+        };
+        journald
+            .write(
+                &Record::builder()
+                    .args(format_args!("Failed to flush table `{}`", "default:users"))
+                    .level(Level::Error)
+                    .target("skyd::services::bgsave")
+                    .module_path(Some("skyd::services::bgsave"))
+                    .file(Some("server/src/services/bgsave.rs"))
+                    .line(Some(73))
+                    .build(),
+            )
+            .unwrap();
+        let fields = parse(&journald.socket.0[0]);
+        assert_eq!(field(&fields, "PRIORITY"), Some(&b"3"[..]));
+        assert_eq!(field(&fields, "SYSLOG_IDENTIFIER"), Some(&b"skyd"[..]));
+        assert_eq!(
+            field(&fields, "TARGET"),
+            Some(&b"skyd::services::bgsave"[..])
+        );
+        assert_eq!(
+            field(&fields, "CODE_FILE"),
+            Some(&b"server/src/services/bgsave.rs"[..])
+        );
+        assert_eq!(field(&fields, "CODE_LINE"), Some(&b"73"[..]));
+        assert_eq!(
+            field(&fields, "MESSAGE"),
+            Some(&b"Failed to flush table `default:users`"[..])
+        );
+    }
+
+    #[test]
+    fn test_priorities_of_the_levels() {
+        let priorities = [
+            (Level::Error, b"3"),
+            (Level::Warn, b"4"),
+            (Level::Info, b"6"),
+            (Level::Debug, b"7"),
+            (Level::Trace, b"7"),
+        ];
+        for (level, priority) in priorities.iter() {
+            let datagram = serialize(&Record::builder().level(*level).build());
+            assert_eq!(field(&parse(&datagram), "PRIORITY"), Some(&priority[..]));
+        }
+    }
+
+    #[test]
+    fn test_multiline_messages_are_sent_with_their_length() {
+        let datagram = serialize(
+            &Record::builder()
+                .args(format_args!("first\nsecond"))
+                .build(),
+        );
+        // no location is sent if it isn't known
+        let fields = parse(&datagram);
+        assert_eq!(field(&fields, "CODE_LINE"), None);
+        assert_eq!(field(&fields, "MESSAGE"), Some(&b"first\nsecond"[..]));
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&12u64.to_le_bytes());
+        expected.extend_from_slice(b"first\nsecond\n");
+        assert!(datagram.ends_with(&expected));
+    }
+}
@@ -0,0 +1,326 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Logging
+//!
+//! The logs can be written to any of these targets:
+//! - the standard error (which is where they go by default)
+//! - a file, which is rotated once it grows past a size or gets older than a duration
+//! - the systemd journal, with the priority and the source of every record as fields of its
+//! own
+//! - the local syslog daemon, in RFC 5424 format
+//!
+//! Every target has a filter of its own, in the syntax of `SKY_LOG` (like
+//! `info,skyd::dbnet=debug`), so that (say) the journal can get everything while the standard
+//! error only gets the warnings. Until the configuration file is read, everything that
+//! `SKY_LOG` lets through goes to the standard error. On unix, the targets are set up again from
+//! the `[log]` section of the configuration file on a `SIGHUP`, which also reopens the log file
+//! (so that it can be rotated by an outside tool too).
+
+mod file;
+mod journald;
+mod syslog;
+
+use self::file::RollingFile;
+use crate::config::{LogPref, LogTarget};
+use crate::corestore::lock::QuickLock;
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::env;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+#[cfg(unix)]
+use {
+    crate::config::ParsedConfig,
+    crate::dbnet::Terminator,
+    std::os::unix::net::UnixDatagram,
+    std::path::Path,
+    tokio::signal::unix::{signal, SignalKind},
+};
+
+/// The filter that is used if neither the configuration file nor `SKY_LOG` has one
+const DEFAULT_FILTER: &str = "info";
+
+/// Somewhere that a datagram can be sent: the journal or the syslog daemon (or what the tests
+/// have in their place)
+pub trait Socket: Send {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()>;
+}
+
+#[cfg(unix)]
+/// A unix datagram socket that sends to `path`. The datagrams are addressed one by one, so that
+/// a daemon that is restarted (and binds the path again) still gets them
+pub struct UnixSocket {
+    socket: UnixDatagram,
+    path: &'static str,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    pub fn connect(path: &'static str) -> io::Result<Self> {
+        if !Path::new(path).exists() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("nothing is listening on `{}`", path),
+            ));
+        }
+        Ok(UnixSocket {
+            socket: UnixDatagram::unbound()?,
+            path,
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Socket for UnixSocket {
+    fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+        self.socket.send_to(datagram, self.path).map(|_| ())
+    }
+}
+
+/// Returns the syslog severity of `level` (which the journal uses too)
+const fn severity_of(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,
+        Level::Warn => 4,
+        Level::Info => 6,
+        Level::Debug | Level::Trace => 7,
+    }
+}
+
+/// Somewhere that the log records are written
+pub trait Target: Send {
+    /// Write `record`, which the filter of the target has already let through
+    fn write(&mut self, record: &Record<'_>) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A target along with its filter
+struct Sink {
+    name: &'static str,
+    filter: Filter,
+    target: Mutex<Box<dyn Target>>,
+}
+
+impl Sink {
+    fn new(name: &'static str, spec: &str, target: Box<dyn Target>) -> Self {
+        Sink {
+            name,
+            filter: FilterBuilder::new().parse(spec).build(),
+            target: Mutex::new(target),
+        }
+    }
+    fn target(&self) -> MutexGuard<'_, Box<dyn Target>> {
+        // a target that panicked is still as good as any to write to
+        self.target
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+    fn write(&self, record: &Record<'_>) {
+        if let Err(e) = self.target().write(record) {
+            // there's nowhere else to log this
+            let _ = writeln!(
+                io::stderr(),
+                "Failed to write a log record to the {} target: {}",
+                self.name,
+                e
+            );
+        }
+    }
+}
+
+/// The logger, which hands every record to the targets whose filters let it through
+struct Dispatcher {
+    /// The targets are swapped out as a whole when they're set up again, so that a record is
+    /// never written while holding this lock
+    sinks: QuickLock<Option<Arc<Vec<Sink>>>>,
+}
+
+static LOGGER: Dispatcher = Dispatcher {
+    sinks: QuickLock::new(None),
+};
+
+impl Dispatcher {
+    fn sinks(&self) -> Option<Arc<Vec<Sink>>> {
+        self.sinks.lock().clone()
+    }
+    fn swap(&self, sinks: Vec<Sink>) {
+        let max = sinks
+            .iter()
+            .map(|sink| sink.filter.filter())
+            .max()
+            .unwrap_or(LevelFilter::Off);
+        *self.sinks.lock() = Some(Arc::new(sinks));
+        log::set_max_level(max);
+    }
+}
+
+impl Log for Dispatcher {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.sinks().map_or(false, |sinks| {
+            sinks.iter().any(|sink| sink.filter.enabled(metadata))
+        })
+    }
+    fn log(&self, record: &Record<'_>) {
+        if let Some(sinks) = self.sinks() {
+            for sink in sinks.iter().filter(|sink| sink.filter.matches(record)) {
+                sink.write(record);
+            }
+        }
+    }
+    fn flush(&self) {
+        if let Some(sinks) = self.sinks() {
+            for sink in sinks.iter() {
+                let _ = sink.target().flush();
+            }
+        }
+    }
+}
+
+/// The standard error, written to in the same format (and with the same colors) as before there
+/// were other targets
+struct Stderr(env_logger::Logger);
+
+impl Stderr {
+    fn new() -> Self {
+        // the sink has already filtered the records
+        Stderr(
+            env_logger::Builder::new()
+                .filter_level(LevelFilter::Trace)
+                .build(),
+        )
+    }
+}
+
+impl Target for Stderr {
+    fn write(&mut self, record: &Record<'_>) -> io::Result<()> {
+        self.0.log(record);
+        Ok(())
+    }
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush();
+        Ok(())
+    }
+}
+
+/// Returns the filter in `SKY_LOG`
+fn env_filter() -> String {
+    env::var("SKY_LOG").unwrap_or_else(|_| DEFAULT_FILTER.to_owned())
+}
+
+/// Format `record` as a line, in the format that the standard error has (without the colors)
+fn format_line(record: &Record<'_>) -> String {
+    format!(
+        "[{} {:<5} {}] {}\n",
+        chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+        record.level(),
+        record.target(),
+        record.args()
+    )
+}
+
+/// Start writing everything that `SKY_LOG` lets through to the standard error. This has to be
+/// called before anything is logged; [`configure`] sets up the targets of the configuration
+/// file later
+pub fn init() {
+    LOGGER.swap(vec![Sink::new(
+        LogTarget::Stderr.as_str(),
+        &env_filter(),
+        Box::new(Stderr::new()),
+    )]);
+    let _ = log::set_logger(&LOGGER);
+}
+
+/// Set up the targets in `pref` in place of the current ones. If any of them can't be set up,
+/// the current ones are left as they are
+pub fn configure(pref: &LogPref) -> io::Result<()> {
+    pref.check()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let default = pref.level.clone().unwrap_or_else(self::env_filter);
+    let mut sinks = Vec::with_capacity(pref.targets().len());
+    for &target in pref.targets() {
+        let spec = pref.level_of(target).unwrap_or(&default);
+        sinks.push(Sink::new(target.as_str(), spec, self::open(target, pref)?));
+    }
+    LOGGER.swap(sinks);
+    Ok(())
+}
+
+fn open(target: LogTarget, pref: &LogPref) -> io::Result<Box<dyn Target>> {
+    let target: Box<dyn Target> = match target {
+        LogTarget::Stderr => Box::new(Stderr::new()),
+        LogTarget::File => Box::new(RollingFile::open(
+            pref.file.as_deref().unwrap_or_default(),
+            pref.maxsize,
+            pref.every.map(Duration::from_secs),
+            pref.keep,
+        )?),
+        LogTarget::Journald => journald::open()?,
+        LogTarget::Syslog => syslog::open()?,
+    };
+    Ok(target)
+}
+
+#[cfg(unix)]
+/// Set the targets up again from the `[log]` section of `config_file` every time a `SIGHUP` is
+/// received, until a termination signal is received
+pub async fn reload_on_hangup(config_file: String, mut terminator: Terminator) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            log::warn!(
+                "The logging configuration won't be reloaded on SIGHUP: {}",
+                e
+            );
+            return;
+        }
+    };
+    loop {
+        tokio::select! {
+            _ = hangup.recv() => self::reload(&config_file),
+            _ = terminator.receive_signal() => break,
+        }
+    }
+}
+
+#[cfg(unix)]
+/// Set the targets up again from `config_file`. If it can't be read (or the targets can't be set
+/// up), the targets are left as they are
+fn reload(config_file: &str) {
+    let result = ParsedConfig::new_from_file(config_file.to_owned())
+        .map_err(|e| e.to_string())
+        .and_then(|cfg| self::configure(&cfg.log).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => log::info!("Reloaded the logging configuration"),
+        Err(e) => log::error!(
+            "Failed to reload the logging configuration (keeping the current one): {}",
+            e.trim_end()
+        ),
+    }
+}
@@ -0,0 +1,156 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! The local syslog daemon
+//!
+//! The records are sent to `/dev/log` in the format of RFC 5424, with the `daemon` facility,
+//! `skyd` as the name of the app and the target of the record as the ID of the message. The
+//! hostname is left for the daemon to fill in
+
+use super::{Socket, Target};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::Record;
+use std::io;
+#[cfg(unix)]
+use std::process;
+
+#[cfg(unix)]
+/// The socket that the syslog daemon listens on
+const SYSLOG_SOCKET: &str = "/dev/log";
+
+/// The `daemon` facility
+const FACILITY: u8 = 3;
+/// The longest that the ID of a message can be
+const MAX_MSGID_LEN: usize = 32;
+
+/// Format `record`, logged at `time` by the process `pid`, as a syslog message
+pub fn format(record: &Record<'_>, time: DateTime<Utc>, pid: u32) -> String {
+    // the ID can only have printable ASCII in it, and no spaces
+    let msgid: String = record
+        .target()
+        .chars()
+        .map(|c| if c.is_ascii_graphic() { c } else { '_' })
+        .take(MAX_MSGID_LEN)
+        .collect();
+    format!(
+        "<{}>1 {} - skyd {} {} - {}",
+        FACILITY * 8 + super::severity_of(record.level()),
+        time.to_rfc3339_opts(SecondsFormat::Micros, true),
+        pid,
+        if msgid.is_empty() { "-" } else { &msgid },
+        record.args()
+    )
+}
+
+pub struct Syslog<S> {
+    socket: S,
+    pid: u32,
+}
+
+impl<S: Socket> Target for Syslog<S> {
+    fn write(&mut self, record: &Record<'_>) -> io::Result<()> {
+        let message = self::format(record, Utc::now(), self.pid);
+        self.socket.send(message.as_bytes())
+    }
+}
+
+/// Open the socket of the syslog daemon
+pub fn open() -> io::Result<Box<dyn Target>> {
+    #[cfg(unix)]
+    {
+        let socket = super::UnixSocket::connect(SYSLOG_SOCKET)?;
+        Ok(Box::new(Syslog {
+            socket,
+            pid: process::id(),
+        }))
+    }
+    #[cfg(not(unix))]
+    {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            "syslog is only available on unix",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use log::Level;
+
+    struct MockSocket(Vec<Vec<u8>>);
+
+    impl Socket for MockSocket {
+        fn send(&mut self, datagram: &[u8]) -> io::Result<()> {
+            self.0.push(datagram.to_owned());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_format() {
+        let time = Utc.ymd(2026, 10, 14).and_hms_micro(9, 30, 0, 1500);
+        let message = format(
+            &Record::builder()
+                .args(format_args!("Listening on {}", "127.0.0.1:2003"))
+                .level(Level::Warn)
+                .target("skyd::dbnet")
+                .build(),
+            time,
+            4242,
+        );
+        assert_eq!(
+            message,
+            "<28>1 2026-10-14T09:30:00.001500Z - skyd 4242 skyd::dbnet - Listening on 127.0.0.1:2003"
+        );
+        // a target that isn't a valid ID
+        let message = format(
+            &Record::builder()
+                .target("a target that is way too long to be an ID")
+                .build(),
+            time,
+            4242,
+        );
+        assert!(message.contains(" 4242 a_target_that_is_way_too_long_to - "));
+    }
+
+    #[test]
+    fn test_every_record_is_a_datagram() {
+        let mut syslog = Syslog {
+            socket: MockSocket(Vec::new()),
+            pid: 1,
+        };
+        for level in [Level::Error, Level::Info].iter() {
+            syslog
+                .write(&Record::builder().level(*level).target("skyd").build())
+                .unwrap();
+        }
+        assert_eq!(syslog.socket.0.len(), 2);
+        assert!(syslog.socket.0[0].starts_with(b"<27>1 "));
+        assert!(syslog.socket.0[1].starts_with(b"<30>1 "));
+    }
+}
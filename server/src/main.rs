@@ -35,7 +35,6 @@
 
 use crate::corestore::memstore::Memstore;
 use crate::diskstore::flock::FileLock;
-use env_logger::Builder;
 use libsky::util::terminal;
 use libsky::URL;
 use libsky::VERSION;
@@ -52,28 +51,37 @@ mod admin;
 mod arbiter;
 mod config;
 mod corestore;
+mod daemon;
 mod dbnet;
 mod diskstore;
+mod import;
 mod kvengine;
+mod logger;
+mod preflight;
 mod protocol;
 mod queryengine;
 pub mod registry;
 mod resp;
+mod schema;
 mod services;
 mod storage;
 #[cfg(test)]
 mod tests;
 
-const PATH: &str = ".sky_pid";
-
 #[cfg(not(target_env = "msvc"))]
 use jemallocator::Jemalloc;
 
-#[cfg(not(target_env = "msvc"))]
+#[cfg(all(not(target_env = "msvc"), not(test)))]
 #[global_allocator]
 /// Jemallocator - this is the default memory allocator for platforms other than msvc
 static GLOBAL: Jemalloc = Jemalloc;
 
+#[cfg(all(not(target_env = "msvc"), test))]
+#[global_allocator]
+/// Jemallocator again, but counting the allocations made by every thread so that tests can
+/// check that something doesn't allocate (see [`util::alloc`])
+static GLOBAL: util::alloc::Counting<Jemalloc> = util::alloc::Counting(Jemalloc);
+
 /// The terminal art for `!noart` configurations
 const TEXT: &str = "
 ███████ ██   ██ ██    ██ ████████  █████  ██████  ██      ███████
@@ -86,9 +94,42 @@ const TEXT: &str = "
 type IoResult<T> = std::io::Result<T>;
 
 fn main() {
-    Builder::new()
-        .parse_filters(&env::var("SKY_LOG").unwrap_or_else(|_| "info".to_owned()))
-        .init();
+    logger::init();
+    queryengine::assert_action_names_are_unique();
+    // if the service control manager started us, it only waits so long to hear from us
+    #[cfg(windows)]
+    daemon::winservice::start();
+    let (opts, cfg) = config::get_config_file_or_return_cfg();
+    let (cfg, restore_filepath) = check_args_and_get_cfg(opts.mode, cfg);
+    // the targets of the configuration file take over from the standard error
+    if let Err(e) = logger::configure(&cfg.log) {
+        log::error!("Startup failure: Failed to set up the log targets: {}", e);
+        process::exit(0x01);
+    }
+    if let (StartMode::DumpConfig, Some(file)) = (opts.mode, &opts.config_file) {
+        dump_config(file);
+    }
+    registry::set_sandbox(opts.sandbox);
+    let cfg = if opts.sandbox {
+        // there's no data directory to set up or check
+        log::warn!(
+            "Running in sandbox mode. Nothing is saved and all the data will be lost on shutdown"
+        );
+        cfg.into_sandbox()
+    } else {
+        // resolve the data directory once, so that nothing after this depends on the working
+        // directory
+        if let Err(e) = storage::interface::configure_data_dir(cfg.datadir.as_deref()) {
+            log::error!(
+                "Startup failure: Failed to set up the data directory: {}",
+                e
+            );
+            process::exit(0x01);
+        }
+        // run the preflight checks before binding to any port or locking the directory
+        handle_preflight_report(opts.mode, &preflight::run(&cfg));
+        cfg
+    };
     // Start the server which asynchronously waits for a CTRL+C signal
     // which will safely shut down the server
     let runtime = tokio::runtime::Builder::new_multi_thread()
@@ -96,19 +137,133 @@ fn main() {
         .enable_all()
         .build()
         .unwrap();
-    let (ports, bgsave_config, snapshot_config, restore_filepath, maxcon) =
-        check_args_and_get_cfg();
+    configure_backpressure(&cfg);
+    registry::get_compression().configure(
+        cfg.compression.algorithm.get_algorithm(),
+        cfg.compression.threshold,
+    );
+    registry::get_changelog().set_capacity(cfg.syncbuffer);
+    registry::allow_stale_reads(cfg.readpolicy.is_stale_ok());
+    registry::set_value_limit(cfg.maxvaluesize);
+    registry::get_background().set_max_pause(cfg.maxpause.unwrap_or(registry::DEFAULT_MAX_PAUSE));
+    registry::set_max_name_len(cfg.maxnamelen.unwrap_or(registry::DEFAULT_MAX_NAME_LEN));
+    registry::set_snapshot_token(cfg.snaptoken.clone());
+    if let SnapshotConfig::Enabled(pref) = &cfg.snapshot {
+        diskstore::snapshot::set_snapshot_prefix(&pref.prefix);
+    }
+    registry::set_load_threads(cfg.loadthreads.unwrap_or(registry::DEFAULT_LOAD_THREADS));
+    registry::set_max_ordered_keys(
+        cfg.maxorderedkeys
+            .unwrap_or(registry::DEFAULT_MAX_ORDERED_KEYS),
+    );
+    registry::set_bloom_fp_rate(cfg.bloomfprate.unwrap_or(registry::DEFAULT_BLOOM_FP_RATE));
+    registry::get_memory_guard().configure(cfg.memoryceiling);
+    registry::set_paranoid_mode(cfg.paranoid);
+    if cfg.uring && !storage::writer::HAS_URING {
+        log::warn!("This server wasn't built with io_uring support (the `uring` feature), so the flushes won't use it");
+    }
+    registry::set_uring(cfg.uring);
+    registry::set_orphan_policy(cfg.orphans);
+    registry::set_allow_missing(cfg.allowmissing);
+    registry::set_track_reads(cfg.trackreads);
+    registry::set_case_insensitive(cfg.caseinsensitive);
+    if let Err(unknown) = queryengine::disable_actions(&cfg.disabledactions) {
+        log::error!(
+            "Startup failure: There's no action called `{}` to disable",
+            unknown
+        );
+        process::exit(0x01);
+    }
+    registry::get_txn_quota().configure(
+        cfg.transactions.maxqueued,
+        cfg.transactions.maxbytes,
+        cfg.transactions.maxtotalqueued,
+        cfg.transactions.maxtotalbytes,
+    );
+    registry::get_pipeline_limits().configure(
+        cfg.pipeline.maxinflight,
+        cfg.pipeline.maxinflightbytes,
+        cfg.pipeline.maxdepth,
+    );
+    registry::get_slowlog().configure(cfg.slowlog.threshold, cfg.slowlog.size);
+    registry::configure_metrics(cfg.metrics.buckets.clone());
+    let throttle = registry::get_io_throttle();
+    throttle.set_limit(registry::IoKind::Snapshot, cfg.throttle.snapshot);
+    throttle.set_limit(registry::IoKind::Flush, cfg.throttle.flush);
+    registry::get_selfcheck().configure(
+        cfg.selfcheck
+            .every
+            .map(|hours| time::Duration::from_secs(hours.saturating_mul(3600))),
+        cfg.selfcheck.tolerance,
+    );
     // check if any other process is using the data directory and lock it if not (else error)
     // important: create the pid_file just here and nowhere else because check_args can also
     // involve passing --help or wrong arguments which can falsely create a PID file
-    let pid_file = run_pre_startup_tasks();
+    let pid_file = if opts.sandbox {
+        None
+    } else {
+        Some(run_pre_startup_tasks())
+    };
+    // restore the snapshot (if asked to) only once we own the data directory
+    if let Err(e) = restore_snapshot(&opts) {
+        log::error!("Startup failure: {}", e);
+        pre_shutdown_cleanup(pid_file, None);
+        process::exit(0x01);
+    }
+    if let Some(import) = &opts.import {
+        let code = match run_import(&cfg.snapshot, import) {
+            Ok(report) => {
+                log::info!("Finished importing: {}", report);
+                0x00
+            }
+            Err(e) => {
+                log::error!("Import failure: {}", e);
+                0x01
+            }
+        };
+        pre_shutdown_cleanup(pid_file, None);
+        process::exit(code);
+    }
+    if let Some(migrate) = &opts.migrate {
+        log::info!("Migrating the data directory to `{}`", migrate.to.name());
+        let code = match storage::migrate::migrate(migrate.to) {
+            Ok(report) => {
+                log::info!("Finished migrating: {}", report);
+                0x00
+            }
+            Err(e) => {
+                log::error!(
+                    "Migration failure: {}. Run the migration again to resume it",
+                    e
+                );
+                0x01
+            }
+        };
+        pre_shutdown_cleanup(pid_file, None);
+        process::exit(code);
+    }
+    if let Some(schema) = &opts.schema {
+        let code = match run_schema(&cfg.snapshot, schema) {
+            Ok(()) => 0x00,
+            Err(e) => {
+                log::error!("Schema failure: {}", e);
+                0x01
+            }
+        };
+        pre_shutdown_cleanup(pid_file, None);
+        process::exit(code);
+    }
+    let config_file = opts.config_file.clone();
     let db: Result<corestore::Corestore, String> = runtime.block_on(async move {
         arbiter::run(
-            ports,
-            bgsave_config,
-            snapshot_config,
+            cfg.ports,
+            cfg.bgsave,
+            cfg.snapshot,
             restore_filepath,
-            maxcon,
+            cfg.maxcon,
+            cfg.lazyload,
+            cfg.healthport,
+            config_file,
         )
         .await
     });
@@ -129,30 +284,70 @@ fn main() {
         "Maybe the compiler reordered the drop causing more than one instance of Corestore to live at this point"
     );
     log::info!("Stopped accepting incoming connections");
-    loop {
-        // Keep looping until we successfully write the in-memory table to disk
-        match services::bgsave::run_bgsave(&db) {
-            Ok(_) => {
-                log::info!("Successfully saved data to disk");
-                break;
-            }
-            Err(e) => {
-                log::error!(
-                    "Failed to write data with error '{}'. Attempting to retry in 10s",
-                    e
-                );
+    if opts.sandbox {
+        log::info!("Discarding all the data as the server ran in sandbox mode");
+        pre_shutdown_cleanup(pid_file, None);
+    } else {
+        loop {
+            // Keep looping until we successfully write the in-memory table to disk
+            match services::bgsave::run_bgsave(&db) {
+                Ok(_) => {
+                    log::info!("Successfully saved data to disk");
+                    break;
+                }
+                Err(e) => {
+                    log::error!(
+                        "Failed to write data with error '{}'. Attempting to retry in 10s",
+                        e
+                    );
+                }
             }
+            thread::sleep(time::Duration::from_secs(10));
         }
-        thread::sleep(time::Duration::from_secs(10));
+        pre_shutdown_cleanup(pid_file, Some(db.get_store()));
+    }
+    daemon::notify(daemon::Readiness::Stopped);
+    if registry::get_shutdown().get() == Some(registry::ShutdownKind::Restart) {
+        restart();
     }
-    pre_shutdown_cleanup(pid_file, Some(db.get_store()));
     terminal::write_info("Goodbye :)\n").unwrap();
 }
 
-pub fn pre_shutdown_cleanup(mut pid_file: FileLock, mr: Option<&Memstore>) {
-    if let Err(e) = pid_file.unlock() {
-        log::error!("Shutdown failure: Failed to unlock pid file: {}", e);
-        process::exit(0x01);
+/// Start the server again with the same arguments, for `SYS RESTART`. This is only called
+/// once the data has been flushed and the pid file has been unlocked
+fn restart() -> ! {
+    let exe = match env::current_exe() {
+        Ok(exe) => exe,
+        Err(e) => {
+            log::error!("Failed to restart: couldn't find the server binary: {}", e);
+            process::exit(0x01);
+        }
+    };
+    let mut command = process::Command::new(exe);
+    command.args(env::args_os().skip(1));
+    log::info!("Restarting");
+    #[cfg(unix)]
+    let e = {
+        use std::os::unix::process::CommandExt;
+        // this replaces the current process, so it only returns if it failed
+        command.exec()
+    };
+    #[cfg(not(unix))]
+    let e = match command.spawn() {
+        Ok(_) => process::exit(0x00),
+        Err(e) => e,
+    };
+    log::error!("Failed to restart: {}", e);
+    process::exit(0x01);
+}
+
+/// Unlock the pid file (there's none in sandbox mode) and compact the tree of `mr`, if any
+pub fn pre_shutdown_cleanup(pid_file: Option<FileLock>, mr: Option<&Memstore>) {
+    if let Some(mut pid_file) = pid_file {
+        if let Err(e) = pid_file.unlock() {
+            log::error!("Shutdown failure: Failed to unlock pid file: {}", e);
+            process::exit(0x01);
+        }
     }
     if let Some(mr) = mr {
         log::info!("Compacting tree");
@@ -163,48 +358,180 @@ pub fn pre_shutdown_cleanup(mut pid_file: FileLock, mr: Option<&Memstore>) {
     }
 }
 
-use self::config::{BGSave, PortConfig, SnapshotConfig};
+use self::config::{
+    ConfigError, ConfigType, ParsedConfig, PortConfig, SnapshotConfig, StartMode, StartupOpts,
+};
 
-/// This function checks the command line arguments and either returns a config object
-/// or prints an error to `stderr` and terminates the server
-fn check_args_and_get_cfg() -> (PortConfig, BGSave, SnapshotConfig, Option<String>, usize) {
-    let cfg = config::get_config_file_or_return_cfg();
+/// This function checks the parsed command line arguments and either returns a config object
+/// or prints the error (as a failed preflight check) and terminates the server
+fn check_args_and_get_cfg(
+    mode: StartMode,
+    cfg: Result<ConfigType<ParsedConfig, String>, ConfigError>,
+) -> (ParsedConfig, Option<String>) {
+    // the artwork would only get in the way of the report
+    let show_banner = mode == StartMode::Normal;
     let binding_and_cfg = match cfg {
         Ok(config::ConfigType::Custom(cfg, file)) => {
-            if cfg.is_artful() {
-                println!("Skytable v{} | {}\n{}", VERSION, URL, TEXT);
-            } else {
-                println!("Skytable v{} | {}", VERSION, URL);
+            if show_banner {
+                if cfg.is_artful() {
+                    println!("Skytable v{} | {}\n{}", VERSION, URL, TEXT);
+                } else {
+                    println!("Skytable v{} | {}", VERSION, URL);
+                }
             }
             log::info!("Using settings from supplied configuration");
-            (cfg.ports, cfg.bgsave, cfg.snapshot, file, cfg.maxcon)
+            (cfg, file)
         }
         Ok(config::ConfigType::Def(cfg, file)) => {
-            println!("Skytable v{} | {}\n{}", VERSION, URL, TEXT);
+            if show_banner {
+                println!("Skytable v{} | {}\n{}", VERSION, URL, TEXT);
+            }
             log::warn!("No configuration file supplied. Using default settings");
-            (cfg.ports, cfg.bgsave, cfg.snapshot, file, cfg.maxcon)
+            (cfg, file)
         }
         Err(e) => {
-            log::error!("{}", e);
+            let mut report = preflight::Report::default();
+            report.add(
+                "config",
+                preflight::Outcome::Failed(e.to_string().trim_end().to_owned()),
+            );
+            handle_preflight_report(mode, &report);
             std::process::exit(0x01);
         }
     };
     binding_and_cfg
 }
 
-/// On startup, we attempt to check if a `.sky_pid` file exists. If it does, then
-/// this file will contain the kernel/operating system assigned process ID of the
-/// skyd process. We will attempt to read that and log an error complaining that
+/// Print the configuration file at `location` (which has just been validated) in its
+/// canonical form and exit
+fn dump_config(location: &str) -> ! {
+    match config::dump_config(location) {
+        Ok(dump) => {
+            print!("{}", dump);
+            process::exit(0x00);
+        }
+        Err(e) => {
+            log::error!("{}", e.to_string().trim_end());
+            process::exit(0x01);
+        }
+    }
+}
+
+/// Print the preflight report. This terminates the server if we were only asked to run the
+/// checks or if any of them failed
+fn handle_preflight_report(mode: StartMode, report: &preflight::Report) {
+    match mode {
+        StartMode::CheckOnly | StartMode::DumpConfig => {
+            println!("{}", report);
+            process::exit(if report.is_okay() { 0x00 } else { 0x01 });
+        }
+        StartMode::Normal | StartMode::Import | StartMode::Migrate | StartMode::Schema
+            if !report.is_okay() =>
+        {
+            log::error!("Startup failure: Preflight checks failed:\n{}", report);
+            process::exit(0x01);
+        }
+        StartMode::Normal | StartMode::Import | StartMode::Migrate | StartMode::Schema => {
+            for (check, warning) in report.warnings() {
+                log::warn!("Preflight check `{}`: {}", check, warning);
+            }
+        }
+    }
+}
+
+/// Set up the write backpressure in the registry. The mark is only enforced if BGSAVE
+/// is enabled, since nothing else would ever bring the dirty bytes count down
+fn configure_backpressure(cfg: &ParsedConfig) {
+    if let Some(bp) = &cfg.backpressure {
+        if cfg.bgsave.is_disabled() {
+            log::warn!("Ignoring the dirty bytes mark as BGSAVE is disabled");
+        } else {
+            registry::get_dirty_tracker().configure(bp.dirtymark, bp.policy.is_reject());
+        }
+    }
+}
+
+/// Restore the snapshot directory passed with `--restore-from` into the data directory. If
+/// we weren't asked to restore anything, this makes sure that a previous restore wasn't
+/// interrupted (since we'd otherwise boot with a partially copied directory)
+fn restore_snapshot(opts: &StartupOpts) -> Result<(), String> {
+    match &opts.restore_from {
+        Some(src) => match storage::restore::restore_from(src, opts.force) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("Failed to restore snapshot from `{}`: {}", src, e)),
+        },
+        // there's no data directory to check in sandbox mode
+        None if opts.sandbox => Ok(()),
+        None if storage::restore::is_interrupted() => Err(
+            "A previous snapshot restore was interrupted. Run it again with `--restore-from`"
+                .to_owned(),
+        ),
+        None => Ok(()),
+    }
+}
+
+/// Load the data directory, import the file passed to `skyd import` and flush the imported
+/// records to disk. Nothing is flushed if the import fails
+fn run_import(
+    snapcfg: &SnapshotConfig,
+    opts: &import::ImportOpts,
+) -> Result<import::Report, String> {
+    let db = corestore::Corestore::init_with_snapcfg(snapcfg, false)
+        .map_err(|e| format!("Error while initializing database: {}", e))?;
+    let report = import::run(&db, opts).map_err(|e| e.to_string())?;
+    services::bgsave::run_bgsave(&db)
+        .map_err(|e| format!("Failed to write the imported records to disk: {}", e))?;
+    Ok(report)
+}
+
+/// Export or apply the schema file in `opts` (see [`schema`]). The changes that were applied
+/// are flushed to disk before returning
+fn run_schema(snapcfg: &SnapshotConfig, opts: &schema::SchemaOpts) -> Result<(), String> {
+    let db = corestore::Corestore::init_with_snapcfg(snapcfg, true)
+        .map_err(|e| format!("Error while initializing database: {}", e))?;
+    let (dry_run, allow_drop) = match opts.command {
+        schema::SchemaCommand::Export => {
+            std::fs::write(&opts.file, schema::export(&db).render())
+                .map_err(|e| format!("Failed to write the schema to `{}`: {}", opts.file, e))?;
+            log::info!("Exported the schema to `{}`", opts.file);
+            return Ok(());
+        }
+        schema::SchemaCommand::Apply {
+            dry_run,
+            allow_drop,
+        } => (dry_run, allow_drop),
+    };
+    let schema = schema::Schema::read(&opts.file).map_err(|e| e.to_string())?;
+    let plan = schema::plan(&db, &schema).map_err(|e| e.to_string())?;
+    if dry_run {
+        print!("{}", plan);
+        // tell them if the real thing would be refused
+        return schema::check(&plan, allow_drop).map_err(|e| e.to_string());
+    }
+    let applied = schema::apply(&db, &plan, allow_drop).map_err(|e| e.to_string())?;
+    if applied == 0 {
+        log::info!("Nothing to do: the store matches the schema");
+    } else {
+        services::bgsave::run_bgsave(&db)
+            .map_err(|e| format!("Failed to write the schema changes to disk: {}", e))?;
+        log::info!("Applied {} schema change(s)", applied);
+    }
+    Ok(())
+}
+
+/// On startup, we attempt to check if a `.sky_pid` file exists (see
+/// [`storage::interface::pid_file`]). If it does, then this file will contain the
+/// kernel/operating system assigned process ID of the skyd process. We will attempt to read that and log an error complaining that
 /// the directory is in active use by another process. If the file doesn't then
 /// we're free to create our own file and write our own PID to it. Any subsequent
 /// processes will detect this and this helps us prevent two processes from writing
 /// to the same directory which can cause potentially undefined behavior.
 ///
 fn run_pre_startup_tasks() -> FileLock {
-    let mut file = match FileLock::lock(PATH) {
+    let mut file = match storage::interface::lock_pid_file() {
         Ok(fle) => fle,
         Err(e) => {
-            log::error!("Startup failure: Failed to lock pid file: {}", e);
+            log::error!("Startup failure: {}", e);
             process::exit(0x01);
         }
     };
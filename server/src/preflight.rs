@@ -0,0 +1,459 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Preflight checks
+//!
+//! The preflight checks run before any listener is bound so that misconfigurations are
+//! reported upfront instead of surfacing as errors after the server has partially started.
+//! With `--check-config`, the server only runs these checks and then exits with 0 if all of
+//! them passed (warnings are fine) or 1 otherwise
+
+use crate::config::{ParsedConfig, PortConfig, SnapshotConfig, SslOpts};
+use crate::dbnet::tls;
+use crate::diskstore::flock::FileLock;
+use crate::queryengine;
+use crate::storage::interface::{dir_root, dir_snaproot, pid_file};
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+/// The file that is created (and then removed) to check if a directory is writable
+const PROBE_FILE: &str = ".sky_preflight_probe";
+
+#[derive(Debug, PartialEq)]
+/// The outcome of a single check
+pub enum Outcome {
+    /// The check passed
+    Passed,
+    /// The check passed, but something might need attention
+    Warning(String),
+    /// The check failed and the server shouldn't be started
+    Failed(String),
+}
+
+#[derive(Debug, Default)]
+/// The outcomes of all the checks that were run, in order
+pub struct Report {
+    checks: Vec<(&'static str, Outcome)>,
+}
+
+impl Report {
+    /// Add the outcome of the check called `name`
+    pub fn add(&mut self, name: &'static str, outcome: Outcome) {
+        self.checks.push((name, outcome));
+    }
+    /// Returns true if no check failed
+    pub fn is_okay(&self) -> bool {
+        !self
+            .checks
+            .iter()
+            .any(|(_, outcome)| matches!(outcome, Outcome::Failed(_)))
+    }
+    /// Returns the names of the checks that failed
+    pub fn failed(&self) -> Vec<&'static str> {
+        self.checks
+            .iter()
+            .filter(|(_, outcome)| matches!(outcome, Outcome::Failed(_)))
+            .map(|(name, _)| *name)
+            .collect()
+    }
+    /// Returns the checks that passed with a warning, along with the warning
+    pub fn warnings(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        self.checks
+            .iter()
+            .filter_map(|(name, outcome)| match outcome {
+                Outcome::Warning(warning) => Some((*name, warning.as_str())),
+                _ => None,
+            })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (name, outcome)) in self.checks.iter().enumerate() {
+            if i != 0 {
+                writeln!(f)?;
+            }
+            match outcome {
+                Outcome::Passed => write!(f, "[ OK ] {}", name)?,
+                Outcome::Warning(warning) => write!(f, "[WARN] {}: {}", name, warning)?,
+                Outcome::Failed(reason) => write!(f, "[FAIL] {}: {}", name, reason)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run all the checks for `cfg` against the configured data directories and the PID file
+pub fn run(cfg: &ParsedConfig) -> Report {
+    run_with_dirs(
+        cfg,
+        Path::new(dir_root()),
+        Path::new(dir_snaproot()),
+        Path::new(pid_file()),
+    )
+}
+
+/// Run all the checks for `cfg` using the provided directories and lock file
+fn run_with_dirs(cfg: &ParsedConfig, data_root: &Path, snap_root: &Path, lock: &Path) -> Report {
+    let mut report = Report::default();
+    // we wouldn't have gotten here if the configuration was invalid
+    report.add("config", Outcome::Passed);
+    report.add("ports", check_ports(&cfg.ports));
+    if let Some(healthport) = cfg.healthport {
+        report.add("health-port", check_health_port(&cfg.ports, healthport));
+    }
+    report.add("data-dir", check_writable(data_root));
+    if let SnapshotConfig::Enabled(_) = cfg.snapshot {
+        report.add("snapshot-dir", check_writable(snap_root));
+    }
+    match &cfg.ports {
+        PortConfig::SecureOnly { ssl, .. } | PortConfig::Multi { ssl, .. } => {
+            report.add("tls", check_tls(ssl))
+        }
+        PortConfig::InsecureOnly { .. } => {}
+    }
+    report.add("fd-limit", check_fd_limit(cfg.maxcon));
+    report.add("actions", check_disabled_actions(&cfg.disabledactions));
+    report.add("dir-lock", check_lock(lock));
+    report
+}
+
+/// Check that the listeners won't try to bind to the same address
+pub fn check_ports(ports: &PortConfig) -> Outcome {
+    match ports {
+        PortConfig::Multi { port, ssl, .. } if *port == ssl.port => Outcome::Failed(format!(
+            "the TLS and the non-TLS listeners are both configured to use port {}",
+            port
+        )),
+        _ => Outcome::Passed,
+    }
+}
+
+/// Check that the health listener won't try to bind to the port of another listener
+pub fn check_health_port(ports: &PortConfig, healthport: u16) -> Outcome {
+    let taken = match ports {
+        PortConfig::InsecureOnly { port, .. } => *port == healthport,
+        PortConfig::SecureOnly { ssl, .. } => ssl.port == healthport,
+        PortConfig::Multi { port, ssl, .. } => *port == healthport || ssl.port == healthport,
+    };
+    if taken {
+        Outcome::Failed(format!(
+            "the health listener is configured to use port {} which is already used",
+            healthport
+        ))
+    } else {
+        Outcome::Passed
+    }
+}
+
+/// Check that files can be created in `dir` by actually creating (and then removing) a file.
+/// If `dir` doesn't exist yet, this checks the closest parent that does since it will be
+/// created there
+pub fn check_writable(dir: &Path) -> Outcome {
+    let existing = dir
+        .ancestors()
+        .map(|dir| {
+            if dir.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                dir
+            }
+        })
+        .find(|dir| dir.exists())
+        .unwrap_or_else(|| Path::new("."));
+    if !existing.is_dir() {
+        return Outcome::Failed(format!(
+            "can't create {} as {} is not a directory",
+            dir.display(),
+            existing.display()
+        ));
+    }
+    let probe = existing.join(PROBE_FILE);
+    let probed = File::create(&probe).and_then(|mut file| {
+        file.write_all(b"skyd")?;
+        file.sync_all()
+    });
+    let removed = fs::remove_file(&probe);
+    match probed.and(removed) {
+        Ok(()) => Outcome::Passed,
+        Err(e) => Outcome::Failed(format!("can't write to {}: {}", existing.display(), e)),
+    }
+}
+
+/// Check that the TLS material can be loaded
+///
+/// Without a passphrase file, an encrypted private key can only be loaded after an
+/// interactive prompt; so in that case we'll only make sure that the key is readable
+pub fn check_tls(ssl: &SslOpts) -> Outcome {
+    if ssl.passfile.is_some() {
+        match tls::build_acceptor(ssl.key.clone(), ssl.chain.clone(), ssl.passfile.clone()) {
+            Ok(_) => Outcome::Passed,
+            Err(e) => Outcome::Failed(format!("failed to load the TLS material: {}", e)),
+        }
+    } else if let Err(e) = tls::check_chain_file(&ssl.chain) {
+        Outcome::Failed(format!(
+            "failed to load the certificate chain {}: {}",
+            ssl.chain, e
+        ))
+    } else if let Err(e) = fs::read(&ssl.key) {
+        Outcome::Failed(format!("failed to read the private key {}: {}", ssl.key, e))
+    } else {
+        Outcome::Passed
+    }
+}
+
+/// The file descriptors that we need apart from the ones used by connections (for the
+/// listeners, data files and so on)
+#[cfg(unix)]
+const FD_HEADROOM: libc::rlim_t = 64;
+
+/// Check that the open file limit leaves room for `maxcon` connections
+#[cfg(unix)]
+pub fn check_fd_limit(maxcon: usize) -> Outcome {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // UNSAFE: This is fine as we just pass a pointer to a valid rlimit
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return Outcome::Warning(format!(
+            "failed to get the open file limit: {}",
+            std::io::Error::last_os_error()
+        ));
+    }
+    let needed = (maxcon as libc::rlim_t).saturating_add(FD_HEADROOM);
+    if limit.rlim_cur == libc::RLIM_INFINITY || limit.rlim_cur >= needed {
+        Outcome::Passed
+    } else {
+        Outcome::Warning(format!(
+            "the open file limit is {} but up to {} file descriptors may be needed for {} connections",
+            limit.rlim_cur, needed, maxcon
+        ))
+    }
+}
+
+/// Check that the open file limit leaves room for `maxcon` connections
+#[cfg(not(unix))]
+pub fn check_fd_limit(_maxcon: usize) -> Outcome {
+    Outcome::Passed
+}
+
+/// Check that every action that is to be disabled exists, so that a typo doesn't silently
+/// leave the action enabled
+pub fn check_disabled_actions(actions: &[String]) -> Outcome {
+    match queryengine::check_action_names(actions) {
+        Ok(()) => Outcome::Passed,
+        Err(unknown) => Outcome::Failed(format!(
+            "`{}` can't be disabled as there's no such action",
+            unknown
+        )),
+    }
+}
+
+/// Check that no other instance holds the lock on `lockfile`
+pub fn check_lock(lockfile: &Path) -> Outcome {
+    let existed = lockfile.exists();
+    let unlocked = match FileLock::lock(lockfile) {
+        Ok(mut lock) => lock.unlock(),
+        Err(e) => {
+            return Outcome::Failed(format!(
+                "failed to lock {} (is another instance using this directory?): {}",
+                lockfile.display(),
+                e
+            ))
+        }
+    };
+    if !existed {
+        let _ = fs::remove_file(lockfile);
+    }
+    match unlocked {
+        Ok(()) => Outcome::Passed,
+        Err(e) => Outcome::Failed(format!("failed to unlock {}: {}", lockfile.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{
+        BGSave, CompressionPref, LazyLoad, ParanoidMode, ReadPolicy, SnapshotPref, TxnLimits,
+    };
+    use crate::registry::DEFAULT_SYNC_BUFFER;
+
+    fn config_with(snapshot: SnapshotConfig, ports: PortConfig) -> ParsedConfig {
+        ParsedConfig::new(
+            false,
+            BGSave::default(),
+            snapshot,
+            ports,
+            32,
+            None,
+            CompressionPref::default(),
+            DEFAULT_SYNC_BUFFER,
+            ReadPolicy::StaleOk,
+            LazyLoad::Disabled,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+            Vec::new(),
+            None,
+            None,
+            None,
+            ParanoidMode::Off,
+            TxnLimits::default(),
+        )
+    }
+
+    #[test]
+    fn test_check_ports_conflict() {
+        let ssl = SslOpts::new("key.pem".to_owned(), "cert.pem".to_owned(), 2003, None);
+        assert!(matches!(
+            check_ports(&PortConfig::new_multi(
+                "127.0.0.1".parse().unwrap(),
+                2003,
+                ssl
+            )),
+            Outcome::Failed(_)
+        ));
+        assert_eq!(check_ports(&PortConfig::default()), Outcome::Passed);
+    }
+
+    #[test]
+    fn test_check_health_port() {
+        assert!(matches!(
+            check_health_port(&PortConfig::default(), 2003),
+            Outcome::Failed(_)
+        ));
+        assert_eq!(
+            check_health_port(&PortConfig::default(), 2005),
+            Outcome::Passed
+        );
+    }
+
+    #[test]
+    fn test_check_writable() {
+        fs::create_dir_all("preflight_writable").unwrap();
+        assert_eq!(
+            check_writable(Path::new("preflight_writable")),
+            Outcome::Passed
+        );
+        // doesn't exist yet, but can be created
+        assert_eq!(
+            check_writable(Path::new("preflight_writable/a/b")),
+            Outcome::Passed
+        );
+        assert!(!Path::new("preflight_writable").join(PROBE_FILE).exists());
+        fs::remove_dir_all("preflight_writable").unwrap();
+    }
+
+    #[test]
+    fn test_check_lock() {
+        {
+            let mut lock = FileLock::lock("preflight_lock.pid").unwrap();
+            assert!(matches!(
+                check_lock(Path::new("preflight_lock.pid")),
+                Outcome::Failed(_)
+            ));
+            lock.unlock().unwrap();
+        }
+        assert_eq!(check_lock(Path::new("preflight_lock.pid")), Outcome::Passed);
+        fs::remove_file("preflight_lock.pid").unwrap();
+        // the lock file isn't left behind if we created it
+        assert_eq!(check_lock(Path::new("preflight_lock.pid")), Outcome::Passed);
+        assert!(!Path::new("preflight_lock.pid").exists());
+    }
+
+    #[test]
+    fn test_check_disabled_actions() {
+        let actions = vec!["flushdb".to_owned(), "SYS".to_owned()];
+        assert_eq!(check_disabled_actions(&actions), Outcome::Passed);
+        assert_eq!(check_disabled_actions(&[]), Outcome::Passed);
+        let typo = vec!["flushdb".to_owned(), "flushbd".to_owned()];
+        assert_eq!(
+            check_disabled_actions(&typo),
+            Outcome::Failed("`flushbd` can't be disabled as there's no such action".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_named_failures() {
+        // the snapshot directory has to be created under a file, which is impossible
+        fs::write("preflight_notadir", b"").unwrap();
+        fs::create_dir_all("preflight_named").unwrap();
+        let cfg = config_with(
+            SnapshotConfig::Enabled(SnapshotPref::new(3600, 4, true)),
+            PortConfig::new_multi(
+                "127.0.0.1".parse().unwrap(),
+                2003,
+                SslOpts::new(
+                    "/nonexistent/key.pem".to_owned(),
+                    "/nonexistent/cert.pem".to_owned(),
+                    2004,
+                    None,
+                ),
+            ),
+        );
+        let report = run_with_dirs(
+            &cfg,
+            Path::new("preflight_named/data"),
+            Path::new("preflight_notadir/snaps"),
+            Path::new("preflight_named.pid"),
+        );
+        fs::remove_file("preflight_notadir").unwrap();
+        fs::remove_dir_all("preflight_named").unwrap();
+        assert!(!report.is_okay());
+        assert_eq!(report.failed(), vec!["snapshot-dir", "tls"]);
+        let printed = report.to_string();
+        assert!(printed.contains("[ OK ] data-dir"));
+        assert!(printed.contains("[FAIL] snapshot-dir: "));
+        assert!(printed.contains("[FAIL] tls: "));
+        // nothing is left behind
+        assert!(!Path::new("preflight_named.pid").exists());
+    }
+
+    #[test]
+    fn test_all_okay() {
+        fs::create_dir_all("preflight_okay").unwrap();
+        let cfg = config_with(SnapshotConfig::Disabled, PortConfig::default());
+        let report = run_with_dirs(
+            &cfg,
+            Path::new("preflight_okay/data"),
+            Path::new("preflight_okay/snaps"),
+            Path::new("preflight_okay.pid"),
+        );
+        fs::remove_dir_all("preflight_okay").unwrap();
+        assert!(report.is_okay(), "{}", report);
+        // the snapshot directory isn't checked if snapshots are disabled
+        assert!(!report.to_string().contains("snapshot-dir"));
+    }
+}
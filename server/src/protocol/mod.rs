@@ -38,6 +38,8 @@
 
 mod element;
 pub mod responses;
+#[cfg(test)]
+pub(crate) mod tests;
 use crate::util::Unwrappable;
 use bytes::Bytes;
 pub use element::Element;
@@ -47,6 +49,11 @@ const ASCII_UNDERSCORE: u8 = b'_';
 const ASCII_AMPERSAND: u8 = b'&';
 const ASCII_COLON: u8 = b':';
 const ASCII_PLUS_SIGN: u8 = b'+';
+/// The tsymbol of the frame (`#<id>\n`) that tags a query with a request id, on connections
+/// that negotiated request ids (see [`handshake`](crate::dbnet::handshake))
+pub const REQUEST_ID_TSYMBOL: u8 = b'#';
+/// The most digits that a request id can have (it's an [`u64`])
+const REQUEST_ID_MAX_DIGITS: usize = 20;
 
 #[derive(Debug)]
 /// # Skyhash Deserializer (Parser)
@@ -73,6 +80,19 @@ pub(super) struct Parser<'a> {
     cursor: usize,
     /// The buffer slice
     buffer: &'a [u8],
+    /// What to do with the blobs (strings and such) in the buffer
+    blobs: Blobs<'a>,
+}
+
+#[derive(Debug)]
+/// What the [`Parser`] does with the blobs that it comes across
+enum Blobs<'a> {
+    /// Copy them out of the buffer
+    Copy,
+    /// Slice them out of the buffer (which is this `Bytes`), so that they share its memory
+    Slice(&'a Bytes),
+    /// Skip over them, because we only want to know how long the query is
+    Skip,
 }
 
 #[derive(Debug, PartialEq)]
@@ -119,17 +139,78 @@ pub enum Query {
 pub type ParseResult<T> = Result<T, ParseError>;
 
 impl<'a> Parser<'a> {
-    /// Initialize a new parser instance
+    /// Initialize a new parser instance. The elements of the parsed query are copied out of
+    /// `buffer`
     pub const fn new(buffer: &'a [u8]) -> Self {
         Parser {
             cursor: 0usize,
             buffer,
+            blobs: Blobs::Copy,
+        }
+    }
+    /// Initialize a new parser instance for a buffer that holds (at least) an entire query.
+    /// The elements of the parsed query are slices of `buffer`, so nothing is copied
+    pub fn new_shared(buffer: &'a Bytes) -> Self {
+        Parser {
+            cursor: 0usize,
+            buffer: &buffer[..],
+            blobs: Blobs::Slice(buffer),
+        }
+    }
+    /// Returns the number of bytes that the first query in `buffer` takes up, without keeping
+    /// any of its elements. This fails just like [`Self::parse`] would
+    pub fn query_len(buffer: &'a [u8]) -> ParseResult<usize> {
+        let parser = Parser {
+            cursor: 0usize,
+            buffer,
+            blobs: Blobs::Skip,
+        };
+        parser.parse().map(|(_, forward_by)| forward_by)
+    }
+    /// Parse the request id frame (`#<id>\n`) at the start of `buffer` if there is one,
+    /// returning the id along with the number of bytes that the frame takes up
+    pub fn request_id(buffer: &'a [u8]) -> ParseResult<Option<(u64, usize)>> {
+        if buffer.first() != Some(&REQUEST_ID_TSYMBOL) {
+            return Ok(None);
+        }
+        let mut parser = Parser::new(buffer);
+        parser.incr_cursor();
+        let (start, stop) = parser.read_line();
+        if stop - start > REQUEST_ID_MAX_DIGITS {
+            return Err(ParseError::DatatypeParseFailure);
+        }
+        if parser.cursor == stop {
+            // no LF yet
+            return Err(ParseError::NotEnough);
+        }
+        if start == stop {
+            return Err(ParseError::DatatypeParseFailure);
+        }
+        let id = Self::parse_into_u64(&buffer[start..stop])?;
+        Ok(Some((id, parser.cursor)))
+    }
+    /// Get a blob that has the same contents as `chunk` (which is a part of the buffer)
+    fn blob_of(&self, chunk: &[u8]) -> Bytes {
+        match self.blobs {
+            Blobs::Copy => Bytes::copy_from_slice(chunk),
+            Blobs::Slice(buffer) => buffer.slice_ref(chunk),
+            Blobs::Skip => Bytes::new(),
+        }
+    }
+    /// Returns the capacity to reserve for an array of `size` elements. Nothing needs to be
+    /// reserved if we're just skipping over the query
+    fn array_capacity(&self, size: usize) -> usize {
+        if let Blobs::Skip = self.blobs {
+            0
+        } else {
+            size
         }
     }
     /// Read from the current cursor position to `until` number of positions ahead
     /// This **will forward the cursor itself** if the bytes exist or it will just return a `NotEnough` error
-    fn read_until(&mut self, until: usize) -> ParseResult<&[u8]> {
-        if let Some(b) = self.buffer.get(self.cursor..self.cursor + until) {
+    fn read_until(&mut self, until: usize) -> ParseResult<&'a [u8]> {
+        let buffer: &'a [u8] = self.buffer;
+        if let Some(b) = buffer.get(self.cursor..self.cursor + until) {
             self.cursor += until;
             Ok(b)
         } else {
@@ -177,10 +258,43 @@ impl<'a> Parser<'a> {
     fn will_cursor_give_linefeed(&self) -> ParseResult<bool> {
         self.will_cursor_give_char(b'\n', false)
     }
+    /// Read the size line (`<n>\n`) at the current cursor position. This **will forward the
+    /// cursor itself** (past the `\n`, if there is one)
+    ///
+    /// A size line without its `\n` is always `NotEnough`, even if its digits can be parsed,
+    /// because the next read might bring in more digits: `&1` can be the start of `&10\n`,
+    /// and `&0` isn't an empty array until its `\n` comes in. Digits that can't be parsed
+    /// are rejected right away since more data won't fix them
+    fn read_size(&mut self) -> ParseResult<usize> {
+        let (start, stop) = self.read_line();
+        // the cursor only goes past `stop` if the line ended with a `\n`
+        let complete = self.cursor != stop;
+        let digits = &self.buffer[start..stop];
+        if digits.is_empty() {
+            return if complete {
+                Err(ParseError::DatatypeParseFailure)
+            } else {
+                Err(ParseError::NotEnough)
+            };
+        }
+        let size = Self::parse_into_usize(digits)?;
+        if complete {
+            Ok(size)
+        } else {
+            Err(ParseError::NotEnough)
+        }
+    }
+    /// Returns true if nothing is ahead of the cursor, or the next query (or the request id
+    /// frame that it is tagged with) begins there
+    fn is_at_query_boundary(&self) -> bool {
+        self.buffer
+            .get(self.cursor)
+            .map_or(true, |byte| *byte == b'*' || *byte == REQUEST_ID_TSYMBOL)
+    }
     /// Parse a stream of bytes into [`usize`]
     fn parse_into_usize(bytes: &[u8]) -> ParseResult<usize> {
         if bytes.is_empty() {
-            return Err(ParseError::NotEnough);
+            return Err(ParseError::DatatypeParseFailure);
         }
         let byte_iter = bytes.iter();
         let mut item_usize = 0usize;
@@ -213,7 +327,7 @@ impl<'a> Parser<'a> {
     /// Pasre a stream of bytes into an [`u64`]
     fn parse_into_u64(bytes: &[u8]) -> ParseResult<u64> {
         if bytes.is_empty() {
-            return Err(ParseError::NotEnough);
+            return Err(ParseError::DatatypeParseFailure);
         }
         let byte_iter = bytes.iter();
         let mut item_u64 = 0u64;
@@ -252,38 +366,27 @@ impl<'a> Parser<'a> {
             return Err(ParseError::NotEnough);
         }
         // Now we want to read `*<n>\n`
-        let (start, stop) = self.read_line();
-        if let Some(our_chunk) = self.buffer.get(start..stop) {
-            if our_chunk[0] == b'*' {
-                // Good, this will tell us the number of actions
-                // Let us attempt to read the usize from this point onwards
-                // that is excluding the '*' (so 1..)
-                let ret = Self::parse_into_usize(&our_chunk[1..])?;
-                Ok(ret)
-            } else {
-                Err(ParseError::UnexpectedByte)
-            }
+        if self.buffer[self.cursor] == b'*' {
+            // Good, this will tell us the number of actions
+            // Let us attempt to read the usize from this point onwards
+            // that is excluding the '*'
+            self.incr_cursor();
+            self.read_size()
         } else {
-            Err(ParseError::NotEnough)
+            Err(ParseError::UnexpectedByte)
         }
     }
     /// Get the next element **without** the tsymbol
     ///
     /// This function **does not forward the newline**
-    fn __get_next_element(&mut self) -> ParseResult<&[u8]> {
-        let string_sizeline = self.read_line();
-        if let Some(line) = self.buffer.get(string_sizeline.0..string_sizeline.1) {
-            let string_size = Self::parse_into_usize(line)?;
-            let our_chunk = self.read_until(string_size)?;
-            Ok(our_chunk)
-        } else {
-            Err(ParseError::NotEnough)
-        }
+    fn __get_next_element(&mut self) -> ParseResult<&'a [u8]> {
+        let string_size = self.read_size()?;
+        self.read_until(string_size)
     }
     /// The cursor should have passed the `+` tsymbol
     fn parse_next_string(&mut self) -> ParseResult<Bytes> {
         let our_string_chunk = self.__get_next_element()?;
-        let our_string = Bytes::copy_from_slice(our_string_chunk);
+        let our_string = self.blob_of(our_string_chunk);
         if self.will_cursor_give_linefeed()? {
             // there is a lf after the end of the string; great!
             // let's skip that now
@@ -297,13 +400,13 @@ impl<'a> Parser<'a> {
     /// Should be at the `\x1A` (SUB header)
     fn parse_next_byte(&mut self) -> ParseResult<Bytes> {
         let our_chunk = self.__get_next_element()?;
-        let our_ks_name = Bytes::copy_from_slice(our_chunk);
+        let our_ks_name = self.blob_of(our_chunk);
         if self.will_cursor_give_linefeed()? {
             // end has LF; go on
             self.incr_cursor();
             Ok(our_ks_name)
         } else {
-            Err(ParseError::NotEnough)
+            Err(ParseError::UnexpectedByte)
         }
     }
     /// The cursor should have passed the `:` tsymbol
@@ -342,41 +445,36 @@ impl<'a> Parser<'a> {
     }
     /// The cursor should have passed the tsymbol
     fn parse_next_flat_array(&mut self) -> ParseResult<Vec<Bytes>> {
-        let (start, stop) = self.read_line();
-        if let Some(our_size_chunk) = self.buffer.get(start..stop) {
-            let array_size = Self::parse_into_usize(our_size_chunk)?;
-            let mut array = Vec::with_capacity(array_size);
-            for _ in 0..array_size {
-                if let Some(tsymbol) = self.buffer.get(self.cursor) {
-                    // good, there is a tsymbol; move the cursor ahead
-                    self.incr_cursor();
-                    let ret = match *tsymbol {
-                        b'+' => self.parse_next_string()?,
-                        _ => return Err(ParseError::UnknownDatatype),
-                    };
+        let array_size = self.read_size()?;
+        let mut array = Vec::with_capacity(self.array_capacity(array_size));
+        for _ in 0..array_size {
+            if let Some(tsymbol) = self.buffer.get(self.cursor) {
+                // good, there is a tsymbol; move the cursor ahead
+                self.incr_cursor();
+                let ret = match *tsymbol {
+                    b'+' => self.parse_next_string()?,
+                    _ => return Err(ParseError::UnknownDatatype),
+                };
+                if !matches!(self.blobs, Blobs::Skip) {
                     array.push(ret);
-                } else {
-                    return Err(ParseError::NotEnough);
                 }
+            } else {
+                return Err(ParseError::NotEnough);
             }
-            Ok(array)
-        } else {
-            Err(ParseError::NotEnough)
         }
+        Ok(array)
     }
     /// The tsymbol `&` should have been passed!
     fn parse_next_array(&mut self) -> ParseResult<Vec<Element>> {
-        let (start, stop) = self.read_line();
-        if let Some(our_size_chunk) = self.buffer.get(start..stop) {
-            let array_size = Self::parse_into_usize(our_size_chunk)?;
-            let mut array = Vec::with_capacity(array_size);
-            for _ in 0..array_size {
-                array.push(self.parse_next_element()?);
+        let array_size = self.read_size()?;
+        let mut array = Vec::with_capacity(self.array_capacity(array_size));
+        for _ in 0..array_size {
+            let element = self.parse_next_element()?;
+            if !matches!(self.blobs, Blobs::Skip) {
+                array.push(element);
             }
-            Ok(array)
-        } else {
-            Err(ParseError::NotEnough)
         }
+        Ok(array)
     }
     /// Parse a query and return the [`Query`] and an `usize` indicating the number of bytes that
     /// can be safely discarded from the buffer. It will otherwise return errors if they are found.
@@ -391,16 +489,9 @@ impl<'a> Parser<'a> {
         if number_of_queries == 1 {
             // This is a simple query
             let single_group = self.parse_next_element()?;
-            // The below line defaults to false if no item is there in the buffer
-            // or it checks if the next time is a \r char; if it is, then it is the beginning
-            // of the next query
-            // clippy thinks we're doing something complex when we aren't, at all!
-            #[allow(clippy::blocks_in_if_conditions)]
-            if unsafe {
-                // UNSAFE(@ohsayan): This will never be the case because we'll always get a result and no error value
-                // as we've passed true which will yield Ok(true) even if there is no byte ahead
-                self.will_cursor_give_char(b'*', true).unsafe_unwrap()
-            } {
+            // The below line defaults to true if no item is there in the buffer
+            // or it checks if the next item is the beginning of the next query
+            if self.is_at_query_boundary() {
                 Ok((Query::SimpleQuery(single_group), self.cursor))
             } else {
                 // the next item isn't the beginning of a query but something else?
@@ -414,7 +505,7 @@ impl<'a> Parser<'a> {
             for _ in 0..number_of_queries {
                 queries.push(self.parse_next_element()?);
             }
-            if self.will_cursor_give_char(b'*', true)? {
+            if self.is_at_query_boundary() {
                 Ok((Query::PipelinedQuery(queries), self.cursor))
             } else {
                 Err(ParseError::UnexpectedByte)
@@ -800,3 +891,84 @@ fn test_ks_sub() {
         )
     );
 }
+
+#[test]
+fn test_shared_parse_matches_copied_parse() {
+    let packets: [&[u8]; 8] = [
+        b"*1\n_3\n+3\nSET\n+5\nHello\n+5\nWorld\n",
+        b"*1\n&3\n+3\nACT\n+3\nfoo\n&4\n+5\nsayan\n+2\nis\n+7\nworking\n&1\n+5\napril\n",
+        b"*2\n&3\n+3\nACT\n+3\nfoo\n:2\n12\n&1\n+4\nHEYA\n",
+        b"*1\n_1\n+4\nHEYA\n*1\n_1\n+4\nHE",
+        b"*1\n\x1A5\nsayan\n",
+        b"*1\n_3\n+3\nSET\n+5\nHello\n+5\nWor",
+        b"*1\n_1\n+4\nHEYA\nX",
+        b"*1\n%1\n",
+    ];
+    for packet in packets.iter().copied() {
+        let shared = Bytes::copy_from_slice(packet);
+        let copied = Parser::new(packet).parse();
+        assert_eq!(Parser::new_shared(&shared).parse(), copied);
+        assert_eq!(
+            Parser::query_len(packet),
+            copied.map(|(_, forward_by)| forward_by)
+        );
+    }
+}
+
+#[test]
+fn test_shared_parse_slices_the_buffer() {
+    let buffer = Bytes::from("*1\n_3\n+3\nSET\n+5\nHello\n+5\nWorld\n");
+    let range = buffer.as_ptr_range();
+    match Parser::new_shared(&buffer).parse().unwrap() {
+        (Query::SimpleQuery(Element::FlatArray(args)), forward_by) => {
+            assert_eq!(forward_by, buffer.len());
+            assert_eq!(args, vec!["SET", "Hello", "World"]);
+            for arg in args {
+                assert!(range.contains(&arg.as_ptr()));
+            }
+        }
+        _ => panic!("Expected a flat array"),
+    }
+}
+
+#[test]
+fn test_request_id() {
+    assert_eq!(Parser::request_id(b"*1\n_1\n+4\nHEYA\n"), Ok(None));
+    assert_eq!(Parser::request_id(b""), Ok(None));
+    assert_eq!(
+        Parser::request_id(b"#42\n*1\n_1\n+4\nHEYA\n"),
+        Ok(Some((42, 4)))
+    );
+    assert_eq!(
+        Parser::request_id(b"#18446744073709551615\n"),
+        Ok(Some((u64::MAX, 22)))
+    );
+    assert_eq!(Parser::request_id(b"#42"), Err(ParseError::NotEnough));
+    assert_eq!(
+        Parser::request_id(b"#\n*1\n"),
+        Err(ParseError::DatatypeParseFailure)
+    );
+    assert_eq!(
+        Parser::request_id(b"#4x\n*1\n"),
+        Err(ParseError::DatatypeParseFailure)
+    );
+    assert_eq!(
+        Parser::request_id(b"#18446744073709551616\n"),
+        Err(ParseError::DatatypeParseFailure)
+    );
+    assert_eq!(
+        Parser::request_id(b"#123456789012345678901"),
+        Err(ParseError::DatatypeParseFailure)
+    );
+}
+
+#[test]
+fn test_query_followed_by_a_tagged_query() {
+    let packet = b"*1\n_1\n+4\nHEYA\n#7\n*1\n_1\n+4\nHEYA\n";
+    let (query, forward_by) = Parser::new(packet).parse().unwrap();
+    assert_eq!(
+        query,
+        Query::SimpleQuery(Element::FlatArray(vec![Bytes::from("HEYA")]))
+    );
+    assert_eq!(&packet[forward_by..], b"#7\n*1\n_1\n+4\nHEYA\n");
+}
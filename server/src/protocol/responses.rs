@@ -61,6 +61,25 @@ pub mod groups {
     pub const SNAPSHOT_DISABLED: &[u8] = "!21\nerr-snapshot-disabled\n".as_bytes();
     /// Snapshot has illegal name (other error)
     pub const SNAPSHOT_ILLEGAL_NAME: &[u8] = "!25\nerr-invalid-snapshot-name\n".as_bytes();
+    /// There's no snapshot to take an incremental snapshot against (other error)
+    pub const SNAPSHOT_UNKNOWN_BASE: &[u8] = "!25\nerr-unknown-base-snapshot\n".as_bytes();
+    /// The snapshot chain can't have any more incremental snapshots (other error)
+    pub const SNAPSHOT_CHAIN_FULL: &[u8] = "!23\nerr-snapshot-chain-full\n".as_bytes();
+    /// There's no snapshot with that name (other error)
+    pub const SNAPSHOT_NOT_FOUND: &[u8] = "!20\nerr-unknown-snapshot\n".as_bytes();
+    /// The token for shipping snapshots is wrong, or no token is configured (other error)
+    pub const SNAPSHOT_TOKEN_ERR: &[u8] = "!18\nerr-snapshot-token\n".as_bytes();
+    /// The snapshot couldn't be sent to the other server (other error)
+    pub const SNAPSHOT_TRANSFER_FAILED: &[u8] = "!28\nerr-snapshot-transfer-failed\n".as_bytes();
+    /// There's no transfer of this snapshot in progress (other error)
+    pub const NO_SNAPSHOT_TRANSFER: &[u8] = "!24\nerr-no-snapshot-transfer\n".as_bytes();
+    /// The files of a shipped snapshot don't match its manifest (other error)
+    pub const SNAPSHOT_CHECKSUM: &[u8] = "!21\nerr-snapshot-checksum\n".as_bytes();
+    /// Nothing was flushed before `SYS FLUSHWAIT` timed out (other error)
+    pub const FLUSH_TIMEOUT: &[u8] = "!17\nerr-flush-timeout\n".as_bytes();
+    /// The server runs in sandbox mode and has nowhere to write snapshots or flushes to
+    /// (other error)
+    pub const SANDBOX_MODE: &[u8] = "!16\nerr-sandbox-mode\n".as_bytes();
     /// Access after termination signal (other error)
     pub const ERR_ACCESS_AFTER_TERMSIG: &[u8] = "!24\nerr-access-after-termsig\n".as_bytes();
 
@@ -69,6 +88,8 @@ pub mod groups {
     pub const CONTAINER_NOT_FOUND: &[u8] = "!19\ncontainer-not-found\n".as_bytes();
     pub const STILL_IN_USE: &[u8] = "!12\nstill-in-use\n".as_bytes();
     pub const PROTECTED_OBJECT: &[u8] = "!20\nerr-protected-object\n".as_bytes();
+    /// The table is the default table of its keyspace and can't be dropped
+    pub const DEFAULT_TABLE: &[u8] = "!17\nerr-default-table\n".as_bytes();
     pub const WRONG_MODEL: &[u8] = "!11\nwrong-model\n".as_bytes();
     pub const ALREADY_EXISTS: &[u8] = "!18\nerr-already-exists\n".as_bytes();
     pub const NOT_READY: &[u8] = "!9\nnot-ready\n".as_bytes();
@@ -82,6 +103,66 @@ pub mod groups {
     pub const UNKNOWN_INSPECT_QUERY: &[u8] = "!21\nunknown-inspect-query\n".as_bytes();
     pub const UNKNOWN_PROPERTY: &[u8] = "!16\nunknown-property\n".as_bytes();
     pub const KEYSPACE_NOT_EMPTY: &[u8] = "!18\nkeyspace-not-empty\n".as_bytes();
+    pub const DUPLICATE_OPTION: &[u8] = "!16\nduplicate-option\n".as_bytes();
+    pub const UNKNOWN_SYS_QUERY: &[u8] = "!17\nunknown-sys-query\n".as_bytes();
+    /// The dirty bytes mark was crossed and writes are being rejected (other error)
+    pub const SERVER_BUSY_WRITES: &[u8] = "!18\nserver-busy-writes\n".as_bytes();
+    /// The table doesn't keep an ordered index (other error)
+    pub const NO_ORDERED_INDEX: &[u8] = "!16\nno-ordered-index\n".as_bytes();
+    /// The table has too many keys to sort them for `ORDERED`; the unordered form of the
+    /// query still works (other error)
+    pub const TOO_MANY_TO_ORDER: &[u8] = "!27\ntoo-many-keys-try-unordered\n".as_bytes();
+    /// The key is protected from deletion (other error)
+    pub const PROTECTED_KEY: &[u8] = "!13\nprotected-key\n".as_bytes();
+    /// The key of an `IFEQ` doesn't exist or doesn't hold the expected value, so nothing was
+    /// written (other error)
+    pub const NOT_MATCHED: &[u8] = "!11\nnot-matched\n".as_bytes();
+    /// The dump is malformed or failed the checksum (other error)
+    pub const CORRUPTED_DUMP: &[u8] = "!14\ncorrupted-dump\n".as_bytes();
+    /// The dump was written in an unknown version of the dump format (other error)
+    pub const UNKNOWN_DUMP_VERSION: &[u8] = "!20\nunknown-dump-version\n".as_bytes();
+    /// The token passed to `SYS SHUTDOWN` or `SYS RESTART` wasn't handed out by
+    /// `SYS SHUTDOWN PREPARE` on this connection (other error)
+    pub const BAD_SHUTDOWN_TOKEN: &[u8] = "!18\nbad-shutdown-token\n".as_bytes();
+    /// A query was queued in a transaction
+    pub const QUEUED: &[u8] = "+6\nQUEUED\n".as_bytes();
+    /// `MULTI` in an open transaction (other error)
+    pub const NESTED_MULTI: &[u8] = "!16\nerr-nested-multi\n".as_bytes();
+    /// `EXEC` or `DISCARD` without a transaction (other error)
+    pub const NOT_IN_MULTI: &[u8] = "!16\nerr-not-in-multi\n".as_bytes();
+    /// The action can't be queued in a transaction (other error)
+    pub const NOT_ALLOWED_IN_MULTI: &[u8] = "!24\nerr-not-allowed-in-multi\n".as_bytes();
+    /// A query failed to queue, so the transaction was aborted (other error)
+    pub const EXEC_ABORTED: &[u8] = "!16\nerr-exec-aborted\n".as_bytes();
+    /// A watched key was written since it was watched, so `EXEC` ran nothing (other error)
+    pub const WATCHED_CHANGED: &[u8] = "!19\nerr-watched-changed\n".as_bytes();
+    /// Queueing the query would take the transaction (or all of them) over their limits, so
+    /// the transaction was aborted (other error)
+    pub const TXN_TOO_LARGE: &[u8] = "!18\nerr-txn-too-large\n".as_bytes();
+    /// The action was disabled in the configuration (other error)
+    pub const ACTION_DISABLED: &[u8] = "!19\nerr-action-disabled\n".as_bytes();
+    /// The process uses more memory than the configured ceiling, so writes are rejected
+    /// (other error)
+    pub const OUT_OF_MEMORY: &[u8] = "!17\nerr-out-of-memory\n".as_bytes();
+    /// Unknown `OBJECT` subcommand; this lists the valid ones (other error)
+    pub const UNKNOWN_OBJECT_QUERY: &[u8] =
+        "!39\nunknown-object-query:TYPE|ENCODING|SIZE\n".as_bytes();
+    /// Unknown `VALIDATE` subcommand; this lists the valid ones (other error)
+    pub const UNKNOWN_VALIDATE_QUERY: &[u8] =
+        "!37\nunknown-validate-query:KEY|VALUE|PAIR\n".as_bytes();
+    /// The keyspace or table is read-only, like the ones of an attached snapshot (other error)
+    pub const READ_ONLY_ENTITY: &[u8] = "!20\nerr-read-only-entity\n".as_bytes();
+    /// The snapshot is broken or can't be attached (other error)
+    pub const BAD_SNAPSHOT: &[u8] = "!16\nerr-bad-snapshot\n".as_bytes();
+    /// A TLS client connected to the plain port; this is written in the clear before the
+    /// connection is closed (other error)
+    pub const TLS_ON_PLAIN_PORT: &[u8] = "!21\nerr-tls-on-plain-port\n".as_bytes();
+    /// The key exists, but the stored bytes of its value don't decode to a value of the
+    /// table's type (other error)
+    pub const VALUE_UNREADABLE: &[u8] = "!20\nerr-value-unreadable\n".as_bytes();
+    /// The keyspace was archived with `ARCHIVE KEYSPACE` and has to be unarchived before it
+    /// can be used (other error)
+    pub const KEYSPACE_ARCHIVED: &[u8] = "!21\nerr-keyspace-archived\n".as_bytes();
 }
 
 pub mod full_responses {
@@ -0,0 +1,237 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Split reads: a query can arrive over any number of reads and can be cut at any byte, so
+//! every prefix of a frame should need more data, and a stream of frames read in any number
+//! of pieces should be parsed into the same queries as the whole stream
+
+use super::{ParseError, Parser, Query};
+use bytes::BytesMut;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// The lengths of the values in the corpus, at (and around) the lengths that take another
+/// digit to write
+const VALUE_LENS: [usize; 12] = [0, 1, 9, 10, 11, 99, 100, 101, 999, 1000, 1001, 4096];
+/// The frames that are longer than this are left out of the exhaustive two-way splits of a
+/// whole stream (they're split at every byte on their own)
+const SHORT_FRAME: usize = 128;
+
+/// A value of `len` bytes that has the bytes that mean something to the parser in it
+fn value_of(len: usize) -> String {
+    "ab\n*#+_&:9".chars().cycle().take(len).collect()
+}
+
+/// Encode a query the way that clients send them: as a flat array of strings
+fn encoded(args: &[&str]) -> Vec<u8> {
+    let mut frame = format!("*1\n_{}\n", args.len()).into_bytes();
+    for arg in args {
+        frame.extend(format!("+{}\n{}\n", arg.len(), arg).into_bytes());
+    }
+    frame
+}
+
+/// Valid frames: written out by hand (for the element types that clients rarely send) and
+/// encoded like the queries that clients send all the time, with values whose lengths are
+/// at the boundaries of their length prefixes
+pub(crate) fn corpus() -> Vec<Vec<u8>> {
+    let by_hand: [&[u8]; 10] = [
+        b"*1\n_3\n+3\nSET\n+5\nHello\n+5\nWorld\n",
+        b"*1\n&3\n+3\nACT\n+3\nfoo\n&4\n+5\nsayan\n+2\nis\n+7\nworking\n&1\n+5\napril\n",
+        b"*2\n&3\n+3\nACT\n+3\nfoo\n:2\n12\n&1\n+4\nHEYA\n",
+        b"*1\n:20\n18446744073709551615\n",
+        b"*1\n\x1A5\nsayan\n",
+        b"*1\n_0\n",
+        b"*1\n&0\n",
+        b"*2\n&0\n_0\n",
+        b"*1\n_2\n+0\n\n+0\n\n",
+        b"*1\n&2\n&1\n&0\n_1\n+1\n\n\n",
+    ];
+    let mut corpus: Vec<Vec<u8>> = by_hand.iter().map(|frame| frame.to_vec()).collect();
+    corpus.push(encoded(&["HEYA"]));
+    for len in VALUE_LENS.iter().copied() {
+        let value = value_of(len);
+        corpus.push(encoded(&["SET", "x", &value]));
+        corpus.push(encoded(&["MSET", &value, "y", "z", &value]));
+    }
+    corpus
+}
+
+/// Reads the queries out of the pieces of a stream as they come in, just like a connection
+/// does: whatever can't be parsed yet stays in the buffer until the next piece is read in
+#[derive(Default)]
+struct Reader {
+    buffer: BytesMut,
+    /// the bytes of every query that was read
+    frames: Vec<Vec<u8>>,
+    queries: Vec<Query>,
+}
+
+impl Reader {
+    fn read(&mut self, piece: &[u8]) {
+        self.buffer.extend_from_slice(piece);
+        while !self.buffer.is_empty() {
+            match Parser::query_len(&self.buffer) {
+                Ok(forward_by) => {
+                    let frame = self.buffer.split_to(forward_by).freeze();
+                    let (query, parsed) = Parser::new_shared(&frame).parse().unwrap();
+                    assert_eq!(parsed, forward_by);
+                    self.frames.push(frame.to_vec());
+                    self.queries.push(query);
+                }
+                Err(ParseError::NotEnough) => break,
+                Err(e) => panic!("{:?} with {:?} in the buffer", e, &self.buffer[..]),
+            }
+        }
+    }
+    /// Read the `stream` in pieces that are cut at `cuts` (in order)
+    fn read_cut(stream: &[u8], cuts: &[usize]) -> Self {
+        let mut reader = Self::default();
+        let mut start = 0;
+        for cut in cuts.iter().copied().chain(Some(stream.len())) {
+            reader.read(&stream[start..cut]);
+            start = cut;
+        }
+        reader
+    }
+}
+
+/// Returns the frames along with what they're parsed into when they're read whole
+fn parsed_whole(frames: &[Vec<u8>]) -> Vec<Query> {
+    frames
+        .iter()
+        .map(|frame| {
+            let (query, forward_by) = Parser::new(frame).parse().unwrap();
+            assert_eq!(forward_by, frame.len());
+            query
+        })
+        .collect()
+}
+
+/// Check that a stream of `frames` read in the pieces cut at `cuts` was read into the same
+/// queries as the whole frames, and that no byte was lost or read twice
+fn assert_reads_whole(frames: &[Vec<u8>], expected: &[Query], cuts: &[usize]) {
+    let stream = frames.concat();
+    let reader = Reader::read_cut(&stream, cuts);
+    assert!(reader.buffer.is_empty(), "left over with cuts {:?}", cuts);
+    assert_eq!(reader.frames, frames, "cuts {:?}", cuts);
+    assert_eq!(reader.queries, expected, "cuts {:?}", cuts);
+}
+
+#[test]
+fn test_every_prefix_needs_more_data() {
+    for frame in corpus() {
+        let whole = Parser::new(&frame).parse();
+        assert_eq!(whole.as_ref().map(|(_, len)| *len), Ok(frame.len()));
+        for cut in 0..frame.len() {
+            let prefix = &frame[..cut];
+            assert_eq!(
+                Parser::new(prefix).parse(),
+                Err(ParseError::NotEnough),
+                "{:?} cut at {}",
+                String::from_utf8_lossy(&frame),
+                cut
+            );
+            assert_eq!(Parser::query_len(prefix), Err(ParseError::NotEnough));
+        }
+    }
+}
+
+#[test]
+fn test_every_two_way_split_of_a_stream() {
+    let frames: Vec<Vec<u8>> = corpus()
+        .into_iter()
+        .filter(|frame| frame.len() <= SHORT_FRAME)
+        .collect();
+    let expected = parsed_whole(&frames);
+    for cut in 0..=frames.concat().len() {
+        assert_reads_whole(&frames, &expected, &[cut]);
+    }
+}
+
+#[test]
+fn test_random_splits_of_a_stream() {
+    let frames = corpus();
+    let expected = parsed_whole(&frames);
+    let len = frames.concat().len();
+    for seed in 0..100 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let pieces = rng.gen_range(2..=64);
+        let mut cuts: Vec<usize> = (1..pieces).map(|_| rng.gen_range(0..=len)).collect();
+        cuts.sort_unstable();
+        assert_reads_whole(&frames, &expected, &cuts);
+    }
+    // and one byte at a time
+    let cuts: Vec<usize> = (1..len).collect();
+    assert_reads_whole(&frames, &expected, &cuts);
+}
+
+#[test]
+fn test_incomplete_size_lines_need_more_data() {
+    // these parsed (one byte short) before the size lines had to end with their `\n`
+    for frame in [&b"*1\n_0"[..], b"*1\n&0", b"*2\n&0\n_0", b"*1\n&1\n&0"].iter() {
+        assert_eq!(Parser::new(frame).parse(), Err(ParseError::NotEnough));
+    }
+    // a size can't be told apart from the start of a longer one
+    assert_eq!(
+        Parser::new(b"*1\n_1\n+1").parse(),
+        Err(ParseError::NotEnough)
+    );
+    // but bad digits won't get any better with more data
+    assert_eq!(
+        Parser::new(b"*1\n_1x").parse(),
+        Err(ParseError::DatatypeParseFailure)
+    );
+}
+
+#[test]
+fn test_empty_size_lines_are_malformed() {
+    // these waited for more data forever (or panicked) instead of being rejected
+    assert_eq!(
+        Parser::new(b"*\n_1\n+4\nHEYA\n").parse(),
+        Err(ParseError::DatatypeParseFailure)
+    );
+    assert_eq!(
+        Parser::new(b"*1\n_\n").parse(),
+        Err(ParseError::DatatypeParseFailure)
+    );
+    assert_eq!(
+        Parser::new(b"*1\n_1\n+\nHEYA\n").parse(),
+        Err(ParseError::DatatypeParseFailure)
+    );
+    assert_eq!(
+        Parser::new(b"*1\n:0\n\n").parse(),
+        Err(ParseError::DatatypeParseFailure)
+    );
+    assert_eq!(
+        Parser::new(b"\n\n\n").parse(),
+        Err(ParseError::UnexpectedByte)
+    );
+    assert_eq!(
+        Parser::new(b"*1\n\x1A5\nsayanX").parse(),
+        Err(ParseError::UnexpectedByte)
+    );
+}
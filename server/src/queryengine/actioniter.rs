@@ -0,0 +1,238 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Action iterators
+//!
+//! An [`ActionIter`] is what every action receives: the arguments of the query (excluding
+//! the action itself). Apart from linear iteration, it lets composite actions peek at the
+//! next argument, carve out a bounded sub-iterator and parse trailing `KEYWORD value`
+//! option lists. None of these copy the underlying buffers
+
+use crate::protocol::responses;
+use bytes::Bytes;
+use std::vec::IntoIter;
+
+/// An iterator over the arguments of an action
+#[derive(Debug)]
+pub struct ActionIter {
+    inner: IntoIter<Bytes>,
+}
+
+impl ActionIter {
+    pub fn new(args: Vec<Bytes>) -> Self {
+        Self {
+            inner: args.into_iter(),
+        }
+    }
+    /// Look at the next argument without consuming it
+    pub fn peek(&self) -> Option<&Bytes> {
+        self.inner.as_slice().first()
+    }
+    /// Returns the remaining arguments as a slice
+    pub fn as_slice(&self) -> &[Bytes] {
+        self.inner.as_slice()
+    }
+    /// Returns the next argument or an action error. This is meant to be used after the
+    /// arity has been checked with `check_arity!`
+    pub fn next_or_err(&mut self) -> Result<Bytes, &'static [u8]> {
+        self.inner.next().ok_or(responses::groups::ACTION_ERR)
+    }
+    /// Returns a sub-iterator over exactly the next `n` arguments, or `None` if fewer
+    /// than `n` arguments remain. Arguments that the sub-iterator does not consume are
+    /// skipped when it is dropped
+    ///
+    /// This is not called `take` to avoid being shadowed by [`Iterator::take`]
+    pub fn take_exact(&mut self, n: usize) -> Option<SubIter<'_>> {
+        if self.inner.len() < n {
+            None
+        } else {
+            Some(SubIter {
+                parent: self,
+                remaining: n,
+            })
+        }
+    }
+    #[cfg(test)]
+    /// Same as [`ActionIter::parse_options_and_flags`], without any flags
+    pub fn parse_options(
+        &mut self,
+        accept: &[&'static [u8]],
+    ) -> Result<ActionOptions, OptionError> {
+        self.parse_options_and_flags(accept, &[])
+    }
+    /// Parse all the remaining arguments as a `KEYWORD value` option list. Only the
+    /// keywords in `accept` are allowed; they are matched case-insensitively and each of
+    /// them may appear at most once. The keywords in `flags` stand on their own (without a
+    /// value), and are looked up with [`ActionOptions::has`]
+    pub fn parse_options_and_flags(
+        &mut self,
+        accept: &[&'static [u8]],
+        flags: &[&'static [u8]],
+    ) -> Result<ActionOptions, OptionError> {
+        let mut opts: Vec<(&'static [u8], Bytes)> = Vec::with_capacity(self.inner.len() / 2);
+        while let Some(keyword) = self.inner.next() {
+            let (keyword, value) = match flags.iter().find(|kw| kw.eq_ignore_ascii_case(&keyword)) {
+                Some(flag) => (flag, Bytes::new()),
+                None => {
+                    let keyword = accept
+                        .iter()
+                        .find(|kw| kw.eq_ignore_ascii_case(&keyword))
+                        .ok_or(OptionError::UnknownKeyword)?;
+                    let value = self.inner.next().ok_or(OptionError::MissingValue)?;
+                    (keyword, value)
+                }
+            };
+            if opts.iter().any(|(kw, _)| kw == keyword) {
+                return Err(OptionError::DuplicateKeyword);
+            }
+            opts.push((*keyword, value));
+        }
+        Ok(ActionOptions { opts })
+    }
+}
+
+impl From<Vec<Bytes>> for ActionIter {
+    fn from(args: Vec<Bytes>) -> Self {
+        Self::new(args)
+    }
+}
+
+impl AsRef<[Bytes]> for ActionIter {
+    fn as_ref(&self) -> &[Bytes] {
+        self.as_slice()
+    }
+}
+
+impl Iterator for ActionIter {
+    type Item = Bytes;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl ExactSizeIterator for ActionIter {}
+
+/// A bounded iterator over the next few arguments of an [`ActionIter`]
+#[derive(Debug)]
+pub struct SubIter<'a> {
+    parent: &'a mut ActionIter,
+    remaining: usize,
+}
+
+impl SubIter<'_> {
+    #[cfg(test)]
+    /// Look at the next argument without consuming it
+    pub fn peek(&self) -> Option<&Bytes> {
+        self.as_slice().first()
+    }
+    #[cfg(test)]
+    /// Returns the remaining arguments of this sub-iterator as a slice
+    pub fn as_slice(&self) -> &[Bytes] {
+        &self.parent.as_slice()[..self.remaining]
+    }
+    /// Returns the next argument or an action error
+    pub fn next_or_err(&mut self) -> Result<Bytes, &'static [u8]> {
+        self.next().ok_or(responses::groups::ACTION_ERR)
+    }
+}
+
+impl Iterator for SubIter<'_> {
+    type Item = Bytes;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            None
+        } else {
+            self.remaining -= 1;
+            self.parent.next()
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for SubIter<'_> {}
+
+impl Drop for SubIter<'_> {
+    fn drop(&mut self) {
+        if self.remaining != 0 {
+            let _ = self.parent.inner.nth(self.remaining - 1);
+        }
+    }
+}
+
+/// Options parsed by [`ActionIter::parse_options_and_flags`]
+#[derive(Debug, PartialEq)]
+pub struct ActionOptions {
+    opts: Vec<(&'static [u8], Bytes)>,
+}
+
+impl ActionOptions {
+    /// Returns the value of the provided keyword, if it was set
+    pub fn get(&self, keyword: &[u8]) -> Option<&Bytes> {
+        self.opts
+            .iter()
+            .find(|(kw, _)| kw.eq_ignore_ascii_case(keyword))
+            .map(|(_, value)| value)
+    }
+    /// Returns true if the provided keyword (or flag) was set
+    pub fn has(&self, keyword: &[u8]) -> bool {
+        self.get(keyword).is_some()
+    }
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.opts.len()
+    }
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.opts.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// Errors that can occur while parsing an option list
+pub enum OptionError {
+    /// The keyword isn't accepted by this action
+    UnknownKeyword,
+    /// The keyword was provided more than once
+    DuplicateKeyword,
+    /// The keyword wasn't followed by a value
+    MissingValue,
+}
+
+impl OptionError {
+    /// Returns the response group element for this error
+    pub const fn response(&self) -> &'static [u8] {
+        match self {
+            Self::UnknownKeyword => responses::groups::UNKNOWN_PROPERTY,
+            Self::DuplicateKeyword => responses::groups::DUPLICATE_OPTION,
+            Self::MissingValue => responses::groups::ACTION_ERR,
+        }
+    }
+}
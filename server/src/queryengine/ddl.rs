@@ -25,212 +25,480 @@
 */
 
 use super::parser;
-use super::parser::VALID_CONTAINER_NAME;
 use crate::corestore::memstore::DdlError;
 use crate::corestore::memstore::ObjectID;
 use crate::dbnet::connection::prelude::*;
+use crate::dbnet::connection::Peer;
 use crate::kvengine::encoding;
-use crate::registry;
+use crate::registry::{self, DdlRecord};
+use crate::storage::interface::ThreadDataDir;
+use crate::util::fmt_key_safe;
 use core::str;
 
 pub const TABLE: &[u8] = "TABLE".as_bytes();
 pub const KEYSPACE: &[u8] = "KEYSPACE".as_bytes();
 const VOLATILE: &[u8] = "volatile".as_bytes();
+const ORDERED: &[u8] = "ordered".as_bytes();
+const CACHED: &[u8] = "cached".as_bytes();
+const MAX_VALUE_SIZE: &[u8] = "maxvaluesize:".as_bytes();
 const FORCE_REMOVE: &[u8] = "force".as_bytes();
+pub const DEFAULT: &[u8] = "DEFAULT".as_bytes();
+/// The names that a new keyspace or table can't have (in any case). All the names that start
+/// with an underscore are reserved too
+const RESERVED_NAMES: &[&str] = &["system", "default"];
+
+/// Like `check_arity!`, but returns the error instead of writing it out
+macro_rules! arity_or_ret {
+    ($act:ident, $arity:expr) => {{
+        let arity: Arity = $arity;
+        if !arity.allows($act.len()) {
+            return arity.error();
+        }
+    }};
+}
 
 action!(
     /// Handle `create table <tableid> <model>(args)` and `create keyspace <ksid>`
     /// like queries
-    fn create(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        // minlength is 2 (create has already been checked)
-        err_if_len_is!(act, con, lt 2);
-        let mut create_what = unsafe { act.next().unsafe_unwrap() }.to_vec();
-        create_what.make_ascii_uppercase();
-        match create_what.as_ref() {
-            TABLE => create_table(handle, con, act).await?,
-            KEYSPACE => create_keyspace(handle, con, act).await?,
-            _ => {
-                con.write_response(responses::groups::UNKNOWN_DDL_QUERY)
-                    .await?;
-            }
-        }
-        Ok(())
+    fn create(handle: &Corestore, con: &mut T, act: ActionIter) {
+        let statement = statement_of("CREATE", &act);
+        let ret = create_what(handle, act);
+        log_ddl(handle, con.get_peer(), statement, &ret);
+        con.write_response(ret).await
     }
 );
 
 action!(
     /// Handle `drop table <tableid>` and `drop keyspace <ksid>`
     /// like queries
-    fn ddl_drop(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        // minlength is 2 (create has already been checked)
-        err_if_len_is!(act, con, lt 2);
-        let mut create_what = unsafe { act.next().unsafe_unwrap() }.to_vec();
-        create_what.make_ascii_uppercase();
-        match create_what.as_ref() {
-            TABLE => drop_table(handle, con, act).await?,
-            KEYSPACE => drop_keyspace(handle, con, act).await?,
-            _ => {
-                con.write_response(responses::groups::UNKNOWN_DDL_QUERY)
-                    .await?;
-            }
-        }
-        Ok(())
+    fn ddl_drop(handle: &Corestore, con: &mut T, act: ActionIter) {
+        let statement = statement_of("DROP", &act);
+        let ret = drop_what(handle, act);
+        log_ddl(handle, con.get_peer(), statement, &ret);
+        con.write_response(ret).await
     }
 );
 
 action!(
-    /// We should have `<tableid> <model>(args)`
-    fn create_table(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(con, act.len() > 3 || act.len() < 2);
-        let (table_entity, model_code) = match parser::parse_table_args(&mut act) {
-            Ok(v) => v,
-            Err(e) => return con.write_response(e).await,
-        };
-        let is_volatile = match act.next() {
-            Some(maybe_volatile) => {
-                if maybe_volatile.eq(VOLATILE) {
-                    true
-                } else {
-                    return conwrite!(con, responses::groups::UNKNOWN_PROPERTY);
-                }
-            }
-            None => false,
-        };
-        if registry::state_okay() {
-            match handle.create_table(table_entity, model_code, is_volatile) {
-                Ok(_) => con.write_response(responses::groups::OKAY).await?,
-                Err(DdlError::AlreadyExists) => {
-                    con.write_response(responses::groups::ALREADY_EXISTS)
-                        .await?;
-                }
-                Err(DdlError::WrongModel) => unsafe {
-                    // we have already checked the model ourselves
-                    impossible!()
-                },
-                Err(DdlError::DefaultNotFound) => {
-                    con.write_response(responses::groups::DEFAULT_UNSET).await?
-                }
-                Err(_) => unsafe {
-                    // we know that Corestore::create_table won't return anything else
-                    impossible!()
-                },
-            }
+    /// Handle `alter keyspace <ksid> default table <tblid>` like queries
+    fn alter(handle: &Corestore, con: &mut T, act: ActionIter) {
+        let statement = statement_of("ALTER", &act);
+        let ret = alter_what(handle, act);
+        log_ddl(handle, con.get_peer(), statement, &ret);
+        con.write_response(ret).await
+    }
+);
+
+action!(
+    /// Handle `archive keyspace <ksid>`. The keyspace is flushed on a blocking thread, since
+    /// a large keyspace takes a while to write out
+    fn archive(handle: &Corestore, con: &mut T, act: ActionIter) {
+        let statement = statement_of("ARCHIVE", &act);
+        let ret = if registry::is_sandbox() {
+            // the data would have nowhere to go
+            responses::groups::SANDBOX_MODE.to_owned()
         } else {
-            conwrite!(con, responses::groups::SERVER_ERR)?;
-        }
-        Ok(())
+            let owned_handle = handle.clone();
+            let data_dir = ThreadDataDir::current();
+            tokio::task::spawn_blocking(move || {
+                data_dir.inherit();
+                archive_what(&owned_handle, act, true)
+            })
+            .await
+            .expect("Something caused archiving to panic")
+        };
+        log_ddl(handle, con.get_peer(), statement, &ret);
+        con.write_response(ret).await
     }
 );
 
 action!(
-    /// We should have `<ksid>`
-    fn create_keyspace(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        err_if_len_is!(act, con, not 1);
-        match act.next() {
-            Some(ksid) => {
-                if !encoding::is_utf8(&ksid) {
-                    return con.write_response(responses::groups::ENCODING_ERROR).await;
-                }
-                let ksid_str = unsafe { str::from_utf8_unchecked(&ksid) };
-                if !VALID_CONTAINER_NAME.is_match(ksid_str) {
-                    return con.write_response(responses::groups::BAD_EXPRESSION).await;
-                }
-                if ksid.len() > 64 {
-                    return con
-                        .write_response(responses::groups::CONTAINER_NAME_TOO_LONG)
-                        .await;
-                }
-                let ksid = unsafe { ObjectID::from_slice(ksid_str) };
-                if registry::state_okay() {
-                    match handle.create_keyspace(ksid) {
-                        Ok(()) => return con.write_response(responses::groups::OKAY).await,
-                        Err(DdlError::AlreadyExists) => {
-                            return con.write_response(responses::groups::ALREADY_EXISTS).await
-                        }
-                        Err(_) => unsafe {
-                            // we already know that Corestore::create_keyspace doesn't return anything else
-                            impossible!()
-                        },
-                    }
-                } else {
-                    return conwrite!(con, responses::groups::SERVER_ERR);
-                }
-            }
-            None => return con.write_response(responses::groups::ACTION_ERR).await,
-        }
+    /// Handle `unarchive keyspace <ksid>`
+    fn unarchive(handle: &Corestore, con: &mut T, act: ActionIter) {
+        let statement = statement_of("UNARCHIVE", &act);
+        let ret = archive_what(handle, act, false);
+        log_ddl(handle, con.get_peer(), statement, &ret);
+        con.write_response(ret).await
     }
 );
 
-action! {
-    /// Drop a table (`<tblid>` only)
-    fn drop_table(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        match act.next() {
-            Some(eg) => {
-                let entity_group = match parser::get_query_entity(&eg) {
-                    Ok(egroup) => egroup,
-                    Err(e) => return con.write_response(e).await,
-                };
-                if registry::state_okay() {
-                    let ret = match handle.drop_table(entity_group) {
-                        Ok(()) => responses::groups::OKAY,
-                        Err(DdlError::DefaultNotFound) => responses::groups::DEFAULT_UNSET,
-                        Err(DdlError::ProtectedObject) => responses::groups::PROTECTED_OBJECT,
-                        Err(DdlError::ObjectNotFound) => responses::groups::CONTAINER_NOT_FOUND,
-                        Err(DdlError::StillInUse) => responses::groups::STILL_IN_USE,
-                        Err(_) => unsafe {
-                            // we know that Memstore::drop_table won't ever return anything else
-                            impossible!()
-                        }
-                    };
-                    con.write_response(ret).await?;
-                } else {
-                    conwrite!(con, responses::groups::SERVER_ERR)?;
-                }
-            },
-            None => con.write_response(responses::groups::ACTION_ERR).await?,
-        }
-        Ok(())
+/// The rule that the name of a new keyspace or table breaks
+#[derive(Debug, PartialEq)]
+pub(crate) enum NameError {
+    /// The name is reserved (see [`RESERVED_NAMES`])
+    Reserved,
+    /// The name is longer than the limit (in bytes)
+    TooLong(usize),
+    /// The name has something other than ASCII letters, digits, `_` and `-`, or it starts with
+    /// a digit or a `-`
+    BadCharacter,
+}
+
+impl NameError {
+    /// The error string that is returned, which names the rule: `bad-container-name:<rule>`
+    pub(crate) fn error(&self) -> Vec<u8> {
+        let rule = match self {
+            Self::Reserved => "reserved".to_owned(),
+            Self::TooLong(limit) => format!("longer-than-{}", limit),
+            Self::BadCharacter => "bad-character".to_owned(),
+        };
+        let error = format!("bad-container-name:{}", rule);
+        format!("!{}\n{}\n", error.len(), error).into_bytes()
+    }
+}
+
+/// Check the name of a new keyspace or table. This is only done when a container is created,
+/// so the ones that were created under the older rules still load and can be used
+pub(crate) fn check_new_name(name: &[u8]) -> Result<(), NameError> {
+    let limit = registry::get_max_name_len();
+    if name.len() > limit {
+        return Err(NameError::TooLong(limit));
+    }
+    let starts_okay =
+        matches!(name.first(), Some(byte) if byte.is_ascii_alphabetic() || *byte == b'_');
+    if !starts_okay
+        || !name
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || *byte == b'_' || *byte == b'-')
+    {
+        return Err(NameError::BadCharacter);
+    }
+    let reserved = name.starts_with(b"_")
+        || RESERVED_NAMES
+            .iter()
+            .any(|reserved| name.eq_ignore_ascii_case(reserved.as_bytes()));
+    if reserved {
+        return Err(NameError::Reserved);
     }
+    Ok(())
 }
 
-action! {
-    /// Drop a keyspace (`<ksid>` only)
-    fn drop_keyspace(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        match act.next() {
-            Some(ksid) => {
-                if ksid.len() > 64 {
-                    return con.write_response(responses::groups::CONTAINER_NAME_TOO_LONG).await;
-                }
-                let force_remove = match act.next() {
-                    Some(bts) if bts.eq(FORCE_REMOVE) => true,
-                    None => false,
-                    _ => return conwrite!(con, responses::groups::UNKNOWN_ACTION)
-                };
-                if registry::state_okay() {
-                    let objid = unsafe {ObjectID::from_slice(ksid)};
-                    let result = if force_remove {
-                        handle.force_drop_keyspace(objid)
-                    } else {
-                        handle.drop_keyspace(objid)
-                    };
-                    let ret = match result {
-                        Ok(()) => responses::groups::OKAY,
-                        Err(DdlError::ProtectedObject) => responses::groups::PROTECTED_OBJECT,
-                        Err(DdlError::ObjectNotFound) => responses::groups::CONTAINER_NOT_FOUND,
-                        Err(DdlError::StillInUse) => responses::groups::STILL_IN_USE,
-                        Err(DdlError::NotEmpty) => responses::groups::KEYSPACE_NOT_EMPTY,
-                        Err(_) => unsafe {
-                            // we know that Memstore::drop_table won't ever return anything else
-                            impossible!()
-                        }
-                    };
-                    con.write_response(ret).await?;
-                } else {
-                    conwrite!(con, responses::groups::SERVER_ERR)?;
-                }
-            },
-            None => con.write_response(responses::groups::ACTION_ERR).await?,
+/// Reassemble the query from the action and its arguments, for the DDL log (the arguments are
+/// rendered with [`fmt_key_safe`] since they're whatever the client sent)
+fn statement_of(action: &str, act: &ActionIter) -> String {
+    let mut statement = action.to_owned();
+    for arg in act.as_slice() {
+        statement.push(' ');
+        statement.push_str(&fmt_key_safe(arg));
+    }
+    statement
+}
+
+/// Append the outcome of a DDL query to the DDL log. The query has already been run, so
+/// failing to log it doesn't fail the query
+fn log_ddl(handle: &Corestore, peer: &Peer, statement: String, response: &[u8]) {
+    let time = handle.get_store().get_clock().now();
+    let record = DdlRecord::new(time, peer, statement, response);
+    if let Err(e) = registry::get_ddl_log().append(record) {
+        log::error!("Failed to write to the DDL log: {}", e);
+    }
+}
+
+fn create_what(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    // minlength is 2 (create has already been checked)
+    arity_or_ret!(act, Arity::AtLeast(2));
+    let mut create_what = unsafe {
+        // UNSAFE: We have already checked the arity
+        act.next().unsafe_unwrap()
+    }
+    .to_vec();
+    create_what.make_ascii_uppercase();
+    match create_what.as_ref() {
+        TABLE => create_table(handle, act),
+        KEYSPACE => create_keyspace(handle, act),
+        _ => responses::groups::UNKNOWN_DDL_QUERY.to_owned(),
+    }
+}
+
+fn drop_what(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    // minlength is 2 (drop has already been checked)
+    arity_or_ret!(act, Arity::AtLeast(2));
+    let mut drop_what = unsafe {
+        // UNSAFE: We have already checked the arity
+        act.next().unsafe_unwrap()
+    }
+    .to_vec();
+    drop_what.make_ascii_uppercase();
+    match drop_what.as_ref() {
+        TABLE => drop_table(handle, act),
+        KEYSPACE => drop_keyspace(handle, act),
+        _ => responses::groups::UNKNOWN_DDL_QUERY.to_owned(),
+    }
+}
+
+fn alter_what(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    // minlength is 2 (alter has already been checked)
+    arity_or_ret!(act, Arity::AtLeast(2));
+    let mut alter_what = unsafe {
+        // UNSAFE: We have already checked the arity
+        act.next().unsafe_unwrap()
+    }
+    .to_vec();
+    alter_what.make_ascii_uppercase();
+    match alter_what.as_ref() {
+        KEYSPACE => alter_keyspace(handle, act),
+        _ => responses::groups::UNKNOWN_DDL_QUERY.to_owned(),
+    }
+}
+
+/// We should have `keyspace <ksid>`, which is archived if `archive` is set and unarchived
+/// otherwise (see [`Corestore::archive_keyspace`] and [`Corestore::unarchive_keyspace`])
+fn archive_what(handle: &Corestore, mut act: ActionIter, archive: bool) -> Vec<u8> {
+    arity_or_ret!(act, Arity::Exactly(2));
+    let (archive_what, ksid) = unsafe {
+        // UNSAFE: We have already checked the arity
+        (act.next().unsafe_unwrap(), act.next().unsafe_unwrap())
+    };
+    if !archive_what.eq_ignore_ascii_case(KEYSPACE) {
+        return responses::groups::UNKNOWN_DDL_QUERY.to_owned();
+    }
+    if ksid.len() > 64 {
+        return responses::groups::CONTAINER_NAME_TOO_LONG.to_owned();
+    }
+    if !registry::state_okay() {
+        return responses::groups::SERVER_ERR.to_owned();
+    }
+    let ksid = unsafe { ObjectID::from_slice(&ksid) };
+    let result = if archive {
+        handle.archive_keyspace(ksid)
+    } else {
+        handle.unarchive_keyspace(ksid)
+    };
+    let ret = match result {
+        Ok(()) => responses::groups::OKAY,
+        Err(DdlError::ProtectedObject) => responses::groups::PROTECTED_OBJECT,
+        Err(DdlError::ObjectNotFound) => responses::groups::CONTAINER_NOT_FOUND,
+        Err(DdlError::StillInUse) => responses::groups::STILL_IN_USE,
+        Err(DdlError::ReadOnly) => responses::groups::READ_ONLY_ENTITY,
+        Err(DdlError::DdlTransactionFailure) => responses::groups::DDL_TRANSACTIONAL_FAILURE,
+        Err(_) => unsafe {
+            // we know that Corestore::archive_keyspace and Corestore::unarchive_keyspace
+            // won't return anything else
+            impossible!()
+        },
+    };
+    ret.to_owned()
+}
+
+/// We should have `<tableid> <model>(args) <properties>` where the properties can be
+/// `volatile`, `ordered`, `cached` and/or `maxvaluesize:<bytes>`
+fn create_table(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    arity_or_ret!(act, Arity::Between(2, 6));
+    let (table_entity, model_code) = match parser::parse_table_args(&mut act) {
+        Ok(v) => v,
+        Err(e) => return e.to_owned(),
+    };
+    // only the table is new; the keyspace (if there is one) has to exist already
+    let tblid = match &table_entity {
+        (_, Some(tblid)) | (Some(tblid), None) => tblid,
+        (None, None) => unsafe {
+            // the entity parser always returns at least one part
+            impossible!()
+        },
+    };
+    if let Err(e) = check_new_name(tblid) {
+        return e.error();
+    }
+    let (mut is_volatile, mut is_ordered, mut is_cached) = (false, false, false);
+    let mut value_limit = None;
+    for property in act {
+        if let Some(limit) = property.strip_prefix(MAX_VALUE_SIZE) {
+            if value_limit.is_some() {
+                return responses::groups::DUPLICATE_OPTION.to_owned();
+            }
+            // a limit of zero would leave nothing but empty values
+            match str::from_utf8(limit)
+                .ok()
+                .and_then(|l| l.parse::<u64>().ok())
+            {
+                Some(limit) if limit != 0 => value_limit = Some(limit),
+                _ => return responses::groups::BAD_EXPRESSION.to_owned(),
+            }
+            continue;
         }
-        Ok(())
+        let flag = match property.as_ref() {
+            VOLATILE => &mut is_volatile,
+            ORDERED => &mut is_ordered,
+            CACHED => &mut is_cached,
+            _ => return responses::groups::UNKNOWN_PROPERTY.to_owned(),
+        };
+        if *flag {
+            return responses::groups::DUPLICATE_OPTION.to_owned();
+        }
+        *flag = true;
+    }
+    if !registry::state_okay() {
+        return responses::groups::SERVER_ERR.to_owned();
+    }
+    let ret = match handle.create_table(
+        table_entity,
+        model_code,
+        is_volatile,
+        is_ordered,
+        is_cached,
+        value_limit,
+    ) {
+        Ok(_) => responses::groups::OKAY,
+        Err(DdlError::AlreadyExists) => responses::groups::ALREADY_EXISTS,
+        Err(DdlError::WrongModel) => unsafe {
+            // we have already checked the model ourselves
+            impossible!()
+        },
+        Err(DdlError::DefaultNotFound) => responses::groups::DEFAULT_UNSET,
+        Err(DdlError::ReadOnly) => responses::groups::READ_ONLY_ENTITY,
+        Err(DdlError::Archived) => responses::groups::KEYSPACE_ARCHIVED,
+        Err(_) => unsafe {
+            // we know that Corestore::create_table won't return anything else
+            impossible!()
+        },
+    };
+    ret.to_owned()
+}
+
+/// We should have `<ksid>`
+fn create_keyspace(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    arity_or_ret!(act, Arity::Exactly(1));
+    let ksid = unsafe {
+        // UNSAFE: We have already checked the arity
+        act.next().unsafe_unwrap()
+    };
+    if !encoding::is_utf8(&ksid) {
+        return responses::groups::ENCODING_ERROR.to_owned();
+    }
+    if ksid.is_empty() {
+        return responses::groups::BAD_EXPRESSION.to_owned();
+    }
+    if let Err(e) = check_new_name(&ksid) {
+        return e.error();
+    }
+    let ksid = unsafe { ObjectID::from_slice(&ksid) };
+    if !registry::state_okay() {
+        return responses::groups::SERVER_ERR.to_owned();
+    }
+    let ret = match handle.create_keyspace(ksid) {
+        Ok(()) => responses::groups::OKAY,
+        Err(DdlError::AlreadyExists) => responses::groups::ALREADY_EXISTS,
+        Err(_) => unsafe {
+            // we already know that Corestore::create_keyspace doesn't return anything else
+            impossible!()
+        },
+    };
+    ret.to_owned()
+}
+
+/// We should have `<ksid> default table <tblid>`. The table becomes the one that a `USE` of
+/// just the keyspace switches to
+fn alter_keyspace(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    arity_or_ret!(act, Arity::Exactly(4));
+    let (ksid, property, kind, tblid) = unsafe {
+        // UNSAFE: We have already checked the arity
+        (
+            act.next().unsafe_unwrap(),
+            act.next().unsafe_unwrap(),
+            act.next().unsafe_unwrap(),
+            act.next().unsafe_unwrap(),
+        )
+    };
+    if !property.eq_ignore_ascii_case(DEFAULT) || !kind.eq_ignore_ascii_case(TABLE) {
+        return responses::groups::UNKNOWN_PROPERTY.to_owned();
+    }
+    if ksid.len() > 64 || tblid.len() > 64 {
+        return responses::groups::CONTAINER_NAME_TOO_LONG.to_owned();
+    }
+    let ks = match handle.get_keyspace(&ksid[..]) {
+        Some(ks) => ks,
+        None => return responses::groups::CONTAINER_NOT_FOUND.to_owned(),
+    };
+    if ks.is_read_only() {
+        return responses::groups::READ_ONLY_ENTITY.to_owned();
+    }
+    if ks.is_archived() {
+        return responses::groups::KEYSPACE_ARCHIVED.to_owned();
+    }
+    if !registry::state_okay() {
+        return responses::groups::SERVER_ERR.to_owned();
+    }
+    let tblid = unsafe { ObjectID::from_slice(&tblid) };
+    // the partition map is written out on every flush, so this is persisted with the next one
+    let ret = match ks.set_default_table(tblid) {
+        Ok(()) => responses::groups::OKAY,
+        Err(DdlError::ObjectNotFound) => responses::groups::CONTAINER_NOT_FOUND,
+        Err(_) => unsafe {
+            // we know that Keyspace::set_default_table won't return anything else
+            impossible!()
+        },
+    };
+    ret.to_owned()
+}
+
+/// Drop a table (`<tblid>` only)
+fn drop_table(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    let eg = match act.next() {
+        Some(eg) => eg,
+        None => return responses::groups::ACTION_ERR.to_owned(),
+    };
+    let entity_group = match parser::get_query_entity(&eg) {
+        Ok(egroup) => egroup,
+        Err(e) => return e.to_owned(),
+    };
+    if !registry::state_okay() {
+        return responses::groups::SERVER_ERR.to_owned();
+    }
+    let ret = match handle.drop_table(entity_group) {
+        Ok(()) => responses::groups::OKAY,
+        Err(DdlError::DefaultNotFound) => responses::groups::DEFAULT_UNSET,
+        Err(DdlError::ProtectedObject) => responses::groups::PROTECTED_OBJECT,
+        Err(DdlError::IsDefault) => responses::groups::DEFAULT_TABLE,
+        Err(DdlError::ObjectNotFound) => responses::groups::CONTAINER_NOT_FOUND,
+        Err(DdlError::StillInUse) => responses::groups::STILL_IN_USE,
+        Err(DdlError::ReadOnly) => responses::groups::READ_ONLY_ENTITY,
+        Err(DdlError::Archived) => responses::groups::KEYSPACE_ARCHIVED,
+        // the table is gone, but its files couldn't be removed
+        Err(DdlError::DdlTransactionFailure) => responses::groups::DDL_TRANSACTIONAL_FAILURE,
+        Err(_) => unsafe {
+            // we know that Memstore::drop_table won't ever return anything else
+            impossible!()
+        },
+    };
+    ret.to_owned()
+}
+
+/// Drop a keyspace (`<ksid>` only)
+fn drop_keyspace(handle: &Corestore, mut act: ActionIter) -> Vec<u8> {
+    let ksid = match act.next() {
+        Some(ksid) => ksid,
+        None => return responses::groups::ACTION_ERR.to_owned(),
+    };
+    if ksid.len() > 64 {
+        return responses::groups::CONTAINER_NAME_TOO_LONG.to_owned();
+    }
+    let force_remove = match act.next() {
+        Some(bts) if bts.eq(FORCE_REMOVE) => true,
+        None => false,
+        _ => return responses::groups::UNKNOWN_ACTION.to_owned(),
+    };
+    if !registry::state_okay() {
+        return responses::groups::SERVER_ERR.to_owned();
     }
+    let objid = unsafe { ObjectID::from_slice(&ksid) };
+    let result = if force_remove {
+        handle.force_drop_keyspace(objid)
+    } else {
+        handle.drop_keyspace(objid)
+    };
+    let ret = match result {
+        Ok(()) => responses::groups::OKAY,
+        Err(DdlError::ProtectedObject) => responses::groups::PROTECTED_OBJECT,
+        Err(DdlError::ObjectNotFound) => responses::groups::CONTAINER_NOT_FOUND,
+        Err(DdlError::StillInUse) => responses::groups::STILL_IN_USE,
+        Err(DdlError::NotEmpty) => responses::groups::KEYSPACE_NOT_EMPTY,
+        // attached snapshots are detached with DETACHSNAP
+        Err(DdlError::ReadOnly) => responses::groups::READ_ONLY_ENTITY,
+        Err(DdlError::DdlTransactionFailure) => responses::groups::DDL_TRANSACTIONAL_FAILURE,
+        Err(_) => unsafe {
+            // we know that Memstore::drop_table won't ever return anything else
+            impossible!()
+        },
+    };
+    ret.to_owned()
 }
@@ -0,0 +1,102 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Disabled actions
+//!
+//! Actions can be disabled in the configuration (`disabledactions`), so that deployments can
+//! turn off things like `FLUSHDB` without building a different server. A disabled action is
+//! still recognized but returns `err-action-disabled` instead of running. The set is resolved
+//! into a bit for every action before the server accepts connections, so that checking it is
+//! a single load and a mask when a query is dispatched
+
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+
+/// The most actions that can be told apart (one bit each)
+pub const MAX_ACTIONS: usize = 64;
+
+/// The set of disabled actions out of a fixed list of action names
+pub struct DisabledActions {
+    names: &'static [&'static [u8]],
+    mask: AtomicU64,
+}
+
+impl DisabledActions {
+    /// Create a set (with nothing disabled) over `names`, which can't be more than
+    /// [`MAX_ACTIONS`]
+    pub const fn new(names: &'static [&'static [u8]]) -> Self {
+        Self {
+            names,
+            mask: AtomicU64::new(0),
+        }
+    }
+    /// Returns the position of the action called `name` (the case is ignored)
+    fn index_of(&self, name: &[u8]) -> Option<usize> {
+        self.names
+            .iter()
+            .position(|action| action.eq_ignore_ascii_case(name))
+    }
+    /// Check that every one of `names` is an action, returning the first one that isn't
+    pub fn validate<'a>(&self, names: &'a [String]) -> Result<(), &'a str> {
+        match names
+            .iter()
+            .find(|name| self.index_of(name.as_bytes()).is_none())
+        {
+            Some(unknown) => Err(unknown.as_str()),
+            None => Ok(()),
+        }
+    }
+    /// Disable the actions in `names` (and enable every other one). If any of them isn't an
+    /// action, nothing is changed and that name is returned
+    pub fn disable<'a>(&self, names: &'a [String]) -> Result<(), &'a str> {
+        self.validate(names)?;
+        let mask = names
+            .iter()
+            .filter_map(|name| self.index_of(name.as_bytes()))
+            .fold(0u64, |mask, index| mask | (1 << index));
+        self.mask.store(mask, Ordering::Release);
+        Ok(())
+    }
+    /// Returns true if the action at `index` (in the list of names) is disabled
+    pub fn is_disabled(&self, index: usize) -> bool {
+        self.mask.load(Ordering::Relaxed) & (1 << index) != 0
+    }
+    /// Returns true if the action called `name` is disabled
+    pub fn is_disabled_named(&self, name: &[u8]) -> bool {
+        self.index_of(name)
+            .map_or(false, |index| self.is_disabled(index))
+    }
+    /// Returns the names of the disabled actions, in the order of the list of names
+    pub fn list(&self) -> Vec<&'static [u8]> {
+        let mask = self.mask.load(Ordering::Acquire);
+        self.names
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| mask & (1 << index) != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
@@ -24,43 +24,42 @@
  *
 */
 
-use super::ddl::{KEYSPACE, TABLE};
+use super::ddl::{DEFAULT, KEYSPACE, TABLE};
+use crate::admin::sys::write_pairs;
 use crate::corestore::memstore::ObjectID;
 use crate::dbnet::connection::prelude::*;
 
 const KEYSPACES: &[u8] = "KEYSPACES".as_bytes();
 action! {
     fn inspect(handle: &Corestore, con: &mut T, mut act: ActionIter) {
-        match act.next() {
-            Some(inspect_what) => {
-                let mut inspect_what = inspect_what.to_vec();
-                inspect_what.make_ascii_uppercase();
-                match inspect_what.as_ref() {
-                    KEYSPACE => inspect_keyspace(handle, con, act).await?,
-                    TABLE => inspect_table(handle, con, act).await?,
-                    KEYSPACES => {
-                        // let's return what all keyspaces exist
-                        let ks_list: Vec<ObjectID> = handle
-                            .get_store()
-                            .keyspaces
-                            .iter()
-                            .map(|kv| kv.key().clone())
-                            .collect();
-                        con.write_flat_array_length(ks_list.len()).await?;
-                        for tbl in ks_list {
-                            con.write_response(tbl).await?;
-                        }
-                    }
-                    _ => conwrite!(con, responses::groups::UNKNOWN_INSPECT_QUERY)?,
+        check_arity!(act, con, Arity::NonZero);
+        let mut inspect_what = next_or_err!(act, con).to_vec();
+        inspect_what.make_ascii_uppercase();
+        match inspect_what.as_ref() {
+            KEYSPACE => inspect_keyspace(handle, con, act).await?,
+            TABLE => inspect_table(handle, con, act).await?,
+            KEYSPACES => {
+                // let's return what all keyspaces exist, by their names as they were created
+                let ks_list: Vec<ObjectID> = handle
+                    .get_store()
+                    .keyspaces
+                    .iter()
+                    .map(|kv| kv.value().name().cloned().unwrap_or_else(|| kv.key().clone()))
+                    .collect();
+                con.write_flat_array_length(ks_list.len()).await?;
+                for tbl in ks_list {
+                    con.write_response(tbl).await?;
                 }
             }
-            None => aerr!(con, aerr),
+            _ => conwrite!(con, responses::groups::UNKNOWN_INSPECT_QUERY)?,
         }
         Ok(())
     }
 }
 
 action! {
+    /// Handle `INSPECT KEYSPACE <ksid>`, which returns the tables in the keyspace, and
+    /// `INSPECT KEYSPACE <ksid> DEFAULT`, which returns its default table (Nil if it has none)
     fn inspect_keyspace(handle: &Corestore, con: &mut T, mut act: ActionIter) {
         match act.next() {
             Some(keyspace_name) => {
@@ -73,10 +72,24 @@ action! {
                     Some(kspace) => kspace,
                     None => return conwrite!(con, responses::groups::CONTAINER_NOT_FOUND),
                 };
-                let tbl_list: Vec<ObjectID> = ks.tables.iter().map(|kv| kv.key().clone()).collect();
-                con.write_flat_array_length(tbl_list.len()).await?;
-                for tbl in tbl_list {
-                    con.write_response(tbl).await?;
+                match act.next() {
+                    None => {
+                        let tbl_list: Vec<ObjectID> =
+                            ks.tables.iter().map(|kv| ks.table_name(kv.key())).collect();
+                        con.write_flat_array_length(tbl_list.len()).await?;
+                        for tbl in tbl_list {
+                            con.write_response(tbl).await?;
+                        }
+                    }
+                    Some(what) if what.eq_ignore_ascii_case(DEFAULT) && act.len() == 0 => {
+                        let default_table = ks.default_table();
+                        if ks.tables.contains_key(&default_table) {
+                            con.write_response(ks.table_name(&default_table)).await?;
+                        } else {
+                            conwrite!(con, responses::groups::NIL)?;
+                        }
+                    }
+                    Some(_) => conwrite!(con, responses::groups::UNKNOWN_INSPECT_QUERY)?,
                 }
             },
             None => aerr!(con, aerr),
@@ -86,11 +99,15 @@ action! {
 }
 
 action! {
+    /// Handle `INSPECT TABLE <entity>`: this returns the
+    /// [properties](crate::corestore::table::Table::properties) of the table as a flat array
+    /// of `<name> <value>` pairs
     fn inspect_table(handle: &Corestore, con: &mut T, mut act: ActionIter) {
         match act.next() {
             Some(entity) => {
                 let entity = handle_entity!(con, entity);
-                conwrite!(con, get_tbl!(entity, handle, con).describe_self())?;
+                let properties = get_tbl!(entity, handle, con).properties();
+                write_pairs(con, &properties).await?;
             },
             None => aerr!(con, aerr),
         }
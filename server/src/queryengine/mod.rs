@@ -31,38 +31,98 @@ use crate::corestore::Corestore;
 use crate::dbnet::connection::prelude::*;
 use crate::protocol::responses;
 use crate::protocol::Element;
+use crate::registry::SlowStages;
 use crate::{actions, admin};
-use bytes::Bytes;
+use std::time::{Duration, Instant};
+pub mod actioniter;
 mod ddl;
+mod disabled;
 mod inspect;
+pub mod multi;
 pub mod parser;
+pub mod plan;
 #[cfg(test)]
 mod tests;
 
-use std::vec::IntoIter;
-pub type ActionIter = IntoIter<Bytes>;
+pub use actioniter::ActionIter;
+pub(crate) use ddl::{check_new_name, NameError};
+use disabled::DisabledActions;
+
+/// The actions that are disabled in the configuration
+static DISABLED: DisabledActions = DisabledActions::new(tags::ALL);
 
 macro_rules! gen_constants_and_matches {
-    ($con:ident, $buf:ident, $db:ident, $($action:ident => $fns:expr),*) => {
+    ($($action:ident => $fns:expr),*) => {
         mod tags {
             //! This module is a collection of tags/strings used for evaluating queries
             //! and responses
             $(
                 pub const $action: &[u8] = stringify!($action).as_bytes();
             )*
+            /// The names of all the actions
+            pub const ALL: &[&[u8]] = &[$($action),*];
+            /// The length of the longest action name
+            pub const LONGEST: usize = super::longest(ALL);
         }
-        let mut first = match $buf.next() {
-            Some(frst) => frst.to_vec(),
-            None => return $con.write_response(responses::groups::PACKET_ERR).await,
-        };
-        first.make_ascii_uppercase();
-        match first.as_ref() {
-            $(
-                tags::$action => $fns($db, $con, $buf).await?,
-            )*
-            _ => {
-                return $con.write_response(responses::groups::UNKNOWN_ACTION).await;
+        /// Returns the name of the action (as in [`tags`]) that `name` refers to. An exact
+        /// match is looked for first; only if that fails is the case folded (into `folded`,
+        /// so that this never allocates)
+        fn lookup<'a>(name: &'a [u8], folded: &'a mut [u8; tags::LONGEST]) -> Option<&'a [u8]> {
+            match name {
+                $(tags::$action)|* => Some(name),
+                _ => fold_action_name(name, folded).filter(|name| tags::ALL.contains(name)),
+            }
+        }
+        /// Run the action named by the first element of the query
+        async fn dispatch<T, Strm>(
+            db: &mut Corestore,
+            con: &mut T,
+            mut buf: ActionIter,
+        ) -> std::io::Result<()>
+        where
+            T: ProtocolConnectionExt<Strm>,
+            Strm: AsyncReadExt + AsyncWriteExt + Unpin + Send + Sync,
+        {
+            let first = match buf.next() {
+                Some(frst) => frst,
+                None => return con.write_response(responses::groups::PACKET_ERR).await,
+            };
+            let mut folded = [0u8; tags::LONGEST];
+            match lookup(&first, &mut folded) {
+                $(
+                    Some(tags::$action) => {
+                        const INDEX: usize = position(tags::ALL, tags::$action);
+                        const REPLANS: bool = plan::is_replanned_by(tags::$action);
+                        if DISABLED.is_disabled(INDEX) {
+                            return con.write_response(responses::groups::ACTION_DISABLED).await;
+                        }
+                        // these are only timed if the connection is traced
+                        if let Some(trace) = con.get_mut_trace() {
+                            trace.dispatched(tags::$action, buf.len());
+                        }
+                        let args = buf.len();
+                        let started = Instant::now();
+                        $fns(db, con, buf).await?;
+                        if REPLANS {
+                            // the queries after this one resolve the current table again
+                            con.get_mut_plan().invalidate();
+                        }
+                        let took = started.elapsed();
+                        db.stamp_activity();
+                        registry::get_metrics().record_action(INDEX, took);
+                        if let Some(trace) = con.get_mut_trace() {
+                            trace.executed();
+                        }
+                        if registry::get_slowlog().is_slow(took) {
+                            record_slow(db, con, tags::$action, args, took);
+                        }
+                    }
+                )*
+                _ => {
+                    return con.write_response(responses::groups::UNKNOWN_ACTION).await;
+                }
             }
+            Ok(())
         }
     };
 }
@@ -100,51 +160,202 @@ where
     T: ProtocolConnectionExt<Strm>,
     Strm: AsyncReadExt + AsyncWriteExt + Unpin + Send + Sync,
 {
+    let in_txn = matches!(con.get_mut_txn_state(), multi::TxnState::Queueing(_));
     let buf = match buf {
+        Element::FlatArray(a) if in_txn => return multi::queue(db, con, a).await,
         Element::FlatArray(a) => a,
+        Element::SwapKSHeader(_) if in_txn => {
+            // the queued queries have to run on the table they were queued for
+            return multi::abort_with(con, responses::groups::NOT_ALLOWED_IN_MULTI).await;
+        }
         Element::SwapKSHeader(swapks) => {
             swap_entity!(con, db, swapks);
+            con.get_mut_plan().invalidate();
             return Ok(());
         }
         _ => return con.write_response(responses::groups::WRONGTYPE_ERR).await,
     };
-    let mut buf = buf.into_iter();
-    gen_constants_and_matches!(
-        con, buf, db,
-        GET => actions::get::get,
-        SET => actions::set::set,
-        UPDATE => actions::update::update,
-        DEL => actions::del::del,
-        HEYA => actions::heya::heya,
-        EXISTS => actions::exists::exists,
-        MSET => actions::mset::mset,
-        MGET => actions::mget::mget,
-        MUPDATE => actions::mupdate::mupdate,
-        SSET => actions::strong::sset,
-        SDEL => actions::strong::sdel,
-        SUPDATE => actions::strong::supdate,
-        DBSIZE => actions::dbsize::dbsize,
-        FLUSHDB => actions::flushdb::flushdb,
-        USET => actions::uset::uset,
-        KEYLEN => actions::keylen::keylen,
-        MKSNAP => admin::mksnap::mksnap,
-        LSKEYS => actions::lskeys::lskeys,
-        POP => actions::pop::pop,
-        CREATE => ddl::create,
-        DROP => ddl::ddl_drop,
-        USE => self::entity_swap,
-        INSPECT => inspect::inspect
+    dispatch(db, con, ActionIter::new(buf)).await
+}
+
+gen_constants_and_matches!(
+    GET => actions::get::get,
+    SET => actions::set::set,
+    UPDATE => actions::update::update,
+    DEL => actions::del::del,
+    HEYA => actions::heya::heya,
+    HANDSHAKE => actions::handshake::handshake,
+    EXISTS => actions::exists::exists,
+    MSET => actions::mset::mset,
+    MGET => actions::mget::mget,
+    GETM => actions::mget::getm,
+    MUPDATE => actions::mupdate::mupdate,
+    SSET => actions::strong::sset,
+    SDEL => actions::strong::sdel,
+    SUPDATE => actions::strong::supdate,
+    DBSIZE => actions::dbsize::dbsize,
+    FLUSHDB => actions::flushdb::flushdb,
+    FLUSHKS => actions::flushdb::flushks,
+    USET => actions::uset::uset,
+    KEYLEN => actions::keylen::keylen,
+    MKSNAP => admin::mksnap::mksnap,
+    ATTACHSNAP => admin::attachsnap::attachsnap,
+    DETACHSNAP => admin::attachsnap::detachsnap,
+    SENDSNAP => admin::shipsnap::sendsnap,
+    RECVSNAP => admin::shipsnap::recvsnap,
+    SYS => admin::sys::sys,
+    PROTECT => admin::protect::protect,
+    UNPROTECT => admin::protect::unprotect,
+    LSKEYS => actions::lskeys::lskeys,
+    POP => actions::pop::pop,
+    GETDEL => actions::pop::getdel,
+    IFEQ => actions::ifeq::ifeq,
+    EXPIREPREFIX => actions::expire::expireprefix,
+    PERSISTPREFIX => actions::expire::persistprefix,
+    RANGEKEYS => actions::rangekeys::rangekeys,
+    DUMPKEY => actions::dump::dumpkey,
+    RESTOREKEY => actions::dump::restorekey,
+    OBJECT => actions::object::object,
+    VALIDATE => actions::validate::validate,
+    SYNCSTREAM => actions::syncstream::syncstream,
+    CREATE => ddl::create,
+    DROP => ddl::ddl_drop,
+    ALTER => ddl::alter,
+    ARCHIVE => ddl::archive,
+    UNARCHIVE => ddl::unarchive,
+    USE => self::entity_swap,
+    INSPECT => inspect::inspect,
+    MULTI => multi::multi,
+    EXEC => multi::not_in_multi,
+    DISCARD => multi::not_in_multi,
+    WATCH => multi::watch,
+    UNWATCH => multi::unwatch
+);
+
+/// Add a query whose action took `took` to the [slow query log](registry::SlowLog), along
+/// with the stages before the action if the connection is traced
+fn record_slow<T, Strm>(db: &Corestore, con: &mut T, action: &[u8], args: usize, took: Duration)
+where
+    T: ProtocolConnectionExt<Strm>,
+    Strm: AsyncReadExt + AsyncWriteExt + Unpin + Send + Sync,
+{
+    let stages = con
+        .get_mut_trace()
+        .as_ref()
+        .and_then(|trace| trace.before_action())
+        .map(|(parse, dispatch)| SlowStages { parse, dispatch });
+    registry::get_slowlog().record(
+        db.get_store().get_clock().now(),
+        con.get_peer().id,
+        action,
+        args,
+        took,
+        stages,
     );
-    Ok(())
+}
+
+/// Returns the length of the longest name in `names`
+const fn longest(names: &[&[u8]]) -> usize {
+    let (mut i, mut longest) = (0, 0);
+    while i < names.len() {
+        if names[i].len() > longest {
+            longest = names[i].len();
+        }
+        i += 1;
+    }
+    longest
+}
+
+/// Returns the position of `name` in `names` (or the length of `names` if it isn't there)
+const fn position(names: &[&[u8]], name: &[u8]) -> usize {
+    let mut i = 0;
+    while i < names.len() {
+        let candidate = names[i];
+        if candidate.len() == name.len() {
+            let mut j = 0;
+            while j < name.len() && candidate[j] == name[j] {
+                j += 1;
+            }
+            if j == name.len() {
+                return i;
+            }
+        }
+        i += 1;
+    }
+    i
+}
+
+/// Disable the actions in `names` (the case is ignored) for every query from here on. If any
+/// of them isn't an action, nothing is disabled and that name is returned
+pub fn disable_actions(names: &[String]) -> Result<(), &str> {
+    DISABLED.disable(names)
+}
+
+/// Check that every one of `names` is an action, returning the first one that isn't
+pub fn check_action_names(names: &[String]) -> Result<(), &str> {
+    DISABLED.validate(names)
+}
+
+/// Returns true if the action named `name` (in uppercase) is disabled
+pub fn is_disabled(name: &[u8]) -> bool {
+    DISABLED.is_disabled_named(name)
+}
+
+/// Returns the names of the disabled actions
+pub fn disabled_actions() -> Vec<&'static [u8]> {
+    DISABLED.list()
+}
+
+/// Returns the names of all the actions, in the order that their latency is recorded in (see
+/// [`registry::Metrics::record_action`])
+pub fn action_names() -> &'static [&'static [u8]] {
+    tags::ALL
+}
+
+/// Uppercase `name` into `folded`, returning `None` if it can't be the name of an action
+/// (because it's longer than every action name or isn't ASCII)
+fn fold_action_name<'a>(name: &[u8], folded: &'a mut [u8]) -> Option<&'a [u8]> {
+    if name.len() > folded.len() || !name.is_ascii() {
+        return None;
+    }
+    let folded = &mut folded[..name.len()];
+    folded.copy_from_slice(name);
+    folded.make_ascii_uppercase();
+    Some(folded)
+}
+
+/// Check that the action names are uppercase and that no two of them are the same once the
+/// case is folded, since the lookup couldn't tell them apart. This is run once at startup
+pub fn assert_action_names_are_unique() {
+    assert!(
+        tags::ALL.len() <= disabled::MAX_ACTIONS,
+        "there are more actions than can be disabled"
+    );
+    for (i, name) in tags::ALL.iter().enumerate() {
+        assert!(
+            !name.iter().any(u8::is_ascii_lowercase),
+            "action `{}` isn't uppercase",
+            String::from_utf8_lossy(name)
+        );
+        if let Some(other) = tags::ALL[i + 1..]
+            .iter()
+            .find(|other| other.eq_ignore_ascii_case(name))
+        {
+            panic!(
+                "actions `{}` and `{}` collide",
+                String::from_utf8_lossy(name),
+                String::from_utf8_lossy(other)
+            );
+        }
+    }
 }
 
 action! {
     /// Handle `use <entity>` like queries
     fn entity_swap(handle: &mut Corestore, con: &mut T, mut act: ActionIter) {
-        match act.next() {
-            Some(entity) => swap_entity!(con, handle, entity),
-            None => con.write_response(responses::groups::ACTION_ERR).await?,
-        }
+        check_arity!(act, con, Arity::Exactly(1));
+        let entity = next_or_err!(act, con);
+        swap_entity!(con, handle, entity);
         Ok(())
     }
 }
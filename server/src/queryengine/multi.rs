@@ -0,0 +1,368 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Transactions
+//!
+//! `MULTI` starts a transaction on a connection. The queries that follow are checked (that
+//! the action exists, may be used in a transaction and has a possible number of arguments)
+//! and queued instead of being run, and `EXEC` runs all of them back to back with the
+//! [write gate](crate::registry::WriteGate) closed, so that no other client's write lands in
+//! between. The responses are returned as an array. If any query fails to queue, the
+//! transaction is aborted and `EXEC` runs nothing. `DISCARD` drops the queue.
+//!
+//! A transaction can only queue so much (see [`TxnQuota`]): a query that would take it over
+//! its limits aborts it with `err-txn-too-large`
+//!
+//! `WATCH` makes a transaction optimistic: the keys that it watches (before `MULTI`) are
+//! checked by `EXEC` once it has closed the write gate, and if any of them was written since
+//! it was watched (by anyone, this connection included), `EXEC` runs nothing and returns
+//! `err-watched-changed`. `EXEC`, `DISCARD` and `UNWATCH` stop watching the keys, and so does
+//! closing the connection
+
+use super::{dispatch, lookup, tags, DISABLED};
+use crate::corestore::htable::Data;
+use crate::corestore::table::Table;
+use crate::dbnet::connection::prelude::*;
+use crate::kvengine::KeyVersion;
+use crate::registry::TxnQuota;
+use crate::resp::Writable;
+use bytes::Bytes;
+use core::mem;
+use core::sync::atomic::Ordering;
+use std::sync::Arc;
+
+/// The state of the transaction on a connection
+#[derive(Debug)]
+pub enum TxnState {
+    /// Not in a transaction
+    Idle,
+    /// Between `MULTI` and `EXEC` (or `DISCARD`)
+    Queueing(Transaction),
+    /// `EXEC` is running the queued queries
+    Executing,
+}
+
+impl Default for TxnState {
+    fn default() -> Self {
+        Self::Idle
+    }
+}
+
+impl TxnState {
+    /// Returns true if the queries in a transaction are being run. These don't pass through
+    /// the write gate, since `EXEC` has closed it
+    pub fn is_executing(&self) -> bool {
+        matches!(self, Self::Executing)
+    }
+}
+
+/// The queries queued in a transaction
+#[derive(Debug)]
+pub struct Transaction {
+    /// the queued queries (with the action name), copied out of the read buffer so that it
+    /// isn't held on to while the transaction is open
+    queued: Vec<Vec<Bytes>>,
+    /// the queries and the bytes that are reserved from the quota, until the transaction is
+    /// dropped
+    reserved: (usize, usize),
+    /// set if a query failed to queue
+    aborted: bool,
+    quota: &'static TxnQuota,
+}
+
+impl Transaction {
+    pub(super) fn new(quota: &'static TxnQuota) -> Self {
+        Self {
+            queued: Vec::new(),
+            reserved: (0, 0),
+            aborted: false,
+            quota,
+        }
+    }
+    /// Queue `query`, unless that would take this transaction (or all of them) over their
+    /// limits. Returns false if the query wasn't queued
+    pub(super) fn push(&mut self, query: Vec<Bytes>) -> bool {
+        let bytes = query.iter().map(|arg| arg.len()).sum();
+        let (queued, queued_bytes) = self.reserved;
+        if !self.quota.reserve(queued, queued_bytes, bytes) {
+            return false;
+        }
+        self.reserved = (queued + 1, queued_bytes + bytes);
+        self.queued.push(query);
+        true
+    }
+    /// Abort the transaction, so that `EXEC` runs nothing. The queued queries are dropped
+    /// right away
+    pub(super) fn abort(&mut self) {
+        self.aborted = true;
+        self.queued = Vec::new();
+        let (queued, bytes) = mem::take(&mut self.reserved);
+        self.quota.release(queued, bytes);
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        let (queued, bytes) = self.reserved;
+        self.quota.release(queued, bytes);
+    }
+}
+
+/// A key that a connection is watching
+struct Watch {
+    table: Arc<Table>,
+    key: Data,
+    version: KeyVersion,
+    /// the version of the key when it was watched
+    seen: u64,
+}
+
+/// The keys that a connection is watching. Dropping this (along with the connection) stops
+/// watching them
+#[derive(Default)]
+pub struct Watches(Vec<Watch>);
+
+impl Watches {
+    /// Watch `key` of `table`
+    fn watch(&mut self, table: Arc<Table>, key: Data) {
+        let version = match table.loaded_kvstore() {
+            Some(kve) => kve.watch(key.clone()),
+            None => return,
+        };
+        let seen = version.load(Ordering::SeqCst);
+        self.0.push(Watch {
+            table,
+            key,
+            version,
+            seen,
+        });
+    }
+    /// Returns true if none of the watched keys were written since they were watched
+    fn unchanged(&self) -> bool {
+        self.0
+            .iter()
+            .all(|watch| watch.version.load(Ordering::SeqCst) == watch.seen)
+    }
+    /// Stop watching every key
+    pub fn clear(&mut self) {
+        for watch in self.0.drain(..) {
+            // a table that was read in stays in memory
+            if let Some(kve) = watch.table.loaded_kvstore() {
+                kve.unwatch(&watch.key, watch.version);
+            }
+        }
+    }
+}
+
+impl Drop for Watches {
+    fn drop(&mut self) {
+        self.clear()
+    }
+}
+
+/// Returns the arity that a query for `action` is checked against when it is queued, or
+/// `None` if the action can't be used in a transaction. For the actions that take an optional
+/// flag, this is the loosest arity; the action still checks its arguments when it is run
+fn queued_arity(action: &[u8]) -> Option<Arity> {
+    let arity = match action {
+        tags::GET | tags::KEYLEN | tags::GETDEL | tags::PERSISTPREFIX | tags::DUMPKEY => {
+            Arity::Exactly(1)
+        }
+        tags::UPDATE | tags::EXPIREPREFIX => Arity::Exactly(2),
+        tags::DEL | tags::EXISTS | tags::MGET | tags::SDEL | tags::POP | tags::OBJECT => {
+            Arity::NonZero
+        }
+        tags::SSET | tags::SUPDATE | tags::USET => Arity::Even(2),
+        // these may come with a `STRICT` flag
+        tags::MSET | tags::MUPDATE => Arity::AtLeast(2),
+        // and this with `NX`, `XX` or `EX <seconds>`
+        tags::SET => Arity::AtLeast(2),
+        // and this ends with `WITHMISSING`
+        tags::GETM => Arity::AtLeast(2),
+        tags::HEYA => Arity::AtLeast(0),
+        tags::DBSIZE => Arity::AtMost(1),
+        tags::FLUSHDB => Arity::AtMost(2),
+        tags::LSKEYS => Arity::AtMost(4),
+        tags::RANGEKEYS => Arity::Between(2, 4),
+        tags::RESTOREKEY => Arity::Between(2, 3),
+        tags::IFEQ => Arity::Between(3, 4),
+        tags::VALIDATE => Arity::Between(2, 4),
+        // anything that changes the connection, the keyspaces or the server itself can't wait
+        // for `EXEC`
+        _ => return None,
+    };
+    Some(arity)
+}
+
+action!(
+    /// Handle `MULTI`: this starts a transaction on the connection
+    fn multi(_handle: &Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        *con.get_mut_txn_state() = TxnState::Queueing(Transaction::new(registry::get_txn_quota()));
+        conwrite!(con, groups::OKAY)
+    }
+);
+
+action!(
+    /// Handle `WATCH <key> ...`: this watches the keys of the current table, so that the
+    /// `EXEC` of the next transaction runs nothing if any of them is written in the meantime
+    fn watch(handle: &Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::NonZero);
+        // only a key-value table has keys to watch
+        let _ = kve!(con, handle);
+        let table = match handle.get_ctable() {
+            Some(table) => table,
+            None => return conwrite!(con, groups::DEFAULT_UNSET),
+        };
+        let watches = con.get_mut_watches();
+        for key in act {
+            watches.watch(table.clone(), Data::copy_from_slice(&key));
+        }
+        conwrite!(con, groups::OKAY)
+    }
+);
+
+action!(
+    /// Handle `UNWATCH`: this stops watching every key that the connection watches
+    fn unwatch(_handle: &Corestore, con: &mut T, act: ActionIter) {
+        check_arity!(act, con, Arity::Exactly(0));
+        con.get_mut_watches().clear();
+        conwrite!(con, groups::OKAY)
+    }
+);
+
+action!(
+    /// Handle an `EXEC` or a `DISCARD` outside a transaction
+    fn not_in_multi(_handle: &Corestore, con: &mut T, _act: ActionIter) {
+        conwrite!(con, groups::NOT_IN_MULTI)
+    }
+);
+
+action!(
+    /// Handle a query on a connection with an open transaction: `EXEC` and `DISCARD` end the
+    /// transaction and anything else is queued
+    fn queue(db: &mut Corestore, con: &mut T, query: Vec<Bytes>) {
+        let mut folded = [0u8; tags::LONGEST];
+        let action = match query.first() {
+            Some(name) => lookup(name, &mut folded),
+            None => return conwrite!(con, groups::PACKET_ERR),
+        };
+        let args = query.len() - 1;
+        match action {
+            Some(tags::EXEC) | Some(tags::DISCARD) if args != 0 => {
+                conwrite!(con, Arity::Exactly(0).error())
+            }
+            Some(tags::EXEC) => exec(db, con).await,
+            Some(tags::DISCARD) => {
+                *con.get_mut_txn_state() = TxnState::Idle;
+                con.get_mut_watches().clear();
+                con.get_client().set_queued(0, 0);
+                conwrite!(con, groups::OKAY)
+            }
+            // this doesn't abort the transaction that is already open
+            Some(tags::MULTI) => conwrite!(con, groups::NESTED_MULTI),
+            Some(action) if DISABLED.is_disabled_named(action) => {
+                abort_with(con, groups::ACTION_DISABLED).await
+            }
+            Some(action) => match queued_arity(action) {
+                Some(arity) if arity.allows(args) => {
+                    let query = query.iter().map(|arg| Bytes::copy_from_slice(arg));
+                    let reserved = match con.get_mut_txn_state() {
+                        TxnState::Queueing(txn) => {
+                            if txn.push(query.collect()) {
+                                Some(txn.reserved)
+                            } else {
+                                None
+                            }
+                        }
+                        _ => None,
+                    };
+                    match reserved {
+                        Some((queued, bytes)) => {
+                            con.get_client().set_queued(queued, bytes);
+                            conwrite!(con, groups::QUEUED)
+                        }
+                        None => abort_with(con, groups::TXN_TOO_LARGE).await,
+                    }
+                }
+                Some(arity) => abort_with(con, arity.error()).await,
+                None => abort_with(con, groups::NOT_ALLOWED_IN_MULTI).await,
+            },
+            None => abort_with(con, groups::UNKNOWN_ACTION).await,
+        }
+    }
+);
+
+action!(
+    /// Abort the open transaction, so that `EXEC` runs nothing, and write out `error`
+    fn abort_with(con: &mut T, error: impl Writable + Send + 'static) {
+        if let TxnState::Queueing(txn) = con.get_mut_txn_state() {
+            txn.abort();
+        }
+        con.get_client().set_queued(0, 0);
+        conwrite!(con, error)
+    }
+);
+
+action!(
+    /// Run the queries of the open transaction, returning their responses as an array, unless
+    /// a watched key was written since it was watched
+    fn exec(db: &mut Corestore, con: &mut T) {
+        let mut txn = match mem::take(con.get_mut_txn_state()) {
+            TxnState::Queueing(txn) => txn,
+            _ => return conwrite!(con, groups::NOT_IN_MULTI),
+        };
+        con.get_client().set_queued(0, 0);
+        if txn.aborted {
+            con.get_mut_watches().clear();
+            return conwrite!(con, groups::EXEC_ABORTED);
+        }
+        // hold the other writers out until every query has run. The writes that got in before
+        // have all bumped the versions of the keys they wrote by now
+        let _closed = registry::get_write_gate().close().await;
+        let unchanged = con.get_mut_watches().unchanged();
+        con.get_mut_watches().clear();
+        if !unchanged {
+            return conwrite!(con, groups::WATCHED_CHANGED);
+        }
+        *con.get_mut_txn_state() = TxnState::Executing;
+        // the quota is only given back (when `txn` is dropped) once the queries have run
+        let ran = run_queued(db, con, mem::take(&mut txn.queued)).await;
+        *con.get_mut_txn_state() = TxnState::Idle;
+        ran
+    }
+);
+
+action!(
+    /// Run `queued` one after the other, writing out an array with their responses
+    fn run_queued(db: &mut Corestore, con: &mut T, queued: Vec<Vec<Bytes>>) {
+        con.write_array_length(queued.len()).await?;
+        for query in queued {
+            dispatch(db, con, ActionIter::new(query)).await?;
+        }
+        Ok(())
+    }
+);
@@ -31,7 +31,6 @@ use crate::protocol::responses;
 use crate::queryengine::ActionIter;
 use crate::util::compiler;
 use crate::util::compiler::cold_err;
-use crate::util::Unwrappable;
 use core::str;
 use regex::Regex;
 
@@ -39,14 +38,23 @@ const KEYMAP: &[u8] = "keymap".as_bytes();
 const BINSTR: &[u8] = "binstr".as_bytes();
 const STR: &[u8] = "str".as_bytes();
 
+/// What the names of keyspaces and tables (and the model types) have to look like. This is
+/// wider than what a new keyspace or table can be named (see `ddl::check_new_name`), so that
+/// the containers created under the older rules can still be used
 pub(super) static VALID_CONTAINER_NAME: Lazy<Regex, fn() -> Regex> =
-    Lazy::new(|| Regex::new("^[a-zA-Z_$][a-zA-Z_$0-9]*$").unwrap());
+    Lazy::new(|| Regex::new("^[a-zA-Z_$][a-zA-Z_$0-9-]*$").unwrap());
 
-pub(super) fn parse_table_args(
+/// Returns true if `name` can be the name of a keyspace or a table (see
+/// [`VALID_CONTAINER_NAME`])
+pub(crate) fn is_valid_container_name(name: &str) -> bool {
+    VALID_CONTAINER_NAME.is_match(name)
+}
+
+pub(crate) fn parse_table_args(
     act: &mut ActionIter,
 ) -> Result<(OwnedEntityGroup, u8), &'static [u8]> {
-    let table_name = unsafe { act.next().unsafe_unwrap() };
-    let model_name = unsafe { act.next().unsafe_unwrap() };
+    let table_name = act.next_or_err()?;
+    let model_name = act.next_or_err()?;
     if compiler::unlikely(!encoding::is_utf8(&table_name) || !encoding::is_utf8(&model_name)) {
         return Err(responses::groups::ENCODING_ERROR);
     }
@@ -0,0 +1,104 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Batch plans
+//!
+//! A client that pipelines its queries sends many of them in one go, and these are then
+//! parsed from the same read off the socket, one after the other. Such a _batch_ mostly runs
+//! on the same table, so what the first query of the batch resolved for the current table is
+//! kept in the connection's [`BatchPlan`] and reused by the queries after it. The plan is
+//! thrown away before the connection reads from the socket again, and after every query that
+//! may change which table the connection is on (see [`REPLANNED_BY`]), so the queries that
+//! follow such a query in the same batch resolve the table again.
+//!
+//! Only what can't change for a table is kept: once its data is read in, it stays in memory
+//! and its model never changes. Whether the table is archived is still checked for every
+//! query since any connection can archive it at any time, and so is the system state: it's a
+//! single load either way, and a query that went by the state at the start of the batch
+//! would write to a table after the system was poisoned
+
+use super::tags;
+
+/// The actions after which the plan is thrown away, since they can change which table the
+/// connection is on (`USE`) or what that table is (the DDL queries and the snapshots that
+/// replace tables)
+pub const REPLANNED_BY: &[&[u8]] = &[
+    tags::USE,
+    tags::CREATE,
+    tags::DROP,
+    tags::ALTER,
+    tags::ARCHIVE,
+    tags::UNARCHIVE,
+    tags::ATTACHSNAP,
+    tags::DETACHSNAP,
+    tags::RECVSNAP,
+];
+
+/// What the queries of a batch have resolved so far (see the [module docs](self))
+#[derive(Debug, Default)]
+pub struct BatchPlan {
+    /// the current table was found to be a key/value table whose data is in memory
+    kvstore: bool,
+}
+
+impl BatchPlan {
+    /// Returns a plan that has nothing resolved
+    pub const fn new() -> Self {
+        Self { kvstore: false }
+    }
+    /// Returns true if an earlier query of the batch found the current table to be a
+    /// key/value table whose data is in memory
+    pub const fn has_kvstore(&self) -> bool {
+        self.kvstore
+    }
+    /// Keep that the current table is a key/value table whose data is in memory
+    pub fn resolved_kvstore(&mut self) {
+        self.kvstore = true;
+    }
+    /// Throw away what was resolved, because the batch is over or because the current table
+    /// may have changed
+    pub fn invalidate(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Returns true if the plan has to be thrown away after a query runs `action` (as named in
+/// [`tags`])
+pub const fn is_replanned_by(action: &[u8]) -> bool {
+    super::position(REPLANNED_BY, action) < REPLANNED_BY.len()
+}
+
+#[test]
+fn test_replanned_by() {
+    for action in REPLANNED_BY {
+        assert!(tags::ALL.contains(action));
+        assert!(is_replanned_by(action));
+    }
+    // these run on the current table, but never change it
+    for action in [tags::GET, tags::SET, tags::FLUSHDB, tags::EXEC].iter() {
+        assert!(!is_replanned_by(action));
+    }
+}
@@ -33,28 +33,28 @@ mod parser_ddl_tests {
     #[test]
     fn test_table_args_valid() {
         // create table [mytbl keymap(str, str)]
-        let mut it = vec![byt!("mytbl"), byt!("keymap(binstr,binstr)")].into_iter();
+        let mut it = bi!("mytbl", "keymap(binstr,binstr)");
         let (tbl_name, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tbl_name, unsafe {
             (Some(ObjectID::from_slice("mytbl")), None)
         });
         assert_eq!(mcode, 0);
 
-        let mut it = vec![byt!("mytbl"), byt!("keymap(binstr,str)")].into_iter();
+        let mut it = bi!("mytbl", "keymap(binstr,str)");
         let (tbl_name, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tbl_name, unsafe {
             (Some(ObjectID::from_slice("mytbl")), None)
         });
         assert_eq!(mcode, 1);
 
-        let mut it = vec![byt!("mytbl"), byt!("keymap(str,str)")].into_iter();
+        let mut it = bi!("mytbl", "keymap(str,str)");
         let (tbl_name, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tbl_name, unsafe {
             (Some(ObjectID::from_slice("mytbl")), None)
         });
         assert_eq!(mcode, 2);
 
-        let mut it = vec![byt!("mytbl"), byt!("keymap(str,binstr)")].into_iter();
+        let mut it = bi!("mytbl", "keymap(str,binstr)");
         let (tbl_name, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tbl_name, unsafe {
             (Some(ObjectID::from_slice("mytbl")), None)
@@ -63,12 +63,12 @@ mod parser_ddl_tests {
     }
     #[test]
     fn test_table_bad_ident() {
-        let mut it = vec![byt!("1one"), byt!("keymap(binstr,binstr)")].into_iter();
+        let mut it = bi!("1one", "keymap(binstr,binstr)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::BAD_EXPRESSION
         );
-        let mut it = vec![byt!("%whywouldsomeone"), byt!("keymap(binstr,binstr)")].into_iter();
+        let mut it = bi!("%whywouldsomeone", "keymap(binstr,binstr)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::BAD_EXPRESSION
@@ -76,28 +76,28 @@ mod parser_ddl_tests {
     }
     #[test]
     fn test_table_whitespaced_datatypes() {
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(binstr, binstr)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(binstr, binstr)");
         let (tblid, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tblid, unsafe {
             (Some(ObjectID::from_slice("mycooltbl")), None)
         });
         assert_eq!(mcode, 0);
 
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(binstr, str)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(binstr, str)");
         let (tblid, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tblid, unsafe {
             (Some(ObjectID::from_slice("mycooltbl")), None)
         });
         assert_eq!(mcode, 1);
 
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(str, str)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(str, str)");
         let (tblid, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tblid, unsafe {
             (Some(ObjectID::from_slice("mycooltbl")), None)
         });
         assert_eq!(mcode, 2);
 
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(str, binstr)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(str, binstr)");
         let (tblid, mcode) = parse_table_args(&mut it).unwrap();
         assert_eq!(tblid, unsafe {
             (Some(ObjectID::from_slice("mycooltbl")), None)
@@ -107,22 +107,22 @@ mod parser_ddl_tests {
 
     #[test]
     fn test_table_badty() {
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(wth, str)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(wth, str)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::UNKNOWN_DATA_TYPE
         );
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(wth, wth)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(wth, wth)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::UNKNOWN_DATA_TYPE
         );
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(str, wth)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(str, wth)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::UNKNOWN_DATA_TYPE
         );
-        let mut it = vec![byt!("mycooltbl"), byt!("keymap(wth1, wth2)")].into_iter();
+        let mut it = bi!("mycooltbl", "keymap(wth1, wth2)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::UNKNOWN_DATA_TYPE
@@ -130,17 +130,17 @@ mod parser_ddl_tests {
     }
     #[test]
     fn test_table_bad_model() {
-        let mut it = vec![byt!("mycooltbl"), byt!("wthmap(wth, wth)")].into_iter();
+        let mut it = bi!("mycooltbl", "wthmap(wth, wth)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::UNKNOWN_MODEL
         );
-        let mut it = vec![byt!("mycooltbl"), byt!("wthmap(str, str)")].into_iter();
+        let mut it = bi!("mycooltbl", "wthmap(str, str)");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::UNKNOWN_MODEL
         );
-        let mut it = vec![byt!("mycooltbl"), byt!("wthmap()")].into_iter();
+        let mut it = bi!("mycooltbl", "wthmap()");
         assert_eq!(
             parse_table_args(&mut it).unwrap_err(),
             responses::groups::UNKNOWN_MODEL
@@ -316,3 +316,331 @@ mod entity_parser_tests {
         );
     }
 }
+
+mod name_check_tests {
+    use crate::queryengine::ddl::{check_new_name, NameError};
+    #[test]
+    fn test_new_name_okay() {
+        assert!(check_new_name(b"mytbl").is_ok());
+        assert!(check_new_name(b"my-tbl_2").is_ok());
+        assert!(check_new_name(b"systemd").is_ok());
+        assert!(check_new_name(&[b'a'; 64]).is_ok());
+    }
+    #[test]
+    fn test_new_name_reserved() {
+        assert_eq!(check_new_name(b"system"), Err(NameError::Reserved));
+        assert_eq!(check_new_name(b"DEFAULT"), Err(NameError::Reserved));
+        assert_eq!(check_new_name(b"_mytbl"), Err(NameError::Reserved));
+    }
+    #[test]
+    fn test_new_name_too_long() {
+        assert_eq!(check_new_name(&[b'a'; 65]), Err(NameError::TooLong(64)));
+    }
+    #[test]
+    fn test_new_name_bad_character() {
+        assert_eq!(check_new_name(b"my$tbl"), Err(NameError::BadCharacter));
+        assert_eq!(check_new_name(b"mytbl."), Err(NameError::BadCharacter));
+        assert_eq!(check_new_name(b"my tbl"), Err(NameError::BadCharacter));
+        assert_eq!(check_new_name(b"2021"), Err(NameError::BadCharacter));
+        assert_eq!(check_new_name(b"-mytbl"), Err(NameError::BadCharacter));
+        assert_eq!(
+            check_new_name("täble".as_bytes()),
+            Err(NameError::BadCharacter)
+        );
+    }
+    #[test]
+    fn test_new_name_error_names_the_rule() {
+        assert_eq!(
+            NameError::Reserved.error(),
+            b"!27\nbad-container-name:reserved\n".to_vec()
+        );
+        assert_eq!(
+            NameError::TooLong(64).error(),
+            b"!33\nbad-container-name:longer-than-64\n".to_vec()
+        );
+        assert_eq!(
+            NameError::BadCharacter.error(),
+            b"!32\nbad-container-name:bad-character\n".to_vec()
+        );
+    }
+}
+
+mod actioniter_tests {
+    use super::super::actioniter::OptionError;
+    use crate::protocol::responses;
+    #[test]
+    fn test_peek_does_not_consume() {
+        let mut it = bi!("k1", "k2");
+        assert_eq!(it.peek().unwrap(), &byt!("k1"));
+        assert_eq!(it.len(), 2);
+        assert_eq!(it.next().unwrap(), byt!("k1"));
+        assert_eq!(it.peek().unwrap(), &byt!("k2"));
+        it.next();
+        assert!(it.peek().is_none());
+    }
+    #[test]
+    fn test_next_or_err() {
+        let mut it = bi!("k1");
+        assert_eq!(it.next_or_err().unwrap(), byt!("k1"));
+        assert_eq!(it.next_or_err().unwrap_err(), responses::groups::ACTION_ERR);
+    }
+    #[test]
+    fn test_take_exact() {
+        let mut it = bi!("sub", "a", "b", "c", "tail");
+        it.next();
+        {
+            let mut sub = it.take_exact(3).unwrap();
+            assert_eq!(sub.len(), 3);
+            assert_eq!(sub.as_slice(), &[byt!("a"), byt!("b"), byt!("c")]);
+            assert_eq!(sub.next().unwrap(), byt!("a"));
+            assert_eq!(sub.len(), 2);
+            assert_eq!(sub.peek().unwrap(), &byt!("b"));
+        }
+        // the unconsumed elements of the sub-iterator are skipped
+        assert_eq!(it.len(), 1);
+        assert_eq!(it.next().unwrap(), byt!("tail"));
+    }
+    #[test]
+    fn test_take_exact_too_many() {
+        let mut it = bi!("a", "b");
+        assert!(it.take_exact(3).is_none());
+        assert_eq!(it.len(), 2);
+        let sub = it.take_exact(2).unwrap();
+        assert_eq!(sub.collect::<Vec<_>>(), vec![byt!("a"), byt!("b")]);
+        assert!(it.next().is_none());
+    }
+    #[test]
+    fn test_parse_options_okay() {
+        let mut it = bi!("key", "count", "10", "MATCH", "user:*");
+        it.next();
+        let opts = it.parse_options(&[b"MATCH", b"COUNT"]).unwrap();
+        assert_eq!(opts.len(), 2);
+        assert_eq!(opts.get(b"count").unwrap(), &byt!("10"));
+        assert_eq!(opts.get(b"match").unwrap(), &byt!("user:*"));
+        assert!(it.next().is_none());
+    }
+    #[test]
+    fn test_parse_options_empty() {
+        let mut it = bi!("key");
+        it.next();
+        let opts = it.parse_options(&[b"COUNT"]).unwrap();
+        assert!(opts.is_empty());
+        assert!(opts.get(b"COUNT").is_none());
+    }
+    #[test]
+    fn test_parse_options_unknown_keyword() {
+        let mut it = bi!("count", "10", "limit", "2");
+        assert_eq!(
+            it.parse_options(&[b"COUNT"]).unwrap_err(),
+            OptionError::UnknownKeyword
+        );
+    }
+    #[test]
+    fn test_parse_options_duplicate_keyword() {
+        let mut it = bi!("count", "10", "COUNT", "2");
+        let e = it.parse_options(&[b"COUNT"]).unwrap_err();
+        assert_eq!(e, OptionError::DuplicateKeyword);
+        assert_eq!(e.response(), responses::groups::DUPLICATE_OPTION);
+    }
+    #[test]
+    fn test_parse_options_missing_value() {
+        let mut it = bi!("match", "user:*", "count");
+        let e = it.parse_options(&[b"MATCH", b"COUNT"]).unwrap_err();
+        assert_eq!(e, OptionError::MissingValue);
+        assert_eq!(e.response(), responses::groups::ACTION_ERR);
+    }
+    #[test]
+    fn test_parse_options_and_flags() {
+        let mut it = bi!("key", "value", "nx", "EX", "30");
+        it.next();
+        it.next();
+        let opts = it
+            .parse_options_and_flags(&[b"EX"], &[b"NX", b"XX"])
+            .unwrap();
+        assert_eq!(opts.len(), 2);
+        assert!(opts.has(b"NX"));
+        assert!(!opts.has(b"XX"));
+        assert_eq!(opts.get(b"ex").unwrap(), &byt!("30"));
+        // a flag doesn't take the next argument as its value
+        let mut it = bi!("EX", "30", "NX");
+        let opts = it.parse_options_and_flags(&[b"EX"], &[b"NX"]).unwrap();
+        assert!(opts.has(b"NX"));
+        let mut it = bi!("NX", "nx");
+        assert_eq!(
+            it.parse_options_and_flags(&[b"EX"], &[b"NX"]).unwrap_err(),
+            OptionError::DuplicateKeyword
+        );
+        let mut it = bi!("NX", "30");
+        assert_eq!(
+            it.parse_options_and_flags(&[b"EX"], &[b"NX"]).unwrap_err(),
+            OptionError::UnknownKeyword
+        );
+    }
+}
+
+mod action_lookup_tests {
+    use super::super::{assert_action_names_are_unique, lookup, tags};
+    fn lookup_of(name: &[u8]) -> Option<Vec<u8>> {
+        let mut folded = [0u8; tags::LONGEST];
+        lookup(name, &mut folded).map(|name| name.to_vec())
+    }
+    #[test]
+    fn test_mixed_case_action_names() {
+        for (name, action) in [
+            ("get", tags::GET),
+            ("Set", tags::SET),
+            ("mGeT", tags::MGET),
+            ("flushDB", tags::FLUSHDB),
+            ("FlushKs", tags::FLUSHKS),
+            ("syncstream", tags::SYNCSTREAM),
+            ("Inspect", tags::INSPECT),
+            ("use", tags::USE),
+        ]
+        .iter()
+        {
+            assert_eq!(lookup_of(name.as_bytes()).unwrap(), *action);
+        }
+    }
+    #[test]
+    fn test_unknown_action_names() {
+        // non-ASCII bytes never match, even if they fold to something similar
+        assert!(lookup_of("gét".as_bytes()).is_none());
+        assert!(lookup_of(b"GET\xFF").is_none());
+        // longer than any action
+        assert!(lookup_of(b"syncstreamsyncstream").is_none());
+        assert!(lookup_of(b"").is_none());
+        assert!(lookup_of(b"gett").is_none());
+    }
+    #[test]
+    fn test_action_names_are_unique() {
+        assert_action_names_are_unique();
+    }
+    #[test]
+    #[cfg(not(target_env = "msvc"))]
+    fn test_lookup_does_not_allocate() {
+        use crate::util::alloc;
+        let lowercase: Vec<Vec<u8>> = tags::ALL
+            .iter()
+            .map(|name| name.to_ascii_lowercase())
+            .collect();
+        let mut folded = [0u8; tags::LONGEST];
+        let before = alloc::allocations();
+        for _ in 0..1000 {
+            for name in tags::ALL {
+                // the exact match
+                assert_eq!(lookup(name, &mut folded), Some(*name));
+            }
+            for name in lowercase.iter() {
+                // and the folded one
+                assert!(lookup(name, &mut folded).is_some());
+            }
+        }
+        assert_eq!(alloc::allocations(), before);
+    }
+}
+
+mod disabled_action_tests {
+    use super::super::disabled::DisabledActions;
+    use super::super::{check_action_names, tags};
+    const NAMES: &[&[u8]] = &[b"GET", b"SET", b"FLUSHDB", b"DEL"];
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| (*name).to_owned()).collect()
+    }
+    #[test]
+    fn test_disable_actions() {
+        let disabled = DisabledActions::new(NAMES);
+        assert!(disabled.list().is_empty());
+        disabled.disable(&names(&["flushdb", "Del"])).unwrap();
+        assert!(disabled.is_disabled(2));
+        assert!(disabled.is_disabled(3));
+        // the others still work
+        assert!(!disabled.is_disabled(0));
+        assert!(!disabled.is_disabled(1));
+        assert!(disabled.is_disabled_named(b"FLUSHDB"));
+        assert!(!disabled.is_disabled_named(b"GET"));
+        assert_eq!(disabled.list(), vec![&b"FLUSHDB"[..], &b"DEL"[..]]);
+        // disabling again replaces the set
+        disabled.disable(&names(&["get"])).unwrap();
+        assert_eq!(disabled.list(), vec![&b"GET"[..]]);
+    }
+    #[test]
+    fn test_disable_unknown_action() {
+        let disabled = DisabledActions::new(NAMES);
+        disabled.disable(&names(&["set"])).unwrap();
+        assert_eq!(
+            disabled.disable(&names(&["del", "flushbd"])),
+            Err("flushbd")
+        );
+        // nothing changed
+        assert_eq!(disabled.list(), vec![&b"SET"[..]]);
+    }
+    #[test]
+    fn test_check_action_names() {
+        assert!(check_action_names(&names(&["flushdb", "sys", "MULTI"])).is_ok());
+        assert_eq!(check_action_names(&names(&["tabledump"])), Err("tabledump"));
+        assert!(tags::ALL.len() <= super::super::disabled::MAX_ACTIONS);
+    }
+}
+
+mod txn_quota_tests {
+    use crate::queryengine::multi::Transaction;
+    use crate::registry::TxnQuota;
+    use bytes::Bytes;
+
+    /// A quota of its own for the test, so that the other tests' transactions don't count
+    fn quota(max_queued: usize, max_bytes: usize, total: usize) -> &'static TxnQuota {
+        let quota = Box::leak(Box::new(TxnQuota::new()));
+        quota.configure(max_queued, max_bytes, total, total * max_bytes);
+        quota
+    }
+    fn set(key: &'static str) -> Vec<Bytes> {
+        // 3 + 1 + 5 bytes
+        vec![
+            Bytes::from_static(b"SET"),
+            Bytes::from_static(key.as_bytes()),
+            Bytes::from_static(b"value"),
+        ]
+    }
+    #[test]
+    fn test_txn_too_many_queries() {
+        let quota = quota(2, 1000, 10);
+        let mut txn = Transaction::new(quota);
+        assert!(txn.push(set("a")));
+        assert!(txn.push(set("b")));
+        assert!(!txn.push(set("c")));
+        assert_eq!(quota.total(), (2, 18));
+        // EXEC and DISCARD drop the transaction, and so does closing the connection
+        drop(txn);
+        assert_eq!(quota.total(), (0, 0));
+    }
+    #[test]
+    fn test_txn_too_many_bytes() {
+        let quota = quota(10, 20, 10);
+        let mut txn = Transaction::new(quota);
+        assert!(txn.push(set("a")));
+        assert!(txn.push(set("b")));
+        assert!(!txn.push(set("c")));
+        assert_eq!(quota.total(), (2, 18));
+        // the abort frees the quota right away
+        txn.abort();
+        assert_eq!(quota.total(), (0, 0));
+        drop(txn);
+        assert_eq!(quota.total(), (0, 0));
+    }
+    #[test]
+    fn test_txn_global_quota() {
+        let quota = quota(10, 9, 2);
+        let (mut first, mut second, mut third) = (
+            Transaction::new(quota),
+            Transaction::new(quota),
+            Transaction::new(quota),
+        );
+        assert!(first.push(set("a")));
+        assert!(second.push(set("b")));
+        // every transaction is within its own limits, but not all of them together
+        assert!(!third.push(set("c")));
+        drop(first);
+        assert!(third.push(set("c")));
+        assert_eq!(quota.total(), (2, 18));
+    }
+}
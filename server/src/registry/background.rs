@@ -0,0 +1,216 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Pausing the background services
+//!
+//! `SYS BGPAUSE` pauses BGSAVE and the snapshot scheduler (say, for the duration of a bulk
+//! import) and `SYS BGRESUME` resumes them. A paused service skips the runs that come due (with
+//! a log line) until it is resumed or until the pause runs out. Every pause runs out after the
+//! configured maximum at the latest, so that a forgotten pause can't stop the flushes for good.
+//! Pausing doesn't touch a run that is already underway.
+//!
+//! The services also publish when they'll run next, which `SYS BGSTATE` reports
+
+use crate::corestore::lock::QuickLock;
+use core::sync::atomic::{AtomicU64, Ordering};
+use tokio::time::{Duration, Instant};
+
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// The longest that a service can be paused for if no maximum is configured (an hour)
+pub const DEFAULT_MAX_PAUSE: u64 = 3600;
+
+/// The pause state of a background service and its next scheduled run
+pub struct ServiceControl {
+    /// the name of the service, as `SYS BGPAUSE` knows it
+    name: &'static str,
+    /// until when the service is paused, if it is
+    paused_until: QuickLock<Option<Instant>>,
+    /// when the service runs next, if it is scheduled
+    next_run: QuickLock<Option<Instant>>,
+}
+
+impl ServiceControl {
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            paused_until: QuickLock::new(None),
+            next_run: QuickLock::new(None),
+        }
+    }
+    /// Returns the name of the service
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+    /// Pause the service for `duration`, replacing any earlier pause
+    pub fn pause(&self, duration: Duration) {
+        *self.paused_until.lock() = Some(Instant::now() + duration);
+    }
+    /// Resume the service, returning false if it wasn't paused
+    pub fn resume(&self) -> bool {
+        let was_paused = self.paused_for().is_some();
+        *self.paused_until.lock() = None;
+        was_paused
+    }
+    /// Returns how much longer the service is paused for, if it is paused
+    pub fn paused_for(&self) -> Option<Duration> {
+        let mut paused_until = self.paused_until.lock();
+        let left = (*paused_until).and_then(|until| until.checked_duration_since(Instant::now()));
+        match left {
+            Some(left) if left > Duration::from_secs(0) => Some(left),
+            _ => {
+                // the pause ran out
+                *paused_until = None;
+                None
+            }
+        }
+    }
+    /// Returns true if the service is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused_for().is_some()
+    }
+    /// Publish when the service runs next (or that it isn't scheduled anymore)
+    pub fn set_next_run(&self, at: Option<Instant>) {
+        *self.next_run.lock() = at;
+    }
+    /// Returns how long it is until the service runs next, if it is scheduled
+    pub fn next_run_in(&self) -> Option<Duration> {
+        let next_run = *self.next_run.lock();
+        next_run.map(|at| at.saturating_duration_since(Instant::now()))
+    }
+}
+
+/// The background services that can be paused
+pub struct BackgroundControl {
+    /// BGSAVE
+    pub flush: ServiceControl,
+    /// the snapshot scheduler
+    pub snapshot: ServiceControl,
+    /// the longest that a service can be paused for, in seconds
+    max_pause: AtomicU64,
+}
+
+impl Default for BackgroundControl {
+    fn default() -> Self {
+        Self {
+            flush: ServiceControl::new("flush"),
+            snapshot: ServiceControl::new("snapshot"),
+            max_pause: AtomicU64::new(DEFAULT_MAX_PAUSE),
+        }
+    }
+}
+
+impl BackgroundControl {
+    /// Set the longest that a service can be paused for (in seconds)
+    pub fn set_max_pause(&self, seconds: u64) {
+        self.max_pause.store(seconds, ORD_RLX)
+    }
+    /// Returns the longest that a service can be paused for
+    pub fn max_pause(&self) -> Duration {
+        Duration::from_secs(self.max_pause.load(ORD_RLX))
+    }
+    /// Returns the services: both of them
+    pub fn all(&self) -> [&ServiceControl; 2] {
+        [&self.flush, &self.snapshot]
+    }
+    /// Returns the services that `name` (`flush`, `snapshot` or `all`, in any case) stands for
+    pub fn lookup(&self, name: &[u8]) -> Option<Vec<&ServiceControl>> {
+        if name.eq_ignore_ascii_case(b"all") {
+            Some(self.all().to_vec())
+        } else {
+            self.all()
+                .iter()
+                .find(|service| service.name().as_bytes().eq_ignore_ascii_case(name))
+                .map(|service| vec![*service])
+        }
+    }
+    /// Returns how long a pause for `requested` (or for as long as possible, if `None`) lasts:
+    /// never longer than the maximum
+    pub fn pause_duration(&self, requested: Option<Duration>) -> Duration {
+        let max = self.max_pause();
+        requested.map_or(max, |requested| requested.min(max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BackgroundControl, ServiceControl};
+    use tokio::time::{self, Duration};
+
+    #[tokio::test]
+    async fn test_pause_runs_out() {
+        time::pause();
+        let service = ServiceControl::new("flush");
+        assert!(!service.is_paused());
+        service.pause(Duration::from_secs(10));
+        assert_eq!(service.paused_for(), Some(Duration::from_secs(10)));
+        time::advance(Duration::from_secs(4)).await;
+        assert_eq!(service.paused_for(), Some(Duration::from_secs(6)));
+        time::advance(Duration::from_secs(6)).await;
+        assert!(!service.is_paused());
+        // and it has nothing to resume from
+        assert!(!service.resume());
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        time::pause();
+        let service = ServiceControl::new("snapshot");
+        service.pause(Duration::from_secs(10));
+        assert!(service.resume());
+        assert!(!service.is_paused());
+        assert!(!service.resume());
+    }
+
+    #[test]
+    fn test_pause_is_capped() {
+        let control = BackgroundControl::default();
+        control.set_max_pause(60);
+        assert_eq!(control.pause_duration(None), Duration::from_secs(60));
+        assert_eq!(
+            control.pause_duration(Some(Duration::from_secs(30))),
+            Duration::from_secs(30)
+        );
+        assert_eq!(
+            control.pause_duration(Some(Duration::from_secs(3600))),
+            Duration::from_secs(60)
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let control = BackgroundControl::default();
+        let names = |name: &[u8]| {
+            control
+                .lookup(name)
+                .map(|services| services.iter().map(|s| s.name()).collect::<Vec<_>>())
+        };
+        assert_eq!(names(b"FLUSH"), Some(vec!["flush"]));
+        assert_eq!(names(b"snapshot"), Some(vec!["snapshot"]));
+        assert_eq!(names(b"all"), Some(vec!["flush", "snapshot"]));
+        assert_eq!(names(b"expiry"), None);
+    }
+}
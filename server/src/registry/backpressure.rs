@@ -0,0 +1,204 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Write backpressure
+//!
+//! Every mutation on a [`KVEngine`](crate::kvengine::KVEngine) adds its byte delta to the
+//! global dirty counter and every completed flush takes away what it has written out. Once
+//! the counter crosses the configured high-water mark, writers are either delayed until the
+//! flush service catches up or rejected outright. Reads are never affected
+
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+use tokio::sync::Notify;
+use tokio::time::{self, Duration};
+
+const ORD_ACQ: Ordering = Ordering::Acquire;
+const ORD_REL: Ordering = Ordering::Release;
+const ORD_SEQ: Ordering = Ordering::SeqCst;
+
+/// A delayed writer rechecks the counter after this much time, just in case it missed
+/// a flush notification
+const RECHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Tracks the number of bytes that were mutated but haven't been flushed yet
+#[derive(Debug, Default)]
+pub struct DirtyTracker {
+    /// the number of dirty bytes
+    bytes: AtomicUsize,
+    /// the high-water mark (`0` if backpressure is disabled)
+    mark: AtomicUsize,
+    /// reject writes instead of delaying them once the mark is crossed
+    reject: AtomicBool,
+    /// wakes up the flush service for an out-of-schedule flush
+    flush_request: Notify,
+    /// wakes up the delayed writers once a flush completes
+    flush_complete: Notify,
+}
+
+impl DirtyTracker {
+    /// Set the high-water mark and whether writes should be rejected (instead of being
+    /// delayed) once it is crossed. A mark of `0` disables backpressure
+    pub fn configure(&self, mark: usize, reject: bool) {
+        self.reject.store(reject, ORD_REL);
+        self.mark.store(mark, ORD_REL);
+    }
+    /// Returns the current number of dirty bytes
+    pub fn get(&self) -> usize {
+        self.bytes.load(ORD_ACQ)
+    }
+    /// Returns the high-water mark (`0` if backpressure is disabled)
+    pub fn get_mark(&self) -> usize {
+        self.mark.load(ORD_ACQ)
+    }
+    /// Check if the high-water mark has been crossed
+    pub fn is_crossed(&self) -> bool {
+        let mark = self.get_mark();
+        mark != 0 && self.get() >= mark
+    }
+    /// Add the byte delta of a mutation. If this mutation crosses the mark, the flush
+    /// service is asked to flush right away
+    pub fn add(&self, delta: usize) {
+        let prev = self.bytes.fetch_add(delta, ORD_SEQ);
+        let mark = self.get_mark();
+        if mark != 0 && prev < mark && prev.saturating_add(delta) >= mark {
+            self.flush_request.notify_one();
+        }
+    }
+    /// Take away bytes that have been flushed (or dropped)
+    pub fn sub(&self, delta: usize) {
+        let _ = self
+            .bytes
+            .fetch_update(ORD_SEQ, ORD_SEQ, |cur| Some(cur.saturating_sub(delta)));
+    }
+    /// Wait until some writer asks for an out-of-schedule flush
+    pub async fn flush_requested(&self) {
+        self.flush_request.notified().await
+    }
+    /// Wake up all the writers that are waiting on a flush
+    pub fn notify_flushed(&self) {
+        self.flush_complete.notify_waiters()
+    }
+    /// Returns `true` if a write can go ahead. If the mark was crossed, this will either
+    /// wait for the flush service to bring the counter below the mark or immediately
+    /// return `false` if writes are to be rejected. A writer that waits for longer than
+    /// `timeout` (if there is one) gives up and gets `false` too
+    pub async fn throttle(&self, timeout: Option<Duration>) -> bool {
+        if !self.is_crossed() {
+            return true;
+        }
+        if self.reject.load(ORD_ACQ) {
+            return false;
+        }
+        let drained = async {
+            loop {
+                let flushed = self.flush_complete.notified();
+                if !self.is_crossed() {
+                    break true;
+                }
+                self.flush_request.notify_one();
+                let _ = time::timeout(RECHECK_INTERVAL, flushed).await;
+            }
+        };
+        match timeout {
+            Some(timeout) => time::timeout(timeout, drained).await.unwrap_or(false),
+            None => drained.await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DirtyTracker;
+    use std::sync::Arc;
+    use tokio::time::{self, Duration};
+
+    #[test]
+    fn test_disabled_by_default() {
+        let tracker = DirtyTracker::default();
+        tracker.add(usize::MAX / 2);
+        assert!(!tracker.is_crossed());
+    }
+    #[test]
+    fn test_sub_saturates() {
+        let tracker = DirtyTracker::default();
+        tracker.add(10);
+        tracker.sub(20);
+        assert_eq!(tracker.get(), 0);
+    }
+    #[tokio::test]
+    async fn test_reject_until_flushed() {
+        let tracker = DirtyTracker::default();
+        tracker.configure(16, true);
+        tracker.add(8);
+        assert!(tracker.throttle(None).await);
+        // no flush is running, so this stays dirty
+        tracker.add(8);
+        assert!(tracker.is_crossed());
+        assert!(!tracker.throttle(None).await);
+        // the flush service is asked to flush right away
+        time::timeout(Duration::from_secs(1), tracker.flush_requested())
+            .await
+            .unwrap();
+        // now the flush completes
+        tracker.sub(16);
+        tracker.notify_flushed();
+        assert!(tracker.throttle(None).await);
+    }
+    #[tokio::test]
+    async fn test_delay_until_flushed() {
+        let tracker = Arc::new(DirtyTracker::default());
+        tracker.configure(16, false);
+        tracker.add(32);
+        let writer_tracker = tracker.clone();
+        let mut writer = tokio::spawn(async move { writer_tracker.throttle(None).await });
+        // the writer can't go ahead as long as nothing has been flushed
+        assert!(time::timeout(Duration::from_millis(300), &mut writer)
+            .await
+            .is_err());
+        time::timeout(Duration::from_secs(1), tracker.flush_requested())
+            .await
+            .unwrap();
+        tracker.sub(32);
+        tracker.notify_flushed();
+        let can_write = time::timeout(Duration::from_secs(1), writer)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(can_write);
+    }
+    #[tokio::test]
+    async fn test_delay_times_out() {
+        let tracker = DirtyTracker::default();
+        tracker.configure(16, false);
+        tracker.add(32);
+        // nothing is flushed, so the writer gives up
+        assert!(!tracker.throttle(Some(Duration::from_millis(50))).await);
+        tracker.sub(32);
+        assert!(tracker.throttle(Some(Duration::from_millis(50))).await);
+    }
+}
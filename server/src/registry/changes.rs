@@ -0,0 +1,489 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The change log
+//!
+//! Every mutation on a table is numbered with a global sequence number and the most recent
+//! mutations are kept in a bounded in-memory ring buffer. `SYNCSTREAM` reads from (and waits
+//! on) this log to feed warm standbys. Since the ring is bounded, a reader that falls too far
+//! behind has to start over from a snapshot.
+//!
+//! The sequence number isn't persisted: it starts over from zero when the server restarts.
+//! Every run of the server gets a new [epoch](ChangeLog::epoch) instead, and a reader has to
+//! name the epoch that its sequence number is from, so that it is asked to start over rather
+//! than being handed the changes of another run that happen to have the same numbers. DDL
+//! queries (creating or dropping tables and keyspaces) aren't logged, so a standby needs to
+//! have the same tables as the primary. Also note that concurrent writes to the _same key_ may
+//! be logged in a different order than the one they were applied in
+//!
+//! The log also feeds the key events of `SYS EVENTS ON`, through an [`EventReader`]. Every
+//! mutation is an event of its own type (see [`Mutation::event`]), and the keys that the
+//! expiry sweeper removes are logged as [`Mutation::Expire`] rather than as the `DEL`s that
+//! they replay as, so that a reader can tell them apart. Unlike `SYNCSTREAM`, a reader of
+//! events that falls behind isn't cut off: it skips what was dropped from the log and is told
+//! how many changes it missed
+
+use crate::corestore::lock::QuickLock;
+use crate::corestore::Data;
+use bytes::Bytes;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::broadcast;
+
+const ORD_ACQ: Ordering = Ordering::Acquire;
+const ORD_REL: Ordering = Ordering::Release;
+
+/// The number of mutations that are kept around by default
+pub const DEFAULT_SYNC_BUFFER: usize = 4096;
+
+/// A mutation on a table. Every mutation is logged as the query that replays it
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mutation {
+    /// `SET <key> <value>`
+    Set(Data, Data),
+    /// `UPDATE <key> <value>`
+    Update(Data, Data),
+    /// `USET <key> <value>`
+    Upsert(Data, Data),
+    /// `DEL <key>`
+    Remove(Data),
+    /// `PROTECT <key>`
+    Protect(Data),
+    /// `UNPROTECT <key>`
+    Unprotect(Data),
+    /// `FLUSHDB [FORCE]`
+    Flush { force: bool },
+    /// `DEL <key>`, made by the expiry sweeper once the key went past its expiry time
+    Expire(Data),
+}
+
+impl Mutation {
+    /// Returns the query that replays this mutation
+    pub fn to_query(&self) -> Vec<Bytes> {
+        let action = |name: &'static str| Bytes::from_static(name.as_bytes());
+        let arg = |data: &Data| data.get_blob().clone();
+        match self {
+            Self::Set(key, value) => vec![action("SET"), arg(key), arg(value)],
+            Self::Update(key, value) => vec![action("UPDATE"), arg(key), arg(value)],
+            Self::Upsert(key, value) => vec![action("USET"), arg(key), arg(value)],
+            Self::Remove(key) => vec![action("DEL"), arg(key)],
+            Self::Protect(key) => vec![action("PROTECT"), arg(key)],
+            Self::Unprotect(key) => vec![action("UNPROTECT"), arg(key)],
+            Self::Flush { force: true } => vec![action("FLUSHDB"), action("FORCE")],
+            Self::Flush { force: false } => vec![action("FLUSHDB")],
+            Self::Expire(key) => vec![action("DEL"), arg(key)],
+        }
+    }
+    /// Returns the type of the key event that this mutation is
+    pub const fn event(&self) -> &'static str {
+        match self {
+            Self::Set(..) => "set",
+            Self::Update(..) => "update",
+            Self::Upsert(..) => "uset",
+            Self::Remove(_) => "del",
+            Self::Protect(_) => "protect",
+            Self::Unprotect(_) => "unprotect",
+            Self::Flush { .. } => "flushdb",
+            Self::Expire(_) => "expired",
+        }
+    }
+    /// Returns the key that this mutation touched (a flush touches every key)
+    pub fn key(&self) -> Option<&Data> {
+        match self {
+            Self::Set(key, _)
+            | Self::Update(key, _)
+            | Self::Upsert(key, _)
+            | Self::Remove(key)
+            | Self::Protect(key)
+            | Self::Unprotect(key)
+            | Self::Expire(key) => Some(key),
+            Self::Flush { .. } => None,
+        }
+    }
+}
+
+/// A logged mutation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// the sequence number of this mutation
+    pub seq: u64,
+    /// the entity (`<keyspace>:<table>`) that was mutated
+    pub entity: Data,
+    /// the mutation itself
+    pub mutation: Mutation,
+    /// the second (since the unix epoch) that the mutation was logged in
+    pub at: u64,
+}
+
+/// Returned when the requested changes are no longer (or not yet) in the log, or are from
+/// another epoch
+#[derive(Debug, PartialEq)]
+pub struct TooFarBehind {
+    /// the epoch of the log
+    pub epoch: u64,
+    /// the sequence number of the latest change
+    pub current: u64,
+}
+
+#[derive(Debug)]
+struct Ring {
+    /// the sequence number of the latest change
+    seq: u64,
+    /// the latest changes, oldest first
+    changes: VecDeque<Change>,
+    /// the maximum number of changes to keep
+    capacity: usize,
+}
+
+/// The change log. See the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct ChangeLog {
+    ring: QuickLock<Ring>,
+    /// readers waiting for new changes are woken up with the latest sequence number
+    notifier: broadcast::Sender<u64>,
+    /// is the server shutting down
+    closed: AtomicBool,
+    /// identifies this numbering of the sequence
+    epoch: u64,
+}
+
+impl Default for ChangeLog {
+    fn default() -> Self {
+        Self::new(DEFAULT_SYNC_BUFFER)
+    }
+}
+
+impl ChangeLog {
+    /// Create a new change log that keeps (at most) `capacity` changes
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            ring: QuickLock::new(Ring {
+                seq: 0,
+                changes: VecDeque::with_capacity(capacity.min(DEFAULT_SYNC_BUFFER)),
+                capacity,
+            }),
+            notifier: broadcast::channel(16).0,
+            closed: AtomicBool::new(false),
+            epoch: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_nanos() as u64)
+                .unwrap_or(0),
+        }
+    }
+    /// Set the maximum number of changes to keep, discarding the oldest changes if needed
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut ring = self.ring.lock();
+        while ring.changes.len() > capacity {
+            ring.changes.pop_front();
+        }
+        ring.capacity = capacity;
+    }
+    /// Log a mutation on `entity`, returning its sequence number
+    pub fn record(&self, entity: Data, mutation: Mutation) -> u64 {
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|since| since.as_secs())
+            .unwrap_or(0);
+        let seq = {
+            let mut ring = self.ring.lock();
+            ring.seq += 1;
+            let seq = ring.seq;
+            if ring.capacity != 0 {
+                if ring.changes.len() == ring.capacity {
+                    ring.changes.pop_front();
+                }
+                ring.changes.push_back(Change {
+                    seq,
+                    entity,
+                    mutation,
+                    at,
+                });
+            }
+            seq
+        };
+        // there might not be any readers; that's fine
+        let _ = self.notifier.send(seq);
+        seq
+    }
+    /// Returns the sequence number of the latest change
+    pub fn current_seq(&self) -> u64 {
+        self.ring.lock().seq
+    }
+    /// Returns the epoch of this log. The sequence numbers start over in a new epoch, so two
+    /// sequence numbers can only be compared if they're from the same epoch
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+    /// Returns the changes starting with sequence number `from` of `epoch` (sequence numbers
+    /// start at 1; so 0 is the same as 1). If `epoch` isn't the epoch of this log, if some of
+    /// those changes have already been discarded or if `from` is ahead of the log,
+    /// [`TooFarBehind`] is returned instead
+    pub fn since(&self, epoch: u64, from: u64) -> Result<Vec<Change>, TooFarBehind> {
+        let from = from.max(1);
+        let ring = self.ring.lock();
+        let oldest = ring.seq + 1 - ring.changes.len() as u64;
+        if epoch != self.epoch || from < oldest || from > ring.seq + 1 {
+            return Err(TooFarBehind {
+                epoch: self.epoch,
+                current: ring.seq,
+            });
+        }
+        Ok(ring
+            .changes
+            .iter()
+            .skip((from - oldest) as usize)
+            .cloned()
+            .collect())
+    }
+    /// Returns the changes starting with sequence number `from` like [`ChangeLog::since`],
+    /// except that if some of them have already been discarded, the ones that are left are
+    /// returned along with the number of changes that were discarded. Nothing is returned if
+    /// `from` is ahead of the log
+    pub fn since_lossy(&self, from: u64) -> (u64, Vec<Change>) {
+        let from = from.max(1);
+        let ring = self.ring.lock();
+        let oldest = ring.seq + 1 - ring.changes.len() as u64;
+        if from > ring.seq + 1 {
+            return (0, Vec::new());
+        }
+        let dropped = oldest.saturating_sub(from);
+        let skip = from.saturating_sub(oldest) as usize;
+        (dropped, ring.changes.iter().skip(skip).cloned().collect())
+    }
+    /// Get notified whenever a change is logged. Subscribe **before** reading the log so that
+    /// no change slips through in between
+    pub fn subscribe(&self) -> broadcast::Receiver<u64> {
+        self.notifier.subscribe()
+    }
+    /// Tell the readers to stop waiting since the server is shutting down
+    pub fn close(&self) {
+        self.closed.store(true, ORD_REL);
+        let _ = self.notifier.send(self.current_seq());
+    }
+    /// Check if the server is shutting down
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(ORD_ACQ)
+    }
+}
+
+/// A key event of a table
+#[derive(Debug, PartialEq)]
+pub struct KeyEvent {
+    /// the type of the event (see [`Mutation::event`])
+    pub event: &'static str,
+    /// the key, which is empty for a flush
+    pub key: Bytes,
+    /// the second (since the unix epoch) that the event happened in
+    pub at: u64,
+}
+
+/// Reads the key events of a table from the change log, in the order that they were logged
+pub struct EventReader {
+    /// the entity (`<keyspace>:<table>`) of the table
+    entity: Data,
+    /// the sequence number of the next change to read
+    next: u64,
+}
+
+impl EventReader {
+    /// Create a reader of the events of `entity` that are logged in `log` from now on
+    pub fn new(log: &ChangeLog, entity: Data) -> Self {
+        Self {
+            entity,
+            next: log.current_seq() + 1,
+        }
+    }
+    /// Returns the events of the table that were logged since the last read, along with the
+    /// number of changes (of any table) that were dropped from the log before they could be
+    /// read
+    pub fn read(&mut self, log: &ChangeLog) -> (u64, Vec<KeyEvent>) {
+        let (dropped, changes) = log.since_lossy(self.next);
+        self.next += dropped + changes.len() as u64;
+        let events = changes
+            .into_iter()
+            .filter(|change| change.entity == self.entity)
+            .map(|change| KeyEvent {
+                event: change.mutation.event(),
+                key: change
+                    .mutation
+                    .key()
+                    .map(|key| key.get_blob().clone())
+                    .unwrap_or_default(),
+                at: change.at,
+            })
+            .collect();
+        (dropped, events)
+    }
+}
+
+#[test]
+fn test_changelog_since() {
+    let log = ChangeLog::new(3);
+    let epoch = log.epoch();
+    assert_eq!(log.since(epoch, 0).unwrap(), vec![]);
+    assert_eq!(log.since(epoch, 1).unwrap(), vec![]);
+    for i in 0..5u8 {
+        let seq = log.record(Data::from("ks:tbl"), Mutation::Remove(Data::from(vec![i])));
+        assert_eq!(seq, i as u64 + 1);
+    }
+    assert_eq!(log.current_seq(), 5);
+    // only the last three are left
+    assert_eq!(
+        log.since(epoch, 2).unwrap_err(),
+        TooFarBehind { epoch, current: 5 }
+    );
+    let seqs: Vec<u64> = log.since(epoch, 3).unwrap().iter().map(|c| c.seq).collect();
+    assert_eq!(seqs, vec![3, 4, 5]);
+    assert_eq!(
+        log.since(epoch, 5).unwrap()[0].mutation,
+        Mutation::Remove(Data::from(vec![4]))
+    );
+    // caught up
+    assert_eq!(log.since(epoch, 6).unwrap(), vec![]);
+    // ahead of the log
+    assert_eq!(
+        log.since(epoch, 7).unwrap_err(),
+        TooFarBehind { epoch, current: 5 }
+    );
+}
+
+#[test]
+fn test_changelog_capacity() {
+    let log = ChangeLog::new(0);
+    let epoch = log.epoch();
+    log.record(Data::from("ks:tbl"), Mutation::Flush { force: true });
+    // nothing is kept, but the sequence still moves on
+    assert_eq!(log.current_seq(), 1);
+    assert!(log.since(epoch, 1).is_err());
+    assert_eq!(log.since(epoch, 2).unwrap(), vec![]);
+    log.set_capacity(2);
+    for _ in 0..3 {
+        log.record(Data::from("ks:tbl"), Mutation::Flush { force: false });
+    }
+    assert!(log.since(epoch, 2).is_err());
+    assert_eq!(log.since(epoch, 3).unwrap().len(), 2);
+    log.set_capacity(1);
+    assert!(log.since(epoch, 3).is_err());
+    assert_eq!(log.since(epoch, 4).unwrap().len(), 1);
+}
+
+#[test]
+fn test_changelog_since_another_epoch() {
+    let before = ChangeLog::new(8);
+    for _ in 0..3 {
+        before.record(Data::from("ks:tbl"), Mutation::Flush { force: false });
+    }
+    // a reader has seen every change, and then the server restarts
+    let (epoch, from) = (before.epoch(), before.current_seq() + 1);
+    drop(before);
+    let after = ChangeLog::new(8);
+    assert_ne!(after.epoch(), epoch);
+    for _ in 0..5 {
+        after.record(Data::from("ks:tbl"), Mutation::Flush { force: true });
+    }
+    // the changes of the new run have the numbers that the reader asks for, but they aren't
+    // the changes that it's missing
+    assert_eq!(
+        after.since(epoch, from).unwrap_err(),
+        TooFarBehind {
+            epoch: after.epoch(),
+            current: 5
+        }
+    );
+    assert_eq!(after.since(after.epoch(), from).unwrap().len(), 1);
+}
+
+#[test]
+fn test_mutation_to_query() {
+    let (key, value) = (Data::from("k"), Data::from("v"));
+    assert_eq!(
+        Mutation::Upsert(key.clone(), value).to_query(),
+        vec![Bytes::from("USET"), Bytes::from("k"), Bytes::from("v")]
+    );
+    assert_eq!(
+        Mutation::Remove(key).to_query(),
+        vec![Bytes::from("DEL"), Bytes::from("k")]
+    );
+    assert_eq!(
+        Mutation::Flush { force: true }.to_query(),
+        vec![Bytes::from("FLUSHDB"), Bytes::from("FORCE")]
+    );
+    // an expiry replays as a delete, but it's an event of its own
+    let expired = Mutation::Expire(Data::from("k"));
+    assert_eq!(
+        expired.to_query(),
+        vec![Bytes::from("DEL"), Bytes::from("k")]
+    );
+    assert_eq!(expired.event(), "expired");
+    assert_eq!(Mutation::Remove(Data::from("k")).event(), "del");
+}
+
+#[test]
+fn test_event_reader_skips_what_was_dropped() {
+    let log = ChangeLog::new(3);
+    let (ours, theirs) = (Data::from("ks:ours"), Data::from("ks:theirs"));
+    // this happened before the reader was created
+    log.record(ours.clone(), Mutation::Remove(Data::from("old")));
+    let mut reader = EventReader::new(&log, ours.clone());
+    log.record(
+        ours.clone(),
+        Mutation::Set(Data::from("a"), Data::from("1")),
+    );
+    log.record(theirs, Mutation::Remove(Data::from("b")));
+    log.record(ours.clone(), Mutation::Expire(Data::from("a")));
+    let (dropped, events) = reader.read(&log);
+    assert_eq!(dropped, 0);
+    let seen: Vec<(&str, Bytes)> = events.into_iter().map(|e| (e.event, e.key)).collect();
+    // in the order they were logged, and only those of the table
+    assert_eq!(
+        seen,
+        vec![("set", Bytes::from("a")), ("expired", Bytes::from("a"))]
+    );
+    assert_eq!(reader.read(&log), (0, Vec::new()));
+    // a slow reader misses what the log no longer has, and is told how much that was
+    for i in 0..5u8 {
+        log.record(ours.clone(), Mutation::Remove(Data::from(vec![b'k', i])));
+    }
+    let (dropped, events) = reader.read(&log);
+    assert_eq!(dropped, 2);
+    let keys: Vec<Bytes> = events.into_iter().map(|e| e.key).collect();
+    assert_eq!(
+        keys,
+        vec![
+            Bytes::from(&b"k\x02"[..]),
+            Bytes::from(&b"k\x03"[..]),
+            Bytes::from(&b"k\x04"[..])
+        ]
+    );
+    // and carries on from there
+    log.record(ours, Mutation::Flush { force: false });
+    let (dropped, events) = reader.read(&log);
+    assert_eq!(dropped, 0);
+    assert_eq!(events[0].event, "flushdb");
+    assert!(events[0].key.is_empty());
+}
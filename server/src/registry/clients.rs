@@ -0,0 +1,168 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Open connections
+//!
+//! Every open connection is registered here along with the number of bytes of its responses
+//! that it has buffered but not yet handed over to the socket, so that `SYS CLIENTS` can point
+//! out the connections that are stuck on a slow reader. What its open transaction (if any) has
+//! queued is recorded too, and so are the responses to pipelined queries that it holds back
+//! (see [`PipelineLimits`](super::PipelineLimits))
+
+use crate::corestore::htable::Coremap;
+use crate::dbnet::connection::Peer;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// An open connection
+#[derive(Debug)]
+pub struct ClientStats {
+    /// the peer on the other end of the connection
+    peer: Peer,
+    /// the bytes that are buffered right now
+    buffered: AtomicUsize,
+    /// the most bytes that were ever buffered at once
+    peak_buffered: AtomicUsize,
+    /// the queries queued in the open transaction
+    queued: AtomicUsize,
+    /// the bytes of those queries
+    queued_bytes: AtomicUsize,
+    /// the responses that are held back until the stream is flushed
+    inflight: AtomicUsize,
+    /// the bytes of responses written since the stream was last flushed
+    inflight_bytes: AtomicUsize,
+    /// the queries read since the connection last waited for more of them
+    depth: AtomicUsize,
+}
+
+impl ClientStats {
+    fn new(peer: Peer) -> Self {
+        Self {
+            peer,
+            buffered: AtomicUsize::new(0),
+            peak_buffered: AtomicUsize::new(0),
+            queued: AtomicUsize::new(0),
+            queued_bytes: AtomicUsize::new(0),
+            inflight: AtomicUsize::new(0),
+            inflight_bytes: AtomicUsize::new(0),
+            depth: AtomicUsize::new(0),
+        }
+    }
+    /// Returns the peer on the other end of the connection
+    pub fn peer(&self) -> &Peer {
+        &self.peer
+    }
+    /// The connection now has `bytes` buffered
+    pub fn set_buffered(&self, bytes: usize) {
+        self.buffered.store(bytes, Ordering::Relaxed);
+        self.peak_buffered.fetch_max(bytes, Ordering::Relaxed);
+    }
+    /// Returns the number of bytes that are buffered right now
+    pub fn buffered(&self) -> usize {
+        self.buffered.load(Ordering::Relaxed)
+    }
+    /// Returns the most bytes that were ever buffered at once
+    pub fn peak_buffered(&self) -> usize {
+        self.peak_buffered.load(Ordering::Relaxed)
+    }
+    /// The open transaction of the connection now has `queued` queries of `bytes` bytes
+    pub fn set_queued(&self, queued: usize, bytes: usize) {
+        self.queued.store(queued, Ordering::Relaxed);
+        self.queued_bytes.store(bytes, Ordering::Relaxed);
+    }
+    /// Returns the queries queued in the open transaction and their bytes
+    pub fn queued(&self) -> (usize, usize) {
+        (
+            self.queued.load(Ordering::Relaxed),
+            self.queued_bytes.load(Ordering::Relaxed),
+        )
+    }
+    /// The connection wrote `bytes` bytes of a response
+    pub fn wrote(&self, bytes: usize) {
+        self.inflight_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+    /// The connection finished a response that isn't flushed yet. This returns the responses
+    /// that aren't flushed (this one included) and their bytes
+    pub fn finished_response(&self) -> (usize, usize) {
+        (
+            self.inflight.fetch_add(1, Ordering::Relaxed) + 1,
+            self.inflight_bytes.load(Ordering::Relaxed),
+        )
+    }
+    /// The connection flushed its stream, so it no longer holds back any responses
+    pub fn flushed(&self) {
+        self.inflight.store(0, Ordering::Relaxed);
+        self.inflight_bytes.store(0, Ordering::Relaxed);
+    }
+    /// Returns the responses that the connection holds back right now
+    pub fn inflight(&self) -> usize {
+        self.inflight.load(Ordering::Relaxed)
+    }
+    /// The connection read a query. This returns the queries that it read since it last
+    /// waited for more of them (this one included)
+    pub fn read_query(&self) -> usize {
+        self.depth.fetch_add(1, Ordering::Relaxed) + 1
+    }
+    /// The connection ran out of queries and is about to wait for more
+    pub fn drained(&self) {
+        self.depth.store(0, Ordering::Relaxed);
+    }
+}
+
+/// The open connections, by connection ID. See the [module level docs](self) for more
+/// information
+#[derive(Debug)]
+pub struct Clients {
+    open: Coremap<u64, Arc<ClientStats>>,
+}
+
+impl Default for Clients {
+    fn default() -> Self {
+        Self {
+            open: Coremap::new(),
+        }
+    }
+}
+
+impl Clients {
+    /// Register a connection (again, if its peer changed) and return its stats
+    pub fn register(&self, peer: &Peer) -> Arc<ClientStats> {
+        let stats = Arc::new(ClientStats::new(peer.clone()));
+        self.open.upsert(peer.id, stats.clone());
+        stats
+    }
+    /// Forget a connection once it is closed
+    pub fn unregister(&self, connection: u64) {
+        let _ = self.open.true_if_removed(&connection);
+    }
+    /// Returns the open connections, ordered by their IDs
+    pub fn list(&self) -> Vec<Arc<ClientStats>> {
+        let mut clients: Vec<Arc<ClientStats>> =
+            self.open.iter().map(|kv| kv.value().clone()).collect();
+        clients.sort_by_key(|client| client.peer.id);
+        clients
+    }
+}
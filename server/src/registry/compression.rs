@@ -0,0 +1,100 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Response compression settings
+//!
+//! The algorithm and threshold that connections can negotiate for compressing their
+//! responses, along with counters for the compressed frames that have been written
+
+use crate::dbnet::compression::Algorithm;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicUsize;
+use core::sync::atomic::Ordering;
+
+const ORD_ACQ: Ordering = Ordering::Acquire;
+const ORD_REL: Ordering = Ordering::Release;
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// Responses that are at least these many bytes long are compressed by default
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 4096;
+
+/// The compression settings and statistics
+#[derive(Debug)]
+pub struct CompressionSettings {
+    /// can clients negotiate LZ4
+    lz4: AtomicBool,
+    /// responses this long (or longer) are compressed
+    threshold: AtomicUsize,
+    /// the number of compressed frames written
+    frames: AtomicUsize,
+    /// the number of bytes that compressing the frames saved
+    bytes_saved: AtomicUsize,
+}
+
+impl Default for CompressionSettings {
+    fn default() -> Self {
+        Self {
+            lz4: AtomicBool::new(true),
+            threshold: AtomicUsize::new(DEFAULT_COMPRESSION_THRESHOLD),
+            frames: AtomicUsize::new(0),
+            bytes_saved: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl CompressionSettings {
+    /// Set the algorithm that clients can negotiate (none if `None`) and the threshold
+    pub fn configure(&self, algorithm: Option<Algorithm>, threshold: usize) {
+        self.lz4.store(algorithm == Some(Algorithm::Lz4), ORD_REL);
+        self.threshold.store(threshold, ORD_REL);
+    }
+    /// Returns the algorithm that clients can negotiate, if any
+    pub fn get_algorithm(&self) -> Option<Algorithm> {
+        if self.lz4.load(ORD_ACQ) {
+            Some(Algorithm::Lz4)
+        } else {
+            None
+        }
+    }
+    /// Returns the threshold
+    pub fn get_threshold(&self) -> usize {
+        self.threshold.load(ORD_ACQ)
+    }
+    /// Count a compressed frame
+    pub fn record_frame(&self, uncompressed: usize, compressed: usize) {
+        self.frames.fetch_add(1, ORD_RLX);
+        self.bytes_saved
+            .fetch_add(uncompressed.saturating_sub(compressed), ORD_RLX);
+    }
+    /// Returns the number of compressed frames written
+    pub fn get_frames(&self) -> usize {
+        self.frames.load(ORD_RLX)
+    }
+    /// Returns the number of bytes saved by compressing frames
+    pub fn get_bytes_saved(&self) -> usize {
+        self.bytes_saved.load(ORD_RLX)
+    }
+}
@@ -0,0 +1,277 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The DDL log
+//!
+//! Every `CREATE`, `DROP` and `ALTER` (of tables and keyspaces) is appended to the DDL log along with
+//! the time, the client that ran it and its outcome, so that there is a record of who changed
+//! the schema (and when) even if the attempt failed. Unlike the [change log](super::ChangeLog),
+//! this log is always on and persistent: every record is a line of JSON in [`ddl_log_path`]
+//! which is synced to disk before the client hears back. Since DDL queries are rare, the
+//! records are also kept in memory for `SYS DDLLOG`.
+//!
+//! The log is read back when it is opened on startup. If the server crashed while a record
+//! was being written, the partial record at the end of the file is dropped; anything else
+//! that can't be read means that the log is corrupted
+
+use crate::corestore::lock::QuickLock;
+use crate::storage::interface;
+use crate::IoResult;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error as IoError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+/// The file that the DDL log is kept in (in the data directory)
+pub const DDL_LOG_FILE: &str = "ddl.log";
+
+/// The path to the DDL log
+pub fn ddl_log_path() -> PathBuf {
+    Path::new(interface::dir_root()).join(DDL_LOG_FILE)
+}
+
+/// A record in the DDL log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DdlRecord {
+    /// when the query was run (as an RFC 3339 timestamp in UTC)
+    pub time: String,
+    /// the connection that ran the query (see [`Peer`](crate::dbnet::connection::Peer))
+    pub client: String,
+    /// the query itself
+    pub statement: String,
+    /// what the query responded with: `okay`, or the error
+    pub outcome: String,
+}
+
+impl DdlRecord {
+    pub fn new(
+        time: DateTime<Utc>,
+        client: impl ToString,
+        statement: String,
+        response: &[u8],
+    ) -> Self {
+        Self {
+            time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            client: client.to_string(),
+            statement,
+            outcome: outcome_of(response),
+        }
+    }
+}
+
+/// Returns the name of a response code or the error string in `response`. So `!1\n0\n` is
+/// `okay` and `!18\nerr-already-exists\n` is `err-already-exists`
+pub fn outcome_of(response: &[u8]) -> String {
+    let body = response
+        .strip_prefix(b"!")
+        .and_then(|rest| rest.splitn(2, |b| *b == b'\n').nth(1))
+        .map(|body| body.strip_suffix(b"\n").unwrap_or(body))
+        .unwrap_or(response);
+    let code = match body {
+        b"0" => "okay",
+        b"1" => "nil",
+        b"2" => "overwrite-error",
+        b"3" => "action-error",
+        b"4" => "packet-error",
+        b"5" => "server-error",
+        b"6" => "other-error",
+        b"7" => "wrongtype-error",
+        b"8" => "unknown-data-type",
+        b"9" => "encoding-error",
+        _ => return String::from_utf8_lossy(body).into_owned(),
+    };
+    code.to_owned()
+}
+
+#[derive(Debug, Default)]
+struct Log {
+    /// the file the records are appended to (if the log was opened)
+    file: Option<File>,
+    /// all the records, oldest first
+    records: Vec<DdlRecord>,
+}
+
+/// The DDL log. See the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct DdlLog {
+    log: QuickLock<Log>,
+}
+
+impl Default for DdlLog {
+    fn default() -> Self {
+        Self {
+            log: QuickLock::new(Log::default()),
+        }
+    }
+}
+
+impl DdlLog {
+    /// Open the log file at `path` (creating it if it doesn't exist) and read its records.
+    /// Until a log is opened, the records are only kept in memory
+    pub fn open(&self, path: impl AsRef<Path>) -> IoResult<()> {
+        let path = path.as_ref();
+        let records = match fs::read(path) {
+            Ok(contents) => self::read_records(path, &contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut log = self.log.lock();
+        log.file = Some(file);
+        log.records = records;
+        Ok(())
+    }
+    /// Append a record and sync it to disk. The record is kept even if it couldn't be
+    /// written out
+    pub fn append(&self, record: DdlRecord) -> IoResult<()> {
+        let mut log = self.log.lock();
+        let ret = match &mut log.file {
+            Some(file) => {
+                let mut line = serde_json::to_vec(&record)?;
+                line.push(b'\n');
+                file.write_all(&line).and_then(|_| file.sync_data())
+            }
+            None => Ok(()),
+        };
+        log.records.push(record);
+        ret
+    }
+    /// Returns the last `n` records, oldest first
+    pub fn tail(&self, n: usize) -> Vec<DdlRecord> {
+        let log = self.log.lock();
+        log.records[log.records.len().saturating_sub(n)..].to_vec()
+    }
+}
+
+/// Read the records in `contents` (that were read from `path`) and cut off a partial record
+/// at the end of the file, if there is one
+fn read_records(path: &Path, contents: &[u8]) -> IoResult<Vec<DdlRecord>> {
+    let complete = contents
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map(|last_newline| last_newline + 1)
+        .unwrap_or(0);
+    let records = contents[..complete]
+        .split(|b| *b == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            serde_json::from_slice(line)
+                .map_err(|e| IoError::new(ErrorKind::InvalidData, format!("bad DDL log: {}", e)))
+        })
+        .collect::<IoResult<Vec<DdlRecord>>>()?;
+    if complete != contents.len() {
+        log::warn!(
+            "Dropping a partially written record at the end of the DDL log ({} bytes)",
+            contents.len() - complete
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(complete as u64)?;
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+fn record(statement: &str, response: &[u8]) -> DdlRecord {
+    use chrono::TimeZone;
+    let time = Utc.ymd(2021, 7, 1).and_hms(10, 0, 0);
+    DdlRecord::new(time, "#1 127.0.0.1:2003", statement.to_owned(), response)
+}
+
+#[test]
+fn test_outcome_of() {
+    use crate::protocol::responses::groups;
+    assert_eq!(outcome_of(groups::OKAY), "okay");
+    assert_eq!(outcome_of(groups::SERVER_ERR), "server-error");
+    assert_eq!(outcome_of(groups::ALREADY_EXISTS), "err-already-exists");
+    assert_eq!(
+        outcome_of(&crate::actions::Arity::Exactly(1).error()),
+        "bad-arity:exactly-1"
+    );
+    assert_eq!(
+        record("DROP TABLE x", groups::OKAY).time,
+        "2021-07-01T10:00:00.000Z"
+    );
+}
+
+#[test]
+fn test_ddl_log_persists_across_restarts() {
+    let path = "ddllog_test_restart.log";
+    let _ = fs::remove_file(path);
+    let log = DdlLog::default();
+    log.open(path).unwrap();
+    log.append(record("CREATE KEYSPACE ks", b"!1\n0\n"))
+        .unwrap();
+    log.append(record("DROP KEYSPACE ks", b"!1\n5\n")).unwrap();
+    assert_eq!(log.tail(1), vec![record("DROP KEYSPACE ks", b"!1\n5\n")]);
+    drop(log);
+    // "restart"
+    let log = DdlLog::default();
+    log.open(path).unwrap();
+    assert_eq!(
+        log.tail(10),
+        vec![
+            record("CREATE KEYSPACE ks", b"!1\n0\n"),
+            record("DROP KEYSPACE ks", b"!1\n5\n")
+        ]
+    );
+    // and the new records go after the old ones
+    log.append(record("DROP KEYSPACE ks", b"!1\n0\n")).unwrap();
+    drop(log);
+    let log = DdlLog::default();
+    log.open(path).unwrap();
+    let outcomes: Vec<String> = log.tail(10).into_iter().map(|r| r.outcome).collect();
+    assert_eq!(outcomes, vec!["okay", "server-error", "okay"]);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_ddl_log_drops_partial_record() {
+    let path = "ddllog_test_partial.log";
+    let mut contents = serde_json::to_vec(&record("CREATE KEYSPACE ks", b"!1\n0\n")).unwrap();
+    contents.push(b'\n');
+    let complete = contents.len();
+    contents.extend_from_slice(br#"{"time":"2021-07-01T10:"#);
+    fs::write(path, &contents).unwrap();
+    let log = DdlLog::default();
+    log.open(path).unwrap();
+    assert_eq!(log.tail(10).len(), 1);
+    assert_eq!(fs::metadata(path).unwrap().len(), complete as u64);
+    log.append(record("DROP KEYSPACE ks", b"!1\n0\n")).unwrap();
+    drop(log);
+    let log = DdlLog::default();
+    log.open(path).unwrap();
+    assert_eq!(log.tail(10).len(), 2);
+    // but a broken record in the middle is an error
+    fs::write(path, b"not json\n").unwrap();
+    assert_eq!(
+        DdlLog::default().open(path).unwrap_err().kind(),
+        ErrorKind::InvalidData
+    );
+    fs::remove_file(path).unwrap();
+}
@@ -0,0 +1,176 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Flush progress
+//!
+//! The flush service publishes the [change log](crate::registry::ChangeLog) sequence number
+//! at the start of every flush, and that number becomes the _durable_ sequence number once
+//! the flush completes successfully: every mutation numbered at or below it is on disk. A
+//! client that needs its writes to be durable records the current sequence number and waits
+//! for the durable sequence number to catch up with it. The waiters ask the flush service
+//! for a flush, and since the service keeps at most one pending request, all the waiters of
+//! a flush share it
+
+use crate::registry;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::Ordering;
+use tokio::sync::Notify;
+use tokio::time::{self, Duration};
+
+const ORD_ACQ: Ordering = Ordering::Acquire;
+const ORD_REL: Ordering = Ordering::Release;
+
+/// A waiter rechecks the durable sequence number after this much time, just in case it missed
+/// a flush notification
+const RECHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The progress of the flush service
+#[derive(Debug, Default)]
+pub struct FlushProgress {
+    /// the sequence number at the start of the latest flush to have started
+    started: AtomicU64,
+    /// the sequence number at the start of the latest flush to have completed successfully
+    durable: AtomicU64,
+    /// set if the latest flush failed
+    failed: AtomicBool,
+    /// wakes up the flush service for an out-of-schedule flush
+    flush_request: Notify,
+    /// wakes up the waiters once a flush completes (successfully or not)
+    flush_complete: Notify,
+}
+
+impl FlushProgress {
+    /// Run a flush, publishing the sequence number at its start and, if `flush` returns true,
+    /// marking everything up to that number as durable. This returns what `flush` returned
+    pub fn flush(&self, flush: impl FnOnce() -> bool) -> bool {
+        // every mutation up to this number is visible to the flush that follows
+        self.flush_at(registry::get_changelog().current_seq(), flush)
+    }
+    fn flush_at(&self, seq: u64, flush: impl FnOnce() -> bool) -> bool {
+        self.started.fetch_max(seq, ORD_REL);
+        let okay = flush();
+        if okay {
+            self.durable.fetch_max(seq, ORD_REL);
+        }
+        self.failed.store(!okay, ORD_REL);
+        self.flush_complete.notify_waiters();
+        okay
+    }
+    /// Returns the sequence number at the start of the latest flush to have started
+    pub fn started(&self) -> u64 {
+        self.started.load(ORD_ACQ)
+    }
+    /// Returns the sequence number up to which every mutation is on disk
+    pub fn durable(&self) -> u64 {
+        self.durable.load(ORD_ACQ)
+    }
+    /// Wait until some client asks for an out-of-schedule flush
+    pub async fn flush_requested(&self) {
+        self.flush_request.notified().await
+    }
+    /// Wait until every mutation up to `seq` is on disk, asking the flush service for a flush
+    /// if it needs one. This returns false if the latest flush failed (and `seq` isn't durable
+    /// yet). Use a timeout, since nothing is ever flushed if the flush service is disabled
+    pub async fn wait_durable(&self, seq: u64) -> bool {
+        loop {
+            let flushed = self.flush_complete.notified();
+            if self.durable() >= seq {
+                break true;
+            }
+            if self.failed.load(ORD_ACQ) {
+                break false;
+            }
+            self.flush_request.notify_one();
+            let _ = time::timeout(RECHECK_INTERVAL, flushed).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FlushProgress;
+    use std::sync::Arc;
+    use tokio::time::{self, Duration};
+
+    #[tokio::test]
+    async fn test_wait_times_out_without_flushes() {
+        let progress = FlushProgress::default();
+        assert!(
+            time::timeout(Duration::from_millis(300), progress.wait_durable(1))
+                .await
+                .is_err()
+        );
+        // the flush service was asked for a flush all the same
+        time::timeout(Duration::from_secs(1), progress.flush_requested())
+            .await
+            .unwrap();
+    }
+    #[tokio::test]
+    async fn test_waiters_share_a_flush() {
+        let progress = Arc::new(FlushProgress::default());
+        let waiters: Vec<_> = (0..4)
+            .map(|_| {
+                let progress = progress.clone();
+                tokio::spawn(async move { progress.wait_durable(10).await })
+            })
+            .collect();
+        time::timeout(Duration::from_secs(1), progress.flush_requested())
+            .await
+            .unwrap();
+        // a flush that started before the writes doesn't do
+        assert!(progress.flush_at(5, || true));
+        assert_eq!(progress.durable(), 5);
+        time::timeout(Duration::from_secs(1), progress.flush_requested())
+            .await
+            .unwrap();
+        // but a single flush that started after them wakes up every waiter
+        assert!(progress.flush_at(10, || true));
+        for waiter in waiters {
+            let durable = time::timeout(Duration::from_secs(1), waiter)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(durable);
+        }
+    }
+    #[tokio::test]
+    async fn test_failed_flush_wakes_waiters() {
+        let progress = Arc::new(FlushProgress::default());
+        let waiter_progress = progress.clone();
+        let waiter = tokio::spawn(async move { waiter_progress.wait_durable(1).await });
+        time::timeout(Duration::from_secs(1), progress.flush_requested())
+            .await
+            .unwrap();
+        assert!(!progress.flush_at(1, || false));
+        let durable = time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(!durable);
+        assert_eq!(progress.durable(), 0);
+    }
+}
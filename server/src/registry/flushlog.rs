@@ -0,0 +1,206 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The flush log
+//!
+//! Every flush of the data files (by BGSAVE, and at startup and on shutdown) is recorded along
+//! with what it wrote, so that the flush interval and the dirty bytes mark can be tuned
+//! against the write amplification they lead to: the bytes that were written out for every
+//! byte that was mutated since the previous flush. The last [`FLUSH_LOG_BUFFER`] records are
+//! kept in memory for `SYS FLUSHLOG`, and `SYS STATS` reports the [totals](FlushTotals) since
+//! the server was started
+
+use crate::corestore::lock::QuickLock;
+use crate::storage::flush::{self, FlushReport};
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The number of flush records kept around for `SYS FLUSHLOG`
+pub const FLUSH_LOG_BUFFER: usize = 64;
+
+/// A flush that completed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlushRecord {
+    /// when the flush completed (as an RFC 3339 timestamp in UTC)
+    pub time: String,
+    /// how long the flush took
+    pub duration: Duration,
+    /// what the flush wrote out
+    pub report: FlushReport,
+}
+
+impl FlushRecord {
+    pub fn new(time: DateTime<Utc>, duration: Duration, report: FlushReport) -> Self {
+        Self {
+            time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            duration,
+            report,
+        }
+    }
+}
+
+/// The totals of all the flushes that were recorded
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct FlushTotals {
+    /// the number of flushes
+    pub flushes: u64,
+    /// the number of tables that were written out (a table counts once for every flush)
+    pub tables: u64,
+    /// the bytes that were written out
+    pub bytes: u64,
+    /// the bytes that were mutated in the tables that were written out
+    pub dirty: u64,
+    /// the time spent flushing
+    pub duration: Duration,
+}
+
+impl FlushTotals {
+    /// Returns the bytes written for every byte that was mutated, across all the flushes
+    pub fn write_amplification(&self) -> Option<f64> {
+        flush::write_amplification(self.bytes, self.dirty)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Log {
+    /// the most recent records, oldest first
+    records: VecDeque<FlushRecord>,
+    totals: FlushTotals,
+}
+
+/// The flush log. See the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct FlushLog {
+    log: QuickLock<Log>,
+}
+
+impl Default for FlushLog {
+    fn default() -> Self {
+        Self {
+            log: QuickLock::new(Log::default()),
+        }
+    }
+}
+
+impl FlushLog {
+    /// Add a record to the totals and keep it around, dropping the oldest record if the buffer
+    /// is full
+    pub fn record(&self, record: FlushRecord) {
+        let report = &record.report;
+        log::debug!(
+            "Flushed {} table(s) with {} bytes ({} dirty bytes) in {}ms",
+            report.tables.len(),
+            report.bytes(),
+            report.dirty(),
+            record.duration.as_millis()
+        );
+        let mut log = self.log.lock();
+        log.totals.flushes += 1;
+        log.totals.tables += report.tables.len() as u64;
+        log.totals.bytes += report.bytes();
+        log.totals.dirty += report.dirty() as u64;
+        log.totals.duration += record.duration;
+        if log.records.len() == FLUSH_LOG_BUFFER {
+            log.records.pop_front();
+        }
+        log.records.push_back(record);
+    }
+    /// Returns the last `n` records, oldest first
+    pub fn tail(&self, n: usize) -> Vec<FlushRecord> {
+        let log = self.log.lock();
+        log.records
+            .iter()
+            .skip(log.records.len().saturating_sub(n))
+            .cloned()
+            .collect()
+    }
+    /// Returns the totals of all the flushes that were recorded
+    pub fn totals(&self) -> FlushTotals {
+        self.log.lock().totals
+    }
+}
+
+#[cfg(test)]
+fn record(tables: &[(&str, u64, usize)], metadata: u64) -> FlushRecord {
+    use crate::storage::flush::TableFlush;
+    use chrono::TimeZone;
+    let tables = tables
+        .iter()
+        .map(|(table, bytes, dirty)| TableFlush {
+            table: (*table).to_owned(),
+            bytes: *bytes,
+            dirty: *dirty,
+        })
+        .collect();
+    FlushRecord::new(
+        Utc.ymd(2021, 7, 1).and_hms(10, 0, 0),
+        Duration::from_millis(5),
+        FlushReport { tables, metadata },
+    )
+}
+
+#[test]
+fn test_flush_log_totals() {
+    let log = FlushLog::default();
+    assert_eq!(log.totals(), FlushTotals::default());
+    assert_eq!(log.totals().write_amplification(), None);
+    let first = record(&[("default:default", 100, 25), ("ks:tbl", 50, 0)], 10);
+    assert_eq!(first.report.bytes(), 160);
+    assert_eq!(first.report.dirty(), 25);
+    assert_eq!(first.report.write_amplification(), Some(6.4));
+    assert_eq!(first.time, "2021-07-01T10:00:00.000Z");
+    log.record(first);
+    // nothing was mutated since the last flush
+    let second = record(&[("default:default", 100, 0)], 0);
+    assert_eq!(second.report.write_amplification(), None);
+    log.record(second);
+    let totals = log.totals();
+    assert_eq!(totals.flushes, 2);
+    assert_eq!(totals.tables, 3);
+    assert_eq!(totals.bytes, 260);
+    assert_eq!(totals.dirty, 25);
+    assert_eq!(totals.duration, Duration::from_millis(10));
+    assert_eq!(totals.write_amplification(), Some(10.4));
+}
+
+#[test]
+fn test_flush_log_keeps_the_latest_records() {
+    let log = FlushLog::default();
+    for bytes in 0..FLUSH_LOG_BUFFER as u64 + 2 {
+        log.record(record(&[], bytes));
+    }
+    let records = log.tail(FLUSH_LOG_BUFFER * 2);
+    assert_eq!(records.len(), FLUSH_LOG_BUFFER);
+    assert_eq!(records[0].report.metadata, 2);
+    let last_two: Vec<u64> = log.tail(2).iter().map(|r| r.report.metadata).collect();
+    assert_eq!(
+        last_two,
+        vec![FLUSH_LOG_BUFFER as u64, FLUSH_LOG_BUFFER as u64 + 1]
+    );
+    // the totals still count the records that were dropped
+    assert_eq!(log.totals().flushes, FLUSH_LOG_BUFFER as u64 + 2);
+}
@@ -0,0 +1,77 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The write gate
+//!
+//! The writes made by clients pass through the write gate, which `EXEC` closes while it runs
+//! the queries of a transaction so that no other client's write lands between them. Any
+//! number of writes can be in the gate at once, so writes only ever wait on a transaction
+//! (and a transaction waits for the writes that are already in the gate to complete)
+
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A gate that writes pass through and that transactions close
+#[derive(Debug, Default)]
+pub struct WriteGate {
+    lock: RwLock<()>,
+}
+
+impl WriteGate {
+    /// Pass through the gate, waiting for it to open if a transaction is running. Hold on to
+    /// the guard until the write completes
+    pub async fn pass(&self) -> RwLockReadGuard<'_, ()> {
+        self.lock.read().await
+    }
+    /// Close the gate, waiting for the writes in it to complete. It stays closed to every
+    /// other writer until the guard is dropped
+    pub async fn close(&self) -> RwLockWriteGuard<'_, ()> {
+        self.lock.write().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WriteGate;
+    use tokio::time::{self, Duration};
+
+    #[tokio::test]
+    async fn test_writes_wait_for_a_closed_gate() {
+        let gate = WriteGate::default();
+        // writes don't hold each other out
+        let (first, second) = (gate.pass().await, gate.pass().await);
+        // but a transaction waits for them
+        assert!(time::timeout(Duration::from_millis(100), gate.close())
+            .await
+            .is_err());
+        drop((first, second));
+        let closed = gate.close().await;
+        assert!(time::timeout(Duration::from_millis(100), gate.pass())
+            .await
+            .is_err());
+        drop(closed);
+        drop(gate.pass().await);
+    }
+}
@@ -0,0 +1,359 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The lock registry
+//!
+//! The locks that can hold up everything else (the snapshot lock, the flush lock and the
+//! `PARTMAP` locks of the keyspaces) record who holds them and since when in a fixed slab of
+//! [`LOCK_SLOTS`] slots, which `SYS LOCKS` reads to tell which lock a stalled server is stuck
+//! on. A record is a single atomic word, so recording a lock is a compare-and-swap on
+//! acquisition and a store on release; the locks themselves behave just as they would
+//! without it. If every slot is taken, the lock is still acquired but isn't recorded.
+//!
+//! Alongside the locks that are held right now, the longest hold of every lock that ended in
+//! the last [`RECENT_SECS`] seconds is kept
+
+use core::cmp::Reverse;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The number of locks that can be recorded as held at the same time
+pub const LOCK_SLOTS: usize = 64;
+/// For how long (in seconds after it ended) a hold counts towards the longest recent hold
+pub const RECENT_SECS: u64 = 60;
+/// The bits of a record that hold the time at which the lock was acquired
+const TIME_MASK: u64 = (1 << 48) - 1;
+/// The number of locks that are recorded
+const KINDS: usize = 3;
+// nothing else is ordered by the records, they're only ever read for `SYS LOCKS`
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// The locks that are recorded
+pub enum LockKind {
+    /// The snapshot lock (see [`SnapLock`](crate::corestore::snaplock::SnapLock))
+    Snapshot = 1,
+    /// The global flush lock
+    Flush = 2,
+    /// The `PARTMAP` lock of a keyspace
+    Partmap = 3,
+}
+
+impl LockKind {
+    /// Every lock that is recorded, in the order that `SYS LOCKS` reports them in
+    pub const ALL: [Self; KINDS] = [Self::Snapshot, Self::Flush, Self::Partmap];
+    /// Returns the name of the lock, as reported by `SYS LOCKS`
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Snapshot => "snapshot",
+            Self::Flush => "flush",
+            Self::Partmap => "partmap",
+        }
+    }
+    const fn index(&self) -> usize {
+        *self as usize - 1
+    }
+    fn from_u8(code: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| *kind as u8 == code)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+/// Who holds a lock
+pub enum LockHolder {
+    /// The snapshot service
+    Scheduler = 1,
+    /// A client's `MKSNAP`
+    Mksnap = 2,
+    /// A client's `SYS COMPACT`
+    Compact = 3,
+    /// BGSAVE
+    Bgsave = 4,
+    /// A client's `CREATE` (or any other change to the keyspaces and tables)
+    Ddl = 5,
+    /// The consistency checker
+    Selfcheck = 6,
+}
+
+impl LockHolder {
+    const ALL: [Self; 6] = [
+        Self::Scheduler,
+        Self::Mksnap,
+        Self::Compact,
+        Self::Bgsave,
+        Self::Ddl,
+        Self::Selfcheck,
+    ];
+    /// Returns the name of the holder, as reported by `SYS LOCKS`
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Self::Scheduler => "scheduler",
+            Self::Mksnap => "mksnap",
+            Self::Compact => "compact",
+            Self::Bgsave => "bgsave",
+            Self::Ddl => "ddl",
+            Self::Selfcheck => "selfcheck",
+        }
+    }
+    fn from_u8(code: u8) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|holder| *holder as u8 == code)
+    }
+}
+
+/// The current time, in milliseconds since the UNIX epoch
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// A held lock, packed into a word: the lock, the holder and the time it was acquired at (a zero
+/// is a free slot)
+fn pack(kind: LockKind, holder: LockHolder, since_ms: u64) -> u64 {
+    ((kind as u64) << 56) | ((holder as u64) << 48) | (since_ms & TIME_MASK)
+}
+
+fn unpack(record: u64) -> Option<(LockKind, LockHolder, u64)> {
+    let kind = LockKind::from_u8((record >> 56) as u8)?;
+    let holder = LockHolder::from_u8((record >> 48) as u8)?;
+    Some((kind, holder, record & TIME_MASK))
+}
+
+/// A lock that is held right now, as reported by [`LockTable::held`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeldLock {
+    pub kind: LockKind,
+    pub holder: LockHolder,
+    /// for how long it has been held
+    pub held: Duration,
+}
+
+/// The slab that the held locks are recorded in
+pub struct LockTable {
+    slots: [AtomicU64; LOCK_SLOTS],
+    /// the longest recent hold of every lock, as the milliseconds it was held for (in the
+    /// upper half) and the second (since the UNIX epoch) it ended at
+    longest: [AtomicU64; KINDS],
+}
+
+/// Records a lock as held until it is dropped
+#[must_use]
+pub struct LockRecord<'a> {
+    table: &'a LockTable,
+    kind: LockKind,
+    /// the slot of the record, unless every slot was taken
+    slot: Option<usize>,
+    since_ms: u64,
+}
+
+impl LockTable {
+    pub const fn new() -> Self {
+        const FREE: AtomicU64 = AtomicU64::new(0);
+        Self {
+            slots: [FREE; LOCK_SLOTS],
+            longest: [FREE; KINDS],
+        }
+    }
+    /// Record that `holder` just acquired the lock `kind`
+    pub fn record(&self, kind: LockKind, holder: LockHolder) -> LockRecord<'_> {
+        let since_ms = now_ms();
+        let record = pack(kind, holder, since_ms);
+        let slot = self
+            .slots
+            .iter()
+            .position(|slot| slot.compare_exchange(0, record, ORD_RLX, ORD_RLX).is_ok());
+        LockRecord {
+            table: self,
+            kind,
+            slot,
+            since_ms,
+        }
+    }
+    /// Returns the locks that have been held for at least `min`, the longest held first
+    pub fn held(&self, min: Duration) -> Vec<HeldLock> {
+        let now = now_ms();
+        let mut held: Vec<HeldLock> = self
+            .slots
+            .iter()
+            .filter_map(|slot| unpack(slot.load(ORD_RLX)))
+            .map(|(kind, holder, since_ms)| HeldLock {
+                kind,
+                holder,
+                held: Duration::from_millis(now.saturating_sub(since_ms)),
+            })
+            .filter(|lock| lock.held >= min)
+            .collect();
+        held.sort_by_key(|lock| Reverse(lock.held));
+        held
+    }
+    /// Returns the longest hold of `kind` that ended in the last [`RECENT_SECS`] seconds (or
+    /// zero if there was none)
+    pub fn longest_recent(&self, kind: LockKind) -> Duration {
+        let longest = self.longest[kind.index()].load(ORD_RLX);
+        let ended_s = longest & u32::MAX as u64;
+        if (now_ms() / 1000).saturating_sub(ended_s) >= RECENT_SECS {
+            Duration::from_millis(0)
+        } else {
+            Duration::from_millis(longest >> 32)
+        }
+    }
+    fn release(&self, kind: LockKind, since_ms: u64) {
+        let now = now_ms();
+        let held_ms = now.saturating_sub(since_ms).min(u32::MAX as u64);
+        let ended_s = (now / 1000) & u32::MAX as u64;
+        let ours = (held_ms << 32) | ended_s;
+        let longest = &self.longest[kind.index()];
+        let mut current = longest.load(ORD_RLX);
+        // an older longest hold is replaced once it's no longer recent
+        while held_ms > current >> 32
+            || ended_s.saturating_sub(current & u32::MAX as u64) >= RECENT_SECS
+        {
+            match longest.compare_exchange_weak(current, ours, ORD_RLX, ORD_RLX) {
+                Ok(_) => break,
+                Err(now_longest) => current = now_longest,
+            }
+        }
+    }
+}
+
+impl<'a> Drop for LockRecord<'a> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            self.table.slots[slot].store(0, ORD_RLX);
+        }
+        self.table.release(self.kind, self.since_ms);
+    }
+}
+
+/// A lock guard that is recorded in the [lock registry](super::get_locks) for as long as it's
+/// held
+pub struct Recorded<G> {
+    // the record goes before the guard, so that it's cleared before the lock is released (and
+    // the next holder can't have its record cleared by us)
+    _record: LockRecord<'static>,
+    _guard: G,
+}
+
+impl<G> Recorded<G> {
+    /// Record `guard` (which was just acquired) as the lock `kind` held by `holder`
+    pub fn new(kind: LockKind, holder: LockHolder, guard: G) -> Self {
+        Self {
+            _record: super::get_locks().record(kind, holder),
+            _guard: guard,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_pack_unpack() {
+        let record = pack(LockKind::Flush, LockHolder::Bgsave, 1_600_000_000_123);
+        assert_eq!(
+            unpack(record),
+            Some((LockKind::Flush, LockHolder::Bgsave, 1_600_000_000_123))
+        );
+        assert_eq!(unpack(0), None);
+    }
+
+    #[test]
+    fn test_held_locks_are_reported() {
+        let table = LockTable::new();
+        assert!(table.held(Duration::from_millis(0)).is_empty());
+        let snapshot = table.record(LockKind::Snapshot, LockHolder::Mksnap);
+        thread::sleep(Duration::from_millis(30));
+        let flush = table.record(LockKind::Flush, LockHolder::Compact);
+        let held = table.held(Duration::from_millis(0));
+        assert_eq!(held.len(), 2);
+        // the longest held first
+        assert_eq!(
+            (held[0].kind, held[0].holder),
+            (LockKind::Snapshot, LockHolder::Mksnap)
+        );
+        assert!(held[0].held >= Duration::from_millis(30));
+        assert_eq!(
+            (held[1].kind, held[1].holder),
+            (LockKind::Flush, LockHolder::Compact)
+        );
+        // only the locks held for long enough
+        let held = table.held(Duration::from_millis(30));
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].kind, LockKind::Snapshot);
+        drop(snapshot);
+        let held = table.held(Duration::from_millis(0));
+        assert_eq!(held.len(), 1);
+        assert_eq!(held[0].kind, LockKind::Flush);
+        drop(flush);
+        assert!(table.held(Duration::from_millis(0)).is_empty());
+    }
+
+    #[test]
+    fn test_longest_recent_hold() {
+        let table = LockTable::new();
+        assert_eq!(
+            table.longest_recent(LockKind::Snapshot),
+            Duration::from_millis(0)
+        );
+        {
+            let _record = table.record(LockKind::Snapshot, LockHolder::Scheduler);
+            thread::sleep(Duration::from_millis(30));
+        }
+        let longest = table.longest_recent(LockKind::Snapshot);
+        assert!(longest >= Duration::from_millis(30));
+        // a shorter hold doesn't replace it
+        drop(table.record(LockKind::Snapshot, LockHolder::Mksnap));
+        assert_eq!(table.longest_recent(LockKind::Snapshot), longest);
+        // and the other locks have their own
+        assert_eq!(
+            table.longest_recent(LockKind::Flush),
+            Duration::from_millis(0)
+        );
+    }
+
+    #[test]
+    fn test_full_slab() {
+        let table = LockTable::new();
+        let records: Vec<_> = (0..LOCK_SLOTS)
+            .map(|_| table.record(LockKind::Partmap, LockHolder::Ddl))
+            .collect();
+        // this one isn't recorded, but releasing it doesn't clear anyone else's record
+        drop(table.record(LockKind::Flush, LockHolder::Bgsave));
+        let held = table.held(Duration::from_millis(0));
+        assert_eq!(held.len(), LOCK_SLOTS);
+        assert!(held.iter().all(|lock| lock.kind == LockKind::Partmap));
+        drop(records);
+        assert!(table.held(Duration::from_millis(0)).is_empty());
+    }
+}
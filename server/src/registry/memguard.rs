@@ -0,0 +1,152 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The memory guard
+//!
+//! If the server runs out of memory, it gets killed and loses everything since the last flush,
+//! so a memory ceiling can be configured instead. The [memory watcher] samples what the
+//! process uses and once that crosses the ceiling, the guard blocks writes: they're rejected
+//! with `err-out-of-memory` while reads are still served, and so are the writes that free
+//! memory (`DEL`, `SDEL` and `FLUSHDB`). Writes are allowed again once the usage drops below
+//! [`RESUME_PERCENT`] of the ceiling, so that the server doesn't flip back and forth while it
+//! hovers right around the ceiling
+//!
+//! [memory watcher]: crate::services::memwatch
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Writes are allowed again once the memory usage drops below this percentage of the ceiling
+pub const RESUME_PERCENT: usize = 90;
+
+const ORD_ACQ: Ordering = Ordering::Acquire;
+const ORD_REL: Ordering = Ordering::Release;
+
+/// Blocks writes while the memory that the process uses is above the configured ceiling. See
+/// the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct MemoryGuard {
+    /// the ceiling in bytes (`0` if there's none)
+    ceiling: AtomicUsize,
+    /// the last sampled usage in bytes
+    usage: AtomicUsize,
+    /// set while writes are blocked
+    blocking: AtomicBool,
+}
+
+impl MemoryGuard {
+    pub const fn new() -> Self {
+        Self {
+            ceiling: AtomicUsize::new(0),
+            usage: AtomicUsize::new(0),
+            blocking: AtomicBool::new(false),
+        }
+    }
+    /// Set the ceiling in bytes, or remove it with `None` (which allows writes again)
+    pub fn configure(&self, ceiling: Option<usize>) {
+        self.ceiling.store(ceiling.unwrap_or(0), ORD_REL);
+        if ceiling.is_none() {
+            self.blocking.store(false, ORD_REL);
+        }
+    }
+    /// Returns the ceiling in bytes, if there is one
+    pub fn ceiling(&self) -> Option<usize> {
+        match self.ceiling.load(ORD_ACQ) {
+            0 => None,
+            ceiling => Some(ceiling),
+        }
+    }
+    /// Returns the last sampled memory usage in bytes
+    pub fn usage(&self) -> usize {
+        self.usage.load(ORD_ACQ)
+    }
+    /// Returns true if writes are blocked since the memory usage is too high
+    pub fn is_blocking(&self) -> bool {
+        self.blocking.load(ORD_ACQ)
+    }
+    /// Record the memory usage that was just sampled, blocking or allowing writes depending on
+    /// it. This returns the new state if it changed: `Some(true)` if writes were just blocked
+    /// and `Some(false)` if they were just allowed again
+    pub fn observe(&self, usage: usize) -> Option<bool> {
+        self.usage.store(usage, ORD_REL);
+        let ceiling = self.ceiling()?;
+        let blocking = self.is_blocking();
+        let now_blocking = if blocking {
+            usage >= self::resume_below(ceiling)
+        } else {
+            usage > ceiling
+        };
+        if now_blocking == blocking {
+            None
+        } else {
+            self.blocking.store(now_blocking, ORD_REL);
+            Some(now_blocking)
+        }
+    }
+}
+
+/// The usage that writes are allowed again below, [`RESUME_PERCENT`] of `ceiling` (this
+/// doesn't overflow, even for the largest ceilings)
+const fn resume_below(ceiling: usize) -> usize {
+    ceiling / 100 * RESUME_PERCENT + ceiling % 100 * RESUME_PERCENT / 100
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MemoryGuard;
+
+    #[test]
+    fn test_guard_blocks_above_the_ceiling() {
+        let guard = MemoryGuard::new();
+        // nothing is blocked without a ceiling
+        assert_eq!(guard.observe(usize::MAX), None);
+        assert!(!guard.is_blocking());
+        guard.configure(Some(1000));
+        assert_eq!(guard.observe(1000), None);
+        assert_eq!(guard.observe(1001), Some(true));
+        assert!(guard.is_blocking());
+        assert_eq!(guard.observe(2000), None);
+        assert_eq!(guard.usage(), 2000);
+    }
+
+    #[test]
+    fn test_guard_resumes_below_the_threshold() {
+        let guard = MemoryGuard::new();
+        guard.configure(Some(1000));
+        assert_eq!(guard.observe(1500), Some(true));
+        // dropping just below the ceiling isn't enough
+        assert_eq!(guard.observe(999), None);
+        assert_eq!(guard.observe(900), None);
+        assert!(guard.is_blocking());
+        assert_eq!(guard.observe(899), Some(false));
+        assert!(!guard.is_blocking());
+        // and it blocks again once the ceiling is crossed
+        assert_eq!(guard.observe(950), None);
+        assert_eq!(guard.observe(1001), Some(true));
+        // removing the ceiling allows writes right away
+        guard.configure(None);
+        assert!(!guard.is_blocking());
+    }
+}
@@ -0,0 +1,442 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Latency histograms
+//!
+//! The time that every action takes to run, that every flush and that every snapshot takes
+//! is recorded in a [`Histogram`] with fixed buckets: by default these are log-scaled from
+//! 100µs to 10s ([`DEFAULT_BUCKETS`]), but they can be set in the `[metrics]` section of the
+//! config file. `SYS METRICS` returns them in a compact form and `SYS METRICS PROMETHEUS` in
+//! the Prometheus text exposition format, so that they can be aggregated across servers.
+//!
+//! Recording a duration is three relaxed increments: the bucket is found from the number of
+//! leading zeros of the duration (in microseconds), which narrows it down to the buckets
+//! within the same power of two. The histogram of an action is only created when the action
+//! is first run, and the histograms are only ever reset with `SYS METRICS RESET`
+
+use crate::corestore::lazy::LazyCell;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The upper bounds of the buckets (in microseconds) that are used if none are configured
+pub const DEFAULT_BUCKETS: [u64; 16] = [
+    100, 250, 500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000,
+    1_000_000, 2_500_000, 5_000_000, 10_000_000,
+];
+/// The most buckets that a histogram can have (not counting the one for everything larger)
+pub const MAX_BUCKETS: usize = 64;
+
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// The upper bounds of the buckets of a histogram, in microseconds. They're strictly
+/// increasing and the last one is followed by a bucket for everything that's larger
+#[derive(Debug)]
+pub struct Buckets {
+    bounds: Box<[u64]>,
+    /// the first bucket that a duration with `i` significant bits can be in
+    first: [u8; 65],
+}
+
+impl Buckets {
+    /// Create the buckets with the upper bounds `bounds`, which have to be strictly increasing
+    /// and can't be more than [`MAX_BUCKETS`] (the config is checked for this, see
+    /// [`check_buckets`])
+    pub fn new(bounds: Vec<u64>) -> Self {
+        assert!(self::check_buckets(&bounds).is_ok());
+        let mut first = [0u8; 65];
+        for (bits, first) in first.iter_mut().enumerate().skip(1) {
+            // the smallest duration with that many significant bits
+            let smallest = 1u64 << (bits - 1);
+            *first = bounds.iter().take_while(|bound| **bound < smallest).count() as u8;
+        }
+        Self {
+            bounds: bounds.into_boxed_slice(),
+            first,
+        }
+    }
+    /// Returns the bucket that `micros` falls in: the first one whose upper bound is at least
+    /// `micros`, or the one after the last bound if it's larger than all of them
+    fn index_of(&self, micros: u64) -> usize {
+        let bits = (64 - micros.leading_zeros()) as usize;
+        let mut index = self.first[bits] as usize;
+        while index < self.bounds.len() && self.bounds[index] < micros {
+            index += 1;
+        }
+        index
+    }
+    /// Returns the upper bounds, in microseconds
+    pub fn bounds(&self) -> &[u64] {
+        &self.bounds
+    }
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self::new(DEFAULT_BUCKETS.to_vec())
+    }
+}
+
+/// Check that `bounds` can be the upper bounds of the buckets of a histogram
+pub fn check_buckets(bounds: &[u64]) -> Result<(), &'static str> {
+    if bounds.is_empty() || bounds.len() > MAX_BUCKETS {
+        return Err("A histogram has to have between 1 and 64 buckets!");
+    }
+    if bounds[0] == 0 || bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return Err(
+            "The bounds of the histogram buckets have to be greater than 0 and increasing!",
+        );
+    }
+    Ok(())
+}
+
+/// A histogram of durations. See the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct Histogram {
+    buckets: Arc<Buckets>,
+    /// the number of durations in every bucket (not cumulative), with the one for everything
+    /// larger than the last bound at the end
+    counts: Box<[AtomicU64]>,
+    /// the sum of the durations in microseconds
+    sum: AtomicU64,
+}
+
+/// The values of a histogram at some point in time
+#[derive(Debug, PartialEq)]
+pub struct HistogramValues {
+    /// the number of durations in every bucket (not cumulative), with the one for everything
+    /// larger than the last bound at the end
+    pub counts: Vec<u64>,
+    /// the sum of the durations in microseconds
+    pub sum: u64,
+}
+
+impl HistogramValues {
+    /// Returns the number of durations that were recorded
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+}
+
+impl Histogram {
+    pub fn new(buckets: Arc<Buckets>) -> Self {
+        let counts = (0..=buckets.bounds.len())
+            .map(|_| AtomicU64::new(0))
+            .collect();
+        Self {
+            buckets,
+            counts,
+            sum: AtomicU64::new(0),
+        }
+    }
+    /// Record `took`
+    pub fn record(&self, took: Duration) {
+        let micros = took.as_micros().min(u64::MAX as u128) as u64;
+        self.counts[self.buckets.index_of(micros)].fetch_add(1, ORD_RLX);
+        self.sum.fetch_add(micros, ORD_RLX);
+    }
+    /// Returns the current values. Since nothing is locked, a duration that is recorded at
+    /// the same time may be in the counts but not in the sum (or the other way around)
+    pub fn values(&self) -> HistogramValues {
+        HistogramValues {
+            counts: self
+                .counts
+                .iter()
+                .map(|count| count.load(ORD_RLX))
+                .collect(),
+            sum: self.sum.load(ORD_RLX),
+        }
+    }
+    /// Zero the counts and the sum
+    pub fn reset(&self) {
+        self.counts.iter().for_each(|count| count.store(0, ORD_RLX));
+        self.sum.store(0, ORD_RLX);
+    }
+}
+
+/// The latency histograms of the server
+pub struct Metrics {
+    buckets: Arc<Buckets>,
+    /// the names of the actions, in the order of [`Self::actions`]
+    names: &'static [&'static [u8]],
+    /// the histogram of every action, which is only created once it's first run
+    actions: Box<[LazyCell<Histogram>]>,
+    flush: Histogram,
+    snapshot: Histogram,
+}
+
+impl Metrics {
+    /// Create the histograms with `buckets`, for the actions named in `names`
+    pub fn new(buckets: Buckets, names: &'static [&'static [u8]]) -> Self {
+        let buckets = Arc::new(buckets);
+        Self {
+            names,
+            actions: names.iter().map(|_| LazyCell::new()).collect(),
+            flush: Histogram::new(buckets.clone()),
+            snapshot: Histogram::new(buckets.clone()),
+            buckets,
+        }
+    }
+    /// Record that the action at `index` (in the names this was created with) took `took`
+    pub fn record_action(&self, index: usize, took: Duration) {
+        self.actions[index]
+            .get_or_init(|| Histogram::new(self.buckets.clone()))
+            .record(took)
+    }
+    /// Record that a flush took `took`
+    pub fn record_flush(&self, took: Duration) {
+        self.flush.record(took)
+    }
+    /// Record that a snapshot took `took`
+    pub fn record_snapshot(&self, took: Duration) {
+        self.snapshot.record(took)
+    }
+    /// Returns the upper bounds of the buckets, in microseconds
+    pub fn bounds(&self) -> &[u64] {
+        self.buckets.bounds()
+    }
+    /// Returns the histograms of the actions that were run, with their names
+    fn used_actions(&self) -> impl Iterator<Item = (&str, &Histogram)> {
+        self.names
+            .iter()
+            .zip(self.actions.iter())
+            .filter_map(|(name, histogram)| {
+                // the names are ASCII
+                histogram
+                    .get()
+                    .map(|histogram| (core::str::from_utf8(name).unwrap_or("?"), histogram))
+            })
+    }
+    /// Zero every histogram
+    pub fn reset(&self) {
+        self.actions
+            .iter()
+            .filter_map(LazyCell::get)
+            .for_each(Histogram::reset);
+        self.flush.reset();
+        self.snapshot.reset();
+    }
+    /// Returns every histogram as a `<name> <value>` pair: `flush`, `snapshot` and then
+    /// `action:<name>` for every action that was run. The value has the `count`, the `sum_us`
+    /// and then `<bound>:<count>` for every bucket that isn't empty (`inf` for the durations
+    /// larger than the last bound), all separated by spaces
+    pub fn compact(&self) -> Vec<(String, String)> {
+        let mut pairs = vec![
+            ("flush".to_owned(), self.compact_one(&self.flush)),
+            ("snapshot".to_owned(), self.compact_one(&self.snapshot)),
+        ];
+        pairs
+            .extend(self.used_actions().map(|(name, histogram)| {
+                (format!("action:{}", name), self.compact_one(histogram))
+            }));
+        pairs
+    }
+    fn compact_one(&self, histogram: &Histogram) -> String {
+        let values = histogram.values();
+        let mut compact = format!("count={} sum_us={}", values.count(), values.sum);
+        for (i, count) in values.counts.iter().enumerate() {
+            if *count == 0 {
+                continue;
+            }
+            match self.bounds().get(i) {
+                Some(bound) => write!(compact, " {}:{}", bound, count),
+                None => write!(compact, " inf:{}", count),
+            }
+            .unwrap();
+        }
+        compact
+    }
+    /// Returns every histogram in the Prometheus text exposition format: the time the actions
+    /// took to run (`skyd_action_duration_seconds`, labelled with the `action`), the flushes
+    /// (`skyd_flush_duration_seconds`) and the snapshots (`skyd_snapshot_duration_seconds`)
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        Self::write_header(
+            &mut out,
+            "skyd_action_duration_seconds",
+            "The time that the actions took to run",
+        );
+        for (name, histogram) in self.used_actions() {
+            let label = format!("action=\"{}\"", name);
+            self.write_histogram(&mut out, "skyd_action_duration_seconds", &label, histogram);
+        }
+        Self::write_header(
+            &mut out,
+            "skyd_flush_duration_seconds",
+            "The time that the flushes took",
+        );
+        self.write_histogram(&mut out, "skyd_flush_duration_seconds", "", &self.flush);
+        Self::write_header(
+            &mut out,
+            "skyd_snapshot_duration_seconds",
+            "The time that the snapshots took",
+        );
+        self.write_histogram(
+            &mut out,
+            "skyd_snapshot_duration_seconds",
+            "",
+            &self.snapshot,
+        );
+        out
+    }
+    fn write_header(out: &mut String, name: &str, help: &str) {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} histogram", name).unwrap();
+    }
+    fn write_histogram(&self, out: &mut String, name: &str, label: &str, histogram: &Histogram) {
+        let values = histogram.values();
+        // the buckets are cumulative in the exposition format
+        let mut cumulative = 0;
+        let separator = if label.is_empty() { "" } else { "," };
+        for (i, count) in values.counts.iter().enumerate() {
+            cumulative += count;
+            let bound = match self.bounds().get(i) {
+                Some(bound) => self::seconds(*bound),
+                None => "+Inf".to_owned(),
+            };
+            writeln!(
+                out,
+                "{}_bucket{{{}{}le=\"{}\"}} {}",
+                name, label, separator, bound, cumulative
+            )
+            .unwrap();
+        }
+        let labels = if label.is_empty() {
+            String::new()
+        } else {
+            format!("{{{}}}", label)
+        };
+        writeln!(out, "{}_sum{} {}", name, labels, self::seconds(values.sum)).unwrap();
+        writeln!(out, "{}_count{} {}", name, labels, cumulative).unwrap();
+    }
+}
+
+/// Returns `micros` in seconds, as the exposition format has them
+fn seconds(micros: u64) -> String {
+    (micros as f64 / 1_000_000.0).to_string()
+}
+
+#[test]
+fn test_histogram_buckets() {
+    let histogram = Histogram::new(Arc::new(Buckets::default()));
+    // on a bound, just above it and past the last one
+    for micros in [
+        0,
+        100,
+        101,
+        999,
+        1_000,
+        7_000,
+        10_000_000,
+        10_000_001,
+        u64::MAX,
+    ]
+    .iter()
+    {
+        histogram.record(Duration::from_micros(*micros));
+    }
+    let values = histogram.values();
+    let mut expected = vec![0; 17];
+    expected[0] = 2; // 0 and 100
+    expected[1] = 1; // 101
+    expected[3] = 2; // 999 and 1000
+    expected[6] = 1; // 7000
+    expected[15] = 1; // 10s
+    expected[16] = 2; // the rest
+    assert_eq!(values.counts, expected);
+    assert_eq!(values.count(), 9);
+    histogram.reset();
+    assert_eq!(histogram.values().count(), 0);
+    assert_eq!(histogram.values().sum, 0);
+}
+
+#[test]
+fn test_histogram_custom_buckets() {
+    // several bounds with the same number of significant bits
+    let buckets = Buckets::new(vec![5, 6, 7, 8, 1_000]);
+    let histogram = Histogram::new(Arc::new(buckets));
+    for micros in 1..=10 {
+        histogram.record(Duration::from_micros(micros));
+    }
+    histogram.record(Duration::from_micros(2_000));
+    let values = histogram.values();
+    assert_eq!(values.counts, vec![5, 1, 1, 1, 2, 1]);
+    assert_eq!(values.sum, 55 + 2_000);
+    assert!(check_buckets(&[]).is_err());
+    assert!(check_buckets(&[0, 1]).is_err());
+    assert!(check_buckets(&[2, 2]).is_err());
+    assert!(check_buckets(&[1; MAX_BUCKETS + 1]).is_err());
+}
+
+#[test]
+fn test_metrics_exposition() {
+    const NAMES: &[&[u8]] = &[b"GET", b"SET"];
+    let metrics = Metrics::new(Buckets::new(vec![1_000, 1_000_000]), NAMES);
+    metrics.record_action(1, Duration::from_micros(500));
+    metrics.record_action(1, Duration::from_millis(30));
+    metrics.record_action(1, Duration::from_secs(2));
+    metrics.record_flush(Duration::from_micros(250));
+    // GET was never run, so it doesn't have a histogram
+    assert!(metrics.actions[0].get().is_none());
+    assert_eq!(
+        metrics.compact(),
+        vec![
+            ("flush".to_owned(), "count=1 sum_us=250 1000:1".to_owned()),
+            ("snapshot".to_owned(), "count=0 sum_us=0".to_owned()),
+            (
+                "action:SET".to_owned(),
+                "count=3 sum_us=2030500 1000:1 1000000:1 inf:1".to_owned()
+            ),
+        ]
+    );
+    let expected = "\
+# HELP skyd_action_duration_seconds The time that the actions took to run
+# TYPE skyd_action_duration_seconds histogram
+skyd_action_duration_seconds_bucket{action=\"SET\",le=\"0.001\"} 1
+skyd_action_duration_seconds_bucket{action=\"SET\",le=\"1\"} 2
+skyd_action_duration_seconds_bucket{action=\"SET\",le=\"+Inf\"} 3
+skyd_action_duration_seconds_sum{action=\"SET\"} 2.0305
+skyd_action_duration_seconds_count{action=\"SET\"} 3
+# HELP skyd_flush_duration_seconds The time that the flushes took
+# TYPE skyd_flush_duration_seconds histogram
+skyd_flush_duration_seconds_bucket{le=\"0.001\"} 1
+skyd_flush_duration_seconds_bucket{le=\"1\"} 1
+skyd_flush_duration_seconds_bucket{le=\"+Inf\"} 1
+skyd_flush_duration_seconds_sum 0.00025
+skyd_flush_duration_seconds_count 1
+# HELP skyd_snapshot_duration_seconds The time that the snapshots took
+# TYPE skyd_snapshot_duration_seconds histogram
+skyd_snapshot_duration_seconds_bucket{le=\"0.001\"} 0
+skyd_snapshot_duration_seconds_bucket{le=\"1\"} 0
+skyd_snapshot_duration_seconds_bucket{le=\"+Inf\"} 0
+skyd_snapshot_duration_seconds_sum 0
+skyd_snapshot_duration_seconds_count 0
+";
+    assert_eq!(metrics.prometheus(), expected);
+    metrics.reset();
+    assert_eq!(metrics.compact()[2].1, "count=0 sum_us=0");
+}
@@ -29,14 +29,85 @@
 //! The registry module provides interfaces for system-wide, global state management
 //!
 
+use crate::config::{OrphanPolicy, ParanoidMode};
+use crate::corestore::lazy::{Lazy, LazyCell};
 use crate::corestore::lock::{QLGuard, QuickLock};
+use crate::storage::reconcile::RecoveryReport;
 use core::sync::atomic::AtomicBool;
+use core::sync::atomic::AtomicU64;
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::AtomicUsize;
 use core::sync::atomic::Ordering;
+#[cfg(test)]
+use std::cell::Cell;
+
+mod background;
+mod backpressure;
+mod changes;
+mod clients;
+mod compression;
+mod ddllog;
+mod durability;
+mod flushlog;
+mod gate;
+mod locks;
+mod memguard;
+mod metrics;
+mod pipeline;
+mod selfcheck;
+mod shutdown;
+mod slowlog;
+mod state;
+mod throttle;
+mod trace;
+mod txnquota;
+pub use background::{BackgroundControl, ServiceControl, DEFAULT_MAX_PAUSE};
+pub use backpressure::DirtyTracker;
+pub use changes::{
+    Change, ChangeLog, EventReader, KeyEvent, Mutation, TooFarBehind, DEFAULT_SYNC_BUFFER,
+};
+pub use clients::{ClientStats, Clients};
+pub use compression::{CompressionSettings, DEFAULT_COMPRESSION_THRESHOLD};
+pub use ddllog::{ddl_log_path, DdlLog, DdlRecord};
+pub use durability::FlushProgress;
+pub use flushlog::{FlushLog, FlushRecord};
+pub use gate::WriteGate;
+pub use locks::{LockHolder, LockKind, LockRecord, LockTable, Recorded};
+pub use memguard::MemoryGuard;
+use metrics::Buckets;
+pub use metrics::{check_buckets, Metrics};
+pub use pipeline::{
+    PipelineLimits, DEFAULT_MAX_INFLIGHT, DEFAULT_MAX_INFLIGHT_BYTES, DEFAULT_MAX_PIPELINE_DEPTH,
+};
+pub use selfcheck::SelfCheck;
+pub use shutdown::{ShutdownKind, ShutdownRequest};
+pub use slowlog::{SlowLog, SlowStages, DEFAULT_SLOWLOG_SIZE, DEFAULT_SLOWLOG_THRESHOLD};
+#[cfg(test)]
+pub use state::override_state;
+use state::AtomicState;
+pub use state::SystemState;
+pub use throttle::{IoKind, IoThrottle, SnapshotProgress, TokenBucket};
+pub use trace::{QueryTrace, Tracer};
+pub use txnquota::{
+    TxnQuota, DEFAULT_MAX_QUEUED, DEFAULT_MAX_TOTAL_QUEUED, DEFAULT_MAX_TOTAL_TXN_BYTES,
+    DEFAULT_MAX_TXN_BYTES,
+};
 
 const ORD_ACQ: Ordering = Ordering::Acquire;
 const ORD_REL: Ordering = Ordering::Release;
 const ORD_SEQ: Ordering = Ordering::SeqCst;
 
+/// The longest name (in bytes) that a new keyspace or table can have by default. This is also
+/// the most that can be configured, since it's all that an `ObjectID` can hold
+pub const DEFAULT_MAX_NAME_LEN: usize = 64;
+/// The most threads that the tables are read in with at startup by default
+pub const DEFAULT_LOAD_THREADS: usize = 8;
+/// The most keys that a table can have for `LSKEYS ... ORDERED` to sort them by default
+pub const DEFAULT_MAX_ORDERED_KEYS: usize = 1_000_000;
+/// The false positive rate of the bloom filters that are written along with the tables by
+/// default
+pub const DEFAULT_BLOOM_FP_RATE: f64 = 0.01;
+
 /// A digital _trip switch_ that can be tripped and untripped in a thread
 /// friendly, consistent manner. It is slightly expensive on processors
 /// with weaker memory ordering (like ARM) when compared to the native
@@ -74,34 +145,622 @@ impl Trip {
 }
 
 /// The global system health
-static GLOBAL_STATE: AtomicBool = AtomicBool::new(true);
+static GLOBAL_STATE: AtomicState = AtomicState::new(SystemState::Okay);
+/// Whether reads are served while writes are blocked
+static STALE_READS: AtomicBool = AtomicBool::new(true);
+/// The largest value (in bytes) that can be written to a table that doesn't have a limit of
+/// its own. A zero means that there is no limit
+static VALUE_LIMIT: AtomicU64 = AtomicU64::new(0);
+/// Bumped every time the system is poisoned (see [`poison_epoch`])
+static POISON_EPOCH: AtomicU64 = AtomicU64::new(0);
+/// The token that snapshots are sent and received with, if snapshots can be shipped
+static SNAPSHOT_TOKEN: QuickLock<Option<String>> = QuickLock::new(None);
+/// The longest name (in bytes) that a new keyspace or table can have
+static MAX_NAME_LEN: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_NAME_LEN);
+/// The most threads that the tables are read in with at startup
+static LOAD_THREADS: AtomicUsize = AtomicUsize::new(DEFAULT_LOAD_THREADS);
+/// The most keys that a table can have for `LSKEYS ... ORDERED` to sort them
+static MAX_ORDERED_KEYS: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ORDERED_KEYS);
+/// The bits of the false positive rate of the bloom filters (0 if the default is used)
+static BLOOM_FP_RATE: AtomicU64 = AtomicU64::new(0);
+/// Whether the records are written with checksums (see [`ParanoidMode`])
+static PARANOID_MODE: AtomicU8 = AtomicU8::new(ParanoidMode::Off as u8);
+/// Whether the flushes are batched through io_uring (see [`storage::writer`](crate::storage::writer))
+static URING: AtomicBool = AtomicBool::new(false);
+/// What happens at startup to the table files that no `PARTMAP` has (see [`OrphanPolicy`])
+static ORPHAN_POLICY: AtomicU8 = AtomicU8::new(OrphanPolicy::Fail as u8);
+/// Whether the tables without a file are loaded empty at startup
+static ALLOW_MISSING: AtomicBool = AtomicBool::new(false);
+/// Whether the time of the latest read of every table is tracked
+static TRACK_READS: AtomicBool = AtomicBool::new(false);
+/// Whether the names of keyspaces and tables are compared without regard to ASCII case
+static CASE_INSENSITIVE: AtomicBool = AtomicBool::new(false);
+/// Whether the server runs in sandbox mode, without reading or writing the data directory
+static SANDBOX: AtomicBool = AtomicBool::new(false);
+/// What didn't match the `PARTMAP`s at startup (see [`storage::reconcile`](crate::storage::reconcile))
+static RECOVERY_REPORT: QuickLock<RecoveryReport> = QuickLock::new(RecoveryReport::new());
+/// The records that didn't match their checksums when the tables were read in
+static CHECKSUM_MISMATCHES: AtomicUsize = AtomicUsize::new(0);
+/// The number of reads that found a value that doesn't decode
+static UNREADABLE_VALUES: AtomicUsize = AtomicUsize::new(0);
 /// The global flush state
 static FLUSH_STATE: QuickLock<()> = QuickLock::new(());
+/// The locks that are held right now (see [`LockTable`])
+static LOCKS: LockTable = LockTable::new();
 /// The preload trip switch
 static PRELOAD_TRIPSWITCH: Trip = Trip::new_untripped();
+/// The global dirty bytes tracker
+static DIRTY_TRACKER: Lazy<DirtyTracker, fn() -> DirtyTracker> = Lazy::new(DirtyTracker::default);
+/// The global response compression settings
+static COMPRESSION: Lazy<CompressionSettings, fn() -> CompressionSettings> =
+    Lazy::new(CompressionSettings::default);
+/// The global change log
+static CHANGELOG: Lazy<ChangeLog, fn() -> ChangeLog> = Lazy::new(ChangeLog::default);
+/// The global DDL log
+static DDL_LOG: Lazy<DdlLog, fn() -> DdlLog> = Lazy::new(DdlLog::default);
+/// The global flush log
+static FLUSH_LOG: Lazy<FlushLog, fn() -> FlushLog> = Lazy::new(FlushLog::default);
+/// The global shutdown request
+static SHUTDOWN: Lazy<ShutdownRequest, fn() -> ShutdownRequest> =
+    Lazy::new(ShutdownRequest::default);
+/// The global request tracer
+static TRACER: Lazy<Tracer, fn() -> Tracer> = Lazy::new(Tracer::default);
+/// The limits on the transactions and what they have queued
+static TXN_QUOTA: TxnQuota = TxnQuota::new();
+/// The limits on pipelined queries
+static PIPELINE_LIMITS: PipelineLimits = PipelineLimits::new();
+/// The global slow query log
+static SLOW_LOG: SlowLog = SlowLog::new();
+/// The limits on the bandwidth of the snapshots and the periodic flushes
+static IO_THROTTLE: IoThrottle = IoThrottle::new();
+/// The global latency histograms (see [`get_metrics`])
+static METRICS: LazyCell<Metrics> = LazyCell::new();
+/// The global memory guard
+static MEMORY_GUARD: MemoryGuard = MemoryGuard::new();
+/// The settings and the findings of the consistency checker
+static SELF_CHECK: SelfCheck = SelfCheck::new();
+/// The global registry of open connections
+static CLIENTS: Lazy<Clients, fn() -> Clients> = Lazy::new(Clients::default);
+
+static FLUSH_PROGRESS: Lazy<FlushProgress, fn() -> FlushProgress> =
+    Lazy::new(FlushProgress::default);
+
+static WRITE_GATE: Lazy<WriteGate, fn() -> WriteGate> = Lazy::new(WriteGate::default);
+
+/// The global pause state of the background services
+static BACKGROUND: Lazy<BackgroundControl, fn() -> BackgroundControl> =
+    Lazy::new(BackgroundControl::default);
+
+/// Get the global system state
+pub fn get_state() -> SystemState {
+    #[cfg(test)]
+    {
+        if let Some(state) = state::get_override() {
+            return state;
+        }
+    }
+    GLOBAL_STATE.get()
+}
+
+/// Get the state that clients are served in: the global system state, except that writes are
+/// blocked while the [memory guard](get_memory_guard) blocks them too. This is what health
+/// checks report
+pub fn get_serving_state() -> SystemState {
+    match get_state() {
+        SystemState::Okay if get_memory_guard().is_blocking() => SystemState::WriteBlocked,
+        state => state,
+    }
+}
 
-/// Check the global system state
+/// Check if the global system state allows writes
 pub fn state_okay() -> bool {
-    GLOBAL_STATE.load(ORD_ACQ)
+    get_state().allows_writes()
+}
+
+/// Check if the global system state allows reads
+pub fn reads_okay() -> bool {
+    get_state().allows_reads()
+}
+
+/// Get the poison epoch. This changes every time the system is poisoned, so a multi-key write
+/// reads it once before it starts and checks it with [`unpoisoned_since`] once it's done,
+/// instead of checking the state for every key
+pub fn poison_epoch() -> u64 {
+    #[cfg(test)]
+    {
+        POISON_EPOCH.load(ORD_ACQ) + state::get_override_epoch()
+    }
+    #[cfg(not(test))]
+    {
+        POISON_EPOCH.load(ORD_ACQ)
+    }
+}
+
+/// Check that the system allows writes and hasn't been poisoned since the [`poison_epoch`]
+/// was `epoch` (even if it has been unpoisoned again since)
+pub fn unpoisoned_since(epoch: u64) -> bool {
+    state_okay() && poison_epoch() == epoch
+}
+
+/// Set whether reads are served (from memory) when the system is [poisoned](poison), or
+/// rejected just like the writes
+pub fn allow_stale_reads(allow: bool) {
+    STALE_READS.store(allow, ORD_REL)
+}
+
+/// Set the largest value (in bytes) that can be written to the tables that don't have a
+/// limit of their own, or remove the limit with `None`
+pub fn set_value_limit(limit: Option<u64>) {
+    VALUE_LIMIT.store(limit.unwrap_or(0), ORD_REL)
+}
+
+/// Get the largest value (in bytes) that can be written to the tables that don't have a limit
+/// of their own, if there is one
+pub fn get_value_limit() -> Option<u64> {
+    match VALUE_LIMIT.load(ORD_ACQ) {
+        0 => None,
+        limit => Some(limit),
+    }
+}
+
+/// Set the longest name (in bytes) that a new keyspace or table can have. The containers that
+/// already exist keep their names
+pub fn set_max_name_len(len: usize) {
+    MAX_NAME_LEN.store(len, ORD_REL)
+}
+
+/// Get the longest name (in bytes) that a new keyspace or table can have
+pub fn get_max_name_len() -> usize {
+    MAX_NAME_LEN.load(ORD_ACQ)
+}
+
+/// Set the most threads that the tables are read in with at startup (there are never more of
+/// them than CPUs)
+pub fn set_load_threads(threads: usize) {
+    LOAD_THREADS.store(threads, ORD_REL)
+}
+
+/// Get the most threads that the tables are read in with at startup
+pub fn get_load_threads() -> usize {
+    LOAD_THREADS.load(ORD_ACQ)
+}
+
+/// Set the most keys that a table can have for `LSKEYS ... ORDERED` to sort them (tables with
+/// an ordered index don't need sorting and are never refused)
+pub fn set_max_ordered_keys(keys: usize) {
+    MAX_ORDERED_KEYS.store(keys, ORD_REL)
+}
+
+/// Get the most keys that a table can have for `LSKEYS ... ORDERED` to sort them
+pub fn get_max_ordered_keys() -> usize {
+    MAX_ORDERED_KEYS.load(ORD_ACQ)
+}
+
+/// Set the false positive rate of the bloom filters that are written along with the tables
+/// (this has to be between 0 and 1)
+pub fn set_bloom_fp_rate(rate: f64) {
+    BLOOM_FP_RATE.store(rate.to_bits(), ORD_REL)
+}
+
+/// Get the false positive rate of the bloom filters that are written along with the tables
+pub fn get_bloom_fp_rate() -> f64 {
+    match BLOOM_FP_RATE.load(ORD_ACQ) {
+        0 => DEFAULT_BLOOM_FP_RATE,
+        bits => f64::from_bits(bits),
+    }
+}
+
+/// Set whether the records of the tables are written with checksums and what happens to the
+/// ones that don't match theirs when they're read in
+pub fn set_paranoid_mode(mode: ParanoidMode) {
+    PARANOID_MODE.store(mode as u8, ORD_REL)
+}
+
+/// Set whether the flushes are batched through io_uring (if it's available)
+pub fn set_uring(on: bool) {
+    URING.store(on, ORD_REL)
+}
+
+/// Returns true if the flushes should be batched through io_uring (if it's available)
+pub fn uses_uring() -> bool {
+    URING.load(ORD_ACQ)
+}
+
+/// Set what happens at startup to the table files that no `PARTMAP` has
+pub fn set_orphan_policy(policy: OrphanPolicy) {
+    ORPHAN_POLICY.store(policy as u8, ORD_REL)
+}
+
+/// Get what happens at startup to the table files that no `PARTMAP` has
+pub fn get_orphan_policy() -> OrphanPolicy {
+    #[cfg(test)]
+    {
+        if let Some(policy) = ORPHAN_POLICY_OVERRIDE.with(|cell| cell.get()) {
+            return policy;
+        }
+    }
+    match ORPHAN_POLICY.load(ORD_ACQ) {
+        1 => OrphanPolicy::Adopt,
+        2 => OrphanPolicy::Quarantine,
+        _ => OrphanPolicy::Fail,
+    }
+}
+
+/// Set whether the tables without a file are loaded empty at startup
+pub fn set_allow_missing(allow: bool) {
+    ALLOW_MISSING.store(allow, ORD_REL)
+}
+
+/// Returns true if the tables without a file are loaded empty at startup
+pub fn allows_missing() -> bool {
+    #[cfg(test)]
+    {
+        if let Some(allow) = ALLOW_MISSING_OVERRIDE.with(|cell| cell.get()) {
+            return allow;
+        }
+    }
+    ALLOW_MISSING.load(ORD_ACQ)
+}
+
+/// Set whether the time of the latest read of every table is tracked (`trackreads`)
+pub fn set_track_reads(track: bool) {
+    TRACK_READS.store(track, ORD_REL)
 }
 
-/// Lock the global flush state. **Remember to drop the lock guard**; else you'll
+/// Returns true if the time of the latest read of every table is tracked. Since this raises a
+/// flag on the table for every read, it is off by default
+pub fn tracks_reads() -> bool {
+    #[cfg(test)]
+    {
+        if let Some(track) = TRACK_READS_OVERRIDE.with(|cell| cell.get()) {
+            return track;
+        }
+    }
+    TRACK_READS.load(ORD_ACQ)
+}
+
+/// Set whether the names of keyspaces and tables are compared without regard to ASCII case
+/// (`caseinsensitive`)
+pub fn set_case_insensitive(insensitive: bool) {
+    CASE_INSENSITIVE.store(insensitive, ORD_REL)
+}
+
+/// Returns true if the names of keyspaces and tables are compared without regard to ASCII
+/// case (see [`entity_key`](crate::corestore::memstore::entity_key))
+pub fn is_case_insensitive() -> bool {
+    #[cfg(test)]
+    {
+        if let Some(insensitive) = CASE_INSENSITIVE_OVERRIDE.with(|cell| cell.get()) {
+            return insensitive;
+        }
+    }
+    CASE_INSENSITIVE.load(ORD_ACQ)
+}
+
+/// Set whether the server runs in sandbox mode (`--sandbox`)
+pub fn set_sandbox(sandbox: bool) {
+    SANDBOX.store(sandbox, ORD_REL)
+}
+
+/// Returns true if the server runs in sandbox mode: all the data is kept in memory and
+/// nothing is ever read from or written to the data directory
+pub fn is_sandbox() -> bool {
+    #[cfg(test)]
+    {
+        if let Some(sandbox) = SANDBOX_OVERRIDE.with(|cell| cell.get()) {
+            return sandbox;
+        }
+    }
+    SANDBOX.load(ORD_ACQ)
+}
+
+/// Keep the report of what didn't match the `PARTMAP`s at startup
+pub fn set_recovery_report(report: RecoveryReport) {
+    *RECOVERY_REPORT.lock() = report;
+}
+
+/// Get the report of what didn't match the `PARTMAP`s at startup
+pub fn get_recovery_report() -> RecoveryReport {
+    RECOVERY_REPORT.lock().clone()
+}
+
+/// Get whether the records of the tables are written with checksums and what happens to the
+/// ones that don't match theirs when they're read in
+pub fn get_paranoid_mode() -> ParanoidMode {
+    #[cfg(test)]
+    {
+        if let Some(mode) = PARANOID_MODE_OVERRIDE.with(|cell| cell.get()) {
+            return mode;
+        }
+    }
+    match PARANOID_MODE.load(ORD_ACQ) {
+        1 => ParanoidMode::Report,
+        2 => ParanoidMode::Reject,
+        _ => ParanoidMode::Off,
+    }
+}
+
+/// Count `count` more records that didn't match their checksums
+pub fn add_checksum_mismatches(count: usize) {
+    CHECKSUM_MISMATCHES.fetch_add(count, ORD_SEQ);
+}
+
+/// Get the number of records that didn't match their checksums when the tables were read in
+pub fn get_checksum_mismatches() -> usize {
+    CHECKSUM_MISMATCHES.load(ORD_SEQ)
+}
+
+/// Count a read that found a value whose stored bytes don't decode to a value of its table's
+/// type (see [`ReadError::Unreadable`](crate::kvengine::ReadError::Unreadable))
+pub fn count_unreadable_value() {
+    UNREADABLE_VALUES.fetch_add(1, ORD_SEQ);
+}
+
+/// Get the number of reads that found a value that doesn't decode
+pub fn get_unreadable_values() -> usize {
+    UNREADABLE_VALUES.load(ORD_SEQ)
+}
+
+/// Set the token that `SENDSNAP` presents and that `RECVSNAP` has to be given, or stop
+/// shipping snapshots altogether with `None`
+pub fn set_snapshot_token(token: Option<String>) {
+    *SNAPSHOT_TOKEN.lock() = token
+}
+
+/// Get the token that snapshots are shipped with, if there is one
+pub fn get_snapshot_token() -> Option<String> {
+    SNAPSHOT_TOKEN.lock().clone()
+}
+
+/// Lock the global flush state for `holder`. **Remember to drop the lock guard**; else you'll
 /// end up pausing all sorts of global flushing/transactional systems
-pub fn lock_flush_state() -> QLGuard<'static, ()> {
-    FLUSH_STATE.lock()
+pub fn lock_flush_state(holder: LockHolder) -> Recorded<QLGuard<'static, ()>> {
+    Recorded::new(LockKind::Flush, holder, FLUSH_STATE.lock())
 }
 
-/// Poison the global system state
+/// Get a static reference to the registry of held locks
+pub fn get_locks() -> &'static LockTable {
+    &LOCKS
+}
+
+/// Poison the global system state. This blocks writes and, unless stale reads are
+/// allowed, reads too
 pub fn poison() {
-    GLOBAL_STATE.store(false, ORD_REL)
+    POISON_EPOCH.fetch_add(1, ORD_SEQ);
+    if STALE_READS.load(ORD_ACQ) {
+        GLOBAL_STATE.set(SystemState::WriteBlocked)
+    } else {
+        GLOBAL_STATE.set(SystemState::Poisoned)
+    }
 }
 
 /// Unpoison the global system state
 pub fn unpoison() {
-    GLOBAL_STATE.store(true, ORD_REL)
+    GLOBAL_STATE.set(SystemState::Okay)
 }
 
 /// Get a static reference to the global preload trip switch
 pub fn get_preload_tripswitch() -> &'static Trip {
     &PRELOAD_TRIPSWITCH
 }
+
+/// Get a static reference to the global dirty bytes tracker
+pub fn get_dirty_tracker() -> &'static DirtyTracker {
+    #[cfg(test)]
+    {
+        if let Some(tracker) = DIRTY_TRACKER_OVERRIDE.with(|cell| cell.get()) {
+            return tracker;
+        }
+    }
+    &DIRTY_TRACKER
+}
+
+/// Get a static reference to the global response compression settings
+pub fn get_compression() -> &'static CompressionSettings {
+    &COMPRESSION
+}
+
+/// Get a static reference to the global change log
+pub fn get_changelog() -> &'static ChangeLog {
+    &CHANGELOG
+}
+
+/// Get a static reference to the global DDL log
+pub fn get_ddl_log() -> &'static DdlLog {
+    &DDL_LOG
+}
+
+/// Get a static reference to the global flush log
+pub fn get_flush_log() -> &'static FlushLog {
+    &FLUSH_LOG
+}
+
+/// Get a static reference to the global shutdown request
+pub fn get_shutdown() -> &'static ShutdownRequest {
+    &SHUTDOWN
+}
+
+/// Get a static reference to the global request tracer
+pub fn get_tracer() -> &'static Tracer {
+    &TRACER
+}
+
+/// Get a static reference to the global slow query log
+pub fn get_slowlog() -> &'static SlowLog {
+    &SLOW_LOG
+}
+
+/// Set the upper bounds (in microseconds) of the buckets of the latency histograms, or keep
+/// the default ones with `None`. This has to be done before anything is recorded, otherwise
+/// it does nothing
+pub fn configure_metrics(bounds: Option<Vec<u64>>) {
+    METRICS.get_or_init(|| {
+        let buckets = bounds.map(Buckets::new).unwrap_or_default();
+        Metrics::new(buckets, crate::queryengine::action_names())
+    });
+}
+
+/// Get a static reference to the global latency histograms
+pub fn get_metrics() -> &'static Metrics {
+    METRICS.get_or_init(|| Metrics::new(Buckets::default(), crate::queryengine::action_names()))
+}
+
+/// Get a static reference to the global registry of open connections
+pub fn get_clients() -> &'static Clients {
+    &CLIENTS
+}
+
+/// Get a static reference to the limits on the transactions
+pub fn get_txn_quota() -> &'static TxnQuota {
+    &TXN_QUOTA
+}
+
+/// Get a static reference to the global flush progress
+pub fn get_flush_progress() -> &'static FlushProgress {
+    &FLUSH_PROGRESS
+}
+
+/// Get a static reference to the global write gate
+pub fn get_write_gate() -> &'static WriteGate {
+    &WRITE_GATE
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The memory guard that the calling thread uses instead of the global one, so that tests
+    /// that block writes don't block the writes of the tests that run alongside them
+    static MEMORY_GUARD_OVERRIDE: Cell<Option<&'static MemoryGuard>> = Cell::new(None);
+    /// The dirty bytes tracker that the calling thread uses instead of the global one, so that
+    /// tests that hold back writes don't hold back the writes of the other tests
+    static DIRTY_TRACKER_OVERRIDE: Cell<Option<&'static DirtyTracker>> = Cell::new(None);
+    /// The paranoid mode that the calling thread uses instead of the global one
+    static PARANOID_MODE_OVERRIDE: Cell<Option<ParanoidMode>> = Cell::new(None);
+    /// The orphan policy that the calling thread uses instead of the global one
+    static ORPHAN_POLICY_OVERRIDE: Cell<Option<OrphanPolicy>> = Cell::new(None);
+    /// Whether the calling thread loads the tables without a file empty, instead of what the
+    /// global setting says
+    static ALLOW_MISSING_OVERRIDE: Cell<Option<bool>> = Cell::new(None);
+    /// Whether the calling thread tracks the reads of the tables, instead of what the global
+    /// setting says
+    static TRACK_READS_OVERRIDE: Cell<Option<bool>> = Cell::new(None);
+    /// Whether the calling thread compares names without regard to case, instead of what the
+    /// global setting says
+    static CASE_INSENSITIVE_OVERRIDE: Cell<Option<bool>> = Cell::new(None);
+    /// Whether the calling thread runs in sandbox mode, instead of what the global setting says
+    static SANDBOX_OVERRIDE: Cell<Option<bool>> = Cell::new(None);
+    /// The pipeline limits that the calling thread uses instead of the global ones
+    static PIPELINE_LIMITS_OVERRIDE: Cell<Option<&'static PipelineLimits>> = Cell::new(None);
+    /// The IO throttle that the calling thread uses instead of the global one, so that tests
+    /// that throttle their snapshots don't slow down the snapshots of the other tests
+    static IO_THROTTLE_OVERRIDE: Cell<Option<&'static IoThrottle>> = Cell::new(None);
+}
+
+#[cfg(test)]
+/// Make the calling thread use `limits` instead of the global pipeline limits (or stop doing
+/// so if `None`)
+pub fn override_pipeline_limits(limits: Option<&'static PipelineLimits>) {
+    PIPELINE_LIMITS_OVERRIDE.with(|cell| cell.set(limits))
+}
+
+/// Get a static reference to the limits on pipelined queries
+pub fn get_pipeline_limits() -> &'static PipelineLimits {
+    #[cfg(test)]
+    {
+        if let Some(limits) = PIPELINE_LIMITS_OVERRIDE.with(|cell| cell.get()) {
+            return limits;
+        }
+    }
+    &PIPELINE_LIMITS
+}
+
+#[cfg(test)]
+/// Make the calling thread use `throttle` instead of the global IO throttle (or stop doing so
+/// if `None`)
+pub fn override_io_throttle(throttle: Option<&'static IoThrottle>) {
+    IO_THROTTLE_OVERRIDE.with(|cell| cell.set(throttle))
+}
+
+/// Get a static reference to the settings and the findings of the consistency checker (see
+/// [`SelfCheck`])
+pub fn get_selfcheck() -> &'static SelfCheck {
+    &SELF_CHECK
+}
+
+/// Get a static reference to the limits on the bandwidth of the snapshots and the periodic
+/// flushes (see [`IoThrottle`])
+pub fn get_io_throttle() -> &'static IoThrottle {
+    #[cfg(test)]
+    {
+        if let Some(throttle) = IO_THROTTLE_OVERRIDE.with(|cell| cell.get()) {
+            return throttle;
+        }
+    }
+    &IO_THROTTLE
+}
+
+#[cfg(test)]
+/// Make the calling thread write and read the tables in paranoid mode `mode` (or use the
+/// global mode again if `None`)
+pub fn override_paranoid_mode(mode: Option<ParanoidMode>) {
+    PARANOID_MODE_OVERRIDE.with(|cell| cell.set(mode))
+}
+
+#[cfg(test)]
+/// Make the calling thread reconcile the data directory with `policy` and `allow_missing`
+/// (see [`storage::reconcile`](crate::storage::reconcile)) instead of the global settings, or
+/// use the global settings again if `None`
+pub fn override_reconcile(settings: Option<(OrphanPolicy, bool)>) {
+    ORPHAN_POLICY_OVERRIDE.with(|cell| cell.set(settings.map(|(policy, _)| policy)));
+    ALLOW_MISSING_OVERRIDE.with(|cell| cell.set(settings.map(|(_, allow)| allow)));
+}
+
+#[cfg(test)]
+/// Make the calling thread track the reads of the tables (or not), or follow the global
+/// setting again if `None`
+pub fn override_track_reads(track: Option<bool>) {
+    TRACK_READS_OVERRIDE.with(|cell| cell.set(track))
+}
+
+#[cfg(test)]
+/// Make the calling thread compare names without regard to case (or not), or follow the
+/// global setting again if `None`
+pub fn override_case_insensitive(insensitive: Option<bool>) {
+    CASE_INSENSITIVE_OVERRIDE.with(|cell| cell.set(insensitive))
+}
+
+#[cfg(test)]
+/// Make the calling thread run in sandbox mode (or not), or follow the global setting again
+/// if `None`
+pub fn override_sandbox(sandbox: Option<bool>) {
+    SANDBOX_OVERRIDE.with(|cell| cell.set(sandbox))
+}
+
+#[cfg(test)]
+/// Make the calling thread use `tracker` instead of the global dirty bytes tracker (or stop
+/// doing so if `None`)
+pub fn override_dirty_tracker(tracker: Option<&'static DirtyTracker>) {
+    DIRTY_TRACKER_OVERRIDE.with(|cell| cell.set(tracker))
+}
+
+#[cfg(test)]
+/// Make the calling thread use `guard` instead of the global memory guard (or stop doing so
+/// if `None`)
+pub fn override_memory_guard(guard: Option<&'static MemoryGuard>) {
+    MEMORY_GUARD_OVERRIDE.with(|cell| cell.set(guard))
+}
+
+/// Get a static reference to the global memory guard
+pub fn get_memory_guard() -> &'static MemoryGuard {
+    #[cfg(test)]
+    {
+        if let Some(guard) = MEMORY_GUARD_OVERRIDE.with(|cell| cell.get()) {
+            return guard;
+        }
+    }
+    &MEMORY_GUARD
+}
+
+/// Get a static reference to the global pause state of the background services
+pub fn get_background() -> &'static BackgroundControl {
+    &BACKGROUND
+}
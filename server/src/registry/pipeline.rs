@@ -0,0 +1,99 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Pipeline limits
+//!
+//! Queries that a client pipelines (writes before it has read the responses to the queries
+//! ahead of them) are answered straight from the read buffer, and their responses are held
+//! back so that they go out to the socket together. A connection holds back at most
+//! [`max_inflight`](PipelineLimits::max_inflight) responses (and at most
+//! [`max_inflight_bytes`](PipelineLimits::max_inflight_bytes) bytes of them) before it flushes
+//! them. Flushing waits for the socket to take the responses, so a client that doesn't read
+//! them stops the connection from parsing any more of its queries, and TCP pushes back on the
+//! client from there on.
+//!
+//! A client can only pipeline [`max_depth`](PipelineLimits::max_depth) queries in one go (that
+//! is, without the connection ever running out of queries to answer and having to wait for
+//! more). The connection is closed beyond that
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The most responses that a connection holds back by default
+pub const DEFAULT_MAX_INFLIGHT: usize = 128;
+/// The most bytes of responses that a connection holds back by default
+pub const DEFAULT_MAX_INFLIGHT_BYTES: usize = 64 * 1024;
+/// The most queries that a client can pipeline in one go by default (0 is no limit)
+pub const DEFAULT_MAX_PIPELINE_DEPTH: usize = 0;
+
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// The limits on pipelined queries. See the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct PipelineLimits {
+    max_inflight: AtomicUsize,
+    max_inflight_bytes: AtomicUsize,
+    max_depth: AtomicUsize,
+}
+
+impl PipelineLimits {
+    pub const fn new() -> Self {
+        Self {
+            max_inflight: AtomicUsize::new(DEFAULT_MAX_INFLIGHT),
+            max_inflight_bytes: AtomicUsize::new(DEFAULT_MAX_INFLIGHT_BYTES),
+            max_depth: AtomicUsize::new(DEFAULT_MAX_PIPELINE_DEPTH),
+        }
+    }
+    /// Set the most responses (and bytes of them) that a connection holds back and the most
+    /// queries that a client can pipeline in one go (0 for no limit)
+    pub fn configure(&self, max_inflight: usize, max_inflight_bytes: usize, max_depth: usize) {
+        self.max_inflight.store(max_inflight, ORD_RLX);
+        self.max_inflight_bytes.store(max_inflight_bytes, ORD_RLX);
+        self.max_depth.store(max_depth, ORD_RLX);
+    }
+    /// Returns the most responses that a connection holds back
+    pub fn max_inflight(&self) -> usize {
+        self.max_inflight.load(ORD_RLX)
+    }
+    /// Returns the most bytes of responses that a connection holds back
+    pub fn max_inflight_bytes(&self) -> usize {
+        self.max_inflight_bytes.load(ORD_RLX)
+    }
+    /// Returns the most queries that a client can pipeline in one go (0 if there's no limit)
+    pub fn max_depth(&self) -> usize {
+        self.max_depth.load(ORD_RLX)
+    }
+    /// Returns true if the connection can hold back another response, now that it holds back
+    /// `inflight` responses of `bytes` bytes
+    pub fn can_hold_back(&self, inflight: usize, bytes: usize) -> bool {
+        inflight < self.max_inflight() && bytes < self.max_inflight_bytes()
+    }
+    /// Returns true if a client that has pipelined `depth` queries in one go has gone over
+    /// the limit
+    pub fn is_too_deep(&self, depth: usize) -> bool {
+        let max_depth = self.max_depth();
+        max_depth != 0 && depth > max_depth
+    }
+}
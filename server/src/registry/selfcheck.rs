@@ -0,0 +1,113 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The consistency checker's findings
+//!
+//! If the `[selfcheck]` section is set in the config file, the [consistency checker] compares
+//! what every table holds in memory with what the header of its file says was written, every
+//! `every` hours. The tables that drifted from their files in the latest check are kept here
+//! (`SYS HEALTH` reports them as reasons for being degraded), along with the number of times
+//! that a table was found to drift since the server started
+//!
+//! [consistency checker]: crate::services::selfcheck
+
+use crate::corestore::lock::QuickLock;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// The settings and the findings of the consistency checker. See the [module level docs](self)
+/// for more information
+#[derive(Debug)]
+pub struct SelfCheck {
+    /// how often the tables are checked, in seconds (`0` if they aren't)
+    every: AtomicU64,
+    /// the records that a table can drift by before it is flagged
+    tolerance: AtomicU64,
+    /// the number of times that a table was found to drift
+    drifts: AtomicU64,
+    /// the tables (as `<keyspace>:<table>`) that drifted in the latest check
+    drifting: QuickLock<Vec<String>>,
+}
+
+impl SelfCheck {
+    pub const fn new() -> Self {
+        Self {
+            every: AtomicU64::new(0),
+            tolerance: AtomicU64::new(0),
+            drifts: AtomicU64::new(0),
+            drifting: QuickLock::new(Vec::new()),
+        }
+    }
+    /// Check the tables `every` so often (or never with `None`), flagging the tables that
+    /// drift by more than `tolerance` records
+    pub fn configure(&self, every: Option<Duration>, tolerance: u64) {
+        let every = every.map(|every| every.as_secs().max(1)).unwrap_or(0);
+        self.every.store(every, ORD_RLX);
+        self.tolerance.store(tolerance, ORD_RLX);
+    }
+    /// Returns how often the tables are checked, if they are
+    pub fn interval(&self) -> Option<Duration> {
+        match self.every.load(ORD_RLX) {
+            0 => None,
+            every => Some(Duration::from_secs(every)),
+        }
+    }
+    /// Returns the records that a table can drift by before it is flagged
+    pub fn tolerance(&self) -> u64 {
+        self.tolerance.load(ORD_RLX)
+    }
+    /// Record the tables that drifted in the check that just completed, replacing the ones
+    /// from the check before
+    pub fn record(&self, drifting: Vec<String>) {
+        self.drifts.fetch_add(drifting.len() as u64, ORD_RLX);
+        *self.drifting.lock() = drifting;
+    }
+    /// Returns the tables that drifted in the latest check
+    pub fn drifting(&self) -> Vec<String> {
+        self.drifting.lock().clone()
+    }
+    /// Returns the number of times that a table was found to drift since the server started
+    pub fn drifts(&self) -> u64 {
+        self.drifts.load(ORD_RLX)
+    }
+}
+
+#[test]
+fn test_selfcheck_keeps_the_latest_findings() {
+    let selfcheck = SelfCheck::new();
+    assert_eq!(selfcheck.interval(), None);
+    selfcheck.configure(Some(Duration::from_secs(3600)), 5);
+    assert_eq!(selfcheck.interval(), Some(Duration::from_secs(3600)));
+    assert_eq!(selfcheck.tolerance(), 5);
+    selfcheck.record(vec!["a:b".to_owned(), "a:c".to_owned()]);
+    selfcheck.record(vec!["a:c".to_owned()]);
+    assert_eq!(selfcheck.drifting(), vec!["a:c".to_owned()]);
+    assert_eq!(selfcheck.drifts(), 3);
+    selfcheck.record(Vec::new());
+    assert!(selfcheck.drifting().is_empty());
+}
@@ -0,0 +1,93 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Shutdown requests
+//!
+//! `SYS SHUTDOWN` and `SYS RESTART` ask the server to shut down (and start again) through
+//! here. The server then shuts down just like it would on a termination signal
+
+use core::sync::atomic::AtomicU8;
+use core::sync::atomic::Ordering;
+use tokio::sync::Notify;
+
+const ORD_ACQ: Ordering = Ordering::Acquire;
+const ORD_REL: Ordering = Ordering::Release;
+
+const NOT_REQUESTED: u8 = 0;
+const SHUTDOWN: u8 = 1;
+const RESTART: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// What a client asked the server to do
+pub enum ShutdownKind {
+    /// Shut down
+    Shutdown,
+    /// Shut down and then start again with the same arguments
+    Restart,
+}
+
+/// The shutdown request, if any
+#[derive(Debug)]
+pub struct ShutdownRequest {
+    /// what was requested
+    requested: AtomicU8,
+    /// wakes up the task waiting for a request
+    notify: Notify,
+}
+
+impl Default for ShutdownRequest {
+    fn default() -> Self {
+        Self {
+            requested: AtomicU8::new(NOT_REQUESTED),
+            notify: Notify::new(),
+        }
+    }
+}
+
+impl ShutdownRequest {
+    /// Ask the server to shut down (or restart)
+    pub fn request(&self, kind: ShutdownKind) {
+        let kind = match kind {
+            ShutdownKind::Shutdown => SHUTDOWN,
+            ShutdownKind::Restart => RESTART,
+        };
+        self.requested.store(kind, ORD_REL);
+        // this is remembered even if no one is waiting yet
+        self.notify.notify_one();
+    }
+    /// Returns what was requested, if anything
+    pub fn get(&self) -> Option<ShutdownKind> {
+        match self.requested.load(ORD_ACQ) {
+            SHUTDOWN => Some(ShutdownKind::Shutdown),
+            RESTART => Some(ShutdownKind::Restart),
+            _ => None,
+        }
+    }
+    /// Wait until a shutdown is requested
+    pub async fn wait(&self) {
+        self.notify.notified().await
+    }
+}
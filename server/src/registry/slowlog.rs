@@ -0,0 +1,214 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The slow query log
+//!
+//! Every query whose action takes at least the threshold ([`DEFAULT_SLOWLOG_THRESHOLD`]
+//! microseconds by default) to run is logged (at the warn level) and kept in a ring of the
+//! most recent [`DEFAULT_SLOWLOG_SIZE`] records for `SYS SLOWLOG GET`. Both can be set in the
+//! `[slowlog]` section of the config file and the threshold can be changed at runtime with
+//! `SYS SLOWLOG THRESHOLD`.
+//!
+//! Only the name of the action and the number of its arguments are recorded, never the
+//! arguments themselves. The ring is only locked for the queries that are slow; every other
+//! query just compares the time it took with the threshold
+
+use crate::corestore::lock::QuickLock;
+use chrono::{DateTime, SecondsFormat, Utc};
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// The number of microseconds that an action has to take to be logged by default
+pub const DEFAULT_SLOWLOG_THRESHOLD: u64 = 10_000;
+/// The number of slow query records kept around by default
+pub const DEFAULT_SLOWLOG_SIZE: usize = 128;
+
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// The timings of the stages before the action was run, if the connection was
+/// [traced](super::Tracer)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlowStages {
+    /// from the bytes of the query being read to the query being parsed
+    pub parse: Duration,
+    /// from the query being parsed to the action being run
+    pub dispatch: Duration,
+}
+
+/// A query that was slow
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowRecord {
+    /// the ID of the record (these only ever go up)
+    pub id: u64,
+    /// when the query completed (as an RFC 3339 timestamp in UTC)
+    pub time: String,
+    /// the ID of the connection (see [`Peer`](crate::dbnet::connection::Peer))
+    pub connection: u64,
+    /// the name of the action
+    pub action: String,
+    /// the number of arguments passed to the action
+    pub args: usize,
+    /// how long the action took
+    pub duration: Duration,
+    /// the stages before the action, if the connection was traced
+    pub stages: Option<SlowStages>,
+}
+
+/// The slow query log. See the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct SlowLog {
+    /// in microseconds
+    threshold: AtomicU64,
+    size: AtomicUsize,
+    next_id: AtomicU64,
+    /// the most recent records, oldest first
+    records: QuickLock<VecDeque<SlowRecord>>,
+}
+
+impl SlowLog {
+    pub const fn new() -> Self {
+        Self {
+            threshold: AtomicU64::new(DEFAULT_SLOWLOG_THRESHOLD),
+            size: AtomicUsize::new(DEFAULT_SLOWLOG_SIZE),
+            next_id: AtomicU64::new(0),
+            records: QuickLock::new(VecDeque::new()),
+        }
+    }
+    /// Set the threshold (in microseconds) and the number of records that are kept around
+    pub fn configure(&self, threshold: u64, size: usize) {
+        self.set_threshold(threshold);
+        self.size.store(size, ORD_RLX);
+        let mut records = self.records.lock();
+        while records.len() > size {
+            records.pop_front();
+        }
+    }
+    /// Set the threshold (in microseconds). A threshold of 0 logs every query
+    pub fn set_threshold(&self, threshold: u64) {
+        self.threshold.store(threshold, ORD_RLX)
+    }
+    /// Returns the threshold (in microseconds)
+    pub fn threshold(&self) -> u64 {
+        self.threshold.load(ORD_RLX)
+    }
+    /// Returns the number of records that are kept around
+    pub fn size(&self) -> usize {
+        self.size.load(ORD_RLX)
+    }
+    /// Returns true if an action that took `took` is slow
+    pub fn is_slow(&self, took: Duration) -> bool {
+        took.as_micros() >= self.threshold() as u128
+    }
+    /// Log a slow query and keep it around, dropping the oldest record if the ring is full
+    pub fn record(
+        &self,
+        time: DateTime<Utc>,
+        connection: u64,
+        action: &[u8],
+        args: usize,
+        duration: Duration,
+        stages: Option<SlowStages>,
+    ) {
+        let action = String::from_utf8_lossy(action).into_owned();
+        log::warn!(
+            "Slow query on connection #{}: {} ({} arg(s)) took {}us",
+            connection,
+            action,
+            args,
+            duration.as_micros()
+        );
+        let size = self.size();
+        let mut records = self.records.lock();
+        let record = SlowRecord {
+            id: self.next_id.fetch_add(1, ORD_RLX),
+            time: time.to_rfc3339_opts(SecondsFormat::Millis, true),
+            connection,
+            action,
+            args,
+            duration,
+            stages,
+        };
+        while !records.is_empty() && records.len() >= size {
+            records.pop_front();
+        }
+        if size != 0 {
+            records.push_back(record);
+        }
+    }
+    /// Returns the last `n` records, newest first
+    pub fn get(&self, n: usize) -> Vec<SlowRecord> {
+        self.records.lock().iter().rev().take(n).cloned().collect()
+    }
+    /// Returns the number of records that are kept around right now
+    pub fn len(&self) -> usize {
+        self.records.lock().len()
+    }
+    /// Drop every record
+    pub fn reset(&self) {
+        self.records.lock().clear()
+    }
+}
+
+#[cfg(test)]
+fn record(log: &SlowLog, action: &str) {
+    use chrono::TimeZone;
+    log.record(
+        Utc.ymd(2021, 7, 1).and_hms(10, 0, 0),
+        1,
+        action.as_bytes(),
+        2,
+        Duration::from_millis(20),
+        None,
+    );
+}
+
+#[test]
+fn test_slowlog_ring() {
+    let log = SlowLog::new();
+    log.configure(1000, 2);
+    assert!(!log.is_slow(Duration::from_micros(999)));
+    assert!(log.is_slow(Duration::from_micros(1000)));
+    record(&log, "GET");
+    record(&log, "SET");
+    record(&log, "DEL");
+    // the oldest record was dropped and the newest comes first
+    let records = log.get(10);
+    assert_eq!(log.len(), 2);
+    assert_eq!(records[0].action, "DEL");
+    assert_eq!(records[0].id, 2);
+    assert_eq!(records[1].action, "SET");
+    assert_eq!(records[0].time, "2021-07-01T10:00:00.000Z");
+    assert_eq!(log.get(1).len(), 1);
+    // shrinking the ring drops the oldest records
+    log.configure(1000, 1);
+    assert_eq!(log.get(10)[0].action, "DEL");
+    log.reset();
+    assert_eq!(log.len(), 0);
+    // the ids carry on after a reset
+    record(&log, "GET");
+    assert_eq!(log.get(1)[0].id, 3);
+}
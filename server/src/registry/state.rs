@@ -0,0 +1,132 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The system state
+//!
+//! If a flush fails, whatever is only in memory might never make it to disk, so the
+//! system is poisoned until a flush succeeds again. Writes are always rejected in the
+//! meantime. Reads can still be served from memory (which is what was there before the
+//! failure) unless the read policy says that they should fail as well; in which case the
+//! failure poisons the system fully
+
+use core::sync::atomic::{AtomicU8, Ordering};
+#[cfg(test)]
+use std::cell::Cell;
+
+const ORD_ACQ: Ordering = Ordering::Acquire;
+const ORD_REL: Ordering = Ordering::Release;
+
+/// The state of the system, as far as the actions are concerned
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum SystemState {
+    /// Everything is fine
+    Okay = 0,
+    /// Writes are rejected but reads are served
+    WriteBlocked = 1,
+    /// Both reads and writes are rejected
+    Poisoned = 2,
+}
+
+impl SystemState {
+    const fn from_u8(state: u8) -> Self {
+        match state {
+            0 => Self::Okay,
+            1 => Self::WriteBlocked,
+            _ => Self::Poisoned,
+        }
+    }
+    /// Returns true if actions that mutate data can run
+    pub const fn allows_writes(&self) -> bool {
+        matches!(self, Self::Okay)
+    }
+    /// Returns true if actions that only read data can run
+    pub const fn allows_reads(&self) -> bool {
+        !matches!(self, Self::Poisoned)
+    }
+}
+
+/// A [`SystemState`] that can be shared across threads
+#[derive(Debug)]
+pub struct AtomicState {
+    inner: AtomicU8,
+}
+
+impl AtomicState {
+    pub const fn new(state: SystemState) -> Self {
+        Self {
+            inner: AtomicU8::new(state as u8),
+        }
+    }
+    pub fn get(&self) -> SystemState {
+        SystemState::from_u8(self.inner.load(ORD_ACQ))
+    }
+    pub fn set(&self, state: SystemState) {
+        self.inner.store(state as u8, ORD_REL)
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The state that the calling thread sees instead of the global one. Since this is per
+    /// thread, tests that poison the system don't break the tests that run alongside them
+    static OVERRIDE: Cell<Option<SystemState>> = Cell::new(None);
+    /// The number of times that the calling thread was made to see a state that blocks writes,
+    /// which is added to the poison epoch that it sees
+    static OVERRIDE_EPOCH: Cell<u64> = Cell::new(0);
+}
+
+#[cfg(test)]
+/// Make the calling thread see `state` instead of the global state (or stop doing so if
+/// `None`)
+pub fn override_state(state: Option<SystemState>) {
+    if matches!(state, Some(state) if !state.allows_writes()) {
+        OVERRIDE_EPOCH.with(|cell| cell.set(cell.get() + 1));
+    }
+    OVERRIDE.with(|cell| cell.set(state))
+}
+
+#[cfg(test)]
+pub(super) fn get_override() -> Option<SystemState> {
+    OVERRIDE.with(|cell| cell.get())
+}
+
+#[cfg(test)]
+pub(super) fn get_override_epoch() -> u64 {
+    OVERRIDE_EPOCH.with(|cell| cell.get())
+}
+
+#[test]
+fn test_state_allows() {
+    let state = AtomicState::new(SystemState::Okay);
+    assert!(state.get().allows_reads() && state.get().allows_writes());
+    state.set(SystemState::WriteBlocked);
+    assert!(state.get().allows_reads() && !state.get().allows_writes());
+    state.set(SystemState::Poisoned);
+    assert!(!state.get().allows_reads() && !state.get().allows_writes());
+    state.set(SystemState::Okay);
+    assert_eq!(state.get(), SystemState::Okay);
+}
@@ -0,0 +1,285 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # IO throttling
+//!
+//! Snapshots can be kept from taking up all of the disk's bandwidth, so that the periodic
+//! flushes and the reads of the tables that are read in lazily aren't held up behind them.
+//! The files that a snapshot writes then go through a [`TokenBucket`] that lets through at most
+//! the [limit](IoThrottle::limit) of bytes per second (plus a short burst) and has the
+//! blocking thread that the snapshot runs on sleep once it is out of tokens. The periodic
+//! flushes can have a limit of their own, which is usually higher (since they have to keep up
+//! with the writes), while the flushes at startup and at shutdown are never throttled.
+//!
+//! The limits are set in the `[throttle]` section of the config file (there are none by
+//! default) and can be changed at runtime with `SYS THROTTLE`. A change applies to the
+//! snapshot or the flush that is running right away. `SYS SNAPSTATE` reports how far the
+//! snapshot that is running has come (see [`SnapshotProgress`])
+
+use crate::corestore::lock::QuickLock;
+use core::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// The most that a [`TokenBucket`] holds, as the seconds of writes at its rate: this is the
+/// burst that is let through after the writes paused for a while
+const BURST_SECS: f64 = 0.1;
+
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// What writes the files that are throttled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IoKind {
+    /// a snapshot (full or incremental)
+    Snapshot,
+    /// a periodic flush (BGSAVE)
+    Flush,
+}
+
+impl IoKind {
+    /// Returns the name of the kind, as `SYS THROTTLE` takes it
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            IoKind::Snapshot => "snapshot",
+            IoKind::Flush => "flush",
+        }
+    }
+}
+
+/// The limits on the bandwidth of the snapshots and the periodic flushes, along with the
+/// progress of the snapshot that is running. See the [module level docs](self) for more
+/// information
+#[derive(Debug)]
+pub struct IoThrottle {
+    /// in bytes per second (0 is no limit)
+    snapshot: AtomicU64,
+    /// in bytes per second (0 is no limit)
+    flush: AtomicU64,
+    progress: SnapshotProgress,
+}
+
+impl IoThrottle {
+    pub const fn new() -> Self {
+        Self {
+            snapshot: AtomicU64::new(0),
+            flush: AtomicU64::new(0),
+            progress: SnapshotProgress::new(),
+        }
+    }
+    fn limit_of(&self, kind: IoKind) -> &AtomicU64 {
+        match kind {
+            IoKind::Snapshot => &self.snapshot,
+            IoKind::Flush => &self.flush,
+        }
+    }
+    /// Set the most bytes per second that `kind` can write, or lift the limit with `None`
+    pub fn set_limit(&self, kind: IoKind, limit: Option<u64>) {
+        self.limit_of(kind).store(limit.unwrap_or(0), ORD_RLX)
+    }
+    /// Returns the most bytes per second that `kind` can write, or `None` if there's no limit
+    pub fn limit(&self, kind: IoKind) -> Option<u64> {
+        match self.limit_of(kind).load(ORD_RLX) {
+            0 => None,
+            limit => Some(limit),
+        }
+    }
+    /// Returns the progress of the snapshot that is running
+    pub fn snapshot_progress(&self) -> &SnapshotProgress {
+        &self.progress
+    }
+}
+
+/// How far the snapshot that is running has come: the bytes it has written so far and the
+/// bytes that it was estimated to write when it began (from what the tables hold, so this is
+/// only ever a rough guess)
+#[derive(Debug)]
+pub struct SnapshotProgress {
+    /// when the snapshot began, if one is running
+    began: QuickLock<Option<Instant>>,
+    written: AtomicU64,
+    estimated: AtomicU64,
+}
+
+impl SnapshotProgress {
+    const fn new() -> Self {
+        Self {
+            began: QuickLock::new(None),
+            written: AtomicU64::new(0),
+            estimated: AtomicU64::new(0),
+        }
+    }
+    /// Record that a snapshot that is estimated to write `estimated` bytes began
+    pub fn begin(&self, estimated: u64) {
+        self.written.store(0, ORD_RLX);
+        self.estimated.store(estimated, ORD_RLX);
+        *self.began.lock() = Some(Instant::now());
+    }
+    /// Record that the snapshot that is running wrote another `bytes` bytes
+    pub fn record(&self, bytes: u64) {
+        self.written.fetch_add(bytes, ORD_RLX);
+    }
+    /// Record that the snapshot that was running is done (or failed)
+    pub fn end(&self) {
+        *self.began.lock() = None;
+    }
+    /// Returns the bytes written so far and the bytes that were estimated, if a snapshot is
+    /// running
+    pub fn bytes(&self) -> Option<(u64, u64)> {
+        (*self.began.lock()).map(|_| (self.written.load(ORD_RLX), self.estimated.load(ORD_RLX)))
+    }
+    /// Returns how much longer the snapshot that is running should take, going by the rate
+    /// that it has written at so far. This is `None` if no snapshot is running or if it hasn't
+    /// written anything yet. A snapshot that has gone past its estimate is expected to be done
+    /// any moment now
+    pub fn eta(&self) -> Option<Duration> {
+        let began = (*self.began.lock())?;
+        let (written, estimated) = (self.written.load(ORD_RLX), self.estimated.load(ORD_RLX));
+        if written == 0 {
+            return None;
+        }
+        let left = estimated.saturating_sub(written) as f64;
+        Some(began.elapsed().mul_f64(left / written as f64))
+    }
+}
+
+/// Paces writes to a rate: every byte that is written takes a token, and the tokens are
+/// refilled at the rate, up to [`BURST_SECS`] worth of them. Once the bucket is out of tokens,
+/// the writer has to wait for the ones that it took in advance to be refilled
+#[derive(Debug)]
+pub struct TokenBucket {
+    /// the bytes that can be written right away (negative if more were written than the
+    /// bucket had)
+    tokens: f64,
+    /// when the tokens were last refilled
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Returns an empty bucket
+    pub fn new() -> Self {
+        Self::new_at(Instant::now())
+    }
+    fn new_at(now: Instant) -> Self {
+        Self {
+            tokens: 0.0,
+            refilled: now,
+        }
+    }
+    /// Take `bytes` tokens at `rate` bytes per second (no limit if `None`) and return how long
+    /// the writer has to wait for before it writes anything else
+    pub fn take(&mut self, bytes: u64, rate: Option<u64>) -> Duration {
+        self.take_at(bytes, rate, Instant::now())
+    }
+    fn take_at(&mut self, bytes: u64, rate: Option<u64>, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.refilled = now;
+        let rate = match rate {
+            Some(rate) => rate as f64,
+            None => {
+                // nothing is held back while there's no limit, nor once there's one again
+                self.tokens = 0.0;
+                return Duration::from_secs(0);
+            }
+        };
+        self.tokens = (self.tokens + elapsed * rate).min(rate * BURST_SECS) - bytes as f64;
+        if self.tokens < 0.0 {
+            Duration::from_secs_f64(-self.tokens / rate)
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+impl Default for TokenBucket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_paces_to_the_rate() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new_at(start);
+        // an empty bucket holds back every byte until it's refilled
+        assert_eq!(
+            bucket.take_at(500, Some(1000), start),
+            Duration::from_millis(500)
+        );
+        // once the writer waited, it has to wait again for the next bytes
+        let later = start + Duration::from_millis(500);
+        assert_eq!(
+            bucket.take_at(1000, Some(1000), later),
+            Duration::from_secs(1)
+        );
+        // an idle bucket only fills up to the burst
+        let idle = later + Duration::from_secs(11);
+        assert_eq!(
+            bucket.take_at(100, Some(1000), idle),
+            Duration::from_secs(0)
+        );
+        assert_eq!(
+            bucket.take_at(100, Some(1000), idle),
+            Duration::from_millis(100)
+        );
+        // nothing is held back without a limit, and the debt is forgiven
+        assert_eq!(bucket.take_at(1 << 30, None, idle), Duration::from_secs(0));
+        assert_eq!(
+            bucket.take_at(10, Some(1000), idle),
+            Duration::from_millis(10)
+        );
+    }
+
+    #[test]
+    fn test_io_throttle_limits() {
+        let throttle = IoThrottle::new();
+        assert_eq!(throttle.limit(IoKind::Snapshot), None);
+        throttle.set_limit(IoKind::Snapshot, Some(100));
+        throttle.set_limit(IoKind::Flush, Some(1000));
+        assert_eq!(throttle.limit(IoKind::Snapshot), Some(100));
+        assert_eq!(throttle.limit(IoKind::Flush), Some(1000));
+        throttle.set_limit(IoKind::Snapshot, None);
+        assert_eq!(throttle.limit(IoKind::Snapshot), None);
+    }
+
+    #[test]
+    fn test_snapshot_progress() {
+        let progress = SnapshotProgress::new();
+        assert_eq!(progress.bytes(), None);
+        assert_eq!(progress.eta(), None);
+        progress.begin(1000);
+        assert_eq!(progress.bytes(), Some((0, 1000)));
+        assert_eq!(progress.eta(), None);
+        progress.record(1000);
+        progress.record(500);
+        assert_eq!(progress.bytes(), Some((1500, 1000)));
+        assert_eq!(progress.eta(), Some(Duration::from_secs(0)));
+        progress.end();
+        assert_eq!(progress.bytes(), None);
+    }
+}
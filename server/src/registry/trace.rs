@@ -0,0 +1,188 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Request tracing
+//!
+//! Tracing is turned on per connection: with `SYS TRACE ON` for the connection itself, or with
+//! `SYS TRACE <connection-id> ON` for another one. A traced connection times the stages of
+//! every query it runs (see [`TraceRecord`]) and the records are logged (at the debug level)
+//! and kept in a ring buffer of the last [`TRACE_BUFFER`] records for `SYS TRACE DUMP`.
+//!
+//! Connections that aren't traced don't time anything at all; they only check their flag
+//! when a query starts coming in
+
+use crate::corestore::htable::Coremap;
+use crate::corestore::lock::QuickLock;
+use core::sync::atomic::{AtomicBool, Ordering};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// The number of trace records kept around for `SYS TRACE DUMP`
+pub const TRACE_BUFFER: usize = 1024;
+
+/// The timings of a query run on a traced connection. Only the name of the action and the
+/// number of arguments are recorded, never the arguments themselves
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceRecord {
+    /// the ID of the connection (see [`Peer`](crate::dbnet::connection::Peer))
+    pub connection: u64,
+    /// the name of the action
+    pub action: String,
+    /// the number of arguments passed to the action
+    pub args: usize,
+    /// from the bytes of the query being read to the query being parsed
+    pub parse: Duration,
+    /// from the query being parsed to the action being run
+    pub dispatch: Duration,
+    /// the action itself
+    pub execute: Duration,
+    /// writing out the response
+    pub write: Duration,
+}
+
+/// A query that is being traced, from its bytes being read to its response being written
+#[derive(Debug)]
+pub struct QueryTrace {
+    read: Instant,
+    parsed: Option<Instant>,
+    action: Option<(String, usize, Instant, Instant)>,
+}
+
+impl QueryTrace {
+    /// Start tracing a query whose bytes were just read
+    pub fn start() -> Self {
+        Self {
+            read: Instant::now(),
+            parsed: None,
+            action: None,
+        }
+    }
+    /// The query has been parsed
+    pub fn parsed(&mut self) {
+        self.parsed = Some(Instant::now());
+    }
+    /// The action `name` is about to be run with `args` arguments
+    pub fn dispatched(&mut self, name: &[u8], args: usize) {
+        let now = Instant::now();
+        let name = String::from_utf8_lossy(name).into_owned();
+        self.action = Some((name, args, now, now));
+    }
+    /// Returns the time it took to parse the query and then to dispatch it, once the action
+    /// has been dispatched
+    pub fn before_action(&self) -> Option<(Duration, Duration)> {
+        let parsed = self.parsed?;
+        let (_, _, dispatched, _) = self.action.as_ref()?;
+        Some((parsed - self.read, *dispatched - parsed))
+    }
+    /// The action has run
+    pub fn executed(&mut self) {
+        if let Some((_, _, _, executed)) = &mut self.action {
+            *executed = Instant::now();
+        }
+    }
+    /// The response has been written out. This returns `None` if no action was run for the
+    /// query, say because it was malformed
+    pub fn finish(self, connection: u64) -> Option<TraceRecord> {
+        let written = Instant::now();
+        let parsed = self.parsed?;
+        let (action, args, dispatched, executed) = self.action?;
+        Some(TraceRecord {
+            connection,
+            action,
+            args,
+            parse: parsed - self.read,
+            dispatch: dispatched - parsed,
+            execute: executed - dispatched,
+            write: written - executed,
+        })
+    }
+}
+
+/// The tracing flags of the open connections along with the most recent trace records. See
+/// the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct Tracer {
+    /// the tracing flags of the open connections, by connection ID
+    flags: Coremap<u64, Arc<AtomicBool>>,
+    /// the most recent records, oldest first
+    records: QuickLock<VecDeque<TraceRecord>>,
+}
+
+impl Default for Tracer {
+    fn default() -> Self {
+        Self {
+            flags: Coremap::new(),
+            records: QuickLock::new(VecDeque::new()),
+        }
+    }
+}
+
+impl Tracer {
+    /// Register a new connection and return its tracing flag (which is off)
+    pub fn register(&self, connection: u64) -> Arc<AtomicBool> {
+        let flag = Arc::new(AtomicBool::new(false));
+        self.flags.upsert(connection, flag.clone());
+        flag
+    }
+    /// Forget a connection once it is closed
+    pub fn unregister(&self, connection: u64) {
+        let _ = self.flags.true_if_removed(&connection);
+    }
+    /// Turn tracing on (or off) for a connection. This returns false if there's no open
+    /// connection with that ID
+    pub fn set_tracing(&self, connection: u64, on: bool) -> bool {
+        match self.flags.get(&connection) {
+            Some(flag) => {
+                flag.store(on, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+    /// Log a record and keep it around, dropping the oldest record if the buffer is full
+    pub fn record(&self, record: TraceRecord) {
+        log::debug!(
+            "Trace #{} {} ({} arg(s)): parse {}us, dispatch {}us, execute {}us, write {}us",
+            record.connection,
+            record.action,
+            record.args,
+            record.parse.as_micros(),
+            record.dispatch.as_micros(),
+            record.execute.as_micros(),
+            record.write.as_micros()
+        );
+        let mut records = self.records.lock();
+        if records.len() == TRACE_BUFFER {
+            records.pop_front();
+        }
+        records.push_back(record);
+    }
+    /// Returns the records that are kept around, oldest first
+    pub fn dump(&self) -> Vec<TraceRecord> {
+        self.records.lock().iter().cloned().collect()
+    }
+}
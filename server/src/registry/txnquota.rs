@@ -0,0 +1,166 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Transaction quotas
+//!
+//! The queries that a transaction queues are held in memory until `EXEC` (or `DISCARD`), so
+//! both a single transaction and all the open transactions together are limited in the number
+//! of queries and the bytes that they can queue. A query that would go over any of the limits
+//! isn't queued: it aborts the transaction with `err-txn-too-large` instead, which frees what
+//! the transaction had queued.
+//!
+//! What a transaction has queued is counted against the quota until the transaction ends,
+//! however it ends: by `EXEC`, `DISCARD`, an abort or the connection being closed
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// The most queries that a transaction can queue by default
+pub const DEFAULT_MAX_QUEUED: usize = 10_000;
+/// The most bytes that a transaction can queue by default
+pub const DEFAULT_MAX_TXN_BYTES: usize = 16 * 1024 * 1024;
+/// The most queries that all the open transactions can queue together by default
+pub const DEFAULT_MAX_TOTAL_QUEUED: usize = 1_000_000;
+/// The most bytes that all the open transactions can queue together by default
+pub const DEFAULT_MAX_TOTAL_TXN_BYTES: usize = 256 * 1024 * 1024;
+
+const ORD_RLX: Ordering = Ordering::Relaxed;
+
+/// The limits on the transactions and what all the open transactions have queued so far. See
+/// the [module level docs](self) for more information
+#[derive(Debug)]
+pub struct TxnQuota {
+    max_queued: AtomicUsize,
+    max_bytes: AtomicUsize,
+    max_total_queued: AtomicUsize,
+    max_total_bytes: AtomicUsize,
+    total_queued: AtomicUsize,
+    total_bytes: AtomicUsize,
+}
+
+impl TxnQuota {
+    pub const fn new() -> Self {
+        Self {
+            max_queued: AtomicUsize::new(DEFAULT_MAX_QUEUED),
+            max_bytes: AtomicUsize::new(DEFAULT_MAX_TXN_BYTES),
+            max_total_queued: AtomicUsize::new(DEFAULT_MAX_TOTAL_QUEUED),
+            max_total_bytes: AtomicUsize::new(DEFAULT_MAX_TOTAL_TXN_BYTES),
+            total_queued: AtomicUsize::new(0),
+            total_bytes: AtomicUsize::new(0),
+        }
+    }
+    /// Set the limits of a transaction (`max_queued` and `max_bytes`) and of all of them
+    /// together
+    pub fn configure(
+        &self,
+        max_queued: usize,
+        max_bytes: usize,
+        max_total_queued: usize,
+        max_total_bytes: usize,
+    ) {
+        self.max_queued.store(max_queued, ORD_RLX);
+        self.max_bytes.store(max_bytes, ORD_RLX);
+        self.max_total_queued.store(max_total_queued, ORD_RLX);
+        self.max_total_bytes.store(max_total_bytes, ORD_RLX);
+    }
+    /// Returns the most queries that a transaction can queue
+    pub fn max_queued(&self) -> usize {
+        self.max_queued.load(ORD_RLX)
+    }
+    /// Returns the most bytes that a transaction can queue
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes.load(ORD_RLX)
+    }
+    #[cfg(test)]
+    /// Returns the queries and the bytes that all the open transactions have queued
+    pub fn total(&self) -> (usize, usize) {
+        (
+            self.total_queued.load(ORD_RLX),
+            self.total_bytes.load(ORD_RLX),
+        )
+    }
+    /// Reserve a query of `bytes` bytes for a transaction that has already queued `queued`
+    /// queries of `queued_bytes` bytes. Returns false (and reserves nothing) if that would go
+    /// over any of the limits
+    pub fn reserve(&self, queued: usize, queued_bytes: usize, bytes: usize) -> bool {
+        if queued + 1 > self.max_queued() || queued_bytes + bytes > self.max_bytes() {
+            return false;
+        }
+        let total_queued = self.total_queued.fetch_add(1, ORD_RLX) + 1;
+        let total_bytes = self.total_bytes.fetch_add(bytes, ORD_RLX) + bytes;
+        if total_queued > self.max_total_queued.load(ORD_RLX)
+            || total_bytes > self.max_total_bytes.load(ORD_RLX)
+        {
+            self.release(1, bytes);
+            return false;
+        }
+        true
+    }
+    /// Give back `queued` queries of `bytes` bytes that a transaction had reserved
+    pub fn release(&self, queued: usize, bytes: usize) {
+        self.total_queued.fetch_sub(queued, ORD_RLX);
+        self.total_bytes.fetch_sub(bytes, ORD_RLX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_of_a_transaction() {
+        let quota = TxnQuota::new();
+        quota.configure(2, 100, 10, 1000);
+        assert!(quota.reserve(0, 0, 40));
+        assert!(quota.reserve(1, 40, 40));
+        // a third query
+        assert!(!quota.reserve(2, 80, 1));
+        // too many bytes
+        assert!(!quota.reserve(1, 40, 61));
+        assert_eq!(quota.total(), (2, 80));
+        quota.release(2, 80);
+        assert_eq!(quota.total(), (0, 0));
+    }
+
+    #[test]
+    fn test_limits_of_all_transactions() {
+        let quota = TxnQuota::new();
+        quota.configure(10, 100, 3, 150);
+        // three transactions with a query each
+        assert!(quota.reserve(0, 0, 50));
+        assert!(quota.reserve(0, 0, 50));
+        // the bytes of all of them
+        assert!(!quota.reserve(0, 0, 51));
+        assert!(quota.reserve(0, 0, 50));
+        // and the queries of all of them
+        assert!(!quota.reserve(0, 0, 0));
+        // a failed reservation doesn't count
+        assert_eq!(quota.total(), (3, 150));
+        // once one of the transactions ends, another one can queue
+        quota.release(1, 50);
+        assert!(quota.reserve(0, 0, 50));
+        assert_eq!(quota.total(), (3, 150));
+    }
+}
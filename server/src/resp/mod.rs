@@ -36,6 +36,9 @@ use std::pin::Pin;
 use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 
+pub mod retry;
+pub mod tristate;
+
 /// # The `Writable` trait
 /// All trait implementors are given access to an asynchronous stream to which
 /// they must write a response.
@@ -90,6 +93,11 @@ impl BytesWrapper {
     }
 }
 
+/// A `FrameWrapper` wraps around a response frame that was already serialized (like the
+/// frames in a [read cache](crate::kvengine::cache)), which is written out as is
+#[derive(Debug, PartialEq)]
+pub struct FrameWrapper(pub Bytes);
+
 impl Writable for Vec<u8> {
     fn write<'s>(
         self,
@@ -179,6 +187,19 @@ impl Writable for BytesWrapper {
     }
 }
 
+impl Writable for FrameWrapper {
+    fn write<'s>(
+        self,
+        con: &'s mut impl IsConnection,
+    ) -> Pin<Box<(dyn Future<Output = Result<(), IoError>> + Send + Sync + 's)>> {
+        async fn write_bytes(con: &mut impl IsConnection, frame: Bytes) -> Result<(), IoError> {
+            con.write_lowlevel(&frame).await?;
+            Ok(())
+        }
+        Box::pin(write_bytes(con, self.0))
+    }
+}
+
 impl Writable for RespCode {
     fn write<'s>(
         self,
@@ -189,17 +210,15 @@ impl Writable for RespCode {
                 // Since this is an other error which contains a description
                 // we'll write !<no_of_bytes> followed by the string
                 con.write_lowlevel(&[b'!']).await?;
-                // Convert the string into a vector of bytes
-                let e = e.to_string().into_bytes();
-                // Now get the length of the byte vector and turn it into
-                // a string and then into a byte vector
+                let e = e.as_bytes();
+                // Now get the length of the error string as bytes
                 let len_as_bytes = Integer64::from(e.len());
                 // Write the length
                 con.write_lowlevel(&len_as_bytes).await?;
                 // Then an LF
                 con.write_lowlevel(&[b'\n']).await?;
                 // Then the error string
-                con.write_lowlevel(&e).await?;
+                con.write_lowlevel(e).await?;
                 // Then another LF
                 con.write_lowlevel(&[b'\n']).await?;
                 // And now we're done
@@ -212,7 +231,7 @@ impl Writable for RespCode {
             // We need to get the u8 version of the response code
             let code: u8 = code.into();
             // We need the UTF8 equivalent of the response code
-            let code_bytes = code.to_string().into_bytes();
+            let code_bytes = Integer64::init(code as u64);
             con.write_lowlevel(&code_bytes).await?;
             // Now append a newline
             con.write_lowlevel(&[b'\n']).await?;
@@ -229,7 +248,7 @@ impl Writable for usize {
     ) -> Pin<Box<(dyn Future<Output = Result<(), IoError>> + Send + Sync + 's)>> {
         async fn write_bytes(con: &mut impl IsConnection, val: usize) -> Result<(), IoError> {
             con.write_lowlevel(b":").await?;
-            let usize_bytes = val.to_string().into_bytes();
+            let usize_bytes = Integer64::from(val);
             let usize_bytes_len = Integer64::from(usize_bytes.len());
             con.write_lowlevel(&usize_bytes_len).await?;
             con.write_lowlevel(b"\n").await?;
@@ -248,7 +267,7 @@ impl Writable for u64 {
     ) -> Pin<Box<(dyn Future<Output = Result<(), IoError>> + Send + Sync + 's)>> {
         async fn write_bytes(con: &mut impl IsConnection, val: u64) -> Result<(), IoError> {
             con.write_lowlevel(b":").await?;
-            let usize_bytes = val.to_string().into_bytes();
+            let usize_bytes = Integer64::init(val);
             let usize_bytes_len = Integer64::from(usize_bytes.len());
             con.write_lowlevel(&usize_bytes_len).await?;
             con.write_lowlevel(b"\n").await?;
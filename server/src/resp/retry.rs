@@ -0,0 +1,164 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Retry hints
+//!
+//! Some errors only mean "not right now": writes that are turned away by backpressure or by
+//! the memory ceiling go through once the server catches up, and a snapshot that is refused
+//! because another one is underway can be taken once that one completes. Other errors (a key
+//! that doesn't match the encoding of its table, for one) fail the same way however often the
+//! query is retried. A connection that negotiates the `retryhints` capability (see
+//! [`handshake`](crate::dbnet::handshake)) has the errors that are worth retrying written with
+//! a hint after their code, in the same error frame:
+//! - `!<len>\n<code>;retry\n` if the query can be retried, but there's no telling when it
+//!   would go through
+//! - `!<len>\n<code>;retry=<ms>\n` if it's likely to go through after about `ms` milliseconds
+//!
+//! An error without a hint isn't worth retrying. Connections that didn't negotiate the
+//! capability get the same response codes as always.
+//!
+//! Every error that is written through here is a [`Hinted`] error, and [`Hinted::retry`] has
+//! to say whether it can be retried: a new error can't be added without deciding that
+
+use crate::protocol::responses::groups;
+use crate::registry;
+use crate::services::memwatch;
+use bytes::BytesMut;
+use tokio::time::Duration;
+
+/// The shortest time that a retry hint suggests waiting for, which is how often a writer
+/// that's held back by backpressure rechecks the dirty bytes
+const MIN_RETRY_AFTER: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// An error response whose retryability is known
+pub enum Hinted {
+    /// The dirty bytes are above the mark and writes are rejected (or the connection's
+    /// timeout ran out while the write was held back)
+    BusyWrites,
+    /// The memory usage is above the ceiling and writes are rejected
+    OutOfMemory,
+    /// Another snapshot is being taken
+    SnapshotBusy,
+    /// Nothing was flushed before `SYS FLUSHWAIT` timed out
+    FlushTimeout,
+    /// The key or the value doesn't match the encoding of the table
+    Encoding,
+    /// The table isn't a key/value table
+    WrongModel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// Whether the query that failed with an error is worth retrying
+pub enum Retry {
+    /// The query fails the same way however often it is retried
+    Never,
+    /// The query may go through if it is retried (after about this long, if that's known)
+    After(Option<Duration>),
+}
+
+impl Hinted {
+    /// Returns the response code element of the error, as connections without the
+    /// `retryhints` capability get it
+    pub const fn code(self) -> &'static [u8] {
+        match self {
+            Self::BusyWrites => groups::SERVER_BUSY_WRITES,
+            Self::OutOfMemory => groups::OUT_OF_MEMORY,
+            Self::SnapshotBusy => groups::SNAPSHOT_BUSY,
+            Self::FlushTimeout => groups::FLUSH_TIMEOUT,
+            Self::Encoding => groups::ENCODING_ERROR,
+            Self::WrongModel => groups::WRONG_MODEL,
+        }
+    }
+    /// Returns whether (and when) the query that failed with this error can be retried, from
+    /// the state of the server right now
+    pub fn retry(self) -> Retry {
+        match self {
+            Self::BusyWrites => Retry::After(Some(busy_writes_retry_after())),
+            // the memory ceiling is only lifted once the usage is sampled again
+            Self::OutOfMemory => Retry::After(Some(memwatch::SAMPLE_INTERVAL)),
+            Self::SnapshotBusy | Self::FlushTimeout => Retry::After(None),
+            Self::Encoding | Self::WrongModel => Retry::Never,
+        }
+    }
+    /// Assemble the error frame with its retry hint (if it has one) in `scratch`
+    pub fn assemble(self, scratch: &mut BytesMut) {
+        let code = self.code();
+        let hint = match self.retry() {
+            Retry::Never => {
+                scratch.extend_from_slice(code);
+                return;
+            }
+            Retry::After(None) => "retry".to_owned(),
+            Retry::After(Some(after)) => format!("retry={}", after.as_millis()),
+        };
+        // the code element is `!<len>\n<code>\n`
+        let start = code.iter().position(|byte| *byte == b'\n').unwrap_or(0) + 1;
+        let code = &code[start..code.len() - 1];
+        let len = code.len() + 1 + hint.len();
+        scratch.extend_from_slice(format!("!{}\n", len).as_bytes());
+        scratch.extend_from_slice(code);
+        scratch.extend_from_slice(b";");
+        scratch.extend_from_slice(hint.as_bytes());
+        scratch.extend_from_slice(b"\n");
+    }
+}
+
+/// Returns how long a writer that was turned away by backpressure should wait before it
+/// retries: until a pause of the flush service runs out, or otherwise as long as a flush takes
+/// on average, since crossing the mark asks for a flush right away
+fn busy_writes_retry_after() -> Duration {
+    if let Some(paused_for) = registry::get_background().flush.paused_for() {
+        return paused_for.max(MIN_RETRY_AFTER);
+    }
+    let totals = registry::get_flush_log().totals();
+    if totals.flushes == 0 {
+        return MIN_RETRY_AFTER;
+    }
+    let average = totals.duration.as_millis() / totals.flushes as u128;
+    Duration::from_millis(average as u64).max(MIN_RETRY_AFTER)
+}
+
+#[test]
+fn test_hints() {
+    let frame = |error: Hinted| {
+        let mut scratch = BytesMut::new();
+        error.assemble(&mut scratch);
+        String::from_utf8(scratch.to_vec()).unwrap()
+    };
+    assert_eq!(
+        frame(Hinted::SnapshotBusy),
+        "!23\nerr-snapshot-busy;retry\n"
+    );
+    assert_eq!(
+        frame(Hinted::OutOfMemory),
+        "!27\nerr-out-of-memory;retry=500\n"
+    );
+    // the errors that aren't worth retrying are written as they always were
+    assert_eq!(frame(Hinted::Encoding).as_bytes(), groups::ENCODING_ERROR);
+    assert_eq!(frame(Hinted::WrongModel).as_bytes(), groups::WRONG_MODEL);
+    assert_eq!(Hinted::Encoding.retry(), Retry::Never);
+}
@@ -0,0 +1,157 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Tristate array elements
+//!
+//! Multi-key actions (like `MGET` and `POP`) return an array with one element per key, and
+//! they have always used a response code for the keys that they couldn't return a value for.
+//! Some client libraries stop parsing an array as soon as they see an element that isn't a
+//! string, so a connection can negotiate the `tristate` capability (see
+//! [`handshake`](crate::dbnet::handshake)) to have these elements written in one of three
+//! forms instead:
+//! - a value: `+<len>\n<bytes>\n` (just like before)
+//! - null, if the key doesn't exist: `-\n`
+//! - an error with a code, if the key exists but its value can't be returned:
+//!   `^<len>\n<code>\n`, where the code is the response code or the error string that the
+//!   element would have had otherwise (for example, `9` for an encoding error)
+
+use super::{BytesWrapper, IsConnection, Writable};
+use crate::kvengine::ReadError;
+use crate::protocol::responses::groups;
+use bytes::Bytes;
+use std::future::Future;
+use std::io::Error as IoError;
+use std::pin::Pin;
+
+/// The tsymbol of a null element
+pub const TSYMBOL_NULL: u8 = b'-';
+/// The tsymbol of an error element
+pub const TSYMBOL_ERROR: u8 = b'^';
+
+#[derive(Debug, PartialEq)]
+/// An element of a multi-key action's array response
+pub enum TriState {
+    /// The value of the key
+    Value(Bytes),
+    /// The key doesn't exist
+    Null,
+    /// The value couldn't be returned. This holds the response code element (from
+    /// [`groups`]) that is written out without the tristate encoding
+    Error(&'static [u8]),
+}
+
+impl TriState {
+    /// Returns the element for the result of a lookup (see [`KVEngine::read`])
+    ///
+    /// [`KVEngine::read`]: crate::kvengine::KVEngine::read
+    pub fn from_lookup(result: Result<Option<Bytes>, ReadError>) -> Self {
+        match result {
+            Ok(Some(value)) => Self::Value(value),
+            Ok(None) => Self::Null,
+            Err(ReadError::BadKey) => Self::Error(groups::ENCODING_ERROR),
+            Err(ReadError::Unreadable) => Self::Error(groups::VALUE_UNREADABLE),
+        }
+    }
+}
+
+impl Writable for TriState {
+    fn write<'s>(
+        self,
+        con: &'s mut impl IsConnection,
+    ) -> Pin<Box<(dyn Future<Output = Result<(), IoError>> + Send + Sync + 's)>> {
+        async fn write_bytes(
+            con: &mut impl IsConnection,
+            element: TriState,
+        ) -> Result<(), IoError> {
+            match element {
+                TriState::Value(value) => BytesWrapper(value).write(con).await,
+                TriState::Null => con.write_lowlevel(&[TSYMBOL_NULL, b'\n']).await,
+                TriState::Error(code) => {
+                    // the code element is `!<len>\n<code>\n`, so only the tsymbol changes
+                    debug_assert_eq!(code.first(), Some(&b'!'));
+                    con.write_lowlevel(&[TSYMBOL_ERROR]).await?;
+                    con.write_lowlevel(&code[1..]).await
+                }
+            }
+        }
+        Box::pin(write_bytes(con, self))
+    }
+}
+
+/// A [`TriState`] that is written out the way that the elements were written before the
+/// tristate encoding: a value as a string, null as response code 1 and an error as its
+/// response code element
+pub struct Legacy(pub TriState);
+
+impl Writable for Legacy {
+    fn write<'s>(
+        self,
+        con: &'s mut impl IsConnection,
+    ) -> Pin<Box<(dyn Future<Output = Result<(), IoError>> + Send + Sync + 's)>> {
+        match self.0 {
+            TriState::Value(value) => BytesWrapper(value).write(con),
+            TriState::Null => groups::NIL.write(con),
+            TriState::Error(code) => code.write(con),
+        }
+    }
+}
+
+#[cfg(test)]
+/// Decode an array response (`&<n>\n` followed by `n` elements, each of which is a string,
+/// a tristate element or a response code) into its elements. Response codes are decoded as
+/// [`TriState::Error`]s with their code, and this panics if `response` is malformed
+pub fn decode_array(response: &[u8]) -> Vec<TriState> {
+    let mut lines = response.split(|byte| *byte == b'\n');
+    let mut next = || lines.next().expect("response ended early");
+    let header = next();
+    assert_eq!(header[0], b'&');
+    let count: usize = std::str::from_utf8(&header[1..]).unwrap().parse().unwrap();
+    let elements = (0..count)
+        .map(|_| {
+            let line = next();
+            match line[0] {
+                TSYMBOL_NULL => {
+                    assert_eq!(line.len(), 1);
+                    TriState::Null
+                }
+                tsymbol @ b'+' | tsymbol @ b'!' | tsymbol @ TSYMBOL_ERROR => {
+                    let len: usize = std::str::from_utf8(&line[1..]).unwrap().parse().unwrap();
+                    let body = next();
+                    assert_eq!(body.len(), len);
+                    if tsymbol == b'+' {
+                        TriState::Value(Bytes::copy_from_slice(body))
+                    } else {
+                        let code = format!("!{}\n{}\n", len, String::from_utf8_lossy(body));
+                        TriState::Error(Box::leak(code.into_bytes().into_boxed_slice()))
+                    }
+                }
+                tsymbol => panic!("unexpected tsymbol {}", tsymbol as char),
+            }
+        })
+        .collect();
+    assert_eq!(next(), b"");
+    elements
+}
@@ -0,0 +1,994 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Schema export and apply
+//!
+//! `skyd schema export <file>` writes the keyspaces and tables of the data directory (and
+//! their properties) to a JSON document, and `skyd schema apply <file>` brings the data
+//! directory in line with such a document: the keyspaces and tables that are missing are
+//! created and the properties that can be changed (the maximum value size of a table, the
+//! default table of a keyspace and whether a keyspace is archived) are changed. The
+//! properties that a table is created with (its model, volatility, ordering and read cache)
+//! can't be changed, so a difference in any of them is reported as a conflict and nothing is
+//! applied.
+//!
+//! The document is deterministic (the keyspaces and tables are sorted by name) so that it can
+//! be reviewed and kept under version control, and it has a `version` field so that the
+//! format can change without older documents being misread. The `system` keyspace and the
+//! attached snapshots are left out.
+//!
+//! Applying a document first computes a [`Plan`], which `--dry-run` only prints. Keyspaces and
+//! tables that aren't in the document are dropped, but only if `--allow-drop` is passed. Since
+//! the plan of a document that was already applied is empty, applying it again does nothing
+
+use crate::config::ConfigError;
+use crate::corestore::memstore::{self, DdlError, Keyspace, ObjectID, DEFAULT, SYSTEM};
+use crate::corestore::table::Table;
+use crate::corestore::{Corestore, OwnedEntityGroup};
+use crate::queryengine::parser;
+use crate::queryengine::{check_new_name, ActionIter, NameError};
+use bytes::Bytes;
+use clap::ArgMatches;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io::Error as IoError;
+use std::sync::Arc;
+
+/// The version of the format of the schema document
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// A schema document: every keyspace (except `system`), sorted by name
+pub struct Schema {
+    /// The version of the format (see [`SCHEMA_VERSION`])
+    pub version: u32,
+    /// The keyspaces
+    pub keyspaces: Vec<KeyspaceSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// A keyspace in a schema document
+pub struct KeyspaceSchema {
+    /// The name of the keyspace, as it was created
+    pub name: String,
+    /// The default table of the keyspace, if it has one
+    #[serde(default)]
+    pub default_table: Option<String>,
+    /// Whether the keyspace is archived
+    #[serde(default)]
+    pub archived: bool,
+    /// The tables of the keyspace, sorted by name
+    #[serde(default)]
+    pub tables: Vec<TableSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+/// A table in a schema document
+pub struct TableSchema {
+    /// The name of the table, as it was created
+    pub name: String,
+    /// The model of the table, like `keymap(str,binstr)`
+    pub model: String,
+    /// Whether the table is volatile
+    #[serde(default)]
+    pub volatile: bool,
+    /// Whether the table keeps an ordered index
+    #[serde(default)]
+    pub ordered: bool,
+    /// Whether the table keeps a read cache
+    #[serde(default)]
+    pub cached: bool,
+    /// The maximum size of a value in the table (in bytes), if the table has its own limit
+    #[serde(default)]
+    pub max_value_size: Option<u64>,
+}
+
+impl Schema {
+    /// Parse a schema document, making sure that we understand its version first
+    pub fn parse(document: &str) -> Result<Self, SchemaError> {
+        let value: serde_json::Value =
+            serde_json::from_str(document).map_err(|e| SchemaError::Parse(e.to_string()))?;
+        match value.get("version").and_then(|version| version.as_u64()) {
+            Some(version) if version == SCHEMA_VERSION as u64 => {}
+            Some(version) => return Err(SchemaError::Version(version)),
+            None => {
+                return Err(SchemaError::Parse(
+                    "the document has no `version` field".to_owned(),
+                ))
+            }
+        }
+        serde_json::from_value(value).map_err(|e| SchemaError::Parse(e.to_string()))
+    }
+    /// Read and parse the schema document in `file`
+    pub fn read(file: &str) -> Result<Self, SchemaError> {
+        Self::parse(&fs::read_to_string(file)?)
+    }
+    /// Render the document, which is the same for the same schema
+    pub fn render(&self) -> String {
+        let mut document =
+            serde_json::to_string_pretty(self).expect("a schema should always serialize");
+        document.push('\n');
+        document
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A step of a [`Plan`]. The keyspaces and tables are named as they are in the document (or
+/// as they were created, for the ones that are dropped)
+pub enum Operation {
+    /// Unarchive the keyspace
+    UnarchiveKeyspace(String),
+    /// Create the keyspace
+    CreateKeyspace(String),
+    /// Create the table in the keyspace
+    CreateTable(String, TableSchema),
+    /// Change the maximum value size of a table
+    SetValueLimit {
+        keyspace: String,
+        table: String,
+        limit: Option<u64>,
+    },
+    /// Make a table the default table of its keyspace
+    SetDefaultTable { keyspace: String, table: String },
+    /// Drop a table
+    DropTable { keyspace: String, table: String },
+    /// Archive the keyspace
+    ArchiveKeyspace(String),
+    /// Drop the keyspace, along with its tables
+    DropKeyspace(String),
+}
+
+impl Operation {
+    /// Returns true if the operation removes data
+    pub fn is_drop(&self) -> bool {
+        matches!(self, Self::DropTable { .. } | Self::DropKeyspace(_))
+    }
+}
+
+impl fmt::Display for Operation {
+    /// The operation, written (as far as possible) like the query that does the same
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnarchiveKeyspace(ks) => write!(f, "UNARCHIVE KEYSPACE {}", ks),
+            Self::CreateKeyspace(ks) => write!(f, "CREATE KEYSPACE {}", ks),
+            Self::CreateTable(ks, table) => {
+                write!(f, "CREATE TABLE {}:{} {}", ks, table.name, table.model)?;
+                let properties = [
+                    (table.volatile, "volatile"),
+                    (table.ordered, "ordered"),
+                    (table.cached, "cached"),
+                ];
+                for (_, property) in properties.iter().filter(|(isset, _)| *isset) {
+                    write!(f, " {}", property)?;
+                }
+                if let Some(limit) = table.max_value_size {
+                    write!(f, " maxvaluesize:{}", limit)?;
+                }
+                Ok(())
+            }
+            Self::SetValueLimit {
+                keyspace,
+                table,
+                limit: Some(limit),
+            } => write!(
+                f,
+                "ALTER TABLE {}:{} maxvaluesize:{}",
+                keyspace, table, limit
+            ),
+            Self::SetValueLimit {
+                keyspace,
+                table,
+                limit: None,
+            } => write!(
+                f,
+                "ALTER TABLE {}:{} maxvaluesize:unlimited",
+                keyspace, table
+            ),
+            Self::SetDefaultTable { keyspace, table } => {
+                write!(f, "ALTER KEYSPACE {} DEFAULT TABLE {}", keyspace, table)
+            }
+            Self::DropTable { keyspace, table } => write!(f, "DROP TABLE {}:{}", keyspace, table),
+            Self::ArchiveKeyspace(ks) => write!(f, "ARCHIVE KEYSPACE {}", ks),
+            Self::DropKeyspace(ks) => write!(f, "DROP KEYSPACE {} force", ks),
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+/// What it takes to bring the store in line with a schema document
+pub struct Plan {
+    /// The operations, in the order in which they are applied
+    pub operations: Vec<Operation>,
+    /// The differences that can't be applied. If there are any, nothing is applied
+    pub conflicts: Vec<String>,
+}
+
+impl Plan {
+    /// Returns true if the store is already in line with the document
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty() && self.conflicts.is_empty()
+    }
+    /// Returns the number of operations that remove data
+    pub fn drops(&self) -> usize {
+        self.operations.iter().filter(|op| op.is_drop()).count()
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "Nothing to do: the store matches the schema");
+        }
+        for op in &self.operations {
+            writeln!(f, "{}", op)?;
+        }
+        for conflict in &self.conflicts {
+            writeln!(f, "CONFLICT: {}", conflict)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// An error that stops a schema from being exported or applied
+pub enum SchemaError {
+    /// The file couldn't be read or written
+    Io(String),
+    /// The file isn't a schema document
+    Parse(String),
+    /// The document has a version that we don't understand
+    Version(u64),
+    /// The document is a schema document, but it doesn't make sense
+    Invalid(String),
+    /// The plan has conflicts, so nothing was applied
+    Conflicts(usize),
+    /// The plan drops keyspaces or tables and we weren't allowed to
+    Destructive(usize),
+    /// An operation failed. The ones before it were applied
+    Failed(String, DdlError),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "failed to access the schema file: {}", e),
+            Self::Parse(e) => write!(f, "bad schema document: {}", e),
+            Self::Version(version) => write!(
+                f,
+                "the schema document has version {}, but only version {} is supported",
+                version, SCHEMA_VERSION
+            ),
+            Self::Invalid(e) => write!(f, "invalid schema document: {}", e),
+            Self::Conflicts(count) => write!(
+                f,
+                "the schema has {} conflict(s) with the store, so nothing was applied",
+                count
+            ),
+            Self::Destructive(count) => write!(
+                f,
+                "applying the schema would drop {} keyspace(s) or table(s); pass `--allow-drop` to allow that",
+                count
+            ),
+            Self::Failed(op, e) => write!(f, "failed to apply `{}`: {:?}", op, e),
+        }
+    }
+}
+
+impl From<IoError> for SchemaError {
+    fn from(e: IoError) -> Self {
+        Self::Io(e.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// What `skyd schema` was asked to do
+pub enum SchemaCommand {
+    /// Write the schema of the store to the file
+    Export,
+    /// Bring the store in line with the file
+    Apply {
+        /// Only print the plan
+        dry_run: bool,
+        /// Allow keyspaces and tables to be dropped
+        allow_drop: bool,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+/// The options passed to `skyd schema`
+pub struct SchemaOpts {
+    /// What to do
+    pub command: SchemaCommand,
+    /// The schema document
+    pub file: String,
+}
+
+impl SchemaOpts {
+    /// Get the options from the matches of the `schema` subcommand
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, ConfigError> {
+        let (command, matches) = match matches.subcommand() {
+            ("export", Some(matches)) => (SchemaCommand::Export, matches),
+            ("apply", Some(matches)) => (
+                SchemaCommand::Apply {
+                    dry_run: matches.is_present("dryrun"),
+                    allow_drop: matches.is_present("allowdrop"),
+                },
+                matches,
+            ),
+            _ => {
+                return Err(ConfigError::CliArgErr(
+                    "Expected `skyd schema export <file>` or `skyd schema apply <file>`",
+                ))
+            }
+        };
+        Ok(Self {
+            command,
+            // this is required
+            file: matches.value_of("file").unwrap_or_default().to_owned(),
+        })
+    }
+}
+
+/// Returns the name that something kept under `key` is shown with
+fn name_of(key: &ObjectID) -> String {
+    String::from_utf8_lossy(key).into_owned()
+}
+
+/// Returns the key that the keyspace or table called `name` is (or would be) kept under, if
+/// it is a name that can be used in a query
+fn key_of(name: &str) -> Option<ObjectID> {
+    if name.is_empty() || name.contains(':') {
+        None
+    } else {
+        memstore::entity_key(name.as_bytes())
+    }
+}
+
+/// Returns `name` as an [`ObjectID`]. The names in a validated document always fit
+fn object_id(name: &str) -> ObjectID {
+    unsafe {
+        // UNSAFE: validate checked the length
+        ObjectID::from_slice(name.as_bytes())
+    }
+}
+
+/// Parse `model` the way `CREATE TABLE` does, for the table `keyspace:table`
+fn parse_model(keyspace: &str, table: &str, model: &str) -> Option<(OwnedEntityGroup, u8)> {
+    let mut args = ActionIter::new(vec![
+        Bytes::from(format!("{}:{}", keyspace, table)),
+        Bytes::copy_from_slice(model.as_bytes()),
+    ]);
+    parser::parse_table_args(&mut args).ok()
+}
+
+/// Get the schema of the store
+pub fn export(db: &Corestore) -> Schema {
+    let mut keyspaces: Vec<KeyspaceSchema> = db
+        .get_store()
+        .keyspaces
+        .iter()
+        .filter(|ks| ks.key().ne(&SYSTEM))
+        .map(|ks| export_keyspace(ks.key(), ks.value()))
+        .collect();
+    keyspaces.sort_by(|a, b| a.name.cmp(&b.name));
+    Schema {
+        version: SCHEMA_VERSION,
+        keyspaces,
+    }
+}
+
+fn export_keyspace(ksid: &ObjectID, ks: &Keyspace) -> KeyspaceSchema {
+    let mut tables: Vec<TableSchema> = ks
+        .tables
+        .iter()
+        .map(|table| export_table(&ks.table_name(table.key()), table.value()))
+        .collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    let default_table = ks.default_table();
+    // a keyspace has no default table until one is set (or a `default` table is created)
+    let default_table = if ks.tables.contains_key(&default_table) {
+        Some(name_of(&ks.table_name(&default_table)))
+    } else {
+        None
+    };
+    KeyspaceSchema {
+        name: name_of(ks.name().unwrap_or(ksid)),
+        default_table,
+        archived: ks.is_archived(),
+        tables,
+    }
+}
+
+fn export_table(name: &ObjectID, table: &Table) -> TableSchema {
+    TableSchema {
+        name: name_of(name),
+        model: table.model_name().to_owned(),
+        volatile: table.is_volatile(),
+        ordered: table.is_ordered(),
+        cached: table.has_read_cache(),
+        max_value_size: table.own_value_limit(),
+    }
+}
+
+/// Make sure that the document makes sense on its own, before it is compared with the store
+fn validate(schema: &Schema) -> Result<(), SchemaError> {
+    fn invalid(e: String) -> Result<(), SchemaError> {
+        Err(SchemaError::Invalid(e))
+    }
+    if schema.version != SCHEMA_VERSION {
+        return Err(SchemaError::Version(schema.version as u64));
+    }
+    let mut keyspaces = HashSet::new();
+    for ks in &schema.keyspaces {
+        let ksid = match key_of(&ks.name) {
+            Some(ksid) => ksid,
+            None => return invalid(format!("`{}` isn't a valid keyspace name", ks.name)),
+        };
+        if ksid.eq(&SYSTEM) {
+            return invalid("the `system` keyspace can't be in a schema".to_owned());
+        }
+        if ks.archived && ksid.eq(&DEFAULT) {
+            return invalid("the `default` keyspace can't be archived".to_owned());
+        }
+        if !keyspaces.insert(ksid) {
+            return invalid(format!("the keyspace `{}` is listed twice", ks.name));
+        }
+        let mut tables = HashSet::new();
+        for table in &ks.tables {
+            let tblid = match key_of(&table.name) {
+                Some(tblid) => tblid,
+                None => {
+                    return invalid(format!(
+                        "`{}` isn't a valid table name (in `{}`)",
+                        table.name, ks.name
+                    ))
+                }
+            };
+            if !tables.insert(tblid) {
+                return invalid(format!(
+                    "the table `{}:{}` is listed twice",
+                    ks.name, table.name
+                ));
+            }
+            if parse_model(&ks.name, &table.name, &table.model).is_none() {
+                return invalid(format!(
+                    "`{}` (of `{}:{}`) isn't a valid model",
+                    table.model, ks.name, table.name
+                ));
+            }
+            if table.max_value_size == Some(0) {
+                return invalid(format!(
+                    "the maximum value size of `{}:{}` can't be zero",
+                    ks.name, table.name
+                ));
+            }
+        }
+        if let Some(default_table) = &ks.default_table {
+            if !matches!(key_of(default_table), Some(tblid) if tables.contains(&tblid)) {
+                return invalid(format!(
+                    "the default table `{}` of `{}` isn't one of its tables",
+                    default_table, ks.name
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the reason why the keyspace or table `entity` can't be created with the name
+/// `name`, if it can't
+fn name_conflict(what: &str, entity: &str, name: &str) -> Option<String> {
+    let rule = match check_new_name(name.as_bytes()) {
+        Ok(()) => return None,
+        Err(NameError::Reserved) => "is reserved".to_owned(),
+        Err(NameError::TooLong(limit)) => format!("is longer than {} bytes", limit),
+        Err(NameError::BadCharacter) => "has a character that isn't allowed".to_owned(),
+    };
+    Some(format!(
+        "can't create the {} `{}`: the name {}",
+        what, entity, rule
+    ))
+}
+
+/// Compare the document with the store and work out what has to be done to bring the store in
+/// line with it. Nothing is changed
+pub fn plan(db: &Corestore, schema: &Schema) -> Result<Plan, SchemaError> {
+    validate(schema)?;
+    let store = db.get_store();
+    let mut plan = Plan::default();
+    let mut listed = HashSet::new();
+    for ks in &schema.keyspaces {
+        let ksid = memstore::key_of(&object_id(&ks.name));
+        let live = store.keyspaces.get(&ksid).map(|live| live.clone());
+        listed.insert(ksid);
+        match live {
+            Some(live) => plan_keyspace(&mut plan, ks, &live),
+            None => plan_new_keyspace(&mut plan, ks),
+        }
+    }
+    let mut dropped: Vec<(String, bool)> = store
+        .keyspaces
+        .iter()
+        .filter(|ks| ks.key().ne(&SYSTEM) && !listed.contains(ks.key()))
+        .map(|ks| {
+            let name = name_of(ks.value().name().unwrap_or_else(|| ks.key()));
+            (name, ks.key().eq(&DEFAULT))
+        })
+        .collect();
+    dropped.sort();
+    for (name, is_default) in dropped {
+        if is_default {
+            plan.conflicts
+                .push("the `default` keyspace can't be dropped".to_owned());
+        } else {
+            plan.operations.push(Operation::DropKeyspace(name));
+        }
+    }
+    Ok(plan)
+}
+
+/// Plan a keyspace that isn't in the store yet
+fn plan_new_keyspace(plan: &mut Plan, ks: &KeyspaceSchema) {
+    plan.conflicts
+        .extend(name_conflict("keyspace", &ks.name, &ks.name));
+    plan.operations
+        .push(Operation::CreateKeyspace(ks.name.clone()));
+    for table in &ks.tables {
+        let entity = format!("{}:{}", ks.name, table.name);
+        plan.conflicts
+            .extend(name_conflict("table", &entity, &table.name));
+        plan.operations
+            .push(Operation::CreateTable(ks.name.clone(), table.clone()));
+    }
+    if let Some(default_table) = &ks.default_table {
+        plan.operations.push(Operation::SetDefaultTable {
+            keyspace: ks.name.clone(),
+            table: default_table.clone(),
+        });
+    }
+    if ks.archived {
+        plan.operations
+            .push(Operation::ArchiveKeyspace(ks.name.clone()));
+    }
+}
+
+/// Plan a keyspace that is already in the store
+fn plan_keyspace(plan: &mut Plan, ks: &KeyspaceSchema, live: &Keyspace) {
+    let mut creates = Vec::new();
+    let mut limits = Vec::new();
+    let mut listed = HashSet::new();
+    for table in &ks.tables {
+        let tblid = memstore::key_of(&object_id(&table.name));
+        let entity = format!("{}:{}", ks.name, table.name);
+        let live_table = live.tables.get(&tblid).map(|table| table.clone());
+        listed.insert(tblid);
+        match live_table {
+            None => {
+                plan.conflicts
+                    .extend(name_conflict("table", &entity, &table.name));
+                creates.push(Operation::CreateTable(ks.name.clone(), table.clone()));
+            }
+            Some(live_table) => {
+                plan.conflicts
+                    .extend(table_conflicts(&entity, table, &live_table));
+                if table.max_value_size != live_table.own_value_limit() {
+                    limits.push(Operation::SetValueLimit {
+                        keyspace: ks.name.clone(),
+                        table: table.name.clone(),
+                        limit: table.max_value_size,
+                    });
+                }
+            }
+        }
+    }
+    let mut changes = creates;
+    changes.extend(limits);
+    let live_default = live.default_table();
+    match &ks.default_table {
+        Some(default_table) => {
+            if memstore::key_of(&object_id(default_table)) != live_default {
+                changes.push(Operation::SetDefaultTable {
+                    keyspace: ks.name.clone(),
+                    table: default_table.clone(),
+                });
+            }
+        }
+        None if live.tables.contains_key(&live_default) => plan
+            .conflicts
+            .push(format!("the default table of `{}` can't be unset", ks.name)),
+        None => {}
+    }
+    let mut dropped: Vec<(String, bool)> = live
+        .tables
+        .iter()
+        .filter(|table| !listed.contains(table.key()))
+        .map(|table| {
+            let name = name_of(&live.table_name(table.key()));
+            (name, table.key().eq(&DEFAULT))
+        })
+        .collect();
+    dropped.sort();
+    for (table, is_default) in dropped {
+        if is_default {
+            plan.conflicts.push(format!(
+                "the table `{}:{}` can't be dropped",
+                ks.name, table
+            ));
+        } else {
+            changes.push(Operation::DropTable {
+                keyspace: ks.name.clone(),
+                table,
+            });
+        }
+    }
+    match (live.is_archived(), ks.archived) {
+        (true, true) if !changes.is_empty() => plan.conflicts.push(format!(
+            "the keyspace `{}` is archived, so its tables can't be changed without unarchiving it",
+            ks.name
+        )),
+        (true, true) => {}
+        (true, false) => {
+            plan.operations
+                .push(Operation::UnarchiveKeyspace(ks.name.clone()));
+            plan.operations.extend(changes);
+        }
+        (false, archived) => {
+            plan.operations.extend(changes);
+            if archived {
+                plan.operations
+                    .push(Operation::ArchiveKeyspace(ks.name.clone()));
+            }
+        }
+    }
+}
+
+/// Returns the properties of `table` that differ from those of `live` and can't be changed
+fn table_conflicts(entity: &str, table: &TableSchema, live: &Table) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    let model_code = parse_model("ks", "tbl", &table.model).map(|(_, code)| code);
+    if model_code != Some(live.get_model_code()) {
+        conflicts.push(format!(
+            "the model of `{}` is `{}` but the schema has `{}`",
+            entity,
+            live.model_name(),
+            table.model
+        ));
+    }
+    let properties = [
+        ("volatile", table.volatile, live.is_volatile()),
+        ("ordered", table.ordered, live.is_ordered()),
+        ("cached", table.cached, live.has_read_cache()),
+    ];
+    for (property, wanted, isset) in properties.iter() {
+        if wanted != isset {
+            conflicts.push(format!(
+                "`{}` can't be made {}{} after it was created",
+                entity,
+                if *wanted { "" } else { "not " },
+                property
+            ));
+        }
+    }
+    conflicts
+}
+
+/// Returns the reason why the plan would be refused by [`apply`], if it would be
+pub fn check(plan: &Plan, allow_drop: bool) -> Result<(), SchemaError> {
+    if !plan.conflicts.is_empty() {
+        return Err(SchemaError::Conflicts(plan.conflicts.len()));
+    }
+    let drops = plan.drops();
+    if drops != 0 && !allow_drop {
+        return Err(SchemaError::Destructive(drops));
+    }
+    Ok(())
+}
+
+/// Apply the plan, returning the number of operations that were applied. Nothing is applied
+/// if the plan has conflicts, or if it drops something and `allow_drop` isn't set. This
+/// doesn't flush anything
+pub fn apply(db: &Corestore, plan: &Plan, allow_drop: bool) -> Result<usize, SchemaError> {
+    check(plan, allow_drop)?;
+    for op in &plan.operations {
+        apply_operation(db, op).map_err(|e| SchemaError::Failed(op.to_string(), e))?;
+        log::info!("Applied `{}`", op);
+    }
+    Ok(plan.operations.len())
+}
+
+fn apply_operation(db: &Corestore, op: &Operation) -> Result<(), DdlError> {
+    let keyspace = |ks: &str| {
+        db.get_store()
+            .get_keyspace_atomic_ref(ks)
+            .ok_or(DdlError::ObjectNotFound)
+    };
+    match op {
+        Operation::UnarchiveKeyspace(ks) => db.unarchive_keyspace(object_id(ks)),
+        Operation::CreateKeyspace(ks) => db.create_keyspace(object_id(ks)),
+        Operation::CreateTable(ks, table) => {
+            let (entity, model_code) =
+                parse_model(ks, &table.name, &table.model).ok_or(DdlError::WrongModel)?;
+            db.create_table(
+                entity,
+                model_code,
+                table.volatile,
+                table.ordered,
+                table.cached,
+                table.max_value_size,
+            )
+        }
+        Operation::SetValueLimit {
+            keyspace: ks,
+            table,
+            limit,
+        } => {
+            let table: Arc<Table> = keyspace(ks)?
+                .get_table_atomic_ref(table)
+                .ok_or(DdlError::ObjectNotFound)?;
+            table.set_value_limit(*limit);
+            Ok(())
+        }
+        Operation::SetDefaultTable {
+            keyspace: ks,
+            table,
+        } => keyspace(ks)?.set_default_table(object_id(table)),
+        Operation::DropTable {
+            keyspace: ks,
+            table,
+        } => {
+            let entity = format!("{}:{}", ks, table);
+            let entity = parser::get_query_entity(entity.as_bytes())
+                .map_err(|_| DdlError::ObjectNotFound)?;
+            db.drop_table(entity)
+        }
+        Operation::ArchiveKeyspace(ks) => db.archive_keyspace(object_id(ks)),
+        Operation::DropKeyspace(ks) => db.force_drop_keyspace(object_id(ks)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corestore::memstore::Memstore;
+    use clap::{load_yaml, App};
+
+    fn table(name: &str, model: &str) -> TableSchema {
+        TableSchema {
+            name: name.to_owned(),
+            model: model.to_owned(),
+            volatile: false,
+            ordered: false,
+            cached: false,
+            max_value_size: None,
+        }
+    }
+
+    fn create(db: &Corestore, ks: &str, table: TableSchema) {
+        let (entity, model_code) = parse_model(ks, &table.name, &table.model).unwrap();
+        db.create_table(
+            entity,
+            model_code,
+            table.volatile,
+            table.ordered,
+            table.cached,
+            table.max_value_size,
+        )
+        .unwrap();
+    }
+
+    fn get_keyspace(db: &Corestore, ks: &str) -> Arc<Keyspace> {
+        db.get_store().get_keyspace_atomic_ref(ks).unwrap()
+    }
+
+    /// A store with a few keyspaces and tables, with all sorts of properties
+    fn populated() -> Corestore {
+        let db = Corestore::default_with_store(Memstore::new_default());
+        db.create_keyspace(object_id("Shop")).unwrap();
+        create(
+            &db,
+            "Shop",
+            TableSchema {
+                ordered: true,
+                max_value_size: Some(1024),
+                ..table("orders", "keymap(str,str)")
+            },
+        );
+        create(
+            &db,
+            "Shop",
+            TableSchema {
+                volatile: true,
+                cached: true,
+                ..table("carts", "keymap(binstr,binstr)")
+            },
+        );
+        get_keyspace(&db, "Shop")
+            .set_default_table(object_id("orders"))
+            .unwrap();
+        db.create_keyspace(object_id("logs")).unwrap();
+        create(&db, "logs", table("events", "keymap(str,binstr)"));
+        db
+    }
+
+    #[test]
+    fn test_schema_round_trip() {
+        let source = populated();
+        let document = export(&source).render();
+        let schema = Schema::parse(&document).unwrap();
+        // sorted by name, without the system keyspace
+        let names: Vec<&str> = schema.keyspaces.iter().map(|ks| ks.name.as_str()).collect();
+        assert_eq!(names, ["Shop", "default", "logs"]);
+        assert_eq!(schema.keyspaces[0].tables[0].name, "carts");
+        assert_eq!(schema.keyspaces[0].default_table.as_deref(), Some("orders"));
+        // apply it to an empty store
+        let target = Corestore::default_with_store(Memstore::new_default());
+        let first = plan(&target, &schema).unwrap();
+        assert!(first.conflicts.is_empty());
+        assert_eq!(first.drops(), 0);
+        assert_eq!(apply(&target, &first, false).unwrap(), 6);
+        assert_eq!(export(&target).render(), document);
+        // and applying it again does nothing
+        let second = plan(&target, &schema).unwrap();
+        assert!(second.is_empty());
+        assert_eq!(apply(&target, &second, false).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_schema_dry_run_of_a_drifted_store() {
+        let mut schema = export(&populated());
+        schema.keyspaces[0]
+            .tables
+            .push(table("refunds", "keymap(str,str)"));
+        schema.keyspaces.push(KeyspaceSchema {
+            name: "metrics".to_owned(),
+            default_table: Some("cpu".to_owned()),
+            archived: false,
+            tables: vec![table("cpu", "keymap(binstr,str)")],
+        });
+        // the store has drifted away from the schema
+        let db = populated();
+        let shop = get_keyspace(&db, "Shop");
+        shop.get_table_atomic_ref("orders")
+            .unwrap()
+            .set_value_limit(Some(2048));
+        shop.set_default_table(object_id("carts")).unwrap();
+        drop(shop);
+        create(&db, "logs", table("debug", "keymap(str,str)"));
+        db.create_keyspace(object_id("scratch")).unwrap();
+        let before = export(&db);
+        let plan = plan(&db, &schema).unwrap();
+        assert!(plan.conflicts.is_empty());
+        assert_eq!(
+            plan.operations,
+            vec![
+                Operation::CreateTable("Shop".to_owned(), table("refunds", "keymap(str,str)")),
+                Operation::SetValueLimit {
+                    keyspace: "Shop".to_owned(),
+                    table: "orders".to_owned(),
+                    limit: Some(1024),
+                },
+                Operation::SetDefaultTable {
+                    keyspace: "Shop".to_owned(),
+                    table: "orders".to_owned(),
+                },
+                Operation::DropTable {
+                    keyspace: "logs".to_owned(),
+                    table: "debug".to_owned(),
+                },
+                Operation::CreateKeyspace("metrics".to_owned()),
+                Operation::CreateTable("metrics".to_owned(), table("cpu", "keymap(binstr,str)")),
+                Operation::SetDefaultTable {
+                    keyspace: "metrics".to_owned(),
+                    table: "cpu".to_owned(),
+                },
+                Operation::DropKeyspace("scratch".to_owned()),
+            ]
+        );
+        assert_eq!(
+            plan.to_string(),
+            "CREATE TABLE Shop:refunds keymap(str,str)\n\
+             ALTER TABLE Shop:orders maxvaluesize:1024\n\
+             ALTER KEYSPACE Shop DEFAULT TABLE orders\n\
+             DROP TABLE logs:debug\n\
+             CREATE KEYSPACE metrics\n\
+             CREATE TABLE metrics:cpu keymap(binstr,str)\n\
+             ALTER KEYSPACE metrics DEFAULT TABLE cpu\n\
+             DROP KEYSPACE scratch force\n"
+        );
+        // the drops are refused, so nothing is applied
+        assert_eq!(apply(&db, &plan, false), Err(SchemaError::Destructive(2)));
+        assert_eq!(export(&db), before);
+    }
+
+    #[test]
+    fn test_schema_conflicts() {
+        let mut schema = export(&populated());
+        schema.keyspaces[0].tables[0].volatile = false;
+        schema.keyspaces[2].tables[0].model = "keymap(str,str)".to_owned();
+        schema.keyspaces[2]
+            .tables
+            .push(table("system", "keymap(str,str)"));
+        let db = populated();
+        let plan = plan(&db, &schema).unwrap();
+        assert_eq!(
+            plan.conflicts,
+            [
+                "`Shop:carts` can't be made not volatile after it was created",
+                "the model of `logs:events` is `keymap(str,binstr)` but the schema has `keymap(str,str)`",
+                "can't create the table `logs:system`: the name is reserved",
+            ]
+        );
+        assert_eq!(apply(&db, &plan, true), Err(SchemaError::Conflicts(3)));
+        assert_eq!(export(&db), export(&populated()));
+    }
+
+    #[test]
+    fn test_schema_documents_are_validated() {
+        assert_eq!(
+            Schema::parse("{\"version\": 2, \"keyspaces\": []}"),
+            Err(SchemaError::Version(2))
+        );
+        assert!(matches!(
+            Schema::parse("{\"keyspaces\": []}"),
+            Err(SchemaError::Parse(_))
+        ));
+        let db = Corestore::default_with_store(Memstore::new_default());
+        let mut schema = export(&db);
+        schema.keyspaces[0].default_table = Some("nope".to_owned());
+        assert!(matches!(plan(&db, &schema), Err(SchemaError::Invalid(_))));
+        let mut schema = export(&db);
+        schema.keyspaces[0].tables[0].max_value_size = Some(0);
+        assert!(matches!(plan(&db, &schema), Err(SchemaError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_schema_opts_from_args() {
+        let cfg_layout = load_yaml!("cli.yml");
+        let matches = App::from_yaml(cfg_layout).get_matches_from(vec![
+            "skyd",
+            "schema",
+            "apply",
+            "--dry-run",
+            "schema.json",
+        ]);
+        let parsed = SchemaOpts::from_matches(matches.subcommand_matches("schema").unwrap());
+        assert_eq!(
+            parsed.unwrap(),
+            SchemaOpts {
+                command: SchemaCommand::Apply {
+                    dry_run: true,
+                    allow_drop: false,
+                },
+                file: "schema.json".to_owned(),
+            }
+        );
+    }
+}
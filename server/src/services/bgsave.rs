@@ -25,12 +25,19 @@
 */
 
 use crate::config::BGSave;
+use crate::corestore::memstore::Memstore;
 use crate::corestore::Corestore;
 use crate::dbnet::Terminator;
 use crate::registry;
+use crate::registry::FlushRecord;
+use crate::registry::LockHolder;
+use crate::services;
 use crate::storage;
+use crate::storage::error::StorageResult;
+use crate::storage::flush::FlushReport;
 use libsky::TResult;
-use tokio::time::{self, Duration};
+use std::time::Instant;
+use tokio::time::Duration;
 
 /// The bgsave_scheduler calls the bgsave task in `Corestore` after `every` seconds
 ///
@@ -45,15 +52,26 @@ pub async fn bgsave_scheduler(handle: Corestore, bgsave_cfg: BGSave, mut termina
             let duration = Duration::from_secs(duration);
             loop {
                 tokio::select! {
-                    // Sleep until `duration` from the current time instant
-                    _ = time::sleep_until(time::Instant::now() + duration) => {
-                        let cloned_handle = handle.clone();
-                        // we spawn this process just to ensure that it doesn't block the runtime's workers
-                        // dedicated to async tasks (non-blocking)
-                        tokio::task::spawn_blocking(move || {
-                            let owned_handle = cloned_handle;
-                            let _ = bgsave_blocking_section(owned_handle);
-                        }).await.expect("Something caused the background service to panic");
+                    // Sleep until `duration` from the current time instant (skipping the runs
+                    // that come due while the service is paused)
+                    _ = services::scheduled(duration, &registry::get_background().flush) => {
+                        run_bgsave_in_background(&handle).await;
+                    }
+                    // Or run it right away if the writes have crossed the dirty bytes mark. The
+                    // delayed writers keep waiting if the service is paused
+                    _ = registry::get_dirty_tracker().flush_requested() => {
+                        if registry::get_background().flush.is_paused() {
+                            log::info!("Dirty bytes crossed the high-water mark but BGSAVE is paused. Not running it");
+                        } else {
+                            log::info!("Dirty bytes crossed the high-water mark. Running BGSAVE out of schedule");
+                            run_bgsave_in_background(&handle).await;
+                        }
+                    }
+                    // Or if a client is waiting for its writes to be flushed. Requests made
+                    // while the flush runs are taken up by the next one. These run even if
+                    // the service is paused, since someone is waiting on them
+                    _ = registry::get_flush_progress().flush_requested() => {
+                        run_bgsave_in_background(&handle).await;
                     }
                     // Otherwise wait for a notification
                     _ = terminator.receive_signal() => {
@@ -62,6 +80,7 @@ pub async fn bgsave_scheduler(handle: Corestore, bgsave_cfg: BGSave, mut termina
                     }
                 }
             }
+            registry::get_background().flush.set_next_run(None);
         }
         BGSave::Disabled => {
             // the user doesn't bother about his data; cool, let's not bother about it either
@@ -70,17 +89,52 @@ pub async fn bgsave_scheduler(handle: Corestore, bgsave_cfg: BGSave, mut termina
     log::info!("BGSAVE service has exited");
 }
 
-/// Run bgsave
+/// Run BGSAVE on a blocking thread and then wake up the writers that may have been waiting
+/// for the dirty bytes to be flushed
+async fn run_bgsave_in_background(handle: &Corestore) {
+    let cloned_handle = handle.clone();
+    // we spawn this process just to ensure that it doesn't block the runtime's workers
+    // dedicated to async tasks (non-blocking)
+    tokio::task::spawn_blocking(move || {
+        let owned_handle = cloned_handle;
+        let _ = bgsave_blocking_section(owned_handle);
+    })
+    .await
+    .expect("Something caused the background service to panic");
+    registry::get_dirty_tracker().notify_flushed();
+}
+
+/// Run bgsave and record what it wrote in the [flush log](registry::FlushLog)
 ///
 /// This function just hides away the BGSAVE blocking section from the _public API_
 pub fn run_bgsave(handle: &Corestore) -> TResult<()> {
-    storage::flush::flush_full(handle.get_store()).map_err(|e| e.into())
+    self::record_bgsave(handle, storage::flush::flush_full)
+}
+
+/// Run the periodic bgsave, with the files paced to the limit that is set for the periodic
+/// flushes (see [`storage::flush::throttled_flush_full`]), and record it like [`run_bgsave`]
+fn run_throttled_bgsave(handle: &Corestore) -> TResult<()> {
+    self::record_bgsave(handle, storage::flush::throttled_flush_full)
+}
+
+/// Run `flush` on the store of `handle` and record what it wrote in the flush log
+fn record_bgsave(
+    handle: &Corestore,
+    flush: impl FnOnce(&Memstore) -> StorageResult<FlushReport>,
+) -> TResult<()> {
+    let started = Instant::now();
+    let report = flush(handle.get_store())?;
+    let now = handle.get_store().get_clock().now();
+    let took = started.elapsed();
+    registry::get_metrics().record_flush(took);
+    registry::get_flush_log().record(FlushRecord::new(now, took, report));
+    Ok(())
 }
 
 /// This just wraps around [`_bgsave_blocking_section`] and prints nice log messages depending on the outcome
 fn bgsave_blocking_section(handle: Corestore) -> bool {
-    registry::lock_flush_state();
-    match run_bgsave(&handle) {
+    registry::lock_flush_state(LockHolder::Bgsave);
+    registry::get_flush_progress().flush(|| match run_throttled_bgsave(&handle) {
         Ok(_) => {
             log::info!("BGSAVE completed successfully");
             registry::unpoison();
@@ -91,5 +145,5 @@ fn bgsave_blocking_section(handle: Corestore) -> bool {
             registry::poison();
             false
         }
-    }
+    })
 }
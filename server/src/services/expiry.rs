@@ -0,0 +1,80 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+use crate::corestore::memstore::Memstore;
+use crate::corestore::Corestore;
+use crate::dbnet::Terminator;
+use crate::registry;
+use tokio::time::{self, Duration};
+
+/// How often the expiry sweeper looks for keys that are past their expiry time
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The expiry sweeper removes the keys that are past their expiry time (as set by
+/// `EXPIREPREFIX`) every [`SWEEP_INTERVAL`], until a termination signal is received. Nothing
+/// is removed while the server doesn't accept writes
+pub async fn expiry_sweeper(handle: Corestore, mut terminator: Terminator) {
+    loop {
+        tokio::select! {
+            _ = time::sleep_until(time::Instant::now() + SWEEP_INTERVAL) => {
+                if registry::state_okay() {
+                    run_sweep_in_background(&handle).await;
+                }
+            }
+            _ = terminator.receive_signal() => {
+                break;
+            }
+        }
+    }
+    log::info!("Expiry sweeper has exited");
+}
+
+/// Run a sweep on a blocking thread, since it walks every table
+async fn run_sweep_in_background(handle: &Corestore) {
+    let cloned_handle = handle.clone();
+    let swept = tokio::task::spawn_blocking(move || sweep_expired(cloned_handle.get_store()))
+        .await
+        .expect("Something caused the expiry sweeper to panic");
+    if swept != 0 {
+        log::debug!("Removed {} expired key(s)", swept);
+    }
+}
+
+/// Remove every key in `store` that is past its expiry time (according to the store's clock)
+/// and return the number of keys that were removed
+pub fn sweep_expired(store: &Memstore) -> usize {
+    let now = store.get_clock().now().timestamp_millis();
+    let mut swept = 0;
+    for keyspace in store.keyspaces.iter() {
+        for table in keyspace.value().tables.iter() {
+            // the tables that haven't been read in yet are swept once they are
+            if let Some(kve) = table.value().loaded_kvstore() {
+                swept += kve.remove_expired(now);
+            }
+        }
+    }
+    swept
+}
@@ -0,0 +1,159 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The memory watcher
+//!
+//! If a memory ceiling is configured, the memory watcher samples what the process uses every
+//! [`SAMPLE_INTERVAL`] and hands it to the [memory guard](crate::registry::MemoryGuard), which
+//! blocks writes above the ceiling. On Linux, the resident set size is read from
+//! `/proc/self/statm`; elsewhere (or if it can't be read) the usage is estimated from the
+//! bytes that the tables hold, plus what their entries cost on top of that
+
+use crate::corestore::memstore::Keyspace;
+use crate::corestore::Corestore;
+use crate::dbnet::Terminator;
+use crate::kvengine::ENTRY_OVERHEAD;
+use crate::registry::{self, MemoryGuard};
+use tokio::time::{self, Duration};
+
+/// How often the memory watcher samples the memory usage
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The memory watcher samples the memory usage every [`SAMPLE_INTERVAL`] until a termination
+/// signal is received
+pub async fn memory_watcher(handle: Corestore, mut terminator: Terminator) {
+    loop {
+        tokio::select! {
+            _ = time::sleep_until(time::Instant::now() + SAMPLE_INTERVAL) => {
+                let usage = resident_bytes().unwrap_or_else(|| estimated_bytes(&handle));
+                check_memory(registry::get_memory_guard(), usage);
+            }
+            _ = terminator.receive_signal() => {
+                break;
+            }
+        }
+    }
+    log::info!("Memory watcher has exited");
+}
+
+/// Hand the memory `usage` that was just sampled to `guard`, logging it if writes were just
+/// blocked or allowed again
+pub fn check_memory(guard: &MemoryGuard, usage: usize) {
+    match guard.observe(usage) {
+        Some(true) => log::error!(
+            "The server uses {} bytes, more than the memory ceiling of {} bytes. Rejecting writes",
+            usage,
+            guard.ceiling().unwrap_or_default()
+        ),
+        Some(false) => log::info!(
+            "The server uses {} bytes, which is under the memory ceiling again. Accepting writes",
+            usage
+        ),
+        None => {}
+    }
+}
+
+/// Returns the resident set size of the process, or `None` if it can't be read
+#[cfg(target_os = "linux")]
+fn resident_bytes() -> Option<usize> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    // the size and then the resident size, in pages
+    let pages: usize = statm.split_whitespace().nth(1)?.parse().ok()?;
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if page_size <= 0 {
+        return None;
+    }
+    Some(pages.saturating_mul(page_size as usize))
+}
+
+/// Returns the resident set size of the process, which isn't read on this platform
+#[cfg(not(target_os = "linux"))]
+fn resident_bytes() -> Option<usize> {
+    None
+}
+
+/// Estimate the memory usage from the bytes that the tables that have been read in hold and
+/// what their entries cost on top of that (see [`ENTRY_OVERHEAD`]). This doesn't walk the data,
+/// only the tables. Attached snapshots are counted too
+pub fn estimated_bytes(handle: &Corestore) -> usize {
+    let store = handle.get_store();
+    store
+        .keyspaces
+        .iter()
+        .chain(store.attached.iter())
+        .map(|keyspace| keyspace_bytes(keyspace.value()))
+        .sum()
+}
+
+/// Estimate the memory used by the tables of `keyspace` (see [`estimated_bytes`])
+pub fn keyspace_bytes(keyspace: &Keyspace) -> usize {
+    keyspace
+        .tables
+        .iter()
+        .filter_map(|table| table.value().loaded_kvstore())
+        .map(|kve| kve.stored_bytes() + kve.len() * ENTRY_OVERHEAD)
+        .sum()
+}
+
+/// Returns the memory that is still available: what's left under the memory ceiling if one
+/// is configured, or else what the system reports as available (only on Linux). This is
+/// `None` if it isn't known
+pub fn available_bytes(handle: &Corestore) -> Option<usize> {
+    let guard = registry::get_memory_guard();
+    match guard.ceiling() {
+        Some(ceiling) => {
+            // the watcher may not have sampled the usage yet
+            let usage = guard.usage().max(estimated_bytes(handle));
+            Some(ceiling.saturating_sub(usage))
+        }
+        None => system_available_bytes(),
+    }
+}
+
+/// Returns the memory that the system reports as available, from `/proc/meminfo`
+#[cfg(target_os = "linux")]
+fn system_available_bytes() -> Option<usize> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?;
+    // MemAvailable:    1234 kB
+    let kib: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib.saturating_mul(1024))
+}
+
+/// Returns the memory that the system reports as available, which isn't read on this platform
+#[cfg(not(target_os = "linux"))]
+fn system_available_bytes() -> Option<usize> {
+    None
+}
+
+#[cfg(all(test, target_os = "linux"))]
+#[test]
+fn test_resident_bytes_can_be_read() {
+    // a process uses at least a page
+    assert!(resident_bytes().unwrap() >= 4096);
+}
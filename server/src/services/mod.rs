@@ -25,4 +25,91 @@
 */
 
 pub mod bgsave;
+pub mod expiry;
+pub mod memwatch;
+pub mod prewarm;
+pub mod selfcheck;
 pub mod snapshot;
+
+use crate::registry::ServiceControl;
+use tokio::time::{self, Duration, Instant};
+
+/// Wait until the next scheduled run of a background service, `every` from now, publishing
+/// when that is for `SYS BGSTATE`. If the service is paused when a run comes due, the run is
+/// skipped (with a log line) and the one after it is waited for instead
+pub async fn scheduled(every: Duration, control: &ServiceControl) {
+    loop {
+        let at = Instant::now() + every;
+        control.set_next_run(Some(at));
+        time::sleep_until(at).await;
+        match control.paused_for() {
+            Some(left) => log::info!(
+                "Skipping a scheduled {} run since the service is paused (for another {}s)",
+                control.name(),
+                left.as_secs()
+            ),
+            None => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::scheduled;
+    use crate::registry::ServiceControl;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::time::{self, Duration, Instant};
+
+    #[tokio::test]
+    async fn test_scheduled_publishes_next_run() {
+        static SERVICE: ServiceControl = ServiceControl::new("flush");
+        time::pause();
+        assert_eq!(SERVICE.next_run_in(), None);
+        let started = Instant::now();
+        let run = tokio::spawn(scheduled(Duration::from_secs(5), &SERVICE));
+        tokio::task::yield_now().await;
+        assert_eq!(SERVICE.next_run_in(), Some(Duration::from_secs(5)));
+        time::advance(Duration::from_secs(2)).await;
+        assert_eq!(SERVICE.next_run_in(), Some(Duration::from_secs(3)));
+        run.await.unwrap();
+        assert_eq!(started.elapsed(), Duration::from_secs(5));
+    }
+
+    /// Pause a service, check that none of its runs happen in the meantime and that the next
+    /// one happens once it is resumed
+    async fn check_pause_and_resume(service: &'static ServiceControl) {
+        time::pause();
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counted = runs.clone();
+        service.pause(Duration::from_secs(3600));
+        let running = tokio::spawn(async move {
+            loop {
+                scheduled(Duration::from_secs(1), service).await;
+                counted.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        time::sleep(Duration::from_millis(10_500)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+        // the skipped runs are still scheduled
+        assert!(service.next_run_in().is_some());
+        assert!(service.resume());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+        running.abort();
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_flush() {
+        static FLUSH: ServiceControl = ServiceControl::new("flush");
+        check_pause_and_resume(&FLUSH).await;
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume_snapshot() {
+        static SNAPSHOT: ServiceControl = ServiceControl::new("snapshot");
+        check_pause_and_resume(&SNAPSHOT).await;
+    }
+}
@@ -0,0 +1,71 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Prewarming
+//!
+//! When the tables are loaded lazily, the prewarm service reads in the data of the tables
+//! listed in the configuration right after boot, so that the first access to them doesn't
+//! have to wait
+
+use crate::corestore::Corestore;
+use crate::dbnet::Terminator;
+use crate::queryengine::parser;
+use crate::util::fmt_key_safe;
+
+/// Read in the tables in `prewarm` (as `<keyspace>:<table>`) one after the other, until
+/// they're all read in or a termination signal is received. Tables that don't exist are
+/// skipped. A table that was accessed in the meantime is already read in, so it is skipped too
+pub async fn prewarm_service(handle: Corestore, prewarm: Vec<String>, mut terminator: Terminator) {
+    for entity in prewarm {
+        let table = match parser::get_query_entity(entity.as_bytes()) {
+            Ok(parsed) => handle.get_table(parsed),
+            Err(_) => {
+                log::warn!(
+                    "Not prewarming `{}` since it isn't a valid entity",
+                    fmt_key_safe(entity.as_bytes())
+                );
+                continue;
+            }
+        };
+        let table = match table {
+            Ok(table) => table,
+            Err(_) => {
+                log::warn!(
+                    "Not prewarming `{}` since the table doesn't exist",
+                    fmt_key_safe(entity.as_bytes())
+                );
+                continue;
+            }
+        };
+        tokio::select! {
+            _ = table.wait_loaded() => {}
+            _ = terminator.receive_signal() => {
+                break;
+            }
+        }
+    }
+    log::info!("Prewarm service has exited");
+}
@@ -0,0 +1,242 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # The consistency checker
+//!
+//! A flush bug, a lost write or a bug in how the tables are accounted for would otherwise only
+//! show up at the next restart, when the tables are read back in. If the `[selfcheck]` section
+//! is set in the config file, the records and the bytes that every table holds in memory are
+//! compared with what the header of its file (and the size of the file) says the latest flush
+//! wrote, every `every` hours. Nothing but the headers is read, so this is cheap even for large
+//! tables, and the flush lock is held throughout so that no flush rewrites a file meanwhile.
+//! If a snapshot holds the snapshot lock, the check is skipped until the next one.
+//!
+//! Every change to the number of records of a table marks at least a byte of it as dirty
+//! until the next flush, so a table can have as many records more or fewer than its file as
+//! it has dirty bytes, plus the configured `tolerance`. The bytes of a table are only compared
+//! if it hasn't changed since it was flushed, and then they have to match. The tables that
+//! drift are logged, counted and reported by `SYS HEALTH` until a check finds them back in
+//! line (see [`SelfCheck`])
+
+use crate::corestore::memstore::{Memstore, ObjectID};
+use crate::corestore::table::Table;
+use crate::corestore::Corestore;
+use crate::dbnet::Terminator;
+use crate::kvengine::KVEngine;
+use crate::registry::{self, LockHolder, SelfCheck};
+use crate::storage::error::StorageResult;
+use crate::storage::unflush::{self, TableHeader};
+use crate::util::fmt_key_safe;
+use std::thread;
+use tokio::time::{self, Duration};
+
+/// How long to wait before a table that drifted is looked at again, which is plenty for a
+/// mutation that was between changing the table and marking it dirty to finish
+const CONFIRM_AFTER: Duration = Duration::from_millis(10);
+
+/// The consistency checker checks the tables every `every` until a termination signal is
+/// received
+pub async fn selfcheck_service(handle: Corestore, every: Duration, mut terminator: Terminator) {
+    loop {
+        tokio::select! {
+            _ = time::sleep_until(time::Instant::now() + every) => {
+                let handle = handle.clone();
+                // the headers are read from disk, so keep this off the runtime's workers
+                let _ = tokio::task::spawn_blocking(move || {
+                    check_store(handle.get_store(), registry::get_selfcheck())
+                })
+                .await;
+            }
+            _ = terminator.receive_signal() => {
+                break;
+            }
+        }
+    }
+    log::info!("Consistency checker has exited");
+}
+
+#[derive(Debug, PartialEq)]
+/// What a check of the tables came to
+pub enum CheckOutcome {
+    /// a snapshot held the snapshot lock, so nothing was checked
+    Skipped,
+    /// the tables (as `<keyspace>:<table>`) that drifted from their files, if any
+    Checked(Vec<String>),
+}
+
+/// Check every table of `store` against its file and record the tables that drifted in
+/// `selfcheck`. Tables that were never read in are the same as their files, and volatile and
+/// archived tables aren't checked
+pub fn check_store(store: &Memstore, selfcheck: &SelfCheck) -> CheckOutcome {
+    if store
+        .snap_config
+        .as_ref()
+        .map_or(false, |snapstatus| snapstatus.is_busy())
+    {
+        log::info!("Skipping the consistency check since a snapshot is running");
+        return CheckOutcome::Skipped;
+    }
+    let _flush_lock = registry::lock_flush_state(LockHolder::Selfcheck);
+    let tolerance = selfcheck.tolerance();
+    let mut drifting = Vec::new();
+    for keyspace in store.keyspaces.iter() {
+        for table in keyspace.value().tables.iter() {
+            let (ksid, tblid) = (keyspace.key(), table.key());
+            let name = format!("{}:{}", fmt_key_safe(ksid), fmt_key_safe(tblid));
+            match self::check_table(ksid, tblid, table.value(), tolerance) {
+                Ok(None) => {}
+                Ok(Some((memory, file))) => {
+                    log::error!(
+                        "Table '{}' drifted from its file: it has {} record(s) ({} bytes) in memory but {} ({} bytes) on disk, with {} byte(s) changed since the latest flush",
+                        name,
+                        memory.records,
+                        memory.bytes,
+                        file.records,
+                        file.bytes,
+                        memory.dirty
+                    );
+                    drifting.push(name);
+                }
+                Err(e) => log::warn!("Failed to check table '{}' against its file: {}", name, e),
+            }
+        }
+    }
+    selfcheck.record(drifting.clone());
+    CheckOutcome::Checked(drifting)
+}
+
+/// What a table holds in memory
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    records: u64,
+    bytes: u64,
+    dirty: u64,
+}
+
+impl Sample {
+    /// Take a sample of `kv`, or return `None` if it was marked dirty while the sample was
+    /// taken
+    fn of(kv: &KVEngine) -> Option<Self> {
+        let dirty = kv.dirty_bytes();
+        let sample = Self {
+            records: kv.len() as u64,
+            bytes: kv.stored_bytes() as u64,
+            dirty: dirty as u64,
+        };
+        if kv.dirty_bytes() == dirty {
+            Some(sample)
+        } else {
+            None
+        }
+    }
+    /// Returns true if this drifted from `file` by more than the dirty bytes and `tolerance`
+    /// allow for
+    fn drifts_from(&self, file: &TableHeader, tolerance: u64) -> bool {
+        let apart = |a: u64, b: u64| a.max(b) - a.min(b);
+        apart(self.records, file.records) > self.dirty.saturating_add(tolerance)
+            || (self.dirty == 0 && self.bytes != file.bytes)
+    }
+}
+
+/// Check `table` against its file, returning what it holds and what its file has if it
+/// drifted. A table that drifts has to drift again a moment later for it to count, since a
+/// mutation may have changed the table without having marked it dirty yet
+fn check_table(
+    ksid: &ObjectID,
+    tblid: &ObjectID,
+    table: &Table,
+    tolerance: u64,
+) -> StorageResult<Option<(Sample, TableHeader)>> {
+    let kv = match table.loaded_kvstore() {
+        Some(kv) if !table.is_volatile() && !table.is_archived() => kv,
+        _ => return Ok(None),
+    };
+    // a table that was never flushed has nothing on disk yet
+    let file = unflush::read_table_header(ksid, tblid)?.unwrap_or_default();
+    match Sample::of(kv) {
+        Some(sample) if sample.drifts_from(&file, tolerance) => {}
+        _ => return Ok(None),
+    }
+    thread::sleep(CONFIRM_AFTER);
+    Ok(Sample::of(kv)
+        .filter(|sample| sample.drifts_from(&file, tolerance))
+        .map(|sample| (sample, file)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_store, CheckOutcome};
+    use crate::corestore::memstore::{Memstore, DEFAULT};
+    use crate::corestore::Data;
+    use crate::registry::SelfCheck;
+    use crate::storage::flush;
+    use crate::storage::interface::{create_tree, override_data_dir};
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn test_selfcheck_flags_a_skewed_table() {
+        let data_dir = env::temp_dir().join(format!("skyd-selfcheck-{}", process::id()));
+        let data_dir = data_dir.to_str().unwrap().to_owned();
+        let _ = fs::remove_dir_all(&data_dir);
+        override_data_dir(Some(&data_dir));
+        let store = Memstore::new_default();
+        let keyspace = store.get_keyspace_atomic_ref(&DEFAULT).unwrap();
+        let table = keyspace.get_table_atomic_ref(&DEFAULT).unwrap();
+        let kv = table.get_kvstore().unwrap();
+        for key in ["a", "b", "c"].iter() {
+            assert!(kv.set(Data::from(*key), Data::from("value")).unwrap());
+        }
+        create_tree(&store).unwrap();
+        flush::flush_full(&store).unwrap();
+        let selfcheck = SelfCheck::new();
+        assert_eq!(
+            check_store(&store, &selfcheck),
+            CheckOutcome::Checked(Vec::new())
+        );
+        // what changed since the flush is allowed for
+        assert!(kv.set(Data::from("d"), Data::from("value")).unwrap());
+        assert_eq!(kv.truncate_table(), 4);
+        assert_eq!(
+            check_store(&store, &selfcheck),
+            CheckOutcome::Checked(Vec::new())
+        );
+        assert!(kv.set(Data::from("e"), Data::from("value")).unwrap());
+        flush::flush_full(&store).unwrap();
+        // but not an accounting bug
+        kv.skew_stored_bytes(7);
+        let drifting = vec!["default:default".to_owned()];
+        assert_eq!(
+            check_store(&store, &selfcheck),
+            CheckOutcome::Checked(drifting.clone())
+        );
+        assert_eq!(selfcheck.drifting(), drifting);
+        assert_eq!(selfcheck.drifts(), 1);
+        override_data_dir(None);
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
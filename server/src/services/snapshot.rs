@@ -29,7 +29,8 @@ use crate::corestore::Corestore;
 use crate::dbnet::Terminator;
 use crate::diskstore::snapshot::SnapshotEngine;
 use crate::registry;
-use tokio::time::{self, Duration};
+use crate::services;
+use tokio::time::Duration;
 
 /// The snapshot service
 ///
@@ -49,18 +50,26 @@ pub async fn snapshot_service(
             return;
         }
         SnapshotConfig::Enabled(configuration) => {
-            let (duration, atmost, failsafe) = configuration.decompose();
+            let (duration, atmost, maxage, failsafe) = configuration.decompose();
             let duration = Duration::from_secs(duration);
+            // an age that chrono can't represent is as good as no age at all
+            let maxage = maxage
+                .and_then(|maxage| chrono::Duration::from_std(Duration::from_secs(maxage)).ok());
             let mut sengine = match SnapshotEngine::new(atmost, &handle) {
-                Ok(ss) => ss,
+                Ok(ss) => ss.with_maxage(maxage),
                 Err(e) => {
                     log::error!("Failed to initialize snapshot service with error: '{}'", e);
                     return;
                 }
             };
+            // get rid of the snapshots that expired while we were down
+            if !sengine.remove_expired().await {
+                log::warn!("Some of the expired snapshots couldn't be removed");
+            }
             loop {
                 tokio::select! {
-                    _ = time::sleep_until(time::Instant::now() + duration) => {
+                    // pausing the service only skips the snapshots that haven't started yet
+                    _ = services::scheduled(duration, &registry::get_background().snapshot) => {
                         if sengine.mksnap().await {
                             // it passed, so unpoison the handle
                             registry::unpoison();
@@ -76,6 +85,7 @@ pub async fn snapshot_service(
                     }
                 }
             }
+            registry::get_background().snapshot.set_next_run(None);
         }
     }
     log::info!("Snapshot service has exited");
@@ -0,0 +1,253 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Attaching snapshots
+//!
+//! Routines to read a snapshot into a read-only keyspace that only lives in memory, for
+//! `ATTACHSNAP`. Like a [restore](super::restore), an incremental snapshot is read along with
+//! the snapshots before it in its chain. Every table of every keyspace in the snapshot becomes
+//! the table `<keyspace>_<table>` of the attached keyspace, and the default table of the
+//! snapshot's `default` keyspace becomes its default table. Keys that are already past their
+//! expiry time are left out; since the expiry sweeper never looks at attached snapshots, the
+//! others are kept for as long as the snapshot is attached.
+//!
+//! The memory that the tables take is counted as they're read in (the same way that the
+//! [memory watcher](crate::services::memwatch) estimates it) and reading stops as soon as it
+//! goes over what's available, dropping everything that was read so far
+
+use super::bytemarks;
+use super::interface::EXPIRY_MAP_EXTENSION;
+use super::interface::PROTECTED_SET_EXTENSION;
+use super::restore::{self, RestoreError};
+use crate::corestore::htable::Coremap;
+use crate::corestore::memstore::{self, Keyspace, ObjectID, DEFAULT};
+use crate::corestore::table::Table;
+use crate::corestore::Data;
+use crate::kvengine::ENTRY_OVERHEAD;
+use core::fmt;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+#[derive(Debug)]
+/// Errors that can occur while attaching a snapshot
+pub enum AttachError {
+    /// The snapshot couldn't be read
+    Snapshot(RestoreError),
+    /// A table of the snapshot can't be named in the attached keyspace: the name is too long
+    /// or another table already has it
+    BadName(String),
+    /// The tables need more memory than the `available` bytes
+    OutOfMemory { available: usize },
+}
+
+impl From<RestoreError> for AttachError {
+    fn from(e: RestoreError) -> Self {
+        Self::Snapshot(e)
+    }
+}
+
+impl fmt::Display for AttachError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Snapshot(e) => write!(f, "{}", e),
+            Self::BadName(name) => write!(f, "the table `{}` can't be attached", name),
+            Self::OutOfMemory { available } => write!(
+                f,
+                "the snapshot needs more than the {} bytes of memory that are available",
+                available
+            ),
+        }
+    }
+}
+
+type AttachResult<T> = Result<T, AttachError>;
+
+/// A snapshot that was read in by [`read_snapshot`]
+pub struct AttachedSnapshot {
+    /// the read-only keyspace with the tables of the snapshot
+    pub keyspace: Keyspace,
+    pub tables: usize,
+    /// the memory that the tables take
+    pub bytes: usize,
+}
+
+/// Keeps count of the memory that the tables take, against what's available
+struct Budget {
+    /// `None` if it isn't known, in which case nothing is ever over the budget
+    available: Option<usize>,
+    used: usize,
+}
+
+impl Budget {
+    /// Fail if `bytes` more don't fit
+    fn check(&self, bytes: u64) -> AttachResult<()> {
+        match self.available {
+            Some(available) if self.used as u64 + bytes > available as u64 => {
+                Err(AttachError::OutOfMemory { available })
+            }
+            _ => Ok(()),
+        }
+    }
+    fn spend(&mut self, bytes: usize) -> AttachResult<()> {
+        self.check(bytes as u64)?;
+        self.used += bytes;
+        Ok(())
+    }
+}
+
+/// Read the snapshot at `src` (and the snapshots before it in its chain) into a read-only
+/// keyspace, using at most `available` bytes of memory (see the [module level docs](self)).
+/// Keys whose expiry time is at or before `now` (in milliseconds since the unix epoch) are
+/// left out
+pub fn read_snapshot(
+    src: &Path,
+    available: Option<usize>,
+    now: i64,
+) -> AttachResult<AttachedSnapshot> {
+    let chain = restore::read_chain(src)?;
+    let preload_path = src.join("PRELOAD");
+    let preload = super::preload::read_preload_raw(restore::read(&preload_path)?, &preload_path)
+        .map_err(|_| RestoreError::BadSnapshot(preload_path.clone()))?;
+    let tables = Coremap::new();
+    let mut default_table = None;
+    let mut budget = Budget { available, used: 0 };
+    for ksid in preload {
+        // the directories and files are named after the keys (see `names`)
+        let ksid = memstore::key_of(&ksid);
+        let ksname = restore::objectid_to_name(&ksid, &preload_path)?;
+        let partmap_path = src.join(&ksname).join("PARTMAP");
+        let partmap =
+            super::preload::read_partfile_raw(restore::read(&partmap_path)?, &partmap_path)
+                .map_err(|_| RestoreError::BadSnapshot(partmap_path.clone()))?;
+        for (tblid, (storage_type, model_code, _, _)) in partmap {
+            let tblname = restore::objectid_to_name(&memstore::key_of(&tblid), &partmap_path)?;
+            let name = format!("{}_{}", ksname, tblname);
+            if name.len() > 64 {
+                return Err(AttachError::BadName(name));
+            }
+            let id = unsafe { ObjectID::from_slice(name.as_bytes()) };
+            if tables.contains_key(&id) {
+                return Err(AttachError::BadName(name));
+            }
+            let ordered = storage_type & bytemarks::BYTEMARK_STORAGE_FLAG_ORDERED != 0;
+            if ksid == DEFAULT && storage_type & bytemarks::BYTEMARK_STORAGE_FLAG_DEFAULT != 0 {
+                default_table = Some(id.clone());
+            }
+            let storage_type = storage_type
+                & !(bytemarks::BYTEMARK_STORAGE_FLAG_ORDERED
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_DEFAULT
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_READ_CACHE
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED);
+            let volatile = storage_type == bytemarks::BYTEMARK_STORAGE_VOLATILE;
+            let (data, protected, expiries) = if volatile {
+                // volatile tables have nothing on disk
+                (Coremap::new(), HashSet::new(), HashMap::new())
+            } else {
+                self::read_table(&chain, &ksname, &tblname, now, &budget)?
+            };
+            let table = Table::from_model_code_with_data(model_code, data, volatile, ordered)
+                .ok_or_else(|| RestoreError::BadSnapshot(partmap_path.clone()))?
+                .with_read_only();
+            if let Ok(kve) = table.get_kvstore() {
+                kve.restore_protected(protected.into_iter());
+                kve.restore_expiries(expiries.into_iter());
+                let index = kve
+                    .get_ordered_index()
+                    .map(|index| index.approx_memory())
+                    .unwrap_or_default();
+                budget.spend(kve.stored_bytes() + kve.len() * ENTRY_OVERHEAD + index)?;
+            }
+            tables.true_if_insert(id, Arc::new(table));
+        }
+    }
+    // the flag isn't set if the default table is `default`
+    let default_table = default_table
+        .unwrap_or_else(|| unsafe { ObjectID::from_slice("default_default".as_bytes()) });
+    let table_count = tables.len();
+    let keyspace = Keyspace::init_with_all_def_strategy(tables).with_read_only();
+    // if there's no such table, a `USE` of the keyspace leaves the current table unset
+    let _ = keyspace.set_default_table(default_table);
+    Ok(AttachedSnapshot {
+        keyspace,
+        tables: table_count,
+        bytes: budget.used,
+    })
+}
+
+/// Read the pairs of a table that isn't volatile from the latest snapshot in `chain` that has
+/// it, along with its protected keys and the expiry times of its keys. The file isn't read at
+/// all if it alone is larger than what's left of the `budget`
+fn read_table(
+    chain: &[PathBuf],
+    ksname: &str,
+    tblname: &str,
+    now: i64,
+    budget: &Budget,
+) -> AttachResult<(Coremap<Data, Data>, HashSet<Data>, HashMap<Data, i64>)> {
+    let kspath = chain
+        .iter()
+        .map(|snapshot| snapshot.join(ksname))
+        .find(|dir| dir.join(tblname).is_file())
+        .unwrap_or_else(|| chain[0].join(ksname));
+    let tblpath = kspath.join(tblname);
+    let len = fs::metadata(&tblpath)
+        .map_err(|e| RestoreError::IoError(tblpath.clone(), e))?
+        .len();
+    // the pairs take at least as much memory as they do on disk
+    budget.check(len)?;
+    let data = super::de::deserialize_map(restore::read(&tblpath)?)
+        .ok_or_else(|| RestoreError::BadSnapshot(tblpath))?;
+    // the protected keys and the expiry times are optional
+    let protected_path = kspath.join(concat_str!(tblname, PROTECTED_SET_EXTENSION));
+    let protected = if protected_path.is_file() {
+        super::de::deserialize_set_ctype::<Data>(&restore::read(&protected_path)?)
+            .ok_or_else(|| RestoreError::BadSnapshot(protected_path))?
+    } else {
+        HashSet::new()
+    };
+    let expiry_path = kspath.join(concat_str!(tblname, EXPIRY_MAP_EXTENSION));
+    let mut expiries = if expiry_path.is_file() {
+        super::de::deserialize_expiries(restore::read(&expiry_path)?)
+            .ok_or_else(|| RestoreError::BadSnapshot(expiry_path))?
+    } else {
+        HashMap::new()
+    };
+    // protected keys are never dropped, just like on a running server
+    let expired: Vec<Data> = expiries
+        .iter()
+        .filter(|(key, at)| **at <= now && !protected.contains(*key))
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in expired.iter() {
+        data.remove(key);
+        expiries.remove(key);
+    }
+    Ok((data, protected, expiries))
+}
@@ -0,0 +1,235 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Bloom filters
+//!
+//! Every flush writes a bloom filter of the keys of a table next to its file
+//! (`<table>.bloom`). When the tables are loaded lazily, the filters are read in at boot in
+//! place of the data so that a lookup of a key that a table doesn't have can be answered
+//! without reading in the table. A filter only describes the file it was written with: it
+//! records the size of that file, and the filter is removed before the file is replaced, so
+//! a crash can leave a table without a filter but never with the filter of another file.
+//! A missing, corrupted or stale filter is simply ignored (and the table is read in).
+//!
+//! ## Format
+//!
+//! All integers are little endian:
+//!
+//! ```text
+//! | data file size (8B) | hashes (4B) | bits (8B) | words (8B each) | CRC32-C (4B) |
+//! ```
+//!
+//! The CRC32-C covers everything before it. The hashes of a key are derived from two 64-bit
+//! FNV-1a hashes, which never change across releases or platforms
+
+use super::crc32c;
+use core::mem;
+
+/// The size of everything but the words
+const HEADER_SIZE: usize = 20;
+const CHECKSUM_SIZE: usize = 4;
+/// The most hashes a filter uses, whatever the false positive rate
+const MAX_HASHES: u32 = 16;
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+/// The offset of the second hash (the first one with a byte appended)
+const SECOND_SEED: u8 = 0xA5;
+
+#[derive(Debug, PartialEq)]
+/// A bloom filter of the keys of a table
+pub struct BloomFilter {
+    words: Vec<u64>,
+    hashes: u32,
+    /// the size of the data file that this filter was written with
+    file_size: u64,
+}
+
+impl BloomFilter {
+    /// Create an empty filter for `count` keys, which lets (about) `fp_rate` of the keys that
+    /// aren't in it through
+    pub fn with_capacity(count: usize, fp_rate: f64) -> Self {
+        let count = count.max(1) as f64;
+        let ln2 = core::f64::consts::LN_2;
+        let bits = (-count * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0);
+        let hashes = ((bits / count) * ln2)
+            .round()
+            .max(1.0)
+            .min(MAX_HASHES as f64) as u32;
+        Self {
+            words: vec![0; (bits as usize + 63) / 64],
+            hashes,
+            file_size: 0,
+        }
+    }
+    fn nbits(&self) -> u64 {
+        self.words.len() as u64 * 64
+    }
+    fn positions(&self, key: &[u8]) -> impl Iterator<Item = usize> {
+        let first = fnv1a(FNV_OFFSET, key);
+        // an odd step always visits distinct bits as long as there are fewer hashes than bits
+        let step = fnv1a(first, &[SECOND_SEED]) | 1;
+        let nbits = self.nbits();
+        (0..self.hashes as u64)
+            .map(move |i| (first.wrapping_add(i.wrapping_mul(step)) % nbits) as usize)
+    }
+    /// Add `key` to the filter
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.positions(key).collect::<Vec<usize>>() {
+            self.words[bit / 64] |= 1 << (bit % 64);
+        }
+    }
+    /// Returns false if `key` definitely isn't in the filter
+    pub fn may_contain(&self, key: &[u8]) -> bool {
+        self.positions(key)
+            .all(|bit| self.words[bit / 64] & (1 << (bit % 64)) != 0)
+    }
+    /// Record the size of the data file that this filter goes along with
+    pub fn set_file_size(&mut self, size: u64) {
+        self.file_size = size;
+    }
+    /// Returns the size of the data file that this filter was written with
+    pub const fn file_size(&self) -> u64 {
+        self.file_size
+    }
+    /// Returns the approximate number of bytes used by the filter
+    pub fn approx_memory(&self) -> usize {
+        self.words.len() * mem::size_of::<u64>()
+    }
+    /// Encode the filter (see the [format](self))
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(HEADER_SIZE + self.words.len() * 8 + CHECKSUM_SIZE);
+        ret.extend_from_slice(&self.file_size.to_le_bytes());
+        ret.extend_from_slice(&self.hashes.to_le_bytes());
+        ret.extend_from_slice(&self.nbits().to_le_bytes());
+        for word in self.words.iter() {
+            ret.extend_from_slice(&word.to_le_bytes());
+        }
+        let checksum = crc32c(&ret);
+        ret.extend_from_slice(&checksum.to_le_bytes());
+        ret
+    }
+    /// Decode a filter, returning `None` if it's malformed or fails the checksum
+    pub fn deserialize(data: &[u8]) -> Option<Self> {
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return None;
+        }
+        let (body, checksum) = data.split_at(data.len() - CHECKSUM_SIZE);
+        if crc32c(body) != u32::from_le_bytes(to_array(checksum)) {
+            return None;
+        }
+        let file_size = u64::from_le_bytes(to_array(&body[..8]));
+        let hashes = u32::from_le_bytes(to_array(&body[8..12]));
+        let nbits = u64::from_le_bytes(to_array(&body[12..20]));
+        let words = &body[HEADER_SIZE..];
+        if hashes == 0
+            || hashes > MAX_HASHES
+            || nbits == 0
+            || nbits % 64 != 0
+            || words.len() as u64 * 8 != nbits
+        {
+            return None;
+        }
+        Some(Self {
+            words: words
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(to_array(word)))
+                .collect(),
+            hashes,
+            file_size,
+        })
+    }
+}
+
+/// The 64-bit FNV-1a hash of `data`, starting from `state`
+fn fnv1a(state: u64, data: &[u8]) -> u64 {
+    data.iter().fold(state, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Copy the bytes of `slice` (which has to have as many bytes as `N`) into an array
+fn to_array<const N: usize>(slice: &[u8]) -> [u8; N] {
+    let mut ret = [0u8; N];
+    ret.copy_from_slice(slice);
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filter_of(count: usize) -> BloomFilter {
+        let mut filter = BloomFilter::with_capacity(count, 0.01);
+        for i in 0..count {
+            filter.insert(format!("key{}", i).as_bytes());
+        }
+        filter
+    }
+
+    #[test]
+    fn test_no_false_negatives() {
+        let filter = filter_of(1000);
+        assert!((0..1000).all(|i| filter.may_contain(format!("key{}", i).as_bytes())));
+    }
+
+    #[test]
+    fn test_false_positive_rate() {
+        let filter = filter_of(1000);
+        let false_positives = (0..10_000)
+            .filter(|i| filter.may_contain(format!("missing{}", i).as_bytes()))
+            .count();
+        // about 1% (100) are expected
+        assert!(false_positives < 300, "{} false positives", false_positives);
+    }
+
+    #[test]
+    fn test_empty_filter_has_nothing() {
+        let filter = BloomFilter::with_capacity(0, 0.01);
+        assert!(!filter.may_contain(b"anything"));
+        assert!(!filter.may_contain(b""));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let mut filter = filter_of(100);
+        filter.set_file_size(12345);
+        let decoded = BloomFilter::deserialize(&filter.serialize()).unwrap();
+        assert_eq!(decoded, filter);
+        assert_eq!(decoded.file_size(), 12345);
+    }
+
+    #[test]
+    fn test_corrupted_filter_is_rejected() {
+        let encoded = filter_of(100).serialize();
+        for i in [0, 9, 13, HEADER_SIZE, encoded.len() - 1].iter() {
+            let mut corrupted = encoded.clone();
+            corrupted[*i] ^= 0x40;
+            assert!(BloomFilter::deserialize(&corrupted).is_none());
+        }
+        assert!(BloomFilter::deserialize(&encoded[..encoded.len() - 1]).is_none());
+        assert!(BloomFilter::deserialize(b"").is_none());
+    }
+}
@@ -46,3 +46,22 @@ pub const BYTEMARK_MODEL_KV_STR_BIN: u8 = 3;
 pub const BYTEMARK_STORAGE_PERSISTENT: u8 = 0;
 /// Volatile storage bytemark
 pub const BYTEMARK_STORAGE_VOLATILE: u8 = 1;
+/// Set on the storage bytemark (along with one of the above) for tables that keep an
+/// ordered index
+pub const BYTEMARK_STORAGE_FLAG_ORDERED: u8 = 0b10;
+/// Set on the storage bytemark for tables that have their own limit on the size of values.
+/// The entry in the partition map is then followed by the limit (8 bytes, little endian)
+pub const BYTEMARK_STORAGE_FLAG_VALUE_LIMIT: u8 = 0b100;
+/// Set on the storage bytemark of the table that was made the default table of its keyspace
+/// (with `ALTER KEYSPACE`). It is never set for a table called `default`
+pub const BYTEMARK_STORAGE_FLAG_DEFAULT: u8 = 0b1000;
+/// Set on the storage bytemark for tables that keep a cache of `GET` responses
+pub const BYTEMARK_STORAGE_FLAG_READ_CACHE: u8 = 0b1_0000;
+/// Set on the storage bytemark for tables that have been written to or read from (creating a
+/// table counts as a write). The entry in the partition map is then followed (after the value
+/// limit, if any) by the times of the latest write and of the latest read (8 bytes each,
+/// little endian, in seconds since the unix epoch and 0 if there was none)
+pub const BYTEMARK_STORAGE_FLAG_ACTIVITY: u8 = 0b10_0000;
+/// Set on the storage bytemark of every table of a keyspace that was archived (with
+/// `ARCHIVE KEYSPACE`), whose tables are only read in once it is unarchived
+pub const BYTEMARK_STORAGE_FLAG_ARCHIVED: u8 = 0b100_0000;
@@ -0,0 +1,187 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Snapshot chains
+//!
+//! An incremental snapshot only has the files of the tables that were modified since the
+//! snapshot it applies on top of (its _parent_), along with the `PRELOAD` and the `PARTMAP` of
+//! every keyspace. The parent can be an incremental snapshot too, so restoring one means going
+//! back along the chain of parents up to the full snapshot at its start.
+//!
+//! Every snapshot made by the snapshot engine has a `CHAIN` file that records its parent (if it
+//! has one), its depth in the chain and the range of change log sequence numbers it covers. It
+//! looks like this:
+//! ```text
+//! parent 20210701-100000
+//! depth 1
+//! epoch 1625133600000000000
+//! seq 120 245
+//! ```
+//! Full snapshots don't have the `parent` line.
+
+use crate::storage::error::{StorageError, StorageResult};
+use crate::storage::interface::sync_dir;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+/// The file (in the snapshot directory) that describes the snapshot's place in its chain
+pub const CHAIN_FILE: &str = "CHAIN";
+
+#[derive(Debug, Clone, PartialEq)]
+/// A snapshot's place in its chain
+pub struct ChainLink {
+    /// the snapshot that this one applies on top of (`None` for a full snapshot)
+    pub parent: Option<String>,
+    /// the number of incremental snapshots from the full snapshot up to (and including) this
+    /// one
+    pub depth: usize,
+    /// the [change log epoch](crate::registry::ChangeLog::epoch) in which the snapshot was taken
+    pub epoch: u64,
+    /// the snapshot has the mutations after this sequence number...
+    pub from: u64,
+    /// ...up to (and including) this one
+    pub to: u64,
+}
+
+impl ChainLink {
+    /// A full snapshot, taken at sequence number `to`
+    pub const fn full(epoch: u64, to: u64) -> Self {
+        Self {
+            parent: None,
+            depth: 0,
+            epoch,
+            from: 0,
+            to,
+        }
+    }
+    /// An incremental snapshot on top of `parent` (which is described by `of`), taken at
+    /// sequence number `to`
+    pub fn incremental(parent: String, of: &ChainLink, epoch: u64, to: u64) -> Self {
+        Self {
+            parent: Some(parent),
+            depth: of.depth + 1,
+            epoch,
+            // the sequence numbers of another epoch don't tell us anything
+            from: if of.epoch == epoch { of.to } else { 0 },
+            to,
+        }
+    }
+    /// Check if a table that was last modified at sequence number `last_modified` (in
+    /// `epoch`) might have changed since this snapshot was taken. This is always the case
+    /// if the snapshot was taken in another epoch
+    pub fn is_outdated(&self, epoch: u64, last_modified: u64) -> bool {
+        self.epoch != epoch || last_modified >= self.to
+    }
+    /// Parse the contents of a `CHAIN` file, returning `None` if they're corrupted
+    pub fn parse(file: &str) -> Option<Self> {
+        let (mut parent, mut depth, mut epoch, mut seq) = (None, None, None, None);
+        // split `a b` into `a` and `b`
+        fn pair(s: &str) -> Option<(&str, &str)> {
+            let mut parts = s.splitn(2, ' ');
+            Some((parts.next()?, parts.next()?))
+        }
+        for line in file.lines() {
+            let (key, value) = pair(line)?;
+            match key {
+                "parent" => parent = Some(value.to_owned()),
+                "depth" => depth = Some(value.parse().ok()?),
+                "epoch" => epoch = Some(value.parse().ok()?),
+                "seq" => {
+                    let (from, to) = pair(value)?;
+                    seq = Some((from.parse().ok()?, to.parse().ok()?));
+                }
+                _ => return None,
+            }
+        }
+        let (depth, epoch, (from, to)) = (depth?, epoch?, seq?);
+        // only the first snapshot in a chain has no parent
+        if parent.is_none() != (depth == 0) {
+            return None;
+        }
+        Some(Self {
+            parent,
+            depth,
+            epoch,
+            from,
+            to,
+        })
+    }
+    /// Returns the contents of the `CHAIN` file for this link
+    pub fn to_file(&self) -> String {
+        let mut file = String::new();
+        if let Some(parent) = &self.parent {
+            file.push_str(&format!("parent {}\n", parent));
+        }
+        file.push_str(&format!(
+            "depth {}\nepoch {}\nseq {} {}\n",
+            self.depth, self.epoch, self.from, self.to
+        ));
+        file
+    }
+}
+
+/// Read the `CHAIN` file of the snapshot at `snapdir`. This returns `None` if the snapshot
+/// doesn't have one (or if it is corrupted)
+pub fn read_link(snapdir: &Path) -> Option<ChainLink> {
+    ChainLink::parse(&fs::read_to_string(snapdir.join(CHAIN_FILE)).ok()?)
+}
+
+/// Write the `CHAIN` file of the snapshot at `snapdir`
+pub fn write_link(snapdir: &Path, link: &ChainLink) -> StorageResult<()> {
+    let temp = snapdir.join(concat_str!(CHAIN_FILE, "_"));
+    fs::File::create(&temp)
+        .and_then(|mut file| {
+            file.write_all(link.to_file().as_bytes())?;
+            file.sync_all()
+        })
+        .map_err(StorageError::io("write", &temp))?;
+    fs::rename(&temp, snapdir.join(CHAIN_FILE)).map_err(StorageError::io("rename", &temp))?;
+    sync_dir(snapdir)
+}
+
+#[test]
+fn test_chain_link_roundtrip() {
+    let full = ChainLink::full(7, 120);
+    assert_eq!(ChainLink::parse(&full.to_file()), Some(full.clone()));
+    let incremental = ChainLink::incremental("20210701-100000".to_owned(), &full, 7, 245);
+    assert_eq!(
+        incremental.to_file(),
+        "parent 20210701-100000\ndepth 1\nepoch 7\nseq 120 245\n"
+    );
+    assert_eq!(ChainLink::parse(&incremental.to_file()), Some(incremental));
+    // the sequence numbers of a snapshot from another epoch are of no use
+    let after_restart = ChainLink::incremental("20210701-100000".to_owned(), &full, 8, 3);
+    assert_eq!((after_restart.from, after_restart.to), (0, 3));
+    assert!(after_restart.is_outdated(9, 0));
+    assert!(after_restart.is_outdated(8, 3));
+    assert!(!after_restart.is_outdated(8, 2));
+    // corrupted files
+    assert!(ChainLink::parse("depth 1\nepoch 7\nseq 120 245\n").is_none());
+    assert!(ChainLink::parse("parent x\ndepth 0\nepoch 7\nseq 1 2\n").is_none());
+    assert!(ChainLink::parse("depth x\nepoch 7\nseq 1 2\n").is_none());
+    assert!(ChainLink::parse("depth 0\nepoch 7\n").is_none());
+}
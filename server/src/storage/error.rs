@@ -0,0 +1,225 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Storage errors
+//!
+//! Everything in the storage module (and the snapshot engine on top of it) reports failures as a
+//! [`StorageError`], which always says which file was involved and what was being done with it.
+//! I/O errors are wrapped along with the path and the operation, while files that could be read
+//! but not decoded are reported as corrupted (or as written by an incompatible version)
+
+use super::reconcile::RecoveryReport;
+use crate::corestore::Data;
+use crate::util::fmt_key_safe;
+use core::fmt;
+use std::io::Error as IoError;
+use std::path::{Path, PathBuf};
+
+/// The result of a storage operation
+pub type StorageResult<T> = Result<T, StorageError>;
+
+#[derive(Debug)]
+/// Errors that can occur while reading from or writing to the data directory
+pub enum StorageError {
+    /// An I/O error while doing `op` (`read`, `create`, `rename` and so on) on `path`
+    Io {
+        op: &'static str,
+        path: PathBuf,
+        error: IoError,
+    },
+    /// The file could be read, but not decoded
+    Corruption {
+        file: PathBuf,
+        /// where the problem is, if we know
+        offset: Option<usize>,
+        reason: &'static str,
+    },
+    /// The file was written with another version (or byte order) of the storage format
+    VersionMismatch {
+        file: PathBuf,
+        found: u8,
+        expected: u8,
+    },
+    /// Records of the file don't match their checksums (and the records that don't match
+    /// are rejected)
+    ChecksumMismatch {
+        file: PathBuf,
+        /// the records that don't match, in the order that they're in the file
+        records: Vec<BadRecord>,
+    },
+    /// The PID file is locked by another process, presumably another server using the data
+    /// directory
+    LockHeld { file: PathBuf },
+    /// More than one table couldn't be read in (the tables are read in side by side, so every
+    /// failure is reported)
+    Several(Vec<StorageError>),
+    /// The files in the data directory don't match the `PARTMAP`s of the keyspaces (see
+    /// [`reconcile`](super::reconcile)), and the configuration says not to start like this
+    Unreconciled(RecoveryReport),
+    /// With `caseinsensitive` set, the names of keyspaces (or of tables in a keyspace) are the
+    /// same key (see [`names`](super::names)). Every group of such names is described
+    NameCollisions(Vec<String>),
+}
+
+#[derive(Debug, PartialEq)]
+/// A record of a table that doesn't match its checksum
+pub struct BadRecord {
+    /// the position of the record in the file (the first one is 0)
+    pub index: usize,
+    /// the byte of the file that the record starts at
+    pub offset: usize,
+    /// the key of the record, as it was read
+    pub key: Data,
+}
+
+impl fmt::Display for BadRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "record {} (at byte {}, with the key '{}')",
+            self.index,
+            self.offset,
+            fmt_key_safe(&self.key)
+        )
+    }
+}
+
+impl StorageError {
+    /// Returns a function that wraps an I/O error while doing `op` on `path`, to be used with
+    /// `map_err`
+    pub fn io(op: &'static str, path: impl AsRef<Path>) -> impl FnOnce(IoError) -> Self {
+        let path = path.as_ref().to_owned();
+        move |error| Self::Io { op, path, error }
+    }
+    /// The file at `file` couldn't be decoded because of `reason`
+    pub fn corrupted(file: impl AsRef<Path>, reason: &'static str) -> Self {
+        Self::Corruption {
+            file: file.as_ref().to_owned(),
+            offset: None,
+            reason,
+        }
+    }
+    /// Same as [`Self::corrupted`], but for a problem at byte `offset` of the file
+    pub fn corrupted_at(file: impl AsRef<Path>, offset: usize, reason: &'static str) -> Self {
+        Self::Corruption {
+            file: file.as_ref().to_owned(),
+            offset: Some(offset),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { op, path, error } => {
+                write!(f, "failed to {} `{}`: {}", op, path.display(), error)
+            }
+            Self::Corruption {
+                file,
+                offset: Some(offset),
+                reason,
+            } => write!(
+                f,
+                "failed to read `{}`: corrupted at byte {}: {}",
+                file.display(),
+                offset,
+                reason
+            ),
+            Self::Corruption {
+                file,
+                offset: None,
+                reason,
+            } => write!(
+                f,
+                "failed to read `{}`: corrupted: {}",
+                file.display(),
+                reason
+            ),
+            Self::VersionMismatch {
+                file,
+                found,
+                expected,
+            } => write!(
+                f,
+                "failed to read `{}`: written with an incompatible version of the storage format \
+                (version mark {:#04x}, expected {:#04x})",
+                file.display(),
+                found,
+                expected
+            ),
+            Self::ChecksumMismatch { file, records } => {
+                write!(
+                    f,
+                    "failed to read `{}`: {} record(s) don't match their checksums",
+                    file.display(),
+                    records.len()
+                )?;
+                if let Some(first) = records.first() {
+                    write!(f, ", the first being {}", first)?;
+                }
+                Ok(())
+            }
+            Self::LockHeld { file } => write!(
+                f,
+                "failed to lock `{}`: another process holds the lock (is another server using \
+                this data directory?)",
+                file.display()
+            ),
+            Self::Several(errors) => {
+                write!(f, "failed to read {} tables", errors.len())?;
+                for error in errors {
+                    write!(f, "; {}", error)?;
+                }
+                Ok(())
+            }
+            Self::Unreconciled(report) => write!(
+                f,
+                "the data directory doesn't match the metadata of its keyspaces: {} (set \
+                `orphans` to `adopt` or `quarantine` the orphans, and `allowmissing` to load \
+                the tables without a file empty)",
+                report
+            ),
+            Self::NameCollisions(collisions) => write!(
+                f,
+                "names that only differ in case are the same with `caseinsensitive` set: {} \
+                (start without `caseinsensitive` to drop or export all but one of each)",
+                collisions.join("; ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StorageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            // the message already has the I/O error in it, but it's still there for those who
+            // want to look at it
+            Self::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
@@ -30,53 +30,360 @@
 //! the table level
 
 use super::interface;
+use super::writer;
 use crate::corestore::memstore::Keyspace;
 use crate::corestore::memstore::Memstore;
 use crate::corestore::memstore::ObjectID;
-use crate::registry;
-use crate::IoResult;
+use crate::corestore::table::Table;
+use crate::registry::{self, IoKind};
+use crate::storage::error::{StorageError, StorageResult};
+use crate::storage::interface::{
+    dir_ksroot, dir_root, dir_snaproot, sync_dir, BLOOM_FILTER_EXTENSION, DROPPED_EXTENSION,
+    EXPIRY_MAP_EXTENSION, PROTECTED_SET_EXTENSION,
+};
+use std::fs::{self, File};
+use std::io::ErrorKind;
+use std::path::Path;
+
+/// The bytes that a pair takes up in the file of a table on top of its key and its value (the
+/// lengths of both), for [estimating](estimated_snapshot_bytes) what a snapshot writes
+const SERIALIZED_PAIR_OVERHEAD: u64 = 16;
+
+/// What the flush of a table wrote out
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableFlush {
+    /// the table, as `<keyspace>:<table>`
+    pub table: String,
+    /// the bytes written for the table, along with the files that go with it
+    pub bytes: u64,
+    /// the bytes that were mutated in the table since it was last flushed
+    pub dirty: usize,
+}
+
+/// What a flush wrote out
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FlushReport {
+    /// the tables that were written out (volatile tables and the tables that were never read
+    /// in aren't)
+    pub tables: Vec<TableFlush>,
+    /// the bytes written for the `PRELOAD` and the `PARTMAP`s
+    pub metadata: u64,
+}
+
+impl FlushReport {
+    /// Returns the bytes written for the tables and the metadata
+    pub fn bytes(&self) -> u64 {
+        self.metadata + self.tables.iter().map(|table| table.bytes).sum::<u64>()
+    }
+    /// Returns the bytes that were mutated in the tables since they were last flushed
+    pub fn dirty(&self) -> usize {
+        self.tables.iter().map(|table| table.dirty).sum()
+    }
+    /// Returns the bytes written for every byte that was mutated (see [`write_amplification`])
+    pub fn write_amplification(&self) -> Option<f64> {
+        self::write_amplification(self.bytes(), self.dirty() as u64)
+    }
+    fn add(&mut self, other: FlushReport) {
+        self.tables.extend(other.tables);
+        self.metadata += other.metadata;
+    }
+}
+
+/// Returns the ratio of the `bytes` that were written out to the `dirty` bytes that were
+/// mutated since the previous flush, or `None` if nothing was mutated
+pub fn write_amplification(bytes: u64, dirty: u64) -> Option<f64> {
+    if dirty == 0 {
+        None
+    } else {
+        Some(bytes as f64 / dirty as f64)
+    }
+}
 
 /// Flushes the entire **keyspace + partmap**
-pub fn flush_keyspace_full(ksid: &ObjectID, keyspace: &Keyspace) -> IoResult<()> {
-    self::oneshot::flush_partmap(ksid, keyspace)?;
-    self::oneshot::flush_keyspace(ksid, keyspace)
+pub fn flush_keyspace_full(ksid: &ObjectID, keyspace: &Keyspace) -> StorageResult<FlushReport> {
+    let metadata = self::oneshot::flush_partmap(ksid, keyspace)?;
+    let tables = self::oneshot::flush_keyspace(ksid, keyspace)?;
+    self::sync_flushed_dir(unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) })?;
+    Ok(FlushReport { tables, metadata })
 }
 
 /// Flush the entire **preload + keyspaces + their partmaps**
-pub fn flush_full(store: &Memstore) -> IoResult<()> {
+pub fn flush_full(store: &Memstore) -> StorageResult<FlushReport> {
     // IMPORTANT: Just untrip and get the status at this exact point in time
     // don't spread it over two atomic accesses because another thread may have updated
     // it in-between. Even if it was untripped, we'll get the expected outcome here: false
     let has_tripped = registry::get_preload_tripswitch().check_and_untrip();
-    if has_tripped {
-        // re-init the tree as new tables/keyspaces may have been added
-        super::interface::create_tree(store)?;
-        self::oneshot::flush_preload(store)?;
-    }
-    for keyspace in store.keyspaces.iter() {
-        self::flush_keyspace_full(keyspace.key(), keyspace.value())?;
-    }
-    Ok(())
+    // the files are written (and synced) in one go if the flushes use io_uring
+    writer::batched(|| {
+        let mut report = FlushReport::default();
+        if has_tripped {
+            // re-init the tree as new tables/keyspaces may have been added
+            super::interface::create_tree(store)?;
+            report.metadata += self::oneshot::flush_preload(store)?;
+            self::sync_flushed_dir(dir_root())?;
+            self::sync_flushed_dir(dir_ksroot())?;
+        }
+        for keyspace in store.keyspaces.iter() {
+            if keyspace.value().is_archived() {
+                // it was flushed when it was archived and nothing has changed since
+                continue;
+            }
+            report.add(self::flush_keyspace_full(keyspace.key(), keyspace.value())?);
+        }
+        Ok(report)
+    })
+}
+
+/// Same as [`flush_full`], except for the files being paced to the limit that is set for the
+/// periodic flushes (see [`writer::throttled`])
+pub fn throttled_flush_full(store: &Memstore) -> StorageResult<FlushReport> {
+    writer::throttled(IoKind::Flush, || self::flush_full(store))
 }
 
 pub fn snap_flush_keyspace_full(
     snapid: &str,
     ksid: &ObjectID,
     keyspace: &Keyspace,
-) -> IoResult<()> {
+) -> StorageResult<()> {
     self::oneshot::snap_flush_partmap(snapid, ksid, keyspace)?;
     self::oneshot::snap_flush_keyspace(snapid, ksid, keyspace)
 }
 
-pub fn snap_flush_full(snapid: &str, store: &Memstore) -> IoResult<()> {
+/// Flush a full snapshot of `store` to `snapid`. The files are paced to the limit that is set
+/// for the snapshots (see [`writer::throttled`])
+pub fn snap_flush_full(snapid: &str, store: &Memstore) -> StorageResult<()> {
     super::interface::snap_create_tree(snapid, store)?;
-    self::oneshot::snap_flush_preload(snapid, store)?;
+    self::tracking_progress(estimated_snapshot_bytes(store, |_| true), || {
+        writer::throttled(IoKind::Snapshot, || {
+            self::oneshot::snap_flush_preload(snapid, store)?;
+            for keyspace in store.keyspaces.iter() {
+                self::snap_flush_keyspace_full(snapid, keyspace.key(), keyspace.value())?;
+            }
+            Ok(())
+        })
+    })?;
+    // the directories are only synced once every file of a batch is in place
+    self::sync_snapshot_dirs(snapid, store)
+}
+
+/// Same as [`snap_flush_full`], except for only flushing the tables for which `include`
+/// returns true. The `PRELOAD` and the `PARTMAP` of every keyspace are always flushed
+pub fn snap_flush_incremental(
+    snapid: &str,
+    store: &Memstore,
+    include: impl Fn(&Table) -> bool,
+) -> StorageResult<()> {
+    super::interface::snap_create_tree(snapid, store)?;
+    self::tracking_progress(estimated_snapshot_bytes(store, &include), || {
+        writer::throttled(IoKind::Snapshot, || {
+            self::oneshot::snap_flush_preload(snapid, store)?;
+            for keyspace in store.keyspaces.iter() {
+                let (ksid, keyspace) = (keyspace.key(), keyspace.value());
+                self::oneshot::snap_flush_partmap(snapid, ksid, keyspace)?;
+                for table in keyspace.tables.iter() {
+                    if include(table.value()) {
+                        self::oneshot::snap_flush_table(snapid, ksid, table.key(), table.value())?;
+                    }
+                }
+            }
+            Ok(())
+        })
+    })?;
+    self::sync_snapshot_dirs(snapid, store)
+}
+
+/// Run the snapshot `flush`, which is estimated to write `estimated` bytes, while recording its
+/// progress for `SYS SNAPSTATE` (see [`SnapshotProgress`](registry::SnapshotProgress))
+fn tracking_progress(
+    estimated: u64,
+    flush: impl FnOnce() -> StorageResult<()>,
+) -> StorageResult<()> {
+    let progress = registry::get_io_throttle().snapshot_progress();
+    progress.begin(estimated);
+    let ret = flush();
+    progress.end();
+    ret
+}
+
+/// Estimate the bytes that a snapshot writes for the tables for which `include` returns true,
+/// from the bytes that the tables hold and the lengths that are written along with their
+/// pairs. The tables that were never read in are copied as they are instead of being written
+/// out (and aren't throttled), so they aren't counted
+pub fn estimated_snapshot_bytes(store: &Memstore, include: impl Fn(&Table) -> bool) -> u64 {
+    store
+        .keyspaces
+        .iter()
+        .map(|keyspace| {
+            keyspace
+                .value()
+                .tables
+                .iter()
+                .filter(|table| include(table.value()) && !table.value().is_volatile())
+                .filter_map(|table| {
+                    table.value().loaded_kvstore().map(|kve| {
+                        kve.stored_bytes() as u64 + kve.len() as u64 * SERIALIZED_PAIR_OVERHEAD
+                    })
+                })
+                .sum::<u64>()
+        })
+        .sum()
+}
+
+/// Sync the directory at `path` after a periodic flush wrote files in it, if the flushes are
+/// configured to (see [`ParanoidMode::syncs_dirs`](crate::config::ParanoidMode::syncs_dirs))
+fn sync_flushed_dir(path: impl AsRef<Path>) -> StorageResult<()> {
+    if registry::get_paranoid_mode().syncs_dirs() {
+        writer::sync_dir(path)
+    } else {
+        Ok(())
+    }
+}
+
+/// Sync the directories of the snapshot `snapid` that was just flushed, along with the
+/// directories that it is in (up to the snapshot directory), so that a snapshot that was
+/// created is still there after a power cut. Unlike the periodic flushes, snapshots always
+/// do this
+fn sync_snapshot_dirs(snapid: &str, store: &Memstore) -> StorageResult<()> {
+    let snapdir = concat_path!(dir_snaproot(), snapid);
     for keyspace in store.keyspaces.iter() {
-        self::snap_flush_keyspace_full(snapid, keyspace.key(), keyspace.value())?;
+        sync_dir(snapdir.join(unsafe { keyspace.key().as_str() }))?;
+    }
+    let snaproot = Path::new(dir_snaproot());
+    for dir in snapdir
+        .ancestors()
+        .take_while(|dir| dir.starts_with(snaproot))
+    {
+        sync_dir(dir)?;
     }
     Ok(())
 }
 
+/// Remove the files of a table that was just dropped from `keyspace`, so that it isn't read
+/// back in after a restart. The file of the table is renamed to a
+/// [tombstone](DROPPED_EXTENSION) first (an empty tombstone is created for a table without a
+/// file), then the `PARTMAP` is rewritten without the table and only then is the tombstone
+/// removed. So if the server crashes in between, the table is either still there (with all its
+/// data) or it has a tombstone and is skipped (and cleaned up) when the data is read in.
+/// Snapshots are left alone, since they have their own copies.
+///
+/// This has to be called with the flush lock held
+pub fn drop_table(ksid: &ObjectID, tblid: &ObjectID, keyspace: &Keyspace) -> StorageResult<()> {
+    let ks_path = unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) };
+    if !Path::new(&ks_path).is_dir() {
+        // the keyspace was never flushed, so neither was the table
+        return Ok(());
+    }
+    let tblpath = unsafe { concat_str!(&ks_path, "/", tblid.as_str()) };
+    let tombstone = concat_str!(&tblpath, DROPPED_EXTENSION);
+    match fs::rename(&tblpath, &tombstone) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            // the table is volatile (or was never flushed), but the PARTMAP may have it
+            File::create(&tombstone)
+                .and_then(|file| file.sync_all())
+                .map_err(StorageError::io("create", &tombstone))?;
+        }
+        Err(e) => return Err(StorageError::io("rename", &tblpath)(e)),
+    }
+    sync_dir(&ks_path)?;
+    let extensions = [
+        PROTECTED_SET_EXTENSION,
+        EXPIRY_MAP_EXTENSION,
+        BLOOM_FILTER_EXTENSION,
+    ];
+    for extension in extensions.iter() {
+        self::remove_if_exists(&concat_str!(&tblpath, extension))?;
+    }
+    self::flush_partmap_safely(ksid, keyspace)?;
+    fs::remove_file(&tombstone).map_err(StorageError::io("remove", &tombstone))?;
+    sync_dir(&ks_path)
+}
+
+/// Remove the directory of a keyspace that was just dropped from `store`. Just like
+/// [`drop_table`], the directory is renamed to a tombstone first and the `PRELOAD` is
+/// rewritten without the keyspace before the tombstone is removed.
+///
+/// This has to be called with the flush lock held
+pub fn drop_keyspace(ksid: &ObjectID, store: &Memstore) -> StorageResult<()> {
+    let ks_path = unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) };
+    if !Path::new(&ks_path).is_dir() {
+        // the keyspace was never flushed
+        return Ok(());
+    }
+    let tombstone = concat_str!(&ks_path, DROPPED_EXTENSION);
+    fs::rename(&ks_path, &tombstone).map_err(StorageError::io("rename", &ks_path))?;
+    sync_dir(dir_ksroot())?;
+    // the new PRELOAD can only name keyspaces that can be read in
+    super::interface::create_tree(store)?;
+    for keyspace in store.keyspaces.iter() {
+        let partmap = unsafe { concat_path!(dir_ksroot(), keyspace.key().as_str(), "PARTMAP") };
+        if !partmap.is_file() {
+            self::flush_partmap_safely(keyspace.key(), keyspace.value())?;
+        }
+    }
+    self::oneshot::flush_preload(store)?;
+    sync_dir(dir_ksroot())?;
+    fs::remove_dir_all(&tombstone).map_err(StorageError::io("remove", &tombstone))?;
+    sync_dir(dir_ksroot())
+}
+
+/// Write out a keyspace that is being archived, since what its tables hold in memory is
+/// dropped right after: the tables and then the `PARTMAP`, which marks the keyspace as
+/// archived. If the keyspace was created since the last flush, its directory is created and
+/// the `PRELOAD` is written out with it. Everything is synced, no matter how the periodic
+/// flushes are configured.
+///
+/// This has to be called with the flush lock held
+pub fn archive_keyspace(
+    ksid: &ObjectID,
+    keyspace: &Keyspace,
+    store: &Memstore,
+) -> StorageResult<()> {
+    let ks_path = unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) };
+    let is_new = !Path::new(&ks_path).is_dir();
+    if is_new {
+        super::interface::create_tree(store)?;
+    }
+    self::oneshot::flush_keyspace(ksid, keyspace)?;
+    self::oneshot::flush_partmap(ksid, keyspace)?;
+    sync_dir(&ks_path)?;
+    if is_new {
+        // just like in drop_keyspace, the PRELOAD can only name keyspaces that can be read in
+        for other in store.keyspaces.iter() {
+            let partmap = unsafe { concat_path!(dir_ksroot(), other.key().as_str(), "PARTMAP") };
+            if !partmap.is_file() {
+                self::flush_partmap_safely(other.key(), other.value())?;
+            }
+        }
+        self::oneshot::flush_preload(store)?;
+        sync_dir(dir_ksroot())?;
+    }
+    Ok(())
+}
+
+/// Flush the `PARTMAP` of a keyspace from memory, after flushing the tables that don't have a
+/// file yet (tables created since the last flush) so that all the tables that it names can be
+/// read in. This is only done for DDL, so the directory of the keyspace is always synced
+fn flush_partmap_safely(ksid: &ObjectID, keyspace: &Keyspace) -> StorageResult<()> {
+    for table in keyspace.tables.iter() {
+        let (tblid, table) = (table.key(), table.value());
+        let has_file =
+            unsafe { concat_path!(dir_ksroot(), ksid.as_str(), tblid.as_str()) }.is_file();
+        if !table.is_volatile() && !has_file {
+            self::oneshot::flush_table(tblid, ksid, table)?;
+        }
+    }
+    self::oneshot::flush_partmap(ksid, keyspace)?;
+    sync_dir(unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) })
+}
+
+fn remove_if_exists(path: &str) -> StorageResult<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(StorageError::io("remove", path)(e)),
+        _ => Ok(()),
+    }
+}
+
 pub mod oneshot {
     //! # Irresponsible flushing
     //!
@@ -84,16 +391,23 @@ pub mod oneshot {
     //! files et al are handled
     //!
     use super::*;
-    use crate::corestore::table::{DataModel, Table};
-    use crate::storage::interface::{DIR_KSROOT, DIR_SNAPROOT};
-    use std::fs::{self, File};
-
-    const PRELOAD_FILE_PATH_TEMP: &str = "data/ks/PRELOAD_";
-    const PRELOAD_FILE_PATH: &str = "data/ks/PRELOAD";
+    use crate::corestore::table::DataModel;
+    use crate::storage::bloom::BloomFilter;
+    use crate::IoResult;
+    use std::io::Write;
 
     macro_rules! tbl_path {
         ($ksid:expr, $tableid:expr) => {
-            unsafe { concat_str!(DIR_KSROOT, "/", $ksid.as_str(), "/", $tableid.as_str(), "_") }
+            unsafe {
+                concat_str!(
+                    dir_ksroot(),
+                    "/",
+                    $ksid.as_str(),
+                    "/",
+                    $tableid.as_str(),
+                    "_"
+                )
+            }
         };
     }
 
@@ -101,7 +415,7 @@ pub mod oneshot {
         ($snapid:expr, $ksid:expr, $tableid:expr) => {
             unsafe {
                 concat_str!(
-                    DIR_SNAPROOT,
+                    dir_snaproot(),
                     "/",
                     $snapid,
                     "/",
@@ -116,26 +430,158 @@ pub mod oneshot {
 
     macro_rules! routine_flushtable {
         ($table:ident, $path:expr) => {
-            if $table.is_volatile() {
-                // no flushing needed
-                Ok(())
+            if $table.is_volatile() || !$table.is_loaded() {
+                // no flushing needed (if the table was never read in, what's on disk is
+                // already up to date)
+                Ok(None)
             } else {
                 // fine, this needs to be flushed
-                let mut file = File::create(&$path)?;
-                match $table.get_model_ref() {
-                    DataModel::KV(kve) => super::interface::serialize_map_into_slow_buffer(
-                        &mut file,
-                        kve.__get_inner_ref(),
-                    )?,
-                }
-                file.sync_all()?;
-                fs::rename(&$path, &$path[..$path.len() - 1])
+                let tblpath = &$path[..$path.len() - 1];
+                let bloom_path = concat_str!(tblpath, BLOOM_FILTER_EXTENSION);
+                // the filter of the old file must never be taken for the filter of the new one
+                self::flush_sidecar(&bloom_path, true, |_| Ok(()))?;
+                let mut bloom = None;
+                let checksums = registry::get_paranoid_mode().has_checksums();
+                let written = self::write_file(&$path, |file| match $table.get_model_ref() {
+                    DataModel::KV(kve) => {
+                        let map = kve.__get_inner_ref();
+                        let mut filter =
+                            BloomFilter::with_capacity(map.len(), registry::get_bloom_fp_rate());
+                        super::interface::serialize_map_into_slow_buffer_with(
+                            file,
+                            map,
+                            checksums,
+                            |key| filter.insert(key),
+                        )?;
+                        bloom = Some(filter);
+                        Ok(())
+                    }
+                })?;
+                let protected = self::flush_protected($table, tblpath)?;
+                let expiries = self::flush_expiries($table, tblpath)?;
+                let filter = match bloom {
+                    Some(filter) => self::flush_bloom_filter(filter, written, &bloom_path)?,
+                    None => 0,
+                };
+                Ok(Some(written + protected + expiries + filter))
             }
         };
     }
-    /// No `partmap` handling. Just flushes the table to the expected location
-    pub fn flush_table(tableid: &ObjectID, ksid: &ObjectID, table: &Table) -> IoResult<()> {
-        routine_flushtable!(table, tbl_path!(ksid, tableid))
+
+    /// Write a file with `serialize` through the temporary file at `temp_path` (the path of the
+    /// file followed by an `_`), which is synced and then renamed, so that the file is never
+    /// left half-written. This returns the number of bytes that were written. If the flush is
+    /// [batched](writer::batched), the file is only put in place once the batch is done
+    fn write_file(
+        temp_path: &str,
+        serialize: impl FnOnce(&mut dyn Write) -> IoResult<()>,
+    ) -> StorageResult<u64> {
+        writer::write_file(temp_path, serialize)
+    }
+
+    /// Flush the keys of `table` that are protected from deletion to the file next to the
+    /// table's file at `tblpath`. If no keys are protected, any older file is removed
+    fn flush_protected(table: &Table, tblpath: &str) -> StorageResult<u64> {
+        match table.get_model_ref() {
+            DataModel::KV(kve) => {
+                let protected = kve.get_protected();
+                self::flush_sidecar(
+                    &concat_str!(tblpath, PROTECTED_SET_EXTENSION),
+                    protected.len() == 0,
+                    |file| super::interface::serialize_set_into_slow_buffer(file, protected),
+                )
+            }
+        }
+    }
+    /// Flush the expiry times of the keys of `table` to the file next to the table's file at
+    /// `tblpath`. If no key has an expiry time, any older file is removed
+    fn flush_expiries(table: &Table, tblpath: &str) -> StorageResult<u64> {
+        match table.get_model_ref() {
+            DataModel::KV(kve) => {
+                let expiries = kve.get_expiries();
+                self::flush_sidecar(
+                    &concat_str!(tblpath, EXPIRY_MAP_EXTENSION),
+                    expiries.len() == 0,
+                    |file| super::interface::serialize_expiries_into_slow_buffer(file, expiries),
+                )
+            }
+        }
+    }
+    /// Write the bloom filter of the keys that were just written to the table's file (which is
+    /// `file_size` bytes long) to `bloom_path`, along with the size of the table's file
+    fn flush_bloom_filter(
+        mut filter: BloomFilter,
+        file_size: u64,
+        bloom_path: &str,
+    ) -> StorageResult<u64> {
+        filter.set_file_size(file_size);
+        self::flush_sidecar(bloom_path, false, |file| {
+            file.write_all(&filter.serialize())
+        })
+    }
+    /// Write a file that goes along with a table's file to `path` with `serialize` (through a
+    /// temporary file, just like the table). If `empty` is set, any older file is removed instead.
+    /// This returns the number of bytes that were written
+    fn flush_sidecar(
+        path: &str,
+        empty: bool,
+        serialize: impl FnOnce(&mut dyn Write) -> IoResult<()>,
+    ) -> StorageResult<u64> {
+        if empty {
+            writer::remove_file(path).map(|()| 0)
+        } else {
+            self::write_file(&concat_str!(path, "_"), serialize)
+        }
+    }
+    /// No `partmap` handling. Just flushes the table to the expected location and returns what
+    /// was written, or `None` if the table didn't need to be written out
+    pub fn flush_table(
+        tableid: &ObjectID,
+        ksid: &ObjectID,
+        table: &Table,
+    ) -> StorageResult<Option<TableFlush>> {
+        // anything written while we're flushing stays dirty until the next flush
+        let dirty = table.dirty_bytes();
+        let written = routine_flushtable!(table, tbl_path!(ksid, tableid))?;
+        table.clear_dirty(dirty);
+        Ok(written.map(|bytes| TableFlush {
+            table: unsafe { concat_str!(ksid.as_str(), ":", tableid.as_str()) },
+            bytes,
+            dirty,
+        }))
+    }
+
+    /// Rewrite the file of a table from what the table holds in memory (just like a flush)
+    /// and return the size of the file before and after. Volatile tables don't have a file,
+    /// so nothing is done for them and `None` is returned. Since this is only done on request,
+    /// the directory of the keyspace is always synced
+    pub fn compact_table(
+        tableid: &ObjectID,
+        ksid: &ObjectID,
+        table: &Table,
+    ) -> StorageResult<Option<(u64, u64)>> {
+        if table.is_volatile() {
+            return Ok(None);
+        }
+        let temp_path = tbl_path!(ksid, tableid);
+        let path = &temp_path[..temp_path.len() - 1];
+        let before = self::file_len(path)?;
+        if let Err(e) = self::flush_table(tableid, ksid, table) {
+            // don't leave the half-written file behind
+            let _ = fs::remove_file(&temp_path);
+            return Err(e);
+        }
+        sync_dir(unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) })?;
+        Ok(Some((before, self::file_len(path)?)))
+    }
+
+    /// Returns the size of the file at `path`, or 0 if there's no such file
+    fn file_len(path: &str) -> StorageResult<u64> {
+        match fs::metadata(path) {
+            Ok(meta) => Ok(meta.len()),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(StorageError::io("stat", path)(e)),
+        }
     }
 
     /// Same as flush_table, except for it being built specifically for snapshots
@@ -144,47 +590,84 @@ pub mod oneshot {
         ksid: &ObjectID,
         tableid: &ObjectID,
         table: &Table,
-    ) -> IoResult<()> {
-        routine_flushtable!(table, snap_tbl_path!(snapid, ksid, tableid))
+    ) -> StorageResult<()> {
+        let path = snap_tbl_path!(snapid, ksid, tableid);
+        if !table.is_volatile() && !table.is_loaded() {
+            // the data was never read in, so copy what's on disk instead
+            return self::copy_unloaded_table(ksid, tableid, &path[..path.len() - 1]);
+        }
+        routine_flushtable!(table, path)?;
+        Ok(())
     }
 
-    /// Flushes an entire keyspace to the expected location. No `partmap` or `preload` handling
-    pub fn flush_keyspace(ksid: &ObjectID, keyspace: &Keyspace) -> IoResult<()> {
-        for table in keyspace.tables.iter() {
-            self::flush_table(table.key(), ksid, table.value())?;
+    /// Copy the file of a table (and the files that go along with it) from the data directory
+    /// to `dest`
+    fn copy_unloaded_table(ksid: &ObjectID, tableid: &ObjectID, dest: &str) -> StorageResult<()> {
+        let src = tbl_path!(ksid, tableid);
+        let src = &src[..src.len() - 1];
+        fs::copy(src, dest).map_err(StorageError::io("copy", src))?;
+        let extensions = [
+            PROTECTED_SET_EXTENSION,
+            EXPIRY_MAP_EXTENSION,
+            BLOOM_FILTER_EXTENSION,
+        ];
+        for extension in extensions.iter() {
+            let src = concat_str!(src, extension);
+            match fs::copy(&src, concat_str!(dest, extension)) {
+                Err(e) if e.kind() != ErrorKind::NotFound => {
+                    return Err(StorageError::io("copy", &src)(e))
+                }
+                _ => {}
+            }
         }
         Ok(())
     }
 
+    /// Flushes an entire keyspace to the expected location and returns what was written for
+    /// the tables that needed it. No `partmap` or `preload` handling
+    pub fn flush_keyspace(ksid: &ObjectID, keyspace: &Keyspace) -> StorageResult<Vec<TableFlush>> {
+        let mut flushed = Vec::new();
+        for table in keyspace.tables.iter() {
+            flushed.extend(self::flush_table(table.key(), ksid, table.value())?);
+        }
+        Ok(flushed)
+    }
+
     /// Flushes an entire keyspace to the expected location. No `partmap` or `preload` handling
-    pub fn snap_flush_keyspace(snapid: &str, ksid: &ObjectID, keyspace: &Keyspace) -> IoResult<()> {
+    pub fn snap_flush_keyspace(
+        snapid: &str,
+        ksid: &ObjectID,
+        keyspace: &Keyspace,
+    ) -> StorageResult<()> {
         for table in keyspace.tables.iter() {
-            self::snap_flush_table(snapid, table.key(), ksid, table.value())?;
+            self::snap_flush_table(snapid, ksid, table.key(), table.value())?;
         }
         Ok(())
     }
 
     macro_rules! routine_flushpartmap {
         ($path:expr, $keyspace:ident) => {{
-            let mut file = File::create(&$path)?;
-            super::interface::serialize_partmap_into_slow_buffer(&mut file, $keyspace)?;
-            file.sync_all()?;
-            fs::rename(&$path, &$path[..$path.len() - 1])?;
-            Ok(())
+            self::write_file(&$path, |file| {
+                super::interface::serialize_partmap_into_slow_buffer(file, $keyspace)
+            })
         }};
     }
 
-    /// Flushes a single partmap
-    pub fn flush_partmap(ksid: &ObjectID, keyspace: &Keyspace) -> IoResult<()> {
-        let path = unsafe { concat_str!(DIR_KSROOT, "/", ksid.as_str(), "/", "PARTMAP_") };
+    /// Flushes a single partmap and returns the number of bytes that were written
+    pub fn flush_partmap(ksid: &ObjectID, keyspace: &Keyspace) -> StorageResult<u64> {
+        let path = unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str(), "/", "PARTMAP_") };
         routine_flushpartmap!(path, keyspace)
     }
 
     /// Flushes a single partmap
-    pub fn snap_flush_partmap(snapid: &str, ksid: &ObjectID, keyspace: &Keyspace) -> IoResult<()> {
+    pub fn snap_flush_partmap(
+        snapid: &str,
+        ksid: &ObjectID,
+        keyspace: &Keyspace,
+    ) -> StorageResult<u64> {
         let path = unsafe {
             concat_str!(
-                DIR_SNAPROOT,
+                dir_snaproot(),
                 "/",
                 snapid,
                 "/",
@@ -197,24 +680,22 @@ pub mod oneshot {
     }
 
     macro_rules! routine_flushpreload {
-        ($store:expr, $preloadtmp:expr, $preloadfinal:expr) => {{
-            let mut file = File::create(&$preloadtmp)?;
-            super::interface::serialize_preload_into_slow_buffer(&mut file, $store)?;
-            file.sync_all()?;
-            fs::rename(&$preloadtmp, &$preloadfinal)?;
-            Ok(())
+        ($store:expr, $preloadtmp:expr) => {{
+            self::write_file(&$preloadtmp, |file| {
+                super::interface::serialize_preload_into_slow_buffer(file, $store)
+            })
         }};
     }
 
-    // Flush the `PRELOAD`
-    pub fn flush_preload(store: &Memstore) -> IoResult<()> {
-        routine_flushpreload!(store, PRELOAD_FILE_PATH_TEMP, PRELOAD_FILE_PATH)
+    // Flush the `PRELOAD` and return the number of bytes that were written
+    pub fn flush_preload(store: &Memstore) -> StorageResult<u64> {
+        let preload_tmp = concat_str!(dir_ksroot(), "/", "PRELOAD_");
+        routine_flushpreload!(store, preload_tmp)
     }
 
     /// Same as flush_preload, but for snapshots
-    pub fn snap_flush_preload(snapid: &str, store: &Memstore) -> IoResult<()> {
-        let preload_tmp = concat_str!(DIR_SNAPROOT, "/", snapid, "/", "PRELOAD_");
-        let preload = &preload_tmp[..preload_tmp.len() - 1];
-        routine_flushpreload!(store, preload_tmp, preload)
+    pub fn snap_flush_preload(snapid: &str, store: &Memstore) -> StorageResult<u64> {
+        let preload_tmp = concat_str!(dir_snaproot(), "/", snapid, "/", "PRELOAD_");
+        routine_flushpreload!(store, preload_tmp)
     }
 }
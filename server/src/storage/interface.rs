@@ -28,20 +28,235 @@
 
 use crate::corestore::htable::Coremap;
 use crate::corestore::htable::Data;
+use crate::corestore::lock::QuickLock;
 use crate::corestore::memstore::Keyspace;
 use crate::corestore::memstore::Memstore;
+use crate::diskstore::flock::FileLock;
 use crate::registry;
+use crate::storage::error::{StorageError, StorageResult};
 use crate::IoResult;
+#[cfg(test)]
+use std::cell::Cell;
 use std::collections::HashSet;
+use std::env;
 use std::fs;
-use std::io::{BufWriter, Write};
+use std::io::{BufWriter, Error as IoError, ErrorKind, Write};
+use std::path::{Path, PathBuf};
 
-pub const DIR_KSROOT: &str = "data/ks";
-pub const DIR_SNAPROOT: &str = "data/snaps";
-pub const DIR_BACKUPS: &str = "data/backups";
-pub const DIR_ROOT: &str = "data";
+/// The data directory that is used if none is configured. This is relative to the working
+/// directory that the server was started in
+pub const DEFAULT_DATA_DIR: &str = "data";
+/// The PID file, which locks the data directory. It is kept in the working directory if the
+/// default data directory is used (as it always was) and in the data directory otherwise
+pub const PID_FILE: &str = ".sky_pid";
 
-/// This creates the root directory structure:
+/// The directories that make up the data directory
+#[derive(Debug, Clone, Copy)]
+struct DataDirs {
+    root: &'static str,
+    ksroot: &'static str,
+    snaproot: &'static str,
+    backups: &'static str,
+    pid_file: &'static str,
+}
+
+impl DataDirs {
+    const DEFAULT: Self = Self {
+        root: "data",
+        ksroot: "data/ks",
+        snaproot: "data/snaps",
+        backups: "data/backups",
+        pid_file: PID_FILE,
+    };
+    fn new(root: &str, pid_file: String) -> Self {
+        // the data directory is only set once at startup so leaking these is fine, and saves
+        // everyone who builds a path from them an allocation
+        let leak = |path: String| -> &'static str { Box::leak(path.into_boxed_str()) };
+        Self {
+            root: leak(root.to_owned()),
+            ksroot: leak(format!("{}/ks", root)),
+            snaproot: leak(format!("{}/snaps", root)),
+            backups: leak(format!("{}/backups", root)),
+            pid_file: leak(pid_file),
+        }
+    }
+}
+
+static DATA_DIRS: QuickLock<DataDirs> = QuickLock::new(DataDirs::DEFAULT);
+
+#[cfg(test)]
+thread_local! {
+    /// The data directory that the calling thread uses instead of the global one, so that
+    /// tests can use their own without moving everyone else's files
+    static OVERRIDE: Cell<Option<DataDirs>> = Cell::new(None);
+    /// What the calling thread does instead of syncing a directory (see [`sync_dir`])
+    static SYNC_DIR_HOOK: Cell<Option<fn(&Path) -> IoResult<()>>> = Cell::new(None);
+}
+
+fn data_dirs() -> DataDirs {
+    #[cfg(test)]
+    {
+        if let Some(dirs) = OVERRIDE.with(|dirs| dirs.get()) {
+            return dirs;
+        }
+    }
+    *DATA_DIRS.lock()
+}
+
+/// Resolve `path` to an absolute path (against `cwd` if it is relative)
+pub(super) fn absolute_path(cwd: &Path, path: &str) -> IoResult<String> {
+    let path = Path::new(path);
+    let path = if path.is_absolute() {
+        path.to_owned()
+    } else {
+        cwd.join(path)
+    };
+    // this also drops any trailing separators
+    let path: PathBuf = path.components().collect();
+    path.into_os_string().into_string().map_err(|_| {
+        IoError::new(
+            ErrorKind::InvalidInput,
+            "the path to the data directory isn't valid UTF-8",
+        )
+    })
+}
+
+/// Use the configured data directory (or the default one, if none is configured). Relative
+/// paths are resolved against the current working directory, so this is called once at
+/// startup (before anything is read from the data directory); after that, nothing depends on
+/// the working directory. A configured data directory is created right away since the PID
+/// file is kept in it
+pub fn configure_data_dir(configured: Option<&str>) -> StorageResult<()> {
+    let dir = configured.unwrap_or(DEFAULT_DATA_DIR);
+    let cwd = env::current_dir().map_err(StorageError::io("resolve", dir))?;
+    let root = absolute_path(&cwd, dir).map_err(StorageError::io("resolve", dir))?;
+    let pid_file = match configured {
+        Some(_) => {
+            fs::create_dir_all(&root).map_err(StorageError::io("create the directory", &root))?;
+            concat_str!(&root, "/", PID_FILE)
+        }
+        None => absolute_path(&cwd, PID_FILE).map_err(StorageError::io("resolve", PID_FILE))?,
+    };
+    *DATA_DIRS.lock() = DataDirs::new(&root, pid_file);
+    Ok(())
+}
+
+#[cfg(test)]
+/// Make the calling thread use `dir` as the data directory (or stop doing so if `None`)
+pub fn override_data_dir(dir: Option<&str>) {
+    let dirs = dir.map(|dir| DataDirs::new(dir, concat_str!(dir, "/", PID_FILE)));
+    OVERRIDE.with(|override_dirs| override_dirs.set(dirs))
+}
+
+/// The data directory of a thread, which the threads that it spawns
+/// [`inherit`](Self::inherit) so that they use the same one (tests can each use their own)
+#[derive(Clone, Copy)]
+pub struct ThreadDataDir {
+    #[cfg(test)]
+    dirs: Option<DataDirs>,
+}
+
+impl ThreadDataDir {
+    /// The data directory of the calling thread
+    pub fn current() -> Self {
+        Self {
+            #[cfg(test)]
+            dirs: OVERRIDE.with(|dirs| dirs.get()),
+        }
+    }
+    /// Make the calling thread use this data directory
+    pub fn inherit(self) {
+        #[cfg(test)]
+        OVERRIDE.with(|dirs| dirs.set(self.dirs));
+    }
+}
+
+/// The data directory
+pub fn dir_root() -> &'static str {
+    data_dirs().root
+}
+
+/// The directory with a directory for every keyspace
+pub fn dir_ksroot() -> &'static str {
+    data_dirs().ksroot
+}
+
+/// The directory with the snapshots
+pub fn dir_snaproot() -> &'static str {
+    data_dirs().snaproot
+}
+
+/// The directory with the backups
+pub fn dir_backups() -> &'static str {
+    data_dirs().backups
+}
+
+/// The path to the PID file (see [`PID_FILE`])
+pub fn pid_file() -> &'static str {
+    data_dirs().pid_file
+}
+
+/// Lock the [PID file](pid_file) so that no other server can use the data directory
+pub fn lock_pid_file() -> StorageResult<FileLock> {
+    let path = pid_file();
+    FileLock::lock(path).map_err(|e| {
+        #[cfg(windows)]
+        let held = e.raw_os_error() == Some(winapi::shared::winerror::ERROR_LOCK_VIOLATION as i32);
+        #[cfg(not(windows))]
+        let held = e.kind() == ErrorKind::WouldBlock;
+        if held {
+            StorageError::LockHeld { file: path.into() }
+        } else {
+            StorageError::io("lock", path)(e)
+        }
+    })
+}
+
+/// Sync the directory at `path`, so that the files that were just created in it, renamed into
+/// it or removed from it stay that way if the machine goes down. Syncing a file doesn't do that
+/// for the entry of the file in its directory, so everything that renames a file into place
+/// has to call this once it's done. There's no way of syncing a directory on Windows (NTFS
+/// journals its directories anyway), so this does nothing there
+pub fn sync_dir(path: impl AsRef<Path>) -> StorageResult<()> {
+    let path = path.as_ref();
+    #[cfg(test)]
+    {
+        if let Some(hook) = SYNC_DIR_HOOK.with(|hook| hook.get()) {
+            return hook(path).map_err(StorageError::io("sync the directory", path));
+        }
+    }
+    #[cfg(unix)]
+    {
+        fs::File::open(path)
+            .and_then(|dir| dir.sync_all())
+            .map_err(StorageError::io("sync the directory", path))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+/// Make [`sync_dir`] call `hook` on the calling thread instead of syncing the directory (or
+/// sync it again if `None`)
+pub fn override_sync_dir(hook: Option<fn(&Path) -> IoResult<()>>) {
+    SYNC_DIR_HOOK.with(|cell| cell.set(hook))
+}
+
+/// The protected keys of a table are stored in `<table>.protected`
+pub const PROTECTED_SET_EXTENSION: &str = ".protected";
+/// The expiry times of the keys of a table are stored in `<table>.expiry`
+pub const EXPIRY_MAP_EXTENSION: &str = ".expiry";
+/// The [bloom filter](crate::storage::bloom) of the keys of a table is stored in `<table>.bloom`
+pub const BLOOM_FILTER_EXTENSION: &str = ".bloom";
+/// The file of a table that is being dropped is renamed to `<table>.dropped` (and the directory
+/// of a keyspace to `<keyspace>.dropped`) until the `PARTMAP` (or the `PRELOAD`) no longer
+/// has it. Tables and keyspaces with such a tombstone are skipped when the data is read in
+pub const DROPPED_EXTENSION: &str = ".dropped";
+
+/// This creates the root directory structure (in the [data directory](dir_root)):
 /// ```
 /// data/
 ///     ks/
@@ -53,20 +268,20 @@ pub const DIR_ROOT: &str = "data";
 /// ```
 ///
 /// If any directories exist, they are simply ignored
-pub fn create_tree(memroot: &Memstore) -> IoResult<()> {
-    try_dir_ignore_existing!(DIR_ROOT, DIR_KSROOT, DIR_BACKUPS, DIR_SNAPROOT);
+pub fn create_tree(memroot: &Memstore) -> StorageResult<()> {
+    try_dir_ignore_existing!(dir_root(), dir_ksroot(), dir_backups(), dir_snaproot());
     for ks in memroot.keyspaces.iter() {
         unsafe {
-            try_dir_ignore_existing!(concat_path!(DIR_KSROOT, ks.key().as_str()))?;
+            try_dir_ignore_existing!(concat_path!(dir_ksroot(), ks.key().as_str()))?;
         }
     }
     Ok(())
 }
 
-pub fn snap_create_tree(snapid: &str, memroot: &Memstore) -> IoResult<()> {
+pub fn snap_create_tree(snapid: &str, memroot: &Memstore) -> StorageResult<()> {
     for ks in memroot.keyspaces.iter() {
         unsafe {
-            try_dir_ignore_existing!(concat_path!(DIR_SNAPROOT, snapid, ks.key().as_str()))?;
+            try_dir_ignore_existing!(concat_path!(dir_snaproot(), snapid, ks.key().as_str()))?;
         }
     }
     Ok(())
@@ -76,11 +291,11 @@ pub fn snap_create_tree(snapid: &str, memroot: &Memstore) -> IoResult<()> {
 ///
 /// **Warning**: Calling this is quite inefficient so consider calling it once or twice
 /// throughout the lifecycle of the server
-pub fn cleanup_tree(memroot: &Memstore) -> IoResult<()> {
+pub fn cleanup_tree(memroot: &Memstore) -> StorageResult<()> {
     if registry::get_preload_tripswitch().is_tripped() {
         // only run a cleanup if someone tripped the switch
         // hashset because the fs itself will not allow duplicate entries
-        let dir_keyspaces: HashSet<String> = read_dir_to_col!(DIR_KSROOT);
+        let dir_keyspaces: HashSet<String> = read_dir_to_col!(dir_ksroot());
         let our_keyspaces: HashSet<String> = memroot
             .keyspaces
             .iter()
@@ -89,13 +304,13 @@ pub fn cleanup_tree(memroot: &Memstore) -> IoResult<()> {
         // these are the folders that we need to remove; plonk the deleted keyspaces first
         for folder in dir_keyspaces.difference(&our_keyspaces) {
             if folder != "PRELOAD" {
-                let ks_path = concat_str!(DIR_KSROOT, "/", folder);
-                fs::remove_dir_all(ks_path)?;
+                let ks_path = concat_str!(dir_ksroot(), "/", folder);
+                fs::remove_dir_all(&ks_path).map_err(StorageError::io("remove", &ks_path))?;
             }
         }
         // now plonk the data files
         for keyspace in memroot.keyspaces.iter() {
-            let ks_path = unsafe { concat_str!(DIR_KSROOT, "/", keyspace.key().as_str()) };
+            let ks_path = unsafe { concat_str!(dir_ksroot(), "/", keyspace.key().as_str()) };
             let dir_tbls: HashSet<String> = read_dir_to_col!(&ks_path);
             let our_tbls: HashSet<String> = keyspace
                 .value()
@@ -104,9 +319,26 @@ pub fn cleanup_tree(memroot: &Memstore) -> IoResult<()> {
                 .map(|v| unsafe { v.key().as_str() }.to_owned())
                 .collect();
             for old_file in dir_tbls.difference(&our_tbls) {
-                if old_file != "PARTMAP" {
+                // the protected keys of a table that we still have
+                let is_protected_set = old_file
+                    .strip_suffix(PROTECTED_SET_EXTENSION)
+                    .map(|tbl| our_tbls.contains(tbl))
+                    .unwrap_or(false);
+                // and the expiry times of one
+                let is_expiry_map = old_file
+                    .strip_suffix(EXPIRY_MAP_EXTENSION)
+                    .map(|tbl| our_tbls.contains(tbl))
+                    .unwrap_or(false);
+                // and the bloom filter of one
+                let is_bloom_filter = old_file
+                    .strip_suffix(BLOOM_FILTER_EXTENSION)
+                    .map(|tbl| our_tbls.contains(tbl))
+                    .unwrap_or(false);
+                if old_file != "PARTMAP" && !is_protected_set && !is_expiry_map && !is_bloom_filter
+                {
                     // plonk this data file; we don't need it anymore
-                    fs::remove_file(concat_path!(&ks_path, old_file))?;
+                    let old_path = concat_path!(&ks_path, old_file);
+                    fs::remove_file(&old_path).map_err(StorageError::io("remove", &old_path))?;
                 }
             }
         }
@@ -117,7 +349,7 @@ pub fn cleanup_tree(memroot: &Memstore) -> IoResult<()> {
 /// Uses a buffered writer under the hood to improve write performance as the provided
 /// writable interface might be very slow. The buffer does flush once done, however, it
 /// is important that you fsync yourself!
-pub fn serialize_map_into_slow_buffer<T: Write>(
+pub fn serialize_map_into_slow_buffer<T: Write + ?Sized>(
     buffer: &mut T,
     map: &Coremap<Data, Data>,
 ) -> IoResult<()> {
@@ -127,14 +359,54 @@ pub fn serialize_map_into_slow_buffer<T: Write>(
     Ok(())
 }
 
-pub fn serialize_partmap_into_slow_buffer<T: Write>(buffer: &mut T, ks: &Keyspace) -> IoResult<()> {
+/// Same as [`serialize_map_into_slow_buffer`], except that `on_key` is called with every key
+/// that is written, and that every record is followed by its checksum if `checksums` is set
+pub fn serialize_map_into_slow_buffer_with<T: Write + ?Sized>(
+    buffer: &mut T,
+    map: &Coremap<Data, Data>,
+    checksums: bool,
+    on_key: impl FnMut(&[u8]),
+) -> IoResult<()> {
+    let mut buffer = BufWriter::new(buffer);
+    super::se::raw_serialize_map_with(map, &mut buffer, checksums, on_key)?;
+    buffer.flush()?;
+    Ok(())
+}
+
+/// Same as [`serialize_map_into_slow_buffer`], except that only the keys of the map are
+/// serialized (like a set)
+pub fn serialize_set_into_slow_buffer<T: Write + ?Sized, V>(
+    buffer: &mut T,
+    set: &Coremap<Data, V>,
+) -> IoResult<()> {
+    let mut buffer = BufWriter::new(buffer);
+    super::se::raw_serialize_set(set, &mut buffer)?;
+    buffer.flush()?;
+    Ok(())
+}
+
+/// Same as [`serialize_map_into_slow_buffer`], except that the map holds expiry times
+pub fn serialize_expiries_into_slow_buffer<T: Write + ?Sized>(
+    buffer: &mut T,
+    map: &Coremap<Data, i64>,
+) -> IoResult<()> {
+    let mut buffer = BufWriter::new(buffer);
+    super::se::raw_serialize_expiries(map, &mut buffer)?;
+    buffer.flush()?;
+    Ok(())
+}
+
+pub fn serialize_partmap_into_slow_buffer<T: Write + ?Sized>(
+    buffer: &mut T,
+    ks: &Keyspace,
+) -> IoResult<()> {
     let mut buffer = BufWriter::new(buffer);
     super::se::raw_serialize_partmap(&mut buffer, ks)?;
     buffer.flush()?;
     Ok(())
 }
 
-pub fn serialize_preload_into_slow_buffer<T: Write>(
+pub fn serialize_preload_into_slow_buffer<T: Write + ?Sized>(
     buffer: &mut T,
     store: &Memstore,
 ) -> IoResult<()> {
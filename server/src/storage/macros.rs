@@ -76,11 +76,15 @@ macro_rules! to_64bit_little_endian {
 
 macro_rules! try_dir_ignore_existing {
     ($dir:expr) => {{
-        match std::fs::create_dir_all($dir) {
+        let dir = $dir;
+        match std::fs::create_dir_all(&dir) {
             Ok(_) => Ok(()),
             Err(e) => match e.kind() {
                 std::io::ErrorKind::AlreadyExists => Ok(()),
-                _ => Err(e),
+                _ => Err($crate::storage::error::StorageError::io(
+                    "create the directory",
+                    &dir,
+                )(e)),
             },
         }
     }};
@@ -107,16 +111,10 @@ macro_rules! concat_str {
     }}};
 }
 
-#[macro_export]
-macro_rules! bad_data {
-    () => {
-        std::io::Error::from(std::io::ErrorKind::InvalidData)
-    };
-}
-
 macro_rules! read_dir_to_col {
     ($root:expr) => {
-        std::fs::read_dir($root)?
+        std::fs::read_dir($root)
+            .map_err($crate::storage::error::StorageError::io("list", $root))?
             .map(|v| {
                 v.expect("Unexpected directory parse failure")
                     .file_name()
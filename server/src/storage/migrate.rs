@@ -0,0 +1,340 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Migrating the data directory
+//!
+//! `skyd migrate-data --to <profile>` rewrites the file of every table in the data directory
+//! in the format of a storage profile: `plain` (the records on their own) or `checksummed` (every
+//! record followed by its checksum, as written with `paranoid` set). The server reads both
+//! formats whatever its configuration says (the format of a file is told by its header), so
+//! the directory can be loaded at any point of a migration: a table is converted into a
+//! temporary file that is synced and then renamed over the old one, just like a flush does.
+//!
+//! The tables are converted one at a time, and every table that is done is appended to the
+//! [migration journal](MIGRATION_JOURNAL) (and synced) right away. If the migration fails or
+//! is interrupted, running it again with the same profile skips the tables in the journal; a
+//! migration to another profile starts over. The journal is removed once every table is done.
+//!
+//! Only the data directory is migrated; snapshots keep the format that they were taken in. Set
+//! `paranoid` in the configuration to match the profile, or the next flush writes the tables
+//! in the old format again
+
+use super::bloom::BloomFilter;
+use super::error::{StorageError, StorageResult};
+use super::interface::{self, dir_ksroot, dir_root, BLOOM_FILTER_EXTENSION, DROPPED_EXTENSION};
+use super::writer;
+use crate::config::ConfigError;
+use crate::corestore::memstore::{self, ObjectID};
+use crate::registry;
+use clap::ArgMatches;
+use core::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+
+/// The journal of the tables that a migration has converted so far. It's in the data
+/// directory, and holds the profile on the first line and a `<keyspace>:<table>` line for
+/// every table that is done
+pub const MIGRATION_JOURNAL: &str = "MIGRATION";
+/// What the first line of the journal starts with
+const JOURNAL_MARK: &str = "skyd-migration";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+/// The format that a migration converts the tables to
+pub enum Profile {
+    /// The records on their own
+    Plain,
+    /// Every record followed by its checksum
+    Checksummed,
+}
+
+impl Profile {
+    /// Returns the name of the profile, as passed to `--to`
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Plain => "plain",
+            Self::Checksummed => "checksummed",
+        }
+    }
+    const fn has_checksums(&self) -> bool {
+        matches!(self, Self::Checksummed)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+/// The options passed to `skyd migrate-data`
+pub struct MigrateOpts {
+    /// The profile to convert the tables to
+    pub to: Profile,
+}
+
+impl MigrateOpts {
+    /// Get the options from the matches of the `migrate-data` subcommand
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, ConfigError> {
+        let to = match matches.value_of("to") {
+            Some("plain") => Profile::Plain,
+            Some("checksummed") => Profile::Checksummed,
+            Some("encrypted") => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--to`. There's no at-rest encryption in this version, so the profile `encrypted` can't be used yet",
+                ))
+            }
+            _ => {
+                return Err(ConfigError::CliArgErr(
+                    "Invalid value for `--to`. Expected `plain` or `checksummed`",
+                ))
+            }
+        };
+        Ok(Self { to })
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+/// What a migration did
+pub struct Report {
+    /// the tables that were converted by this run
+    pub converted: usize,
+    /// the tables that an earlier (interrupted) run had already converted
+    pub skipped: usize,
+    /// the tables that don't have a file (the volatile ones, for instance)
+    pub without_file: usize,
+    /// the size of the converted files, before and after
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "converted {} table(s) ({} bytes to {} bytes), skipped {} table(s) converted by an earlier run and {} table(s) without a file",
+            self.converted, self.bytes_before, self.bytes_after, self.skipped, self.without_file
+        )
+    }
+}
+
+/// The tables that a migration has converted so far
+struct Journal {
+    path: PathBuf,
+    file: File,
+    done: Vec<String>,
+}
+
+impl Journal {
+    /// Open the journal for a migration to `to`, reading what was done by an earlier run to
+    /// the same profile. A journal for another profile is started over
+    fn open(to: Profile) -> StorageResult<Self> {
+        let path = concat_path!(dir_root(), MIGRATION_JOURNAL);
+        let header = concat_str!(JOURNAL_MARK, " ", to.name());
+        let done = match fs::read_to_string(&path) {
+            Ok(journal) => {
+                let mut lines = journal.lines();
+                if lines.next() == Some(header.as_str()) {
+                    lines.map(|line| line.to_owned()).collect()
+                } else {
+                    log::warn!(
+                        "Starting over, since the interrupted migration wasn't to `{}`",
+                        to.name()
+                    );
+                    Vec::new()
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(StorageError::io("read", &path)(e)),
+        };
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(StorageError::io("open", &path))?;
+        if done.is_empty() {
+            file.set_len(0)
+                .and_then(|()| writeln!(file, "{}", header))
+                .and_then(|()| file.sync_all())
+                .map_err(StorageError::io("write", &path))?;
+            interface::sync_dir(dir_root())?;
+        }
+        Ok(Self { path, file, done })
+    }
+    fn is_done(&self, table: &str) -> bool {
+        self.done.iter().any(|done| done == table)
+    }
+    /// Record that `table` is done
+    fn finished(&mut self, table: String) -> StorageResult<()> {
+        writeln!(self.file, "{}", table)
+            .and_then(|()| self.file.sync_all())
+            .map_err(StorageError::io("write", &self.path))?;
+        self.done.push(table);
+        Ok(())
+    }
+    /// Remove the journal, once every table is done
+    fn remove(self) -> StorageResult<()> {
+        drop(self.file);
+        fs::remove_file(&self.path).map_err(StorageError::io("remove", &self.path))?;
+        interface::sync_dir(dir_root())
+    }
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The number of tables after which the migrations on the calling thread fail, to test
+    /// interrupted migrations
+    static INTERRUPT_AFTER: std::cell::Cell<Option<usize>> = std::cell::Cell::new(None);
+}
+
+#[cfg(test)]
+/// Make the migrations on the calling thread fail once they've converted `after` tables (or
+/// not, if `None`)
+pub fn override_interrupt(after: Option<usize>) {
+    INTERRUPT_AFTER.with(|cell| cell.set(after))
+}
+
+/// Convert every table in the data directory to `to`, skipping the tables that an interrupted
+/// migration to the same profile already converted. The progress is logged for every table.
+/// This must only be run while we hold the lock on the data directory, and before anything
+/// is loaded
+pub fn migrate(to: Profile) -> StorageResult<Report> {
+    let mut journal = Journal::open(to)?;
+    let mut report = Report::default();
+    let preload = super::unflush::read_preload()?;
+    super::names::prepare(&preload)?;
+    let mut keyspaces: Vec<ObjectID> = preload.iter().map(memstore::key_of).collect();
+    keyspaces.sort();
+    let mut tables = Vec::new();
+    for ksid in keyspaces {
+        let mut in_keyspace: Vec<ObjectID> = super::unflush::read_partmap(&ksid)?
+            .keys()
+            .map(memstore::key_of)
+            .collect();
+        in_keyspace.sort();
+        tables.extend(in_keyspace.into_iter().map(|tblid| (ksid.clone(), tblid)));
+    }
+    let total = tables.len();
+    for (i, (ksid, tblid)) in tables.into_iter().enumerate() {
+        let name = unsafe { concat_str!(ksid.as_str(), ":", tblid.as_str()) };
+        if journal.is_done(&name) {
+            report.skipped += 1;
+            log::info!(
+                "[{}/{}] Skipping `{}`, which is already converted",
+                i + 1,
+                total,
+                name
+            );
+            continue;
+        }
+        #[cfg(test)]
+        {
+            if INTERRUPT_AFTER.with(|after| after.get()) == Some(report.converted) {
+                return Err(StorageError::io("convert", &name)(std::io::Error::new(
+                    ErrorKind::Other,
+                    "interrupted",
+                )));
+            }
+        }
+        match self::convert_table(&ksid, &tblid, to)? {
+            Some((before, after)) => {
+                report.converted += 1;
+                report.bytes_before += before;
+                report.bytes_after += after;
+                log::info!(
+                    "[{}/{}] Converted `{}` ({} bytes to {} bytes)",
+                    i + 1,
+                    total,
+                    name,
+                    before,
+                    after
+                );
+            }
+            None => {
+                report.without_file += 1;
+                log::info!(
+                    "[{}/{}] Skipping `{}`, which has no file",
+                    i + 1,
+                    total,
+                    name
+                );
+            }
+        }
+        journal.finished(name)?;
+    }
+    journal.remove()?;
+    Ok(report)
+}
+
+/// Convert the file of a table to `to`, returning its size before and after (or `None` if the
+/// table has no file). The bloom filter of the table is removed before the file is replaced
+/// and written again afterwards, since it records the size of the file
+fn convert_table(
+    ksid: &ObjectID,
+    tblid: &ObjectID,
+    to: Profile,
+) -> StorageResult<Option<(u64, u64)>> {
+    let ks_path = unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) };
+    let path = unsafe { concat_str!(&ks_path, "/", tblid.as_str()) };
+    if fs::metadata(concat_str!(&path, DROPPED_EXTENSION)).is_ok() {
+        // the table was being dropped, and is cleaned up when the directory is loaded
+        return Ok(None);
+    }
+    let data = match fs::read(&path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(StorageError::io("read", &path)(e)),
+    };
+    let before = data.len() as u64;
+    match super::checksummed_map_version(&data) {
+        Some(found) if found != super::CHECKSUMMED_MAP_VERSION => {
+            return Err(StorageError::VersionMismatch {
+                file: path.into(),
+                found,
+                expected: super::CHECKSUMMED_MAP_VERSION,
+            })
+        }
+        _ => {}
+    }
+    let (map, bad_records) = super::de::deserialize_map_verified(data, true)
+        .ok_or_else(|| StorageError::corrupted(&path, "the pairs couldn't be decoded"))?;
+    if !bad_records.is_empty() {
+        // converting the file would make the bad records look good
+        return Err(StorageError::ChecksumMismatch {
+            file: path.into(),
+            records: bad_records,
+        });
+    }
+    let bloom_path = concat_str!(&path, BLOOM_FILTER_EXTENSION);
+    writer::remove_file(&bloom_path)?;
+    let mut filter = BloomFilter::with_capacity(map.len(), registry::get_bloom_fp_rate());
+    let after = writer::write_file(&concat_str!(&path, "_"), |file| {
+        interface::serialize_map_into_slow_buffer_with(file, &map, to.has_checksums(), |key| {
+            filter.insert(key)
+        })
+    })?;
+    filter.set_file_size(after);
+    writer::write_file(&concat_str!(&bloom_path, "_"), |file| {
+        file.write_all(&filter.serialize())
+    })?;
+    interface::sync_dir(&ks_path)?;
+    Ok(Some((before, after)))
+}
@@ -42,6 +42,15 @@
 //! All sizes are stored in little endian. How everything else is stored is not worth
 //! discussing here. Byte swaps just need one instruction on most architectures
 //!
+//! ## Checksums
+//!
+//! In paranoid mode (see [`ParanoidMode`](crate::config::ParanoidMode)), the file of a table
+//! starts with an 8B header (`SKYCSUM` followed by the version of the format) and every record is
+//! followed by the CRC32-C (4B, little endian) of its key and value. Files without the header
+//! are read as before, since their first 8 bytes are the number of records, and no table ever
+//! has anywhere near that many. That's 4 bytes more per record (and 8 per file); the
+//! checksums themselves are computed with the CRC32 instruction of the CPU where there is one
+//!
 //! ## Safety
 //!
 //! > Trust me, all methods are bombingly unsafe. They do such crazy things that you might not
@@ -51,6 +60,7 @@
 use crate::corestore::array::Array;
 use crate::corestore::htable::Coremap;
 use crate::corestore::Data;
+use crate::storage::error::BadRecord;
 use core::hash::Hash;
 use core::mem;
 use core::ptr;
@@ -61,11 +71,22 @@ use std::io::Write;
 #[macro_use]
 mod macros;
 // endof do not mess
+pub mod attach;
+pub mod bloom;
 pub mod bytemarks;
+pub mod chain;
+pub mod error;
 pub mod flush;
 pub mod interface;
+pub mod migrate;
+pub mod names;
 pub mod preload;
+pub mod reconcile;
+pub mod restore;
 pub mod unflush;
+#[cfg(all(feature = "uring", target_os = "linux"))]
+mod uring;
+pub mod writer;
 // test
 #[cfg(test)]
 mod tests;
@@ -169,6 +190,36 @@ mod tests;
     Do not down cast before swapping bytes
 */
 
+/// What the header of a file with checksums starts with. It's followed by the version of the
+/// format (see [`CHECKSUMMED_MAP_VERSION`])
+const CHECKSUMMED_MAP_MARK: &[u8; 7] = b"SKYCSUM";
+/// The version of the format of the files with checksums
+pub const CHECKSUMMED_MAP_VERSION: u8 = 1;
+/// The length of the header of a file with checksums
+const CHECKSUMMED_MAP_HEADER_LEN: usize = CHECKSUMMED_MAP_MARK.len() + 1;
+
+/// The CRC32-C of `data`. Everything that we checksum (the records of the files with
+/// checksums, the bloom filters, `DUMPKEY` blobs and the manifests of shipped snapshots) is
+/// checksummed with this
+pub fn crc32c(data: &[u8]) -> u32 {
+    crc32c::crc32c(data)
+}
+
+/// The checksum of a record: the CRC32-C of its key and then its value
+fn record_checksum(key: &[u8], value: &[u8]) -> u32 {
+    crc32c::crc32c_append(self::crc32c(key), value)
+}
+
+/// Get the version of the format with checksums that `data` was written with, or `None` if
+/// it was written without checksums
+pub fn checksummed_map_version(data: &[u8]) -> Option<u8> {
+    if data.len() >= CHECKSUMMED_MAP_HEADER_LEN && data.starts_with(CHECKSUMMED_MAP_MARK) {
+        Some(data[CHECKSUMMED_MAP_MARK.len()])
+    } else {
+        None
+    }
+}
+
 /// Get the raw bytes of anything.
 ///
 /// DISCLAIMER: THIS FUNCTION CAN DO TERRIBLE THINGS
@@ -181,7 +232,7 @@ unsafe fn raw_byte_repr<'a, T: 'a>(len: &'a T) -> &'a [u8] {
 
 mod se {
     use super::*;
-    use crate::corestore::memstore::Keyspace;
+    use crate::corestore::memstore::{Keyspace, DEFAULT};
     #[cfg(test)]
     /// Serialize a map into a _writable_ thing
     pub fn serialize_map(map: &Coremap<Data, Data>) -> Result<Vec<u8>, std::io::Error> {
@@ -198,16 +249,40 @@ mod se {
     pub fn raw_serialize_map<W: Write>(
         map: &Coremap<Data, Data>,
         w: &mut W,
+    ) -> std::io::Result<()> {
+        self::raw_serialize_map_with(map, w, false, |_| {})
+    }
+
+    /// Same as [`raw_serialize_map`], except that `on_key` is called with every key that is
+    /// written (the map may change while it is serialized, so this is the only way to know
+    /// exactly which keys made it into the file). If `checksums` is set, every record is
+    /// followed by its checksum:
+    /// ```text
+    /// [7B: "SKYCSUM"][1B: VERSION][LEN:8B]([KLEN:8B][VLEN:8B][K][V][CRC32-C:4B])*
+    /// ```
+    pub fn raw_serialize_map_with<W: Write>(
+        map: &Coremap<Data, Data>,
+        w: &mut W,
+        checksums: bool,
+        mut on_key: impl FnMut(&[u8]),
     ) -> std::io::Result<()> {
         unsafe {
+            if checksums {
+                w.write_all(CHECKSUMMED_MAP_MARK)?;
+                w.write_all(&[CHECKSUMMED_MAP_VERSION])?;
+            }
             w.write_all(raw_byte_repr(&to_64bit_little_endian!(map.len())))?;
             // now the keys and values
             for kv in map.iter() {
                 let (k, v) = (kv.key(), kv.value());
+                on_key(k);
                 w.write_all(raw_byte_repr(&to_64bit_little_endian!(k.len())))?;
                 w.write_all(raw_byte_repr(&to_64bit_little_endian!(v.len())))?;
                 w.write_all(k)?;
                 w.write_all(v)?;
+                if checksums {
+                    w.write_all(&record_checksum(k, v).to_le_bytes())?;
+                }
             }
         }
         Ok(())
@@ -231,25 +306,72 @@ mod se {
         Ok(())
     }
 
+    /// Serialize the expiry times of a table and write it to a provided buffer. This uses the
+    /// map format, with every value being the 8B (little endian) time
+    pub fn raw_serialize_expiries<W: Write>(
+        map: &Coremap<Data, i64>,
+        w: &mut W,
+    ) -> std::io::Result<()> {
+        unsafe {
+            w.write_all(raw_byte_repr(&to_64bit_little_endian!(map.len())))?;
+            for kv in map.iter() {
+                let (k, at) = (kv.key(), kv.value());
+                w.write_all(raw_byte_repr(&to_64bit_little_endian!(k.len())))?;
+                w.write_all(raw_byte_repr(&to_64bit_little_endian!(
+                    mem::size_of::<i64>()
+                )))?;
+                w.write_all(k)?;
+                w.write_all(&at.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
     /// Generate a partition map for the given keyspace
     /// ```text
-    /// [8B: EXTENT]([8B: LEN][?B: PARTITION ID][1B: Storage type][1B: Model type])*
+    /// [8B: EXTENT]([8B: LEN][?B: PARTITION ID][1B: Storage type][1B: Model type][8B: VALUE LIMIT]?[8B: LAST WRITE][8B: LAST READ]?)*
     /// ```
+    /// where the value limit is only there if the storage type has
+    /// [`bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT`] set and the times of the latest write
+    /// and read only if it has [`bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY`] set. The storage
+    /// type of the default table has [`bytemarks::BYTEMARK_STORAGE_FLAG_DEFAULT`] set, unless
+    /// it is `default`, and the storage type of every table of an archived keyspace has
+    /// [`bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED`] set
     pub fn raw_serialize_partmap<W: Write>(w: &mut W, keyspace: &Keyspace) -> std::io::Result<()> {
+        let default_table = keyspace.default_table();
         unsafe {
             // extent
             w.write_all(raw_byte_repr(&to_64bit_little_endian!(keyspace
                 .tables
                 .len())))?;
             for table in keyspace.tables.iter() {
+                // the table is recorded under its name as it was created
+                let name = keyspace.table_name(table.key());
                 // partition ID len
-                w.write_all(raw_byte_repr(&to_64bit_little_endian!(table.key().len())))?;
+                w.write_all(raw_byte_repr(&to_64bit_little_endian!(name.len())))?;
                 // parition ID
-                w.write_all(table.key())?;
+                w.write_all(&name)?;
                 // now storage type
-                w.write_all(raw_byte_repr(&table.storage_type()))?;
+                let mut storage_type = table.storage_type();
+                if table.key().eq(&default_table) && !default_table.eq(&DEFAULT) {
+                    storage_type |= bytemarks::BYTEMARK_STORAGE_FLAG_DEFAULT;
+                }
+                if keyspace.is_archived() {
+                    storage_type |= bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED;
+                }
+                w.write_all(raw_byte_repr(&storage_type))?;
                 // now model type
                 w.write_all(raw_byte_repr(&table.get_model_code()))?;
+                // and the value limit, if the table has its own
+                if let Some(limit) = table.own_value_limit() {
+                    w.write_all(&limit.to_le_bytes())?;
+                }
+                // and when it was last written to and read from, if it ever was
+                if storage_type & bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY != 0 {
+                    let activity = table.activity();
+                    w.write_all(&activity.last_write().unwrap_or(0).to_le_bytes())?;
+                    w.write_all(&activity.last_read().unwrap_or(0).to_le_bytes())?;
+                }
             }
         }
         Ok(())
@@ -274,6 +396,15 @@ mod de {
         }
     }
 
+    impl DeserializeFrom for Data {
+        fn is_expected_len(_clen: usize) -> bool {
+            true
+        }
+        fn from_slice(slice: &[u8]) -> Self {
+            Data::copy_from_slice(slice)
+        }
+    }
+
     /// Deserialize a set to a custom type
     pub fn deserialize_set_ctype<T>(data: &[u8]) -> Option<HashSet<T>>
     where
@@ -327,8 +458,13 @@ mod de {
         }
     }
 
-    /// Deserializes a map-like set which has an 2x1B _bytemark_ for every entry
-    pub fn deserialize_set_ctype_bytemark<T>(data: &[u8]) -> Option<HashMap<T, (u8, u8)>>
+    /// Deserializes a map-like set which has an 2x1B _bytemark_ for every entry. If the first
+    /// bytemark has [`bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT`] set, the bytemarks are
+    /// followed by an 8B value limit and if it has [`bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY`]
+    /// set, by the 8B times of the latest write and read
+    pub fn deserialize_set_ctype_bytemark<T>(
+        data: &[u8],
+    ) -> Option<HashMap<T, (u8, u8, Option<u64>, Option<(u64, u64)>)>>
     where
         T: DeserializeFrom + Eq + Hash,
     {
@@ -368,8 +504,38 @@ mod de {
                     ptr = ptr.add(1);
                     let bytemark_b = ptr::read(ptr);
                     ptr = ptr.add(1);
+                    let value_limit =
+                        if bytemark_a & bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT != 0 {
+                            if ptr.add(8) > end_ptr {
+                                return None;
+                            }
+                            let mut limit = [0u8; 8];
+                            limit.copy_from_slice(slice::from_raw_parts(ptr, 8));
+                            ptr = ptr.add(8);
+                            Some(u64::from_le_bytes(limit))
+                        } else {
+                            None
+                        };
+                    let activity = if bytemark_a & bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY != 0 {
+                        if ptr.add(16) > end_ptr {
+                            return None;
+                        }
+                        let (mut last_write, mut last_read) = ([0u8; 8], [0u8; 8]);
+                        last_write.copy_from_slice(slice::from_raw_parts(ptr, 8));
+                        last_read.copy_from_slice(slice::from_raw_parts(ptr.add(8), 8));
+                        ptr = ptr.add(16);
+                        Some((
+                            u64::from_le_bytes(last_write),
+                            u64::from_le_bytes(last_read),
+                        ))
+                    } else {
+                        None
+                    };
                     // push it in
-                    if set.insert(key, (bytemark_a, bytemark_b)).is_some() {
+                    if set
+                        .insert(key, (bytemark_a, bytemark_b, value_limit, activity))
+                        .is_some()
+                    {
                         // repeat?; that's not what we wanted
                         return None;
                     }
@@ -383,10 +549,30 @@ mod de {
             }
         }
     }
-    /// Deserialize a file that contains a serialized map. This also returns the model code
+    /// Deserialize a file that contains a serialized map. If the records have checksums,
+    /// they aren't verified
     pub fn deserialize_map(data: Vec<u8>) -> Option<Coremap<Data, Data>> {
+        self::deserialize_map_verified(data, false).map(|(map, _)| map)
+    }
+
+    /// Same as [`deserialize_map`], except that if `verify` is set and the records have
+    /// checksums, the records that don't match theirs are returned as well (they're still in
+    /// the map)
+    pub fn deserialize_map_verified(
+        data: Vec<u8>,
+        verify: bool,
+    ) -> Option<(Coremap<Data, Data>, Vec<BadRecord>)> {
+        let (start, checksummed) = match checksummed_map_version(&data) {
+            Some(CHECKSUMMED_MAP_VERSION) => (CHECKSUMMED_MAP_HEADER_LEN, true),
+            // a version that we don't know of
+            Some(_) => return None,
+            None => (0, false),
+        };
+        // the checksum after every record
+        let trailer = if checksummed { 4 } else { 0 };
+        let mut bad_records = Vec::new();
         // First read the length header
-        if data.len() < 8 {
+        if data.len() < start + 8 {
             // so the file doesn't even have the length/model header? noice, just return
             None
         } else {
@@ -399,7 +585,7 @@ mod de {
                  can guarantee that we won't ever read incorrect lengths of data
                  and we won't read into others' memory (or corrupt our own)
                 */
-                let mut ptr = data.as_ptr();
+                let mut ptr = data.as_ptr().add(start);
                 // so we have 8B. Just unsafe access and transmute it; nobody cares
                 let len = transmute_len(ptr);
                 // move 8 bytes ahead since we're done with len
@@ -407,31 +593,44 @@ mod de {
                 let hm = Coremap::with_capacity(len);
                 // this is what we have left: [KLEN:8B][VLEN:8B]
                 let end_ptr = data.as_ptr().add(data.len());
-                for _ in 0..len {
+                for index in 0..len {
                     if (ptr.add(16)) >= end_ptr {
                         // not enough space
                         return None;
                     }
+                    let offset = ptr.offset_from(data.as_ptr()) as usize;
                     let lenkey = transmute_len(ptr);
                     ptr = ptr.add(8);
                     let lenval = transmute_len(ptr);
                     ptr = ptr.add(8);
-                    if (ptr.add(lenkey + lenval)) > end_ptr {
+                    if (ptr.add(lenkey + lenval + trailer)) > end_ptr {
                         // not enough data left
                         return None;
                     }
                     // get the key as a raw slice, we've already checked if end_ptr is less
-                    let key = Data::copy_from_slice(slice::from_raw_parts(ptr, lenkey));
+                    let key = slice::from_raw_parts(ptr, lenkey);
                     // move the ptr ahead; done with the key
                     ptr = ptr.add(lenkey);
-                    let val = Data::copy_from_slice(slice::from_raw_parts(ptr, lenval));
+                    let val = slice::from_raw_parts(ptr, lenval);
                     // move the ptr ahead; done with the value
                     ptr = ptr.add(lenval);
+                    if checksummed {
+                        let mut checksum = [0u8; 4];
+                        checksum.copy_from_slice(slice::from_raw_parts(ptr, 4));
+                        ptr = ptr.add(4);
+                        if verify && u32::from_le_bytes(checksum) != record_checksum(key, val) {
+                            bad_records.push(BadRecord {
+                                index,
+                                offset,
+                                key: Data::copy_from_slice(key),
+                            });
+                        }
+                    }
                     // push it in
-                    hm.upsert(key, val);
+                    hm.upsert(Data::copy_from_slice(key), Data::copy_from_slice(val));
                 }
                 if ptr == end_ptr {
-                    Some(hm)
+                    Some((hm, bad_records))
                 } else {
                     // nope, someone gave us more data
                     None
@@ -440,6 +639,22 @@ mod de {
         }
     }
 
+    /// Deserialize the expiry times of a table (as written by `raw_serialize_expiries`). Every
+    /// value has to be exactly 8B long
+    pub fn deserialize_expiries(data: Vec<u8>) -> Option<HashMap<Data, i64>> {
+        let map = self::deserialize_map(data)?;
+        let mut expiries = HashMap::with_capacity(map.len());
+        for kv in map.iter() {
+            if kv.value().len() != mem::size_of::<i64>() {
+                return None;
+            }
+            let mut at = [0u8; 8];
+            at.copy_from_slice(kv.value());
+            expiries.insert(kv.key().clone(), i64::from_le_bytes(at));
+        }
+        Some(expiries)
+    }
+
     #[allow(clippy::needless_return)] // Clippy really misunderstands this
     pub(super) unsafe fn transmute_len(start_ptr: *const u8) -> usize {
         little_endian!({
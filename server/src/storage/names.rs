@@ -0,0 +1,177 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Names of keyspaces and tables on disk
+//!
+//! The `PRELOAD` and the `PARTMAP`s record the keyspaces and the tables under their names as
+//! they were created, while their directories and files are named after their keys (see
+//! [`entity_key`](crate::corestore::memstore::entity_key)). The key of a name with uppercase
+//! letters changes when `caseinsensitive` is switched on or off, so before anything is read
+//! in at startup, [`prepare`] renames the directories and files that are still named the
+//! other way. If switching `caseinsensitive` on made the names of two keyspaces (or of two
+//! tables of a keyspace) the same key, the startup fails with every such collision instead,
+//! and nothing is touched
+
+use super::error::{StorageError, StorageResult};
+use super::interface::dir_ksroot;
+use super::interface::sync_dir;
+use super::interface::BLOOM_FILTER_EXTENSION;
+use super::interface::EXPIRY_MAP_EXTENSION;
+use super::interface::PROTECTED_SET_EXTENSION;
+use crate::corestore::memstore::{self, ObjectID};
+use crate::registry;
+use crate::util::fmt_key_safe;
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// The extensions of the files of a table, which are named after the key of the table
+const TABLE_FILES: [&str; 4] = [
+    "",
+    PROTECTED_SET_EXTENSION,
+    EXPIRY_MAP_EXTENSION,
+    BLOOM_FILTER_EXTENSION,
+];
+
+/// Returns what the directory or file of `name` is called if it was written with
+/// `caseinsensitive` set the other way
+fn other_form(name: &ObjectID) -> ObjectID {
+    let mut other = name.clone();
+    if !registry::is_case_insensitive() {
+        other.make_ascii_lowercase();
+    }
+    other
+}
+
+/// Returns the directory of the keyspace `name`: the one that is named after the name as it
+/// is if there is one, and the one that is named after the name lowercased otherwise
+fn keyspace_dir(root: &Path, name: &ObjectID) -> ObjectID {
+    if unsafe { root.join(name.as_str()) }.is_dir() {
+        name.clone()
+    } else {
+        let mut lowercased = name.clone();
+        lowercased.make_ascii_lowercase();
+        lowercased
+    }
+}
+
+/// Returns the names (sorted) of every key that more than one of `names` has
+fn collisions<'a>(names: impl Iterator<Item = &'a ObjectID>) -> Vec<String> {
+    let mut by_key: BTreeMap<ObjectID, Vec<String>> = BTreeMap::new();
+    for name in names {
+        by_key
+            .entry(memstore::key_of(name))
+            .or_default()
+            .push(format!("'{}'", fmt_key_safe(name)));
+    }
+    by_key
+        .into_iter()
+        .map(|(_, names)| names)
+        .filter(|names| names.len() > 1)
+        .map(|mut names| {
+            names.sort();
+            names.join(", ")
+        })
+        .collect()
+}
+
+/// Rename `from` to `to` (in `dir`) if `to` isn't there but `from` is. Returns true if it
+/// was renamed
+fn rename_if_missing(dir: &Path, from: &str, to: &str) -> StorageResult<bool> {
+    let (from, to) = (dir.join(from), dir.join(to));
+    if to.exists() || !from.exists() {
+        return Ok(false);
+    }
+    fs::rename(&from, &to).map_err(StorageError::io("rename", &from))?;
+    log::info!("Renamed `{}` to `{}`", from.display(), to.display());
+    Ok(true)
+}
+
+/// Check the names of the keyspaces in the `PRELOAD` (`preload`) and of the tables in their
+/// `PARTMAP`s for collisions, and then rename the directories and files that aren't named
+/// after their keys. A keyspace without a `PARTMAP` is left out, since it was either being
+/// dropped or the reading of its `PARTMAP` fails later anyway
+pub fn prepare(preload: &HashSet<ObjectID>) -> StorageResult<()> {
+    let root = Path::new(dir_ksroot());
+    let mut collisions = Vec::new();
+    let mut keyspaces = Vec::with_capacity(preload.len());
+    let mut present = Vec::with_capacity(preload.len());
+    for ksname in preload {
+        let (ksid, dir) = (memstore::key_of(ksname), self::keyspace_dir(root, ksname));
+        let path = unsafe { concat_path!(dir_ksroot(), dir.as_str(), "PARTMAP") };
+        if !path.is_file() {
+            continue;
+        }
+        let partfile = fs::read(&path).map_err(StorageError::io("read", &path))?;
+        let partmap = super::preload::read_partfile_raw(partfile, &path)?;
+        for names in self::collisions(partmap.keys()) {
+            collisions.push(format!(
+                "the tables {} of the keyspace '{}'",
+                names,
+                fmt_key_safe(ksname)
+            ));
+        }
+        let tables: Vec<ObjectID> = partmap.into_iter().map(|(tblid, _)| tblid).collect();
+        keyspaces.push((ksid, dir, tables));
+        present.push(ksname);
+    }
+    for names in self::collisions(present.into_iter()) {
+        collisions.push(concat_str!("the keyspaces ", &names));
+    }
+    if !collisions.is_empty() {
+        collisions.sort();
+        return Err(StorageError::NameCollisions(collisions));
+    }
+    let mut renamed_keyspaces = false;
+    for (ksid, dir, tables) in keyspaces {
+        let (ksid, dir) = unsafe { (ksid.as_str(), dir.as_str()) };
+        renamed_keyspaces |= self::rename_if_missing(root, dir, ksid)?;
+        let ks_path = root.join(ksid);
+        let mut renamed_tables = false;
+        for tblname in tables {
+            let (tblid, other) = (memstore::key_of(&tblname), self::other_form(&tblname));
+            if tblid == other {
+                continue;
+            }
+            let (tblid, other) = unsafe { (tblid.as_str(), other.as_str()) };
+            for extension in TABLE_FILES.iter() {
+                renamed_tables |= self::rename_if_missing(
+                    &ks_path,
+                    &concat_str!(other, extension),
+                    &concat_str!(tblid, extension),
+                )?;
+            }
+        }
+        if renamed_tables {
+            sync_dir(&ks_path)?;
+        }
+    }
+    if renamed_keyspaces {
+        sync_dir(root)?;
+    }
+    Ok(())
+}
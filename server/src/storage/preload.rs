@@ -34,15 +34,17 @@
 
 use crate::corestore::memstore::Memstore;
 use crate::corestore::memstore::ObjectID;
+use crate::storage::error::{StorageError, StorageResult};
 use crate::IoResult;
 use core::ptr;
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::io::Error as IoError;
-use std::io::ErrorKind;
 use std::io::Write;
+use std::path::Path;
 
-pub type LoadedPartfile = HashMap<ObjectID, (u8, u8)>;
+/// The tables in a partition map, with their storage type, model code and (if they have them)
+/// value limit and times of the latest write and read
+pub type LoadedPartfile = HashMap<ObjectID, (u8, u8, Option<u64>, Option<(u64, u64)>)>;
 
 // our version and endian are based on nibbles
 
@@ -59,39 +61,62 @@ const META_SEGMENT: u8 = 0b1000_0001;
 /// ([8B: Partion ID len][8B: Parition ID (not padded)])* => Data segment
 /// ```
 ///
+/// The keyspaces are recorded under their names as they were created (see
+/// [`entity_key`](crate::corestore::memstore::entity_key)), which are their keys unless
+/// `caseinsensitive` is set
 pub(super) fn raw_generate_preload<W: Write>(w: &mut W, store: &Memstore) -> IoResult<()> {
     // generate the meta segment
     #[allow(clippy::identity_op)]
     w.write_all(&[META_SEGMENT])?;
-    super::se::raw_serialize_set(&store.keyspaces, w)?;
+    w.write_all(&(store.keyspaces.len() as u64).to_le_bytes())?;
+    for ks in store.keyspaces.iter() {
+        let name = ks.value().name().unwrap_or_else(|| ks.key());
+        w.write_all(&(name.len() as u64).to_le_bytes())?;
+        w.write_all(name)?;
+    }
     Ok(())
 }
 
-/// Reads the preload file and returns a set
-pub(super) fn read_preload_raw(preload: Vec<u8>) -> IoResult<HashSet<ObjectID>> {
+/// Reads the preload file (that was read from `path`) and returns a set
+pub(super) fn read_preload_raw(preload: Vec<u8>, path: &Path) -> StorageResult<HashSet<ObjectID>> {
     if preload.len() < 16 {
         // nah, this is a bad disk file
-        return Err(IoError::from(ErrorKind::UnexpectedEof));
+        return Err(StorageError::corrupted_at(
+            path,
+            preload.len(),
+            "the file is truncated",
+        ));
     }
     // first read in the meta segment
     unsafe {
         let meta_segment: u8 = ptr::read(preload.as_ptr());
         if meta_segment != META_SEGMENT {
-            return Err(IoError::from(ErrorKind::Unsupported));
+            return Err(StorageError::VersionMismatch {
+                file: path.to_owned(),
+                found: meta_segment,
+                expected: META_SEGMENT,
+            });
         }
     }
     // all checks complete; time to decode
     let ret = super::de::deserialize_set_ctype(&preload[1..]);
     match ret {
         Some(ret) => Ok(ret),
-        _ => Err(IoError::from(ErrorKind::InvalidData)),
+        _ => Err(StorageError::corrupted_at(
+            path,
+            1,
+            "the set of keyspaces couldn't be decoded",
+        )),
     }
 }
 
-/// Reads the partfile and returns a set
-pub fn read_partfile_raw(partfile: Vec<u8>) -> IoResult<LoadedPartfile> {
+/// Reads the partfile (that was read from `path`) and returns a set
+pub fn read_partfile_raw(partfile: Vec<u8>, path: &Path) -> StorageResult<LoadedPartfile> {
     match super::de::deserialize_set_ctype_bytemark(&partfile) {
         Some(s) => Ok(s),
-        None => Err(IoError::from(ErrorKind::InvalidData)),
+        None => Err(StorageError::corrupted(
+            path,
+            "the set of tables couldn't be decoded",
+        )),
     }
 }
@@ -0,0 +1,273 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Startup reconciliation
+//!
+//! Before the tables are read in at startup, the files in the directory of every keyspace are
+//! compared with the `PARTMAP` of the keyspace. There are two kinds of discrepancies (usually
+//! left behind by a partial manual restore):
+//! - **Orphans**: files that look like the files of a table (the table itself, or its
+//!   protected keys, expiry times or bloom filter), but whose table isn't in the `PARTMAP`.
+//!   Depending on the [`OrphanPolicy`](crate::config::OrphanPolicy), the startup fails, the
+//!   orphans are adopted as persistent `keymap(binstr,binstr)` tables, or they're moved into
+//!   the [`QUARANTINE_DIR`] of their keyspace
+//! - **Missing tables**: tables in the `PARTMAP` that aren't volatile, but have no file. The
+//!   startup fails unless missing tables are allowed, in which case they're loaded empty
+//!
+//! When the startup fails, the [`RecoveryReport`] names everything that doesn't match, and
+//! nothing in the data directory is touched. Otherwise the report is kept and can be read with
+//! `SYS RECOVERYREPORT`
+
+use super::error::{StorageError, StorageResult};
+use super::interface::sync_dir;
+use super::interface::BLOOM_FILTER_EXTENSION;
+use super::interface::DROPPED_EXTENSION;
+use super::interface::EXPIRY_MAP_EXTENSION;
+use super::interface::PROTECTED_SET_EXTENSION;
+use crate::corestore::memstore::ObjectID;
+use crate::queryengine::parser::is_valid_container_name;
+use core::fmt;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The directory (in the directory of a keyspace) that orphans are quarantined in
+pub const QUARANTINE_DIR: &str = ".orphaned";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// What was done with an orphan
+pub enum OrphanAction {
+    /// Nothing; the startup failed
+    Reported,
+    /// The orphan was registered as a table of its keyspace
+    Adopted,
+    /// The files of the orphan were moved into the [`QUARANTINE_DIR`]
+    Quarantined,
+}
+
+impl OrphanAction {
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            OrphanAction::Reported => "orphan",
+            OrphanAction::Adopted => "orphan-adopted",
+            OrphanAction::Quarantined => "orphan-quarantined",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// The files of a table that isn't in the `PARTMAP` of its keyspace
+pub struct Orphan {
+    pub keyspace: String,
+    pub table: String,
+    /// the names of the files, sorted
+    pub files: Vec<String>,
+    pub action: OrphanAction,
+}
+
+impl Orphan {
+    /// Returns true if the orphan can be adopted: it has the file of the table itself (and
+    /// not just, say, a bloom filter), and its name fits in an [`ObjectID`]
+    pub fn is_adoptable(&self) -> bool {
+        self.table.len() <= ObjectID::new().capacity()
+            && self.files.iter().any(|f| *f == self.table)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A table in the `PARTMAP` of its keyspace that isn't volatile, but has no file
+pub struct MissingTable {
+    pub keyspace: String,
+    pub table: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+/// Everything in the data directory that didn't match the `PARTMAP`s at startup
+pub struct RecoveryReport {
+    pub orphans: Vec<Orphan>,
+    pub missing: Vec<MissingTable>,
+}
+
+impl RecoveryReport {
+    pub const fn new() -> Self {
+        Self {
+            orphans: Vec::new(),
+            missing: Vec::new(),
+        }
+    }
+    /// Returns the entity of every discrepancy along with what was done about it. Missing
+    /// tables are only ever reported this way once they're loaded empty, since the startup
+    /// fails otherwise
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let orphans = self.orphans.iter().map(|orphan| {
+            (
+                concat_str!(&orphan.keyspace, ":", &orphan.table),
+                orphan.action.as_str().to_owned(),
+            )
+        });
+        let missing = self.missing.iter().map(|missing| {
+            (
+                concat_str!(&missing.keyspace, ":", &missing.table),
+                "missing-loaded-empty".to_owned(),
+            )
+        });
+        orphans.chain(missing).collect()
+    }
+}
+
+impl fmt::Display for RecoveryReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} orphaned table(s) and {} table(s) without a file",
+            self.orphans.len(),
+            self.missing.len()
+        )?;
+        for orphan in &self.orphans {
+            write!(
+                f,
+                "; `{}:{}` isn't in the PARTMAP of its keyspace, but has the file(s) {}",
+                orphan.keyspace,
+                orphan.table,
+                orphan.files.join(", ")
+            )?;
+            match orphan.action {
+                OrphanAction::Reported => {}
+                OrphanAction::Adopted => write!(f, " (adopted)")?,
+                OrphanAction::Quarantined => write!(f, " (quarantined)")?,
+            }
+        }
+        for missing in &self.missing {
+            write!(
+                f,
+                "; `{}:{}` is in the PARTMAP of its keyspace, but has no file",
+                missing.keyspace, missing.table
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the table that the file `name` belongs to, if it's a file of a table at all. The
+/// temporary files of a table (written to `<name>_` first) belong to it too, unless they're
+/// the file of another table
+fn table_of<'a>(name: &'a str, known: &[&str]) -> Option<&'a str> {
+    if known.contains(&name) {
+        return Some(name);
+    }
+    let name = name.strip_suffix('_').unwrap_or(name);
+    let extensions = [
+        PROTECTED_SET_EXTENSION,
+        EXPIRY_MAP_EXTENSION,
+        BLOOM_FILTER_EXTENSION,
+    ];
+    let table = extensions
+        .iter()
+        .find_map(|extension| name.strip_suffix(extension))
+        .unwrap_or(name);
+    if is_valid_container_name(table) {
+        Some(table)
+    } else {
+        None
+    }
+}
+
+/// Find the orphans in the directory of the keyspace `ksid` at `ks_path`, given the tables in
+/// its `PARTMAP` (`known`) and the tables that were being dropped (`dropped`), whose leftovers
+/// are cleaned up anyway. Files that can't be the files of a table are left alone
+pub fn find_orphans(
+    ks_path: &Path,
+    ksid: &str,
+    known: &[&str],
+    dropped: &[&str],
+) -> StorageResult<Vec<Orphan>> {
+    let entries = fs::read_dir(ks_path).map_err(StorageError::io("read", ks_path))?;
+    let mut orphans: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for entry in entries {
+        let entry = entry.map_err(StorageError::io("read", ks_path))?;
+        if entry.path().is_dir() {
+            // the quarantine, say
+            continue;
+        }
+        let name = match entry.file_name().into_string() {
+            Ok(name) => name,
+            Err(name) => {
+                log::warn!(
+                    "Ignoring the file '{}' in the directory of the keyspace '{}'",
+                    name.to_string_lossy(),
+                    ksid
+                );
+                continue;
+            }
+        };
+        if name == "PARTMAP" || name == "PARTMAP_" || name.ends_with(DROPPED_EXTENSION) {
+            continue;
+        }
+        match self::table_of(&name, known) {
+            Some(table) if known.contains(&table) || dropped.contains(&table) => {}
+            Some(table) => orphans.entry(table.to_owned()).or_default().push(name),
+            None => log::warn!(
+                "Ignoring the file '{}' in the directory of the keyspace '{}', which isn't the \
+                file of a table",
+                name,
+                ksid
+            ),
+        }
+    }
+    Ok(orphans
+        .into_iter()
+        .map(|(table, mut files)| {
+            files.sort();
+            Orphan {
+                keyspace: ksid.to_owned(),
+                table,
+                files,
+                action: OrphanAction::Reported,
+            }
+        })
+        .collect())
+}
+
+/// Move the files of `orphan` into the [`QUARANTINE_DIR`] of its keyspace at `ks_path`. A
+/// file that would replace one that is already there (from an earlier quarantine) gets a
+/// numeric suffix instead
+pub fn quarantine(ks_path: &Path, orphan: &Orphan) -> StorageResult<()> {
+    let quarantine = ks_path.join(QUARANTINE_DIR);
+    fs::create_dir_all(&quarantine).map_err(StorageError::io("create", &quarantine))?;
+    for file in &orphan.files {
+        let mut target = quarantine.join(file);
+        let mut suffix = 0;
+        while target.exists() {
+            suffix += 1;
+            target = quarantine.join(format!("{}.{}", file, suffix));
+        }
+        let source = ks_path.join(file);
+        fs::rename(&source, &target).map_err(StorageError::io("rename", &source))?;
+    }
+    // the files are in the quarantine for good before they're gone from the keyspace
+    sync_dir(&quarantine)?;
+    sync_dir(ks_path)
+}
@@ -0,0 +1,418 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Restoring snapshots
+//!
+//! Routines to restore a snapshot directory (the `data/snaps/<snapshot>` directory of this or
+//! any other node) into the data directory before the server loads any data. The snapshot is
+//! validated in full before anything is copied. While the files are being copied, a marker
+//! file is kept in the data directory so that an interrupted restore can be detected (and
+//! redone) on the next attempt. Keys that are already past their expiry time when the snapshot
+//! is restored are dropped (and counted in the [`RestoreSummary`]) instead of being copied.
+//!
+//! An [incremental snapshot](super::chain) is restored along with the snapshots before it in
+//! its chain (which have to be next to it): every table comes from the latest snapshot in the
+//! chain that has it
+
+use super::bytemarks;
+use super::chain::{ChainLink, CHAIN_FILE};
+use super::interface::dir_ksroot;
+use super::interface::dir_root;
+use super::interface::EXPIRY_MAP_EXTENSION;
+use super::interface::PROTECTED_SET_EXTENSION;
+use crate::corestore::htable::Coremap;
+use crate::corestore::memstore::{self, ObjectID};
+use crate::corestore::Data;
+use chrono::Utc;
+use core::fmt;
+use std::fs;
+use std::io::Error as IoError;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The file that is present in the data directory while a restore is in progress
+pub const RESTORE_MARKER: &str = "RESTORE_IN_PROGRESS";
+
+#[derive(Debug)]
+/// Errors that can occur while restoring a snapshot
+pub enum RestoreError {
+    /// An I/O error while reading the snapshot or writing the data directory
+    IoError(PathBuf, IoError),
+    /// A file in the snapshot is missing or corrupted
+    BadSnapshot(PathBuf),
+    /// The data directory already has data and `--force` wasn't passed
+    DataDirNotEmpty,
+    /// An incremental snapshot depends on a snapshot that isn't there
+    BrokenChain {
+        /// the snapshot that depends on the missing one
+        snapshot: PathBuf,
+        /// the name of the missing snapshot
+        missing: String,
+    },
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IoError(path, e) => write!(f, "I/O error on `{}`: {}", path.display(), e),
+            Self::BadSnapshot(path) => {
+                write!(f, "missing or corrupted snapshot file `{}`", path.display())
+            }
+            Self::DataDirNotEmpty => write!(
+                f,
+                "the data directory already contains data. Pass `--force` to overwrite it"
+            ),
+            Self::BrokenChain { snapshot, missing } => write!(
+                f,
+                "the incremental snapshot `{}` depends on the snapshot `{}`, which is missing",
+                snapshot.display(),
+                missing
+            ),
+        }
+    }
+}
+
+type RestoreResult<T> = Result<T, RestoreError>;
+
+#[derive(Debug, Default, PartialEq)]
+/// What was restored
+pub struct RestoreSummary {
+    pub keyspaces: usize,
+    pub tables: usize,
+    pub records: usize,
+    /// the records that were past their expiry time and were dropped
+    pub expired: usize,
+}
+
+impl fmt::Display for RestoreSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "restored {} keyspace(s) with {} table(s) and {} record(s) (dropped {} expired record(s))",
+            self.keyspaces, self.tables, self.records, self.expired
+        )
+    }
+}
+
+/// A file of a keyspace in the snapshot
+struct ManifestFile {
+    name: String,
+    /// the directory of the keyspace in the snapshot (of the chain) that has the file
+    from: PathBuf,
+    /// what to write instead of copying the file (for the tables that had expired keys
+    /// dropped, and their expiry times)
+    rewrite: Option<Vec<u8>>,
+}
+
+impl ManifestFile {
+    fn copy(name: String, from: &Path) -> Self {
+        Self {
+            name,
+            from: from.to_owned(),
+            rewrite: None,
+        }
+    }
+    fn rewrite(name: String, with: Vec<u8>) -> Self {
+        Self {
+            name,
+            from: PathBuf::new(),
+            rewrite: Some(with),
+        }
+    }
+}
+
+/// A validated snapshot: the keyspaces and the files that are to be copied for each of them
+struct Manifest {
+    keyspaces: Vec<(String, Vec<ManifestFile>)>,
+    summary: RestoreSummary,
+}
+
+/// Get the name of a keyspace or table in the snapshot. Since these are used as paths, we
+/// don't trust anything that isn't a valid entity name
+pub(super) fn objectid_to_name(id: &ObjectID, path: &Path) -> RestoreResult<String> {
+    match core::str::from_utf8(id.as_ref()) {
+        Ok(name)
+            if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') =>
+        {
+            Ok(name.to_owned())
+        }
+        _ => Err(RestoreError::BadSnapshot(path.to_owned())),
+    }
+}
+
+pub(super) fn read(path: &Path) -> RestoreResult<Vec<u8>> {
+    fs::read(path).map_err(|e| RestoreError::IoError(path.to_owned(), e))
+}
+
+/// Returns the snapshots in the chain of the snapshot at `src`, starting with `src` and going
+/// back to the full snapshot. A snapshot that isn't part of a chain is a chain of its own
+pub(super) fn read_chain(src: &Path) -> RestoreResult<Vec<PathBuf>> {
+    let mut chain = vec![src.to_owned()];
+    let mut snapshot = src.to_owned();
+    let mut expected_depth = None;
+    loop {
+        let chain_path = snapshot.join(CHAIN_FILE);
+        let link = if chain_path.is_file() {
+            let file = fs::read_to_string(&chain_path)
+                .map_err(|e| RestoreError::IoError(chain_path.clone(), e))?;
+            ChainLink::parse(&file).ok_or_else(|| RestoreError::BadSnapshot(chain_path.clone()))?
+        } else if expected_depth.is_none() {
+            // a snapshot that was made without a chain file
+            break;
+        } else {
+            return Err(RestoreError::BadSnapshot(chain_path));
+        };
+        // the depth goes down by one with every parent, so we can't go around in circles
+        if matches!(expected_depth, Some(depth) if depth != link.depth) {
+            return Err(RestoreError::BadSnapshot(chain_path));
+        }
+        let parent = match link.parent {
+            Some(parent) => parent,
+            None => break,
+        };
+        let parent_path = match snapshot.parent() {
+            Some(dir) if !parent.contains(|c: char| c == '/' || c == '\\') && parent != ".." => {
+                dir.join(&parent)
+            }
+            _ => return Err(RestoreError::BadSnapshot(chain_path)),
+        };
+        if !parent_path.is_dir() {
+            return Err(RestoreError::BrokenChain {
+                snapshot,
+                missing: parent,
+            });
+        }
+        expected_depth = Some(link.depth - 1);
+        chain.push(parent_path.clone());
+        snapshot = parent_path;
+    }
+    Ok(chain)
+}
+
+/// Check the `PRELOAD`, the `PARTMAP` of every keyspace and every table in the snapshot (the
+/// first one in `chain`). A table that isn't in the snapshot is looked for in the snapshots
+/// before it in the chain. Keys whose expiry time is at or before `now` (in milliseconds since
+/// the unix epoch) are dropped
+fn validate(chain: &[PathBuf], now: i64) -> RestoreResult<Manifest> {
+    let src = &chain[0];
+    let preload_path = src.join("PRELOAD");
+    let preload = super::preload::read_preload_raw(self::read(&preload_path)?, &preload_path)
+        .map_err(|_| RestoreError::BadSnapshot(preload_path.clone()))?;
+    let mut summary = RestoreSummary::default();
+    let mut keyspaces = Vec::with_capacity(preload.len());
+    for ksid in preload {
+        // the directories and files are named after the keys (see `names`)
+        let ksid = self::objectid_to_name(&memstore::key_of(&ksid), &preload_path)?;
+        let kspath = src.join(&ksid);
+        let partmap_path = kspath.join("PARTMAP");
+        let partmap = super::preload::read_partfile_raw(self::read(&partmap_path)?, &partmap_path)
+            .map_err(|_| RestoreError::BadSnapshot(partmap_path.clone()))?;
+        let mut files = vec![ManifestFile::copy("PARTMAP".to_owned(), &kspath)];
+        for (tblid, (storage_type, _, _, _)) in partmap {
+            let tblid = self::objectid_to_name(&memstore::key_of(&tblid), &partmap_path)?;
+            let storage_type = storage_type
+                & !(bytemarks::BYTEMARK_STORAGE_FLAG_ORDERED
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_DEFAULT
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_READ_CACHE
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY
+                    | bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED);
+            summary.tables += 1;
+            if storage_type == bytemarks::BYTEMARK_STORAGE_VOLATILE {
+                // volatile tables have nothing on disk
+                continue;
+            }
+            // the files of the table come from the latest snapshot that has it
+            let kspath = chain
+                .iter()
+                .map(|snapshot| snapshot.join(&ksid))
+                .find(|dir| dir.join(&tblid).is_file())
+                .unwrap_or_else(|| kspath.clone());
+            let tblpath = kspath.join(&tblid);
+            let data = super::de::deserialize_map(self::read(&tblpath)?)
+                .ok_or_else(|| RestoreError::BadSnapshot(tblpath))?;
+            // the protected keys and the expiry times are optional
+            let protected = concat_str!(&tblid, PROTECTED_SET_EXTENSION);
+            let protected_path = kspath.join(&protected);
+            let protected_keys = if protected_path.is_file() {
+                let keys = super::de::deserialize_set_ctype::<Data>(&self::read(&protected_path)?)
+                    .ok_or_else(|| RestoreError::BadSnapshot(protected_path))?;
+                Some((protected, keys))
+            } else {
+                None
+            };
+            let expiry = concat_str!(&tblid, EXPIRY_MAP_EXTENSION);
+            let expiry_path = kspath.join(&expiry);
+            let mut expiries = if expiry_path.is_file() {
+                let expiries = super::de::deserialize_expiries(self::read(&expiry_path)?)
+                    .ok_or_else(|| RestoreError::BadSnapshot(expiry_path))?;
+                Some((expiry, expiries))
+            } else {
+                None
+            };
+            let mut expired = 0;
+            if let Some((_, expiries)) = &mut expiries {
+                // protected keys are never dropped, just like on a running server
+                let is_protected =
+                    |key: &Data| matches!(&protected_keys, Some((_, keys)) if keys.contains(key));
+                let keys: Vec<Data> = expiries
+                    .iter()
+                    .filter(|(key, at)| {
+                        **at <= now && data.contains_key(*key) && !is_protected(*key)
+                    })
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                for key in keys.iter() {
+                    data.remove(key);
+                    expiries.remove(key);
+                }
+                expired = keys.len();
+            }
+            summary.records += data.len();
+            summary.expired += expired;
+            if expired == 0 {
+                files.push(ManifestFile::copy(tblid, &kspath));
+                if let Some((expiry, _)) = expiries {
+                    files.push(ManifestFile::copy(expiry, &kspath));
+                }
+            } else {
+                // write out what's left instead
+                let mut table = Vec::new();
+                super::se::raw_serialize_map(&data, &mut table)
+                    .map_err(|e| RestoreError::IoError(kspath.clone(), e))?;
+                files.push(ManifestFile::rewrite(tblid, table));
+                match expiries {
+                    Some((expiry, expiries)) if !expiries.is_empty() => {
+                        let remaining = Coremap::with_capacity(expiries.len());
+                        expiries
+                            .into_iter()
+                            .for_each(|(key, at)| remaining.upsert(key, at));
+                        let mut buf = Vec::new();
+                        super::se::raw_serialize_expiries(&remaining, &mut buf)
+                            .map_err(|e| RestoreError::IoError(kspath.clone(), e))?;
+                        files.push(ManifestFile::rewrite(expiry, buf));
+                    }
+                    _ => {}
+                }
+            }
+            if let Some((protected, _)) = protected_keys {
+                files.push(ManifestFile::copy(protected, &kspath));
+            }
+        }
+        summary.keyspaces += 1;
+        keyspaces.push((ksid, files));
+    }
+    Ok(Manifest { keyspaces, summary })
+}
+
+/// Returns true if the data directory has a `PRELOAD` or any table files
+fn has_data(ksroot: &Path) -> RestoreResult<bool> {
+    let to_err = |e| RestoreError::IoError(ksroot.to_owned(), e);
+    if !ksroot.exists() {
+        return Ok(false);
+    }
+    for entry in fs::read_dir(ksroot).map_err(to_err)? {
+        let path = entry.map_err(to_err)?.path();
+        if !path.is_dir() || fs::read_dir(&path).map_err(to_err)?.next().is_some() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Returns true if a previous restore was interrupted
+pub fn is_interrupted() -> bool {
+    Path::new(dir_root()).join(RESTORE_MARKER).exists()
+}
+
+/// Restore the snapshot directory `src` into the data directory. This will refuse to
+/// overwrite existing data unless `force` is set (or a previous restore was interrupted)
+pub fn restore_from(src: &str, force: bool) -> RestoreResult<RestoreSummary> {
+    self::restore_into(
+        Path::new(src),
+        Path::new(dir_ksroot()),
+        &Path::new(dir_root()).join(RESTORE_MARKER),
+        force,
+        Utc::now().timestamp_millis(),
+    )
+}
+
+pub(super) fn restore_into(
+    src: &Path,
+    ksroot: &Path,
+    marker: &Path,
+    force: bool,
+    now: i64,
+) -> RestoreResult<RestoreSummary> {
+    log::info!("Validating snapshot at `{}`", src.display());
+    let chain = self::read_chain(src)?;
+    if chain.len() > 1 {
+        log::info!(
+            "The snapshot is incremental; restoring it along with {} snapshot(s) before it",
+            chain.len() - 1
+        );
+    }
+    let manifest = self::validate(&chain, now)?;
+    let interrupted = marker.exists();
+    if interrupted {
+        log::warn!("A previous restore was interrupted. Restoring again");
+    } else if !force && self::has_data(ksroot)? {
+        return Err(RestoreError::DataDirNotEmpty);
+    }
+    let io_err = |path: &Path| {
+        let path = path.to_owned();
+        move |e| RestoreError::IoError(path, e)
+    };
+    // the marker is only removed once everything has been copied
+    if let Some(parent) = marker.parent() {
+        fs::create_dir_all(parent).map_err(io_err(parent))?;
+    }
+    fs::write(marker, b"").map_err(io_err(marker))?;
+    if ksroot.exists() {
+        fs::remove_dir_all(ksroot).map_err(io_err(ksroot))?;
+    }
+    for (ksid, files) in manifest.keyspaces.iter() {
+        let ks_dst = ksroot.join(ksid);
+        fs::create_dir_all(&ks_dst).map_err(io_err(&ks_dst))?;
+        for file in files {
+            let to = ks_dst.join(&file.name);
+            match &file.rewrite {
+                Some(with) => fs::write(&to, with).map_err(io_err(&to))?,
+                None => {
+                    let from = file.from.join(&file.name);
+                    fs::copy(&from, &to).map_err(io_err(&from))?;
+                }
+            }
+        }
+        log::info!("Restored keyspace `{}` ({} file(s))", ksid, files.len());
+    }
+    // the preload goes in last since it is what marks the directory as initialized
+    let preload = src.join("PRELOAD");
+    fs::copy(&preload, ksroot.join("PRELOAD")).map_err(io_err(&preload))?;
+    fs::remove_file(marker).map_err(io_err(marker))?;
+    log::info!("Snapshot restore complete: {}", manifest.summary);
+    Ok(manifest.summary)
+}
@@ -25,6 +25,64 @@
 */
 
 use super::*;
+use crate::config::SnapshotConfig;
+use crate::corestore::memstore::{Memstore, ObjectID};
+use crate::corestore::table::Table;
+use crate::kvengine::KVEngine;
+use std::env;
+use std::fs;
+use std::process;
+
+/// The ID of the keyspace or table `name`
+fn id(name: &str) -> ObjectID {
+    unsafe { ObjectID::from_slice(name) }
+}
+
+/// Returns the path of the data directory of the test `name`, in the temporary directory
+fn temp_data_dir(name: &str) -> String {
+    let data_dir = env::temp_dir().join(format!("skyd-{}-{}", name, process::id()));
+    data_dir.to_str().unwrap().to_owned()
+}
+
+/// Flush a store with the `keyspaces`, each with the `tables`, to `data_dir` (which is
+/// emptied first and left as the data directory of this thread). `fill` sets the keys of
+/// every table, given the names of its keyspace and of the table
+fn populate_store<K: AsRef<str>, T: AsRef<str>>(
+    data_dir: &str,
+    keyspaces: &[K],
+    tables: &[T],
+    fill: impl Fn(&str, &str, &KVEngine),
+) -> Memstore {
+    let _ = fs::remove_dir_all(data_dir);
+    let store = Memstore::new_default();
+    for ks in keyspaces.iter().map(AsRef::as_ref) {
+        assert!(store.create_keyspace(id(ks)));
+        let keyspace = store.get_keyspace_atomic_ref(&id(ks)).unwrap();
+        for tbl in tables.iter().map(AsRef::as_ref) {
+            let table = Table::new_default_kve();
+            fill(ks, tbl, table.get_kvstore().unwrap());
+            assert!(keyspace.create_table(id(tbl), table));
+        }
+    }
+    interface::override_data_dir(Some(data_dir));
+    interface::create_tree(&store).unwrap();
+    flush::oneshot::flush_preload(&store).unwrap();
+    flush::flush_full(&store).unwrap();
+    store
+}
+
+/// Read the store back in from the data directory of this thread (with one thread)
+fn boot() -> Result<Memstore, error::StorageError> {
+    unflush::read_full_with(&SnapshotConfig::default(), false, 1)
+}
+
+#[test]
+fn test_crc32c() {
+    assert_eq!(crc32c(b"123456789"), 0xE306_9283);
+    assert_eq!(crc32c(b""), 0);
+    // a record is checksummed as if its key and value were one
+    assert_eq!(record_checksum(b"1234", b"56789"), 0xE306_9283);
+}
 
 #[test]
 fn test_serialize_deserialize_empty() {
@@ -47,6 +105,40 @@ fn test_ser_de_few_elements() {
         .all(|kv| cmap.get(kv.key()).unwrap().eq(kv.value())));
 }
 
+#[test]
+fn test_ser_de_with_checksums() {
+    let cmap = Coremap::new();
+    cmap.upsert("sayan".into(), "writes code".into());
+    let mut ser = Vec::new();
+    se::raw_serialize_map_with(&cmap, &mut ser, true, |_| {}).unwrap();
+    assert_eq!(checksummed_map_version(&ser), Some(CHECKSUMMED_MAP_VERSION));
+    // the header, the length, the record and its checksum
+    assert_eq!(ser.len(), 8 + 8 + 16 + 5 + 11 + 4);
+    let (de, bad) = de::deserialize_map_verified(ser.clone(), true).unwrap();
+    assert!(bad.is_empty());
+    assert!(de
+        .get(&Data::from("sayan"))
+        .unwrap()
+        .eq(&Data::from("writes code")));
+    // a corrupted checksum is only noticed if the records are verified
+    let last = ser.len() - 1;
+    ser[last] ^= 0xFF;
+    assert_eq!(
+        de::deserialize_map_verified(ser.clone(), true)
+            .unwrap()
+            .1
+            .len(),
+        1
+    );
+    assert!(de::deserialize_map(ser.clone()).is_some());
+    // a version of the format that we don't know of
+    ser[CHECKSUMMED_MAP_HEADER_LEN - 1] = CHECKSUMMED_MAP_VERSION + 1;
+    assert!(de::deserialize_map(ser).is_none());
+    // and files without checksums have no header
+    let plain = se::serialize_map(&cmap).unwrap();
+    assert_eq!(checksummed_map_version(&plain), None);
+}
+
 cfg_test!(
     use libstress::utils::generate_random_string_vector;
     use rand::thread_rng;
@@ -129,14 +221,20 @@ fn test_runtime_panic_32bit_or_lower() {
 }
 
 mod interface_tests {
-    use super::interface::{create_tree, DIR_KSROOT, DIR_SNAPROOT};
-    use crate::corestore::memstore::Memstore;
+    use super::interface::{
+        absolute_path, create_tree, dir_ksroot, dir_snaproot, override_data_dir,
+    };
+    use super::{flush, unflush};
+    use crate::config::SnapshotConfig;
+    use crate::corestore::memstore::{Memstore, ObjectID};
+    use std::env;
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::process;
     #[test]
     fn test_tree() {
         create_tree(&Memstore::new_default()).unwrap();
-        let read_ks: Vec<String> = fs::read_dir(DIR_KSROOT)
+        let read_ks: Vec<String> = fs::read_dir(dir_ksroot())
             .unwrap()
             .map(|dir| {
                 let v = dir.unwrap().file_name();
@@ -146,7 +244,7 @@ mod interface_tests {
         assert!(read_ks.contains(&"system".to_owned()));
         assert!(read_ks.contains(&"default".to_owned()));
         // just read one level of the snaps dir
-        let read_snaps: Vec<String> = fs::read_dir(DIR_SNAPROOT)
+        let read_snaps: Vec<String> = fs::read_dir(dir_snaproot())
             .unwrap()
             .map(|dir| {
                 let v = dir.unwrap().file_name();
@@ -156,17 +254,295 @@ mod interface_tests {
         assert_eq!(read_snaps, Vec::<String>::new());
         assert!(PathBuf::from("data/backups").is_dir());
     }
+    #[test]
+    fn test_tree_in_data_dir() {
+        let data_dir = env::temp_dir().join(format!("skyd-datadir-{}", process::id()));
+        let data_dir = data_dir.to_str().unwrap();
+        let _ = fs::remove_dir_all(data_dir);
+        override_data_dir(Some(data_dir));
+        let store = Memstore::new_default();
+        create_tree(&store).unwrap();
+        flush::flush_full(&store).unwrap();
+        override_data_dir(None);
+        let root = Path::new(data_dir);
+        assert!(root.join("ks/PRELOAD").is_file());
+        assert!(root.join("ks/default/PARTMAP").is_file());
+        assert!(root.join("snaps").is_dir());
+        assert!(root.join("backups").is_dir());
+        // and it can be read back from there
+        override_data_dir(Some(data_dir));
+        assert!(!unflush::is_new_instance());
+        let read = unflush::read_full(&SnapshotConfig::default(), false).unwrap();
+        override_data_dir(None);
+        assert!(read
+            .get_keyspace_atomic_ref(&unsafe { ObjectID::from_slice("default") })
+            .is_some());
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+    #[test]
+    fn test_data_dir_is_absolute() {
+        // this is what happens with a configured data directory if the server is started in
+        // `cwd`: relative paths are resolved once, and absolute ones don't depend on it at all
+        let cwd = env::temp_dir().join("skyd-cwd");
+        let elsewhere = env::temp_dir().join("skyd-elsewhere");
+        let elsewhere = elsewhere.to_str().unwrap();
+        assert_eq!(absolute_path(&cwd, elsewhere).unwrap(), elsewhere);
+        assert_eq!(
+            absolute_path(&cwd, "mydata/").unwrap(),
+            cwd.join("mydata").to_str().unwrap()
+        );
+        assert_eq!(
+            absolute_path(&cwd, "data").unwrap(),
+            cwd.join("data").to_str().unwrap()
+        );
+    }
+}
+
+mod parallel_load_tests {
+    use super::error::StorageError;
+    use super::interface::override_data_dir;
+    use super::{id, populate_store, temp_data_dir, unflush};
+    use crate::config::SnapshotConfig;
+    use crate::corestore::memstore::Memstore;
+    use crate::corestore::Data;
+    use std::fs;
+    use std::path::Path;
+
+    const KEYSPACES: usize = 3;
+    const TABLES: usize = 5;
+    const KEYS: usize = 200;
+
+    fn keyspaces() -> Vec<String> {
+        (0..KEYSPACES).map(|ks| format!("loadks{}", ks)).collect()
+    }
+    fn tables() -> Vec<String> {
+        (0..TABLES).map(|tbl| format!("tbl{}", tbl)).collect()
+    }
+    fn value(ks: &str, tbl: &str, key: usize) -> Data {
+        Data::from(format!("{}:{}:{}", ks, tbl, key))
+    }
+    /// Flush a few keyspaces with a few tables each to `data_dir` (which is left as the data
+    /// directory of this thread)
+    fn populate(data_dir: &str) {
+        populate_store(data_dir, &keyspaces(), &tables(), |ks, tbl, kve| {
+            for key in 0..KEYS {
+                kve.set(Data::from(format!("key{}", key)), value(ks, tbl, key))
+                    .unwrap();
+            }
+        });
+    }
+    fn assert_loaded(store: &Memstore) {
+        for ks in keyspaces().iter() {
+            let keyspace = store.get_keyspace_atomic_ref(&id(ks)).unwrap();
+            assert_eq!(keyspace.table_count(), TABLES);
+            for tbl in tables().iter() {
+                let table = keyspace.get_table_atomic_ref(&id(tbl)).unwrap();
+                let kve = table.get_kvstore().unwrap();
+                assert_eq!(kve.len(), KEYS);
+                for key in 0..KEYS {
+                    assert_eq!(
+                        kve.take_snapshot(format!("key{}", key).as_bytes()),
+                        Some(value(ks, tbl, key))
+                    );
+                }
+            }
+        }
+    }
+    #[test]
+    fn test_parallel_load() {
+        let data_dir = temp_data_dir("parload");
+        let data_dir = data_dir.as_str();
+        populate(data_dir);
+        // the threads share out the tables in whatever order they get to them
+        let parallel = unflush::read_full_with(&SnapshotConfig::default(), false, 4).unwrap();
+        let sequential = unflush::read_full_with(&SnapshotConfig::default(), false, 1).unwrap();
+        override_data_dir(None);
+        assert_loaded(&parallel);
+        assert_loaded(&sequential);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+    #[test]
+    fn test_parallel_load_reports_every_failure() {
+        let data_dir = temp_data_dir("parload-errors");
+        let data_dir = data_dir.as_str();
+        populate(data_dir);
+        let broken = [
+            Path::new(data_dir).join("ks/loadks0/tbl1"),
+            Path::new(data_dir).join("ks/loadks2/tbl3"),
+        ];
+        for path in broken.iter() {
+            let file = fs::read(path).unwrap();
+            fs::write(path, &file[..file.len() - 3]).unwrap();
+        }
+        let e = unflush::read_full_with(&SnapshotConfig::default(), false, 4).unwrap_err();
+        override_data_dir(None);
+        let errors = match e {
+            StorageError::Several(errors) => errors,
+            e => panic!("Unexpected error: {:?}", e),
+        };
+        assert_eq!(errors.len(), broken.len());
+        // every failure is reported, although in no particular order
+        for path in broken.iter() {
+            assert!(errors
+                .iter()
+                .any(|e| matches!(e, StorageError::Corruption { file, .. } if file == path)));
+        }
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
+
+mod drop_tests {
+    use super::interface::override_data_dir;
+    use super::{flush, id, populate_store, temp_data_dir, unflush};
+    use crate::corestore::memstore::Memstore;
+    use crate::corestore::Data;
+    use std::fs;
+    use std::path::Path;
+
+    /// Flush the keyspace `dropks` with the tables `kept` and `gone` (each with a protected
+    /// key) to `data_dir` (which is left as the data directory of this thread)
+    fn populate(data_dir: &str) -> Memstore {
+        populate_store(data_dir, &["dropks"], &["kept", "gone"], |_, tbl, kve| {
+            kve.set(Data::from("hello"), Data::from(tbl)).unwrap();
+            kve.protect(Data::from("hello")).unwrap();
+        })
+    }
+    fn boot() -> Memstore {
+        super::boot().unwrap()
+    }
+    fn assert_only_kept(store: &Memstore) {
+        let keyspace = store.get_keyspace_atomic_ref(&id("dropks")).unwrap();
+        assert!(keyspace.get_table_atomic_ref(&id("gone")).is_none());
+        let kept = keyspace.get_table_atomic_ref(&id("kept")).unwrap();
+        assert_eq!(
+            kept.get_kvstore()
+                .unwrap()
+                .take_snapshot("hello".as_bytes()),
+            Some(Data::from("kept"))
+        );
+    }
+    #[test]
+    fn test_dropped_table_is_removed_from_disk() {
+        let data_dir = temp_data_dir("drop-table");
+        let data_dir = data_dir.as_str();
+        let store = populate(data_dir);
+        let keyspace = store.get_keyspace_atomic_ref(&id("dropks")).unwrap();
+        keyspace.drop_table(&id("gone")).unwrap();
+        flush::drop_table(&id("dropks"), &id("gone"), &keyspace).unwrap();
+        let ks_path = Path::new(data_dir).join("ks/dropks");
+        for file in ["gone", "gone.protected", "gone.dropped"].iter() {
+            assert!(!ks_path.join(file).exists(), "{} was left behind", file);
+        }
+        assert!(!unflush::read_partmap(&id("dropks"))
+            .unwrap()
+            .contains_key(&id("gone")));
+        let store = boot();
+        override_data_dir(None);
+        assert_only_kept(&store);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+    #[test]
+    fn test_table_dropped_before_a_crash_does_not_resurrect() {
+        let data_dir = temp_data_dir("drop-crash");
+        let data_dir = data_dir.as_str();
+        populate(data_dir);
+        // the server went down right after the tombstone was put in place: the PARTMAP still
+        // has the table and its protected keys are still there
+        let ks_path = Path::new(data_dir).join("ks/dropks");
+        fs::rename(ks_path.join("gone"), ks_path.join("gone.dropped")).unwrap();
+        let store = boot();
+        assert_only_kept(&store);
+        // the tombstone (and what was left of the table) has been cleaned up, and the
+        // PARTMAP no longer has the table
+        assert!(!ks_path.join("gone.dropped").exists());
+        assert!(!ks_path.join("gone.protected").exists());
+        assert!(!unflush::read_partmap(&id("dropks"))
+            .unwrap()
+            .contains_key(&id("gone")));
+        // so it stays dropped
+        let store = boot();
+        override_data_dir(None);
+        assert_only_kept(&store);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+    #[test]
+    fn test_keyspace_dropped_before_a_crash_does_not_resurrect() {
+        let data_dir = temp_data_dir("dropks-crash");
+        let data_dir = data_dir.as_str();
+        populate(data_dir);
+        let ks_root = Path::new(data_dir).join("ks");
+        fs::rename(ks_root.join("dropks"), ks_root.join("dropks.dropped")).unwrap();
+        let store = boot();
+        assert!(store.get_keyspace_atomic_ref(&id("dropks")).is_none());
+        assert!(!ks_root.join("dropks.dropped").exists());
+        assert!(!unflush::read_preload().unwrap().contains(&id("dropks")));
+        // and the whole drop, from the start
+        let store = populate(data_dir);
+        store.force_drop_keyspace(id("dropks")).unwrap();
+        flush::drop_keyspace(&id("dropks"), &store).unwrap();
+        assert!(!ks_root.join("dropks").exists());
+        assert!(!ks_root.join("dropks.dropped").exists());
+        let store = boot();
+        override_data_dir(None);
+        assert!(store.get_keyspace_atomic_ref(&id("dropks")).is_none());
+        assert!(store.get_keyspace_atomic_ref(&id("default")).is_some());
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
+
+mod activity_tests {
+    use super::interface::{create_tree, override_data_dir};
+    use super::{flush, unflush};
+    use crate::config::SnapshotConfig;
+    use crate::corestore::memstore::{Memstore, ObjectID, DEFAULT};
+    use crate::corestore::table::Table;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn test_activity_survives_a_flush() {
+        let data_dir = env::temp_dir().join(format!("skyd-activity-{}", process::id()));
+        let data_dir = data_dir.to_str().unwrap();
+        let _ = fs::remove_dir_all(data_dir);
+        let store = Memstore::new_default();
+        let keyspace = store.get_keyspace_atomic_ref(&DEFAULT).unwrap();
+        let (active, idle) =
+            unsafe { (ObjectID::from_slice("active"), ObjectID::from_slice("idle")) };
+        let table = Table::new_default_kve();
+        table.activity().restore(1_000, 2_000);
+        keyspace.create_table(active.clone(), table);
+        keyspace.create_table(idle.clone(), Table::new_default_kve());
+        override_data_dir(Some(data_dir));
+        create_tree(&store).unwrap();
+        flush::oneshot::flush_preload(&store).unwrap();
+        flush::flush_full(&store).unwrap();
+        let store = unflush::read_full(&SnapshotConfig::default(), false).unwrap();
+        override_data_dir(None);
+        let keyspace = store.get_keyspace_atomic_ref(&DEFAULT).unwrap();
+        let activity = keyspace.get_table_atomic_ref(&active).unwrap();
+        let activity = activity.activity();
+        assert_eq!(
+            (activity.last_write(), activity.last_read()),
+            (Some(1_000), Some(2_000))
+        );
+        // and a table that was never active stays that way
+        let idle = keyspace.get_table_atomic_ref(&idle).unwrap();
+        assert_eq!(idle.activity().last_active(), None);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
 }
 
 mod preload_tests {
     use super::*;
     use crate::corestore::memstore::Memstore;
+    use std::path::Path;
     #[test]
     fn test_preload() {
         let memstore = Memstore::new_default();
         let mut v = Vec::new();
         preload::raw_generate_preload(&mut v, &memstore).unwrap();
-        let de: Vec<String> = preload::read_preload_raw(v)
+        let de: Vec<String> = preload::read_preload_raw(v, Path::new("PRELOAD"))
             .unwrap()
             .into_iter()
             .map(|each| unsafe { each.as_str().to_owned() })
@@ -185,7 +561,8 @@ mod bytemark_set_tests {
         let ks = Keyspace::empty_default();
         let mut v = Vec::new();
         se::raw_serialize_partmap(&mut v, &ks).unwrap();
-        let ret: HashMap<ObjectID, (u8, u8)> = de::deserialize_set_ctype_bytemark(&v).unwrap();
+        let ret: HashMap<ObjectID, (u8, u8, Option<u64>, Option<(u64, u64)>)> =
+            de::deserialize_set_ctype_bytemark(&v).unwrap();
         let mut expected = HashMap::new();
         unsafe {
             expected.insert(
@@ -193,6 +570,8 @@ mod bytemark_set_tests {
                 (
                     bytemarks::BYTEMARK_STORAGE_PERSISTENT,
                     bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
                 ),
             );
         }
@@ -213,7 +592,8 @@ mod bytemark_set_tests {
         }
         let mut v = Vec::new();
         se::raw_serialize_partmap(&mut v, &ks).unwrap();
-        let ret: HashMap<ObjectID, (u8, u8)> = de::deserialize_set_ctype_bytemark(&v).unwrap();
+        let ret: HashMap<ObjectID, (u8, u8, Option<u64>, Option<(u64, u64)>)> =
+            de::deserialize_set_ctype_bytemark(&v).unwrap();
         let mut expected = HashMap::new();
         unsafe {
             // our cache is volatile
@@ -222,6 +602,8 @@ mod bytemark_set_tests {
                 (
                     bytemarks::BYTEMARK_STORAGE_VOLATILE,
                     bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
                 ),
             );
             // our supersafe is non volatile
@@ -230,10 +612,298 @@ mod bytemark_set_tests {
                 (
                     bytemarks::BYTEMARK_STORAGE_PERSISTENT,
                     bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
+                ),
+            );
+        }
+        assert_hmeq!(expected, ret);
+    }
+    #[test]
+    fn test_bytemark_with_value_limit() {
+        let ks = Keyspace::empty();
+        unsafe {
+            ks.create_table(
+                ObjectID::from_slice("limited"),
+                Table::new_default_kve().with_value_limit(Some(1024)),
+            );
+            ks.create_table(ObjectID::from_slice("unlimited"), Table::new_default_kve());
+        }
+        let mut v = Vec::new();
+        se::raw_serialize_partmap(&mut v, &ks).unwrap();
+        let ret: HashMap<ObjectID, (u8, u8, Option<u64>, Option<(u64, u64)>)> =
+            de::deserialize_set_ctype_bytemark(&v).unwrap();
+        let mut expected = HashMap::new();
+        unsafe {
+            expected.insert(
+                ObjectID::from_slice("limited"),
+                (
+                    bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT,
+                    bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    Some(1024),
+                    None,
+                ),
+            );
+            expected.insert(
+                ObjectID::from_slice("unlimited"),
+                (
+                    bytemarks::BYTEMARK_STORAGE_PERSISTENT,
+                    bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
+                ),
+            );
+        }
+        assert_hmeq!(expected, ret);
+        // a partition map that is cut off in the middle of the limit is bad data
+        assert!(de::deserialize_set_ctype_bytemark::<ObjectID>(&v[..v.len() - 1]).is_none());
+    }
+    #[test]
+    fn test_bytemark_with_read_cache() {
+        let ks = Keyspace::empty();
+        unsafe {
+            ks.create_table(
+                ObjectID::from_slice("cached"),
+                Table::new_kve_with_volatile(true).with_read_cache(true),
+            );
+        }
+        let mut v = Vec::new();
+        se::raw_serialize_partmap(&mut v, &ks).unwrap();
+        let ret: HashMap<ObjectID, (u8, u8, Option<u64>, Option<(u64, u64)>)> =
+            de::deserialize_set_ctype_bytemark(&v).unwrap();
+        let mut expected = HashMap::new();
+        unsafe {
+            expected.insert(
+                ObjectID::from_slice("cached"),
+                (
+                    bytemarks::BYTEMARK_STORAGE_VOLATILE
+                        | bytemarks::BYTEMARK_STORAGE_FLAG_READ_CACHE,
+                    bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
+                ),
+            );
+        }
+        assert_hmeq!(expected, ret);
+    }
+    #[test]
+    fn test_bytemark_with_activity() {
+        let ks = Keyspace::empty();
+        let active = Table::new_default_kve();
+        active.activity().restore(1_000, 2_000);
+        unsafe {
+            ks.create_table(ObjectID::from_slice("active"), active);
+            ks.create_table(ObjectID::from_slice("idle"), Table::new_default_kve());
+        }
+        let mut v = Vec::new();
+        se::raw_serialize_partmap(&mut v, &ks).unwrap();
+        let ret: HashMap<ObjectID, (u8, u8, Option<u64>, Option<(u64, u64)>)> =
+            de::deserialize_set_ctype_bytemark(&v).unwrap();
+        let mut expected = HashMap::new();
+        unsafe {
+            expected.insert(
+                ObjectID::from_slice("active"),
+                (
+                    bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY,
+                    bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    Some((1_000, 2_000)),
+                ),
+            );
+            expected.insert(
+                ObjectID::from_slice("idle"),
+                (
+                    bytemarks::BYTEMARK_STORAGE_PERSISTENT,
+                    bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
+                ),
+            );
+        }
+        assert_hmeq!(expected, ret);
+    }
+    #[test]
+    fn test_bytemark_archived() {
+        let ks = Keyspace::empty();
+        unsafe {
+            ks.create_table(ObjectID::from_slice("cold"), Table::new_default_kve());
+            ks.create_table(
+                ObjectID::from_slice("scratch"),
+                Table::new_kve_with_volatile(true),
+            );
+        }
+        ks.set_archived(true);
+        let mut v = Vec::new();
+        se::raw_serialize_partmap(&mut v, &ks).unwrap();
+        let ret: HashMap<ObjectID, (u8, u8, Option<u64>, Option<(u64, u64)>)> =
+            de::deserialize_set_ctype_bytemark(&v).unwrap();
+        let mut expected = HashMap::new();
+        unsafe {
+            expected.insert(
+                ObjectID::from_slice("cold"),
+                (
+                    bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED,
+                    bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
+                ),
+            );
+            expected.insert(
+                ObjectID::from_slice("scratch"),
+                (
+                    bytemarks::BYTEMARK_STORAGE_VOLATILE
+                        | bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED,
+                    bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+                    None,
+                    None,
                 ),
             );
         }
         assert_hmeq!(expected, ret);
+        assert!(ks.tables.iter().all(|table| table.value().is_archived()));
+    }
+}
+
+mod storage_errors {
+    use super::error::{BadRecord, StorageError};
+    use super::{flush, preload, unflush};
+    use crate::config::ParanoidMode;
+    use crate::corestore::memstore::{Memstore, ObjectID};
+    use crate::corestore::table::Table;
+    use crate::corestore::Data;
+    use crate::registry;
+    use std::fs;
+    use std::io::ErrorKind;
+    use std::path::Path;
+    #[test]
+    fn test_missing_table_file() {
+        fs::create_dir_all("data/ks/myks_errors").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_errors") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl_missing") };
+        let e = unflush::read_table(&ksid, &tblid, false, false, 0).unwrap_err();
+        match &e {
+            StorageError::Io { op, path, error } => {
+                assert_eq!(*op, "read");
+                assert_eq!(path, Path::new("data/ks/myks_errors/mytbl_missing"));
+                assert_eq!(error.kind(), ErrorKind::NotFound);
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        let rendered = e.to_string();
+        assert!(rendered.starts_with("failed to read `data/ks/myks_errors/mytbl_missing`"));
+    }
+    #[test]
+    fn test_truncated_table_file() {
+        fs::create_dir_all("data/ks/myks_errors").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_errors") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl_truncated") };
+        let tbl = Table::new_default_kve();
+        tbl.get_kvstore()
+            .unwrap()
+            .set("hello".into(), "world".into())
+            .unwrap();
+        flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        let path = Path::new("data/ks/myks_errors/mytbl_truncated");
+        let file = fs::read(path).unwrap();
+        fs::write(path, &file[..file.len() - 3]).unwrap();
+        let e = unflush::read_table(&ksid, &tblid, false, false, 0).unwrap_err();
+        assert!(
+            matches!(&e, StorageError::Corruption { file, .. } if file == path),
+            "Unexpected error: {:?}",
+            e
+        );
+        assert!(e
+            .to_string()
+            .contains("`data/ks/myks_errors/mytbl_truncated`"));
+    }
+    #[test]
+    fn test_checksum_mismatch_is_attributed_to_the_record() {
+        fn find(file: &[u8], needle: &[u8]) -> usize {
+            file.windows(needle.len())
+                .position(|window| window == needle)
+                .unwrap()
+        }
+        fs::create_dir_all("data/ks/myks_paranoid").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_paranoid") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl") };
+        registry::override_paranoid_mode(Some(ParanoidMode::Reject));
+        let tbl = Table::new_default_kve();
+        let kve = tbl.get_kvstore().unwrap();
+        kve.set("hello".into(), "world".into()).unwrap();
+        kve.set("sayan".into(), "writes code".into()).unwrap();
+        flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        let path = Path::new("data/ks/myks_paranoid/mytbl");
+        let mut file = fs::read(path).unwrap();
+        // flip a bit of the value of `sayan`, which turns `writes` into `wriTes`
+        let record_at = find(&file, b"sayan") - 16;
+        let index = if find(&file, b"hello") < record_at {
+            1
+        } else {
+            0
+        };
+        file[find(&file, b"writes code") + 3] ^= 0x20;
+        fs::write(path, &file).unwrap();
+        let read_value = |key: &'static str| {
+            let tbl = unflush::read_table(&ksid, &tblid, false, false, 0)?;
+            let value = tbl.get_kvstore().unwrap().get(Data::from(key)).unwrap();
+            Ok::<_, StorageError>(value.unwrap().clone())
+        };
+        let e = read_value("sayan").unwrap_err();
+        match &e {
+            StorageError::ChecksumMismatch { file, records } => {
+                assert_eq!(file, path);
+                assert_eq!(
+                    records,
+                    &[BadRecord {
+                        index,
+                        offset: record_at,
+                        key: Data::from("sayan"),
+                    }]
+                );
+            }
+            e => panic!("Unexpected error: {:?}", e),
+        }
+        assert!(e.to_string().ends_with(&format!(
+            "1 record(s) don't match their checksums, the first being record {} (at byte {}, \
+            with the key 'sayan')",
+            index, record_at
+        )));
+        // when the mismatches are only reported, the table is read in as it is on disk
+        registry::override_paranoid_mode(Some(ParanoidMode::Report));
+        let mismatches = registry::get_checksum_mismatches();
+        assert_eq!(read_value("sayan").unwrap(), Data::from("wriTes code"));
+        assert!(registry::get_checksum_mismatches() > mismatches);
+        // and without checksums, the file is read without verifying it
+        registry::override_paranoid_mode(Some(ParanoidMode::Off));
+        assert_eq!(read_value("hello").unwrap(), Data::from("world"));
+        // which is also how a file that was written without checksums is read
+        flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        registry::override_paranoid_mode(Some(ParanoidMode::Reject));
+        assert_eq!(read_value("sayan").unwrap(), Data::from("writes code"));
+        registry::override_paranoid_mode(None);
+    }
+    #[test]
+    fn test_truncated_and_foreign_preload() {
+        let path = Path::new("data/ks/PRELOAD");
+        let mut v = Vec::new();
+        preload::raw_generate_preload(&mut v, &Memstore::new_default()).unwrap();
+        let e = preload::read_preload_raw(v[..4].to_owned(), path).unwrap_err();
+        assert!(matches!(
+            &e,
+            StorageError::Corruption { file, offset: Some(4), .. } if file == path
+        ));
+        assert_eq!(
+            e.to_string(),
+            "failed to read `data/ks/PRELOAD`: corrupted at byte 4: the file is truncated"
+        );
+        // a preload from a storage format that we don't know of
+        v[0] ^= 0b0100_0000;
+        let e = preload::read_preload_raw(v, path).unwrap_err();
+        assert!(matches!(
+            &e,
+            StorageError::VersionMismatch { file, .. } if file == path
+        ));
+        assert!(e.to_string().contains("`data/ks/PRELOAD`"));
     }
 }
 
@@ -256,7 +926,7 @@ mod flush_routines {
         fs::create_dir_all("data/ks/myks1").unwrap();
         super::flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
         // now that it's flushed, let's read the table using and unflush routine
-        let ret = super::unflush::read_table(&ksid, &tblid, false, 0).unwrap();
+        let ret = super::unflush::read_table(&ksid, &tblid, false, false, 0).unwrap();
         assert_eq!(
             ret.get_kvstore()
                 .unwrap()
@@ -301,4 +971,1420 @@ mod flush_routines {
         );
         assert!(tbl2_ret.get_kvstore().unwrap().len() == 0);
     }
+    #[test]
+    fn test_lazy_load_defers_untouched_tables() {
+        fs::create_dir_all("data/ks/myks_lazy").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_lazy") };
+        let tbl1 = unsafe { ObjectID::from_slice("mytbl_1") };
+        let tbl2 = unsafe { ObjectID::from_slice("mytbl_2") };
+        let ks = Keyspace::empty();
+        for (tblid, value) in [(&tbl1, "one"), (&tbl2, "two")].iter() {
+            let tbl = Table::new_default_kve();
+            tbl.get_kvstore()
+                .unwrap()
+                .set("hello".into(), Data::from(*value))
+                .unwrap();
+            ks.create_table((*tblid).clone(), tbl);
+        }
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        // boot lazily
+        let ks = super::unflush::read_keyspace_with(&ksid, true).unwrap();
+        let tbl1_ret = ks.get_table_atomic_ref(&tbl1).unwrap();
+        let tbl2_ret = ks.get_table_atomic_ref(&tbl2).unwrap();
+        assert!(!tbl1_ret.is_loaded() && !tbl2_ret.is_loaded());
+        // touch the first table
+        let kve = tbl1_ret.get_kvstore().unwrap();
+        assert_eq!(
+            kve.get(Data::from("hello")).unwrap().unwrap().clone(),
+            Data::from("one")
+        );
+        kve.set("bye".into(), "world".into()).unwrap();
+        assert!(tbl1_ret.is_loaded());
+        assert!(!tbl2_ret.is_loaded());
+        // the flush writes out the first table but mustn't truncate the second one
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        assert!(!tbl2_ret.is_loaded());
+        let on_disk = |tblid: &str| {
+            super::de::deserialize_map(fs::read(format!("data/ks/myks_lazy/{}", tblid)).unwrap())
+                .unwrap()
+        };
+        assert_eq!(on_disk("mytbl_1").len(), 2);
+        assert_eq!(on_disk("mytbl_2").len(), 1);
+        // and the second table is read in once it is touched
+        assert_eq!(
+            tbl2_ret
+                .get_kvstore()
+                .unwrap()
+                .get(Data::from("hello"))
+                .unwrap()
+                .unwrap()
+                .clone(),
+            Data::from("two")
+        );
+        assert!(tbl2_ret.is_loaded());
+    }
+    #[test]
+    fn test_lazy_load_bloom_filter_skips_missing_keys() {
+        fs::create_dir_all("data/ks/myks_bloom").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_bloom") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl") };
+        let ks = Keyspace::empty();
+        let tbl = Table::new_default_kve();
+        tbl.get_kvstore()
+            .unwrap()
+            .set("hello".into(), "world".into())
+            .unwrap();
+        ks.create_table(tblid.clone(), tbl);
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        assert!(fs::metadata("data/ks/myks_bloom/mytbl.bloom").is_ok());
+        // boot lazily
+        let ks = super::unflush::read_keyspace_with(&ksid, true).unwrap();
+        let tbl = ks.get_table_atomic_ref(&tblid).unwrap();
+        // a missing key is answered by the filter, without reading in the table
+        assert!(tbl.surely_lacks(b"missing"));
+        assert!(!tbl.is_loaded());
+        // but a key that is there isn't
+        assert!(!tbl.surely_lacks(b"hello"));
+        assert_eq!(
+            tbl.get_kvstore()
+                .unwrap()
+                .get(Data::from("hello"))
+                .unwrap()
+                .unwrap()
+                .clone(),
+            Data::from("world")
+        );
+        assert!(tbl.is_loaded());
+        // and the filter is out of the picture once the table is read in
+        tbl.get_kvstore()
+            .unwrap()
+            .set("missing".into(), "now".into())
+            .unwrap();
+        assert!(!tbl.surely_lacks(b"missing"));
+    }
+    #[test]
+    fn test_lazy_load_ignores_bad_bloom_filters() {
+        fs::create_dir_all("data/ks/myks_badbloom").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_badbloom") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl") };
+        let ks = Keyspace::empty();
+        let tbl = Table::new_default_kve();
+        tbl.get_kvstore()
+            .unwrap()
+            .set("hello".into(), "world".into())
+            .unwrap();
+        ks.create_table(tblid.clone(), tbl);
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        let path = "data/ks/myks_badbloom/mytbl.bloom";
+        let filter = fs::read(path).unwrap();
+        let surely_lacks_missing = || {
+            let ks = super::unflush::read_keyspace_with(&ksid, true).unwrap();
+            let tbl = ks.get_table_atomic_ref(&tblid).unwrap();
+            tbl.surely_lacks(b"missing")
+        };
+        assert!(surely_lacks_missing());
+        // a corrupted filter
+        let mut corrupted = filter.clone();
+        corrupted[24] ^= 0xFF;
+        fs::write(path, corrupted).unwrap();
+        assert!(!surely_lacks_missing());
+        // a filter that was written with another file
+        fs::write(path, &filter).unwrap();
+        let table_path = "data/ks/myks_badbloom/mytbl";
+        let mut table = fs::read(table_path).unwrap();
+        table.extend_from_slice(b"junk");
+        fs::write(table_path, table).unwrap();
+        assert!(!surely_lacks_missing());
+        // and no filter at all
+        fs::remove_file(path).unwrap();
+        assert!(!surely_lacks_missing());
+    }
+    #[test]
+    fn test_default_table_survives_flush() {
+        fs::create_dir_all("data/ks/myks_default").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_default") };
+        let (tbl1, tbl2) = unsafe {
+            (
+                ObjectID::from_slice("mytbl_1"),
+                ObjectID::from_slice("mytbl_2"),
+            )
+        };
+        let ks = Keyspace::empty();
+        ks.create_table(tbl1.clone(), Table::new_default_kve());
+        ks.create_table(tbl2.clone(), Table::new_kve_with_volatile(true));
+        ks.set_default_table(tbl2.clone()).unwrap();
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        let ks = super::unflush::read_keyspace_with(&ksid, false).unwrap();
+        assert_eq!(ks.default_table(), tbl2);
+        // the flag doesn't hide the storage type
+        let ret = ks.get_default_table().unwrap();
+        assert!(ret.is_volatile());
+        // and the default can still be moved
+        ks.set_default_table(tbl1.clone()).unwrap();
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        let ks = super::unflush::read_keyspace_with(&ksid, false).unwrap();
+        assert_eq!(ks.default_table(), tbl1);
+    }
+    #[test]
+    fn test_grandfathered_names_load() {
+        use crate::queryengine::parser::get_query_entity;
+        // these were fine when they were created, but new containers can't be named like this
+        fs::create_dir_all("data/ks/_legacy$ks").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("_legacy$ks") };
+        let tblid = unsafe { ObjectID::from_slice("odd$tbl") };
+        let ks = Keyspace::empty();
+        ks.create_table(tblid.clone(), Table::new_default_kve());
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        let ks = super::unflush::read_keyspace_with(&ksid, false).unwrap();
+        assert!(ks.get_table_atomic_ref(&tblid).is_some());
+        // and they can still be referred to
+        assert!(get_query_entity(b"_legacy$ks:odd$tbl").is_ok());
+    }
+    #[tokio::test]
+    async fn test_flush_wait_survives_a_crash() {
+        use crate::registry::{self, FlushProgress};
+        use std::sync::Arc;
+        use tokio::time::{self, Duration};
+        fs::create_dir_all("data/ks/myks_flushwait").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_flushwait") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl") };
+        let ks = Arc::new(Keyspace::empty());
+        // the mutations have to be numbered in the change log
+        ks.create_table(
+            tblid.clone(),
+            Table::new_default_kve().with_entity(&ksid, &tblid),
+        );
+        // a flush service that only flushes when it is asked to
+        let progress = Arc::new(FlushProgress::default());
+        let service = {
+            let (ks, ksid, progress) = (ks.clone(), ksid.clone(), progress.clone());
+            tokio::spawn(async move {
+                loop {
+                    progress.flush_requested().await;
+                    progress.flush(|| super::flush::flush_keyspace_full(&ksid, &ks).is_ok());
+                }
+            })
+        };
+        let set = |key: &'static str| {
+            let tbl = ks.get_table_atomic_ref(&tblid).unwrap();
+            tbl.get_kvstore()
+                .unwrap()
+                .set(Data::from(key), Data::from("value"))
+                .unwrap()
+        };
+        assert!(set("durable"));
+        let seq = registry::get_changelog().current_seq();
+        let durable = time::timeout(Duration::from_secs(5), progress.wait_durable(seq))
+            .await
+            .unwrap();
+        assert!(durable);
+        assert!(progress.durable() >= seq);
+        // now crash: nothing is flushed from here on
+        service.abort();
+        assert!(set("lost"));
+        let ret = super::unflush::read_keyspace(&ksid).unwrap();
+        let tbl = ret.get(&tblid).unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        assert!(kve.exists(Data::from("durable"), 0).unwrap());
+        assert!(!kve.exists(Data::from("lost"), 0).unwrap());
+    }
+    #[test]
+    fn test_flush_unflush_protected_keys() {
+        let tbl = Table::new_default_kve();
+        let kve = tbl.get_kvstore().unwrap();
+        kve.set("hello".into(), "world".into()).unwrap();
+        kve.set("bye".into(), "world".into()).unwrap();
+        assert!(kve.protect("hello".into()).unwrap());
+        let tblid = unsafe { ObjectID::from_slice("mytbl_protected") };
+        let ksid = unsafe { ObjectID::from_slice("myks_protected") };
+        fs::create_dir_all("data/ks/myks_protected").unwrap();
+        super::flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        let ret = super::unflush::read_table(&ksid, &tblid, false, false, 0).unwrap();
+        let ret_kve = ret.get_kvstore().unwrap();
+        assert!(ret_kve.is_protected(b"hello"));
+        assert!(!ret_kve.is_protected(b"bye"));
+        // now remove the protection and make sure that it doesn't come back
+        assert!(kve.unprotect("hello".into()).unwrap());
+        super::flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        assert!(!fs::metadata("data/ks/myks_protected/mytbl_protected.protected").is_ok());
+        let ret = super::unflush::read_table(&ksid, &tblid, false, false, 0).unwrap();
+        assert!(!ret.get_kvstore().unwrap().is_protected(b"hello"));
+    }
+    #[test]
+    fn test_flush_unflush_expiries() {
+        let tbl = Table::new_default_kve();
+        let kve = tbl.get_kvstore().unwrap();
+        kve.set("hello".into(), "world".into()).unwrap();
+        kve.set("bye".into(), "world".into()).unwrap();
+        assert!(kve.set_expiry(b"hello", 1_000));
+        let tblid = unsafe { ObjectID::from_slice("mytbl_expiry") };
+        let ksid = unsafe { ObjectID::from_slice("myks_expiry") };
+        fs::create_dir_all("data/ks/myks_expiry").unwrap();
+        super::flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        let ret = super::unflush::read_table(&ksid, &tblid, false, false, 0).unwrap();
+        let ret_kve = ret.get_kvstore().unwrap();
+        assert_eq!(ret_kve.get_expiry(b"hello"), Some(1_000));
+        assert_eq!(ret_kve.get_expiry(b"bye"), None);
+        // the expiry goes away with the key
+        assert!(kve.remove(Data::from("hello")).unwrap());
+        super::flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        assert!(!fs::metadata("data/ks/myks_expiry/mytbl_expiry.expiry").is_ok());
+    }
+    #[test]
+    fn test_compact_table_drops_deleted_keys() {
+        fs::create_dir_all("data/ks/myks_compact").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_compact") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl_compact") };
+        let tbl = Table::new_default_kve();
+        let kve = tbl.get_kvstore().unwrap();
+        for i in 0..100 {
+            kve.set(Data::from(format!("key{}", i)), Data::from("value"))
+                .unwrap();
+        }
+        super::flush::oneshot::flush_table(&tblid, &ksid, &tbl).unwrap();
+        let flushed = fs::metadata("data/ks/myks_compact/mytbl_compact")
+            .unwrap()
+            .len();
+        for i in (0..100).step_by(2) {
+            assert!(kve.remove(Data::from(format!("key{}", i))).unwrap());
+        }
+        let (before, after) = super::flush::oneshot::compact_table(&tblid, &ksid, &tbl)
+            .unwrap()
+            .unwrap();
+        assert_eq!(before, flushed);
+        assert!(after < before);
+        assert!(!fs::metadata("data/ks/myks_compact/mytbl_compact_").is_ok());
+        let ret = super::unflush::read_table(&ksid, &tblid, false, false, 0).unwrap();
+        let ret_kve = ret.get_kvstore().unwrap();
+        assert_eq!(ret_kve.len(), 50);
+        for i in 0..100 {
+            let stored = ret_kve.get(Data::from(format!("key{}", i))).unwrap();
+            assert_eq!(stored.is_some(), i % 2 == 1);
+        }
+        // volatile tables don't have anything to compact
+        let volatile = Table::new_kve_with_volatile(true);
+        assert!(
+            super::flush::oneshot::compact_table(&tblid, &ksid, &volatile)
+                .unwrap()
+                .is_none()
+        );
+    }
+    #[test]
+    fn test_read_keyspace_removes_temp_files() {
+        fs::create_dir_all("data/ks/myks_temp").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_temp") };
+        let ks = Keyspace::empty();
+        // the temporary file of `t` would be `t_`, which is the file of this table
+        ks.create_table(
+            unsafe { ObjectID::from_slice("t") },
+            Table::new_default_kve(),
+        );
+        ks.create_table(
+            unsafe { ObjectID::from_slice("t_") },
+            Table::new_default_kve(),
+        );
+        ks.create_table(
+            unsafe { ObjectID::from_slice("u") },
+            Table::new_default_kve(),
+        );
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        // an interrupted flush leaves these behind
+        for temp_file in ["PARTMAP_", "u_", "u.protected_"].iter() {
+            fs::write(format!("data/ks/myks_temp/{}", temp_file), b"junk").unwrap();
+        }
+        let ret = super::unflush::read_keyspace(&ksid).unwrap();
+        assert_eq!(ret.len(), 3);
+        for temp_file in ["PARTMAP_", "u_", "u.protected_"].iter() {
+            assert!(!fs::metadata(format!("data/ks/myks_temp/{}", temp_file)).is_ok());
+        }
+        assert!(fs::metadata("data/ks/myks_temp/t_").is_ok());
+    }
+    #[test]
+    fn test_flush_unflush_rebuilds_ordered_index() {
+        // create the temp dir for this test
+        fs::create_dir_all("data/ks/myks_ordered").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_ordered") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl_ordered") };
+        let ks = Keyspace::empty();
+        let mytbl = Table::new_kve_with_data(Default::default(), false, true, false, false);
+        let kve = mytbl.get_kvstore().unwrap();
+        kve.set("b".into(), "2".into()).unwrap();
+        kve.set("a".into(), "1".into()).unwrap();
+        ks.create_table(tblid.clone(), mytbl);
+        super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        let ret = super::unflush::read_keyspace(&ksid).unwrap();
+        let tbl_ret = ret.get(&tblid).unwrap();
+        assert!(tbl_ret.is_ordered());
+        assert_eq!(
+            tbl_ret
+                .get_kvstore()
+                .unwrap()
+                .range_keys(b"a", b"z", usize::MAX)
+                .unwrap(),
+            vec![Data::from("a"), Data::from("b")]
+        );
+    }
+    #[test]
+    fn test_flush_reports_written_bytes() {
+        fs::create_dir_all("data/ks/myks_flushstats").unwrap();
+        let ksid = unsafe { ObjectID::from_slice("myks_flushstats") };
+        let tblid = unsafe { ObjectID::from_slice("mytbl") };
+        let ks = Keyspace::empty();
+        let mytbl = Table::new_default_kve();
+        let kve = mytbl.get_kvstore().unwrap();
+        kve.set("hello".into(), "world".into()).unwrap();
+        kve.set("sayan".into(), "code".into()).unwrap();
+        ks.create_table(tblid.clone(), mytbl);
+        // volatile tables aren't written out, so they aren't reported
+        let volatile = Table::new_kve_with_volatile(true);
+        volatile
+            .get_kvstore()
+            .unwrap()
+            .set("a".into(), "b".into())
+            .unwrap();
+        ks.create_table(unsafe { ObjectID::from_slice("myvolatile") }, volatile);
+        // what's on disk for the table (and the files that go with it)
+        let on_disk = || -> u64 {
+            fs::read_dir("data/ks/myks_flushstats")
+                .unwrap()
+                .map(|entry| entry.unwrap())
+                .filter(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    name.starts_with("mytbl") && !name.ends_with('_')
+                })
+                .map(|entry| entry.metadata().unwrap().len())
+                .sum()
+        };
+        let report = super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        assert_eq!(report.tables.len(), 1);
+        assert_eq!(report.tables[0].table, "myks_flushstats:mytbl");
+        assert_eq!(report.tables[0].dirty, 19);
+        assert_eq!(report.tables[0].bytes, on_disk());
+        assert_eq!(
+            report.metadata,
+            fs::metadata("data/ks/myks_flushstats/PARTMAP")
+                .unwrap()
+                .len()
+        );
+        assert_eq!(report.bytes(), report.tables[0].bytes + report.metadata);
+        assert_eq!(report.dirty(), 19);
+        assert_eq!(
+            report.write_amplification(),
+            Some(report.bytes() as f64 / 19.0)
+        );
+        // nothing was mutated, but the table is written out all over again
+        let report = super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        assert_eq!(report.dirty(), 0);
+        assert_eq!(report.tables[0].bytes, on_disk());
+        assert_eq!(report.write_amplification(), None);
+        // only what was mutated since the last flush counts
+        ks.get_table_atomic_ref(&tblid)
+            .unwrap()
+            .get_kvstore()
+            .unwrap()
+            .update("hello".into(), "there".into())
+            .unwrap();
+        let report = super::flush::flush_keyspace_full(&ksid, &ks).unwrap();
+        assert_eq!(report.dirty(), 10);
+        assert!(report.write_amplification().unwrap() > 1.0);
+    }
+}
+
+mod restore_tests {
+    use super::chain;
+    use super::restore::{restore_into, RestoreError, RestoreSummary};
+    use super::{de, flush, interface};
+    use crate::corestore::clock::MockClock;
+    use crate::corestore::memstore::{Memstore, ObjectID, DEFAULT};
+    use crate::corestore::table::Table;
+    use crate::corestore::{Corestore, Data, SnapshotStatus};
+    use crate::diskstore::snapshot::SnapshotEngine;
+    use chrono::Duration;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+
+    /// Write a snapshot of a store with two keys in `default:default` into `<root>/src` and
+    /// return the snapshot directory, the target ks directory and the marker's path
+    fn setup(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let root = Path::new("data/restore_tests").join(name);
+        let _ = fs::remove_dir_all(&root);
+        let src = root.join("src");
+        let store = Memstore::new_default();
+        let tbl = store
+            .get_keyspace_atomic_ref(&DEFAULT)
+            .unwrap()
+            .get_table_atomic_ref(&DEFAULT)
+            .unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        kve.set("hello".into(), "world".into()).unwrap();
+        kve.set("bye".into(), "world".into()).unwrap();
+        let mut buf = Vec::new();
+        interface::serialize_preload_into_slow_buffer(&mut buf, &store).unwrap();
+        fs::create_dir_all(&src).unwrap();
+        fs::write(src.join("PRELOAD"), buf).unwrap();
+        for ks in store.keyspaces.iter() {
+            let kspath = src.join(unsafe { ks.key().as_str() });
+            fs::create_dir_all(&kspath).unwrap();
+            let mut buf = Vec::new();
+            interface::serialize_partmap_into_slow_buffer(&mut buf, ks.value()).unwrap();
+            fs::write(kspath.join("PARTMAP"), buf).unwrap();
+            for tbl in ks.value().tables.iter() {
+                let mut buf = Vec::new();
+                let map = tbl.value().get_kvstore().unwrap().__get_inner_ref();
+                interface::serialize_map_into_slow_buffer(&mut buf, map).unwrap();
+                fs::write(kspath.join(unsafe { tbl.key().as_str() }), buf).unwrap();
+            }
+        }
+        (src, root.join("ks"), root.join("RESTORE_IN_PROGRESS"))
+    }
+
+    /// Put a stray table into the target directory
+    fn populate(ksroot: &Path) -> PathBuf {
+        fs::create_dir_all(ksroot.join("default")).unwrap();
+        let stray = ksroot.join("default").join("stray");
+        fs::write(&stray, b"stray").unwrap();
+        stray
+    }
+
+    fn expected_summary() -> RestoreSummary {
+        RestoreSummary {
+            keyspaces: 2,
+            tables: 1,
+            records: 2,
+            expired: 0,
+        }
+    }
+
+    #[test]
+    fn test_restore_into_empty() {
+        let (src, ksroot, marker) = setup("empty");
+        let summary = restore_into(&src, &ksroot, &marker, false, 0).unwrap();
+        assert_eq!(summary, expected_summary());
+        assert!(!marker.exists());
+        for file in [
+            "PRELOAD",
+            "default/PARTMAP",
+            "default/default",
+            "system/PARTMAP",
+        ] {
+            assert_eq!(
+                fs::read(ksroot.join(file)).unwrap(),
+                fs::read(src.join(file)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_restore_refuses_populated() {
+        let (src, ksroot, marker) = setup("populated");
+        let stray = populate(&ksroot);
+        assert!(matches!(
+            restore_into(&src, &ksroot, &marker, false, 0),
+            Err(RestoreError::DataDirNotEmpty)
+        ));
+        // nothing was touched
+        assert!(stray.exists());
+        assert!(!ksroot.join("PRELOAD").exists());
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_restore_force_overwrites() {
+        let (src, ksroot, marker) = setup("force");
+        let stray = populate(&ksroot);
+        let summary = restore_into(&src, &ksroot, &marker, true, 0).unwrap();
+        assert_eq!(summary, expected_summary());
+        assert!(!stray.exists());
+        assert!(ksroot.join("PRELOAD").is_file());
+        assert!(ksroot.join("default/default").is_file());
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_restore_redoes_interrupted() {
+        let (src, ksroot, marker) = setup("interrupted");
+        // a partial copy from the previous attempt
+        let stray = populate(&ksroot);
+        fs::write(&marker, b"").unwrap();
+        restore_into(&src, &ksroot, &marker, false, 0).unwrap();
+        assert!(!stray.exists());
+        assert!(ksroot.join("PRELOAD").is_file());
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_restore_drops_expired_keys() {
+        let root = Path::new("data/restore_tests").join("expired");
+        let _ = fs::remove_dir_all(&root);
+        let (ksroot, marker) = (root.join("ks"), root.join("RESTORE_IN_PROGRESS"));
+        let store = Memstore::new_default();
+        let tbl = store
+            .get_keyspace_atomic_ref(&DEFAULT)
+            .unwrap()
+            .get_table_atomic_ref(&DEFAULT)
+            .unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        for key in ["session:a", "session:b", "user:a"].iter() {
+            kve.set(Data::from(*key), "value".into()).unwrap();
+        }
+        // expire the sessions at 10s past the epoch, like `EXPIREPREFIX` would
+        for key in kve.keys_with_prefix(b"session:") {
+            assert!(kve.set_expiry(&key, 10_000));
+        }
+        flush::snap_flush_full("restore_tests_expired", &store).unwrap();
+        let src = Path::new("data/snaps/restore_tests_expired");
+        // nothing has expired yet, so everything is copied as it is
+        let summary = restore_into(src, &ksroot, &marker, false, 9_999).unwrap();
+        assert_eq!((summary.records, summary.expired), (3, 0));
+        assert_eq!(
+            fs::read(ksroot.join("default/default.expiry")).unwrap(),
+            fs::read(src.join("default/default.expiry")).unwrap()
+        );
+        // now restore after the sessions have lapsed
+        let summary = restore_into(src, &ksroot, &marker, true, 10_000).unwrap();
+        assert_eq!((summary.records, summary.expired), (1, 2));
+        let restored = fs::read(ksroot.join("default/default")).unwrap();
+        let restored = de::deserialize_map(restored).unwrap();
+        assert_eq!(restored.len(), 1);
+        assert!(restored.contains_key(b"user:a".as_ref()));
+        // no expiry times are left, so neither is the file
+        assert!(!ksroot.join("default/default.expiry").exists());
+    }
+
+    #[test]
+    fn test_restore_rejects_corrupted_table() {
+        let (src, ksroot, marker) = setup("corrupted");
+        fs::write(src.join("default/default"), b"garbage").unwrap();
+        assert!(matches!(
+            restore_into(&src, &ksroot, &marker, false, 0),
+            Err(RestoreError::BadSnapshot(path)) if path == src.join("default/default")
+        ));
+        assert!(!ksroot.exists());
+        assert!(!marker.exists());
+    }
+
+    #[tokio::test]
+    async fn test_restore_incremental_chain() {
+        let root = Path::new("data/restore_tests").join("incremental");
+        let _ = fs::remove_dir_all(&root);
+        let (ksroot, marker) = (root.join("ks"), root.join("RESTORE_IN_PROGRESS"));
+        let snap_path = |name: &str| Path::new("data/snaps").join(name);
+        let clock = Arc::new(MockClock::at(2015, 2, 1, 10, 0, 0));
+        let mut memstore = Memstore::new_default().with_clock(clock.clone());
+        memstore.snap_config = Some(SnapshotStatus::new(0));
+        let other = unsafe { ObjectID::from_slice("other") };
+        let ks = memstore.get_keyspace_atomic_ref(&DEFAULT).unwrap();
+        ks.create_table(
+            other.clone(),
+            Table::new_default_kve().with_entity(&DEFAULT, &other),
+        );
+        let store = Corestore::default_with_store(memstore);
+        let (tbl_default, tbl_other) = (
+            ks.get_table_atomic_ref(&DEFAULT).unwrap(),
+            ks.get_table_atomic_ref(&other).unwrap(),
+        );
+        let default = tbl_default.get_kvstore().unwrap();
+        let other = tbl_other.get_kvstore().unwrap();
+        // keep every snapshot so that none of the existing ones are evicted
+        let mut engine = SnapshotEngine::new(0, &store).unwrap();
+        default.set("k1".into(), "v1".into()).unwrap();
+        other.set("x".into(), "1".into()).unwrap();
+        assert!(engine.mksnap().await);
+        // only `default:default` is modified before the first incremental snapshot
+        clock.advance(Duration::seconds(1));
+        default.set("k2".into(), "v2".into()).unwrap();
+        assert!(default.remove(Data::from("k1")).unwrap());
+        assert!(engine.mksnap_incremental("20150201-100000").await.unwrap());
+        let first = snap_path("20150201-100001");
+        assert!(first.join("default/default").is_file());
+        assert!(!first.join("default/other").exists());
+        // and only `default:other` before the second one, which goes on top of the first
+        clock.advance(Duration::seconds(1));
+        other.set("y".into(), "2".into()).unwrap();
+        assert!(engine.mksnap_incremental("20150201-100000").await.unwrap());
+        let tip = snap_path("20150201-100002");
+        assert!(!tip.join("default/default").exists());
+        assert!(tip.join("default/other").is_file());
+        let link = chain::read_link(&tip).unwrap();
+        assert_eq!(
+            (link.parent.as_deref(), link.depth),
+            (Some("20150201-100001"), 2)
+        );
+        // restoring the tip brings back the latest version of every table
+        let summary = restore_into(&tip, &ksroot, &marker, false, 0).unwrap();
+        assert_eq!((summary.tables, summary.records), (2, 3));
+        for (file, table) in [("default/default", &default), ("default/other", &other)].iter() {
+            let restored = de::deserialize_map(fs::read(ksroot.join(file)).unwrap()).unwrap();
+            assert_eq!(restored.len(), table.len());
+            for pair in table.__get_inner_ref().iter() {
+                assert_eq!(restored.get(pair.key()).unwrap().value(), pair.value());
+            }
+        }
+        assert_eq!(
+            fs::read(ksroot.join("PRELOAD")).unwrap(),
+            fs::read(tip.join("PRELOAD")).unwrap()
+        );
+        // without the base, the chain is broken
+        fs::remove_dir_all(snap_path("20150201-100000")).unwrap();
+        assert!(matches!(
+            restore_into(&tip, &ksroot, &marker, true, 0),
+            Err(RestoreError::BrokenChain { snapshot, missing })
+                if snapshot == first && missing == "20150201-100000"
+        ));
+        assert!(!marker.exists());
+        for snap in [first, tip].iter() {
+            fs::remove_dir_all(snap).unwrap();
+        }
+    }
+}
+
+mod sync_dir_tests {
+    use super::chain::{self, ChainLink};
+    use super::error::StorageError;
+    use super::flush;
+    use super::interface::{self, override_data_dir, override_sync_dir};
+    use crate::config::ParanoidMode;
+    use crate::corestore::memstore::{Memstore, ObjectID};
+    use crate::corestore::table::Table;
+    use crate::corestore::Data;
+    use crate::registry;
+    use std::cell::RefCell;
+    use std::env;
+    use std::fs;
+    use std::io::{Error as IoError, ErrorKind, Result as IoResult};
+    use std::path::{Path, PathBuf};
+    use std::process;
+
+    thread_local! {
+        /// The directories that [`record`] was asked to sync on this thread
+        static SYNCED: RefCell<Vec<PathBuf>> = RefCell::new(Vec::new());
+    }
+
+    fn record(dir: &Path) -> IoResult<()> {
+        SYNCED.with(|synced| synced.borrow_mut().push(dir.to_owned()));
+        Ok(())
+    }
+    fn fail(_: &Path) -> IoResult<()> {
+        Err(IoError::new(ErrorKind::Other, "the disk is gone"))
+    }
+    /// The directories that were synced since the last call
+    fn take_synced() -> Vec<PathBuf> {
+        SYNCED.with(|synced| synced.borrow_mut().drain(..).collect())
+    }
+    fn id(name: &str) -> ObjectID {
+        unsafe { ObjectID::from_slice(name) }
+    }
+    /// Create the tree of a store with the keyspace `syncks` (and its table `tbl`) in the data
+    /// directory `name` (which is left as the data directory of this thread)
+    fn setup(name: &str) -> (PathBuf, Memstore) {
+        let data_dir = env::temp_dir().join(format!("skyd-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        override_data_dir(Some(data_dir.to_str().unwrap()));
+        let store = Memstore::new_default();
+        store.create_keyspace(id("syncks"));
+        let table = Table::new_default_kve();
+        table
+            .get_kvstore()
+            .unwrap()
+            .set(Data::from("hello"), Data::from("world"))
+            .unwrap();
+        store
+            .get_keyspace_atomic_ref(&id("syncks"))
+            .unwrap()
+            .create_table(id("tbl"), table);
+        interface::create_tree(&store).unwrap();
+        (data_dir, store)
+    }
+    fn teardown(data_dir: PathBuf) {
+        registry::override_paranoid_mode(None);
+        override_sync_dir(None);
+        override_data_dir(None);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+    #[test]
+    fn test_flushes_only_sync_directories_in_paranoid_mode() {
+        let (data_dir, store) = setup("sync-flush");
+        override_sync_dir(Some(record));
+        registry::override_paranoid_mode(Some(ParanoidMode::Off));
+        flush::flush_full(&store).unwrap();
+        assert!(take_synced().is_empty());
+        registry::override_paranoid_mode(Some(ParanoidMode::Report));
+        flush::flush_full(&store).unwrap();
+        let synced = take_synced();
+        for ks in ["default", "syncks"].iter() {
+            let ksdir = data_dir.join("ks").join(ks);
+            assert!(synced.contains(&ksdir), "{:?} wasn't synced", ksdir);
+        }
+        teardown(data_dir);
+    }
+    #[test]
+    fn test_snapshots_always_sync_their_directories() {
+        let (data_dir, store) = setup("sync-snapshot");
+        override_sync_dir(Some(record));
+        registry::override_paranoid_mode(Some(ParanoidMode::Off));
+        flush::snap_flush_full("remote/synced", &store).unwrap();
+        let snaproot = data_dir.join("snaps");
+        let snapdir = snaproot.join("remote/synced");
+        let synced = take_synced();
+        let (keyspaces, rest) = synced.split_at(synced.len() - 3);
+        for ks in ["default", "syncks"].iter() {
+            assert!(keyspaces.contains(&snapdir.join(ks)));
+        }
+        // and then every directory up to the snapshot directory, innermost first
+        assert_eq!(rest, [snapdir.clone(), snaproot.join("remote"), snaproot]);
+        chain::write_link(&snapdir, &ChainLink::full(1, 10)).unwrap();
+        assert_eq!(take_synced(), [snapdir]);
+        teardown(data_dir);
+    }
+    #[test]
+    fn test_failed_directory_syncs_are_storage_errors() {
+        let (data_dir, store) = setup("sync-error");
+        let ksdir = data_dir.join("ks/syncks");
+        override_sync_dir(Some(fail));
+        // compactions sync whatever the paranoid mode is
+        let table = store
+            .get_keyspace_atomic_ref(&id("syncks"))
+            .unwrap()
+            .get_table_atomic_ref(&id("tbl"))
+            .unwrap();
+        match flush::oneshot::compact_table(&id("tbl"), &id("syncks"), &table) {
+            Err(StorageError::Io { op, path, error }) => {
+                assert_eq!(op, "sync the directory");
+                assert_eq!(path, ksdir);
+                assert_eq!(error.kind(), ErrorKind::Other);
+            }
+            ret => panic!("unexpected result: {:?}", ret),
+        }
+        override_sync_dir(None);
+        interface::sync_dir(&ksdir).unwrap();
+        #[cfg(unix)]
+        match interface::sync_dir(data_dir.join("missing")) {
+            Err(StorageError::Io { op, error, .. }) => {
+                assert_eq!(op, "sync the directory");
+                assert_eq!(error.kind(), ErrorKind::NotFound);
+            }
+            ret => panic!("unexpected result: {:?}", ret),
+        }
+        teardown(data_dir);
+    }
+}
+
+mod attach_tests {
+    use super::attach::{read_snapshot, AttachError};
+    use super::flush;
+    use crate::corestore::memstore::{Memstore, ObjectID, DEFAULT};
+    use crate::corestore::table::Table;
+    use crate::corestore::Data;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    fn test_attach_reads_the_tables_read_only() {
+        let store = Memstore::new_default();
+        let ks = store.get_keyspace_atomic_ref(&DEFAULT).unwrap();
+        let big = unsafe { ObjectID::from_slice("big") };
+        ks.create_table(
+            big.clone(),
+            Table::new_default_kve().with_entity(&DEFAULT, &big),
+        );
+        let tbl = ks.get_table_atomic_ref(&DEFAULT).unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        kve.set("user:a".into(), "a".into()).unwrap();
+        kve.set("session:a".into(), "a".into()).unwrap();
+        assert!(kve.set_expiry(b"session:a", 10_000));
+        let tbl = ks.get_table_atomic_ref(&big).unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        for i in 0..100 {
+            kve.set(
+                Data::from(format!("key:{}", i)),
+                Data::from(vec![b'x'; 100]),
+            )
+            .unwrap();
+        }
+        flush::snap_flush_full("attach_tests", &store).unwrap();
+        let src = Path::new("data/snaps/attach_tests");
+        let attached = read_snapshot(src, None, 10_000).unwrap();
+        let (keyspace, needed) = (attached.keyspace, attached.bytes);
+        assert_eq!(attached.tables, 2);
+        assert!(keyspace.is_read_only());
+        // the tables are named after their keyspaces, and `default:default` is the default
+        assert_eq!(keyspace.default_table(), unsafe {
+            ObjectID::from_slice("default_default")
+        });
+        let tbl = keyspace.get_default_table().unwrap();
+        assert!(tbl.is_read_only());
+        let kve = tbl.get_kvstore().unwrap();
+        // the session had expired
+        assert_eq!(kve.len(), 1);
+        assert_eq!(
+            kve.take_snapshot("user:a".as_bytes()),
+            Some(Data::from("a"))
+        );
+        let tbl = keyspace
+            .get_table_atomic_ref(&unsafe { ObjectID::from_slice("default_big") })
+            .unwrap();
+        assert!(tbl.is_read_only());
+        assert_eq!(tbl.get_kvstore().unwrap().len(), 100);
+        // whatever was read is dropped once the tables don't fit
+        assert!(matches!(
+            read_snapshot(src, Some(needed - 1), 10_000),
+            Err(AttachError::OutOfMemory { available }) if available == needed - 1
+        ));
+        assert!(read_snapshot(src, Some(needed * 2), 10_000).is_ok());
+        fs::remove_dir_all(src).unwrap();
+    }
+}
+
+mod writer_tests {
+    use super::flush;
+    use super::writer::{self, Direct, FileWriter};
+    use crate::corestore::memstore::{Keyspace, Memstore, ObjectID, DEFAULT};
+    use crate::corestore::table::Table;
+    use crate::corestore::Data;
+    use crate::registry::{self, IoKind, IoThrottle};
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
+
+    fn keyspace(tables: usize, keys: usize) -> Keyspace {
+        let ks = Keyspace::empty_default();
+        for tbl in 0..tables {
+            let table = Table::new_default_kve();
+            let kve = table.get_kvstore().unwrap();
+            for key in 0..keys {
+                kve.set(
+                    Data::from(format!("key:{}", key)),
+                    Data::from(vec![b'x'; 100]),
+                )
+                .unwrap();
+            }
+            assert!(kve.protect("key:0".into()).unwrap());
+            assert!(kve.set_expiry(b"key:1", 10_000));
+            ks.create_table(
+                unsafe { ObjectID::from_slice(&format!("tbl{}", tbl)) },
+                table,
+            );
+        }
+        ks
+    }
+    /// Flush `ks` to `ksdir` (through `writer`, if there's one) and return every file in it
+    fn flush_with(
+        ksname: &str,
+        ks: &Keyspace,
+        writer: Option<Box<dyn FileWriter>>,
+    ) -> Vec<(String, Vec<u8>)> {
+        let ksdir = format!("data/ks/{}", ksname);
+        let _ = fs::remove_dir_all(&ksdir);
+        fs::create_dir_all(&ksdir).unwrap();
+        let ksid = unsafe { ObjectID::from_slice(ksname) };
+        match writer {
+            Some(writer) => {
+                writer::batched_with(writer, || flush::flush_keyspace_full(&ksid, ks)).unwrap()
+            }
+            None => flush::flush_keyspace_full(&ksid, ks).unwrap(),
+        };
+        let mut files: Vec<_> = fs::read_dir(&ksdir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                (name, fs::read(path).unwrap())
+            })
+            .collect();
+        files.sort();
+        fs::remove_dir_all(&ksdir).unwrap();
+        files
+    }
+    #[test]
+    fn test_batched_flush_writes_the_same_files() {
+        let ks = keyspace(3, 100);
+        let direct = flush_with("myks_writer", &ks, None);
+        // the files of our tables (with their sidecars), those of the empty default table
+        // (which only has a bloom filter) and the partmap
+        assert_eq!(direct.len(), 3 * 4 + 2 + 1);
+        assert!(direct.iter().all(|(name, _)| !name.ends_with('_')));
+        let batched = flush_with("myks_writer", &ks, Some(Box::new(Direct)));
+        assert_eq!(direct, batched);
+    }
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    #[test]
+    fn test_uring_flush_writes_the_same_files() {
+        use super::uring::UringWriter;
+        let ks = keyspace(3, 100);
+        let direct = flush_with("myks_uring", &ks, None);
+        let writer = match UringWriter::new() {
+            Some(writer) => writer,
+            None => return eprintln!("io_uring isn't available, skipping"),
+        };
+        let uring = flush_with("myks_uring", &ks, Some(Box::new(writer)));
+        assert_eq!(direct, uring);
+    }
+    /// Returns every file under `dir` (with its path relative to `dir`), sorted by path
+    fn files_under(dir: &Path) -> Vec<(PathBuf, Vec<u8>)> {
+        let mut files = Vec::new();
+        let mut dirs = vec![dir.to_owned()];
+        while let Some(next) = dirs.pop() {
+            for entry in fs::read_dir(next).unwrap() {
+                let path = entry.unwrap().path();
+                if path.is_dir() {
+                    dirs.push(path);
+                } else {
+                    let data = fs::read(&path).unwrap();
+                    files.push((path.strip_prefix(dir).unwrap().to_owned(), data));
+                }
+            }
+        }
+        files.sort();
+        files
+    }
+    #[test]
+    fn test_throttled_snapshot_is_paced_and_writes_the_same_files() {
+        let store = Memstore::new_default();
+        let ks = store.get_keyspace_atomic_ref(&DEFAULT).unwrap();
+        let tbl = ks.get_table_atomic_ref(&DEFAULT).unwrap();
+        let kve = tbl.get_kvstore().unwrap();
+        for key in 0..200 {
+            kve.set(
+                Data::from(format!("key:{}", key)),
+                Data::from(vec![b'x'; 100]),
+            )
+            .unwrap();
+        }
+        let throttle: &'static IoThrottle = Box::leak(Box::new(IoThrottle::new()));
+        registry::override_io_throttle(Some(throttle));
+        flush::snap_flush_full("writer_tests_unthrottled", &store).unwrap();
+        let rate = 16 * 1024;
+        throttle.set_limit(IoKind::Snapshot, Some(rate));
+        let estimated = flush::estimated_snapshot_bytes(&store, |_| true);
+        assert!(estimated > rate);
+        let start = Instant::now();
+        flush::snap_flush_full("writer_tests_throttled", &store).unwrap();
+        let took = start.elapsed();
+        registry::override_io_throttle(None);
+        // at most a tenth of a second of writes goes through without being paced
+        let paced = (estimated - rate / 10) as f64 / rate as f64;
+        assert!(
+            took >= Duration::from_secs_f64(paced),
+            "the snapshot took {:?}, which is less than {}s",
+            took,
+            paced
+        );
+        assert_eq!(throttle.snapshot_progress().bytes(), None);
+        let unthrottled = Path::new("data/snaps/writer_tests_unthrottled");
+        let throttled = Path::new("data/snaps/writer_tests_throttled");
+        assert_eq!(files_under(unthrottled), files_under(throttled));
+        fs::remove_dir_all(unthrottled).unwrap();
+        fs::remove_dir_all(throttled).unwrap();
+    }
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    #[test]
+    #[ignore]
+    fn bench_flush_backends() {
+        use super::uring::UringWriter;
+        use std::time::Instant;
+        for (tables, keys) in [(8, 100_000), (256, 1_000), (1024, 100)].iter().copied() {
+            let ks = keyspace(tables, keys);
+            let start = Instant::now();
+            flush_with("myks_bench", &ks, None);
+            let direct = start.elapsed();
+            let start = Instant::now();
+            flush_with(
+                "myks_bench",
+                &ks,
+                Some(Box::new(
+                    UringWriter::new().expect("io_uring isn't available"),
+                )),
+            );
+            let uring = start.elapsed();
+            println!(
+                "{:>4} tables of {:>6} keys: direct {:?}, io_uring {:?}",
+                tables, keys, direct, uring
+            );
+        }
+    }
+}
+
+mod migrate_tests {
+    use super::interface::override_data_dir;
+    use super::migrate::{self, Profile, MIGRATION_JOURNAL};
+    use super::{id, populate_store, temp_data_dir, unflush};
+    use crate::corestore::Data;
+    use std::fs;
+    use std::path::Path;
+
+    const TABLES: usize = 4;
+    const KEYS: usize = 100;
+
+    fn versions(data_dir: &str) -> Vec<Option<u8>> {
+        (0..TABLES)
+            .map(|tbl| {
+                let path = Path::new(data_dir).join(format!("ks/migks/tbl{}", tbl));
+                super::checksummed_map_version(&fs::read(path).unwrap())
+            })
+            .collect()
+    }
+    #[test]
+    fn test_interrupted_migration_resumes() {
+        let data_dir = temp_data_dir("migrate");
+        let data_dir = data_dir.as_str();
+        let tables: Vec<String> = (0..TABLES).map(|tbl| format!("tbl{}", tbl)).collect();
+        populate_store(data_dir, &["migks"], &tables, |_, tbl, kve| {
+            for key in 0..KEYS {
+                kve.set(
+                    Data::from(format!("key{}", key)),
+                    Data::from(format!("{}:{}", tbl, key)),
+                )
+                .unwrap();
+            }
+        });
+        assert!(versions(data_dir).iter().all(Option::is_none));
+        // fail the first run after a table, as if the server was killed
+        migrate::override_interrupt(Some(1));
+        migrate::migrate(Profile::Checksummed).unwrap_err();
+        migrate::override_interrupt(None);
+        let journal = Path::new(data_dir).join(MIGRATION_JOURNAL);
+        // the profile and the one table that was done
+        assert_eq!(fs::read_to_string(&journal).unwrap().lines().count(), 2);
+        // the second run only converts what's left (which includes the tables of the default
+        // keyspace)
+        let table_count: usize = unflush::read_preload()
+            .unwrap()
+            .iter()
+            .map(|ksid| unflush::read_partmap(ksid).unwrap().len())
+            .sum();
+        let report = migrate::migrate(Profile::Checksummed).unwrap();
+        assert_eq!(report.skipped, 1);
+        assert_eq!(report.converted + report.without_file, table_count - 1);
+        assert!(report.bytes_after > report.bytes_before);
+        assert!(!journal.exists());
+        assert!(versions(data_dir)
+            .iter()
+            .all(|v| *v == Some(super::CHECKSUMMED_MAP_VERSION)));
+        let loaded = super::boot().unwrap();
+        let keyspace = loaded.get_keyspace_atomic_ref(&id("migks")).unwrap();
+        for tbl in tables.iter() {
+            let table = keyspace.get_table_atomic_ref(&id(tbl)).unwrap();
+            let kve = table.get_kvstore().unwrap();
+            assert_eq!(kve.len(), KEYS);
+            assert_eq!(
+                kve.take_snapshot(b"key7"),
+                Some(Data::from(format!("{}:7", tbl)))
+            );
+        }
+        // and migrating back to plain starts over, since no migration is interrupted
+        let report = migrate::migrate(Profile::Plain).unwrap();
+        assert_eq!(report.skipped, 0);
+        assert_eq!(report.converted + report.without_file, table_count);
+        assert!(versions(data_dir).iter().all(Option::is_none));
+        override_data_dir(None);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+}
+
+mod reconcile_tests {
+    use super::error::StorageError;
+    use super::interface::override_data_dir;
+    use super::reconcile::{OrphanAction, QUARANTINE_DIR};
+    use super::{flush, id, populate_store, temp_data_dir, unflush};
+    use crate::config::OrphanPolicy;
+    use crate::corestore::memstore::Memstore;
+    use crate::corestore::Data;
+    use crate::registry;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    const KEYS: usize = 10;
+
+    /// Flush the keyspace `recks` with the tables `a` and `b` to a data directory of its own
+    /// (which is left as the data directory of this thread), returning the directory of the
+    /// keyspace
+    fn populate(name: &str) -> (String, PathBuf) {
+        let data_dir = temp_data_dir(&format!("reconcile-{}", name));
+        populate_store(&data_dir, &["recks"], &["a", "b"], |_, tbl, kve| {
+            for key in 0..KEYS {
+                kve.set(Data::from(format!("key{}", key)), Data::from(tbl))
+                    .unwrap();
+            }
+        });
+        let ks_path = Path::new(&data_dir).join("ks/recks");
+        (data_dir, ks_path)
+    }
+    /// Leave an orphan with a file of its own (a copy of `a`) and one that only has an expiry
+    /// map behind in the keyspace
+    fn add_orphans(ks_path: &Path) {
+        fs::copy(ks_path.join("a"), ks_path.join("orphan")).unwrap();
+        fs::write(ks_path.join("ghost.expiry"), b"junk").unwrap();
+    }
+    fn boot(policy: OrphanPolicy, allow_missing: bool) -> Result<Memstore, StorageError> {
+        registry::override_reconcile(Some((policy, allow_missing)));
+        let ret = super::boot();
+        registry::override_reconcile(None);
+        ret
+    }
+    fn finish(data_dir: &str) {
+        override_data_dir(None);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+    fn table_len(store: &Memstore, tbl: &str) -> Option<usize> {
+        let keyspace = store.get_keyspace_atomic_ref(&id("recks")).unwrap();
+        let table = keyspace.get_table_atomic_ref(&id(tbl))?;
+        let len = table.get_kvstore().unwrap().len();
+        Some(len)
+    }
+    #[test]
+    fn test_orphans_fail_the_startup() {
+        let (data_dir, ks_path) = populate("fail");
+        add_orphans(&ks_path);
+        let report = match boot(OrphanPolicy::Fail, false) {
+            Err(StorageError::Unreconciled(report)) => report,
+            r => panic!("Unexpected result: {:?}", r.map(|_| ())),
+        };
+        let orphans: Vec<(&str, &[String])> = report
+            .orphans
+            .iter()
+            .map(|orphan| (orphan.table.as_str(), &orphan.files[..]))
+            .collect();
+        assert_eq!(
+            orphans,
+            vec![
+                ("ghost", &["ghost.expiry".to_owned()][..]),
+                ("orphan", &["orphan".to_owned()][..])
+            ]
+        );
+        assert!(report
+            .orphans
+            .iter()
+            .all(|orphan| orphan.action == OrphanAction::Reported));
+        assert!(report.missing.is_empty());
+        // nothing was touched
+        assert!(ks_path.join("orphan").is_file());
+        assert!(ks_path.join("ghost.expiry").is_file());
+        assert!(!ks_path.join(QUARANTINE_DIR).exists());
+        finish(&data_dir);
+    }
+    #[test]
+    fn test_orphans_are_quarantined() {
+        let (data_dir, ks_path) = populate("quarantine");
+        add_orphans(&ks_path);
+        let store = boot(OrphanPolicy::Quarantine, false).unwrap();
+        assert_eq!(table_len(&store, "a"), Some(KEYS));
+        assert_eq!(table_len(&store, "orphan"), None);
+        let quarantine = ks_path.join(QUARANTINE_DIR);
+        for file in ["orphan", "ghost.expiry"].iter() {
+            assert!(!ks_path.join(file).exists());
+            assert!(quarantine.join(file).is_file());
+        }
+        // the names in the quarantine aren't reused
+        add_orphans(&ks_path);
+        let store = boot(OrphanPolicy::Quarantine, false).unwrap();
+        assert_eq!(table_len(&store, "orphan"), None);
+        assert!(quarantine.join("orphan.1").is_file());
+        assert!(quarantine.join("ghost.expiry.1").is_file());
+        // and the quarantine is no orphan itself
+        assert!(boot(OrphanPolicy::Fail, false).is_ok());
+        finish(&data_dir);
+    }
+    #[test]
+    fn test_orphans_are_adopted() {
+        let (data_dir, ks_path) = populate("adopt");
+        add_orphans(&ks_path);
+        let store = boot(OrphanPolicy::Adopt, false).unwrap();
+        assert_eq!(table_len(&store, "orphan"), Some(KEYS));
+        let keyspace = store.get_keyspace_atomic_ref(&id("recks")).unwrap();
+        let orphan = keyspace.get_table_atomic_ref(&id("orphan")).unwrap();
+        assert_eq!(orphan.model_name(), "keymap(binstr,binstr)");
+        assert!(!orphan.is_volatile());
+        // there's nothing to adopt for an expiry map on its own
+        assert!(ks_path.join(QUARANTINE_DIR).join("ghost.expiry").is_file());
+        // the adopted table is in the PARTMAP now
+        assert!(unflush::read_partmap(&id("recks"))
+            .unwrap()
+            .contains_key("orphan".as_bytes()));
+        let store = boot(OrphanPolicy::Fail, false).unwrap();
+        assert_eq!(table_len(&store, "orphan"), Some(KEYS));
+        finish(&data_dir);
+    }
+    #[test]
+    fn test_missing_tables_fail_the_startup() {
+        let (data_dir, ks_path) = populate("missing");
+        fs::remove_file(ks_path.join("b")).unwrap();
+        for policy in [
+            OrphanPolicy::Fail,
+            OrphanPolicy::Adopt,
+            OrphanPolicy::Quarantine,
+        ]
+        .iter()
+        {
+            let report = match boot(*policy, false) {
+                Err(StorageError::Unreconciled(report)) => report,
+                r => panic!("Unexpected result: {:?}", r.map(|_| ())),
+            };
+            assert!(report.orphans.is_empty());
+            assert_eq!(report.missing.len(), 1);
+            assert_eq!(
+                (
+                    report.missing[0].keyspace.as_str(),
+                    report.missing[0].table.as_str()
+                ),
+                ("recks", "b")
+            );
+            assert!(report.to_string().contains("`recks:b`"));
+        }
+        finish(&data_dir);
+    }
+    #[test]
+    fn test_missing_tables_are_loaded_empty_if_allowed() {
+        let (data_dir, ks_path) = populate("allowmissing");
+        fs::remove_file(ks_path.join("b")).unwrap();
+        let store = boot(OrphanPolicy::Fail, true).unwrap();
+        assert_eq!(table_len(&store, "a"), Some(KEYS));
+        assert_eq!(table_len(&store, "b"), Some(0));
+        // the table has a file again after the next flush
+        flush::flush_full(&store).unwrap();
+        assert!(ks_path.join("b").is_file());
+        assert!(boot(OrphanPolicy::Fail, false).is_ok());
+        finish(&data_dir);
+    }
+}
+
+mod name_tests {
+    use super::error::StorageError;
+    use super::interface::override_data_dir;
+    use super::{flush, id, populate_store, temp_data_dir};
+    use crate::corestore::memstore::Memstore;
+    use crate::corestore::Data;
+    use crate::registry;
+    use std::fs;
+    use std::path::Path;
+
+    /// Flush the `keyspaces`, each with the `tables` (which have their name as the value of
+    /// `key`), to a data directory of its own (which is left as the data directory of this
+    /// thread). The names are case sensitive here
+    fn populate(name: &str, keyspaces: &[&str], tables: &[&str]) -> String {
+        let data_dir = temp_data_dir(&format!("names-{}", name));
+        registry::override_case_insensitive(Some(false));
+        populate_store(&data_dir, keyspaces, tables, |_, tbl, kve| {
+            kve.set(Data::from("key"), Data::from(tbl)).unwrap();
+        });
+        data_dir
+    }
+    fn boot(case_insensitive: bool) -> Result<Memstore, StorageError> {
+        registry::override_case_insensitive(Some(case_insensitive));
+        super::boot()
+    }
+    fn finish(data_dir: &str) {
+        registry::override_case_insensitive(None);
+        override_data_dir(None);
+        fs::remove_dir_all(data_dir).unwrap();
+    }
+    #[test]
+    fn test_names_that_collide_fail_the_startup() {
+        let data_dir = populate(
+            "collide",
+            &["Users", "users", "logs"],
+            &["Events", "EVENTS", "plain"],
+        );
+        let collisions = match boot(true) {
+            Err(StorageError::NameCollisions(collisions)) => collisions,
+            r => panic!("Unexpected result: {:?}", r.map(|_| ())),
+        };
+        assert_eq!(
+            collisions,
+            vec![
+                "the keyspaces 'Users', 'users'",
+                "the tables 'EVENTS', 'Events' of the keyspace 'Users'",
+                "the tables 'EVENTS', 'Events' of the keyspace 'logs'",
+                "the tables 'EVENTS', 'Events' of the keyspace 'users'",
+            ]
+        );
+        // nothing was touched, so it still starts case sensitively
+        let ks_root = Path::new(&data_dir).join("ks");
+        assert!(ks_root.join("Users/Events").is_file());
+        assert!(ks_root.join("Users/EVENTS").is_file());
+        assert!(boot(false).is_ok());
+        finish(&data_dir);
+    }
+    #[test]
+    fn test_names_keep_their_case_when_switching() {
+        let data_dir = populate("switch", &["Users"], &["Events"]);
+        let ks_root = Path::new(&data_dir).join("ks");
+        let check = |store: &Memstore, ksid: &str, tblid: &str| {
+            let keyspace = store.get_keyspace_atomic_ref(&id(ksid)).unwrap();
+            let table = keyspace.get_table_atomic_ref(&id(tblid)).unwrap();
+            let kve = table.get_kvstore().unwrap();
+            let value = kve.get_cloned(Data::from("key"), 0).unwrap().unwrap();
+            assert_eq!(value, "Events".as_bytes());
+            keyspace
+        };
+        // the directory and the files are renamed after the keys, while the names stay
+        let store = boot(true).unwrap();
+        assert!(ks_root.join("users/events").is_file());
+        assert!(!ks_root.join("Users").exists());
+        let keyspace = check(&store, "USERS", "events");
+        assert_eq!(keyspace.name(), Some(&id("Users")));
+        assert_eq!(keyspace.table_name(&id("events")), id("Events"));
+        // which is how they're written out again
+        flush::oneshot::flush_preload(&store).unwrap();
+        flush::flush_full(&store).unwrap();
+        drop((keyspace, store));
+        check(&boot(true).unwrap(), "users", "EVENTS");
+        // and back
+        let store = boot(false).unwrap();
+        assert!(ks_root.join("Users/Events").is_file());
+        assert!(!ks_root.join("users").exists());
+        let keyspace = check(&store, "Users", "Events");
+        assert_eq!(keyspace.name(), None);
+        assert!(store.get_keyspace_atomic_ref(&id("users")).is_none());
+        drop((keyspace, store));
+        finish(&data_dir);
+    }
+}
+
+mod size_tests {
+    use super::interface::override_data_dir;
+    use super::{id, populate_store, temp_data_dir};
+    use crate::corestore::memstore::Memstore;
+    use crate::corestore::Data;
+    use std::fs;
+
+    #[test]
+    fn test_size_histograms_are_rebuilt_on_load() {
+        let data_dir = temp_data_dir("sizes");
+        let data_dir = data_dir.as_str();
+        let table_of = |store: &Memstore| {
+            store
+                .get_keyspace_atomic_ref(&id("sizeks"))
+                .unwrap()
+                .get_table_atomic_ref(&id("sizes"))
+                .unwrap()
+        };
+        let store = populate_store(data_dir, &["sizeks"], &["sizes"], |_, _, kve| {
+            for len in 1..=64 {
+                kve.set(Data::from(vec![b'k'; len]), Data::from(vec![b'v'; len * 2]))
+                    .unwrap();
+            }
+            // the longest pair is gone by the time of the flush
+            assert!(kve.remove(Data::from(vec![b'k'; 64])).unwrap());
+        });
+        let table = table_of(&store);
+        let kve = table.get_kvstore().unwrap();
+        let loaded = super::boot().unwrap();
+        override_data_dir(None);
+        let reloaded = table_of(&loaded);
+        let reloaded = reloaded.get_kvstore().unwrap();
+        // the same distribution, but the largest sizes are the largest of what was flushed
+        let (keys, values) = (kve.key_sizes().values(), kve.value_sizes().values());
+        let (loaded_keys, loaded_values) = (
+            reloaded.key_sizes().values(),
+            reloaded.value_sizes().values(),
+        );
+        assert_eq!(loaded_keys.counts, keys.counts);
+        assert_eq!(loaded_values.counts, values.counts);
+        assert_eq!(loaded_keys.sum, keys.sum);
+        assert_eq!((keys.max, values.max), (64, 128));
+        assert_eq!((loaded_keys.max, loaded_values.max), (63, 126));
+        assert_eq!(loaded_keys.compact(), "1:1 3:2 7:4 15:8 31:16 63:32");
+        fs::remove_dir_all(data_dir).unwrap();
+    }
 }
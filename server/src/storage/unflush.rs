@@ -28,95 +28,756 @@
 //!
 //! Routines for unflushing data
 
+use super::bloom::BloomFilter;
 use super::bytemarks;
+use crate::config::OrphanPolicy;
+use crate::corestore::memstore;
 use crate::corestore::memstore::Keyspace;
 use crate::corestore::memstore::Memstore;
 use crate::corestore::memstore::ObjectID;
 use crate::corestore::table::Table;
-use crate::storage::interface::DIR_KSROOT;
+use crate::corestore::Data;
+use crate::registry;
+use crate::storage::error::{BadRecord, StorageError, StorageResult};
+use crate::storage::interface::dir_ksroot;
+use crate::storage::interface::sync_dir;
+use crate::storage::interface::ThreadDataDir;
+use crate::storage::interface::BLOOM_FILTER_EXTENSION;
+use crate::storage::interface::DROPPED_EXTENSION;
+use crate::storage::interface::EXPIRY_MAP_EXTENSION;
+use crate::storage::interface::PROTECTED_SET_EXTENSION;
 use crate::storage::preload::LoadedPartfile;
+use crate::storage::reconcile::{self, MissingTable, OrphanAction, RecoveryReport};
 use crate::storage::Coremap;
-use crate::IoResult;
+use crate::util::fmt_key_safe;
 use crate::SnapshotConfig;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
-use std::io::Error as IoError;
 use std::io::ErrorKind;
-use std::path::Path;
+use std::io::Read;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::thread;
 
-type PreloadSet = std::collections::HashSet<ObjectID>;
-const PRELOAD_PATH: &str = "data/ks/PRELOAD";
+type PreloadSet = HashSet<ObjectID>;
+
+/// The path to the `PRELOAD`
+fn preload_path() -> String {
+    concat_str!(dir_ksroot(), "/", "PRELOAD")
+}
 
 /// Read a given table into a [`Table`] object
 ///
-/// This will take care of volatility and the model_code. Just make sure that you pass the proper
-/// keyspace ID and a valid table ID
+/// This will take care of volatility, the ordered index and the model_code. Just make sure that
+/// you pass the proper keyspace ID and a valid table ID
 pub fn read_table(
     ksid: &ObjectID,
     tblid: &ObjectID,
     volatile: bool,
+    ordered: bool,
     model_code: u8,
-) -> IoResult<Table> {
-    let filepath = unsafe { concat_path!(DIR_KSROOT, ksid.as_str(), tblid.as_str()) };
-    let data = if volatile {
+) -> StorageResult<Table> {
+    let (data, protected, expiries) = if volatile {
         // no need to read anything; table is volatile and has no file
-        Coremap::new()
+        (Coremap::new(), HashSet::new(), HashMap::new())
     } else {
         // not volatile, so read this in
-        let f = fs::read(filepath)?;
-        super::de::deserialize_map(f).ok_or_else(|| bad_data!())?
+        self::read_table_data(ksid, tblid)?
     };
     let tbl = match model_code {
         bytemarks::BYTEMARK_MODEL_KV_BIN_BIN => {
-            Table::new_kve_with_data(data, volatile, false, false)
+            Table::new_kve_with_data(data, volatile, ordered, false, false)
         }
         bytemarks::BYTEMARK_MODEL_KV_BIN_STR => {
-            Table::new_kve_with_data(data, volatile, false, true)
+            Table::new_kve_with_data(data, volatile, ordered, false, true)
         }
         bytemarks::BYTEMARK_MODEL_KV_STR_STR => {
-            Table::new_kve_with_data(data, volatile, true, true)
+            Table::new_kve_with_data(data, volatile, ordered, true, true)
         }
         bytemarks::BYTEMARK_MODEL_KV_STR_BIN => {
-            Table::new_kve_with_data(data, volatile, true, false)
+            Table::new_kve_with_data(data, volatile, ordered, true, false)
         }
-        _ => return Err(IoError::from(ErrorKind::Unsupported)),
+        _ => return Err(self::unknown_model(ksid)),
     };
+    let tbl = tbl.with_entity(ksid, tblid);
+    if let Ok(kve) = tbl.get_kvstore() {
+        kve.restore_protected(protected.into_iter());
+        // keys that expired while we were down are removed by the expiry sweeper
+        kve.restore_expiries(expiries.into_iter());
+    }
     Ok(tbl)
 }
 
+/// The error for a table with a model code that we don't know of, which is recorded in the
+/// `PARTMAP` of its keyspace
+fn unknown_model(ksid: &ObjectID) -> StorageError {
+    StorageError::corrupted(
+        unsafe { concat_path!(dir_ksroot(), ksid.as_str(), "PARTMAP") },
+        "a table has an unknown model code",
+    )
+}
+
+/// Read the file at `path`, which has to exist
+fn read(path: PathBuf) -> StorageResult<Vec<u8>> {
+    fs::read(&path).map_err(StorageError::io("read", &path))
+}
+
+/// Read the file at `path`, returning `None` if there's no such file
+fn read_if_exists(path: PathBuf) -> StorageResult<Option<Vec<u8>>> {
+    match fs::read(&path) {
+        Ok(f) => Ok(Some(f)),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(StorageError::io("read", &path)(e)),
+    }
+}
+
+/// Read the pairs of a table that isn't volatile, along with its protected keys and the
+/// expiry times of its keys
+pub fn read_table_data(
+    ksid: &ObjectID,
+    tblid: &ObjectID,
+) -> StorageResult<(Coremap<Data, Data>, HashSet<Data>, HashMap<Data, i64>)> {
+    let filepath = unsafe { concat_path!(dir_ksroot(), ksid.as_str(), tblid.as_str()) };
+    let f = self::read(filepath.clone())?;
+    match super::checksummed_map_version(&f) {
+        Some(found) if found != super::CHECKSUMMED_MAP_VERSION => {
+            return Err(StorageError::VersionMismatch {
+                file: filepath,
+                found,
+                expected: super::CHECKSUMMED_MAP_VERSION,
+            })
+        }
+        _ => {}
+    }
+    let mode = registry::get_paranoid_mode();
+    let (data, bad_records) = super::de::deserialize_map_verified(f, mode.has_checksums())
+        .ok_or_else(|| StorageError::corrupted(&filepath, "the pairs couldn't be decoded"))?;
+    if !bad_records.is_empty() {
+        self::report_bad_records(&filepath, &bad_records);
+        if mode.is_reject() {
+            return Err(StorageError::ChecksumMismatch {
+                file: filepath,
+                records: bad_records,
+            });
+        }
+    }
+    Ok((
+        data,
+        self::read_protected(ksid, tblid)?,
+        self::read_expiries(ksid, tblid)?,
+    ))
+}
+
+/// What the file of a table says was written to it: the number of records (from its header)
+/// and the bytes that their keys and values take up (from the size of the file)
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct TableHeader {
+    pub records: u64,
+    pub bytes: u64,
+}
+
+/// Read the [header](TableHeader) of the file of a table that isn't volatile without reading
+/// its records, or return `None` if the table doesn't have a file (yet)
+pub fn read_table_header(ksid: &ObjectID, tblid: &ObjectID) -> StorageResult<Option<TableHeader>> {
+    let filepath = unsafe { concat_path!(dir_ksroot(), ksid.as_str(), tblid.as_str()) };
+    let file = match fs::File::open(&filepath) {
+        Ok(file) => file,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(StorageError::io("open", &filepath)(e)),
+    };
+    let size = file
+        .metadata()
+        .map_err(StorageError::io("stat", &filepath))?
+        .len();
+    // the checksum header (if there is one) and then the number of records
+    let mut head = Vec::with_capacity(super::CHECKSUMMED_MAP_HEADER_LEN + 8);
+    file.take((super::CHECKSUMMED_MAP_HEADER_LEN + 8) as u64)
+        .read_to_end(&mut head)
+        .map_err(StorageError::io("read", &filepath))?;
+    // every record has the lengths of its key and value (and its checksum) on top of them
+    let (start, per_record) = match super::checksummed_map_version(&head) {
+        Some(found) if found != super::CHECKSUMMED_MAP_VERSION => {
+            return Err(StorageError::VersionMismatch {
+                file: filepath,
+                found,
+                expected: super::CHECKSUMMED_MAP_VERSION,
+            })
+        }
+        Some(_) => (super::CHECKSUMMED_MAP_HEADER_LEN, 20),
+        None => (0, 16),
+    };
+    if head.len() < start + 8 {
+        return Err(StorageError::corrupted(
+            &filepath,
+            "the header is cut short",
+        ));
+    }
+    let mut records = [0u8; 8];
+    records.copy_from_slice(&head[start..start + 8]);
+    let records = u64::from_le_bytes(records);
+    let bytes = records
+        .checked_mul(per_record)
+        .and_then(|overhead| overhead.checked_add(start as u64 + 8))
+        .and_then(|overhead| size.checked_sub(overhead))
+        .ok_or_else(|| {
+            StorageError::corrupted(&filepath, "the file is too short for its records")
+        })?;
+    Ok(Some(TableHeader { records, bytes }))
+}
+
+/// Log and count the records of the file at `path` that don't match their checksums
+fn report_bad_records(path: &Path, records: &[BadRecord]) {
+    registry::add_checksum_mismatches(records.len());
+    for record in records {
+        log::error!(
+            "The checksum of {} of `{}` doesn't match",
+            record,
+            path.display()
+        );
+    }
+}
+
+/// Read the keys of a table that are protected from deletion. If the table has no protected
+/// keys (and hence no file), an empty set is returned
+pub fn read_protected(ksid: &ObjectID, tblid: &ObjectID) -> StorageResult<HashSet<Data>> {
+    let filename = unsafe { concat_str!(tblid.as_str(), PROTECTED_SET_EXTENSION) };
+    let filepath = unsafe { concat_path!(dir_ksroot(), ksid.as_str(), &filename) };
+    match self::read_if_exists(filepath.clone())? {
+        Some(f) => super::de::deserialize_set_ctype(&f).ok_or_else(|| {
+            StorageError::corrupted(&filepath, "the protected keys couldn't be decoded")
+        }),
+        None => Ok(HashSet::new()),
+    }
+}
+
+/// Read the expiry times of the keys of a table. If no key has an expiry time (and hence
+/// there's no file), an empty map is returned
+pub fn read_expiries(ksid: &ObjectID, tblid: &ObjectID) -> StorageResult<HashMap<Data, i64>> {
+    let filename = unsafe { concat_str!(tblid.as_str(), EXPIRY_MAP_EXTENSION) };
+    let filepath = unsafe { concat_path!(dir_ksroot(), ksid.as_str(), &filename) };
+    match self::read_if_exists(filepath.clone())? {
+        Some(f) => super::de::deserialize_expiries(f).ok_or_else(|| {
+            StorageError::corrupted(&filepath, "the expiry times couldn't be decoded")
+        }),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// Read the bloom filter of the file of a table. The filter is only an optimization, so if
+/// there's none, or it can't be read, or it was written with another file (say, if a flush
+/// was interrupted), `None` is returned and the table is always read in
+pub fn read_bloom_filter(ksid: &ObjectID, tblid: &ObjectID) -> Option<BloomFilter> {
+    let filename = unsafe { concat_str!(tblid.as_str(), BLOOM_FILTER_EXTENSION) };
+    let filepath = unsafe { concat_path!(dir_ksroot(), ksid.as_str(), &filename) };
+    let tblpath = unsafe { concat_path!(dir_ksroot(), ksid.as_str(), tblid.as_str()) };
+    let filter = match self::read_if_exists(filepath.clone()) {
+        Ok(Some(f)) => BloomFilter::deserialize(&f),
+        Ok(None) => return None,
+        Err(e) => {
+            log::warn!("Ignoring the bloom filter of a table: {}", e);
+            return None;
+        }
+    };
+    let file_size = fs::metadata(&tblpath).map(|meta| meta.len()).ok();
+    match filter {
+        Some(filter) if Some(filter.file_size()) == file_size => Some(filter),
+        _ => {
+            log::warn!(
+                "Ignoring the bloom filter '{}' since it is corrupted or out of date",
+                filepath.to_string_lossy()
+            );
+            None
+        }
+    }
+}
+
 /// Read an entire keyspace into a Coremap. You'll need to initialize the rest
-pub fn read_keyspace(ksid: &ObjectID) -> IoResult<Coremap<ObjectID, Arc<Table>>> {
-    let partmap = self::read_partmap(ksid)?;
-    let ks: Coremap<ObjectID, Arc<Table>> = Coremap::with_capacity(partmap.len());
-    for (tableid, (table_storage_type, model_code)) in partmap.into_iter() {
+pub fn read_keyspace(ksid: &ObjectID) -> StorageResult<Coremap<ObjectID, Arc<Table>>> {
+    Ok(self::read_keyspace_with(ksid, false)?.tables)
+}
+
+/// Same as [`read_keyspace`], except that if `lazy` is set, only the tables that are volatile
+/// are set up right away. The data of the other tables is read on first access (see
+/// [`Table::ensure_loaded`]). The default table of the keyspace is restored too, and so is
+/// whether it is archived (the data of an archived keyspace is never read in here)
+pub fn read_keyspace_with(ksid: &ObjectID, lazy: bool) -> StorageResult<Keyspace> {
+    let pending = vec![self::read_keyspace_meta(ksid)?];
+    let mut tables = self::load_tables(pending, lazy, 1)?;
+    let (pending, tables) = tables.pop().expect("one keyspace was read in");
+    pending.finish(tables)
+}
+
+/// A table as recorded in the `PARTMAP` of its keyspace
+struct TableMeta {
+    id: ObjectID,
+    /// the name of the table as it was created (see [`entity_key`](memstore::entity_key))
+    name: ObjectID,
+    volatile: bool,
+    ordered: bool,
+    model_code: u8,
+    value_limit: Option<u64>,
+    cached: bool,
+    /// the times of the latest write and read, if the table was ever active
+    activity: Option<(u64, u64)>,
+    /// the table has no file, and is loaded empty (see [`reconcile`])
+    missing: bool,
+}
+
+impl TableMeta {
+    /// An orphan that is adopted into its keyspace: a persistent and unordered
+    /// `keymap(binstr,binstr)` table, with no value limit of its own and no read cache
+    fn adopted(id: ObjectID) -> Self {
+        Self {
+            name: id.clone(),
+            id,
+            volatile: false,
+            ordered: false,
+            model_code: bytemarks::BYTEMARK_MODEL_KV_BIN_BIN,
+            value_limit: None,
+            cached: false,
+            activity: None,
+            missing: false,
+        }
+    }
+}
+
+/// A keyspace whose `PARTMAP` has been read, but whose tables haven't been set up yet
+struct PendingKeyspace {
+    id: ObjectID,
+    tables: Vec<TableMeta>,
+    default_table: Option<ObjectID>,
+    /// the tombstones of the dropped tables (see [`DROPPED_EXTENSION`]), along with whether
+    /// the `PARTMAP` still has the table
+    tombstones: Vec<(String, bool)>,
+    /// tables were adopted into the keyspace, so its `PARTMAP` has to be written out
+    partmap_stale: bool,
+    /// the keyspace was archived, so the data of its tables is left on disk
+    archived: bool,
+}
+
+impl PendingKeyspace {
+    /// Set up `table` of this keyspace, reading its data in unless it is volatile, `lazy`
+    /// is set or the keyspace is archived
+    fn load_table(&self, table: &TableMeta, lazy: bool) -> StorageResult<Table> {
+        let lazy = lazy || self.archived;
+        let tbl = if table.missing {
+            // the next flush writes the file
+            Table::from_model_code(table.model_code, false, table.ordered)
+                .ok_or_else(|| self::unknown_model(&self.id))?
+                .with_entity(&self.id, &table.id)
+        } else if lazy && !table.volatile {
+            let bloom = self::read_bloom_filter(&self.id, &table.id);
+            Table::new_unloaded(&self.id, &table.id, table.ordered, table.model_code, bloom)
+                .ok_or_else(|| self::unknown_model(&self.id))?
+        } else {
+            self::read_table(
+                &self.id,
+                &table.id,
+                table.volatile,
+                table.ordered,
+                table.model_code,
+            )?
+        };
+        if let Some((last_write, last_read)) = table.activity {
+            tbl.activity().restore(last_write, last_read);
+        }
+        // values that are already larger than the limit are left alone and can still be read
+        Ok(tbl
+            .with_value_limit(table.value_limit)
+            .with_read_cache(table.cached))
+    }
+    /// Put the keyspace together from its `tables`, which are in the same order as in
+    /// [`Self::tables`]. If the `PARTMAP` still has dropped tables, it is written out without
+    /// them before their tombstones are removed (and it's written out with the adopted tables,
+    /// if there are any)
+    fn finish(self, tables: Vec<Table>) -> StorageResult<Keyspace> {
+        let ks: Coremap<ObjectID, Arc<Table>> = Coremap::with_capacity(tables.len());
+        let mut names = Vec::new();
+        for (meta, tbl) in self.tables.into_iter().zip(tables) {
+            ks.true_if_insert(meta.id.clone(), Arc::new(tbl));
+            names.push((meta.id, meta.name));
+        }
+        let ks = Keyspace::init_with_all_def_strategy(ks);
+        for (tblid, name) in names {
+            ks.set_table_name(tblid, name);
+        }
+        if self.archived {
+            ks.set_archived(true);
+        }
+        if let Some(default_table) = self.default_table {
+            ks.set_default_table(default_table)
+                .expect("the default table was read in with the keyspace");
+        }
+        let ks_path = unsafe { concat_path!(dir_ksroot(), self.id.as_str()) };
+        if self.partmap_stale || self.tombstones.iter().any(|(_, in_partmap)| *in_partmap) {
+            super::flush::oneshot::flush_partmap(&self.id, &ks)?;
+            // the new PARTMAP has to be there for good before the tombstones go
+            sync_dir(&ks_path)?;
+        }
+        for (tblid, _) in &self.tombstones {
+            if !ks.tables.contains_key(tblid.as_bytes()) {
+                // whatever the drop didn't get to
+                let leftovers = [
+                    "",
+                    PROTECTED_SET_EXTENSION,
+                    EXPIRY_MAP_EXTENSION,
+                    BLOOM_FILTER_EXTENSION,
+                ];
+                for extension in leftovers.iter() {
+                    self::remove_if_exists(ks_path.join(concat_str!(tblid, extension)))?;
+                }
+            }
+            self::remove_if_exists(ks_path.join(concat_str!(tblid, DROPPED_EXTENSION)))?;
+        }
+        Ok(ks)
+    }
+}
+
+/// Read (and check) the `PARTMAP` of a keyspace, removing the temporary files that were left
+/// behind in its directory
+fn read_keyspace_meta(ksid: &ObjectID) -> StorageResult<PendingKeyspace> {
+    // from here on, the tables go by their keys
+    let mut names = HashMap::new();
+    let partmap: LoadedPartfile = self::read_partmap(ksid)?
+        .into_iter()
+        .map(|(name, meta)| {
+            let tblid = memstore::key_of(&name);
+            names.insert(tblid.clone(), name);
+            (tblid, meta)
+        })
+        .collect();
+    self::remove_temp_files(ksid, &partmap)?;
+    let ks_path = unsafe { concat_path!(dir_ksroot(), ksid.as_str()) };
+    let tombstones: Vec<(String, bool)> = self::find_tombstones(&ks_path)?
+        .into_iter()
+        .map(|tblid| {
+            let in_partmap = partmap.contains_key(tblid.as_bytes());
+            (tblid, in_partmap)
+        })
+        .collect();
+    let mut tables = Vec::with_capacity(partmap.len());
+    let mut default_table = None;
+    let mut archived = false;
+    for (tableid, (table_storage_type, model_code, value_limit, activity)) in partmap.into_iter() {
+        let tblid = unsafe { tableid.as_str() };
+        if tombstones.iter().any(|(dropped, _)| dropped == tblid) && !ks_path.join(tblid).is_file()
+        {
+            // the server went down while the table was being dropped
+            log::warn!(
+                "Skipping the table '{}:{}', which was dropped",
+                fmt_key_safe(ksid),
+                fmt_key_safe(&tableid)
+            );
+            continue;
+        }
+        let is_ordered = table_storage_type & bytemarks::BYTEMARK_STORAGE_FLAG_ORDERED != 0;
+        let is_cached = table_storage_type & bytemarks::BYTEMARK_STORAGE_FLAG_READ_CACHE != 0;
+        // it's set on every table of the keyspace
+        archived |= table_storage_type & bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED != 0;
+        if table_storage_type & bytemarks::BYTEMARK_STORAGE_FLAG_DEFAULT != 0 {
+            if default_table.is_some() {
+                return Err(StorageError::corrupted(
+                    unsafe { concat_path!(dir_ksroot(), ksid.as_str(), "PARTMAP") },
+                    "more than one table is the default table",
+                ));
+            }
+            default_table = Some(tableid.clone());
+        }
+        let table_storage_type = table_storage_type
+            & !(bytemarks::BYTEMARK_STORAGE_FLAG_ORDERED
+                | bytemarks::BYTEMARK_STORAGE_FLAG_VALUE_LIMIT
+                | bytemarks::BYTEMARK_STORAGE_FLAG_DEFAULT
+                | bytemarks::BYTEMARK_STORAGE_FLAG_READ_CACHE
+                | bytemarks::BYTEMARK_STORAGE_FLAG_ACTIVITY
+                | bytemarks::BYTEMARK_STORAGE_FLAG_ARCHIVED);
         if table_storage_type > 1 {
-            return Err(bad_data!());
+            return Err(StorageError::corrupted(
+                unsafe { concat_path!(dir_ksroot(), ksid.as_str(), "PARTMAP") },
+                "a table has an unknown storage type",
+            ));
+        }
+        tables.push(TableMeta {
+            name: names
+                .remove(&tableid)
+                .expect("the table was in the PARTMAP"),
+            id: tableid,
+            volatile: table_storage_type == bytemarks::BYTEMARK_STORAGE_VOLATILE,
+            ordered: is_ordered,
+            model_code,
+            value_limit,
+            cached: is_cached,
+            activity,
+            missing: false,
+        });
+    }
+    Ok(PendingKeyspace {
+        id: ksid.clone(),
+        tables,
+        default_table,
+        tombstones,
+        partmap_stale: false,
+        archived,
+    })
+}
+
+/// Compare the files in the directories of the keyspaces with their `PARTMAP`s (see
+/// [`reconcile`]) and deal with the orphans and the missing tables as the configuration says.
+/// If the startup has to fail, nothing is touched and every discrepancy is returned. The
+/// adopted tables are added to their keyspaces and the missing tables are marked so that
+/// they're loaded empty
+fn reconcile(keyspaces: &mut [PendingKeyspace]) -> StorageResult<RecoveryReport> {
+    let policy = registry::get_orphan_policy();
+    let allow_missing = registry::allows_missing();
+    let mut report = RecoveryReport::new();
+    let mut orphans = Vec::with_capacity(keyspaces.len());
+    for ks in keyspaces.iter_mut() {
+        let ksid = unsafe { ks.id.as_str() };
+        let ks_path = unsafe { concat_path!(dir_ksroot(), ksid) };
+        let known: Vec<&str> = ks
+            .tables
+            .iter()
+            .map(|tbl| unsafe { tbl.id.as_str() })
+            .collect();
+        let dropped: Vec<&str> = ks.tombstones.iter().map(|(tbl, _)| tbl.as_str()).collect();
+        orphans.push(reconcile::find_orphans(&ks_path, ksid, &known, &dropped)?);
+        for table in ks.tables.iter_mut() {
+            let tblid = unsafe { table.id.as_str() };
+            if !table.volatile && !ks_path.join(tblid).is_file() {
+                table.missing = true;
+                report.missing.push(MissingTable {
+                    keyspace: ksid.to_owned(),
+                    table: tblid.to_owned(),
+                });
+            }
+        }
+    }
+    let has_orphans = orphans.iter().any(|orphans| !orphans.is_empty());
+    if (has_orphans && policy == OrphanPolicy::Fail)
+        || (!report.missing.is_empty() && !allow_missing)
+    {
+        report.orphans = orphans.into_iter().flatten().collect();
+        return Err(StorageError::Unreconciled(report));
+    }
+    for (ks, orphans) in keyspaces.iter_mut().zip(orphans) {
+        let ks_path = unsafe { concat_path!(dir_ksroot(), ks.id.as_str()) };
+        for mut orphan in orphans {
+            if policy == OrphanPolicy::Adopt && orphan.is_adoptable() {
+                ks.tables.push(TableMeta::adopted(unsafe {
+                    ObjectID::from_slice(&orphan.table)
+                }));
+                ks.partmap_stale = true;
+                orphan.action = OrphanAction::Adopted;
+            } else {
+                // (or there was nothing to adopt, say if only the bloom filter was left)
+                reconcile::quarantine(&ks_path, &orphan)?;
+                orphan.action = OrphanAction::Quarantined;
+            }
+            log::warn!(
+                "The table '{}:{}' wasn't in the PARTMAP of its keyspace: {}",
+                orphan.keyspace,
+                orphan.table,
+                orphan.action.as_str()
+            );
+            report.orphans.push(orphan);
+        }
+    }
+    for missing in &report.missing {
+        log::warn!(
+            "The table '{}:{}' has no file and is loaded empty",
+            missing.keyspace,
+            missing.table
+        );
+    }
+    Ok(report)
+}
+
+/// Returns the names of the [tombstones](DROPPED_EXTENSION) in `dir`, without the extension
+fn find_tombstones(dir: &Path) -> StorageResult<Vec<String>> {
+    let entries = fs::read_dir(dir).map_err(StorageError::io("read", dir))?;
+    let mut tombstones = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(StorageError::io("read", dir))?;
+        if let Some(name) = entry.file_name().to_str() {
+            if let Some(name) = name.strip_suffix(DROPPED_EXTENSION) {
+                tombstones.push(name.to_owned());
+            }
+        }
+    }
+    Ok(tombstones)
+}
+
+/// The tables of the keyspaces that [`load_tables`] sets up, which its threads take one at a
+/// time
+struct Loader {
+    keyspaces: Vec<PendingKeyspace>,
+    /// the keyspace and the table of every table, in order
+    jobs: Vec<(usize, usize)>,
+    /// the next job that no thread has taken yet
+    next: AtomicUsize,
+    lazy: bool,
+}
+
+impl Loader {
+    /// Set up tables until there are none left, returning them along with their jobs
+    fn run(&self) -> Vec<(usize, StorageResult<Table>)> {
+        let mut done = Vec::new();
+        loop {
+            let job = self.next.fetch_add(1, Ordering::Relaxed);
+            let (ks, table) = match self.jobs.get(job) {
+                Some(job) => *job,
+                None => return done,
+            };
+            let ks = &self.keyspaces[ks];
+            done.push((job, ks.load_table(&ks.tables[table], self.lazy)));
+        }
+    }
+}
+
+/// Set up the tables of `keyspaces` with up to `threads` threads (this one included), which
+/// each read in one table at a time. A table that can't be read in doesn't stop the others from
+/// being read in, and every failure is returned
+fn load_tables(
+    keyspaces: Vec<PendingKeyspace>,
+    lazy: bool,
+    threads: usize,
+) -> StorageResult<Vec<(PendingKeyspace, Vec<Table>)>> {
+    let jobs: Vec<(usize, usize)> = keyspaces
+        .iter()
+        .enumerate()
+        .flat_map(|(ks, pending)| (0..pending.tables.len()).map(move |table| (ks, table)))
+        .collect();
+    let threads = threads.min(jobs.len()).max(1);
+    let loader = Arc::new(Loader {
+        keyspaces,
+        jobs,
+        next: AtomicUsize::new(0),
+        lazy,
+    });
+    let data_dir = ThreadDataDir::current();
+    // if a thread can't be spawned, the others just have more to do
+    let handles: Vec<_> = (1..threads)
+        .filter_map(|_| {
+            let loader = loader.clone();
+            thread::Builder::new()
+                .name("loader".to_owned())
+                .spawn(move || {
+                    data_dir.inherit();
+                    loader.run()
+                })
+                .ok()
+        })
+        .collect();
+    let mut results: Vec<Option<StorageResult<Table>>> =
+        (0..loader.jobs.len()).map(|_| None).collect();
+    let mut done = loader.run();
+    for handle in handles {
+        match handle.join() {
+            Ok(theirs) => done.extend(theirs),
+            Err(e) => panic::resume_unwind(e),
+        }
+    }
+    for (job, result) in done {
+        results[job] = Some(result);
+    }
+    let loader = match Arc::try_unwrap(loader) {
+        Ok(loader) => loader,
+        Err(_) => unreachable!("every loader thread was joined"),
+    };
+    let mut tables: Vec<Vec<Table>> = loader
+        .keyspaces
+        .iter()
+        .map(|pending| Vec::with_capacity(pending.tables.len()))
+        .collect();
+    let mut errors = Vec::new();
+    for ((ks, _), result) in loader.jobs.iter().zip(results) {
+        match result.expect("every table was set up") {
+            Ok(tbl) => tables[*ks].push(tbl),
+            Err(e) => errors.push(e),
+        }
+    }
+    match errors.len() {
+        0 => Ok(loader.keyspaces.into_iter().zip(tables).collect()),
+        1 => Err(errors.pop().unwrap()),
+        _ => Err(StorageError::Several(errors)),
+    }
+}
+
+/// Remove the temporary files that a flush (or a compaction) left behind in the directory of
+/// the keyspace if it was interrupted, say by a crash. A file is first written to `<name>_`
+/// and then renamed, so `<table>_` is only removed if it isn't the file of another table
+fn remove_temp_files(ksid: &ObjectID, partmap: &LoadedPartfile) -> StorageResult<()> {
+    let ks_path = unsafe { concat_str!(dir_ksroot(), "/", ksid.as_str()) };
+    let mut temp_files = vec!["PARTMAP_".to_owned()];
+    for tblid in partmap.keys() {
+        let tblid = unsafe { tblid.as_str() };
+        let temp_file = concat_str!(tblid, "_");
+        if !partmap.contains_key(temp_file.as_bytes()) {
+            temp_files.push(temp_file);
         }
-        let is_volatile = table_storage_type == bytemarks::BYTEMARK_STORAGE_VOLATILE;
-        let tbl = self::read_table(ksid, &tableid, is_volatile, model_code)?;
-        ks.true_if_insert(tableid, Arc::new(tbl));
+        temp_files.push(concat_str!(tblid, PROTECTED_SET_EXTENSION, "_"));
+        temp_files.push(concat_str!(tblid, EXPIRY_MAP_EXTENSION, "_"));
+        temp_files.push(concat_str!(tblid, BLOOM_FILTER_EXTENSION, "_"));
+    }
+    for temp_file in temp_files {
+        self::remove_if_exists(concat_path!(&ks_path, temp_file))?;
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: impl AsRef<Path>) -> StorageResult<()> {
+    match fs::remove_file(path.as_ref()) {
+        Ok(_) => {
+            log::warn!(
+                "Removed the leftover file '{}'",
+                path.as_ref().to_string_lossy()
+            );
+            Ok(())
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(StorageError::io("remove", path)(e)),
     }
-    Ok(ks)
 }
 
 /// Read the `PARTMAP` for a given keyspace
-pub fn read_partmap(ksid: &ObjectID) -> IoResult<LoadedPartfile> {
-    let filepath = unsafe { concat_path!(DIR_KSROOT, ksid.as_str(), "PARTMAP") };
-    super::preload::read_partfile_raw(fs::read(filepath)?)
+pub fn read_partmap(ksid: &ObjectID) -> StorageResult<LoadedPartfile> {
+    let filepath = unsafe { concat_path!(dir_ksroot(), ksid.as_str(), "PARTMAP") };
+    super::preload::read_partfile_raw(self::read(filepath.clone())?, &filepath)
 }
 
 /// Read the `PRELOAD`
-pub fn read_preload() -> IoResult<PreloadSet> {
-    let read = fs::read(PRELOAD_PATH)?;
-    super::preload::read_preload_raw(read)
+pub fn read_preload() -> StorageResult<PreloadSet> {
+    let path = PathBuf::from(preload_path());
+    super::preload::read_preload_raw(self::read(path.clone())?, &path)
 }
 
 /// Read everything and return a [`Memstore`]
 ///
 /// If this is a new instance an empty store is returned while the directory tree
 /// is also created. If this is an already initialized instance then the store
-/// is read and returned (and any possible errors that are encountered are returned)
-pub fn read_full(snapshot_config: &SnapshotConfig) -> IoResult<Memstore> {
+/// is read and returned (and any possible errors that are encountered are returned). If `lazy`
+/// is set, the data of the tables is only read on first access (see [`read_keyspace_with`])
+pub fn read_full(snapshot_config: &SnapshotConfig, lazy: bool) -> StorageResult<Memstore> {
+    // nothing is read in lazily at this point, so there's nothing to share out
+    let threads = if lazy {
+        1
+    } else {
+        registry::get_load_threads().min(num_cpus::get())
+    };
+    self::read_full_with(snapshot_config, lazy, threads)
+}
+
+/// Same as [`read_full`], but the tables are read in with up to `threads` threads. The
+/// `PARTMAP`s are read (and checked) first, one keyspace after the other, and reconciled with
+/// the files in the directories of the keyspaces (see [`reconcile`]). Then the tables of all
+/// the keyspaces are shared out between the threads
+pub fn read_full_with(
+    snapshot_config: &SnapshotConfig,
+    lazy: bool,
+    threads: usize,
+) -> StorageResult<Memstore> {
     if is_new_instance() {
         // init an empty store
         let store = Memstore::new_default();
@@ -124,17 +785,57 @@ pub fn read_full(snapshot_config: &SnapshotConfig) -> IoResult<Memstore> {
         super::interface::create_tree(&store)?;
         return Ok(store);
     }
+    self::remove_if_exists(concat_str!(&preload_path(), "_"))?;
     let preload = self::read_preload()?;
+    super::names::prepare(&preload)?;
+    // from here on, the keyspaces go by their keys
+    let mut preload: HashMap<ObjectID, ObjectID> = preload
+        .into_iter()
+        .map(|name| (memstore::key_of(&name), name))
+        .collect();
+    // the keyspaces that were being dropped when the server went down
+    let tombstones = self::find_tombstones(Path::new(dir_ksroot()))?;
+    let mut preload_stale = false;
+    for ksid in &tombstones {
+        if !Path::new(dir_ksroot()).join(ksid).is_dir() && preload.remove(ksid.as_bytes()).is_some()
+        {
+            log::warn!(
+                "Skipping the keyspace '{}', which was dropped",
+                fmt_key_safe(ksid.as_bytes())
+            );
+            preload_stale = true;
+        }
+    }
     let ksmap = Coremap::with_capacity(preload.len());
-    for ksid in preload {
-        let ks = Keyspace::init_with_all_def_strategy(self::read_keyspace(&ksid)?);
+    let mut pending = preload
+        .keys()
+        .map(self::read_keyspace_meta)
+        .collect::<StorageResult<Vec<_>>>()?;
+    let report = self::reconcile(&mut pending)?;
+    for (pending, tables) in self::load_tables(pending, lazy, threads)? {
+        let ksid = pending.id.clone();
+        let name = preload
+            .remove(&ksid)
+            .expect("the keyspace was in the PRELOAD");
+        let ks = pending.finish(tables)?.with_name(&ksid, name);
         ksmap.upsert(ksid, Arc::new(ks));
     }
-    Ok(Memstore::init_with_all(ksmap, snapshot_config))
+    let store = Memstore::init_with_all(ksmap, snapshot_config);
+    if preload_stale {
+        super::flush::oneshot::flush_preload(&store)?;
+        sync_dir(dir_ksroot())?;
+    }
+    for ksid in tombstones {
+        let tombstone = concat_str!(dir_ksroot(), "/", &ksid, DROPPED_EXTENSION);
+        fs::remove_dir_all(&tombstone).map_err(StorageError::io("remove", &tombstone))?;
+    }
+    registry::set_recovery_report(report);
+    Ok(store)
 }
 
-/// Check if the data/PRELOAD file exists (if not: we're on a new instance)
+/// Check if the data/ks/PRELOAD file exists (if not: we're on a new instance)
 pub fn is_new_instance() -> bool {
-    let path = Path::new("data/PRELOAD");
+    let path = preload_path();
+    let path = Path::new(&path);
     !(path.exists() && path.is_file())
 }
@@ -0,0 +1,265 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Writing out files
+//!
+//! Every file that a flush (or a snapshot) writes goes through a temporary file (the path of the
+//! file followed by an `_`) that is synced and then renamed into place, so that a file is never
+//! left half-written. How that's done is up to a [`FileWriter`]:
+//! - [`Direct`] writes, syncs and renames every file right away with the blocking calls of
+//!   `std`. This is what is used unless a flush is [batched](batched)
+//! - If the server was built with the `uring` feature (on Linux) and `uring` is set in the
+//!   configuration, the periodic flushes and the full snapshots queue up their files and write
+//!   and sync all of them through one io_uring submission queue once they're done. The files are still renamed (and the files
+//!   that are no longer needed removed) in the order that they were written in, and only once
+//!   all of them have been synced. If io_uring isn't available (say, on an older kernel or in a
+//!   sandbox that doesn't allow it), the flushes silently fall back to writing directly
+//!
+//! The flush routines don't know which one they're using: they go through [`write_file`],
+//! [`remove_file`] and [`sync_dir`], which use the batch of the flush running on the thread, if
+//! there's one.
+//!
+//! The snapshots and the periodic flushes are [throttled]: their files are written through a
+//! [`Throttled`] writer, which paces the bytes of the files to the limit that is set for them
+//! (see [`IoThrottle`](crate::registry::IoThrottle)). A throttled flush that has a limit when it
+//! begins writes its files directly, since a batch would have the pacing only apply to
+//! queueing the files up and not to writing them
+
+use super::error::{StorageError, StorageResult};
+use crate::registry::{self, IoKind, TokenBucket};
+use crate::IoResult;
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+/// True if the server was built with io_uring support
+pub const HAS_URING: bool = cfg!(all(feature = "uring", target_os = "linux"));
+
+/// Serializes the contents of a file into the writer that it's given
+pub type Serializer<'a> = Box<dyn FnOnce(&mut dyn Write) -> IoResult<()> + 'a>;
+
+/// Writes out the files of the data directory and of snapshots. See the
+/// [module level docs](self) for more information
+pub trait FileWriter {
+    /// Write a file with `serialize` through the temporary file at `temp_path` (the path of
+    /// the file followed by an `_`), which is synced and then renamed. This returns the number
+    /// of bytes that were written. A batched writer may only sync the file and put it in place
+    /// once it's [finished](FileWriter::finish)
+    fn write_file(&mut self, temp_path: &str, serialize: Serializer<'_>) -> StorageResult<u64>;
+    /// Remove the file at `path`, if there's one
+    fn remove_file(&mut self, path: &str) -> StorageResult<()>;
+    /// Sync the directory at `path`, once the files written in it so far are in place
+    fn sync_dir(&mut self, path: &Path) -> StorageResult<()>;
+    /// Make sure that every file that was written is synced and in place
+    fn finish(&mut self) -> StorageResult<()>;
+}
+
+/// Writes every file right away
+#[derive(Debug, Default)]
+pub struct Direct;
+
+impl FileWriter for Direct {
+    fn write_file(&mut self, temp_path: &str, serialize: Serializer<'_>) -> StorageResult<u64> {
+        let path = &temp_path[..temp_path.len() - 1];
+        let mut file = File::create(temp_path).map_err(StorageError::io("create", temp_path))?;
+        serialize(&mut file).map_err(StorageError::io("write", temp_path))?;
+        file.sync_all()
+            .map_err(StorageError::io("sync", temp_path))?;
+        let written = file
+            .metadata()
+            .map_err(StorageError::io("stat", temp_path))?
+            .len();
+        fs::rename(temp_path, path).map_err(StorageError::io("rename", temp_path))?;
+        Ok(written)
+    }
+    fn remove_file(&mut self, path: &str) -> StorageResult<()> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(StorageError::io("remove", path)(e)),
+            _ => Ok(()),
+        }
+    }
+    fn sync_dir(&mut self, path: &Path) -> StorageResult<()> {
+        super::interface::sync_dir(path)
+    }
+    fn finish(&mut self) -> StorageResult<()> {
+        Ok(())
+    }
+}
+
+/// Paces the files that another writer writes to the limit that is set for `kind` (see
+/// [`IoThrottle`](crate::registry::IoThrottle)), by sleeping on the thread that writes them.
+/// The limit is looked up for every write, so a limit that is changed applies right away
+pub struct Throttled {
+    inner: Box<dyn FileWriter>,
+    kind: IoKind,
+    bucket: TokenBucket,
+}
+
+impl Throttled {
+    pub fn new(inner: Box<dyn FileWriter>, kind: IoKind) -> Self {
+        Self {
+            inner,
+            kind,
+            bucket: TokenBucket::new(),
+        }
+    }
+}
+
+impl FileWriter for Throttled {
+    fn write_file(&mut self, temp_path: &str, serialize: Serializer<'_>) -> StorageResult<u64> {
+        let (kind, bucket) = (self.kind, &mut self.bucket);
+        self.inner.write_file(
+            temp_path,
+            Box::new(move |file: &mut dyn Write| serialize(&mut Paced { file, kind, bucket })),
+        )
+    }
+    fn remove_file(&mut self, path: &str) -> StorageResult<()> {
+        self.inner.remove_file(path)
+    }
+    fn sync_dir(&mut self, path: &Path) -> StorageResult<()> {
+        self.inner.sync_dir(path)
+    }
+    fn finish(&mut self) -> StorageResult<()> {
+        self.inner.finish()
+    }
+}
+
+/// The file that a [`Throttled`] writer serializes into
+struct Paced<'a> {
+    file: &'a mut dyn Write,
+    kind: IoKind,
+    bucket: &'a mut TokenBucket,
+}
+
+impl Write for Paced<'_> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = self.file.write(buf)?;
+        let throttle = registry::get_io_throttle();
+        if self.kind == IoKind::Snapshot {
+            throttle.snapshot_progress().record(written as u64);
+        }
+        let wait = self.bucket.take(written as u64, throttle.limit(self.kind));
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+        Ok(written)
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        self.file.flush()
+    }
+}
+
+thread_local! {
+    /// The writer of the batched flush that is running on this thread, if there's one
+    static BATCH: RefCell<Option<Box<dyn FileWriter>>> = RefCell::new(None);
+}
+
+/// Run `f` with the writer of the batched flush that is running on this thread, or with
+/// [`Direct`] if there's none
+fn with_writer<T>(f: impl FnOnce(&mut dyn FileWriter) -> T) -> T {
+    BATCH.with(|batch| match batch.borrow_mut().as_mut() {
+        Some(writer) => f(writer.as_mut()),
+        None => f(&mut Direct),
+    })
+}
+
+/// Write a file with `serialize` through the temporary file at `temp_path` (see
+/// [`FileWriter::write_file`])
+pub fn write_file(
+    temp_path: &str,
+    serialize: impl FnOnce(&mut dyn Write) -> IoResult<()>,
+) -> StorageResult<u64> {
+    with_writer(|writer| writer.write_file(temp_path, Box::new(serialize)))
+}
+
+/// Remove the file at `path`, if there's one (see [`FileWriter::remove_file`])
+pub fn remove_file(path: &str) -> StorageResult<()> {
+    with_writer(|writer| writer.remove_file(path))
+}
+
+/// Sync the directory at `path` (see [`FileWriter::sync_dir`])
+pub fn sync_dir(path: impl AsRef<Path>) -> StorageResult<()> {
+    with_writer(|writer| writer.sync_dir(path.as_ref()))
+}
+
+/// Run `flush`, batching the files that it writes if the flushes are configured to use
+/// io_uring and it's available. Otherwise (or if a batch is already running on this thread),
+/// this just runs `flush`
+pub fn batched<T>(flush: impl FnOnce() -> StorageResult<T>) -> StorageResult<T> {
+    match self::batch_writer() {
+        Some(writer) => self::batched_with(writer, flush),
+        None => flush(),
+    }
+}
+
+/// Run `flush` with the files that it writes paced to the limit that is set for `kind` (see
+/// [`Throttled`]). If there's no limit when it begins, the files are batched just like they
+/// would be with [`batched`]. If a batch is already running on this thread, this just runs
+/// `flush`
+pub fn throttled<T>(kind: IoKind, flush: impl FnOnce() -> StorageResult<T>) -> StorageResult<T> {
+    let inner = match registry::get_io_throttle().limit(kind) {
+        Some(_) => None,
+        None => self::batch_writer(),
+    };
+    let inner = inner.unwrap_or_else(|| Box::new(Direct));
+    self::batched_with(Box::new(Throttled::new(inner, kind)), flush)
+}
+
+/// Returns the writer that the flushes are batched with, if there's one
+fn batch_writer() -> Option<Box<dyn FileWriter>> {
+    if !crate::registry::uses_uring() {
+        return None;
+    }
+    #[cfg(all(feature = "uring", target_os = "linux"))]
+    {
+        if let Some(writer) = super::uring::UringWriter::new() {
+            return Some(Box::new(writer));
+        }
+    }
+    None
+}
+
+/// Run `flush` with the files that it writes going to `writer`, which is finished once `flush`
+/// returns. The files that were written before `flush` failed (if it does) are still put in
+/// place, just like they would have been without a batch
+pub fn batched_with<T>(
+    writer: Box<dyn FileWriter>,
+    flush: impl FnOnce() -> StorageResult<T>,
+) -> StorageResult<T> {
+    if BATCH.with(|batch| batch.borrow().is_some()) {
+        return flush();
+    }
+    BATCH.with(|batch| *batch.borrow_mut() = Some(writer));
+    let ret = flush();
+    let finished = match BATCH.with(|batch| batch.borrow_mut().take()) {
+        Some(mut writer) => writer.finish(),
+        None => Ok(()),
+    };
+    ret.and_then(|value| finished.map(|()| value))
+}
@@ -110,6 +110,34 @@ mod __private {
             Response::Item(Element::RespCode(RespCode::Okay))
         );
     }
+    async fn test_create_table_bad_max_value_size() {
+        let mut rng = rand::thread_rng();
+        let tblname = utils::rand_alphastring(10, &mut rng);
+        query.push("create");
+        query.push("table");
+        query.push(&tblname);
+        query.push("keymap(str,str)");
+        query.push("maxvaluesize:0");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "malformed-expression".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push("create");
+        query.push("table");
+        query.push(&tblname);
+        query.push("keymap(str,str)");
+        query.push("maxvaluesize:16");
+        query.push("maxvaluesize:32");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "duplicate-option".to_owned()
+            )))
+        );
+    }
     async fn test_drop_table() {
         let mut rng = rand::thread_rng();
         let tblname = utils::rand_alphastring(10, &mut rng);
@@ -152,4 +180,153 @@ mod __private {
             Response::Item(Element::RespCode(RespCode::Okay))
         );
     }
+    async fn test_alter_keyspace_default_table() {
+        let mut rng = rand::thread_rng();
+        let ksname = utils::rand_alphastring(10, &mut rng);
+        let tblname = utils::rand_alphastring(10, &mut rng);
+        let my_fqe = ksname.clone() + ":" + &tblname;
+        query.push(vec!["create", "keyspace", ksname.as_str()]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let mut query = Query::new();
+        query.push(vec!["create", "table", my_fqe.as_str(), "keymap(str,str)"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        // the table has to exist
+        let mut query = Query::new();
+        query.push(vec![
+            "alter",
+            "keyspace",
+            ksname.as_str(),
+            "default",
+            "table",
+            "nope",
+        ]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "container-not-found".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push(vec![
+            "alter",
+            "keyspace",
+            ksname.as_str(),
+            "default",
+            "table",
+            tblname.as_str(),
+        ]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let mut query = Query::new();
+        query.push(vec!["inspect", "keyspace", ksname.as_str(), "default"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String(tblname.clone()))
+        );
+        // a new connection that only picks the keyspace lands in the default table
+        let mut other = crate::tests::fixture::TestConnection::new(con.transport()).await;
+        let mut query = Query::new();
+        query.push(vec!["use", ksname.as_str()]);
+        assert_eq!(
+            other.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let mut query = Query::new();
+        query.push(vec!["set", "x", "100"]);
+        assert_eq!(
+            other.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let mut query = Query::new();
+        query.push(vec!["use", my_fqe.as_str()]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let mut query = Query::new();
+        query.push(vec!["get", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+        // and the default table can't be dropped
+        let mut query = Query::new();
+        query.push(vec!["drop", "table", my_fqe.as_str()]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "err-default-table".to_owned()
+            )))
+        );
+    }
+    async fn test_create_bad_container_name() {
+        query.push(vec!["create", "keyspace", "_private"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-container-name:reserved".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push(vec!["create", "table", "default", "keymap(str,str)"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-container-name:reserved".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push(vec!["create", "keyspace", "my$keyspace"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-container-name:bad-character".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push("create");
+        query.push("keyspace");
+        query.push("a".repeat(65));
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-container-name:longer-than-64".to_owned()
+            )))
+        );
+    }
+    async fn test_ddl_arity_errors() {
+        query.push(vec!["use", "default", "default"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push("create");
+        query.push("table");
+        query.push("a");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:between-2-and-5".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push("inspect");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:nonzero".to_owned()
+            )))
+        );
+    }
 }
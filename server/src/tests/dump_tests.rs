@@ -0,0 +1,165 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Tests for `DUMPKEY` and `RESTOREKEY`
+
+#[sky_macros::dbtest]
+mod __private {
+    use skytable::{Element, Query, RespCode, Response};
+    macro_rules! query_of {
+        ($($arg:expr),*) => {{
+            let mut q = Query::new();
+            q.push(vec![$($arg),*]);
+            q
+        }};
+    }
+    /// Dump the provided key and return the blob
+    macro_rules! dump {
+        ($con:ident, $key:expr) => {
+            match $con
+                .run_simple_query(&query_of!("dumpkey", $key))
+                .await
+                .unwrap()
+            {
+                Response::Item(Element::String(blob)) => blob,
+                x => panic!("Expected a dump, but got: {:?}", x),
+            }
+        };
+    }
+    async fn test_dump_restore_roundtrip() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("set", "x", "hello\nworld\u{0}"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let blob = dump!(con, "x");
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "y", blob.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("get", "y")).await.unwrap(),
+            Response::Item(Element::String("hello\nworld\u{0}".to_owned()))
+        );
+    }
+    async fn test_restore_replace() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("mset", "x", "100", "y", "200"))
+                .await
+                .unwrap(),
+            Response::Item(Element::UnsignedInt(2))
+        );
+        let blob = dump!(con, "x");
+        // existing keys are left alone
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "y", blob.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::OverwriteError))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "y", blob.as_str(), "replace"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("get", "y")).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+    }
+    async fn test_restore_corrupted() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("set", "x", "100"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let blob = dump!(con, "x");
+        // flip the last hex digit of the checksum
+        let mut corrupted = blob[..blob.len() - 1].to_owned();
+        corrupted.push(if blob.ends_with('0') { '1' } else { '0' });
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "y", corrupted.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "corrupted-dump".to_owned()
+            )))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "y", "nothex"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "corrupted-dump".to_owned()
+            )))
+        );
+        // a dump from some future version of the format
+        let future = "ff".to_owned() + &blob[2..];
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "y", future.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "unknown-dump-version".to_owned()
+            )))
+        );
+    }
+    async fn test_dump_nil() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("dumpkey", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+    }
+    async fn test_dump_restore_syntax_error() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("dumpkey")).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:between-2-and-3".to_owned()
+            )))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("restorekey", "x", "00", "overwrite"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ActionError))
+        );
+    }
+}
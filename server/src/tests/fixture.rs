@@ -0,0 +1,135 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Test transports
+//!
+//! `make test` starts the test server with both a plain listener (on [`PLAIN_PORT`]) and a
+//! TLS listener (on [`TLS_PORT`]), with a self-signed certificate that `ci/ssl.sh` generates
+//! for every run. Every `#[dbtest]` function runs over both of them: the macro generates one
+//! test for the plain listener (with the name of the function) and another for the TLS
+//! listener (with a `_tls` suffix), each with a [`TestConnection`] as `con`. Since the
+//! connection hides the transport behind the same interface, the bodies of the tests don't
+//! have to care about it, and a regression that only shows up on one path (like buffering
+//! in the TLS stream) fails the tests of that path.
+//!
+//! Tests that don't use the macro can use [`for_each_transport`] to run the same body over
+//! every transport
+
+use skytable::aio::{Connection, TlsConnection};
+use skytable::{Query, Response};
+use std::env;
+use std::future::Future;
+use std::io::Result as IoResult;
+
+/// The host that the test server listens on
+pub const HOST: &str = "127.0.0.1";
+/// The port of the plain listener of the test server
+pub const PLAIN_PORT: u16 = 2003;
+/// The port of the TLS listener of the test server
+pub const TLS_PORT: u16 = 2004;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// A listener of the test server
+pub enum Transport {
+    /// Plain TCP
+    Plain,
+    /// TLS, using the self-signed certificate in `ROOT_DIR`
+    Tls,
+}
+
+impl Transport {
+    /// Every transport, in the order that [`for_each_transport`] runs them in
+    pub const ALL: [Transport; 2] = [Transport::Plain, Transport::Tls];
+    pub const fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Plain => "plain",
+            Transport::Tls => "tls",
+        }
+    }
+}
+
+/// Returns the certificate that the TLS listener of the test server uses, which the client
+/// trusts so that the self-signed certificate is accepted
+fn cert_path() -> String {
+    let mut path = env::var("ROOT_DIR").expect("ROOT_DIR unset");
+    path.push_str("/cert.pem");
+    path
+}
+
+/// A connection to the test server over either transport
+pub enum TestConnection {
+    Plain(Connection),
+    Tls(TlsConnection),
+}
+
+impl TestConnection {
+    /// Connect to the test server over `transport`. This panics if the server can't be
+    /// reached, since no test can do anything without it
+    pub async fn new(transport: Transport) -> Self {
+        let con = match transport {
+            Transport::Plain => Connection::new(HOST, PLAIN_PORT)
+                .await
+                .map(TestConnection::Plain)
+                .map_err(|e| format!("{:?}", e)),
+            Transport::Tls => TlsConnection::new(HOST, TLS_PORT, &cert_path())
+                .await
+                .map(TestConnection::Tls)
+                .map_err(|e| format!("{:?}", e)),
+        };
+        con.unwrap_or_else(|e| {
+            panic!(
+                "Couldn't connect to the test server over {}: {}",
+                transport.as_str(),
+                e
+            )
+        })
+    }
+    /// Returns the transport that this connection uses, so that a test can open another
+    /// connection over the same transport
+    pub const fn transport(&self) -> Transport {
+        match self {
+            TestConnection::Plain(_) => Transport::Plain,
+            TestConnection::Tls(_) => Transport::Tls,
+        }
+    }
+    pub async fn run_simple_query(&mut self, query: &Query) -> IoResult<Response> {
+        match self {
+            TestConnection::Plain(con) => con.run_simple_query(query).await,
+            TestConnection::Tls(con) => con.run_simple_query(query).await,
+        }
+    }
+}
+
+/// Run `body` with a new connection over every transport, one after the other
+pub async fn for_each_transport<F, Fut>(mut body: F)
+where
+    F: FnMut(TestConnection) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    for &transport in Transport::ALL.iter() {
+        body(TestConnection::new(transport).await).await;
+    }
+}
@@ -0,0 +1,657 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! In-process tests for the administrative actions: replication, snapshots, archived
+//! keyspaces, shutdown and the modes that the server can be started in
+
+use super::*;
+use crate::protocol::responses;
+use crate::registry;
+use std::sync::Arc;
+
+/// A store with the `synctest:replay` table in use
+async fn sync_store() -> Corestore {
+    let mut db = new_store();
+    let mut con = new_con();
+    for query in [
+        &["CREATE", "KEYSPACE", "synctest"][..],
+        &[
+            "CREATE",
+            "TABLE",
+            "synctest:replay",
+            "keymap(binstr,binstr)",
+        ],
+        &["USE", "synctest:replay"],
+    ]
+    .iter()
+    {
+        assert_eq!(
+            run(&mut db, &mut con, query).await,
+            output_of(responses::groups::OKAY)
+        );
+    }
+    db
+}
+
+/// Split the output of `SYNCSTREAM` into the changes (each made of the sequence number,
+/// the entity and the query)
+fn parse_changes(mut output: &[u8]) -> Vec<Vec<Vec<u8>>> {
+    fn next_line<'a>(output: &mut &'a [u8]) -> &'a [u8] {
+        let at = output.iter().position(|b| *b == b'\n').unwrap();
+        let line = &output[..at];
+        *output = &output[at + 1..];
+        line
+    }
+    fn next_usize(output: &mut &[u8], tsymbol: u8) -> usize {
+        let line = next_line(output);
+        assert_eq!(line[0], tsymbol);
+        String::from_utf8(line[1..].to_vec())
+            .unwrap()
+            .parse()
+            .unwrap()
+    }
+    let mut changes = Vec::new();
+    while !output.is_empty() {
+        assert_eq!(next_line(&mut output), b"*1");
+        let len = next_usize(&mut output, b'_');
+        let change = (0..len)
+            .map(|_| {
+                let len = next_usize(&mut output, b'+');
+                let element = output[..len].to_vec();
+                output = &output[len..];
+                assert!(next_line(&mut output).is_empty());
+                element
+            })
+            .collect();
+        changes.push(change);
+    }
+    changes
+}
+
+#[tokio::test]
+async fn test_syncstream_replays_on_a_standby() {
+    let mut primary = sync_store().await;
+    let mut con = new_con();
+    let changelog = registry::get_changelog();
+    let (epoch, from) = (changelog.epoch(), changelog.current_seq() + 1);
+    let queries: &[&[&str]] = &[
+        &["SET", "x", "100"],
+        &["MSET", "y", "200", "z", "300"],
+        &["UPDATE", "x", "1000"],
+        &["USET", "w", "0", "z", "3000"],
+        &["DEL", "y"],
+        &["PROTECT", "x"],
+        &["SSET", "a", "1", "b", "2"],
+        &["SUPDATE", "a", "10"],
+        &["SDEL", "b"],
+        &["POP", "w"],
+        // the protected key stays
+        &["FLUSHDB"],
+        &["SET", "y", "after the flush"],
+        &["UNPROTECT", "x"],
+    ];
+    for query in queries {
+        run(&mut primary, &mut con, query).await;
+    }
+    // we're reading from a cursor, so the stream ends once the backlog is sent
+    let mut stream = new_con();
+    let output = run(
+        &mut primary,
+        &mut stream,
+        &["SYNCSTREAM", &epoch.to_string(), &from.to_string()],
+    )
+    .await;
+    let okay = output_of(responses::groups::OKAY);
+    assert!(output.starts_with(&okay));
+    let changes: Vec<Vec<Vec<u8>>> = parse_changes(&output[okay.len()..])
+        .into_iter()
+        // the other tests share the change log
+        .filter(|change| change[1] == b"synctest:replay")
+        .collect();
+    assert!(changes.windows(2).all(|pair| {
+        let seq =
+            |change: &Vec<Vec<u8>>| -> u64 { String::from_utf8_lossy(&change[0]).parse().unwrap() };
+        seq(&pair[0]) < seq(&pair[1])
+    }));
+    let mut standby = sync_store().await;
+    let mut standby_con = new_con();
+    for change in changes {
+        let query: Vec<&str> = change[2..]
+            .iter()
+            .map(|arg| std::str::from_utf8(arg).unwrap())
+            .collect();
+        run(&mut standby, &mut standby_con, &query).await;
+    }
+    let contents = contents_of(&primary);
+    assert_eq!(
+        contents.0,
+        vec![
+            (Data::from("x"), Data::from("1000")),
+            (Data::from("y"), Data::from("after the flush")),
+        ]
+    );
+    assert_eq!(contents_of(&standby), contents);
+}
+
+#[tokio::test]
+async fn test_syncstream_reports_too_far_behind() {
+    let mut db = new_store();
+    let mut con = new_con();
+    let epoch = registry::get_changelog().epoch().to_string();
+    let ahead = (u64::MAX).to_string();
+    let output = run(&mut db, &mut con, &["SYNCSTREAM", &epoch, &ahead]).await;
+    assert!(output.starts_with(b"*1\n!"));
+    let too_far_behind = format!("sync-too-far-behind:{}:", epoch);
+    assert!(String::from_utf8_lossy(&output).contains(&too_far_behind));
+}
+
+#[tokio::test]
+async fn test_syncstream_starts_over_after_a_restart() {
+    let mut db = new_store();
+    let mut con = new_con();
+    let changelog = registry::get_changelog();
+    run(&mut db, &mut con, &["SET", "restart", "1"]).await;
+    // the reader's sequence number is from before the server restarted, so the changes that
+    // have the same numbers now aren't the ones it's missing
+    let stale = (changelog.epoch() - 1).to_string();
+    let output = run(&mut db, &mut con, &["SYNCSTREAM", &stale, "1"]).await;
+    let output = String::from_utf8_lossy(&output);
+    assert!(output.starts_with("*1\n!"), "{}", output);
+    // so it's told the current epoch to start over in, instead of being sent its changes
+    let too_far_behind = format!("sync-too-far-behind:{}:", changelog.epoch());
+    assert!(output.contains(&too_far_behind), "{}", output);
+    assert!(!output.contains("restart"));
+}
+
+#[tokio::test]
+async fn test_sys_shutdown_needs_the_prepared_token() {
+    use crate::registry::ShutdownKind;
+    use std::time::Duration;
+    async fn prepare(db: &mut Corestore, con: &mut TestConnection) -> String {
+        let output = run(db, con, &["SYS", "SHUTDOWN", "PREPARE"]).await;
+        assert!(output.starts_with(b"*1\n+16\n"));
+        String::from_utf8(output[7..output.len() - 1].to_vec()).unwrap()
+    }
+    let mut db = new_store();
+    let mut con = new_con();
+    let bad_token = output_of(responses::groups::BAD_SHUTDOWN_TOKEN);
+    // nothing was prepared yet
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "SHUTDOWN", "0123456789abcdef"]).await,
+        bad_token
+    );
+    // the token is only good on the connection that it was handed out on
+    let token = prepare(&mut db, &mut con).await;
+    let mut other = new_con();
+    assert_eq!(
+        run(&mut db, &mut other, &["SYS", "SHUTDOWN", &token]).await,
+        bad_token
+    );
+    // and a wrong token discards it
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "SHUTDOWN", "nope"]).await,
+        bad_token
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "SHUTDOWN", &token]).await,
+        bad_token
+    );
+    assert!(registry::get_shutdown().get().is_none());
+    // the client hears back and the serving loop is woken up
+    let token = prepare(&mut db, &mut con).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "SHUTDOWN", &token]).await,
+        output_of(responses::groups::OKAY)
+    );
+    assert_eq!(registry::get_shutdown().get(), Some(ShutdownKind::Shutdown));
+    tokio::time::timeout(Duration::from_secs(1), registry::get_shutdown().wait())
+        .await
+        .unwrap();
+    // a token is only good once
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "RESTART", &token]).await,
+        bad_token
+    );
+}
+
+#[tokio::test]
+async fn test_attached_snapshots_are_read_only_copies() {
+    use crate::registry::MemoryGuard;
+    use crate::storage::interface::override_data_dir;
+    use std::{env, fs, process};
+    let root = env::temp_dir().join(format!("skyd-attachsnap-{}", process::id()));
+    let _ = fs::remove_dir_all(&root);
+    override_data_dir(Some(root.to_str().unwrap()));
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let read_only = output_of(responses::groups::READ_ONLY_ENTITY);
+    run(&mut db, &mut con, &["MSET", "a", "1", "b", "2"]).await;
+    assert_eq!(run(&mut db, &mut con, &["MKSNAP", "before"]).await, okay);
+    // the live data moves on
+    run(&mut db, &mut con, &["UPDATE", "a", "10"]).await;
+    run(&mut db, &mut con, &["DEL", "b"]).await;
+    run(&mut db, &mut con, &["SET", "c", "3"]).await;
+    let attached = run(
+        &mut db,
+        &mut con,
+        &["ATTACHSNAP", "remote/before", "AS", "old"],
+    )
+    .await;
+    assert!(attached.starts_with(&output_of(
+        b"_6\n+8\nkeyspace\n+3\nold\n+6\ntables\n+1\n1\n+5\nbytes\n"
+    )));
+    let nil = output_of(responses::groups::NIL);
+    let reads = [
+        ("a", output_of(b"+2\n10\n"), output_of(b"+1\n1\n")),
+        ("b", nil.clone(), output_of(b"+1\n2\n")),
+        ("c", output_of(b"+1\n3\n"), nil.clone()),
+    ];
+    for (key, live, old) in reads.iter() {
+        assert_eq!(&run(&mut db, &mut con, &["GET", *key]).await, live);
+        run(&mut db, &mut con, &["USE", "old"]).await;
+        assert_eq!(&run(&mut db, &mut con, &["GET", *key]).await, old);
+        run(&mut db, &mut con, &["USE", "default:default"]).await;
+    }
+    // nothing in it can be changed
+    assert_eq!(run(&mut db, &mut con, &["USE", "old"]).await, okay);
+    let writes: [&[&str]; 8] = [
+        &["SET", "d", "4"],
+        &["UPDATE", "a", "100"],
+        &["DEL", "a"],
+        &["FLUSHDB"],
+        &["PROTECT", "a"],
+        &["FLUSHKS"],
+        &["CREATE", "TABLE", "old:new", "keymap(str,str)"],
+        &["DROP", "KEYSPACE", "old"],
+    ];
+    for query in writes.iter() {
+        assert_eq!(run(&mut db, &mut con, query).await, read_only);
+    }
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "a"]).await,
+        output_of(b"+1\n1\n")
+    );
+    // it's left out of what's flushed, but its memory is reported
+    assert!(!db.get_store().keyspaces.contains_key("old".as_bytes()));
+    let report = crate::admin::sys::memory_report(&db);
+    assert!(report
+        .iter()
+        .any(|(name, bytes)| name == "attached_bytes:old" && bytes != "0"));
+    // it can't go away while it's in use
+    assert_eq!(
+        run(&mut db, &mut con, &["DETACHSNAP", "old"]).await,
+        output_of(responses::groups::STILL_IN_USE)
+    );
+    run(&mut db, &mut con, &["USE", "default:default"]).await;
+    assert_eq!(run(&mut db, &mut con, &["DETACHSNAP", "old"]).await, okay);
+    assert_eq!(
+        run(&mut db, &mut con, &["USE", "old"]).await,
+        output_of(responses::groups::CONTAINER_NOT_FOUND)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["DETACHSNAP", "old"]).await,
+        output_of(responses::groups::CONTAINER_NOT_FOUND)
+    );
+    // a snapshot that doesn't fit in memory isn't attached
+    let guard: &'static MemoryGuard = Box::leak(Box::new(MemoryGuard::new()));
+    guard.configure(Some(1));
+    registry::override_memory_guard(Some(guard));
+    assert_eq!(
+        run(&mut db, &mut con, &["ATTACHSNAP", "remote/before"]).await,
+        output_of(responses::groups::OUT_OF_MEMORY)
+    );
+    registry::override_memory_guard(None);
+    assert_eq!(db.get_store().attached.len(), 0);
+    // and without the alias, it's named after the snapshot
+    let attached = run(&mut db, &mut con, &["ATTACHSNAP", "remote/before"]).await;
+    assert!(attached.starts_with(&output_of(b"_6\n+8\nkeyspace\n+18\nsnap_remote_before\n")));
+    assert_eq!(
+        run(&mut db, &mut con, &["DETACHSNAP", "snap_remote_before"]).await,
+        okay
+    );
+    override_data_dir(None);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_archived_keyspaces() {
+    use crate::corestore::memstore::ObjectID;
+    use crate::storage::interface::override_data_dir;
+    use crate::storage::unflush;
+    use std::{env, fs, process};
+    let root = env::temp_dir().join(format!("skyd-archive-{}", process::id()));
+    let _ = fs::remove_dir_all(&root);
+    override_data_dir(Some(root.to_str().unwrap()));
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let archived = output_of(responses::groups::KEYSPACE_ARCHIVED);
+    let tenant = unsafe { ObjectID::from_slice("tenant") };
+    let tenant_bytes = |db: &Corestore| {
+        crate::admin::sys::memory_report(db)
+            .into_iter()
+            .find(|(name, _)| name == "keyspace_tracked_bytes:tenant")
+            .map(|(_, bytes)| bytes)
+            .unwrap()
+    };
+    let setup: [&[&str]; 4] = [
+        &["CREATE", "KEYSPACE", "tenant"],
+        &["CREATE", "TABLE", "tenant:t", "keymap(str,str)"],
+        &["USE", "tenant:t"],
+        &["MSET", "a", "1", "b", "2"],
+    ];
+    for query in setup.iter() {
+        run(&mut db, &mut con, query).await;
+    }
+    // the keyspace can't be archived while one of its tables is in use
+    assert_eq!(
+        run(&mut db, &mut con, &["ARCHIVE", "KEYSPACE", "tenant"]).await,
+        output_of(responses::groups::STILL_IN_USE)
+    );
+    run(&mut db, &mut con, &["USE", "default:default"]).await;
+    assert_ne!(tenant_bytes(&db), "0");
+    assert_eq!(
+        run(&mut db, &mut con, &["ARCHIVE", "KEYSPACE", "tenant"]).await,
+        okay
+    );
+    assert_eq!(tenant_bytes(&db), "0");
+    assert_eq!(
+        run(&mut db, &mut con, &["ARCHIVE", "KEYSPACE", "default"]).await,
+        output_of(responses::groups::PROTECTED_OBJECT)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["ARCHIVE", "KEYSPACE", "nope"]).await,
+        output_of(responses::groups::CONTAINER_NOT_FOUND)
+    );
+    // everything that would touch its data is turned away
+    assert_eq!(run(&mut db, &mut con, &["USE", "tenant:t"]).await, okay);
+    let rejected: [&[&str]; 10] = [
+        &["GET", "a"],
+        &["SET", "c", "3"],
+        &["DBSIZE"],
+        &["LSKEYS"],
+        &["FLUSHDB"],
+        &["FLUSHKS", "tenant"],
+        &["INSPECT", "TABLE", "tenant:t"],
+        &["CREATE", "TABLE", "tenant:u", "keymap(str,str)"],
+        &["DROP", "TABLE", "tenant:t"],
+        &["ALTER", "KEYSPACE", "tenant", "DEFAULT", "TABLE", "t"],
+    ];
+    for query in rejected.iter() {
+        assert_eq!(run(&mut db, &mut con, query).await, archived, "{:?}", query);
+    }
+    let table = db.get_ctable().unwrap();
+    assert!(!table.is_loaded());
+    run(&mut db, &mut con, &["USE", "default:default"]).await;
+    drop(table);
+    // a snapshot copies its files and it's still archived after a restart
+    assert_eq!(run(&mut db, &mut con, &["MKSNAP", "archived"]).await, okay);
+    let snapped = run(
+        &mut db,
+        &mut con,
+        &["ATTACHSNAP", "remote/archived", "AS", "old"],
+    )
+    .await;
+    assert!(snapped.starts_with(&output_of(b"_6\n+8\nkeyspace\n+3\nold\n")));
+    run(&mut db, &mut con, &["USE", "old:tenant_t"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "a"]).await,
+        output_of(b"+1\n1\n")
+    );
+    run(&mut db, &mut con, &["USE", "default:default"]).await;
+    assert_eq!(run(&mut db, &mut con, &["DETACHSNAP", "old"]).await, okay);
+    let restarted = unflush::read_keyspace_with(&tenant, false).unwrap();
+    assert!(restarted.is_archived());
+    assert!(!restarted
+        .get_table_atomic_ref("t".as_bytes())
+        .unwrap()
+        .is_loaded());
+    assert_eq!(tenant_bytes(&db), "0");
+    // and it's read back in once it's unarchived
+    assert_eq!(
+        run(&mut db, &mut con, &["UNARCHIVE", "KEYSPACE", "tenant"]).await,
+        okay
+    );
+    run(&mut db, &mut con, &["USE", "tenant:t"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["MGET", "a", "b"]).await,
+        output_of(b"&2\n+1\n1\n+1\n2\n")
+    );
+    assert_eq!(run(&mut db, &mut con, &["SET", "c", "3"]).await, okay);
+    assert_ne!(tenant_bytes(&db), "0");
+    assert!(!unflush::read_keyspace_with(&tenant, false)
+        .unwrap()
+        .is_archived());
+    override_data_dir(None);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_tables_in_use_by_another_connection_are_kept() {
+    use crate::storage::interface::override_data_dir;
+    use std::{env, fs, process};
+    let root = env::temp_dir().join(format!("skyd-inuse-{}", process::id()));
+    let _ = fs::remove_dir_all(&root);
+    override_data_dir(Some(root.to_str().unwrap()));
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let still_in_use = output_of(responses::groups::STILL_IN_USE);
+    let setup: [&[&str]; 4] = [
+        &["CREATE", "KEYSPACE", "tenant"],
+        &["CREATE", "TABLE", "tenant:t", "keymap(str,str)"],
+        &["SET", "a", "1"],
+        &["MKSNAP", "inuse"],
+    ];
+    for query in setup.iter() {
+        run(&mut db, &mut con, query).await;
+    }
+    let attached = run(
+        &mut db,
+        &mut con,
+        &["ATTACHSNAP", "remote/inuse", "AS", "old"],
+    )
+    .await;
+    assert!(attached.starts_with(&output_of(b"_6\n+8\nkeyspace\n+3\nold\n")));
+    // another connection is using a table in each of them
+    let (mut tenant, mut tenant_con) = (db.clone(), new_con());
+    let (mut old, mut old_con) = (db.clone(), new_con());
+    run(&mut tenant, &mut tenant_con, &["USE", "tenant:t"]).await;
+    run(&mut tenant, &mut tenant_con, &["SET", "b", "2"]).await;
+    run(&mut old, &mut old_con, &["USE", "old:default_default"]).await;
+    // so neither can be taken from under it
+    assert_eq!(
+        run(&mut db, &mut con, &["ARCHIVE", "KEYSPACE", "tenant"]).await,
+        still_in_use
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["DETACHSNAP", "old"]).await,
+        still_in_use
+    );
+    assert_eq!(
+        run(&mut tenant, &mut tenant_con, &["GET", "b"]).await,
+        output_of(b"+1\n2\n")
+    );
+    assert_eq!(
+        run(&mut old, &mut old_con, &["GET", "a"]).await,
+        output_of(b"+1\n1\n")
+    );
+    // until the other connections move on
+    run(&mut tenant, &mut tenant_con, &["USE", "default:default"]).await;
+    run(&mut old, &mut old_con, &["USE", "default:default"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["ARCHIVE", "KEYSPACE", "tenant"]).await,
+        okay
+    );
+    assert_eq!(run(&mut db, &mut con, &["DETACHSNAP", "old"]).await, okay);
+    override_data_dir(None);
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[tokio::test]
+async fn test_sys_idle_tables() {
+    use crate::corestore::clock::MockClock;
+    let clock = Arc::new(MockClock::at(2021, 7, 1, 10, 0, 0));
+    let store = Memstore::new_default().with_clock(clock.clone());
+    let mut db = Corestore::default_with_store(store);
+    let mut con = new_con();
+    run(&mut db, &mut con, &["CREATE", "KEYSPACE", "idle"]).await;
+    for table in ["idle:a", "idle:b"].iter() {
+        run(
+            &mut db,
+            &mut con,
+            &["CREATE", "TABLE", *table, "keymap(str,str)"],
+        )
+        .await;
+    }
+    // only `idle:a` is written to after it was created
+    clock.advance(chrono::Duration::seconds(30));
+    run(&mut db, &mut con, &["USE", "idle:a"]).await;
+    run(&mut db, &mut con, &["SET", "x", "1"]).await;
+    clock.advance(chrono::Duration::seconds(60));
+    // the default table was never used, so it's always listed
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "IDLE", "TABLES", "61"]).await,
+        output_of(b"_4\n+15\ndefault:default\n+5\nnever\n+6\nidle:b\n+2\n90\n")
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "IDLE", "TABLES", "60"]).await,
+        output_of(b"_6\n+15\ndefault:default\n+5\nnever\n+6\nidle:b\n+2\n90\n+6\nidle:a\n+2\n60\n")
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "IDLE", "TABLES", "soon"]).await,
+        output_of(responses::groups::WRONGTYPE_ERR)
+    );
+}
+
+#[tokio::test]
+async fn test_sandbox_never_touches_the_data_directory() {
+    use crate::storage::interface::override_data_dir;
+    use std::{env, fs, process};
+    let root = env::temp_dir().join(format!("skyd-sandbox-{}", process::id()));
+    let _ = fs::remove_dir_all(&root);
+    override_data_dir(Some(root.to_str().unwrap()));
+    registry::override_sandbox(Some(true));
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let queries: [&[&str]; 9] = [
+        &["SET", "x", "100"],
+        &["UPDATE", "x", "200"],
+        &["CREATE", "KEYSPACE", "sandbox"],
+        &["CREATE", "TABLE", "sandbox:users", "keymap(str,str)"],
+        &["USE", "sandbox:users"],
+        &["SET", "alice", "admin"],
+        &["USE", "default:default"],
+        &["DROP", "TABLE", "sandbox:users"],
+        &["DROP", "KEYSPACE", "sandbox"],
+    ];
+    for query in queries.iter() {
+        assert_eq!(run(&mut db, &mut con, query).await, okay, "{:?}", query);
+    }
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "x"]).await,
+        output_of(b"+3\n200\n")
+    );
+    // everything that needs the data directory says why it won't work
+    let sandboxed = output_of(responses::groups::SANDBOX_MODE);
+    let persistence: [&[&str]; 7] = [
+        &["MKSNAP"],
+        &["MKSNAP", "named"],
+        &["MKSNAP", "INCREMENTAL", "named"],
+        &["ATTACHSNAP", "remote/named"],
+        &["SYS", "COMPACT"],
+        &["SYS", "FLUSHWAIT", "10"],
+        &["ARCHIVE", "KEYSPACE", "default"],
+    ];
+    for query in persistence.iter() {
+        assert_eq!(
+            run(&mut db, &mut con, query).await,
+            sandboxed,
+            "{:?}",
+            query
+        );
+    }
+    assert!(!root.exists());
+    registry::override_sandbox(None);
+    override_data_dir(None);
+}
+
+#[tokio::test]
+async fn test_case_insensitive_names() {
+    registry::override_case_insensitive(Some(true));
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let exists = output_of(responses::groups::ALREADY_EXISTS);
+    let queries: [&[&str]; 5] = [
+        &["CREATE", "KEYSPACE", "Foo"],
+        &["CREATE", "TABLE", "FOO:Bar", "keymap(str,str)"],
+        &["ALTER", "KEYSPACE", "foo", "DEFAULT", "TABLE", "BAR"],
+        &["USE", "foo"],
+        &["SET", "x", "1"],
+    ];
+    for query in queries.iter() {
+        assert_eq!(run(&mut db, &mut con, query).await, okay, "{:?}", query);
+    }
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "x"]).await,
+        output_of(b"+1\n1\n")
+    );
+    // the names are shown as they were created
+    assert_eq!(
+        run(&mut db, &mut con, &["INSPECT", "KEYSPACE", "fOO"]).await,
+        output_of(b"_1\n+3\nBar\n")
+    );
+    assert_eq!(
+        run(
+            &mut db,
+            &mut con,
+            &["INSPECT", "KEYSPACE", "foo", "DEFAULT"]
+        )
+        .await,
+        output_of(b"+3\nBar\n")
+    );
+    let keyspaces = run(&mut db, &mut con, &["INSPECT", "KEYSPACES"]).await;
+    assert!(keyspaces.windows(6).any(|w| w == b"+3\nFoo"));
+    // while they're kept (and compared) lowercased
+    assert!(db.get_store().keyspaces.contains_key("foo".as_bytes()));
+    let duplicates: [&[&str]; 2] = [
+        &["CREATE", "KEYSPACE", "FOO"],
+        &["CREATE", "TABLE", "foo:bar", "keymap(str,str)"],
+    ];
+    for query in duplicates.iter() {
+        assert_eq!(run(&mut db, &mut con, query).await, exists, "{:?}", query);
+    }
+    // switched off, the names are case sensitive again
+    registry::override_case_insensitive(Some(false));
+    assert_eq!(
+        run(&mut db, &mut con, &["CREATE", "KEYSPACE", "FOO"]).await,
+        okay
+    );
+    registry::override_case_insensitive(None);
+}
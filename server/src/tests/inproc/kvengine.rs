@@ -0,0 +1,815 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! In-process tests for the key/value actions: what they do when writes are held back, how
+//! they handle values and keys that they can't take, and expiry
+
+use super::*;
+use crate::protocol::responses;
+use crate::registry;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn test_reads_and_writes_in_blocked_states() {
+    use crate::registry::SystemState;
+    let mut db = new_store();
+    let mut con = new_con();
+    let server_err = output_of(responses::groups::SERVER_ERR);
+    run(&mut db, &mut con, &["SET", "x", "100"]).await;
+    // reads are served from memory while writes are blocked
+    registry::override_state(Some(SystemState::WriteBlocked));
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "x"]).await,
+        output_of(b"+3\n100\n")
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["MGET", "x"]).await,
+        output_of(b"&1\n+3\n100\n")
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SET", "y", "200"]).await,
+        server_err
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["UPDATE", "x", "200"]).await,
+        server_err
+    );
+    // but nothing is served once the system is fully poisoned
+    registry::override_state(Some(SystemState::Poisoned));
+    for query in [
+        &["GET", "x"][..],
+        &["EXISTS", "x"],
+        &["DBSIZE"],
+        &["SET", "y", "200"],
+    ]
+    .iter()
+    {
+        assert_eq!(run(&mut db, &mut con, query).await, server_err);
+    }
+    registry::override_state(None);
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "x"]).await,
+        output_of(b"+3\n100\n")
+    );
+}
+
+#[tokio::test]
+async fn test_writes_are_rejected_above_the_memory_ceiling() {
+    use crate::registry::MemoryGuard;
+    use crate::services::memwatch::{check_memory, estimated_bytes};
+    let guard: &'static MemoryGuard = Box::leak(Box::new(MemoryGuard::new()));
+    registry::override_memory_guard(Some(guard));
+    let mut db = new_store();
+    let mut con = new_con();
+    let oom = output_of(responses::groups::OUT_OF_MEMORY);
+    let okay = output_of(responses::groups::OKAY);
+    // a tiny ceiling, which a single large value takes the server over
+    guard.configure(Some(1024));
+    let large = "x".repeat(2048);
+    assert_eq!(run(&mut db, &mut con, &["SET", "x", &large]).await, okay);
+    assert_eq!(run(&mut db, &mut con, &["SET", "y", "100"]).await, okay);
+    check_memory(guard, estimated_bytes(&db));
+    assert!(guard.is_blocking());
+    assert_eq!(
+        registry::get_serving_state(),
+        registry::SystemState::WriteBlocked
+    );
+    // writes are rejected
+    assert_eq!(run(&mut db, &mut con, &["SET", "z", "300"]).await, oom);
+    assert_eq!(run(&mut db, &mut con, &["UPDATE", "y", "200"]).await, oom);
+    assert_eq!(run(&mut db, &mut con, &["MSET", "z", "300"]).await, oom);
+    // but reads are served
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "y"]).await,
+        output_of(b"+3\n100\n")
+    );
+    // and deleting (which frees memory) works
+    assert_eq!(
+        run(&mut db, &mut con, &["DEL", "x"]).await,
+        output_of(b":1\n1\n")
+    );
+    check_memory(guard, estimated_bytes(&db));
+    assert!(!guard.is_blocking());
+    assert_eq!(registry::get_serving_state(), registry::SystemState::Okay);
+    // so writes are accepted again
+    assert_eq!(run(&mut db, &mut con, &["SET", "z", "300"]).await, okay);
+    registry::override_memory_guard(None);
+}
+
+#[tokio::test]
+async fn test_expire_and_persist_prefix() {
+    use crate::corestore::clock::MockClock;
+    use crate::services::expiry::sweep_expired;
+    let clock = Arc::new(MockClock::at(2021, 7, 1, 10, 0, 0));
+    let store = Memstore::new_default().with_clock(clock.clone());
+    let mut db = Corestore::default_with_store(store);
+    let mut con = new_con();
+    let count = |n| output_of(&old_usize(n));
+    for key in ["session:a", "session:b", "session:c", "user:a"].iter() {
+        run(&mut db, &mut con, &["SET", *key, "1"]).await;
+    }
+    assert_eq!(
+        run(&mut db, &mut con, &["EXPIREPREFIX", "session:", "60"]).await,
+        count(3)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["PERSISTPREFIX", "session:c"]).await,
+        count(1)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["EXPIREPREFIX", "session:", "soon"]).await,
+        output_of(responses::groups::WRONGTYPE_ERR)
+    );
+    // the expiry goes away with the key, so setting it again doesn't bring it back
+    run(&mut db, &mut con, &["GETDEL", "session:b"]).await;
+    run(&mut db, &mut con, &["SET", "session:b", "2"]).await;
+    clock.advance(chrono::Duration::seconds(59));
+    assert_eq!(sweep_expired(db.get_store()), 0);
+    clock.advance(chrono::Duration::seconds(1));
+    assert_eq!(sweep_expired(db.get_store()), 1);
+    let nil = output_of(responses::groups::NIL);
+    assert_eq!(run(&mut db, &mut con, &["GET", "session:a"]).await, nil);
+    for key in ["session:b", "session:c", "user:a"].iter() {
+        assert_ne!(run(&mut db, &mut con, &["GET", *key]).await, nil);
+    }
+}
+
+#[tokio::test]
+async fn test_expiry_is_an_event_of_its_own() {
+    use crate::corestore::clock::MockClock;
+    use crate::registry::EventReader;
+    use crate::services::expiry::sweep_expired;
+    let clock = Arc::new(MockClock::at(2021, 7, 1, 10, 0, 0));
+    let store = Memstore::new_default().with_clock(clock.clone());
+    let mut db = Corestore::default_with_store(store);
+    let mut con = new_con();
+    let changelog = registry::get_changelog();
+    let entity = db.get_kvstore().unwrap().entity().unwrap().clone();
+    let mut reader = EventReader::new(changelog, entity);
+    run(&mut db, &mut con, &["SET", "expiring:watched", "1"]).await;
+    run(&mut db, &mut con, &["EXPIREPREFIX", "expiring:", "1"]).await;
+    clock.advance(chrono::Duration::seconds(1));
+    assert_eq!(sweep_expired(db.get_store()), 1);
+    // the change log is shared with the other tests, which also use this table
+    let (_, events) = reader.read(changelog);
+    let seen: Vec<&str> = events
+        .iter()
+        .filter(|event| event.key == Bytes::from("expiring:watched"))
+        .map(|event| event.event)
+        .collect();
+    assert_eq!(seen, vec!["set", "expired"]);
+}
+
+#[tokio::test]
+async fn test_set_treats_expired_keys_as_missing() {
+    use crate::corestore::clock::MockClock;
+    use crate::services::expiry::sweep_expired;
+    let clock = Arc::new(MockClock::at(2021, 7, 1, 10, 0, 0));
+    let store = Memstore::new_default().with_clock(clock.clone());
+    let mut db = Corestore::default_with_store(store);
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let nil = output_of(responses::groups::NIL);
+    for key in ["plain", "nx", "xx"].iter() {
+        assert_eq!(
+            run(&mut db, &mut con, &["SET", *key, "1", "EX", "10"]).await,
+            okay
+        );
+    }
+    // the keys are past their expiry time, but they weren't swept yet
+    clock.advance(chrono::Duration::seconds(10));
+    assert_eq!(run(&mut db, &mut con, &["SET", "plain", "2"]).await, okay);
+    assert_eq!(
+        run(&mut db, &mut con, &["SET", "nx", "2", "NX"]).await,
+        okay
+    );
+    assert_eq!(run(&mut db, &mut con, &["SET", "xx", "2", "XX"]).await, nil);
+    // the new keys don't have the expiry times of the old ones
+    let table = db.get_kvstore().unwrap();
+    for key in ["plain", "nx", "xx"].iter() {
+        assert_eq!(table.get_expiry(key.as_bytes()), None);
+    }
+    drop(table);
+    clock.advance(chrono::Duration::seconds(60));
+    assert_eq!(sweep_expired(db.get_store()), 0);
+    let two = output_of(b"+1\n2\n");
+    assert_eq!(run(&mut db, &mut con, &["GET", "plain"]).await, two);
+    assert_eq!(run(&mut db, &mut con, &["GET", "nx"]).await, two);
+    assert_eq!(run(&mut db, &mut con, &["GET", "xx"]).await, nil);
+    // a key that is still live is an overwrite error, and XX keeps working on it
+    run(&mut db, &mut con, &["SET", "live", "1", "EX", "10"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["SET", "live", "2"]).await,
+        output_of(responses::groups::OVERWRITE_ERR)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SET", "live", "2", "XX"]).await,
+        okay
+    );
+}
+
+#[tokio::test]
+async fn test_expired_keys_read_as_missing() {
+    use crate::corestore::clock::MockClock;
+    use crate::services::expiry::sweep_expired;
+    let clock = Arc::new(MockClock::at(2021, 7, 1, 10, 0, 0));
+    let store = Memstore::new_default().with_clock(clock.clone());
+    let mut db = Corestore::default_with_store(store);
+    let mut con = new_con();
+    let create = [
+        "CREATE",
+        "TABLE",
+        "default:cachedexp",
+        "keymap(str,str)",
+        "cached",
+    ];
+    run(&mut db, &mut con, &create).await;
+    let nil = output_of(responses::groups::NIL);
+    for table in ["default:default", "default:cachedexp"].iter() {
+        run(&mut db, &mut con, &["USE", *table]).await;
+        run(&mut db, &mut con, &["SET", "x", "100", "EX", "10"]).await;
+        // a read just before the expiry time puts the frame in the cache (if there is one)
+        clock.advance(chrono::Duration::seconds(9));
+        assert_eq!(
+            run(&mut db, &mut con, &["GET", "x"]).await,
+            output_of(b"+3\n100\n")
+        );
+        // the key wasn't swept yet, but none of the reads can see it
+        clock.advance(chrono::Duration::seconds(1));
+        assert_eq!(run(&mut db, &mut con, &["GET", "x"]).await, nil);
+        assert_eq!(
+            run(&mut db, &mut con, &["MGET", "x"]).await,
+            output_of(b"&1\n!1\n1\n")
+        );
+        assert_eq!(
+            run(&mut db, &mut con, &["GETM", "x", "WITHMISSING"]).await,
+            output_of(b"&2\n_0\n_1\n+1\nx\n")
+        );
+        assert_eq!(
+            run(&mut db, &mut con, &["EXISTS", "x"]).await,
+            output_of(&old_usize(0))
+        );
+        assert_eq!(run(&mut db, &mut con, &["KEYLEN", "x"]).await, nil);
+        assert_eq!(run(&mut db, &mut con, &["DUMPKEY", "x"]).await, nil);
+    }
+    // they're still there for the sweep to remove
+    assert_eq!(sweep_expired(db.get_store()), 2);
+}
+
+#[tokio::test]
+async fn test_validate_agrees_with_set() {
+    let mut db = new_store();
+    let mut con = new_con();
+    let create = ["CREATE", "TABLE", "default:strict", "keymap(str,str)"];
+    run(&mut db, &mut con, &create).await;
+    let okay = output_of(responses::groups::OKAY);
+    let bad_key = output_of(b"!16\nbad-key-encoding\n");
+    let bad_value = output_of(b"!18\nbad-value-encoding\n");
+    let (good, bad): (&[u8], &[u8]) = (b"fine", b"Hello \xF0\x90\x80World");
+    let pairs: [(&[u8], &[u8]); 4] = [(good, good), (bad, good), (good, bad), (bad, bad)];
+    for &(table, strict) in [("default:default", false), ("default:strict", true)].iter() {
+        run(&mut db, &mut con, &["USE", table]).await;
+        for (i, &(key, value)) in pairs.iter().enumerate() {
+            let validated = run_raw(&mut db, &mut con, &[b"VALIDATE", b"PAIR", key, value]).await;
+            let expected = match (strict, key == bad, value == bad) {
+                (true, true, _) => &bad_key,
+                (true, false, true) => &bad_value,
+                _ => &okay,
+            };
+            assert_eq!(&validated, expected);
+            // the key is made unique so that only the encoding can keep the write out
+            let key = [key, format!(":{}", i).as_bytes()].concat();
+            let set = run_raw(&mut db, &mut con, &[b"SET", &key[..], value]).await;
+            assert_eq!(set == okay, validated == okay);
+        }
+        // a key or a value on its own is checked the same way
+        let key_alone = run_raw(&mut db, &mut con, &[b"VALIDATE", b"KEY", bad]).await;
+        let value_alone = run_raw(&mut db, &mut con, &[b"VALIDATE", b"value", bad]).await;
+        if strict {
+            assert_eq!(
+                (key_alone, value_alone),
+                (bad_key.clone(), bad_value.clone())
+            );
+        } else {
+            assert_eq!((key_alone, value_alone), (okay.clone(), okay.clone()));
+        }
+    }
+    // another table can be named, and nothing was touched
+    run(&mut db, &mut con, &["USE", "default:default"]).await;
+    let dbsize = run(&mut db, &mut con, &["DBSIZE"]).await;
+    assert_eq!(
+        run_raw(
+            &mut db,
+            &mut con,
+            &[b"VALIDATE", b"default:strict", b"KEY", bad]
+        )
+        .await,
+        bad_key
+    );
+    assert_eq!(run(&mut db, &mut con, &["DBSIZE"]).await, dbsize);
+    assert_eq!(
+        run(&mut db, &mut con, &["VALIDATE", "PAIR", "onlykey"]).await,
+        output_of(responses::groups::ACTION_ERR)
+    );
+    assert_eq!(
+        run(
+            &mut db,
+            &mut con,
+            &["VALIDATE", "default:strict", "BOTH", "x"]
+        )
+        .await,
+        output_of(responses::groups::UNKNOWN_VALIDATE_QUERY)
+    );
+}
+
+fn dump_of(value: &[u8]) -> String {
+    String::from_utf8(crate::actions::dump::serialize(value)).unwrap()
+}
+
+#[tokio::test]
+async fn test_value_size_limit_at_the_boundary() {
+    use crate::kvengine::ValueTooLarge;
+    let mut db = new_store();
+    let mut con = new_con();
+    // this was written before there was a limit
+    run(&mut db, &mut con, &["SET", "old", "toolong"]).await;
+    db.get_kvstore().unwrap().set_value_limit(Some(4));
+    let okay = output_of(responses::groups::OKAY);
+    let too_large = output_of(&ValueTooLarge(4).error());
+    assert_eq!(run(&mut db, &mut con, &["SET", "a", "1234"]).await, okay);
+    assert_eq!(
+        run(&mut db, &mut con, &["SET", "b", "12345"]).await,
+        too_large
+    );
+    assert_eq!(run(&mut db, &mut con, &["UPDATE", "a", "4321"]).await, okay);
+    assert_eq!(
+        run(&mut db, &mut con, &["UPDATE", "a", "54321"]).await,
+        too_large
+    );
+    // nothing is written if any of the values is too large
+    let queries: [&[&str]; 6] = [
+        &["MSET", "c", "1234", "d", "12345"],
+        &["MSET", "STRICT", "c", "1234", "d", "12345"],
+        &["MUPDATE", "a", "1", "old", "12345"],
+        &["USET", "c", "1", "d", "12345"],
+        &["SSET", "c", "1", "d", "12345"],
+        &["SUPDATE", "a", "1", "old", "12345"],
+    ];
+    for query in queries.iter() {
+        assert_eq!(run(&mut db, &mut con, query).await, too_large);
+    }
+    let (over, at) = (dump_of(b"12345"), dump_of(b"1234"));
+    assert_eq!(
+        run(&mut db, &mut con, &["RESTOREKEY", "r", over.as_str()]).await,
+        too_large
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["RESTOREKEY", "r", at.as_str()]).await,
+        okay
+    );
+    let (pairs, _) = contents_of(&db);
+    let pairs: Vec<(Vec<u8>, Vec<u8>)> = pairs
+        .iter()
+        .map(|(k, v)| (k.to_vec(), v.to_vec()))
+        .collect();
+    let expected = [("a", "4321"), ("old", "toolong"), ("r", "1234")];
+    let expected: Vec<(Vec<u8>, Vec<u8>)> = expected
+        .iter()
+        .map(|(k, v)| (k.as_bytes().to_vec(), v.as_bytes().to_vec()))
+        .collect();
+    assert_eq!(pairs, expected);
+    // the value that was already too large still reads fine
+    assert_ne!(
+        run(&mut db, &mut con, &["GET", "old"]).await,
+        output_of(responses::groups::NIL)
+    );
+}
+
+#[tokio::test]
+async fn test_reads_match_old_output() {
+    let mut db = new_store();
+    let mut con = new_con();
+    let create = ["CREATE", "TABLE", "default:strkeys", "keymap(str,binstr)"];
+    run(&mut db, &mut con, &create).await;
+    run(&mut db, &mut con, &["USE", "default:strkeys"]).await;
+    run(&mut db, &mut con, &["SET", "x", "100"]).await;
+    let nil = output_of(responses::groups::NIL);
+    let bad_key: &[u8] = b"Hello \xF0\x90\x80World";
+    let dump = dump_of(b"100");
+    let dump = format!("+{}\n{}\n", dump.len(), dump);
+    // (query, present key, absent key, badly encoded key)
+    let expected: [(&str, Vec<u8>, Vec<u8>, Vec<u8>); 4] = [
+        ("GET", output_of(b"+3\n100\n"), nil.clone(), nil.clone()),
+        (
+            "MGET",
+            output_of(b"&1\n+3\n100\n"),
+            output_of(b"&1\n!1\n1\n"),
+            output_of(b"&1\n!1\n1\n"),
+        ),
+        ("KEYLEN", output_of(&old_usize(3)), nil.clone(), nil.clone()),
+        (
+            "DUMPKEY",
+            output_of(dump.as_bytes()),
+            nil.clone(),
+            nil.clone(),
+        ),
+    ];
+    for (action, present, absent, bad) in expected.iter() {
+        let action = action.as_bytes();
+        assert_eq!(&run_raw(&mut db, &mut con, &[action, b"x"]).await, present);
+        assert_eq!(
+            &run_raw(&mut db, &mut con, &[action, b"nope"]).await,
+            absent
+        );
+        assert_eq!(&run_raw(&mut db, &mut con, &[action, bad_key]).await, bad);
+    }
+}
+
+#[tokio::test]
+async fn test_unreadable_values() {
+    let mut db = new_store();
+    let mut con = new_con();
+    let create = ["CREATE", "TABLE", "default:rotten", "keymap(str,str)"];
+    run(&mut db, &mut con, &create).await;
+    run(&mut db, &mut con, &["USE", "default:rotten"]).await;
+    run(
+        &mut db,
+        &mut con,
+        &["MSET", "good", "value", "bad", "value"],
+    )
+    .await;
+    db.get_kvstore().unwrap().corrupt_value(
+        Data::from("bad"),
+        Data::from(b"Hello \xF0\x90\x80World".to_vec()),
+    );
+    let counted = registry::get_unreadable_values();
+    let unreadable = output_of(responses::groups::VALUE_UNREADABLE);
+    // the key is there, so it isn't reported as missing
+    assert_eq!(run(&mut db, &mut con, &["GET", "bad"]).await, unreadable);
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "absent"]).await,
+        output_of(responses::groups::NIL)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["MGET", "good", "bad", "absent"]).await,
+        output_of(b"&3\n+5\nvalue\n!20\nerr-value-unreadable\n!1\n1\n")
+    );
+    assert_eq!(run(&mut db, &mut con, &["GETDEL", "bad"]).await, unreadable);
+    // popping it doesn't throw the value away
+    assert_eq!(
+        run(&mut db, &mut con, &["POP", "bad"]).await,
+        output_of(b"&1\n!20\nerr-value-unreadable\n")
+    );
+    assert!(registry::get_unreadable_values() >= counted + 4);
+    // the scan finds the key, and only that key
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "UNREADABLE", "SCAN"]).await,
+        output_of(b"_1\n+3\nbad\n")
+    );
+    assert_eq!(
+        run(
+            &mut db,
+            &mut con,
+            &["SYS", "UNREADABLE", "SCAN", "default:default"]
+        )
+        .await,
+        output_of(b"_0\n")
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "UNREADABLE", "LIST"]).await,
+        output_of(responses::groups::UNKNOWN_SYS_QUERY)
+    );
+}
+
+#[tokio::test]
+async fn test_multi_key_actions_work_in_chunks() {
+    use crate::actions::CHUNK_SIZE;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    let mut db = new_store();
+    let mut con = new_con();
+    let keys: Vec<String> = (0..CHUNK_SIZE * 2 + 7)
+        .map(|i| format!("key{}", i))
+        .collect();
+    let query_of = |action: &'static str, value: Option<&'static str>| {
+        let mut query = vec![action];
+        for key in keys.iter() {
+            query.push(key.as_str());
+            query.extend(value);
+        }
+        query
+    };
+    assert_eq!(
+        run(&mut db, &mut con, &query_of("MSET", Some("v"))).await,
+        output_of(&old_usize(keys.len()))
+    );
+    // the other tasks on this (single threaded) runtime only run if we yield
+    let yielded = Arc::new(AtomicBool::new(false));
+    let flag = yielded.clone();
+    tokio::spawn(async move { flag.store(true, Ordering::SeqCst) });
+    assert_eq!(
+        run(&mut db, &mut con, &["EXISTS", "key0", "nope"]).await,
+        output_of(&old_usize(1))
+    );
+    assert!(!yielded.load(Ordering::SeqCst));
+    let mut exists = query_of("EXISTS", None);
+    exists.push("nope");
+    assert_eq!(
+        run(&mut db, &mut con, &exists).await,
+        output_of(&old_usize(keys.len()))
+    );
+    assert!(yielded.load(Ordering::SeqCst));
+    let mut mget = query_of("MGET", None);
+    mget.push("nope");
+    let mut expected = old_length('&', keys.len() + 1);
+    keys.iter()
+        .for_each(|_| expected.extend_from_slice(b"+1\nv\n"));
+    expected.extend_from_slice(responses::groups::NIL);
+    assert_eq!(run(&mut db, &mut con, &mget).await, output_of(&expected));
+    assert_eq!(
+        run(&mut db, &mut con, &query_of("DEL", None)).await,
+        output_of(&old_usize(keys.len()))
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["DBSIZE"]).await,
+        output_of(&old_usize(0))
+    );
+}
+
+#[tokio::test]
+async fn test_poison_during_multi_key_writes() {
+    use crate::actions::CHUNK_SIZE;
+    use crate::registry::SystemState;
+    let mut db = new_store();
+    let mut con = new_con();
+    let keys: Vec<String> = (0..CHUNK_SIZE + 1).map(|i| format!("key{}", i)).collect();
+    let mut mset = vec!["MSET"];
+    for key in keys.iter() {
+        mset.push(key.as_str());
+        mset.push("v");
+    }
+    let (mut pop, mut del) = (vec!["POP"], vec!["DEL"]);
+    pop.extend(keys.iter().map(|key| key.as_str()));
+    del.extend(keys.iter().map(|key| key.as_str()));
+    for query in [&mset, &pop, &del].iter() {
+        // even if the system is unpoisoned before the query is done, it was poisoned
+        // while the query ran
+        for unpoison in [false, true].iter().copied() {
+            run(&mut db, &mut con, &mset).await;
+            // this runs when the action yields after the first chunk of keys
+            tokio::spawn(async move {
+                registry::override_state(Some(SystemState::WriteBlocked));
+                if unpoison {
+                    registry::override_state(None);
+                }
+            });
+            assert_eq!(
+                run(&mut db, &mut con, query).await,
+                output_of(responses::groups::SERVER_ERR)
+            );
+            registry::override_state(None);
+        }
+    }
+    // and nothing changes if the system isn't poisoned
+    run(&mut db, &mut con, &mset).await;
+    assert_eq!(
+        run(&mut db, &mut con, &del).await,
+        output_of(&old_usize(keys.len()))
+    );
+}
+
+#[tokio::test]
+async fn test_poison_mid_pop_puts_the_values_back() {
+    use crate::actions::CHUNK_SIZE;
+    use crate::corestore::clock::MockClock;
+    use crate::registry::SystemState;
+    let clock = Arc::new(MockClock::at(2021, 7, 1, 10, 0, 0));
+    let store = Memstore::new_default().with_clock(clock.clone());
+    let mut db = Corestore::default_with_store(store);
+    let mut con = new_con();
+    let keys: Vec<String> = (0..CHUNK_SIZE + 1).map(|i| format!("key{}", i)).collect();
+    let mut mset = vec!["MSET"];
+    for key in keys.iter() {
+        mset.push(key.as_str());
+        mset.push("v");
+    }
+    run(&mut db, &mut con, &mset).await;
+    run(&mut db, &mut con, &["SET", "expiring", "v", "EX", "10"]).await;
+    let mut pop = vec!["POP", "expiring"];
+    pop.extend(keys.iter().map(|key| key.as_str()));
+    pop.push("missing");
+    // this runs when the action yields after the first chunk of keys, which are popped by then
+    tokio::spawn(async {
+        registry::override_state(Some(SystemState::WriteBlocked));
+    });
+    assert_eq!(
+        run(&mut db, &mut con, &pop).await,
+        output_of(responses::groups::SERVER_ERR)
+    );
+    registry::override_state(None);
+    // the client never got the values, so every one of them is back
+    assert_eq!(
+        run(&mut db, &mut con, &["DBSIZE"]).await,
+        output_of(&old_usize(keys.len() + 1))
+    );
+    let v = output_of(b"+1\nv\n");
+    assert_eq!(run(&mut db, &mut con, &["GET", "key0"]).await, v);
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", keys[CHUNK_SIZE].as_str()]).await,
+        v
+    );
+    assert_eq!(run(&mut db, &mut con, &["GET", "expiring"]).await, v);
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "missing"]).await,
+        output_of(responses::groups::NIL)
+    );
+    // along with its expiry time
+    clock.advance(chrono::Duration::seconds(10));
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "expiring"]).await,
+        output_of(responses::groups::NIL)
+    );
+    // and they are popped once the system is okay
+    run(&mut db, &mut con, &pop).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["DBSIZE"]).await,
+        output_of(&old_usize(0))
+    );
+}
+
+#[tokio::test]
+async fn test_getm_splits_found_and_missing_keys() {
+    use crate::actions::CHUNK_SIZE;
+    fn string_of(bytes: &[u8]) -> Vec<u8> {
+        let mut ret = format!("+{}\n", bytes.len()).into_bytes();
+        ret.extend_from_slice(bytes);
+        ret.push(b'\n');
+        ret
+    }
+    let mut db = new_store();
+    let mut con = new_con();
+    // binary keys, every other one of which is set (across a few chunks)
+    let keys: Vec<Vec<u8>> = (0..CHUNK_SIZE * 2 + 7)
+        .map(|i| {
+            let mut key = vec![0xFF, 0x00];
+            key.extend_from_slice(i.to_string().as_bytes());
+            key
+        })
+        .collect();
+    let value_of = |i: usize| format!("v{}", i).into_bytes();
+    let values: Vec<Vec<u8>> = (0..keys.len()).map(value_of).collect();
+    let mut mset: Vec<&[u8]> = vec![&b"MSET"[..]];
+    for i in (0..keys.len()).step_by(2) {
+        mset.push(&keys[i]);
+        mset.push(&values[i]);
+    }
+    assert_eq!(
+        run_raw(&mut db, &mut con, &mset).await,
+        output_of(&old_usize((keys.len() + 1) / 2))
+    );
+    let mut getm: Vec<&[u8]> = vec![&b"GETM"[..]];
+    getm.extend(keys.iter().map(|key| &key[..]));
+    // a found key and a missing key, once more
+    getm.push(&keys[0]);
+    getm.push(&keys[1]);
+    getm.push(b"withmissing");
+    let mut found = Vec::new();
+    let mut missing = Vec::new();
+    for (i, key) in keys
+        .iter()
+        .enumerate()
+        .chain([(0, &keys[0]), (1, &keys[1])])
+    {
+        if i % 2 == 0 {
+            found.push(string_of(key));
+            found.push(string_of(&value_of(i)));
+        } else {
+            missing.push(string_of(key));
+        }
+    }
+    let mut expected = old_length('&', 2);
+    expected.extend(old_length('_', found.len()));
+    found.iter().for_each(|element| expected.extend(element));
+    expected.extend(old_length('_', missing.len()));
+    missing.iter().for_each(|element| expected.extend(element));
+    assert_eq!(
+        run_raw(&mut db, &mut con, &getm).await,
+        output_of(&expected)
+    );
+    // the flag has to be there, and it's the last argument
+    for query in [&["GETM", "x", "y"][..], &["GETM", "WITHMISSING", "x"]].iter() {
+        assert_eq!(
+            run(&mut db, &mut con, query).await,
+            output_of(responses::groups::ACTION_ERR)
+        );
+    }
+    // the keys that can't be in a table of strings get a section of their own
+    let create = ["CREATE", "TABLE", "default:getmstr", "keymap(str,str)"];
+    let okay = output_of(responses::groups::OKAY);
+    assert_eq!(run(&mut db, &mut con, &create).await, okay);
+    assert_eq!(
+        run(&mut db, &mut con, &["USE", "default:getmstr"]).await,
+        okay
+    );
+    assert_eq!(run(&mut db, &mut con, &["SET", "x", "100"]).await, okay);
+    let getm: [&[u8]; 5] = [b"GETM", b"\xFF", b"x", b"nope", b"WITHMISSING"];
+    let mut expected = old_length('&', 3);
+    expected.extend(old_length('_', 2));
+    expected.extend(string_of(b"x"));
+    expected.extend(string_of(b"100"));
+    expected.extend(old_length('_', 1));
+    expected.extend(string_of(b"nope"));
+    expected.extend(old_length('_', 1));
+    expected.extend(string_of(b"\xFF"));
+    assert_eq!(
+        run_raw(&mut db, &mut con, &getm).await,
+        output_of(&expected)
+    );
+}
+
+#[tokio::test]
+async fn test_flushks_clears_every_table_of_the_keyspace() {
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let ddl: [&[&str]; 3] = [
+        &["CREATE", "KEYSPACE", "flushks"],
+        &["CREATE", "TABLE", "flushks:strs", "keymap(str,str)"],
+        &[
+            "CREATE",
+            "TABLE",
+            "flushks:ordered",
+            "keymap(binstr,binstr)",
+            "volatile",
+            "ordered",
+        ],
+    ];
+    for query in ddl.iter() {
+        assert_eq!(run(&mut db, &mut con, query).await, okay);
+    }
+    run(&mut db, &mut con, &["USE", "flushks:strs"]).await;
+    run(&mut db, &mut con, &["MSET", "a", "1", "b", "2", "c", "3"]).await;
+    assert_eq!(run(&mut db, &mut con, &["PROTECT", "a"]).await, okay);
+    run(&mut db, &mut con, &["USE", "flushks:ordered"]).await;
+    run(&mut db, &mut con, &["MSET", "x", "1", "y", "2"]).await;
+    run(&mut db, &mut con, &["USE", "default:default"]).await;
+    run(&mut db, &mut con, &["SET", "elsewhere", "1"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["FLUSHKS", "flushks"]).await,
+        output_of(b"_6\n+7\nordered\n+1\n2\n+1\n0\n+4\nstrs\n+1\n2\n+1\n1\n")
+    );
+    // only the protected key is left in the keyspace
+    run(&mut db, &mut con, &["USE", "flushks:strs"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["EXISTS", "a", "b", "c"]).await,
+        output_of(&old_usize(1))
+    );
+    run(&mut db, &mut con, &["USE", "flushks:ordered"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["DBSIZE"]).await,
+        output_of(&old_usize(0))
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["RANGEKEYS", "a", "z"]).await,
+        output_of(b"_0\n")
+    );
+    // the current keyspace is cleared if none is given
+    run(&mut db, &mut con, &["MSET", "x", "1"]).await;
+    run(&mut db, &mut con, &["USE", "flushks"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["FLUSHKS"]).await,
+        output_of(b"_6\n+7\nordered\n+1\n1\n+1\n0\n+4\nstrs\n+1\n0\n+1\n1\n")
+    );
+    // and other keyspaces are left alone
+    run(&mut db, &mut con, &["USE", "default:default"]).await;
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "elsewhere"]).await,
+        output_of(b"+1\n1\n")
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["FLUSHKS", "nope"]).await,
+        output_of(responses::groups::CONTAINER_NOT_FOUND)
+    );
+}
@@ -0,0 +1,130 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! In-process tests
+//!
+//! Unlike the `#[dbtest]` tests, which talk to a test server over the network, these run
+//! queries straight against a [`Corestore`] over a connection that reads from and writes to
+//! memory. That way, they can use what a client can't reach: a mock clock, the overrides in
+//! the [`registry`](crate::registry) and the tables themselves. The helpers here are shared
+//! with the protocol tests in [`connection`](crate::dbnet::connection)
+
+mod admin;
+mod kvengine;
+mod transactions;
+
+use crate::corestore::memstore::Memstore;
+use crate::corestore::{Corestore, Data};
+use crate::dbnet::connection::SIMPLE_QUERY_HEADER;
+use crate::dbnet::Connection;
+use crate::protocol::{Element, Query};
+use bytes::Bytes;
+use std::io::Cursor;
+
+/// A connection that reads from and writes to the same buffer
+pub type TestConnection = Connection<Cursor<Vec<u8>>>;
+
+/// A store with nothing but the default table, which is in use
+pub fn new_store() -> Corestore {
+    Corestore::default_with_store(Memstore::new_default())
+}
+
+/// A connection that has nothing to read
+pub fn new_con() -> TestConnection {
+    TestConnection::new(Cursor::new(Vec::new()))
+}
+
+/// Everything that was written to the connection so far (including what it was handed to
+/// read, if anything)
+pub fn written(con: &TestConnection) -> &[u8] {
+    con.stream.get_ref().get_ref()
+}
+
+/// A length header, the way it was written before frames were assembled in the scratch
+/// buffer
+pub fn old_length(tsymbol: char, len: usize) -> Vec<u8> {
+    format!("{}{}\n", tsymbol, len).into_bytes()
+}
+
+/// An unsigned integer, the way it was written before integers were formatted on the stack
+pub fn old_usize(val: usize) -> Vec<u8> {
+    format!(":{}\n{}\n", val.to_string().len(), val).into_bytes()
+}
+
+/// Encode `query` the way that a client would send it
+pub fn packet_of(query: &[&str]) -> Vec<u8> {
+    let mut packet = format!("*1\n_{}\n", query.len()).into_bytes();
+    for arg in query {
+        packet.extend(format!("+{}\n{}\n", arg.len(), arg).into_bytes());
+    }
+    packet
+}
+
+/// Run a query on the connection and return whatever it wrote out
+pub async fn run(db: &mut Corestore, con: &mut TestConnection, query: &[&str]) -> Vec<u8> {
+    let query: Vec<&[u8]> = query.iter().map(|arg| arg.as_bytes()).collect();
+    run_raw(db, con, &query).await
+}
+
+/// Same as [`run`], except that the arguments don't have to be strings
+pub async fn run_raw(db: &mut Corestore, con: &mut TestConnection, query: &[&[u8]]) -> Vec<u8> {
+    let already_written = written(con).len();
+    let query = Element::FlatArray(
+        query
+            .iter()
+            .map(|arg| Bytes::copy_from_slice(arg))
+            .collect(),
+    );
+    db.execute_query(Query::SimpleQuery(query), con)
+        .await
+        .unwrap();
+    written(con)[already_written..].to_vec()
+}
+
+/// The output of a simple query with the single `response`
+pub fn output_of(response: &[u8]) -> Vec<u8> {
+    let mut ret = SIMPLE_QUERY_HEADER.to_vec();
+    ret.extend_from_slice(response);
+    ret
+}
+
+/// The pairs and the protected keys of the table in use, sorted
+pub fn contents_of(db: &Corestore) -> (Vec<(Data, Data)>, Vec<Data>) {
+    let kvs = db.get_kvstore().unwrap();
+    let mut pairs: Vec<(Data, Data)> = kvs
+        .__get_inner_ref()
+        .iter()
+        .map(|kv| (kv.key().clone(), kv.value().clone()))
+        .collect();
+    let mut protected: Vec<Data> = kvs
+        .get_protected()
+        .iter()
+        .map(|kv| kv.key().clone())
+        .collect();
+    pairs.sort();
+    protected.sort();
+    (pairs, protected)
+}
@@ -0,0 +1,321 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! In-process tests for transactions and the other ways of keeping concurrent writes apart
+
+use super::*;
+use crate::protocol::responses;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_exec_runs_without_interleaved_writes() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    let mut db = new_store();
+    let mut con = new_con();
+    let stop = Arc::new(AtomicBool::new(false));
+    let writes = Arc::new(AtomicUsize::new(0));
+    // another client keeps trying to overwrite the key
+    let writer = {
+        let (mut db, stop, writes) = (db.clone(), stop.clone(), writes.clone());
+        tokio::spawn(async move {
+            let mut con = new_con();
+            while !stop.load(Ordering::Relaxed) {
+                run(&mut db, &mut con, &["UPDATE", "x", "other"]).await;
+                writes.fetch_add(1, Ordering::Relaxed);
+                tokio::task::yield_now().await;
+            }
+        })
+    };
+    let queued = output_of(responses::groups::QUEUED);
+    for round in 0..100 {
+        assert_eq!(
+            run(&mut db, &mut con, &["MULTI"]).await,
+            output_of(responses::groups::OKAY)
+        );
+        for query in [
+            &["DEL", "x"][..],
+            &["SET", "x", "1"][..],
+            &["UPDATE", "x", "2"][..],
+            &["GET", "x"][..],
+        ]
+        .iter()
+        {
+            assert_eq!(run(&mut db, &mut con, query).await, queued);
+        }
+        // the other client's update can't land between the queued queries
+        let deleted = if round == 0 { 0 } else { 1 };
+        assert_eq!(
+            run(&mut db, &mut con, &["EXEC"]).await,
+            format!("*1\n&4\n:1\n{}\n!1\n0\n!1\n0\n+1\n2\n", deleted).into_bytes()
+        );
+    }
+    stop.store(true, Ordering::Relaxed);
+    writer.await.unwrap();
+    assert_ne!(writes.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_flushks_waits_for_a_running_exec() {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    let mut db = new_store();
+    let mut con = new_con();
+    let stop = Arc::new(AtomicBool::new(false));
+    let flushes = Arc::new(AtomicUsize::new(0));
+    // another client keeps clearing the keyspace
+    let flusher = {
+        let (mut db, stop, flushes) = (db.clone(), stop.clone(), flushes.clone());
+        tokio::spawn(async move {
+            let mut con = new_con();
+            while !stop.load(Ordering::Relaxed) {
+                run(&mut db, &mut con, &["FLUSHKS", "default"]).await;
+                flushes.fetch_add(1, Ordering::Relaxed);
+                tokio::task::yield_now().await;
+            }
+        })
+    };
+    let okay = output_of(responses::groups::OKAY);
+    let queued = output_of(responses::groups::QUEUED);
+    for _ in 0..100 {
+        assert_eq!(run(&mut db, &mut con, &["MULTI"]).await, okay);
+        for query in [&["USET", "x", "1"][..], &["GET", "x"], &["GET", "x"]].iter() {
+            assert_eq!(run(&mut db, &mut con, query).await, queued);
+        }
+        // the key can't be cleared between the queued queries
+        assert_eq!(
+            run(&mut db, &mut con, &["EXEC"]).await,
+            b"*1\n&3\n:1\n1\n+1\n1\n+1\n1\n".to_vec()
+        );
+    }
+    stop.store(true, Ordering::Relaxed);
+    flusher.await.unwrap();
+    assert_ne!(flushes.load(Ordering::Relaxed), 0);
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_racing_ifeq_is_linearizable() {
+    const INCREMENTS: usize = 200;
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    assert_eq!(run(&mut db, &mut con, &["SET", "counter", "0"]).await, okay);
+    // every client increments the counter with a GET and an IFEQ, retrying if another
+    // client got in between, so no increment is lost and no value is written twice
+    let clients: Vec<_> = (0..4)
+        .map(|_| {
+            let (mut db, okay) = (db.clone(), okay.clone());
+            tokio::spawn(async move {
+                let mut con = new_con();
+                let not_matched = output_of(responses::groups::NOT_MATCHED);
+                let mut written = Vec::new();
+                while written.len() < INCREMENTS {
+                    let got = run(&mut db, &mut con, &["GET", "counter"]).await;
+                    let got = String::from_utf8(got).unwrap();
+                    let current = got.lines().nth(2).unwrap().to_owned();
+                    let next = (current.parse::<usize>().unwrap() + 1).to_string();
+                    let query = ["IFEQ", "counter", &current, "SET", &next];
+                    let ret = run(&mut db, &mut con, &query).await;
+                    if ret == okay {
+                        written.push(next.parse::<usize>().unwrap());
+                    } else {
+                        assert_eq!(ret, not_matched);
+                    }
+                    tokio::task::yield_now().await;
+                }
+                written
+            })
+        })
+        .collect();
+    let mut written = Vec::new();
+    for client in clients {
+        written.extend(client.await.unwrap());
+    }
+    written.sort_unstable();
+    assert_eq!(written, (1..=4 * INCREMENTS).collect::<Vec<_>>());
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "counter"]).await,
+        output_of(format!("+3\n{}\n", 4 * INCREMENTS).as_bytes())
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_racing_check_and_set_has_one_winner() {
+    const INCREMENTS: usize = 100;
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    assert_eq!(run(&mut db, &mut con, &["SET", "counter", "0"]).await, okay);
+    // every client increments the counter by watching it, reading it and writing the next
+    // value in a transaction, retrying if the transaction was aborted. Of the clients that
+    // read the same value, only one gets to write the next one
+    let clients: Vec<_> = (0..4)
+        .map(|_| {
+            let (mut db, okay) = (db.clone(), okay.clone());
+            tokio::spawn(async move {
+                let mut con = new_con();
+                let queued = output_of(responses::groups::QUEUED);
+                let changed = output_of(responses::groups::WATCHED_CHANGED);
+                let written_okay = output_of(b"&1\n!1\n0\n");
+                let mut written = Vec::new();
+                while written.len() < INCREMENTS {
+                    assert_eq!(run(&mut db, &mut con, &["WATCH", "counter"]).await, okay);
+                    let got = run(&mut db, &mut con, &["GET", "counter"]).await;
+                    let got = String::from_utf8(got).unwrap();
+                    let next = got.lines().nth(2).unwrap().parse::<usize>().unwrap() + 1;
+                    assert_eq!(run(&mut db, &mut con, &["MULTI"]).await, okay);
+                    let query = ["UPDATE", "counter", &next.to_string()];
+                    assert_eq!(run(&mut db, &mut con, &query).await, queued);
+                    let ret = run(&mut db, &mut con, &["EXEC"]).await;
+                    if ret == written_okay {
+                        written.push(next);
+                    } else {
+                        assert_eq!(ret, changed);
+                    }
+                    tokio::task::yield_now().await;
+                }
+                written
+            })
+        })
+        .collect();
+    let mut written = Vec::new();
+    for client in clients {
+        written.extend(client.await.unwrap());
+    }
+    written.sort_unstable();
+    assert_eq!(written, (1..=4 * INCREMENTS).collect::<Vec<_>>());
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "counter"]).await,
+        output_of(format!("+3\n{}\n", 4 * INCREMENTS).as_bytes())
+    );
+    // every EXEC stopped watching the key
+    assert_eq!(db.get_kvstore().unwrap().watched_keys(), 0);
+}
+
+#[tokio::test]
+async fn test_exec_aborts_if_a_watched_key_was_deleted() {
+    let mut db = new_store();
+    let mut con = new_con();
+    let mut other = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    let queued = output_of(responses::groups::QUEUED);
+    let changed = output_of(responses::groups::WATCHED_CHANGED);
+    async fn run_each(
+        db: &mut Corestore,
+        con: &mut TestConnection,
+        queries: &[&[&str]],
+    ) -> Vec<Vec<u8>> {
+        let mut outputs = Vec::new();
+        for query in queries {
+            outputs.push(run(db, con, query).await);
+        }
+        outputs
+    }
+    let transaction: [&[&str]; 3] = [&["MULTI"], &["SET", "x", "2"], &["EXEC"]];
+    assert_eq!(run(&mut db, &mut con, &["SET", "x", "1"]).await, okay);
+    assert_eq!(run(&mut db, &mut con, &["WATCH", "x"]).await, okay);
+    assert_eq!(
+        run(&mut db, &mut other, &["DEL", "x"]).await,
+        output_of(b":1\n1\n")
+    );
+    assert_eq!(
+        run_each(&mut db, &mut con, &transaction).await,
+        vec![okay.clone(), queued.clone(), changed]
+    );
+    // nothing ran, and the key isn't watched any more so the retry goes through
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "x"]).await,
+        output_of(responses::groups::NIL)
+    );
+    assert_eq!(
+        run_each(&mut db, &mut con, &transaction).await,
+        vec![okay.clone(), queued.clone(), output_of(b"&1\n!1\n0\n")]
+    );
+    // UNWATCH forgets the keys
+    assert_eq!(run(&mut db, &mut con, &["WATCH", "x"]).await, okay);
+    assert_eq!(run(&mut db, &mut other, &["UPDATE", "x", "3"]).await, okay);
+    assert_eq!(run(&mut db, &mut con, &["UNWATCH"]).await, okay);
+    assert_eq!(run(&mut db, &mut con, &["MULTI"]).await, okay);
+    assert_eq!(run(&mut db, &mut con, &["GET", "x"]).await, queued);
+    assert_eq!(
+        run(&mut db, &mut con, &["EXEC"]).await,
+        output_of(b"&1\n+1\n3\n")
+    );
+    // and so does closing the connection
+    assert_eq!(run(&mut db, &mut con, &["WATCH", "x", "y"]).await, okay);
+    assert_eq!(db.get_kvstore().unwrap().watched_keys(), 2);
+    drop(con);
+    assert_eq!(db.get_kvstore().unwrap().watched_keys(), 0);
+}
+
+#[tokio::test]
+async fn test_a_failed_query_aborts_the_transaction() {
+    use crate::actions::Arity;
+    let mut db = new_store();
+    let mut con = new_con();
+    let okay = output_of(responses::groups::OKAY);
+    assert_eq!(run(&mut db, &mut con, &["MULTI"]).await, okay);
+    assert_eq!(
+        run(&mut db, &mut con, &["MULTI"]).await,
+        output_of(responses::groups::NESTED_MULTI)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SET", "y", "1"]).await,
+        output_of(responses::groups::QUEUED)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["GET"]).await,
+        output_of(&Arity::Exactly(1).error())
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["NOSUCHACTION"]).await,
+        output_of(responses::groups::UNKNOWN_ACTION)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["SYS", "HEALTH"]).await,
+        output_of(responses::groups::NOT_ALLOWED_IN_MULTI)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["EXEC"]).await,
+        output_of(responses::groups::EXEC_ABORTED)
+    );
+    // nothing was run and the transaction is over
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "y"]).await,
+        output_of(responses::groups::NIL)
+    );
+    assert_eq!(
+        run(&mut db, &mut con, &["EXEC"]).await,
+        output_of(responses::groups::NOT_IN_MULTI)
+    );
+    // a discarded transaction runs nothing either
+    assert_eq!(run(&mut db, &mut con, &["MULTI"]).await, okay);
+    run(&mut db, &mut con, &["SET", "y", "1"]).await;
+    assert_eq!(run(&mut db, &mut con, &["DISCARD"]).await, okay);
+    assert_eq!(
+        run(&mut db, &mut con, &["GET", "y"]).await,
+        output_of(responses::groups::NIL)
+    );
+}
@@ -26,7 +26,63 @@
 
 #[sky_macros::dbtest]
 mod __private {
-    use skytable::{Element, Response};
+    use libstress::utils;
+    use skytable::{Element, Query, RespCode, Response};
+    macro_rules! query_of {
+        ($($arg:expr),*) => {{
+            let mut q = Query::new();
+            q.push(vec![$($arg),*]);
+            q
+        }};
+    }
+    /// Returns the `<name> <value>` pairs of `INSPECT TABLE`
+    macro_rules! inspect_table {
+        ($con:ident, $entity:expr) => {
+            match $con
+                .run_simple_query(&query_of!("inspect", "table", $entity))
+                .await
+                .unwrap()
+            {
+                Response::Item(Element::FlatArray(arr)) => {
+                    assert_eq!(arr.len(), 62);
+                    arr
+                }
+                _ => panic!("Bad response for inspect table"),
+            }
+        };
+    }
+    /// The `INSPECT TABLE` pairs, up to (but not including) `created`, for a table without
+    /// a limit on the size of values
+    macro_rules! describe {
+        (
+            $model:expr,
+            $kt:expr,
+            $vt:expr,
+            $volatile:expr,
+            $ordered:expr,
+            $records:expr,
+            $bytes:expr
+        ) => {
+            vec![
+                "model".to_owned(),
+                $model.to_owned(),
+                "key_type".to_owned(),
+                $kt.to_owned(),
+                "value_type".to_owned(),
+                $vt.to_owned(),
+                "volatile".to_owned(),
+                $volatile.to_string(),
+                "ordered".to_owned(),
+                $ordered.to_string(),
+                "records".to_owned(),
+                $records.to_string(),
+                "tracked_bytes".to_owned(),
+                $bytes.to_string(),
+                "max_value_size".to_owned(),
+                "unlimited".to_owned(),
+            ]
+        };
+    }
     async fn test_inspect_keyspaces() {
         query.push("INSPECT");
         query.push("KEYSPACES");
@@ -47,31 +103,219 @@ mod __private {
     }
     async fn test_inspect_table() {
         let my_table: &str = __MYENTITY__.split(':').collect::<Vec<&str>>()[1];
-        query.push("INSPECT");
-        query.push("TABLE");
-        query.push(my_table);
-        match con.run_simple_query(&query).await.unwrap() {
-            Response::Item(Element::String(st)) => {
-                assert_eq!(
-                    st,
-                    "KeyValue { data:(binstr,binstr), volatile:true }".to_owned()
-                )
-            }
-            _ => panic!("Bad response for inspect table"),
-        }
+        let properties = inspect_table!(con, my_table);
+        assert_eq!(
+            properties[..16],
+            describe!(
+                "keymap(binstr,binstr)",
+                "binstr",
+                "binstr",
+                true,
+                false,
+                0,
+                0
+            )[..]
+        );
+        assert_eq!(properties[16], "created");
+        assert_ne!(properties[17], "unknown");
+        // creating the table counts as a write, and reads aren't tracked by default
+        assert_eq!(properties[18], "last_write");
+        assert_ne!(properties[19], "never");
+        assert_eq!(properties[20], "last_read");
+        assert_eq!(properties[21], "untracked");
+        // nothing was read from the table yet
+        assert_eq!(properties[22], "gets");
+        assert_eq!(properties[23], "0");
     }
     async fn test_inspect_table_fully_qualified_entity() {
-        query.push("INSPECT");
-        query.push("TABLE");
-        query.push(__MYENTITY__);
-        match con.run_simple_query(&query).await.unwrap() {
-            Response::Item(Element::String(st)) => {
-                assert_eq!(
-                    st,
-                    "KeyValue { data:(binstr,binstr), volatile:true }".to_owned()
-                )
-            }
-            _ => panic!("Bad response for inspect table"),
+        let properties = inspect_table!(con, __MYENTITY__);
+        assert_eq!(
+            properties[..16],
+            describe!(
+                "keymap(binstr,binstr)",
+                "binstr",
+                "binstr",
+                true,
+                false,
+                0,
+                0
+            )[..]
+        );
+    }
+    async fn test_inspect_table_models() {
+        let mykeyspace: &str = __MYENTITY__.split(':').collect::<Vec<&str>>()[0];
+        let mut rng = rand::thread_rng();
+        let strbin = mykeyspace.to_owned() + ":" + &utils::rand_alphastring(10, &mut rng);
+        let strstr = mykeyspace.to_owned() + ":" + &utils::rand_alphastring(10, &mut rng);
+        assert_eq!(
+            con.run_simple_query(&query_of!(
+                "create",
+                "table",
+                strbin.as_str(),
+                "keymap(str,binstr)",
+                "ordered"
+            ))
+            .await
+            .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!(
+                "create",
+                "table",
+                strstr.as_str(),
+                "keymap(str,str)",
+                "volatile"
+            ))
+            .await
+            .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let properties = inspect_table!(con, strbin.as_str());
+        assert_eq!(
+            properties[..16],
+            describe!("keymap(str,binstr)", "str", "binstr", false, true, 0, 0)[..]
+        );
+        // the counters follow the writes
+        assert_eq!(
+            con.run_simple_query(&query_of!("use", strbin.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("mset", "x", "100", "yy", "2"))
+                .await
+                .unwrap(),
+            Response::Item(Element::UnsignedInt(2))
+        );
+        let properties = inspect_table!(con, strbin.as_str());
+        assert_eq!(
+            properties[..16],
+            describe!("keymap(str,binstr)", "str", "binstr", false, true, 2, 7)[..]
+        );
+        let properties = inspect_table!(con, strstr.as_str());
+        assert_eq!(
+            properties[..16],
+            describe!("keymap(str,str)", "str", "str", true, false, 0, 0)[..]
+        );
+        // and what the keys and values are checked against closes the list
+        assert_eq!(
+            properties[50..54],
+            ["key_encoding", "utf8", "value_encoding", "utf8"]
+        );
+        let properties = inspect_table!(con, strbin.as_str());
+        assert_eq!(
+            properties[50..54],
+            ["key_encoding", "utf8", "value_encoding", "none"]
+        );
+        // followed by the sizes of the keys and values that were written
+        assert_eq!(
+            properties[54..],
+            [
+                "largest_key",
+                "2",
+                "largest_value",
+                "3",
+                "key_sizes",
+                "1:1 3:1",
+                "value_sizes",
+                "1:1 3:1"
+            ]
+        );
+    }
+    async fn test_inspect_table_max_value_size() {
+        let mykeyspace: &str = __MYENTITY__.split(':').collect::<Vec<&str>>()[0];
+        let mut rng = rand::thread_rng();
+        let limited = mykeyspace.to_owned() + ":" + &utils::rand_alphastring(10, &mut rng);
+        assert_eq!(
+            con.run_simple_query(&query_of!(
+                "create",
+                "table",
+                limited.as_str(),
+                "keymap(binstr,binstr)",
+                "maxvaluesize:16"
+            ))
+            .await
+            .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let properties = inspect_table!(con, limited.as_str());
+        assert_eq!(properties[14], "max_value_size");
+        assert_eq!(properties[15], "16");
+    }
+    async fn test_inspect_table_read_cache() {
+        let mykeyspace: &str = __MYENTITY__.split(':').collect::<Vec<&str>>()[0];
+        let mut rng = rand::thread_rng();
+        let cached = mykeyspace.to_owned() + ":" + &utils::rand_alphastring(10, &mut rng);
+        assert_eq!(
+            con.run_simple_query(&query_of!(
+                "create",
+                "table",
+                cached.as_str(),
+                "keymap(str,str)",
+                "cached"
+            ))
+            .await
+            .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("use", cached.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("set", "x", "100"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        // the first read fills the cache and the second is served from it
+        for _ in 0..2 {
+            assert_eq!(
+                con.run_simple_query(&query_of!("get", "x")).await.unwrap(),
+                Response::Item(Element::String("100".to_owned()))
+            );
         }
+        let properties = inspect_table!(con, cached.as_str());
+        assert_eq!(
+            properties[44..50],
+            ["read_cache", "true", "cache_hits", "1", "cache_misses", "1"]
+        );
+        // a table without the property has no cache
+        let properties = inspect_table!(con, __MYENTITY__);
+        assert_eq!(
+            properties[44..50],
+            [
+                "read_cache",
+                "false",
+                "cache_hits",
+                "0",
+                "cache_misses",
+                "0"
+            ]
+        );
+    }
+    async fn test_inspect_missing_table() {
+        let mykeyspace: &str = __MYENTITY__.split(':').collect::<Vec<&str>>()[0];
+        let notable = mykeyspace.to_owned() + ":thisdoesnotexist";
+        assert_eq!(
+            con.run_simple_query(&query_of!("inspect", "table", notable.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "container-not-found".to_owned()
+            )))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("inspect", "keyspace", "thisdoesnotexist"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "container-not-found".to_owned()
+            )))
+        );
     }
 }
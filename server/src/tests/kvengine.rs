@@ -34,6 +34,13 @@
 
 #[sky_macros::dbtest]
 mod __private {
+    macro_rules! query_of {
+        ($($arg:expr),*) => {{
+            let mut q = Query::new();
+            q.push(vec![$($arg),*]);
+            q
+        }};
+    }
     macro_rules! setkeys {
         ($con:ident, $($key:literal:$value:literal),*) => {
             let mut q = Query::new();
@@ -86,7 +93,9 @@ mod __private {
         query.push("get");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
         );
         let mut query = Query::new();
         query.push("get");
@@ -94,7 +103,9 @@ mod __private {
         query.push("y");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
         );
     }
 
@@ -130,23 +141,159 @@ mod __private {
         );
     }
 
-    /// Test a SET query with incorrect number of arugments
+    /// Test a SET query with incorrect number of arugments (or an argument after the value
+    /// that isn't an option)
     async fn test_set_syntax_error() {
         query.push("set");
         query.push("x");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:at-least-2".to_owned()
+            )))
         );
         let mut query = Query::new();
         query.push("set");
         query.push("x");
         query.push("y");
         query.push("z");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "unknown-property".to_owned()
+            )))
+        );
+    }
+
+    /// Test a SET query with NX: it only sets a key that doesn't exist, and returns nil
+    /// otherwise
+    async fn test_set_nx() {
+        query.push(vec!["set", "x", "100", "nx"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("set", "x", "200", "NX");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+        let query = query_of!("get", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+    }
+
+    /// Test a SET query with XX: it only sets a key that exists, and returns nil otherwise
+    async fn test_set_xx() {
+        query.push(vec!["set", "x", "100", "XX"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+        let query = query_of!("get", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+        setkeys!(con, "x":"100");
+        let query = query_of!("set", "x", "200", "xx");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("get", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("200".to_owned()))
+        );
+    }
+
+    /// Test a SET query with EX, alone and along with NX or XX
+    async fn test_set_ex() {
+        query.push(vec!["set", "x", "100", "EX", "30"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        // without NX, an existing key is still an overwrite error
+        let query = query_of!("set", "x", "200", "EX", "30");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::OverwriteError))
+        );
+        let query = query_of!("set", "x", "200", "NX", "EX", "30");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+        let query = query_of!("set", "y", "200", "EX", "30", "NX");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("set", "x", "300", "XX", "EX", "60");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("set", "z", "1", "EX", "soon");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Wrongtype))
+        );
+        let query = query_of!("set", "z", "1", "EX");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ActionError))
+        );
+    }
+
+    /// Test a SET query with both NX and XX (or a flag twice), which is a usage error
+    async fn test_set_conflicting_flags() {
+        query.push(vec!["set", "x", "100", "NX", "XX"]);
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
             Response::Item(Element::RespCode(RespCode::ActionError))
         );
+        let query = query_of!("set", "x", "100", "NX", "nx");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "duplicate-option".to_owned()
+            )))
+        );
+        // nothing was written
+        let query = query_of!("get", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+    }
+
+    /// Test a SET query whose value looks like an option: options only start after the value
+    async fn test_set_value_like_an_option() {
+        query.push(vec!["set", "x", "NX"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("set", "EX", "XX", "NX");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("get", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("NX".to_owned()))
+        );
+        let query = query_of!("get", "EX");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("XX".to_owned()))
+        );
     }
 
     /// Test an UPDATE query: which should return code: 0
@@ -187,7 +334,9 @@ mod __private {
         query.push("x");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-2".to_owned()
+            )))
         );
         let mut query = Query::new();
         query.push("update");
@@ -196,7 +345,9 @@ mod __private {
         query.push("z");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-2".to_owned()
+            )))
         );
     }
 
@@ -261,7 +412,9 @@ mod __private {
         query.push("del");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:nonzero".to_owned()
+            )))
         );
     }
 
@@ -297,7 +450,9 @@ mod __private {
         query.push("exists");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:nonzero".to_owned()
+            )))
         );
     }
 
@@ -369,7 +524,9 @@ mod __private {
         query.push("mget");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:nonzero".to_owned()
+            )))
         );
     }
 
@@ -437,7 +594,9 @@ mod __private {
         query.push("mset");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
     async fn test_mset_syntax_error_args_three() {
@@ -447,7 +606,9 @@ mod __private {
         query.push("z");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -509,7 +670,9 @@ mod __private {
         query.push("mupdate");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -520,7 +683,102 @@ mod __private {
         query.push("z");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
+        );
+    }
+
+    /// Test a strict MSET query with a mixed set of outcomes: nothing should be set
+    async fn test_mset_strict_mixed() {
+        setkeys!(con, "x":"100");
+        query.push("mset");
+        query.push("strict");
+        query.push("y");
+        query.push("200");
+        query.push("x");
+        query.push("1");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::FlatArray(vec![
+                "ok".to_owned(),
+                "exists".to_owned()
+            ]))
+        );
+        let mut query = Query::new();
+        query.push("mget");
+        query.push("x");
+        query.push("y");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::Array(vec![
+                Element::String("100".to_owned()),
+                Element::RespCode(RespCode::NotFound)
+            ]))
+        );
+        // without the existing key, everything is set
+        let mut query = Query::new();
+        query.push("mset");
+        query.push("STRICT");
+        query.push("y");
+        query.push("200");
+        query.push("z");
+        query.push("300");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(2))
+        );
+    }
+
+    /// Test an MSET query with an even number of arguments that sets a key called `strict`
+    async fn test_mset_strict_as_a_key() {
+        query.push("mset");
+        query.push("strict");
+        query.push("100");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+        let mut query = Query::new();
+        query.push("get");
+        query.push("strict");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+    }
+
+    /// Test a strict MUPDATE query with a mixed set of outcomes: nothing should be updated
+    async fn test_mupdate_strict_mixed() {
+        setkeys!(con, "x":"100");
+        query.push("mupdate");
+        query.push("strict");
+        query.push("x");
+        query.push("1");
+        query.push("y");
+        query.push("2");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::FlatArray(vec![
+                "ok".to_owned(),
+                "missing".to_owned()
+            ]))
+        );
+        let mut query = Query::new();
+        query.push("get");
+        query.push("x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+        let mut query = Query::new();
+        query.push("mupdate");
+        query.push("strict");
+        query.push("x");
+        query.push("1");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
         );
     }
 
@@ -609,7 +867,9 @@ mod __private {
         query.push("sset");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -620,7 +880,9 @@ mod __private {
         query.push("z");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -705,7 +967,9 @@ mod __private {
         query.push("mupdate");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -716,7 +980,9 @@ mod __private {
         query.push("z");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -790,7 +1056,9 @@ mod __private {
         query.push("sdel");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:nonzero".to_owned()
+            )))
         );
     }
 
@@ -832,7 +1100,9 @@ mod __private {
         query.push("ioewjforfifrj");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:at-most-1".to_owned()
+            )))
         );
     }
 
@@ -900,7 +1170,9 @@ mod __private {
         query.push("z");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:at-most-2".to_owned()
+            )))
         );
     }
 
@@ -939,7 +1211,9 @@ mod __private {
         query.push("uset");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -950,7 +1224,9 @@ mod __private {
         query.push("three");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:even-at-least-2".to_owned()
+            )))
         );
     }
 
@@ -979,7 +1255,9 @@ mod __private {
         query.push("keylen");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
         );
     }
     async fn test_keylen_syntax_error_args_two() {
@@ -988,7 +1266,9 @@ mod __private {
         query.push("y");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
         );
     }
     async fn test_mksnap_disabled() {
@@ -1136,16 +1416,70 @@ mod __private {
         query.push("hijklmn");
         query.push("riufrif");
         query.push("fvnjnvv");
+        query.push("ordered");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:at-most-4".to_owned()
+            )))
+        );
+        // three arguments are only fine if the last one is the flag
+        let mut query = Query::new();
+        query.push("lskeys");
+        query.push(&__MYENTITY__);
+        query.push("10");
+        query.push("riufrif");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
             Response::Item(Element::RespCode(RespCode::ActionError))
         );
     }
+    async fn test_lskeys_ordered() {
+        setkeys!(
+            con,
+            "z":100,
+            "x":200,
+            "y":300,
+            "b":400,
+            "a":500,
+            "c":600
+        );
+        query.push("lskeys");
+        query.push(&__MYENTITY__);
+        query.push(4);
+        query.push("ordered");
+        let first = con.run_simple_query(&query).await.unwrap();
+        assert_eq!(
+            first,
+            Response::Item(Element::FlatArray(
+                vec!["a", "b", "c", "x"]
+                    .into_iter()
+                    .map(|element| element.to_owned())
+                    .collect()
+            ))
+        );
+        // the same data, the same output
+        assert_eq!(con.run_simple_query(&query).await.unwrap(), first);
+        let mut query = Query::new();
+        query.push("lskeys");
+        query.push("ORDERED");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::FlatArray(
+                vec!["a", "b", "c", "x", "y", "z"]
+                    .into_iter()
+                    .map(|element| element.to_owned())
+                    .collect()
+            ))
+        );
+    }
     async fn test_pop_syntax_error() {
         query.push("pop");
         assert_eq!(
             con.run_simple_query(&query).await.unwrap(),
-            Response::Item(Element::RespCode(RespCode::ActionError))
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:nonzero".to_owned()
+            )))
         );
     }
     async fn test_pop_all_success() {
@@ -1185,4 +1519,241 @@ mod __private {
             ]))
         );
     }
+    async fn test_getdel_syntax_error() {
+        query.push("getdel");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+        let mut query = Query::new();
+        query.push(vec!["getdel", "x", "y"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+    }
+    async fn test_getdel_present() {
+        setkeys!(con, "x":100);
+        query.push(vec!["getdel", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+        // it's gone now
+        let mut query = Query::new();
+        query.push(vec!["get", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+        let mut query = Query::new();
+        query.push(vec!["getdel", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+    }
+    async fn test_getdel_absent() {
+        query.push(vec!["getdel", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+    }
+    /// An empty value is an empty string, and never NIL
+    async fn test_getdel_empty_value() {
+        query.push(vec!["set", "x", ""]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let mut query = Query::new();
+        query.push(vec!["get", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("".to_owned()))
+        );
+        let mut query = Query::new();
+        query.push(vec!["getdel", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("".to_owned()))
+        );
+        let mut query = Query::new();
+        query.push(vec!["getdel", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+    }
+    /// Action names are matched without regard to their case
+    async fn test_mixed_case_action_names() {
+        query.push(vec!["SeT", "x", "100"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let mut query = Query::new();
+        query.push(vec!["gEt", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+        let mut query = Query::new();
+        query.push("dbsize");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+        // but the name has to be ASCII
+        let mut query = Query::new();
+        query.push("gét");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "Unknown action".to_owned()
+            )))
+        );
+    }
+    async fn test_ifeq_matched() {
+        setkeys!(con, "x":100);
+        query.push(vec!["ifeq", "x", "100", "set", "200"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("IFEQ", "x", "200", "UPDATE", "300");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("get", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("300".to_owned()))
+        );
+        // a delete responds like DEL
+        let query = query_of!("IFEQ", "x", "300", "DEL");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+        let query = query_of!("exists", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(0))
+        );
+    }
+    async fn test_ifeq_not_matched() {
+        setkeys!(con, "x":100);
+        for query in [
+            query_of!("IFEQ", "x", "200", "SET", "300"),
+            query_of!("IFEQ", "x", "10", "DEL"),
+            query_of!("IFEQ", "missing", "100", "UPDATE", "300"),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                con.run_simple_query(query).await.unwrap(),
+                Response::Item(Element::RespCode(RespCode::ErrorString(
+                    "not-matched".to_owned()
+                )))
+            );
+        }
+        // nothing was written
+        query.push(vec!["get", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+        let query = query_of!("exists", "missing");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(0))
+        );
+    }
+    async fn test_ifeq_protected_key() {
+        setkeys!(con, "x":100);
+        query.push(vec!["PROTECT", "x"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("IFEQ", "x", "100", "DEL");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "protected-key".to_owned()
+            )))
+        );
+    }
+    async fn test_ifeq_bad_embedded_action() {
+        setkeys!(con, "x":100);
+        // only SET, UPDATE and DEL can be embedded
+        for query in [
+            query_of!("IFEQ", "x", "100", "GET"),
+            query_of!("IFEQ", "x", "100", "MSET", "y"),
+            query_of!("IFEQ", "x", "100", "nope", "y"),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                con.run_simple_query(query).await.unwrap(),
+                Response::Item(Element::RespCode(RespCode::ErrorString(
+                    "Unknown action".to_owned()
+                )))
+            );
+        }
+        // and with the arguments that they take
+        for query in [
+            query_of!("IFEQ", "x", "100", "SET"),
+            query_of!("IFEQ", "x", "100", "DEL", "y"),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                con.run_simple_query(query).await.unwrap(),
+                Response::Item(Element::RespCode(RespCode::ActionError))
+            );
+        }
+        query.push(vec!["IFEQ", "x", "100"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:between-3-and-4".to_owned()
+            )))
+        );
+        let query = query_of!("get", "x");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::String("100".to_owned()))
+        );
+    }
+    async fn test_getm_withmissing() {
+        setkeys!(con, "x":100, "y":200);
+        query.push(vec!["GETM", "x", "nope", "y", "x", "WITHMISSING"]);
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::Array(vec![
+                Element::FlatArray(vec![
+                    "x".to_owned(),
+                    "100".to_owned(),
+                    "y".to_owned(),
+                    "200".to_owned(),
+                    "x".to_owned(),
+                    "100".to_owned()
+                ]),
+                Element::FlatArray(vec!["nope".to_owned()])
+            ]))
+        );
+        let query = query_of!("GETM", "x", "y");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ActionError))
+        );
+    }
 }
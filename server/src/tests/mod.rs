@@ -27,22 +27,30 @@
 //! This module contains automated tests for queries
 
 mod ddl_tests;
+mod dump_tests;
+mod fixture;
+pub(crate) mod inproc;
 mod inspect_tests;
 mod kvengine;
+mod object_tests;
+mod protect_tests;
+mod rangekeys_tests;
+mod syncstream_tests;
+mod sys_tests;
+mod transcripts;
 
 mod ssl {
-    use skytable::aio::TlsConnection;
+    use super::fixture;
     use skytable::{Element, Query, Response};
-    use std::env;
     #[tokio::test]
     async fn test_ssl() {
-        let mut path = env::var("ROOT_DIR").expect("ROOT_DIR unset");
-        path.push_str("/cert.pem");
-        let mut con = TlsConnection::new("127.0.0.1", 2004, &path).await.unwrap();
-        let q = Query::from("heya");
-        assert_eq!(
-            con.run_simple_query(&q).await.unwrap(),
-            Response::Item(Element::String("HEY!".to_owned()))
-        );
+        fixture::for_each_transport(|mut con| async move {
+            let q = Query::from("heya");
+            assert_eq!(
+                con.run_simple_query(&q).await.unwrap(),
+                Response::Item(Element::String("HEY!".to_owned()))
+            );
+        })
+        .await;
     }
 }
@@ -0,0 +1,138 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Tests for `OBJECT` queries
+
+#[sky_macros::dbtest]
+mod __private {
+    use libstress::utils;
+    use skytable::{Element, Query, RespCode, Response};
+    macro_rules! query_of {
+        ($($arg:expr),*) => {{
+            let mut q = Query::new();
+            q.push(vec![$($arg),*]);
+            q
+        }};
+    }
+    macro_rules! set_x {
+        ($con:ident) => {
+            assert_eq!(
+                $con.run_simple_query(&query_of!("set", "x", "hello"))
+                    .await
+                    .unwrap(),
+                Response::Item(Element::RespCode(RespCode::Okay))
+            );
+        };
+    }
+    async fn test_object_type() {
+        set_x!(con);
+        assert_eq!(
+            con.run_simple_query(&query_of!("object", "type", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::String("binstr".to_owned()))
+        );
+    }
+    async fn test_object_type_str_table() {
+        let mykeyspace: &str = __MYENTITY__.split(':').collect::<Vec<&str>>()[0];
+        let tblname = utils::rand_alphastring(10, &mut rand::thread_rng());
+        let entity = mykeyspace.to_owned() + ":" + &tblname;
+        assert_eq!(
+            con.run_simple_query(&query_of!(
+                "create",
+                "table",
+                entity.as_str(),
+                "keymap(str,str)",
+                "volatile"
+            ))
+            .await
+            .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("use", entity.as_str()))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        set_x!(con);
+        assert_eq!(
+            con.run_simple_query(&query_of!("object", "type", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::String("str".to_owned()))
+        );
+    }
+    async fn test_object_encoding() {
+        set_x!(con);
+        assert_eq!(
+            con.run_simple_query(&query_of!("object", "encoding", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::String("raw".to_owned()))
+        );
+    }
+    async fn test_object_size() {
+        set_x!(con);
+        assert_eq!(
+            con.run_simple_query(&query_of!("object", "size", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::UnsignedInt(5))
+        );
+    }
+    async fn test_object_missing_key() {
+        for subcommand in ["type", "encoding", "size"] {
+            assert_eq!(
+                con.run_simple_query(&query_of!("object", subcommand, "x"))
+                    .await
+                    .unwrap(),
+                Response::Item(Element::RespCode(RespCode::NotFound))
+            );
+        }
+    }
+    async fn test_object_unknown_subcommand() {
+        set_x!(con);
+        assert_eq!(
+            con.run_simple_query(&query_of!("object", "refcount", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "unknown-object-query:TYPE|ENCODING|SIZE".to_owned()
+            )))
+        );
+    }
+    async fn test_object_wrong_args() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("object", "type"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+    }
+}
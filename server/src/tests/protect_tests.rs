@@ -0,0 +1,173 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Tests for `PROTECT` and `UNPROTECT`
+
+#[sky_macros::dbtest]
+mod __private {
+    use skytable::{Element, Query, RespCode, Response};
+    macro_rules! query_of {
+        ($($arg:expr),*) => {{
+            let mut q = Query::new();
+            q.push(vec![$($arg),*]);
+            q
+        }};
+    }
+    macro_rules! setkeys {
+        ($con:ident, $($key:literal:$value:literal),*) => {
+            let mut q = Query::new();
+            q.push("MSET");
+            let mut count = 0;
+            $(
+                q.push($key);
+                q.push($value);
+                count += 1;
+            )*
+            assert_eq!(
+                $con.run_simple_query(&q).await.unwrap(),
+                Response::Item(Element::UnsignedInt(count))
+            );
+        };
+    }
+    macro_rules! protected_key {
+        () => {
+            RespCode::ErrorString("protected-key".to_owned())
+        };
+    }
+    async fn test_protect_refuses_deletes() {
+        setkeys!(
+            con,
+            "x":100,
+            "y":200
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("protect", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("del", "x", "y"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(protected_key!()))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("pop", "x", "y"))
+                .await
+                .unwrap(),
+            Response::Item(Element::Array(vec![
+                Element::RespCode(protected_key!()),
+                Element::String("200".to_owned())
+            ]))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("sdel", "x")).await.unwrap(),
+            Response::Item(Element::RespCode(protected_key!()))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("unprotect", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("del", "x")).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+    }
+    async fn test_protect_nil() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("protect", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+        setkeys!(
+            con,
+            "x":100
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("unprotect", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::NotFound))
+        );
+    }
+    async fn test_protect_syntax_error() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("protect")).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("unprotect", "x", "y"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+    }
+    async fn test_flushdb_skips_protected() {
+        setkeys!(
+            con,
+            "x":100,
+            "y":200,
+            "z":300
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("protect", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("flushdb")).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("exists", "x", "y", "z"))
+                .await
+                .unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+        // force it
+        assert_eq!(
+            con.run_simple_query(&query_of!("flushdb", "force"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("exists", "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::UnsignedInt(0))
+        );
+    }
+}
@@ -0,0 +1,169 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! Tests for `RANGEKEYS` on tables with an ordered index
+
+#[sky_macros::dbtest]
+mod __private {
+    use libstress::utils;
+    use skytable::{Element, Query, RespCode, Response};
+    macro_rules! query_of {
+        ($($arg:expr),*) => {{
+            let mut q = Query::new();
+            q.push(vec![$($arg),*]);
+            q
+        }};
+    }
+    /// Create an ordered table in the test keyspace, switch to it and set the provided keys
+    macro_rules! use_ordered_table {
+        ($con:ident, $myentity:ident, $($key:literal:$value:literal),*) => {
+            let mykeyspace: &str = $myentity.split(':').collect::<Vec<&str>>()[0];
+            let tblname = utils::rand_alphastring(10, &mut rand::thread_rng());
+            let entity = mykeyspace.to_owned() + ":" + &tblname;
+            let mut q = Query::new();
+            q.push("create");
+            q.push("table");
+            q.push(&entity);
+            q.push("keymap(str,str)");
+            q.push("volatile");
+            q.push("ordered");
+            assert_eq!(
+                $con.run_simple_query(&q).await.unwrap(),
+                Response::Item(Element::RespCode(RespCode::Okay))
+            );
+            assert_eq!(
+                $con.run_simple_query(&query_of!("use", entity.as_str())).await.unwrap(),
+                Response::Item(Element::RespCode(RespCode::Okay))
+            );
+            let mut q = Query::new();
+            q.push("MSET");
+            let mut count = 0;
+            $(
+                q.push($key);
+                q.push($value);
+                count += 1;
+            )*
+            assert_eq!(
+                $con.run_simple_query(&q).await.unwrap(),
+                Response::Item(Element::UnsignedInt(count))
+            );
+        };
+    }
+    macro_rules! flat_array {
+        ($($elem:literal),*) => {
+            Response::Item(Element::FlatArray(vec![$($elem.to_owned()),*]))
+        };
+    }
+    async fn test_rangekeys_boundaries() {
+        use_ordered_table!(con, __MYENTITY__, "c": "3", "a": "1", "e": "5", "b": "2", "d": "4");
+        query.push("RANGEKEYS");
+        query.push("b");
+        query.push("d");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            flat_array!("b", "c", "d")
+        );
+        // the bounds don't have to exist
+        let query = query_of!("RANGEKEYS", "0", "bb");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            flat_array!("a", "b")
+        );
+        let query = query_of!("RANGEKEYS", "f", "z");
+        assert_eq!(con.run_simple_query(&query).await.unwrap(), flat_array!());
+        // inverted ranges are just empty
+        let query = query_of!("RANGEKEYS", "d", "b");
+        assert_eq!(con.run_simple_query(&query).await.unwrap(), flat_array!());
+    }
+    async fn test_rangekeys_limit_and_withvalues() {
+        use_ordered_table!(con, __MYENTITY__, "a": "1", "b": "2", "c": "3");
+        query.push("RANGEKEYS");
+        query.push("a");
+        query.push("c");
+        query.push("2");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            flat_array!("a", "b")
+        );
+        let query = query_of!("RANGEKEYS", "b", "c", "withvalues");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            flat_array!("b", "2", "c", "3")
+        );
+        let query = query_of!("RANGEKEYS", "a", "c", "1", "WITHVALUES");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            flat_array!("a", "1")
+        );
+    }
+    async fn test_lskeys_ordered_uses_the_index() {
+        use_ordered_table!(con, __MYENTITY__, "c": "3", "a": "1", "b": "2");
+        query.push("LSKEYS");
+        query.push("2");
+        query.push("ORDERED");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            flat_array!("a", "b")
+        );
+    }
+    async fn test_rangekeys_after_delete() {
+        use_ordered_table!(con, __MYENTITY__, "a": "1", "b": "2", "c": "3");
+        query.push("DEL");
+        query.push("b");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+        let query = query_of!("POP", "c");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::Array(vec![Element::String("3".to_owned())]))
+        );
+        let query = query_of!("RANGEKEYS", "a", "z");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            flat_array!("a")
+        );
+        let query = Query::from("FLUSHDB");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let query = query_of!("RANGEKEYS", "a", "z");
+        assert_eq!(con.run_simple_query(&query).await.unwrap(), flat_array!());
+    }
+    async fn test_rangekeys_unordered_table() {
+        query.push("RANGEKEYS");
+        query.push("a");
+        query.push("z");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "no-ordered-index".to_owned()
+            )))
+        );
+    }
+}
@@ -0,0 +1,50 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+#[sky_macros::dbtest]
+mod __private {
+    use skytable::{Element, RespCode, Response};
+    async fn test_syncstream_syntax_error() {
+        query.push("SYNCSTREAM");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-2".to_owned()
+            )))
+        );
+    }
+    async fn test_syncstream_too_far_behind() {
+        query.push("SYNCSTREAM");
+        query.push("0");
+        query.push(u64::MAX.to_string());
+        match con.run_simple_query(&query).await.unwrap() {
+            Response::Item(Element::RespCode(RespCode::ErrorString(e))) => {
+                assert!(e.starts_with("sync-too-far-behind:"))
+            }
+            _ => panic!("Bad response for syncstream"),
+        }
+    }
+}
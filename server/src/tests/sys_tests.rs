@@ -0,0 +1,750 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+#[sky_macros::dbtest]
+mod __private {
+    use libstress::utils;
+    use skytable::{Element, Query, RespCode, Response};
+    macro_rules! query_of {
+        ($($arg:expr),*) => {{
+            let mut q = Query::new();
+            q.push(vec![$($arg),*]);
+            q
+        }};
+    }
+    macro_rules! sys_memory {
+        ($con:ident) => {
+            match $con
+                .run_simple_query(&query_of!("sys", "memory"))
+                .await
+                .unwrap()
+            {
+                Response::Item(Element::FlatArray(arr)) => arr,
+                _ => panic!("Bad response for sys memory"),
+            }
+        };
+    }
+    /// Returns the value of `name` in the `SYS MEMORY` report
+    macro_rules! memory_stat {
+        ($report:expr, $name:expr) => {
+            $report
+                .chunks(2)
+                .find(|pair| pair[0] == $name)
+                .map(|pair| pair[1].parse::<usize>().unwrap())
+                .unwrap()
+        };
+    }
+    async fn test_sys_health() {
+        query.push("SYS");
+        query.push("HEALTH");
+        match con.run_simple_query(&query).await.unwrap() {
+            Response::Item(Element::FlatArray(arr)) => {
+                assert_eq!(arr.len(), 16);
+                assert_eq!(arr[0], "state");
+                assert_eq!(arr[2], "dirty_bytes");
+                assert!(arr[3].parse::<usize>().is_ok());
+                assert_eq!(arr[4], "dirty_mark");
+                assert!(arr[5].parse::<usize>().is_ok());
+                assert_eq!(arr[6], "flush_started_seq");
+                assert!(arr[7].parse::<u64>().is_ok());
+                assert_eq!(arr[8], "durable_seq");
+                assert!(arr[9].parse::<u64>().is_ok());
+                assert_eq!(arr[10], "memory_state");
+                assert_eq!(arr[11], "okay");
+                assert_eq!(arr[12], "memory_bytes");
+                assert!(arr[13].parse::<usize>().is_ok());
+                // the test server doesn't check its tables
+                assert_eq!(arr[14], "degraded");
+                assert_eq!(arr[15], "none");
+            }
+            _ => panic!("Bad response for sys health"),
+        }
+    }
+    async fn test_sys_stats() {
+        query.push("SYS");
+        query.push("STATS");
+        match con.run_simple_query(&query).await.unwrap() {
+            Response::Item(Element::FlatArray(arr)) => {
+                assert_eq!(arr.len(), 34);
+                assert_eq!(arr[0], "tables");
+                // atleast the test table and the default table
+                assert!(arr[1].parse::<usize>().unwrap() >= 2);
+                assert_eq!(arr[2], "ordered_indexes");
+                assert_eq!(arr[4], "ordered_index_keys");
+                assert_eq!(arr[6], "ordered_index_bytes");
+                assert!(arr[7].parse::<usize>().is_ok());
+                assert_eq!(arr[8], "compressed_frames");
+                assert!(arr[9].parse::<usize>().is_ok());
+                assert_eq!(arr[10], "compression_bytes_saved");
+                assert!(arr[11].parse::<usize>().is_ok());
+                // the test server doesn't load lazily
+                assert_eq!(arr[12], "loaded_tables");
+                assert_eq!(arr[13], arr[1]);
+                assert_eq!(arr[14], "unloaded_tables");
+                assert_eq!(arr[15], "0");
+                assert_eq!(arr[16], "checksum_mismatches");
+                assert!(arr[17].parse::<usize>().is_ok());
+                assert_eq!(arr[18], "flushes");
+                assert!(arr[19].parse::<u64>().is_ok());
+                assert_eq!(arr[20], "flushed_tables");
+                assert!(arr[21].parse::<u64>().is_ok());
+                assert_eq!(arr[22], "flushed_bytes");
+                assert!(arr[23].parse::<u64>().is_ok());
+                assert_eq!(arr[24], "flushed_dirty_bytes");
+                assert!(arr[25].parse::<u64>().is_ok());
+                assert_eq!(arr[26], "flush_time_us");
+                assert!(arr[27].parse::<u128>().is_ok());
+                assert_eq!(arr[28], "write_amplification");
+                assert!(arr[29] == "none" || arr[29].parse::<f64>().is_ok());
+                assert_eq!(arr[30], "unreadable_values");
+                assert!(arr[31].parse::<usize>().is_ok());
+                assert_eq!(arr[32], "selfcheck_drifts");
+                assert_eq!(arr[33], "0");
+            }
+            _ => panic!("Bad response for sys stats"),
+        }
+    }
+    async fn test_sys_memory() {
+        let table_bytes = format!("table_tracked_bytes:{}", __MYENTITY__);
+        let table_entries = format!("table_entries:{}", __MYENTITY__);
+        let report = sys_memory!(con);
+        let (before, entries_before) = (
+            memory_stat!(report, table_bytes),
+            memory_stat!(report, table_entries),
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("mset", "x", "100", "yy", "2000"))
+                .await
+                .unwrap(),
+            Response::Item(Element::UnsignedInt(2))
+        );
+        let report = sys_memory!(con);
+        assert_eq!(memory_stat!(report, table_bytes), before + 10);
+        assert_eq!(memory_stat!(report, table_entries), entries_before + 2);
+        assert_eq!(
+            con.run_simple_query(&query_of!("del", "x")).await.unwrap(),
+            Response::Item(Element::UnsignedInt(1))
+        );
+        let report = sys_memory!(con);
+        assert_eq!(memory_stat!(report, table_bytes), before + 6);
+        assert_eq!(memory_stat!(report, table_entries), entries_before + 1);
+    }
+    async fn test_sys_memory_reports_every_table() {
+        let my_keyspace: &str = __MYENTITY__.split(':').collect::<Vec<&str>>()[0];
+        let tables = match con
+            .run_simple_query(&query_of!("inspect", "keyspace", my_keyspace))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(tables)) => tables,
+            _ => panic!("Bad response for inspect keyspace"),
+        };
+        let report = sys_memory!(con);
+        assert_eq!(report[0], "tracked_bytes");
+        assert_eq!(report[2], "entries");
+        assert_eq!(report[4], "entry_overhead_bytes");
+        assert!(report
+            .chunks(2)
+            .any(|pair| pair[0] == format!("keyspace_tracked_bytes:{}", my_keyspace)));
+        for table in tables {
+            let name = format!("table_tracked_bytes:{}:{}", my_keyspace, table);
+            assert!(report.chunks(2).any(|pair| pair[0] == name));
+        }
+    }
+    async fn test_sys_unknown_subcommand() {
+        query.push("SYS");
+        query.push("HEALTHY");
+        assert_eq!(
+            con.run_simple_query(&query).await.unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "unknown-sys-query".to_owned()
+            )))
+        );
+    }
+    async fn test_sys_stats_reset() {
+        let gets = |arr: Vec<String>| {
+            let at = arr.iter().position(|name| name == "gets").unwrap();
+            arr[at + 1].clone()
+        };
+        assert_eq!(
+            con.run_simple_query(&query_of!("set", "x", "100"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        con.run_simple_query(&query_of!("get", "x")).await.unwrap();
+        let inspect = query_of!("inspect", "table", __MYENTITY__);
+        match con.run_simple_query(&inspect).await.unwrap() {
+            Response::Item(Element::FlatArray(arr)) => assert_eq!(gets(arr), "1"),
+            _ => panic!("Bad response for inspect table"),
+        }
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "stats", "reset", __MYENTITY__))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        match con.run_simple_query(&inspect).await.unwrap() {
+            Response::Item(Element::FlatArray(arr)) => assert_eq!(gets(arr), "0"),
+            _ => panic!("Bad response for inspect table"),
+        }
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "stats", "resets"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "unknown-sys-query".to_owned()
+            )))
+        );
+    }
+    async fn test_sys_flushwait_rejects_a_bad_timeout() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "flushwait", "soon"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Wrongtype))
+        );
+    }
+    async fn test_sys_snapstate_needs_snapshots() {
+        // snapshots are disabled on the test server
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "snapstate"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "err-snapshot-disabled".to_owned()
+            )))
+        );
+    }
+    async fn test_sys_bgpause_and_bgresume() {
+        // snapshots are disabled on the test server, so pausing them disturbs no other test
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "bgpause", "snapshot", "60"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let state = match con
+            .run_simple_query(&query_of!("sys", "bgstate"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys bgstate"),
+        };
+        assert_eq!(state.len(), 14);
+        assert_eq!(state[6], "snapshot_state");
+        assert_eq!(state[7], "paused");
+        assert_eq!(state[8], "snapshot_next_run_ms");
+        assert_eq!(state[9], "none");
+        assert_eq!(state[10], "snapshot_paused_ms");
+        let paused_ms = state[11].parse::<u128>().unwrap();
+        assert!(paused_ms > 0 && paused_ms <= 60_000);
+        assert_eq!(state[12], "max_pause_secs");
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "bgresume", "snapshot"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let state = match con
+            .run_simple_query(&query_of!("sys", "bgstate"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys bgstate"),
+        };
+        assert_eq!(state[7], "running");
+        assert_eq!(state[11], "0");
+    }
+    async fn test_sys_bgpause_rejects_bad_args() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "bgpause", "expiry"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ActionError))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "bgpause", "snapshot", "0"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Wrongtype))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "bgpause", "snapshot", "a while"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Wrongtype))
+        );
+    }
+    async fn test_sys_locks() {
+        let report = match con
+            .run_simple_query(&query_of!("sys", "locks"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys locks"),
+        };
+        // the locks held right now come first, but they're rarely held for long
+        assert_eq!(report.len() % 2, 0);
+        let names: Vec<&str> = report[report.len() - 6..]
+            .chunks(2)
+            .map(|pair| pair[0].as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "longest_snapshot_ms",
+                "longest_flush_ms",
+                "longest_partmap_ms"
+            ]
+        );
+        assert!(report.chunks(2).all(|pair| pair[1].parse::<u64>().is_ok()));
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "locks", "a while"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Wrongtype))
+        );
+    }
+    async fn test_sys_config() {
+        query.push("SYS");
+        query.push("CONFIG");
+        match con.run_simple_query(&query).await.unwrap() {
+            Response::Item(Element::FlatArray(arr)) => {
+                assert_eq!(arr.len(), 24);
+                assert_eq!(arr[0], "disabled_actions");
+                // the test server doesn't disable anything we run here
+                assert!(!arr[1].split(',').any(|action| action == "SYS"));
+                assert_eq!(arr[2], "max_name_len");
+                assert!(arr[3].parse::<usize>().is_ok());
+                assert_eq!(arr[4], "max_pause_secs");
+                assert!(arr[5].parse::<u64>().is_ok());
+                assert_eq!(arr[6], "max_value_size");
+                assert_eq!(arr[8], "max_ordered_keys");
+                assert!(arr[9].parse::<usize>().is_ok());
+                assert_eq!(arr[10], "load_threads");
+                assert!(arr[11].parse::<usize>().is_ok());
+                // the test server writes no checksums
+                assert_eq!(arr[12], "paranoid");
+                assert_eq!(arr[13], "off");
+                assert_eq!(arr[14], "max_inflight");
+                assert!(arr[15].parse::<usize>().is_ok());
+                assert_eq!(arr[16], "max_inflight_bytes");
+                assert!(arr[17].parse::<usize>().is_ok());
+                assert_eq!(arr[18], "max_pipeline_depth");
+                assert_eq!(arr[20], "slowlog_threshold_us");
+                assert!(arr[21].parse::<u64>().is_ok());
+                assert_eq!(arr[22], "slowlog_size");
+                assert!(arr[23].parse::<usize>().is_ok());
+            }
+            _ => panic!("Bad response for sys config"),
+        }
+    }
+    async fn test_sys_keyslot() {
+        // the same slots as libsky::keyslot (and as Redis Cluster's)
+        let slots: &[(&[&str], usize)] = &[
+            (&["foo"], 12182),
+            (&["{user1000}.following"], 3443),
+            (&["{user1000}.followers"], 3443),
+            (&["foo{}{bar}"], 8363),
+            (&["foo", "1024"], 918),
+            (&["foo", "3"], 1),
+        ];
+        for (args, slot) in slots {
+            let mut query = query_of!("sys", "keyslot");
+            for arg in args.iter() {
+                query.push(*arg);
+            }
+            assert_eq!(
+                con.run_simple_query(&query).await.unwrap(),
+                Response::Item(Element::UnsignedInt(*slot as u64)),
+                "{:?}",
+                args
+            );
+        }
+    }
+    async fn test_sys_keyslot_rejects_bad_slots() {
+        for slots in ["0", "65537", "many"].iter() {
+            assert_eq!(
+                con.run_simple_query(&query_of!("sys", "keyslot", "foo", *slots))
+                    .await
+                    .unwrap(),
+                Response::Item(Element::RespCode(RespCode::Wrongtype))
+            );
+        }
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "keyslot"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ActionError))
+        );
+    }
+    async fn test_sys_clients() {
+        match con
+            .run_simple_query(&query_of!("sys", "clients"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => {
+                // at least this connection, with seven fields each
+                assert!(!arr.is_empty() && arr.len() % 7 == 0);
+                for client in arr.chunks(7) {
+                    assert!(client[0].parse::<u64>().is_ok());
+                    assert!(client[2].parse::<usize>().is_ok());
+                    assert!(client[3].parse::<usize>().is_ok());
+                    // what the open transaction has queued, out of the most that it can
+                    for usage in client[4..6].iter() {
+                        let usage: Vec<usize> =
+                            usage.split('/').map(|part| part.parse().unwrap()).collect();
+                        assert!(usage.len() == 2 && usage[0] <= usage[1]);
+                    }
+                    assert!(client[6].parse::<usize>().is_ok());
+                }
+            }
+            _ => panic!("Bad response for sys clients"),
+        }
+    }
+    async fn test_sys_shutdown_rejects_a_bad_token() {
+        // this must never shut the test server down: no token was prepared here
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "shutdown", "0123456789abcdef"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-shutdown-token".to_owned()
+            )))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "restart"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:exactly-1".to_owned()
+            )))
+        );
+    }
+    async fn test_sys_compact_volatile_table() {
+        // the test table is volatile, so it doesn't have a file to rewrite
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "compact", __MYENTITY__))
+                .await
+                .unwrap(),
+            Response::Item(Element::FlatArray(vec![
+                "tables".to_owned(),
+                "0".to_owned(),
+                "bytes_before".to_owned(),
+                "0".to_owned(),
+                "bytes_after".to_owned(),
+                "0".to_owned()
+            ]))
+        );
+    }
+    async fn test_sys_compact_all_tables() {
+        match con
+            .run_simple_query(&query_of!("sys", "compact"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => {
+                assert_eq!(arr.len(), 6);
+                assert_eq!(arr[0], "tables");
+                // atleast the default table
+                assert!(arr[1].parse::<usize>().unwrap() >= 1);
+                assert_eq!(arr[2], "bytes_before");
+                assert!(arr[3].parse::<u64>().is_ok());
+                assert_eq!(arr[4], "bytes_after");
+                assert!(arr[5].parse::<u64>().is_ok());
+            }
+            _ => panic!("Bad response for sys compact"),
+        }
+    }
+    async fn test_sys_compact_bad_entity() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "compact", "nope:nope"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "container-not-found".to_owned()
+            )))
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "compact", __MYENTITY__, "x"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "bad-arity:at-most-1".to_owned()
+            )))
+        );
+    }
+    async fn test_sys_flushlog() {
+        let log = match con
+            .run_simple_query(&query_of!("sys", "flushlog", "1000"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys flushlog"),
+        };
+        // the test server may or may not have flushed by now
+        assert_eq!(log.len() % 7, 0);
+        for record in log.chunks(7) {
+            let tables = record[1].parse::<usize>().unwrap();
+            let bytes = record[2].parse::<u64>().unwrap();
+            assert!(record[3].parse::<u64>().is_ok());
+            assert!(record[4].parse::<u128>().is_ok());
+            assert!(record[5] == "none" || record[5].parse::<f64>().is_ok());
+            let written: Vec<u64> = record[6]
+                .split_whitespace()
+                .map(|table| table.rsplit('=').next().unwrap().parse().unwrap())
+                .collect();
+            assert_eq!(written.len(), tables);
+            assert!(written.iter().sum::<u64>() <= bytes);
+        }
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "flushlog", "many"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Wrongtype))
+        );
+    }
+    async fn test_sys_ddllog() {
+        let mut rng = rand::thread_rng();
+        let ksname = utils::rand_alphastring(10, &mut rng);
+        let table = format!("{}:logged", ksname);
+        let queries = [
+            query_of!("create", "keyspace", ksname.as_str()),
+            query_of!("create", "table", table.as_str(), "keymap(str,str)"),
+            // the keyspace still has a table, so this fails
+            query_of!("drop", "keyspace", ksname.as_str()),
+            query_of!("drop", "table", table.as_str()),
+            query_of!("drop", "keyspace", ksname.as_str()),
+        ];
+        for query in queries.iter() {
+            con.run_simple_query(query).await.unwrap();
+        }
+        // other tests run DDL queries too, so only look at the records for our keyspace
+        let log = match con
+            .run_simple_query(&query_of!("sys", "ddllog", "1000"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys ddllog"),
+        };
+        assert_eq!(log.len() % 4, 0);
+        let ours: Vec<(&str, &str)> = log
+            .chunks(4)
+            .filter(|record| record[2].contains(ksname.as_str()))
+            .map(|record| (record[2].as_str(), record[3].as_str()))
+            .collect();
+        let expected = vec![
+            (format!("CREATE keyspace {}", ksname), "okay"),
+            (format!("CREATE table {} keymap(str,str)", table), "okay"),
+            (format!("DROP keyspace {}", ksname), "keyspace-not-empty"),
+            (format!("DROP table {}", table), "okay"),
+            (format!("DROP keyspace {}", ksname), "okay"),
+        ];
+        assert_eq!(ours.len(), expected.len());
+        for ((statement, outcome), (expected_statement, expected_outcome)) in
+            ours.into_iter().zip(expected)
+        {
+            assert_eq!(statement, expected_statement);
+            assert_eq!(outcome, expected_outcome);
+        }
+    }
+    async fn test_sys_slowlog() {
+        let okay = Response::Item(Element::RespCode(RespCode::Okay));
+        let threshold = match con
+            .run_simple_query(&query_of!("sys", "slowlog", "threshold"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::UnsignedInt(threshold)) => threshold,
+            _ => panic!("Bad response for sys slowlog threshold"),
+        };
+        // only queries that take 100ms or more are slow (so that nothing else gets in the way)
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "slowlog", "threshold", "100000"))
+                .await
+                .unwrap(),
+            okay
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "trace", "on"))
+                .await
+                .unwrap(),
+            okay
+        );
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "sleep", "150"))
+                .await
+                .unwrap(),
+            okay
+        );
+        let log = match con
+            .run_simple_query(&query_of!("sys", "slowlog", "get", "1000"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys slowlog get"),
+        };
+        assert_eq!(log.len() % 8, 0);
+        // the newest record that slept is ours
+        let ours = log
+            .chunks(8)
+            .find(|record| {
+                record[3] == "SYS"
+                    && record[4] == "2"
+                    && record[5].parse::<u128>().unwrap() >= 150_000
+            })
+            .unwrap();
+        let id = ours[0].parse::<u64>().unwrap();
+        assert!(ours[1].ends_with('Z'));
+        assert!(ours[2].parse::<u64>().is_ok());
+        // the connection is traced, so the stages before the action are there too
+        assert!(ours[6].parse::<u128>().is_ok());
+        assert!(ours[7].parse::<u128>().is_ok());
+        match con
+            .run_simple_query(&query_of!("sys", "slowlog", "len"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::UnsignedInt(len)) => assert!(len >= 1),
+            _ => panic!("Bad response for sys slowlog len"),
+        }
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "slowlog", "reset"))
+                .await
+                .unwrap(),
+            okay
+        );
+        let log = match con
+            .run_simple_query(&query_of!("sys", "slowlog", "get", "1000"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys slowlog get"),
+        };
+        // anything that's left was slow after the reset
+        assert!(log
+            .chunks(8)
+            .all(|record| record[0].parse::<u64>().unwrap() > id));
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "trace", "off"))
+                .await
+                .unwrap(),
+            okay
+        );
+        let threshold = threshold.to_string();
+        assert_eq!(
+            con.run_simple_query(&query_of!(
+                "sys",
+                "slowlog",
+                "threshold",
+                threshold.as_str()
+            ))
+            .await
+            .unwrap(),
+            okay
+        );
+        for bad in [
+            query_of!("sys", "slowlog", "get", "many"),
+            query_of!("sys", "slowlog", "threshold", "-1"),
+            query_of!("sys", "sleep", "60000"),
+        ]
+        .iter()
+        {
+            assert_eq!(
+                con.run_simple_query(bad).await.unwrap(),
+                Response::Item(Element::RespCode(RespCode::Wrongtype))
+            );
+        }
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "slowlog", "everything"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "unknown-sys-query".to_owned()
+            )))
+        );
+    }
+    async fn test_sys_metrics() {
+        assert_eq!(
+            con.run_simple_query(&query_of!("set", "x", "100"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::Okay))
+        );
+        let histograms = match con
+            .run_simple_query(&query_of!("sys", "metrics"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::FlatArray(arr)) => arr,
+            _ => panic!("Bad response for sys metrics"),
+        };
+        assert_eq!(histograms[0], "flush");
+        assert_eq!(histograms[2], "snapshot");
+        let set = histograms
+            .chunks(2)
+            .find(|pair| pair[0] == "action:SET")
+            .unwrap();
+        assert!(set[1].starts_with("count="));
+        let exposition = match con
+            .run_simple_query(&query_of!("sys", "metrics", "prometheus"))
+            .await
+            .unwrap()
+        {
+            Response::Item(Element::String(exposition)) => exposition,
+            _ => panic!("Bad response for sys metrics prometheus"),
+        };
+        assert!(exposition.contains("# TYPE skyd_action_duration_seconds histogram\n"));
+        assert!(
+            exposition.contains("skyd_action_duration_seconds_bucket{action=\"SET\",le=\"+Inf\"}")
+        );
+        assert!(exposition.contains("skyd_flush_duration_seconds_count "));
+        // along with the sizes of what the tables hold
+        let table = format!("table=\"{}\"", __MYENTITY__);
+        assert!(exposition.contains(&format!(
+            "skyd_table_key_size_bytes_bucket{{{},le=\"1\"}} 1\n",
+            table
+        )));
+        assert!(exposition.contains(&format!("skyd_table_largest_value_bytes{{{}}} 3\n", table)));
+        assert_eq!(
+            con.run_simple_query(&query_of!("sys", "metrics", "everything"))
+                .await
+                .unwrap(),
+            Response::Item(Element::RespCode(RespCode::ErrorString(
+                "unknown-sys-query".to_owned()
+            )))
+        );
+    }
+}
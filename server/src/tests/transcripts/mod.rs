@@ -0,0 +1,501 @@
+/*
+ * Created on Wed Oct 14 2026
+ *
+ * This file is a part of Skytable
+ * Skytable (formerly known as TerrabaseDB or Skybase) is a free and open-source
+ * NoSQL database written by Sayan Nandan ("the Author") with the
+ * vision to provide flexibility in data modelling without compromising
+ * on performance, queryability or scalability.
+ *
+ * Copyright (c) 2021, Sayan Nandan <ohsayan@outlook.com>
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ *
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+*/
+
+//! # Wire transcripts
+//!
+//! Every `*.transcript` file in this directory is a session with the server, written down as
+//! the raw bytes that the client sent and the raw bytes that it got back. [`test_transcripts`]
+//! starts a server on an ephemeral port for each transcript, replays the requests over TCP and
+//! compares the responses byte for byte. The clients that the other tests use are happy to
+//! paper over changes to the framing, so this is where a change to the bytes on the wire fails.
+//!
+//! The servers are backed by a fresh in-memory store, so nothing is ever written to disk and
+//! every transcript starts out with just the `default:default` table in use.
+//!
+//! ## The format
+//!
+//! - `> ` lines are sent to the server and `< ` lines are what it should send back. Each line
+//! stands for its bytes followed by a `\n`
+//! - The requests that come before a run of responses are sent together, once the responses
+//! of the previous exchange have arrived
+//! - `#` lines are comments and blank lines are ignored
+//! - `\\` is a backslash, `\{` is a brace and `\xNN` is the byte with the hex code `NN`
+//! - In a response, `{any}` stands for any run of bytes (other than a newline) for the parts
+//! that change from one run to the next, like timestamps
+//!
+//! ## Recording a transcript
+//!
+//! Rather than writing a transcript by hand, start a server and record a session with it with:
+//! ```text
+//! SKY_RECORD_TO=src/tests/transcripts/<name>.transcript \
+//!     cargo test record_transcript -- --ignored --nocapture
+//! ```
+//! This waits for one client on `SKY_RECORD_LISTEN` (`127.0.0.1:2005` by default), passes
+//! everything through to the server on `SKY_RECORD_UPSTREAM` (`127.0.0.1:2003` by default)
+//! and writes the transcript once the client disconnects. Replace anything that can change
+//! between runs with `{any}` before checking it in
+
+use crate::corestore::memstore::Memstore;
+use crate::corestore::Corestore;
+use crate::dbnet::{BaseListener, MultiListener, MAXIMUM_CONNECTION_LIMIT};
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::mem;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{broadcast, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time;
+
+/// How long the server gets to respond before the transcript is failed
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// The placeholder for any run of bytes in a response line
+const ANY: &[u8] = b"{any}";
+
+/// A part of an expected response line
+#[derive(Debug, PartialEq)]
+enum Piece {
+    /// Exactly these bytes
+    Bytes(Vec<u8>),
+    /// Any run of bytes, including none at all
+    Any,
+}
+
+/// A response line that the server should send
+#[derive(Debug)]
+struct Expected {
+    /// The line of the transcript that it was written on
+    lineno: usize,
+    /// The line as it was written in the transcript (without the marker)
+    source: String,
+    pieces: Vec<Piece>,
+}
+
+impl Expected {
+    fn matches(&self, line: &[u8]) -> bool {
+        matches(&self.pieces, line)
+    }
+}
+
+/// The requests that are sent together and the response lines that should come back
+#[derive(Debug)]
+struct Exchange {
+    /// The line of the transcript that the exchange starts on
+    lineno: usize,
+    request: Vec<u8>,
+    response: Vec<Expected>,
+}
+
+fn matches(pieces: &[Piece], line: &[u8]) -> bool {
+    match pieces.split_first() {
+        None => line.is_empty(),
+        Some((Piece::Bytes(bytes), rest)) => {
+            line.starts_with(bytes) && matches(rest, &line[bytes.len()..])
+        }
+        Some((Piece::Any, rest)) => (0..=line.len()).any(|at| matches(rest, &line[at..])),
+    }
+}
+
+/// Turn the text of a transcript line into its pieces, resolving the escapes
+fn unescape(text: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = Vec::new();
+    let mut bytes = Vec::new();
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        rest = tail;
+        match byte {
+            b'\\' => {
+                let (&escaped, tail) = rest.split_first().ok_or("dangling `\\`")?;
+                match escaped {
+                    b'\\' | b'{' => {
+                        bytes.push(escaped);
+                        rest = tail;
+                    }
+                    b'x' if tail.len() >= 2 && tail[..2].iter().all(u8::is_ascii_hexdigit) => {
+                        // UNWRAP: these are two hex digits
+                        let hex = std::str::from_utf8(&tail[..2]).unwrap();
+                        bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                        rest = &tail[2..];
+                    }
+                    _ => return Err(format!("unknown escape `\\{}`", escaped as char)),
+                }
+            }
+            b'{' if rest.starts_with(&ANY[1..]) => {
+                if !bytes.is_empty() {
+                    pieces.push(Piece::Bytes(mem::take(&mut bytes)));
+                }
+                pieces.push(Piece::Any);
+                rest = &rest[ANY.len() - 1..];
+            }
+            _ => bytes.push(byte),
+        }
+    }
+    if !bytes.is_empty() {
+        pieces.push(Piece::Bytes(bytes));
+    }
+    Ok(pieces)
+}
+
+/// Write `line` the way it is written in a transcript
+fn escape(line: &[u8]) -> String {
+    let mut escaped = String::with_capacity(line.len());
+    for &byte in line {
+        match byte {
+            b'\\' => escaped.push_str("\\\\"),
+            b'{' => escaped.push_str("\\{"),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => {
+                let _ = write!(escaped, "\\x{:02x}", byte);
+            }
+        }
+    }
+    escaped
+}
+
+/// Parse the exchanges of a transcript
+fn parse(source: &str) -> Result<Vec<Exchange>, String> {
+    let mut exchanges: Vec<Exchange> = Vec::new();
+    for (lineno, line) in source.lines().enumerate().map(|(i, line)| (i + 1, line)) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let failed = |e: String| format!("line {}: {}", lineno, e);
+        let (marker, text) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+        let text = match text.strip_prefix(' ') {
+            Some(text) => text,
+            None if text.is_empty() => text,
+            None => return Err(failed("expected a space after the marker".to_owned())),
+        };
+        let pieces = unescape(text).map_err(failed)?;
+        match marker {
+            ">" => {
+                let mut request = Vec::new();
+                for piece in pieces {
+                    match piece {
+                        Piece::Bytes(bytes) => request.extend(bytes),
+                        Piece::Any => {
+                            return Err(failed("placeholders are only for responses".to_owned()))
+                        }
+                    }
+                }
+                request.push(b'\n');
+                match exchanges.last_mut() {
+                    Some(exchange) if exchange.response.is_empty() => {
+                        exchange.request.extend(request)
+                    }
+                    _ => exchanges.push(Exchange {
+                        lineno,
+                        request,
+                        response: Vec::new(),
+                    }),
+                }
+            }
+            "<" => match exchanges.last_mut() {
+                Some(exchange) => exchange.response.push(Expected {
+                    lineno,
+                    source: text.to_owned(),
+                    pieces,
+                }),
+                None => return Err(failed("a response before any request".to_owned())),
+            },
+            _ => return Err(failed(format!("unknown marker `{}`", marker))),
+        }
+    }
+    match exchanges.last() {
+        None => Err("there are no exchanges".to_owned()),
+        Some(exchange) if exchange.response.is_empty() => Err(format!(
+            "line {}: the last request has no response",
+            exchange.lineno
+        )),
+        Some(_) => Ok(exchanges),
+    }
+}
+
+/// A server on an ephemeral port, backed by a fresh in-memory store
+struct Server {
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl Server {
+    async fn start() -> Self {
+        let db = Corestore::default_with_store(Memstore::new_default());
+        let (signal, _) = broadcast::channel(1);
+        let climit = Arc::new(Semaphore::new(MAXIMUM_CONNECTION_LIMIT));
+        let base = BaseListener::init(&db, IpAddr::V4(Ipv4Addr::LOCALHOST), 0, climit, signal)
+            .await
+            .expect("Failed to bind to an ephemeral port");
+        let addr = base.listener.local_addr().unwrap();
+        let mut server = MultiListener::new_insecure_only(base).unwrap();
+        let handle = tokio::spawn(async move {
+            let _ = server.run_server().await;
+        });
+        Self { addr, handle }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Read from `stream` until there are `count` lines in `pending` (or the server stops
+/// sending), and split those lines off
+async fn read_lines(stream: &mut TcpStream, pending: &mut Vec<u8>, count: usize) -> Vec<Vec<u8>> {
+    let mut chunk = [0u8; 4096];
+    while pending.iter().filter(|&&byte| byte == b'\n').count() < count {
+        match time::timeout(TIMEOUT, stream.read(&mut chunk)).await {
+            Ok(Ok(read)) if read != 0 => pending.extend_from_slice(&chunk[..read]),
+            // the server hung up or is stuck, so the missing lines show up in the report
+            _ => break,
+        }
+    }
+    let mut lines = Vec::with_capacity(count);
+    while lines.len() < count {
+        match pending.iter().position(|&byte| byte == b'\n') {
+            Some(at) => {
+                let mut line: Vec<u8> = pending.drain(..=at).collect();
+                line.pop();
+                lines.push(line);
+            }
+            None => break,
+        }
+    }
+    lines
+}
+
+/// Describe how the response to `exchange` differs from the one in the transcript, if it does
+fn report(name: &str, exchange: &Exchange, got: &[Vec<u8>]) -> Option<String> {
+    let matched = exchange.response.len() == got.len()
+        && exchange
+            .response
+            .iter()
+            .zip(got)
+            .all(|(expected, line)| expected.matches(line));
+    if matched {
+        return None;
+    }
+    let mut report = format!(
+        "--- {}.transcript: the exchange on line {}\n",
+        name, exchange.lineno
+    );
+    let request = &exchange.request[..exchange.request.len() - 1];
+    for line in request.split(|&byte| byte == b'\n') {
+        let _ = writeln!(report, "   > {}", escape(line));
+    }
+    let _ = writeln!(report, "   {:>5} | {:<24} | got", "line", "expected");
+    for i in 0..exchange.response.len().max(got.len()) {
+        let (expected, line) = (exchange.response.get(i), got.get(i));
+        let marker = match (expected, line) {
+            (Some(expected), Some(line)) if expected.matches(line) => "  ",
+            _ => "!!",
+        };
+        let _ = writeln!(
+            report,
+            "{} {:>5} | {:<24} | {}",
+            marker,
+            expected.map_or_else(String::new, |expected| expected.lineno.to_string()),
+            expected.map_or("<nothing>", |expected| expected.source.as_str()),
+            line.map_or_else(|| "<nothing>".to_owned(), |line| escape(line)),
+        );
+    }
+    Some(report)
+}
+
+/// Replay a transcript against a fresh server, stopping at the first exchange that doesn't
+/// match (since the ones after it are likely to fail because of it)
+async fn replay(name: &str, exchanges: &[Exchange]) -> Result<(), String> {
+    let server = Server::start().await;
+    let mut stream = TcpStream::connect(server.addr)
+        .await
+        .map_err(|e| format!("--- {}.transcript: failed to connect: {}\n", name, e))?;
+    let mut pending = Vec::new();
+    for exchange in exchanges {
+        stream
+            .write_all(&exchange.request)
+            .await
+            .map_err(|e| format!("--- {}.transcript: failed to send: {}\n", name, e))?;
+        let got = read_lines(&mut stream, &mut pending, exchange.response.len()).await;
+        if let Some(report) = report(name, exchange, &got) {
+            return Err(report);
+        }
+    }
+    // the server should close the connection once we're done, without sending anything else
+    let _ = stream.shutdown().await;
+    let _ = time::timeout(TIMEOUT, stream.read_to_end(&mut pending)).await;
+    if pending.is_empty() {
+        Ok(())
+    } else {
+        Err(format!(
+            "--- {}.transcript: the server sent more than the transcript has:\n   < {}\n",
+            name,
+            escape(&pending)
+        ))
+    }
+}
+
+#[tokio::test]
+async fn test_transcripts() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/transcripts");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "transcript"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "there are no transcripts in {:?}", dir);
+    let mut failures = Vec::new();
+    for path in paths.iter() {
+        let name = path.file_stem().unwrap().to_string_lossy();
+        let source = fs::read_to_string(path).unwrap();
+        let result = match parse(&source) {
+            Ok(exchanges) => replay(&name, &exchanges).await,
+            Err(e) => Err(format!("--- {}.transcript: {}\n", name, e)),
+        };
+        if let Err(failure) = result {
+            failures.push(failure);
+        }
+    }
+    if !failures.is_empty() {
+        panic!(
+            "{} of {} transcripts failed:\n{}",
+            failures.len(),
+            paths.len(),
+            failures.join("\n")
+        );
+    }
+}
+
+#[test]
+fn test_transcript_format() {
+    let exchanges = parse(concat!(
+        "# a comment\n",
+        "> *1\n",
+        "> \\\\\\x00\\{any}\n",
+        "< {any}:{any}\n",
+        "<\n",
+        "\n",
+        "> again\n",
+        "< ok\n",
+    ))
+    .unwrap();
+    assert_eq!(exchanges.len(), 2);
+    assert_eq!(exchanges[0].request, b"*1\n\\\x00{any}\n");
+    assert_eq!(exchanges[1].lineno, 7);
+    let (wild, empty) = (&exchanges[0].response[0], &exchanges[0].response[1]);
+    assert!(wild.matches(b"a:b") && wild.matches(b":") && wild.matches(b"a:b:c"));
+    assert!(!wild.matches(b"ab"));
+    assert!(empty.matches(b"") && !empty.matches(b" "));
+    assert_eq!(escape(b"\\{any}\x00\xff"), "\\\\\\{any}\\x00\\xff");
+    assert!(parse("< before\n").is_err());
+    assert!(parse("> {any}\n< x\n").is_err());
+    assert!(parse("> no response\n").is_err());
+    assert!(parse("> \\q\n< x\n").is_err());
+}
+
+/// Lays out what goes through a recorded session as the lines of a transcript
+#[derive(Default)]
+struct Recorder {
+    transcript: String,
+    /// The bytes of the line that is being sent to the server and the one that is being
+    /// sent back
+    partial: [Vec<u8>; 2],
+    /// Whether the last line that was written is a response
+    in_response: bool,
+}
+
+impl Recorder {
+    fn record(&mut self, response: bool, bytes: &[u8]) {
+        let partial = &mut self.partial[response as usize];
+        partial.extend_from_slice(bytes);
+        while let Some(at) = partial.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = partial.drain(..=at).collect();
+            if self.in_response && !response {
+                self.transcript.push('\n');
+            }
+            self.in_response = response;
+            let marker = if response { '<' } else { '>' };
+            let _ = writeln!(self.transcript, "{} {}", marker, escape(&line[..at]));
+        }
+    }
+    fn finish(mut self) -> String {
+        for (partial, marker) in self.partial.iter().zip(['>', '<'].iter()) {
+            if !partial.is_empty() {
+                let _ = writeln!(
+                    self.transcript,
+                    "# the session ended in the middle of this line\n{} {}",
+                    marker,
+                    escape(partial)
+                );
+            }
+        }
+        self.transcript
+    }
+}
+
+/// Record a transcript from a live session (see the module docs)
+#[tokio::test]
+#[ignore]
+async fn record_transcript() {
+    let to = env::var("SKY_RECORD_TO").expect("SKY_RECORD_TO unset");
+    let upstream = env::var("SKY_RECORD_UPSTREAM").unwrap_or_else(|_| "127.0.0.1:2003".to_owned());
+    let listen = env::var("SKY_RECORD_LISTEN").unwrap_or_else(|_| "127.0.0.1:2005".to_owned());
+    let listener = TcpListener::bind(&listen).await.unwrap();
+    println!("Recording to {}: connect to {} to start", to, listen);
+    let (mut client, _) = listener.accept().await.unwrap();
+    let mut server = TcpStream::connect(&upstream).await.unwrap();
+    let (mut client_rx, mut client_tx) = client.split();
+    let (mut server_rx, mut server_tx) = server.split();
+    let mut recorder = Recorder::default();
+    let (mut request, mut response) = ([0u8; 4096], [0u8; 4096]);
+    loop {
+        tokio::select! {
+            read = client_rx.read(&mut request) => match read.unwrap() {
+                0 => break,
+                read => {
+                    server_tx.write_all(&request[..read]).await.unwrap();
+                    recorder.record(false, &request[..read]);
+                }
+            },
+            read = server_rx.read(&mut response) => match read.unwrap() {
+                0 => break,
+                read => {
+                    client_tx.write_all(&response[..read]).await.unwrap();
+                    recorder.record(true, &response[..read]);
+                }
+            },
+        }
+    }
+    let transcript = format!("# Recorded from {}\n{}", upstream, recorder.finish());
+    fs::write(&to, transcript).unwrap();
+    println!("Wrote {}", to);
+}
@@ -24,6 +24,10 @@
  *
 */
 
+use std::borrow::Cow;
+use std::fmt::Write;
+use std::str;
+
 /// # Unsafe unwrapping
 ///
 /// This trait provides a method `unsafe_unwrap` that is potentially unsafe and has
@@ -142,6 +146,16 @@ macro_rules! assert_hmeq {
 /// action requirements have been happily addressed with this macro and that you don't have
 /// to write a lot of code to do the exact same thing
 ///
+/// ## Array responses
+///
+/// An action can return at any `?` (or with an early `return` from the likes of `kve!`), so
+/// an action that writes an array response has to start it with `con.start_array` (or
+/// `con.start_flat_array`) and write the elements through the returned
+/// [`ArrayWriter`](crate::dbnet::connection::ArrayWriter). If the action returns before
+/// every element is written, the writer marks the connection as desynced and the connection
+/// is closed once the action is done, instead of the client reading the next response as
+/// a part of the array. Do whatever can fail with an error response _before_ the array is
+/// started
 ///
 /// ## Limitations
 ///
@@ -202,6 +216,110 @@ pub mod compiler {
     }
 }
 
+/// The most bytes of a key that [`fmt_key_safe`] renders; the rest is only counted
+pub const MAX_RENDERED_KEY_LEN: usize = 128;
+
+/// Render a key (or anything else that a client sent) for a log line or an error message.
+/// Invalid UTF-8 is replaced with `U+FFFD`, control characters (newlines and the escape
+/// character included) and backslashes are escaped like `\n`, `\x1b` and `\\`, and
+/// anything past the first [`MAX_RENDERED_KEY_LEN`] bytes is cut off and replaced with
+/// `...(<n> more bytes)`, so a key can neither garble nor flood the output. A key that needs
+/// none of this is returned as it is, without allocating
+pub fn fmt_key_safe(key: &[u8]) -> Cow<'_, str> {
+    let (head, rest) = key.split_at(key.len().min(MAX_RENDERED_KEY_LEN));
+    if rest.is_empty() {
+        if let Ok(key) = str::from_utf8(head) {
+            if !key.chars().any(needs_escape) {
+                return Cow::Borrowed(key);
+            }
+        }
+    }
+    let mut rendered = String::with_capacity(head.len() + 16);
+    let mut remaining = head;
+    while !remaining.is_empty() {
+        let (valid, invalid) = match str::from_utf8(remaining) {
+            Ok(valid) => (valid, 0),
+            Err(e) => {
+                // UNSAFE: from_utf8 just checked these bytes
+                let valid = unsafe { str::from_utf8_unchecked(&remaining[..e.valid_up_to()]) };
+                // a character that was cut off at the end is invalid too
+                let invalid = e
+                    .error_len()
+                    .unwrap_or_else(|| remaining.len() - e.valid_up_to());
+                (valid, invalid)
+            }
+        };
+        for c in valid.chars() {
+            match c {
+                '\\' => rendered.push_str("\\\\"),
+                '\n' => rendered.push_str("\\n"),
+                '\r' => rendered.push_str("\\r"),
+                '\t' => rendered.push_str("\\t"),
+                // every control character is below U+0100
+                c if c.is_control() => {
+                    let _ = write!(rendered, "\\x{:02x}", c as u32);
+                }
+                c => rendered.push(c),
+            }
+        }
+        if invalid != 0 {
+            rendered.push(char::REPLACEMENT_CHARACTER);
+        }
+        remaining = &remaining[valid.len() + invalid..];
+    }
+    if !rest.is_empty() {
+        let _ = write!(rendered, "...({} more bytes)", rest.len());
+    }
+    Cow::Owned(rendered)
+}
+
+fn needs_escape(c: char) -> bool {
+    c == '\\' || c.is_control()
+}
+
+#[cfg(all(test, not(target_env = "msvc")))]
+pub mod alloc {
+    //! A global allocator for tests that counts the allocations made by every thread
+
+    use std::alloc::{GlobalAlloc, Layout};
+    use std::cell::Cell;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = Cell::new(0);
+    }
+
+    /// Wraps an allocator and counts the allocations (and reallocations) made through it
+    pub struct Counting<A>(pub A);
+
+    fn count() {
+        // the counter may be gone while the thread is shutting down
+        let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+    }
+
+    unsafe impl<A: GlobalAlloc> GlobalAlloc for Counting<A> {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count();
+            self.0.alloc(layout)
+        }
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.0.dealloc(ptr, layout)
+        }
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            count();
+            self.0.alloc_zeroed(layout)
+        }
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count();
+            self.0.realloc(ptr, layout, new_size)
+        }
+    }
+
+    /// Returns the number of allocations made by this thread so far
+    pub fn allocations() -> usize {
+        ALLOCATIONS.with(Cell::get)
+    }
+}
+
 #[macro_export]
 macro_rules! byt {
     ($f:expr) => {
@@ -211,7 +329,7 @@ macro_rules! byt {
 #[macro_export]
 macro_rules! bi {
     ($($x:expr),+ $(,)?) => {{
-        vec![$(bytes::Bytes::from($x),)*].into_iter()
+        crate::queryengine::ActionIter::new(vec![$(bytes::Bytes::from($x),)*])
     }};
 }
 
@@ -221,3 +339,79 @@ macro_rules! do_sleep {
         std::thread::sleep(std::time::Duration::from_secs($dur));
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fmt_key_safe_leaves_plain_keys_alone() {
+        assert!(matches!(fmt_key_safe(b"hello"), Cow::Borrowed("hello")));
+        assert!(matches!(
+            fmt_key_safe("héllo wörld".as_bytes()),
+            Cow::Borrowed("héllo wörld")
+        ));
+        assert!(matches!(fmt_key_safe(b""), Cow::Borrowed("")));
+    }
+
+    #[test]
+    #[cfg(not(target_env = "msvc"))]
+    fn test_fmt_key_safe_does_not_allocate_for_plain_keys() {
+        let before = alloc::allocations();
+        let rendered = fmt_key_safe(b"user:1234:profile");
+        assert_eq!(alloc::allocations(), before);
+        assert_eq!(rendered, "user:1234:profile");
+    }
+
+    #[test]
+    fn test_fmt_key_safe_escapes_newlines() {
+        assert_eq!(
+            fmt_key_safe(b"evil\nINFO fake log line\r\n"),
+            "evil\\nINFO fake log line\\r\\n"
+        );
+        assert_eq!(fmt_key_safe(b"a\tb\\c"), "a\\tb\\\\c");
+    }
+
+    #[test]
+    fn test_fmt_key_safe_escapes_ansi_sequences() {
+        assert_eq!(
+            fmt_key_safe(b"\x1b[31mred\x1b[0m\x07"),
+            "\\x1b[31mred\\x1b[0m\\x07"
+        );
+        // C1 controls and DEL are control characters too
+        assert_eq!(fmt_key_safe("\u{9b}2J\x7f".as_bytes()), "\\x9b2J\\x7f");
+    }
+
+    #[test]
+    fn test_fmt_key_safe_replaces_invalid_utf8() {
+        assert_eq!(fmt_key_safe(b"ab\xffcd"), "ab\u{fffd}cd");
+        assert_eq!(fmt_key_safe(b"\xc3\x28"), "\u{fffd}(");
+        // a character that is cut off at the end
+        assert_eq!(fmt_key_safe(b"ab\xe2\x82"), "ab\u{fffd}");
+    }
+
+    #[test]
+    fn test_fmt_key_safe_truncates_long_keys() {
+        let key = vec![b'k'; 10 * 1024];
+        let expected = format!(
+            "{}...({} more bytes)",
+            "k".repeat(MAX_RENDERED_KEY_LEN),
+            10 * 1024 - MAX_RENDERED_KEY_LEN
+        );
+        assert_eq!(fmt_key_safe(&key), expected);
+        // escaping and truncating together: only the bytes that are kept are escaped
+        let mut key = vec![b'\n'; 10 * 1024];
+        key[MAX_RENDERED_KEY_LEN] = 0xff;
+        assert_eq!(
+            fmt_key_safe(&key),
+            format!(
+                "{}...({} more bytes)",
+                "\\n".repeat(MAX_RENDERED_KEY_LEN),
+                10 * 1024 - MAX_RENDERED_KEY_LEN
+            )
+        );
+        // a key of exactly the limit isn't cut off
+        let key = vec![b'k'; MAX_RENDERED_KEY_LEN];
+        assert!(matches!(fmt_key_safe(&key), Cow::Borrowed(_)));
+    }
+}
@@ -38,7 +38,7 @@
 //!
 //! ### Macros and ghost values
 //! - `#[dbtest]`:
-//!     - `con` - `skytable::AsyncConnection`
+//!     - `con` - the `TestConnection` of the server's test fixture (over plain TCP or TLS)
 //!     - `query` - `skytable::Query`
 //!
 
@@ -49,27 +49,53 @@ use syn::{self};
 
 const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
 
+/// The transports that every `dbtest` function runs over: the suffix of the name of the
+/// generated test and the variant of `Transport` in the server's test fixture
+const TRANSPORTS: [(&str, &str); 2] = [("", "Plain"), ("_tls", "Tls")];
+
 /// This parses a function within a `dbtest` module
 ///
-/// This accepts an `async` function and returns a non-`async` version of it - by
-/// making the body of the function use the `tokio` runtime
+/// This accepts an `async` function and returns non-`async` versions of it - by
+/// making the body of the function use the `tokio` runtime. There's one version for every
+/// transport in [`TRANSPORTS`], each with its own table
 fn parse_dbtest(
     mut input: syn::ItemFn,
     rng: &mut impl rand::Rng,
 ) -> Result<TokenStream, syn::Error> {
     let sig = &mut input.sig;
-    let fname = sig.ident.to_string();
+    if sig.asyncness.is_none() {
+        let msg = "`dbtest` functions need to be async";
+        return Err(syn::Error::new_spanned(sig.fn_token, msg));
+    }
+    sig.asyncness = None;
+    let mut result = quote! {};
+    for (suffix, transport) in TRANSPORTS.iter() {
+        let test = parse_dbtest_over(&input, suffix, transport, rng);
+        result = quote! {
+            #result
+            #test
+        };
+    }
+    Ok(result.into())
+}
+
+/// Returns the test that runs the (no longer `async`) function `input` over `transport`
+fn parse_dbtest_over(
+    input: &syn::ItemFn,
+    suffix: &str,
+    transport: &str,
+    rng: &mut impl rand::Rng,
+) -> proc_macro2::TokenStream {
+    let mut sig = input.sig.clone();
+    let fname = format!("{}{}", sig.ident, suffix);
+    sig.ident = syn::Ident::new(&fname, sig.ident.span());
+    let transport = syn::Ident::new(transport, Span::call_site());
     let body = &input.block;
     let attrs = &input.attrs;
     let vis = &input.vis;
     let header = quote! {
         #[::core::prelude::v1::test]
     };
-    if sig.asyncness.is_none() {
-        let msg = "`dbtest` functions need to be async";
-        return Err(syn::Error::new_spanned(sig.fn_token, msg));
-    }
-    sig.asyncness = None;
     let rand_string: String = (0..30)
         .map(|_| {
             let idx = rng.gen_range(0..CHARSET.len());
@@ -77,7 +103,9 @@ fn parse_dbtest(
         })
         .collect();
     let body = quote! {
-        let mut con = skytable::AsyncConnection::new("127.0.0.1", 2003).await.unwrap();
+        let mut con = crate::tests::fixture::TestConnection::new(
+            crate::tests::fixture::Transport::#transport
+        ).await;
         let __create_ks =
             con.run_simple_query(
                 &skytable::query!("create", "keyspace", "testsuite")
@@ -108,7 +136,7 @@ fn parse_dbtest(
             );
         }
     };
-    let result = quote! {
+    quote! {
         #header
         #(#attrs)*
         #vis #sig {
@@ -121,8 +149,7 @@ fn parse_dbtest(
             .unwrap()
             .block_on(async { #body });
         }
-    };
-    Ok(result.into())
+    }
 }
 
 /// This function checks if the current function is eligible to be a test
@@ -219,11 +246,8 @@ fn parse_test_module(args: TokenStream, item: TokenStream) -> TokenStream {
                     };
                     continue;
                 }
-                let inp = parse_test_sig(function, &mut rng);
-                let __tok: syn::ItemFn = syn::parse_macro_input!(inp as syn::ItemFn);
-                let tok = quote! {
-                    #__tok
-                };
+                // one test for every transport
+                let tok = proc_macro2::TokenStream::from(parse_test_sig(function, &mut rng));
                 result = quote! {
                     #result
                     #tok
@@ -258,9 +282,13 @@ fn parse_string(int: syn::Lit, span: Span, field: &str) -> Result<String, syn::E
 ///
 /// All tests will clean up all values once a single test is over. **These tests should not
 /// be run in multi-threaded environments because they often use the same keys**
+///
+/// Every test function is run over both the plain and the TLS listener of the test server:
+/// `test_x` runs over plain TCP and `test_x_tls` over TLS (see `tests::fixture` in the server)
+///
 /// ## _Ghost_ values
-/// This macro gives a `skytable::AsyncConnection` accessible by the `con` variable and a mutable
-/// `skytable::Query` accessible by the `query` variable
+/// This macro gives a `tests::fixture::TestConnection` accessible by the `con` variable and a
+/// mutable `skytable::Query` accessible by the `query` variable
 ///
 /// ## Requirements
 ///